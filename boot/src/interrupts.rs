@@ -5,6 +5,8 @@
 #![cfg_attr(target_arch = "x86_64", feature(abi_x86_interrupt))]
 #![cfg(not(target_os = "uefi"))]
 
+#[cfg(target_arch = "x86_64")]
+use core::sync::atomic::{AtomicU8, Ordering};
 #[cfg(target_arch = "x86_64")]
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
@@ -16,6 +18,18 @@ pub type InterruptHandler = extern "x86-interrupt" fn(InterruptStackFrame);
 #[cfg(target_arch = "x86_64")]
 static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
 
+/// Legacy IRQ line routed to the network handler (0 = none)
+#[cfg(target_arch = "x86_64")]
+static NETWORK_IRQ_LINE: AtomicU8 = AtomicU8::new(0);
+
+/// Vector of IRQ 0 on the master PIC; the slave follows at +8
+#[cfg(target_arch = "x86_64")]
+const PIC_VECTOR_BASE: u8 = 32;
+
+/// PIT input clock in Hz
+#[cfg(target_arch = "x86_64")]
+const PIT_FREQUENCY_HZ: u64 = 1_193_182;
+
 /// Initialize the Interrupt Descriptor Table
 ///
 /// Sets up handlers for:
@@ -40,6 +54,108 @@ pub unsafe fn init_idt() {
 
     // Load the IDT
     IDT.load();
+
+    // Move the PICs off the firmware's vectors and start the tick
+    init_pics();
+    init_pit(shared::timer::get_frequency());
+
+    #[cfg(not(feature = "uefi-minimal"))]
+    {
+        kernel::init::set_timer_irq_active(true);
+
+        // The NIC's line is only known once the kernel has picked a driver
+        kernel::init::set_network_irq_installer(Some(install_network_irq));
+    }
+}
+
+/// Remap the 8259 PICs to vectors 32-47 and unmask only the timer and keyboard
+///
+/// Lines for other devices are unmasked when a handler is installed.
+#[cfg(target_arch = "x86_64")]
+unsafe fn init_pics() {
+    use x86_64::instructions::port::Port;
+
+    let mut master_command = Port::<u8>::new(0x20);
+    let mut master_data = Port::<u8>::new(0x21);
+    let mut slave_command = Port::<u8>::new(0xA0);
+    let mut slave_data = Port::<u8>::new(0xA1);
+
+    // ICW1: start initialization, ICW4 follows
+    master_command.write(0x11);
+    slave_command.write(0x11);
+    // ICW2: vector bases
+    master_data.write(PIC_VECTOR_BASE);
+    slave_data.write(PIC_VECTOR_BASE + 8);
+    // ICW3: slave on master line 2, slave cascade identity 2
+    master_data.write(1 << 2);
+    slave_data.write(2);
+    // ICW4: 8086 mode
+    master_data.write(0x01);
+    slave_data.write(0x01);
+
+    // OCW1: mask everything but IRQ 0 (timer) and IRQ 1 (keyboard)
+    master_data.write(!0b0000_0011);
+    slave_data.write(0xFF);
+}
+
+/// Program PIT channel 0 to interrupt at `frequency_hz`
+///
+/// The kernel's clock counts these ticks (see `shared::timer`).
+#[cfg(target_arch = "x86_64")]
+unsafe fn init_pit(frequency_hz: u64) {
+    use x86_64::instructions::port::Port;
+
+    let divisor = (PIT_FREQUENCY_HZ / frequency_hz.max(1)).clamp(1, u16::MAX as u64) as u16;
+
+    // Channel 0, lobyte/hibyte, mode 2 (rate generator)
+    Port::<u8>::new(0x43).write(0x34);
+    let mut channel0 = Port::<u8>::new(0x40);
+    channel0.write(divisor as u8);
+    channel0.write((divisor >> 8) as u8);
+}
+
+/// Route a NIC's legacy IRQ line to `network_interrupt_handler`
+///
+/// Lines 0-2 (timer, keyboard, PIC cascade) are never shared with a NIC.
+#[cfg(all(target_arch = "x86_64", not(feature = "uefi-minimal")))]
+fn install_network_irq(line: u8) -> bool {
+    if !(3..16).contains(&line) {
+        return false;
+    }
+    NETWORK_IRQ_LINE.store(line, Ordering::Relaxed);
+
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        IDT[(PIC_VECTOR_BASE + line) as usize].set_handler_fn(network_interrupt_handler);
+
+        // Unmask the line (and the cascade, for the slave PIC)
+        let (mask_port, bit) = if line < 8 { (0x21, line) } else { (0xA1, line - 8) };
+        let mut mask = x86_64::instructions::port::Port::<u8>::new(mask_port);
+        let value = mask.read();
+        mask.write(value & !(1 << bit));
+        if line >= 8 {
+            let mut master = x86_64::instructions::port::Port::<u8>::new(0x21);
+            let value = master.read();
+            master.write(value & !(1 << 2));
+        }
+    });
+    true
+}
+
+/// Network interrupt handler
+///
+/// Collects received frames for the driver and wakes the event loop; the
+/// frames themselves are processed outside interrupt context.
+#[cfg(all(target_arch = "x86_64", not(feature = "uefi-minimal")))]
+extern "x86-interrupt" fn network_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    kernel::init::handle_network_irq();
+
+    // Send EOI to the slave PIC too if the line is behind it
+    unsafe {
+        if NETWORK_IRQ_LINE.load(Ordering::Relaxed) >= 8 {
+            x86_64::instructions::port::Port::new(0xA0).write(0x20u8);
+        }
+        x86_64::instructions::port::Port::new(0x20).write(0x20u8);
+    }
 }
 
 /// Breakpoint exception handler
@@ -70,16 +186,17 @@ extern "x86-interrupt" fn double_fault_handler(
 
 /// Timer interrupt handler
 ///
-/// Called periodically by the timer hardware (HPET/APIC).
+/// Called periodically by the PIT (IRQ 0, see `init_pit`).
 /// This handler should be fast and not perform heavy operations.
 #[cfg(target_arch = "x86_64")]
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
     // Increment tick counter
     shared::timer::increment_ticks();
 
-    // Acknowledge the interrupt
-    // For APIC: write to EOI register
-    // For HPET: handled by hardware
+    // Send EOI to PIC
+    unsafe {
+        x86_64::instructions::port::Port::new(0x20).write(0x20u8);
+    }
 }
 
 /// Keyboard interrupt handler
@@ -71,20 +71,23 @@ pub fn efi_main(
         (0, 0)
     };
 
+    // Read the firmware RTC while runtime services are still mapped 1:1
+    let boot_time_unix = super::read_boot_time(st_boot_ref.runtime_services());
+
+    // ACPI tables stay in reserved memory after exiting boot services
+    // (firmware that only provides a Device Tree has no RSDP)
+    let rsdp_addr = super::find_rsdp(st_boot_ref);
+
     // Exit boot services (required before using memory allocator)
     // This invalidates the boot services pointer, so we must do this last
     // In uefi 0.27, exit_boot_services is a method on SystemTable<Boot>
     // It takes MemoryType and returns (SystemTable<Runtime>, MemoryMap<'static>)
     // It consumes st_boot and returns a Runtime view
     // We need to move st_boot here, so we can't use it after this point
-    let (_st_runtime, _final_memory_map) = st_boot.exit_boot_services(
+    let (st_runtime, _final_memory_map) = st_boot.exit_boot_services(
         MemoryType::LOADER_DATA
     );
 
-    // Get ACPI RSDP address (if available)
-    // Note: ARM64 systems may use Device Tree instead of ACPI
-    let rsdp_addr = None; // TODO: Locate ACPI RSDP or Device Tree
-
     // Create BootInfo
     let boot_info = BootInfo::new(
         framebuffer_info,
@@ -92,6 +95,8 @@ pub fn efi_main(
         rsdp_addr,
         heap_start,
         heap_size,
+        boot_time_unix,
+        Some(st_runtime.as_ptr() as usize),
     );
 
     // Configure MMU for ARM64
@@ -159,7 +164,7 @@ fn acquire_framebuffer(bs: &BootServices) -> Result<FramebufferInfo, uefi::Statu
     // Get framebuffer base address
     let framebuffer_base = gop.frame_buffer().as_mut_ptr() as *mut u8;
 
-    let stride = stride_pixels * 4;
+    let stride = stride_pixels * pixel_format.bytes_per_pixel();
 
     Ok(FramebufferInfo::new(
         framebuffer_base,
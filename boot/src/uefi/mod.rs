@@ -6,3 +6,35 @@ pub mod x86_64;
 
 #[cfg(target_arch = "aarch64")]
 pub mod aarch64;
+
+use uefi::table::cfg::{ACPI2_GUID, ACPI_GUID};
+use uefi::table::runtime::RuntimeServices;
+use uefi::table::{Boot, SystemTable};
+
+/// Physical address of the ACPI RSDP from the firmware configuration table,
+/// preferring the ACPI 2.0 entry
+pub(crate) fn find_rsdp(st: &SystemTable<Boot>) -> Option<usize> {
+    let table = st.config_table();
+    [ACPI2_GUID, ACPI_GUID].iter().find_map(|guid| {
+        table
+            .iter()
+            .find(|entry| entry.guid == *guid)
+            .map(|entry| entry.address as usize)
+    })
+}
+
+/// Firmware RTC time as seconds since the Unix epoch, if the firmware provides it
+pub(crate) fn read_boot_time(rt: &RuntimeServices) -> Option<u64> {
+    let time = rt.get_time().ok()?;
+    let local = shared::timer::unix_seconds(
+        time.year(),
+        time.month(),
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second(),
+    );
+    // UEFI defines local time as UTC - time_zone (in minutes)
+    let offset_secs = time.time_zone().map_or(0, |minutes| minutes as i64 * 60);
+    Some((local as i64 + offset_secs).max(0) as u64)
+}
@@ -103,6 +103,12 @@ pub fn efi_main(
         (0, 0)
     };
 
+    // Read the firmware RTC while runtime services are still mapped 1:1
+    let boot_time_unix = super::read_boot_time(st_boot_ref.runtime_services());
+
+    // ACPI tables stay in reserved memory after exiting boot services
+    let rsdp_addr = super::find_rsdp(st_boot_ref);
+
     // Exit boot services (required before using memory allocator)
     // This invalidates the boot services pointer, so we must do this last
     // In uefi 0.27, exit_boot_services is a method on SystemTable<Boot>
@@ -114,13 +120,10 @@ pub fn efi_main(
         let bs = st_boot_ref.boot_services();
         let _ = bs.stall(1_000_000);
     }
-    let (_st_runtime, _final_memory_map) = st_boot.exit_boot_services(
+    let (st_runtime, _final_memory_map) = st_boot.exit_boot_services(
         MemoryType::LOADER_DATA
     );
 
-    // Get ACPI RSDP address (if available)
-    let rsdp_addr = None; // TODO: Locate ACPI RSDP
-
     // Create BootInfo
     let boot_info = BootInfo::new(
         framebuffer_info,
@@ -128,6 +131,8 @@ pub fn efi_main(
         rsdp_addr,
         heap_start,
         heap_size,
+        boot_time_unix,
+        Some(st_runtime.as_ptr() as usize),
     );
 
     // Boot services are invalid past this point; jump straight to the kernel.
@@ -218,8 +223,13 @@ fn acquire_framebuffer(bs: &BootServices) -> Result<FramebufferInfo, uefi::Statu
         uefi::proto::console::gop::PixelFormat::Rgb => PixelFormat::Rgba,
         // BGR means blue byte first (B, G, R, A order in memory) - most common
         uefi::proto::console::gop::PixelFormat::Bgr => PixelFormat::Bgra,
-        // Bitmask requires inspecting pixel mask - default to BGRA (most common)
-        uefi::proto::console::gop::PixelFormat::Bitmask => PixelFormat::Bgra,
+        // Bitmask requires inspecting pixel mask - 16-bit 5:6:5, else BGRA (most common)
+        uefi::proto::console::gop::PixelFormat::Bitmask => match mode_info.pixel_bitmask() {
+            Some(mask) if (mask.red, mask.green, mask.blue) == (0xF800, 0x07E0, 0x001F) => {
+                PixelFormat::Rgb565
+            }
+            _ => PixelFormat::Bgra,
+        },
         // BltOnly shouldn't reach here (filtered above), but handle it
         uefi::proto::console::gop::PixelFormat::BltOnly => {
             return Err(uefi::Status::UNSUPPORTED);
@@ -229,7 +239,7 @@ fn acquire_framebuffer(bs: &BootServices) -> Result<FramebufferInfo, uefi::Statu
     // Get framebuffer base address
     let framebuffer_base = gop.frame_buffer().as_mut_ptr() as *mut u8;
 
-    let stride = stride_pixels * 4;
+    let stride = stride_pixels * pixel_format.bytes_per_pixel();
 
     Ok(FramebufferInfo::new(
         framebuffer_base,
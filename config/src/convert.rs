@@ -0,0 +1,1174 @@
+//! Conversion between `MoteConfig` and TOML values
+//!
+//! `ConfigStorage` persists a generic TOML `Value`; this module maps the typed
+//! configuration onto that document and back. Encrypted secrets are stored as
+//! lowercase hex strings.
+
+extern crate alloc;
+
+use crate::error::ConfigError;
+use crate::migrate::CONFIG_VERSION;
+use crate::pem::decode_pem_certificates;
+use crate::toml::Value;
+use crate::types::{
+    ConnectionType, IpConfig, LocalProviderConfig, MoteConfig, NetworkConfig, Persona,
+    Preferences, ProviderConfig, ProviderConfigs, ProxyConfig, ThemeChoice, THEME_COLOR_NAMES,
+};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+type Table = BTreeMap<String, Value>;
+
+impl MoteConfig {
+    /// Convert the configuration into a TOML document suitable for storage
+    ///
+    /// The document carries `version = CONFIG_VERSION` so later releases can
+    /// migrate it (see `migrate`).
+    pub fn to_value(&self) -> Value {
+        let mut root = Table::new();
+        root.insert("version".into(), Value::Integer(CONFIG_VERSION as i64));
+        root.insert("network".into(), network_to_value(&self.network));
+        root.insert("providers".into(), providers_to_value(&self.providers));
+        root.insert("preferences".into(), preferences_to_value(&self.preferences));
+        Value::Table(root)
+    }
+
+    /// Build a configuration from a stored TOML document
+    ///
+    /// Missing sections and keys fall back to their defaults; keys with the
+    /// wrong type are reported as `ConfigError::InvalidValue`. Documents of an
+    /// older `version` must go through `migrate` first.
+    pub fn from_value(value: &Value) -> Result<Self, ConfigError> {
+        let root = as_table(value, "config")?;
+        let mut config = MoteConfig::default();
+
+        if let Some(network) = root.get("network") {
+            config.network = network_from_value(network)?;
+        }
+        if let Some(providers) = root.get("providers") {
+            config.providers = providers_from_value(providers)?;
+        }
+        if let Some(preferences) = root.get("preferences") {
+            config.preferences = preferences_from_value(preferences)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Check invariants that loading alone doesn't guarantee
+    ///
+    /// Every violation is reported, not just the first, each as a
+    /// `ConfigError::Validation` naming the offending key, so the setup
+    /// wizard can list them together.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        validate_default_provider(self, &mut errors);
+        if let Some(static_ip) = &self.network.static_ip {
+            if let Err(error) = validate_static_ip(static_ip) {
+                errors.push(match error {
+                    ConfigError::InvalidValue(msg) => ConfigError::Validation(msg),
+                    other => other,
+                });
+            }
+        }
+        validate_preferences(&self.preferences, &mut errors);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The default provider must be known and configured well enough to use
+fn validate_default_provider(config: &MoteConfig, errors: &mut Vec<ConfigError>) {
+    let providers = &config.providers;
+    let name = config.preferences.default_provider.as_str();
+    let missing = || {
+        ConfigError::validation(&format!(
+            "preferences.default_provider: '{}' is selected but [providers.{}] is missing",
+            name, name
+        ))
+    };
+
+    let provider = match name {
+        "openai" => &providers.openai,
+        "anthropic" => &providers.anthropic,
+        "groq" => &providers.groq,
+        "xai" => &providers.xai,
+        "azure" => &providers.azure,
+        "custom" => &providers.custom,
+        "ollama" => {
+            match &providers.ollama {
+                None => errors.push(missing()),
+                Some(ollama) if ollama.endpoint.trim().is_empty() => errors.push(
+                    ConfigError::validation("providers.ollama.endpoint: must not be empty"),
+                ),
+                Some(_) => {}
+            }
+            return;
+        }
+        // The bundled model needs no settings
+        "local" => return,
+        other => {
+            errors.push(ConfigError::validation(&format!(
+                "preferences.default_provider: unknown provider '{}'",
+                other
+            )));
+            return;
+        }
+    };
+    let Some(provider) = provider else {
+        errors.push(missing());
+        return;
+    };
+
+    let is_blank =
+        |setting: &Option<String>| setting.as_deref().is_none_or(|s| s.trim().is_empty());
+    if name == "custom" {
+        // Self-hosted servers may not need a key, but always need an address
+        if is_blank(&provider.base_url) {
+            errors.push(ConfigError::validation(
+                "providers.custom.base_url: required for the custom provider",
+            ));
+        }
+    } else if provider.api_key_encrypted.is_empty() {
+        errors.push(ConfigError::validation(&format!(
+            "providers.{}.api_key: must not be empty",
+            name
+        )));
+    }
+    if name == "azure" {
+        let required = [
+            ("azure_resource", &provider.azure_resource),
+            ("azure_deployment", &provider.azure_deployment),
+        ];
+        for (key, setting) in required {
+            if is_blank(setting) {
+                errors.push(ConfigError::validation(&format!(
+                    "providers.azure.{}: required for Azure OpenAI",
+                    key
+                )));
+            }
+        }
+    }
+}
+
+/// Theme colors must name a known color and parse; sampling settings must
+/// be in the range providers accept
+fn validate_preferences(preferences: &Preferences, errors: &mut Vec<ConfigError>) {
+    for (name, hex) in &preferences.theme_colors {
+        if !THEME_COLOR_NAMES.contains(&name.as_str()) {
+            errors.push(ConfigError::validation(&format!(
+                "preferences.theme_colors.{}: unknown color",
+                name
+            )));
+        } else if !is_hex_color(hex) {
+            errors.push(ConfigError::validation(&format!(
+                "preferences.theme_colors.{}: '{}' is not a #RGB, #RRGGBB or #RRGGBBAA color",
+                name, hex
+            )));
+        }
+    }
+
+    let ranges = [
+        ("temperature", Some(preferences.temperature), 0.0, 2.0),
+        ("top_p", preferences.top_p, 0.0, 1.0),
+        ("presence_penalty", preferences.presence_penalty, -2.0, 2.0),
+        ("frequency_penalty", preferences.frequency_penalty, -2.0, 2.0),
+    ];
+    for (key, value, min, max) in ranges {
+        let Some(value) = value else {
+            continue;
+        };
+        if !(min..=max).contains(&value) {
+            errors.push(ConfigError::validation(&format!(
+                "preferences.{}: {} is not in {} to {}",
+                key, value, min, max
+            )));
+        }
+    }
+}
+
+/// `#` (optional) followed by 3, 6 or 8 hex digits, as the theme accepts
+fn is_hex_color(text: &str) -> bool {
+    let digits = text.strip_prefix('#').unwrap_or(text);
+    matches!(digits.len(), 3 | 6 | 8) && digits.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn network_to_value(network: &NetworkConfig) -> Value {
+    let mut table = Table::new();
+    let connection_type = match network.connection_type {
+        ConnectionType::Ethernet => "ethernet",
+        ConnectionType::Wifi => "wifi",
+    };
+    table.insert("connection_type".into(), Value::String(connection_type.into()));
+    if let Some(ssid) = &network.wifi_ssid {
+        table.insert("wifi_ssid".into(), Value::String(ssid.clone()));
+    }
+    if let Some(password) = &network.wifi_password_encrypted {
+        table.insert("wifi_password".into(), Value::String(hex_encode(password)));
+    }
+    if let Some(ip) = &network.static_ip {
+        let mut static_ip = Table::new();
+        static_ip.insert("ip".into(), Value::String(format_ipv4(ip.ip)));
+        static_ip.insert("gateway".into(), Value::String(format_ipv4(ip.gateway)));
+        static_ip.insert("subnet_mask".into(), Value::String(format_ipv4(ip.subnet_mask)));
+        let dns = ip
+            .dns
+            .iter()
+            .map(|addr| Value::String(format_ipv4(*addr)))
+            .collect();
+        static_ip.insert("dns".into(), Value::Array(dns));
+        table.insert("static_ip".into(), Value::Table(static_ip));
+    }
+    if let Some(pem) = &network.tls_extra_ca_pem {
+        let mut tls = Table::new();
+        tls.insert("extra_ca_pem".into(), Value::String(pem.clone()));
+        table.insert("tls".into(), Value::Table(tls));
+    }
+    if let Some(server) = &network.ntp_server {
+        table.insert("ntp_server".into(), Value::String(server.clone()));
+    }
+    if let Some(proxy) = &network.proxy {
+        let mut entry = Table::new();
+        entry.insert("host".into(), Value::String(proxy.host.clone()));
+        entry.insert("port".into(), Value::Integer(proxy.port as i64));
+        if let Some(username) = &proxy.username {
+            entry.insert("username".into(), Value::String(username.clone()));
+        }
+        if let Some(password) = &proxy.password_encrypted {
+            entry.insert("password".into(), Value::String(hex_encode(password)));
+        }
+        table.insert("proxy".into(), Value::Table(entry));
+    }
+    if !network.mdns {
+        table.insert("mdns".into(), Value::Boolean(false));
+    }
+    Value::Table(table)
+}
+
+fn network_from_value(value: &Value) -> Result<NetworkConfig, ConfigError> {
+    let table = as_table(value, "network")?;
+    let mut network = NetworkConfig::default();
+
+    if let Some(kind) = get_str(table, "network.connection_type")? {
+        network.connection_type = match kind {
+            "ethernet" => ConnectionType::Ethernet,
+            "wifi" => ConnectionType::Wifi,
+            other => {
+                return Err(ConfigError::invalid_value(&format!(
+                    "network.connection_type: unknown type '{}'",
+                    other
+                )))
+            }
+        };
+    }
+    network.wifi_ssid = get_str(table, "network.wifi_ssid")?.map(String::from);
+    network.wifi_password_encrypted = get_str(table, "network.wifi_password")?
+        .map(|hex| hex_decode(hex, "network.wifi_password"))
+        .transpose()?;
+
+    if let Some(static_ip) = table.get("static_ip") {
+        let ip_table = as_table(static_ip, "network.static_ip")?;
+        let required_ip = |key: &str| -> Result<[u8; 4], ConfigError> {
+            let path = format!("network.static_ip.{}", key);
+            let text = get_str(ip_table, &path)?.ok_or_else(|| ConfigError::missing_key(&path))?;
+            parse_ipv4(text, &path)
+        };
+        let mut dns = Vec::new();
+        if let Some(servers) = ip_table.get("dns") {
+            let Value::Array(servers) = servers else {
+                return Err(ConfigError::invalid_value("network.static_ip.dns: expected array"));
+            };
+            for server in servers {
+                let Value::String(text) = server else {
+                    return Err(ConfigError::invalid_value(
+                        "network.static_ip.dns: expected string entries",
+                    ));
+                };
+                dns.push(parse_ipv4(text, "network.static_ip.dns")?);
+            }
+        }
+        // `prefix_len = 24` may be given instead of `subnet_mask`
+        let subnet_mask = match get_u64(ip_table, "network.static_ip.prefix_len")? {
+            Some(prefix_len) => u8::try_from(prefix_len)
+                .ok()
+                .and_then(IpConfig::mask_from_prefix)
+                .ok_or_else(|| {
+                    ConfigError::invalid_value(&format!(
+                        "network.static_ip.prefix_len: {} is not in 0-32",
+                        prefix_len
+                    ))
+                })?,
+            None => required_ip("subnet_mask")?,
+        };
+        let static_ip = IpConfig {
+            ip: required_ip("ip")?,
+            gateway: required_ip("gateway")?,
+            subnet_mask,
+            dns,
+        };
+        validate_static_ip(&static_ip)?;
+        network.static_ip = Some(static_ip);
+    }
+
+    if let Some(tls) = table.get("tls") {
+        let tls = as_table(tls, "network.tls")?;
+        let path = "network.tls.extra_ca_pem";
+        if let Some(pem) = get_str(tls, path)? {
+            decode_pem_certificates(pem, path)?;
+            network.tls_extra_ca_pem = Some(pem.into());
+        }
+    }
+    network.ntp_server = get_str(table, "network.ntp_server")?.map(String::from);
+
+    if let Some(proxy) = table.get("proxy") {
+        let proxy = as_table(proxy, "network.proxy")?;
+        let host = get_str(proxy, "network.proxy.host")?
+            .filter(|host| !host.is_empty())
+            .ok_or_else(|| ConfigError::missing_key("network.proxy.host"))?;
+        let port = get_u64(proxy, "network.proxy.port")?
+            .ok_or_else(|| ConfigError::missing_key("network.proxy.port"))?;
+        let port = u16::try_from(port).ok().filter(|&port| port != 0).ok_or_else(|| {
+            ConfigError::invalid_value(&format!("network.proxy.port: {} is not a valid port", port))
+        })?;
+        network.proxy = Some(ProxyConfig {
+            host: host.into(),
+            port,
+            username: get_str(proxy, "network.proxy.username")?.map(String::from),
+            password_encrypted: get_str(proxy, "network.proxy.password")?
+                .map(|hex| hex_decode(hex, "network.proxy.password"))
+                .transpose()?,
+        });
+    }
+    match table.get("mdns") {
+        None => {}
+        Some(Value::Boolean(b)) => network.mdns = *b,
+        Some(_) => {
+            return Err(ConfigError::invalid_value(
+                "network.mdns: expected boolean",
+            ))
+        }
+    }
+
+    Ok(network)
+}
+
+/// Reject static addressing that can't work: a non-contiguous mask, an
+/// address that isn't a usable host, or a gateway off the subnet
+fn validate_static_ip(static_ip: &IpConfig) -> Result<(), ConfigError> {
+    let invalid = |key: &str, reason: String| {
+        ConfigError::invalid_value(&format!("network.static_ip.{}: {}", key, reason))
+    };
+    let prefix_len = static_ip.prefix_len().ok_or_else(|| {
+        invalid(
+            "subnet_mask",
+            format!("{} is not a valid mask", format_ipv4(static_ip.subnet_mask)),
+        )
+    })?;
+    let mask = u32::from_be_bytes(static_ip.subnet_mask);
+    let ip = u32::from_be_bytes(static_ip.ip);
+
+    if !is_unicast_host(static_ip.ip) {
+        return Err(invalid(
+            "ip",
+            format!("{} is not a host address", format_ipv4(static_ip.ip)),
+        ));
+    }
+    // /31 and /32 links have no network or broadcast address
+    if prefix_len < 31 && (ip & !mask == 0 || ip | mask == u32::MAX) {
+        return Err(invalid(
+            "ip",
+            format!(
+                "{} is the subnet's network or broadcast address",
+                format_ipv4(static_ip.ip)
+            ),
+        ));
+    }
+
+    if static_ip.gateway != [0; 4] {
+        let gateway = u32::from_be_bytes(static_ip.gateway);
+        if !is_unicast_host(static_ip.gateway) || gateway == ip || gateway & mask != ip & mask {
+            return Err(invalid(
+                "gateway",
+                format!(
+                    "{} is not another host on the subnet",
+                    format_ipv4(static_ip.gateway)
+                ),
+            ));
+        }
+    }
+
+    for &server in &static_ip.dns {
+        if !is_unicast_host(server) {
+            return Err(invalid(
+                "dns",
+                format!("{} is not a server address", format_ipv4(server)),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Not "this network", loopback, multicast or reserved/broadcast
+fn is_unicast_host(addr: [u8; 4]) -> bool {
+    !matches!(addr[0], 0 | 127 | 224..=255)
+}
+
+fn providers_to_value(providers: &ProviderConfigs) -> Value {
+    let mut table = Table::new();
+    let cloud = [
+        ("openai", &providers.openai),
+        ("anthropic", &providers.anthropic),
+        ("groq", &providers.groq),
+        ("xai", &providers.xai),
+        ("azure", &providers.azure),
+        ("custom", &providers.custom),
+    ];
+    for (name, provider) in cloud {
+        if let Some(provider) = provider {
+            table.insert(name.into(), provider_to_value(provider));
+        }
+    }
+    let local = [("ollama", &providers.ollama), ("local", &providers.local)];
+    for (name, provider) in local {
+        if let Some(provider) = provider {
+            let mut entry = Table::new();
+            entry.insert("endpoint".into(), Value::String(provider.endpoint.clone()));
+            entry.insert("default_model".into(), Value::String(provider.default_model.clone()));
+            table.insert(name.into(), Value::Table(entry));
+        }
+    }
+    Value::Table(table)
+}
+
+fn provider_to_value(provider: &ProviderConfig) -> Value {
+    let mut table = Table::new();
+    table.insert("api_key".into(), Value::String(hex_encode(&provider.api_key_encrypted)));
+    table.insert("default_model".into(), Value::String(provider.default_model.clone()));
+    let azure = [
+        ("azure_resource", &provider.azure_resource),
+        ("azure_deployment", &provider.azure_deployment),
+        ("azure_api_version", &provider.azure_api_version),
+    ];
+    for (key, setting) in azure {
+        if let Some(setting) = setting {
+            table.insert(key.into(), Value::String(setting.clone()));
+        }
+    }
+    if let Some(base_url) = &provider.base_url {
+        table.insert("base_url".into(), Value::String(base_url.clone()));
+    }
+    let timeouts = [
+        ("connect_timeout_ms", provider.connect_timeout_ms),
+        ("read_timeout_ms", provider.read_timeout_ms),
+    ];
+    for (key, timeout) in timeouts {
+        if let Some(timeout) = timeout {
+            table.insert(key.into(), Value::Integer(timeout as i64));
+        }
+    }
+    if let Some(fingerprint) = &provider.pinned_cert_sha256 {
+        table.insert("pinned_cert_sha256".into(), Value::String(hex_encode(fingerprint)));
+    }
+    Value::Table(table)
+}
+
+fn providers_from_value(value: &Value) -> Result<ProviderConfigs, ConfigError> {
+    let table = as_table(value, "providers")?;
+    let cloud = |name: &str| -> Result<Option<ProviderConfig>, ConfigError> {
+        table
+            .get(name)
+            .map(|entry| provider_from_value(entry, name))
+            .transpose()
+    };
+    let local = |name: &str| -> Result<Option<LocalProviderConfig>, ConfigError> {
+        let Some(entry) = table.get(name) else {
+            return Ok(None);
+        };
+        let path = format!("providers.{}", name);
+        let entry = as_table(entry, &path)?;
+        Ok(Some(LocalProviderConfig {
+            endpoint: get_str(entry, &format!("{}.endpoint", path))?
+                .unwrap_or_default()
+                .to_string(),
+            default_model: get_str(entry, &format!("{}.default_model", path))?
+                .unwrap_or_default()
+                .to_string(),
+        }))
+    };
+
+    Ok(ProviderConfigs {
+        openai: cloud("openai")?,
+        anthropic: cloud("anthropic")?,
+        groq: cloud("groq")?,
+        xai: cloud("xai")?,
+        azure: cloud("azure")?,
+        custom: cloud("custom")?,
+        ollama: local("ollama")?,
+        local: local("local")?,
+    })
+}
+
+fn provider_from_value(value: &Value, name: &str) -> Result<ProviderConfig, ConfigError> {
+    let path = format!("providers.{}", name);
+    let table = as_table(value, &path)?;
+    let key_path = format!("{}.api_key", path);
+    let api_key_encrypted = match get_str(table, &key_path)? {
+        Some(hex) => hex_decode(hex, &key_path)?,
+        None => Vec::new(),
+    };
+    let default_model = get_str(table, &format!("{}.default_model", path))?
+        .unwrap_or_default()
+        .to_string();
+
+    let mut provider = ProviderConfig::new(api_key_encrypted, default_model);
+    provider.azure_resource =
+        get_str(table, &format!("{}.azure_resource", path))?.map(String::from);
+    provider.azure_deployment =
+        get_str(table, &format!("{}.azure_deployment", path))?.map(String::from);
+    provider.azure_api_version =
+        get_str(table, &format!("{}.azure_api_version", path))?.map(String::from);
+    provider.base_url = get_str(table, &format!("{}.base_url", path))?.map(String::from);
+    provider.connect_timeout_ms = get_u64(table, &format!("{}.connect_timeout_ms", path))?;
+    provider.read_timeout_ms = get_u64(table, &format!("{}.read_timeout_ms", path))?;
+    let pin_path = format!("{}.pinned_cert_sha256", path);
+    if let Some(fingerprint) = get_str(table, &pin_path)? {
+        // Accept the colon-separated form tools print, e.g. "AB:CD:..."
+        let hex: String = fingerprint.chars().filter(|c| *c != ':').collect();
+        let bytes = hex_decode(&hex, &pin_path)?;
+        let fingerprint = <[u8; 32]>::try_from(bytes.as_slice()).map_err(|_| {
+            ConfigError::invalid_value(&format!("{}: expected a 32-byte SHA-256 fingerprint", pin_path))
+        })?;
+        provider.pinned_cert_sha256 = Some(fingerprint);
+    }
+    Ok(provider)
+}
+
+fn preferences_to_value(preferences: &Preferences) -> Value {
+    let mut table = Table::new();
+    table.insert(
+        "default_provider".into(),
+        Value::String(preferences.default_provider.clone()),
+    );
+    table.insert(
+        "default_model".into(),
+        Value::String(preferences.default_model.clone()),
+    );
+    let theme = match preferences.theme {
+        ThemeChoice::Dark => "dark",
+        ThemeChoice::Light => "light",
+    };
+    table.insert("theme".into(), Value::String(theme.into()));
+    if !preferences.theme_colors.is_empty() {
+        let colors = preferences
+            .theme_colors
+            .iter()
+            .map(|(name, hex)| (name.clone(), Value::String(hex.clone())))
+            .collect();
+        table.insert("theme_colors".into(), Value::Table(colors));
+    }
+    table.insert(
+        "temperature".into(),
+        Value::Float(preferences.temperature as f64),
+    );
+    table.insert(
+        "stream_responses".into(),
+        Value::Boolean(preferences.stream_responses),
+    );
+    let optional = [
+        ("top_p", preferences.top_p),
+        ("presence_penalty", preferences.presence_penalty),
+        ("frequency_penalty", preferences.frequency_penalty),
+    ];
+    for (key, setting) in optional {
+        if let Some(setting) = setting {
+            table.insert(key.into(), Value::Float(setting as f64));
+        }
+    }
+    if !preferences.stop_sequences.is_empty() {
+        let stops = preferences
+            .stop_sequences
+            .iter()
+            .map(|s| Value::String(s.clone()))
+            .collect();
+        table.insert("stop_sequences".into(), Value::Array(stops));
+    }
+    if !preferences.system_prompt.is_empty() {
+        table.insert(
+            "system_prompt".into(),
+            Value::String(preferences.system_prompt.clone()),
+        );
+    }
+    if !preferences.personas.is_empty() {
+        // Stored as a name -> prompt table
+        let personas = preferences
+            .personas
+            .iter()
+            .map(|p| (p.name.clone(), Value::String(p.system_prompt.clone())))
+            .collect();
+        table.insert("personas".into(), Value::Table(personas));
+    }
+    if preferences.debug_llm {
+        table.insert("debug_llm".into(), Value::Boolean(true));
+    }
+    if preferences.debug_tls {
+        table.insert("debug_tls".into(), Value::Boolean(true));
+    }
+    table.insert(
+        "history_max_bytes".into(),
+        Value::Integer(preferences.history_max_bytes as i64),
+    );
+    if preferences.archive_conversations {
+        table.insert("archive_conversations".into(), Value::Boolean(true));
+    }
+    Value::Table(table)
+}
+
+fn preferences_from_value(value: &Value) -> Result<Preferences, ConfigError> {
+    let table = as_table(value, "preferences")?;
+    let mut preferences = Preferences::default();
+
+    if let Some(provider) = get_str(table, "preferences.default_provider")? {
+        preferences.default_provider = provider.into();
+    }
+    if let Some(model) = get_str(table, "preferences.default_model")? {
+        preferences.default_model = model.into();
+    }
+    if let Some(theme) = get_str(table, "preferences.theme")? {
+        preferences.theme = match theme {
+            "dark" => ThemeChoice::Dark,
+            "light" => ThemeChoice::Light,
+            other => {
+                return Err(ConfigError::invalid_value(&format!(
+                    "preferences.theme: unknown theme '{}'",
+                    other
+                )))
+            }
+        };
+    }
+    if let Some(colors) = table.get("theme_colors") {
+        for (name, hex) in as_table(colors, "preferences.theme_colors")? {
+            let Value::String(hex) = hex else {
+                return Err(ConfigError::invalid_value(&format!(
+                    "preferences.theme_colors.{}: expected string",
+                    name
+                )));
+            };
+            preferences.theme_colors.push((name.clone(), hex.clone()));
+        }
+    }
+    if let Some(temperature) = get_f32(table, "preferences.temperature")? {
+        preferences.temperature = temperature;
+    }
+    preferences.top_p = get_f32(table, "preferences.top_p")?;
+    preferences.presence_penalty = get_f32(table, "preferences.presence_penalty")?;
+    preferences.frequency_penalty = get_f32(table, "preferences.frequency_penalty")?;
+    match table.get("stream_responses") {
+        None => {}
+        Some(Value::Boolean(b)) => preferences.stream_responses = *b,
+        Some(_) => {
+            return Err(ConfigError::invalid_value(
+                "preferences.stream_responses: expected boolean",
+            ))
+        }
+    }
+
+    match table.get("debug_llm") {
+        None => {}
+        Some(Value::Boolean(b)) => preferences.debug_llm = *b,
+        Some(_) => {
+            return Err(ConfigError::invalid_value(
+                "preferences.debug_llm: expected boolean",
+            ))
+        }
+    }
+    match table.get("debug_tls") {
+        None => {}
+        Some(Value::Boolean(b)) => preferences.debug_tls = *b,
+        Some(_) => {
+            return Err(ConfigError::invalid_value(
+                "preferences.debug_tls: expected boolean",
+            ))
+        }
+    }
+    if let Some(max) = get_u64(table, "preferences.history_max_bytes")? {
+        preferences.history_max_bytes = max as usize;
+    }
+    match table.get("archive_conversations") {
+        None => {}
+        Some(Value::Boolean(b)) => preferences.archive_conversations = *b,
+        Some(_) => {
+            return Err(ConfigError::invalid_value(
+                "preferences.archive_conversations: expected boolean",
+            ))
+        }
+    }
+
+    if let Some(stops) = table.get("stop_sequences") {
+        let Value::Array(stops) = stops else {
+            return Err(ConfigError::invalid_value(
+                "preferences.stop_sequences: expected array",
+            ));
+        };
+        for stop in stops {
+            let Value::String(stop) = stop else {
+                return Err(ConfigError::invalid_value(
+                    "preferences.stop_sequences: expected string entries",
+                ));
+            };
+            preferences.stop_sequences.push(stop.clone());
+        }
+    }
+    if let Some(prompt) = get_str(table, "preferences.system_prompt")? {
+        preferences.system_prompt = prompt.into();
+    }
+    if let Some(personas) = table.get("personas") {
+        for (name, prompt) in as_table(personas, "preferences.personas")? {
+            let Value::String(prompt) = prompt else {
+                return Err(ConfigError::invalid_value(&format!(
+                    "preferences.personas.{}: expected string",
+                    name
+                )));
+            };
+            preferences
+                .personas
+                .push(Persona::new(name.clone(), prompt.clone()));
+        }
+    }
+
+    Ok(preferences)
+}
+
+fn as_table<'a>(value: &'a Value, path: &str) -> Result<&'a Table, ConfigError> {
+    match value {
+        Value::Table(table) => Ok(table),
+        _ => Err(ConfigError::invalid_value(&format!(
+            "{}: expected table",
+            path
+        ))),
+    }
+}
+
+/// Look up an optional string key; `path` is the dotted name used in errors
+fn get_str<'a>(table: &'a Table, path: &str) -> Result<Option<&'a str>, ConfigError> {
+    let key = path.rsplit('.').next().unwrap_or(path);
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.as_str())),
+        Some(_) => Err(ConfigError::invalid_value(&format!(
+            "{}: expected string",
+            path
+        ))),
+    }
+}
+
+/// Look up an optional number key, accepting integers as well as floats
+fn get_f32(table: &Table, path: &str) -> Result<Option<f32>, ConfigError> {
+    let key = path.rsplit('.').next().unwrap_or(path);
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Float(f)) => Ok(Some(*f as f32)),
+        Some(Value::Integer(i)) => Ok(Some(*i as f32)),
+        Some(_) => Err(ConfigError::invalid_value(&format!(
+            "{}: expected number",
+            path
+        ))),
+    }
+}
+
+/// Look up an optional non-negative integer key
+fn get_u64(table: &Table, path: &str) -> Result<Option<u64>, ConfigError> {
+    let key = path.rsplit('.').next().unwrap_or(path);
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Integer(i)) if *i >= 0 => Ok(Some(*i as u64)),
+        Some(_) => Err(ConfigError::invalid_value(&format!(
+            "{}: expected non-negative integer",
+            path
+        ))),
+    }
+}
+
+fn format_ipv4(addr: [u8; 4]) -> String {
+    format!("{}.{}.{}.{}", addr[0], addr[1], addr[2], addr[3])
+}
+
+fn parse_ipv4(text: &str, path: &str) -> Result<[u8; 4], ConfigError> {
+    let invalid = || ConfigError::invalid_value(&format!("{}: invalid IPv4 address '{}'", path, text));
+    let mut addr = [0u8; 4];
+    let mut parts = text.split('.');
+    for octet in addr.iter_mut() {
+        *octet = parts
+            .next()
+            .and_then(|p| p.parse::<u8>().ok())
+            .ok_or_else(invalid)?;
+    }
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    Ok(addr)
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        out.push(DIGITS[(byte >> 4) as usize] as char);
+        out.push(DIGITS[(byte & 0x0f) as usize] as char);
+    }
+    out
+}
+
+fn hex_decode(text: &str, path: &str) -> Result<Vec<u8>, ConfigError> {
+    let invalid = || ConfigError::invalid_value(&format!("{}: invalid hex string", path));
+    if !text.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let hi = (pair[0] as char).to_digit(16).ok_or_else(invalid)?;
+            let lo = (pair[1] as char).to_digit(16).ok_or_else(invalid)?;
+            Ok((hi * 16 + lo) as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toml::TomlParser;
+
+    #[test]
+    fn test_roundtrip_through_toml() {
+        let mut config = MoteConfig::default();
+        config.network.connection_type = ConnectionType::Wifi;
+        config.network.wifi_ssid = Some("cafe".into());
+        config.network.static_ip = Some(IpConfig {
+            ip: [192, 168, 1, 20],
+            gateway: [192, 168, 1, 1],
+            dns: alloc::vec![[1, 1, 1, 1]],
+            subnet_mask: [255, 255, 255, 0],
+        });
+        config.network.tls_extra_ca_pem =
+            Some("-----BEGIN CERTIFICATE-----\nMAMCAQU=\n-----END CERTIFICATE-----\n".into());
+        config.network.ntp_server = Some("time.cloudflare.com".into());
+        config.network.proxy = Some(ProxyConfig {
+            host: "proxy.corp".into(),
+            port: 3128,
+            username: Some("alice".into()),
+            password_encrypted: Some(b"hunter2".to_vec()),
+        });
+        config.network.mdns = false;
+        let mut openai = ProviderConfig::new(b"sk-test".to_vec(), "gpt-4o".into());
+        openai.base_url = Some("https://proxy.internal:8443".into());
+        openai.read_timeout_ms = Some(90_000);
+        openai.pinned_cert_sha256 = Some([0xab; 32]);
+        config.providers.openai = Some(openai);
+        config.preferences.default_provider = "openai".into();
+        config.preferences.theme = ThemeChoice::Light;
+        config.preferences.theme_colors =
+            alloc::vec![(String::from("accent_primary"), String::from("#FF8800"))];
+        config.preferences.top_p = Some(0.5);
+        config.preferences.stop_sequences = alloc::vec![String::from("END")];
+        config.preferences.system_prompt = "Be \"brief\".".into();
+        config.preferences.personas = alloc::vec![
+            Persona::new("code review".into(), "You review Rust code.".into()),
+            Persona::new("pirate".into(), "Talk like a pirate.".into()),
+        ];
+        config.preferences.debug_llm = true;
+        config.preferences.debug_tls = true;
+        config.preferences.history_max_bytes = 0;
+        config.preferences.archive_conversations = true;
+
+        let toml = TomlParser::serialize(&config.to_value()).unwrap();
+        let parsed = MoteConfig::from_value(&TomlParser::parse(&toml).unwrap()).unwrap();
+
+        assert_eq!(parsed.network.connection_type, ConnectionType::Wifi);
+        assert_eq!(parsed.network.wifi_ssid.as_deref(), Some("cafe"));
+        let ip = parsed.network.static_ip.unwrap();
+        assert_eq!(ip.ip, [192, 168, 1, 20]);
+        assert_eq!(ip.dns, alloc::vec![[1, 1, 1, 1]]);
+        assert_eq!(parsed.network.tls_extra_ca_pem, config.network.tls_extra_ca_pem);
+        assert_eq!(parsed.network.ntp_server.as_deref(), Some("time.cloudflare.com"));
+        assert_eq!(parsed.network.proxy, config.network.proxy);
+        assert!(!parsed.network.mdns);
+        let openai = parsed.providers.openai.unwrap();
+        assert_eq!(openai.api_key_encrypted, b"sk-test".to_vec());
+        assert_eq!(openai.default_model, "gpt-4o");
+        assert_eq!(openai.base_url.as_deref(), Some("https://proxy.internal:8443"));
+        assert_eq!(openai.connect_timeout_ms, None);
+        assert_eq!(openai.read_timeout_ms, Some(90_000));
+        assert_eq!(openai.pinned_cert_sha256, Some([0xab; 32]));
+        assert!(parsed.providers.anthropic.is_none());
+        assert_eq!(parsed.preferences.default_provider, "openai");
+        assert_eq!(parsed.preferences.theme, ThemeChoice::Light);
+        assert_eq!(
+            parsed.preferences.theme_colors,
+            config.preferences.theme_colors
+        );
+        assert_eq!(parsed.preferences.top_p, Some(0.5));
+        assert_eq!(parsed.preferences.presence_penalty, None);
+        assert_eq!(parsed.preferences.stop_sequences, alloc::vec![String::from("END")]);
+        assert_eq!(parsed.preferences.system_prompt, "Be \"brief\".");
+        assert_eq!(parsed.preferences.personas, config.preferences.personas);
+        assert!(parsed.preferences.debug_llm);
+        assert!(parsed.preferences.debug_tls);
+        assert_eq!(parsed.preferences.history_max_bytes, 0);
+        assert!(parsed.preferences.archive_conversations);
+    }
+
+    #[test]
+    fn test_missing_sections_use_defaults() {
+        let config = MoteConfig::from_value(&Value::Table(Table::new())).unwrap();
+        assert_eq!(config.preferences.default_provider, "local");
+        assert_eq!(
+            config.preferences.history_max_bytes,
+            crate::history::DEFAULT_HISTORY_MAX_BYTES
+        );
+        assert!(config.providers.openai.is_none());
+    }
+
+    #[test]
+    fn test_wrong_type_is_rejected() {
+        let mut preferences = Table::new();
+        preferences.insert("theme".into(), Value::Integer(3));
+        let mut root = Table::new();
+        root.insert("preferences".into(), Value::Table(preferences));
+        assert!(matches!(
+            MoteConfig::from_value(&Value::Table(root)),
+            Err(ConfigError::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    fn test_static_ip_is_validated() {
+        let parse = |body: &str| {
+            let toml = format!("[network.static_ip]\nip = \"192.168.1.20\"\n{}", body);
+            MoteConfig::from_value(&TomlParser::parse(&toml).unwrap())
+        };
+
+        let config = parse("gateway = \"192.168.1.1\"\nprefix_len = 24\ndns = [\"1.1.1.1\"]").unwrap();
+        let ip = config.network.static_ip.unwrap();
+        assert_eq!(ip.subnet_mask, [255, 255, 255, 0]);
+        assert_eq!(ip.prefix_len(), Some(24));
+        assert!(parse("gateway = \"0.0.0.0\"\nsubnet_mask = \"255.255.0.0\"").is_ok());
+
+        for (body, key) in [
+            ("gateway = \"192.168.1.1\"\nprefix_len = 33", "prefix_len"),
+            ("gateway = \"192.168.1.1\"\nsubnet_mask = \"255.0.255.0\"", "subnet_mask"),
+            ("gateway = \"192.168.2.1\"\nprefix_len = 24", "gateway"),
+            ("gateway = \"192.168.1.20\"\nprefix_len = 24", "gateway"),
+            ("gateway = \"192.168.1.1\"\nprefix_len = 24\ndns = [\"0.0.0.0\"]", "dns"),
+        ] {
+            assert!(matches!(
+                parse(body),
+                Err(ConfigError::InvalidValue(msg)) if msg.contains(key)
+            ));
+        }
+
+        // Not a host address, or the subnet's broadcast address
+        let toml = "[network.static_ip]\nip = \"192.168.1.255\"\ngateway = \"192.168.1.1\"\nprefix_len = 24";
+        assert!(MoteConfig::from_value(&TomlParser::parse(toml).unwrap()).is_err());
+        let toml = "[network.static_ip]\nip = \"224.0.0.1\"\ngateway = \"0.0.0.0\"\nprefix_len = 0";
+        assert!(MoteConfig::from_value(&TomlParser::parse(toml).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_proxy_is_rejected() {
+        let toml = "[network.proxy]\nhost = \"proxy.corp\"\nport = 70000";
+        let value = TomlParser::parse(toml).unwrap();
+        assert!(matches!(
+            MoteConfig::from_value(&value),
+            Err(ConfigError::InvalidValue(msg)) if msg.contains("network.proxy.port")
+        ));
+
+        let toml = "[network.proxy]\nport = 3128";
+        let value = TomlParser::parse(toml).unwrap();
+        assert!(MoteConfig::from_value(&value).is_err());
+    }
+
+    #[test]
+    fn test_invalid_tls_settings_are_rejected() {
+        let toml = "[network.tls]\nextra_ca_pem = \"-----BEGIN CERTIFICATE-----\\nnot base64!\\n-----END CERTIFICATE-----\"";
+        let value = TomlParser::parse(toml).unwrap();
+        assert!(matches!(
+            MoteConfig::from_value(&value),
+            Err(ConfigError::InvalidValue(msg)) if msg.contains("extra_ca_pem")
+        ));
+
+        let toml = "[providers.custom]\npinned_cert_sha256 = \"ab:cd\"";
+        let value = TomlParser::parse(toml).unwrap();
+        assert!(matches!(
+            MoteConfig::from_value(&value),
+            Err(ConfigError::InvalidValue(msg)) if msg.contains("pinned_cert_sha256")
+        ));
+    }
+
+    /// Validation messages, to check which keys were reported
+    fn violations(config: &MoteConfig) -> Vec<String> {
+        match config.validate() {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .into_iter()
+                .map(|error| match error {
+                    ConfigError::Validation(msg) => msg,
+                    other => panic!("unexpected error {:?}", other),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_validate_default_provider() {
+        assert_eq!(MoteConfig::default().validate(), Ok(()));
+
+        let mut config = MoteConfig::default();
+        config.preferences.default_provider = "gemini".into();
+        assert_eq!(
+            violations(&config),
+            ["preferences.default_provider: unknown provider 'gemini'"]
+        );
+
+        config.preferences.default_provider = "openai".into();
+        assert!(violations(&config)[0].contains("[providers.openai] is missing"));
+        config.providers.openai = Some(ProviderConfig::new(Vec::new(), "gpt-4o".into()));
+        assert_eq!(violations(&config), ["providers.openai.api_key: must not be empty"]);
+        config.providers.openai = Some(ProviderConfig::new(b"sk-test".to_vec(), "gpt-4o".into()));
+        assert_eq!(config.validate(), Ok(()));
+
+        // Azure also needs its resource and deployment
+        config.preferences.default_provider = "azure".into();
+        config.providers.azure = Some(ProviderConfig::new(b"key".to_vec(), "gpt-4o".into()));
+        let errors = violations(&config);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("providers.azure.azure_resource"));
+        assert!(errors[1].contains("providers.azure.azure_deployment"));
+
+        // A custom server may go without a key but not without a URL
+        config.preferences.default_provider = "custom".into();
+        config.providers.custom = Some(ProviderConfig::new(Vec::new(), "llama3".into()));
+        assert_eq!(
+            violations(&config),
+            ["providers.custom.base_url: required for the custom provider"]
+        );
+        config.providers.custom.as_mut().unwrap().base_url = Some("http://10.0.0.5:8080".into());
+        assert_eq!(config.validate(), Ok(()));
+
+        config.preferences.default_provider = "ollama".into();
+        config.providers.ollama =
+            Some(LocalProviderConfig { endpoint: " ".into(), default_model: "llama3".into() });
+        assert_eq!(violations(&config), ["providers.ollama.endpoint: must not be empty"]);
+    }
+
+    #[test]
+    fn test_validate_theme() {
+        // The theme itself must be one the TUI has, which loading enforces
+        let parse = |theme: &str| {
+            let toml = format!("[preferences]\ntheme = \"{}\"", theme);
+            MoteConfig::from_value(&TomlParser::parse(&toml).unwrap())
+        };
+        assert_eq!(
+            parse("light").unwrap().preferences.theme,
+            ThemeChoice::Light
+        );
+        assert!(matches!(
+            parse("solarized"),
+            Err(ConfigError::InvalidValue(msg)) if msg.contains("unknown theme 'solarized'")
+        ));
+
+        // Overrides must name a known color and parse
+        let mut config = MoteConfig::default();
+        config.preferences.theme_colors = alloc::vec![
+            (String::from("accent_primary"), String::from("#FF8800")),
+            (String::from("accent_primary"), String::from("#F80")),
+            (String::from("accent_pirmary"), String::from("#FF8800")),
+            (String::from("background"), String::from("#12345")),
+        ];
+        assert_eq!(
+            violations(&config),
+            [
+                "preferences.theme_colors.accent_pirmary: unknown color",
+                "preferences.theme_colors.background: '#12345' is not a #RGB, #RRGGBB or #RRGGBBAA color",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_static_ip() {
+        let mut config = MoteConfig::default();
+        config.network.static_ip = Some(IpConfig {
+            ip: [192, 168, 1, 20],
+            gateway: [192, 168, 1, 1],
+            dns: Vec::new(),
+            subnet_mask: [255, 255, 255, 0],
+        });
+        assert_eq!(config.validate(), Ok(()));
+
+        config.network.static_ip.as_mut().unwrap().gateway = [10, 0, 0, 1];
+        let errors = violations(&config);
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].contains("network.static_ip.gateway"),
+            "{}",
+            errors[0]
+        );
+    }
+
+    #[test]
+    fn test_validate_sampling_ranges() {
+        let mut config = MoteConfig::default();
+        config.preferences.temperature = 2.0;
+        config.preferences.top_p = Some(1.0);
+        config.preferences.presence_penalty = Some(-2.0);
+        assert_eq!(config.validate(), Ok(()));
+
+        config.preferences.temperature = 2.5;
+        config.preferences.top_p = Some(1.5);
+        config.preferences.presence_penalty = Some(-2.5);
+        config.preferences.frequency_penalty = Some(f32::NAN);
+        let errors = violations(&config);
+        let keys = [
+            "preferences.temperature: 2.5 is not in 0 to 2",
+            "preferences.top_p: 1.5 is not in 0 to 1",
+            "preferences.presence_penalty",
+            "preferences.frequency_penalty",
+        ];
+        assert_eq!(errors.len(), keys.len(), "{:?}", errors);
+        for (error, key) in errors.iter().zip(keys) {
+            assert!(error.contains(key), "{} should mention {}", error, key);
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_every_violation() {
+        let mut config = MoteConfig::default();
+        config.preferences.default_provider = "gemini".into();
+        config.preferences.theme_colors =
+            alloc::vec![(String::from("accent_pirmary"), String::from("#FF8800"))];
+        config.preferences.temperature = 2.5;
+        config.network.static_ip = Some(IpConfig {
+            ip: [192, 168, 1, 20],
+            gateway: [10, 0, 0, 1],
+            dns: Vec::new(),
+            subnet_mask: [255, 255, 255, 0],
+        });
+
+        let errors = violations(&config);
+        let keys = [
+            "preferences.default_provider",
+            "network.static_ip.gateway",
+            "preferences.theme_colors.accent_pirmary",
+            "preferences.temperature",
+        ];
+        assert_eq!(errors.len(), keys.len(), "{:?}", errors);
+        for (error, key) in errors.iter().zip(keys) {
+            assert!(error.contains(key), "{} should mention {}", error, key);
+        }
+    }
+}
@@ -10,14 +10,39 @@
 //!
 //! Before production use, this module MUST be updated to implement proper AES-256-GCM
 //! encryption using the `aes-gcm` crate and secure key derivation.
+//!
+//! Whole-config blobs (`encrypt_config`/`decrypt_config`) already use AES-256-GCM,
+//! behind a versioned header; only the key derivation is still a placeholder.
 
 #![no_std]
 
 extern crate alloc;
+use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use sha2::{Digest, Sha256};
+
 use crate::error::ConfigError;
+use crate::toml::{TomlParser, Value};
+
+/// First bytes of an encrypted config blob
+pub const CONFIG_BLOB_MAGIC: [u8; 7] = *b"MOTECFG";
+
+/// Format of the blob after the magic; bump when the layout changes
+///
+/// Version 1: 12-byte nonce, then the AES-256-GCM ciphertext of the TOML
+/// text with its 16-byte tag. Still read, never written.
+///
+/// Version 2: as version 1, but the nonce is random where the CPU has an
+/// RNG and the header (magic and version) is authenticated along with the
+/// ciphertext.
+pub const CONFIG_BLOB_VERSION: u8 = 2;
+
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = CONFIG_BLOB_MAGIC.len() + 1;
 
 /// Encrypts an API key for secure storage
 ///
@@ -70,6 +95,98 @@ pub fn decrypt_api_key(ciphertext: &[u8]) -> Result<String, ConfigError> {
     String::from_utf8(ciphertext.to_vec()).map_err(|_| ConfigError::DecryptionFailed)
 }
 
+/// Serializes `value` to TOML and encrypts it with AES-256-GCM under `key`
+///
+/// The result starts with `CONFIG_BLOB_MAGIC` and `CONFIG_BLOB_VERSION`,
+/// which are authenticated as associated data. The nonce comes from the
+/// hardware RNG; without one it is derived from the key and the plaintext,
+/// so different configs still never share a nonce.
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - Header, nonce and ciphertext
+/// * `Err(ConfigError)` - Serialization or encryption error
+pub fn encrypt_config(value: &Value, key: &[u8; 32]) -> Result<Vec<u8>, ConfigError> {
+    let toml = TomlParser::serialize(value).map_err(|e| {
+        ConfigError::serialization_error(&format!("Failed to serialize TOML: {:?}", e))
+    })?;
+
+    let nonce = generate_nonce().unwrap_or_else(|| {
+        let mut hasher = Sha256::new();
+        hasher.update(key);
+        hasher.update(toml.as_bytes());
+        let digest = hasher.finalize();
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&digest[..NONCE_LEN]);
+        nonce
+    });
+
+    let mut blob = Vec::with_capacity(HEADER_LEN + NONCE_LEN + toml.len() + 16);
+    blob.extend_from_slice(&CONFIG_BLOB_MAGIC);
+    blob.push(CONFIG_BLOB_VERSION);
+
+    let cipher = Aes256Gcm::new(key.into());
+    let payload = Payload {
+        msg: toml.as_bytes(),
+        aad: &blob,
+    };
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), payload)
+        .map_err(|_| ConfigError::EncryptionFailed)?;
+
+    blob.extend_from_slice(&nonce);
+    blob.extend_from_slice(&ciphertext);
+    Ok(blob)
+}
+
+/// Decrypts a blob made by `encrypt_config` and parses the TOML inside
+///
+/// # Returns
+/// * `Ok(Value)` - The configuration
+/// * `Err(ConfigError::DecryptionFailed)` - Wrong key, or the blob was altered
+/// * `Err(ConfigError)` - Not a config blob, an unknown version, or bad TOML
+pub fn decrypt_config(blob: &[u8], key: &[u8; 32]) -> Result<Value, ConfigError> {
+    if !is_encrypted_config(blob) {
+        return Err(ConfigError::deserialization_error(
+            "Not an encrypted config",
+        ));
+    }
+    let (header, body) = blob.split_at(HEADER_LEN);
+    let aad = match header[CONFIG_BLOB_MAGIC.len()] {
+        // Version 1 didn't authenticate its header
+        1 => &[][..],
+        CONFIG_BLOB_VERSION => header,
+        version => {
+            return Err(ConfigError::deserialization_error(&format!(
+                "Unsupported encrypted config version {}",
+                version
+            )));
+        }
+    };
+    if body.len() < NONCE_LEN {
+        return Err(ConfigError::DecryptionFailed);
+    }
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+
+    let cipher = Aes256Gcm::new(key.into());
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| ConfigError::DecryptionFailed)?;
+    let toml = core::str::from_utf8(&plaintext).map_err(|_| ConfigError::DecryptionFailed)?;
+    TomlParser::parse(toml)
+        .map_err(|e| ConfigError::deserialization_error(&format!("Failed to parse TOML: {:?}", e)))
+}
+
+/// Whether `data` starts like an `encrypt_config` blob (of any version)
+///
+/// Lets storage tell encrypted configs from ones saved as plain TOML.
+pub fn is_encrypted_config(data: &[u8]) -> bool {
+    data.len() >= HEADER_LEN && data.starts_with(&CONFIG_BLOB_MAGIC)
+}
+
 /// Derives an encryption key from hardware if available
 ///
 /// Attempts to use hardware-specific information (CPUID, serial numbers, TPM)
@@ -78,7 +195,12 @@ pub fn decrypt_api_key(ciphertext: &[u8]) -> Result<String, ConfigError> {
 ///
 /// # Returns
 /// * `[u8; 32]` - 256-bit encryption key
-fn derive_key() -> [u8; 32] {
+#[cfg(any(
+    target_os = "uefi",
+    all(target_arch = "x86_64", feature = "uefi"),
+    all(target_arch = "aarch64", feature = "uefi")
+))]
+pub(crate) fn derive_key() -> [u8; 32] {
     // TODO: Implement hardware key derivation
     //
     // Real implementation should:
@@ -98,14 +220,46 @@ fn derive_key() -> [u8; 32] {
 
 /// Generates a random nonce for AES-GCM
 ///
+/// Uses RDRAND where the CPU has it.
+///
 /// # Returns
-/// * `[u8; 12]` - 96-bit nonce
-fn generate_nonce() -> [u8; 12] {
-    // TODO: Implement proper random nonce generation
-    //
-    // Real implementation should use a CSPRNG or hardware RNG
-    // For now, return a dummy nonce (INSECURE)
-    [0u8; 12]
+/// * `Some([u8; 12])` - 96-bit nonce
+/// * `None` - No hardware RNG, or it failed
+#[cfg(target_arch = "x86_64")]
+fn generate_nonce() -> Option<[u8; NONCE_LEN]> {
+    use core::arch::x86_64::{__cpuid, _rdrand64_step};
+
+    /// # Safety
+    ///
+    /// The CPU must support RDRAND.
+    #[target_feature(enable = "rdrand")]
+    unsafe fn rdrand64() -> Option<u64> {
+        let mut value = 0;
+        // RDRAND can briefly run dry; Intel suggests retrying 10 times
+        for _ in 0..10 {
+            if _rdrand64_step(&mut value) == 1 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    // CPUID leaf 1, ECX bit 30
+    if __cpuid(1).ecx & (1 << 30) == 0 {
+        return None;
+    }
+    let mut nonce = [0u8; NONCE_LEN];
+    for chunk in nonce.chunks_mut(8) {
+        // SAFETY: CPUID reports RDRAND
+        let value = unsafe { rdrand64() }?;
+        chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]);
+    }
+    Some(nonce)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn generate_nonce() -> Option<[u8; NONCE_LEN]> {
+    None
 }
 
 #[cfg(test)]
@@ -121,4 +275,86 @@ mod tests {
 
         assert_eq!(api_key, decrypted);
     }
+
+    fn sample_config() -> Value {
+        TomlParser::parse(
+            "[network]\nwifi_ssid = \"Cafe\"\nwifi_password = \"hunter22\"\n\n[ai]\ndefault_provider = \"openai\"\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_config_roundtrip_hides_plaintext() {
+        let key = [7u8; 32];
+        let config = sample_config();
+
+        let blob = encrypt_config(&config, &key).unwrap();
+        assert!(blob.starts_with(b"MOTECFG\x02"));
+        assert!(is_encrypted_config(&blob));
+        assert!(!blob.windows(8).any(|w| w == b"hunter22"));
+        assert_eq!(decrypt_config(&blob, &key).unwrap(), config);
+    }
+
+    #[test]
+    fn test_config_wrong_key_or_tampering_fails() {
+        let config = sample_config();
+        let mut blob = encrypt_config(&config, &[7u8; 32]).unwrap();
+
+        assert_eq!(
+            decrypt_config(&blob, &[8u8; 32]),
+            Err(ConfigError::DecryptionFailed)
+        );
+        let last = blob.len() - 1;
+        blob[last] ^= 1;
+        assert_eq!(
+            decrypt_config(&blob, &[7u8; 32]),
+            Err(ConfigError::DecryptionFailed)
+        );
+
+        blob[last] ^= 1;
+
+        // The header is authenticated, so it can't be passed off as version 1
+        blob[CONFIG_BLOB_MAGIC.len()] = 1;
+        assert_eq!(
+            decrypt_config(&blob, &[7u8; 32]),
+            Err(ConfigError::DecryptionFailed)
+        );
+
+        blob[CONFIG_BLOB_MAGIC.len()] = CONFIG_BLOB_VERSION + 1;
+        assert!(matches!(
+            decrypt_config(&blob, &[7u8; 32]),
+            Err(ConfigError::DeserializationError(_))
+        ));
+        assert!(!is_encrypted_config(b"[network]\n"));
+    }
+
+    #[test]
+    fn test_config_nonce_is_random() {
+        if generate_nonce().is_none() {
+            // No hardware RNG on this machine
+            return;
+        }
+        let key = [7u8; 32];
+        let config = sample_config();
+        let first = encrypt_config(&config, &key).unwrap();
+        let second = encrypt_config(&config, &key).unwrap();
+        assert_ne!(first[HEADER_LEN..], second[HEADER_LEN..]);
+        assert_eq!(decrypt_config(&second, &key).unwrap(), config);
+    }
+
+    #[test]
+    fn test_config_version_1_still_decrypts() {
+        let key = [7u8; 32];
+        let config = sample_config();
+        let toml = TomlParser::serialize(&config).unwrap();
+        let nonce = [3u8; NONCE_LEN];
+        let ciphertext = Aes256Gcm::new((&key).into())
+            .encrypt(Nonce::from_slice(&nonce), toml.as_bytes())
+            .unwrap();
+
+        let mut blob = b"MOTECFG\x01".to_vec();
+        blob.extend_from_slice(&nonce);
+        blob.extend_from_slice(&ciphertext);
+        assert_eq!(decrypt_config(&blob, &key).unwrap(), config);
+    }
 }
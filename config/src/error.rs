@@ -17,6 +17,8 @@ pub enum ConfigError {
     EfiError(String),
     EncryptionFailed,
     DecryptionFailed,
+    /// A loaded configuration breaks an invariant (see `MoteConfig::validate`)
+    Validation(String),
 }
 
 impl ConfigError {
@@ -47,4 +49,8 @@ impl ConfigError {
     pub fn efi_error(msg: &str) -> Self {
         ConfigError::EfiError(String::from(msg))
     }
+
+    pub fn validation(msg: &str) -> Self {
+        ConfigError::Validation(String::from(msg))
+    }
 }
@@ -0,0 +1,275 @@
+//! Conversation history persistence
+//!
+//! The active conversation is stored as TOML (`[[messages]]` tables with a
+//! `role` and `content`) through any [`ConfigStorage`], normally its own EFI
+//! variable. EFI variable space is small and shared with the firmware, so
+//! the stored size is capped and the oldest messages are dropped to fit.
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::error::ConfigError;
+use crate::storage::ConfigStorage;
+use crate::toml::{TomlParser, Value};
+
+/// Default cap on the serialized history, in bytes
+pub const DEFAULT_HISTORY_MAX_BYTES: usize = 16 * 1024;
+
+/// Who sent a stored message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoredRole {
+    System,
+    User,
+    Assistant,
+}
+
+impl StoredRole {
+    fn as_str(self) -> &'static str {
+        match self {
+            StoredRole::System => "system",
+            StoredRole::User => "user",
+            StoredRole::Assistant => "assistant",
+        }
+    }
+
+    fn parse(role: &str) -> Option<Self> {
+        match role {
+            "system" => Some(StoredRole::System),
+            "user" => Some(StoredRole::User),
+            "assistant" => Some(StoredRole::Assistant),
+            _ => None,
+        }
+    }
+}
+
+/// One message of a stored conversation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredMessage {
+    pub role: StoredRole,
+    pub content: String,
+}
+
+impl StoredMessage {
+    pub fn new(role: StoredRole, content: String) -> Self {
+        Self { role, content }
+    }
+}
+
+/// Saves and restores a conversation, keeping it under a size limit
+pub struct ConversationStore<S: ConfigStorage> {
+    storage: S,
+    max_bytes: usize,
+}
+
+impl<S: ConfigStorage> ConversationStore<S> {
+    /// Store conversations in `storage`, using at most `max_bytes`
+    pub fn new(storage: S, max_bytes: usize) -> Self {
+        Self { storage, max_bytes }
+    }
+
+    /// The stored conversation; empty when none has been saved
+    pub fn load(&self) -> Result<Vec<StoredMessage>, ConfigError> {
+        match self.storage.load()? {
+            Some(value) => messages_from_value(&value),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Store `messages`, dropping the oldest ones until they fit
+    ///
+    /// A leading system message is kept as long as anything is stored, so
+    /// a restored conversation keeps its instructions.
+    ///
+    /// # Returns
+    /// The number of messages dropped to fit the limit
+    pub fn save(&mut self, messages: &[StoredMessage]) -> Result<usize, ConfigError> {
+        let (value, dropped) = bounded_value(messages, self.max_bytes)?;
+        self.storage.save(&value)?;
+        Ok(dropped)
+    }
+
+    /// Forget the stored conversation
+    pub fn clear(&mut self) -> Result<(), ConfigError> {
+        self.storage.save(&messages_to_value(&[]))
+    }
+}
+
+/// The largest suffix of `messages` (plus a leading system message) whose
+/// TOML fits in `max_bytes`, and how many messages were left out
+fn bounded_value(
+    messages: &[StoredMessage],
+    max_bytes: usize,
+) -> Result<(Value, usize), ConfigError> {
+    let (system, rest) = match messages.split_first() {
+        Some((first, rest)) if first.role == StoredRole::System => (Some(first), rest),
+        _ => (None, messages),
+    };
+
+    for skip in 0..=rest.len() {
+        let kept: Vec<StoredMessage> = system.into_iter().chain(&rest[skip..]).cloned().collect();
+        // The system message alone is not worth keeping
+        let kept = if skip == rest.len() { Vec::new() } else { kept };
+        let value = messages_to_value(&kept);
+        if TomlParser::serialize(&value)?.len() <= max_bytes {
+            return Ok((value, messages.len() - kept.len()));
+        }
+    }
+    Ok((messages_to_value(&[]), messages.len()))
+}
+
+/// TOML value holding `messages` as an array of tables
+pub fn messages_to_value(messages: &[StoredMessage]) -> Value {
+    let messages = messages
+        .iter()
+        .map(|message| {
+            let mut table = BTreeMap::new();
+            table.insert("role".into(), Value::String(message.role.as_str().into()));
+            table.insert("content".into(), Value::String(message.content.clone()));
+            Value::Table(table)
+        })
+        .collect();
+    let mut root = BTreeMap::new();
+    root.insert("messages".into(), Value::Array(messages));
+    Value::Table(root)
+}
+
+/// Messages from a value written by [`messages_to_value`]
+pub fn messages_from_value(value: &Value) -> Result<Vec<StoredMessage>, ConfigError> {
+    let Value::Table(root) = value else {
+        return Err(ConfigError::invalid_value("history: expected table"));
+    };
+    let Some(entries) = root.get("messages") else {
+        return Ok(Vec::new());
+    };
+    let Value::Array(entries) = entries else {
+        return Err(ConfigError::invalid_value(
+            "history.messages: expected array",
+        ));
+    };
+
+    let mut messages = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.iter().enumerate() {
+        let field = |key: &str| match entry {
+            Value::Table(table) => match table.get(key) {
+                Some(Value::String(text)) => Ok(text.as_str()),
+                _ => Err(ConfigError::invalid_value(&format!(
+                    "history.messages[{}].{}: expected string",
+                    index, key
+                ))),
+            },
+            _ => Err(ConfigError::invalid_value(&format!(
+                "history.messages[{}]: expected table",
+                index
+            ))),
+        };
+        let role = field("role")?;
+        let role = StoredRole::parse(role).ok_or_else(|| {
+            ConfigError::invalid_value(&format!(
+                "history.messages[{}].role: unknown role '{}'",
+                index, role
+            ))
+        })?;
+        messages.push(StoredMessage::new(role, field("content")?.into()));
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// In-memory storage that keeps the TOML text, like the EFI backend
+    #[derive(Default)]
+    struct MemoryStorage {
+        toml: Option<String>,
+    }
+
+    impl ConfigStorage for MemoryStorage {
+        fn load(&self) -> Result<Option<Value>, ConfigError> {
+            self.toml.as_deref().map(TomlParser::parse).transpose()
+        }
+
+        fn save(&mut self, config: &Value) -> Result<(), ConfigError> {
+            self.toml = Some(TomlParser::serialize(config)?);
+            Ok(())
+        }
+
+        fn exists(&self) -> bool {
+            self.toml.is_some()
+        }
+    }
+
+    fn conversation(turns: usize) -> Vec<StoredMessage> {
+        let mut messages = vec![StoredMessage::new(
+            StoredRole::System,
+            "Be \"brief\".".into(),
+        )];
+        for turn in 0..turns {
+            messages.push(StoredMessage::new(
+                StoredRole::User,
+                format!("question {}\nsecond line", turn),
+            ));
+            messages.push(StoredMessage::new(
+                StoredRole::Assistant,
+                format!("answer {}", turn),
+            ));
+        }
+        messages
+    }
+
+    #[test]
+    fn test_conversation_roundtrip() {
+        let mut store = ConversationStore::new(MemoryStorage::default(), 4096);
+        assert!(store.load().unwrap().is_empty());
+
+        let messages = conversation(3);
+        assert_eq!(store.save(&messages).unwrap(), 0);
+        assert_eq!(store.load().unwrap(), messages);
+
+        store.clear().unwrap();
+        assert!(store.load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_oldest_messages_are_dropped_to_fit() {
+        let messages = conversation(20);
+        let full = TomlParser::serialize(&messages_to_value(&messages))
+            .unwrap()
+            .len();
+        let mut store = ConversationStore::new(MemoryStorage::default(), full / 2);
+
+        let dropped = store.save(&messages).unwrap();
+        assert!(dropped > 0);
+        let stored = store.load().unwrap();
+        assert_eq!(stored.len(), messages.len() - dropped);
+        // The system prompt stays and the newest messages are kept
+        assert_eq!(stored[0], messages[0]);
+        assert_eq!(stored.last(), messages.last());
+        assert!(
+            TomlParser::serialize(&messages_to_value(&stored))
+                .unwrap()
+                .len()
+                <= full / 2
+        );
+
+        // Nothing fits: nothing is stored
+        let mut tiny = ConversationStore::new(MemoryStorage::default(), 8);
+        assert_eq!(tiny.save(&messages).unwrap(), messages.len());
+        assert!(tiny.load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unknown_role_is_rejected() {
+        let value =
+            TomlParser::parse("[[messages]]\nrole = \"robot\"\ncontent = \"hi\"\n").unwrap();
+        assert!(matches!(
+            messages_from_value(&value),
+            Err(ConfigError::InvalidValue(msg)) if msg.contains("robot")
+        ));
+    }
+}
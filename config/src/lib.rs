@@ -2,19 +2,41 @@
 
 extern crate alloc;
 
+pub mod convert;
 pub mod crypto;
 pub mod error;
+pub mod history;
+pub mod migrate;
+pub mod pem;
 pub mod storage;
 pub mod toml;
 pub mod types;
 pub mod wizard;
 
-pub use crypto::{decrypt_api_key, encrypt_api_key};
+pub use crypto::{
+    decrypt_api_key, decrypt_config, encrypt_api_key, encrypt_config, is_encrypted_config,
+    CONFIG_BLOB_MAGIC, CONFIG_BLOB_VERSION,
+};
 pub use error::ConfigError;
-pub use storage::{efi::EfiConfigStorage, ConfigStorage};
+pub use history::{
+    ConversationStore, StoredMessage, StoredRole, DEFAULT_HISTORY_MAX_BYTES,
+};
+pub use migrate::{config_version, migrate, migrate_to_current, CONFIG_VERSION};
+pub use pem::decode_pem_certificates;
+pub use storage::{
+    efi::{
+        runtime_system_table, EfiConfigStorage, RuntimeSystemTable, ARCHIVE_VARIABLE_NAME,
+        CONVERSATION_VARIABLE_NAME,
+    },
+    ConfigStorage,
+};
 pub use toml::{TomlParser, Value};
 pub use types::{
-    ConnectionType, IpConfig, LocalProviderConfig, MoteConfig, NetworkConfig, Preferences,
-    ProviderConfig, ProviderConfigs, SecurityType, ThemeChoice, WifiNetwork,
+    ConnectionType, IpConfig, LocalProviderConfig, MoteConfig, NetworkConfig, Persona,
+    Preferences, ProviderConfig, ProviderConfigs, ProxyConfig, SecurityType, ThemeChoice,
+    WifiNetwork, DEFAULT_AZURE_API_VERSION, THEME_COLOR_NAMES,
+};
+pub use wizard::{
+    wifi_network_label, ApiKeyProvider, AzureField, CustomField, Key, SetupWizard, WizardEvent,
+    WizardState,
 };
-pub use wizard::{ApiKeyProvider, Key, SetupWizard, WizardEvent, WizardState};
@@ -0,0 +1,182 @@
+//! Versioning and migration of stored configuration documents
+//!
+//! `MoteConfig::to_value` writes a top-level `version`; documents from before
+//! versioning count as version 1, the layout described in the PRD. `migrate`
+//! brings an older document up to `CONFIG_VERSION` one step at a time, so
+//! each step only has to know about the version right before it.
+
+extern crate alloc;
+
+use crate::error::ConfigError;
+use crate::toml::Value;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+
+type Table = BTreeMap<String, Value>;
+
+/// One migration step, rewriting the root table in place
+type Migration = fn(&mut Table) -> Result<(), ConfigError>;
+
+/// Version of the document `MoteConfig::to_value` writes
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Step from version `n` to `n + 1` is `MIGRATIONS[n - 1]`
+const MIGRATIONS: [Migration; (CONFIG_VERSION - 1) as usize] = [migrate_v1_to_v2];
+
+/// Version of a stored document; 1 when it has none
+pub fn config_version(value: &Value) -> Result<u32, ConfigError> {
+    let Value::Table(root) = value else {
+        return Err(ConfigError::invalid_value("config: expected table"));
+    };
+    match root.get("version") {
+        None => Ok(1),
+        Some(Value::Integer(version)) => u32::try_from(*version)
+            .ok()
+            .filter(|&version| version > 0)
+            .ok_or_else(|| {
+                ConfigError::invalid_value(&format!("version: {} is not a valid version", version))
+            }),
+        Some(_) => Err(ConfigError::invalid_value("version: expected integer")),
+    }
+}
+
+/// Bring a document of `from_version` up to `CONFIG_VERSION`
+///
+/// Steps run in order and the result carries the current `version`.
+/// Documents written by a newer moteOS are rejected rather than guessed at.
+pub fn migrate(value: Value, from_version: u32) -> Result<Value, ConfigError> {
+    if from_version > CONFIG_VERSION {
+        return Err(ConfigError::invalid_value(&format!(
+            "version: config version {} is newer than the supported {}",
+            from_version, CONFIG_VERSION
+        )));
+    }
+    if from_version == 0 {
+        return Err(ConfigError::invalid_value(
+            "version: 0 is not a valid version",
+        ));
+    }
+    let Value::Table(mut root) = value else {
+        return Err(ConfigError::invalid_value("config: expected table"));
+    };
+
+    for step in &MIGRATIONS[from_version as usize - 1..] {
+        step(&mut root)?;
+    }
+    root.insert("version".into(), Value::Integer(CONFIG_VERSION as i64));
+    Ok(Value::Table(root))
+}
+
+/// Read a document's version and migrate it if it is older than current
+pub fn migrate_to_current(value: Value) -> Result<Value, ConfigError> {
+    let version = config_version(&value)?;
+    if version == CONFIG_VERSION {
+        return Ok(value);
+    }
+    migrate(value, version)
+}
+
+/// Version 1 is the PRD layout
+///
+/// `[network]` used `type` and `ssid`, the static address lived in
+/// `[network.static]`, and providers stored `api_key_encrypted`. Keys that
+/// already have their new name are left alone, so configs saved before
+/// versioning (which had no `version` but the new names) pass unchanged.
+fn migrate_v1_to_v2(root: &mut Table) -> Result<(), ConfigError> {
+    if let Some(network) = root.get_mut("network") {
+        let network = as_table_mut(network, "network")?;
+        rename(network, "type", "connection_type");
+        rename(network, "ssid", "wifi_ssid");
+        rename(network, "static", "static_ip");
+    }
+    if let Some(providers) = root.get_mut("providers") {
+        let providers = as_table_mut(providers, "providers")?;
+        for (name, provider) in providers.iter_mut() {
+            let provider = as_table_mut(provider, &format!("providers.{}", name))?;
+            rename(provider, "api_key_encrypted", "api_key");
+        }
+    }
+    Ok(())
+}
+
+/// Move `from` to `to` unless `to` is already set
+fn rename(table: &mut Table, from: &str, to: &str) {
+    if table.contains_key(to) {
+        return;
+    }
+    if let Some(value) = table.remove(from) {
+        table.insert(to.into(), value);
+    }
+}
+
+fn as_table_mut<'a>(value: &'a mut Value, path: &str) -> Result<&'a mut Table, ConfigError> {
+    match value {
+        Value::Table(table) => Ok(table),
+        _ => Err(ConfigError::invalid_value(&format!(
+            "{}: expected table",
+            path
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toml::TomlParser;
+    use crate::types::{ConnectionType, MoteConfig};
+
+    const V1_DOCUMENT: &str = r#"
+[network]
+type = "wifi"
+ssid = "MyNetwork"
+
+[network.static]
+ip = "192.168.1.42"
+gateway = "192.168.1.1"
+subnet_mask = "255.255.255.0"
+dns = ["8.8.8.8", "1.1.1.1"]
+
+[providers.openai]
+api_key_encrypted = "736b2d74657374"
+default_model = "gpt-4o"
+
+[preferences]
+default_provider = "openai"
+theme = "dark"
+"#;
+
+    #[test]
+    fn test_migrates_v1_document_to_current() {
+        let value = TomlParser::parse(V1_DOCUMENT).unwrap();
+        assert_eq!(config_version(&value), Ok(1));
+
+        let migrated = migrate(value, 1).unwrap();
+        assert_eq!(config_version(&migrated), Ok(CONFIG_VERSION));
+        let config = MoteConfig::from_value(&migrated).unwrap();
+        assert_eq!(config.network.connection_type, ConnectionType::Wifi);
+        assert_eq!(config.network.wifi_ssid.as_deref(), Some("MyNetwork"));
+        assert_eq!(config.network.static_ip.unwrap().ip, [192, 168, 1, 42]);
+        let openai = config.providers.openai.unwrap();
+        assert_eq!(openai.api_key_encrypted, b"sk-test");
+        assert_eq!(openai.default_model, "gpt-4o");
+
+        // Current documents come back untouched
+        let current = MoteConfig::default().to_value();
+        assert_eq!(migrate_to_current(current.clone()), Ok(current));
+    }
+
+    #[test]
+    fn test_rejects_newer_or_invalid_versions() {
+        let newer = TomlParser::parse("version = 3\n").unwrap();
+        assert_eq!(config_version(&newer), Ok(3));
+        assert!(matches!(
+            migrate(newer.clone(), 3),
+            Err(ConfigError::InvalidValue(msg)) if msg.contains("newer")
+        ));
+        assert!(migrate_to_current(newer).is_err());
+
+        assert!(config_version(&TomlParser::parse("version = 0\n").unwrap()).is_err());
+        assert!(config_version(&TomlParser::parse("version = \"2\"\n").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! PEM certificate decoding
+//!
+//! Extra TLS trust anchors are stored in the config as PEM text. They are
+//! decoded when the config is loaded so a malformed certificate is reported
+//! instead of being silently dropped at connect time.
+
+extern crate alloc;
+use alloc::format;
+use alloc::vec::Vec;
+
+use crate::error::ConfigError;
+
+const BEGIN_CERTIFICATE: &str = "-----BEGIN CERTIFICATE-----";
+const END_CERTIFICATE: &str = "-----END CERTIFICATE-----";
+
+/// Decode every `CERTIFICATE` block in `pem` to DER
+///
+/// `path` names the config key in error messages. Text outside the blocks is
+/// ignored, but at least one certificate is required.
+pub fn decode_pem_certificates(pem: &str, path: &str) -> Result<Vec<Vec<u8>>, ConfigError> {
+    let mut certificates = Vec::new();
+    let mut rest = pem;
+
+    while let Some(start) = rest.find(BEGIN_CERTIFICATE) {
+        let body = &rest[start + BEGIN_CERTIFICATE.len()..];
+        let Some(end) = body.find(END_CERTIFICATE) else {
+            return Err(ConfigError::invalid_value(&format!(
+                "{}: certificate {} is missing its END line",
+                path,
+                certificates.len() + 1
+            )));
+        };
+        let der = base64_decode(&body[..end]).ok_or_else(|| {
+            ConfigError::invalid_value(&format!(
+                "{}: certificate {} is not valid base64",
+                path,
+                certificates.len() + 1
+            ))
+        })?;
+        // Every X.509 certificate is a DER SEQUENCE
+        if der.first() != Some(&0x30) {
+            return Err(ConfigError::invalid_value(&format!(
+                "{}: certificate {} is not a DER certificate",
+                path,
+                certificates.len() + 1
+            )));
+        }
+        certificates.push(der);
+        rest = &body[end + END_CERTIFICATE.len()..];
+    }
+
+    if certificates.is_empty() {
+        return Err(ConfigError::invalid_value(&format!(
+            "{}: no PEM certificate found",
+            path
+        )));
+    }
+    Ok(certificates)
+}
+
+/// Decode standard base64, skipping whitespace; None on any other bad input
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    let mut padding = 0;
+
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => {
+                padding += 1;
+                continue;
+            }
+            b' ' | b'\t' | b'\r' | b'\n' => continue,
+            _ => return None,
+        };
+        // Data after padding
+        if padding > 0 {
+            return None;
+        }
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+
+    // Leftover bits must be the zero fill of a padded final group
+    if padding > 2 || bits >= 6 || buffer & ((1 << bits) - 1) != 0 {
+        return None;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_pem_certificates() {
+        // Not a real certificate, just DER-shaped bytes: 30 03 02 01 05
+        let pem = concat!(
+            "leading text\n",
+            "-----BEGIN CERTIFICATE-----\nMAMCAQU=\n-----END CERTIFICATE-----\n",
+            "-----BEGIN CERTIFICATE-----\r\nMAMC\r\nAQU=\r\n-----END CERTIFICATE-----\n",
+        );
+        let certificates = decode_pem_certificates(pem, "network.tls.extra_ca_pem").unwrap();
+        assert_eq!(certificates.len(), 2);
+        assert_eq!(certificates[0], [0x30, 0x03, 0x02, 0x01, 0x05]);
+        assert_eq!(certificates[1], certificates[0]);
+    }
+
+    #[test]
+    fn test_invalid_pem_is_rejected() {
+        let path = "network.tls.extra_ca_pem";
+        for pem in [
+            "",
+            "MAMCAQU=",
+            "-----BEGIN CERTIFICATE-----\nMAMCAQU=\n",
+            "-----BEGIN CERTIFICATE-----\nMA!CAQU=\n-----END CERTIFICATE-----",
+            "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----",
+        ] {
+            assert!(
+                matches!(
+                    decode_pem_certificates(pem, path),
+                    Err(ConfigError::InvalidValue(_))
+                ),
+                "{}",
+                pem
+            );
+        }
+    }
+}
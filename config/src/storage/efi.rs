@@ -10,6 +10,12 @@ use crate::storage::ConfigStorage;
 use crate::toml::{TomlParser, Value};
 use alloc::vec::Vec;
 
+/// EFI variable holding the active conversation
+pub const CONVERSATION_VARIABLE_NAME: &str = "MoteOS-Conversation";
+
+/// EFI variable holding the last conversation cleared with F9
+pub const ARCHIVE_VARIABLE_NAME: &str = "MoteOS-Archive";
+
 // UEFI types are only available when building for UEFI targets
 // This code will not compile in host tests, which is expected
 #[cfg(any(
@@ -19,6 +25,9 @@ use alloc::vec::Vec;
 ))]
 mod efi_impl {
     use super::*;
+    use crate::crypto;
+    use crate::migrate;
+    use alloc::boxed::Box;
     use alloc::format;
     use uefi::{
         prelude::*,
@@ -41,27 +50,60 @@ mod efi_impl {
         [0x1f, 0x3a, 0x5b, 0x7c, 0x9d, 0x0e],
     );
 
+    /// Runtime view of the UEFI system table, through which EFI variables
+    /// are reached once boot services have exited
+    pub type RuntimeSystemTable = &'static SystemTable<Runtime>;
+
+    /// The runtime system table at `addr`, as handed to the kernel in `BootInfo`
+    ///
+    /// # Safety
+    ///
+    /// `addr` must be the system table returned by `exit_boot_services`.
+    pub unsafe fn runtime_system_table(addr: usize) -> Option<RuntimeSystemTable> {
+        // SAFETY: the caller passes the firmware's system table
+        let table = unsafe { SystemTable::<Runtime>::from_ptr(addr as *mut core::ffi::c_void) }?;
+        Some(Box::leak(Box::new(table)))
+    }
+
     /// EFI variable storage for configuration
     ///
     /// This implementation stores configuration in EFI variables, which persist
     /// across reboots and are accessible from both UEFI boot services and runtime.
     ///
-    /// The configuration is stored as a TOML string in the EFI variable "MoteOS-Config"
-    /// with a custom vendor GUID.
+    /// The configuration is stored as TOML encrypted with `crypto::encrypt_config` in
+    /// the EFI variable "MoteOS-Config" with a custom vendor GUID. Variables written
+    /// as plain TOML by older versions still load, and are encrypted on the next save.
+    /// Configs from older releases are migrated to `CONFIG_VERSION` as they load.
     pub struct EfiConfigStorage {
         /// System table reference
-        system_table: Option<&'static SystemTable<Runtime>>,
+        system_table: Option<RuntimeSystemTable>,
+        /// Name of the EFI variable
+        variable: &'static str,
     }
 
     impl EfiConfigStorage {
         /// Create a new EFI config storage instance
-        pub fn new(system_table: Option<&'static SystemTable<Runtime>>) -> Self {
-            Self { system_table }
+        pub fn new(system_table: Option<RuntimeSystemTable>) -> Self {
+            Self::with_variable_name(system_table, CONFIG_VARIABLE_NAME)
+        }
+
+        /// Create a storage instance backed by another EFI variable
+        ///
+        /// Used for data kept apart from the configuration, such as the
+        /// conversation history.
+        pub fn with_variable_name(
+            system_table: Option<RuntimeSystemTable>,
+            variable: &'static str,
+        ) -> Self {
+            Self {
+                system_table,
+                variable,
+            }
         }
 
         /// Get the variable name as a CString16
         fn variable_name(&self) -> Result<CString16, ConfigError> {
-            CString16::try_from(CONFIG_VARIABLE_NAME)
+            CString16::try_from(self.variable)
                 .map_err(|_| ConfigError::efi_error("Failed to create variable name"))
         }
 
@@ -135,13 +177,16 @@ mod efi_impl {
                     ConfigError::efi_error(&msg)
                 })
         }
-    }
 
-    impl ConfigStorage for EfiConfigStorage {
-        fn load(&self) -> Result<Option<Value>, ConfigError> {
+        /// Read and decrypt the variable, as stored
+        fn load_document(&self) -> Result<Option<Value>, ConfigError> {
             match self.read_variable()? {
                 None => Ok(None),
+                Some(data) if crypto::is_encrypted_config(&data) => {
+                    crypto::decrypt_config(&data, &crypto::derive_key()).map(Some)
+                }
                 Some(data) => {
+                    // Plain TOML, as saved before configs were encrypted
                     // Convert bytes to string
                     let toml_str = core::str::from_utf8(&data).map_err(|e| {
                         ConfigError::deserialization_error(&format!(
@@ -159,18 +204,24 @@ mod efi_impl {
                 }
             }
         }
+    }
 
-        fn save(&mut self, config: &Value) -> Result<(), ConfigError> {
-            // Serialize to TOML
-            let toml_str = TomlParser::serialize(config).map_err(|e| {
-                ConfigError::serialization_error(&format!("Failed to serialize TOML: {:?}", e))
-            })?;
+    impl ConfigStorage for EfiConfigStorage {
+        fn load(&self) -> Result<Option<Value>, ConfigError> {
+            let value = self.load_document()?;
+            // Only the configuration is versioned, not the conversation history
+            if self.variable != CONFIG_VARIABLE_NAME {
+                return Ok(value);
+            }
+            value.map(migrate::migrate_to_current).transpose()
+        }
 
-            // Convert to bytes
-            let data = toml_str.as_bytes();
+        fn save(&mut self, config: &Value) -> Result<(), ConfigError> {
+            // Serialize to TOML and encrypt
+            let data = crypto::encrypt_config(config, &crypto::derive_key())?;
 
             // Write to EFI variable
-            self.write_variable(data)
+            self.write_variable(&data)
         }
 
         fn exists(&self) -> bool {
@@ -189,7 +240,7 @@ mod efi_impl {
     all(target_arch = "x86_64", feature = "uefi"),
     all(target_arch = "aarch64", feature = "uefi")
 ))]
-pub use efi_impl::EfiConfigStorage;
+pub use efi_impl::{runtime_system_table, EfiConfigStorage, RuntimeSystemTable};
 
 // For non-UEFI targets (tests, etc.), provide a stub or alternative implementation
 #[cfg(not(any(
@@ -199,13 +250,39 @@ pub use efi_impl::EfiConfigStorage;
 )))]
 pub struct EfiConfigStorage;
 
+#[cfg(not(any(
+    target_os = "uefi",
+    all(target_arch = "x86_64", feature = "uefi"),
+    all(target_arch = "aarch64", feature = "uefi")
+)))]
+pub type RuntimeSystemTable = ();
+
+/// # Safety
+///
+/// Nothing to uphold: there is no system table outside UEFI.
+#[cfg(not(any(
+    target_os = "uefi",
+    all(target_arch = "x86_64", feature = "uefi"),
+    all(target_arch = "aarch64", feature = "uefi")
+)))]
+pub unsafe fn runtime_system_table(_addr: usize) -> Option<RuntimeSystemTable> {
+    None
+}
+
 #[cfg(not(any(
     target_os = "uefi",
     all(target_arch = "x86_64", feature = "uefi"),
     all(target_arch = "aarch64", feature = "uefi")
 )))]
 impl EfiConfigStorage {
-    pub fn new(_system_table: Option<()>) -> Self {
+    pub fn new(_system_table: Option<RuntimeSystemTable>) -> Self {
+        Self
+    }
+
+    pub fn with_variable_name(
+        _system_table: Option<RuntimeSystemTable>,
+        _variable: &'static str,
+    ) -> Self {
         Self
     }
 }
@@ -64,8 +64,16 @@ impl<'a> Parser<'a> {
 
             // Parse table header or key-value pair
             if self.peek() == Some('[') {
-                // Table header - we'll handle nested tables by path
+                // Table header - we'll handle nested tables by path;
+                // `[[name]]` appends a table to an array of tables
+                let is_array_entry = self.input[self.pos..].starts_with("[[");
+                if is_array_entry {
+                    self.advance();
+                }
                 let path = self.parse_table_header()?;
+                if is_array_entry {
+                    self.expect_char(']')?;
+                }
                 self.skip_whitespace();
 
                 // Parse key-value pairs until next table or EOF
@@ -86,7 +94,11 @@ impl<'a> Parser<'a> {
                 }
 
                 // Insert nested table
-                self.insert_nested(&mut root, &path, Value::Table(table))?;
+                if is_array_entry {
+                    self.append_nested(&mut root, &path, Value::Table(table))?;
+                } else {
+                    self.insert_nested(&mut root, &path, Value::Table(table))?;
+                }
             } else {
                 // Root-level key-value pair
                 let (key, value) = self.parse_key_value()?;
@@ -242,63 +254,100 @@ impl<'a> Parser<'a> {
     fn parse_number(&mut self) -> Result<Value, ConfigError> {
         let start = self.pos;
         let mut is_float = false;
+        // The number with its `_` separators removed
+        let mut digits = String::new();
 
         // Optional sign
-        if self.peek() == Some('-') || self.peek() == Some('+') {
+        if let Some(sign @ ('-' | '+')) = self.peek() {
+            digits.push(sign);
             self.advance();
         }
 
-        // Integer part
-        if !self.peek().map_or(false, |c| c.is_ascii_digit()) {
-            return Err(ConfigError::parse_error("Invalid number"));
+        // Hex, octal and binary integers (which take no sign)
+        let radix = match self.input[self.pos..].get(..2) {
+            Some("0x") => 16,
+            Some("0o") => 8,
+            Some("0b") => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            if !digits.is_empty() {
+                return Err(ConfigError::parse_error("Sign on prefixed integer"));
+            }
+            self.pos += 2;
+            self.read_digits(radix, &mut digits, "Invalid integer")?;
+            let num_str = &self.input[start..self.pos];
+            return i64::from_str_radix(&digits, radix)
+                .map(Value::Integer)
+                .map_err(|_| ConfigError::InvalidNumber(num_str.to_string()));
         }
 
-        while self.peek().map_or(false, |c| c.is_ascii_digit()) {
-            self.advance();
-        }
+        // Integer part
+        self.read_digits(10, &mut digits, "Invalid number")?;
 
         // Optional fractional part
         if self.peek() == Some('.') {
             is_float = true;
+            digits.push('.');
             self.advance();
-            if !self.peek().map_or(false, |c| c.is_ascii_digit()) {
-                return Err(ConfigError::parse_error("Invalid float"));
-            }
-            while self.peek().map_or(false, |c| c.is_ascii_digit()) {
-                self.advance();
-            }
+            self.read_digits(10, &mut digits, "Invalid float")?;
         }
 
         // Optional exponent
-        if self.peek() == Some('e') || self.peek() == Some('E') {
+        if let Some(e @ ('e' | 'E')) = self.peek() {
             is_float = true;
+            digits.push(e);
             self.advance();
-            if self.peek() == Some('-') || self.peek() == Some('+') {
-                self.advance();
-            }
-            if !self.peek().map_or(false, |c| c.is_ascii_digit()) {
-                return Err(ConfigError::parse_error("Invalid exponent"));
-            }
-            while self.peek().map_or(false, |c| c.is_ascii_digit()) {
+            if let Some(sign @ ('-' | '+')) = self.peek() {
+                digits.push(sign);
                 self.advance();
             }
+            self.read_digits(10, &mut digits, "Invalid exponent")?;
         }
 
         let num_str = &self.input[start..self.pos];
 
         if is_float {
-            num_str
+            digits
                 .parse::<f64>()
                 .map(Value::Float)
                 .map_err(|_| ConfigError::InvalidNumber(num_str.to_string()))
         } else {
-            num_str
+            digits
                 .parse::<i64>()
                 .map(Value::Integer)
                 .map_err(|_| ConfigError::InvalidNumber(num_str.to_string()))
         }
     }
 
+    /// Read a run of digits in `radix` into `out`
+    ///
+    /// A `_` separator is allowed only between two digits.
+    fn read_digits(
+        &mut self,
+        radix: u32,
+        out: &mut String,
+        error: &str,
+    ) -> Result<(), ConfigError> {
+        if !self.peek().is_some_and(|c| c.is_digit(radix)) {
+            return Err(ConfigError::parse_error(error));
+        }
+        while let Some(ch) = self.peek() {
+            if ch.is_digit(radix) {
+                out.push(ch);
+                self.advance();
+            } else if ch == '_' {
+                self.advance();
+                if !self.peek().is_some_and(|c| c.is_digit(radix)) {
+                    return Err(ConfigError::parse_error("Misplaced '_' in number"));
+                }
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     fn parse_boolean(&mut self) -> Result<Value, ConfigError> {
         if self.consume("true") {
             Ok(Value::Boolean(true))
@@ -406,18 +455,51 @@ impl<'a> Parser<'a> {
         path: &[String],
         value: Value,
     ) -> Result<(), ConfigError> {
-        if path.is_empty() {
-            return Err(ConfigError::parse_error("Empty table path"));
-        }
+        let (final_key, parent) = self.parent_table(root, path)?;
+        parent.insert(final_key.clone(), value);
+        Ok(())
+    }
 
-        if path.len() == 1 {
-            root.insert(path[0].clone(), value);
-            return Ok(());
+    /// Append `value` to the array of tables at `path`, creating it if needed
+    fn append_nested(
+        &self,
+        root: &mut BTreeMap<String, Value>,
+        path: &[String],
+        value: Value,
+    ) -> Result<(), ConfigError> {
+        let (final_key, parent) = self.parent_table(root, path)?;
+        match parent.get_mut(final_key) {
+            None => {
+                parent.insert(final_key.clone(), Value::Array(Vec::from([value])));
+            }
+            Some(Value::Array(entries)) => entries.push(value),
+            Some(_) => {
+                let msg = fmt::format(format_args!(
+                    "Key '{}' already exists as non-array",
+                    final_key
+                ));
+                return Err(ConfigError::parse_error(&msg));
+            }
         }
+        Ok(())
+    }
+
+    /// Find (creating as needed) the table holding the last key of `path`
+    ///
+    /// A path segment naming an array of tables refers to its last entry, so
+    /// `[a.b]` after `[[a]]` adds to the most recent `a`.
+    fn parent_table<'p, 'm>(
+        &self,
+        root: &'m mut BTreeMap<String, Value>,
+        path: &'p [String],
+    ) -> Result<(&'p String, &'m mut BTreeMap<String, Value>), ConfigError> {
+        let Some((final_key, parents)) = path.split_last() else {
+            return Err(ConfigError::parse_error("Empty table path"));
+        };
 
         // Navigate/create nested structure
         let mut current = root;
-        for key in path.iter().take(path.len() - 1) {
+        for key in parents {
             let needs_insert = !current.contains_key(key);
             if needs_insert {
                 let new_table = BTreeMap::new();
@@ -428,6 +510,16 @@ impl<'a> Parser<'a> {
                 Some(Value::Table(ref mut table)) => {
                     current = table;
                 }
+                Some(Value::Array(entries)) => match entries.last_mut() {
+                    Some(Value::Table(table)) => current = table,
+                    _ => {
+                        let msg = fmt::format(format_args!(
+                            "Key '{}' is not an array of tables",
+                            key
+                        ));
+                        return Err(ConfigError::parse_error(&msg));
+                    }
+                },
                 Some(_) => {
                     let msg =
                         fmt::format(format_args!("Key '{}' already exists as non-table", key));
@@ -440,11 +532,7 @@ impl<'a> Parser<'a> {
             }
         }
 
-        // Insert at final level
-        let final_key = &path[path.len() - 1];
-        current.insert(final_key.clone(), value);
-
-        Ok(())
+        Ok((final_key, current))
     }
 
     // Utility methods
@@ -516,6 +604,18 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Whether `value` is a non-empty array holding only tables
+fn is_array_of_tables(value: &Value) -> bool {
+    matches!(value, Value::Array(items)
+        if !items.is_empty() && items.iter().all(|item| matches!(item, Value::Table(_))))
+}
+
+/// Whether `value` is written as its own `[table]` or `[[table]]` section
+/// rather than as a `key = value` line
+fn is_section(value: &Value) -> bool {
+    matches!(value, Value::Table(_)) || is_array_of_tables(value)
+}
+
 /// TOML serializer
 struct Serializer {
     output: String,
@@ -533,7 +633,7 @@ impl Serializer {
     fn serialize(&mut self, value: &Value) -> Result<(), ConfigError> {
         match value {
             Value::Table(table) => {
-                self.serialize_table(table, false)?;
+                self.serialize_sections(&mut Vec::new(), table)?;
             }
             _ => {
                 return Err(ConfigError::parse_error("Root value must be a table"));
@@ -542,6 +642,78 @@ impl Serializer {
         Ok(())
     }
 
+    /// Write `table`'s own keys, then each sub-table as a `[path.key]`
+    /// section and each array of tables as `[[path.key]]` sections
+    ///
+    /// Keys must come first: once a header is written, every following line
+    /// belongs to that section.
+    fn serialize_sections(
+        &mut self,
+        path: &mut Vec<String>,
+        table: &BTreeMap<String, Value>,
+    ) -> Result<(), ConfigError> {
+        let (sections, entries): (BTreeMap<_, _>, BTreeMap<_, _>) = table
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .partition(|(_, value)| is_section(value));
+        if !entries.is_empty() {
+            if !self.output.is_empty() {
+                self.output.push('\n');
+            }
+            self.serialize_table(&entries, false)?;
+        }
+
+        for (key, value) in &sections {
+            path.push(key.clone());
+            match value {
+                Value::Table(sub) => {
+                    // `[a.b]` creates `a` implicitly, so a header for a table
+                    // holding only sub-tables would just be noise
+                    if sub.is_empty() || !sub.values().all(is_section) {
+                        self.serialize_header(path, false)?;
+                    }
+                    self.serialize_sections(path, sub)?;
+                }
+                Value::Array(items) => {
+                    for item in items {
+                        if let Value::Table(item) = item {
+                            self.serialize_header(path, true)?;
+                            self.serialize_sections(path, item)?;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            path.pop();
+        }
+        Ok(())
+    }
+
+    /// Write a `[a.b]` header, or `[[a.b]]` for an array of tables entry
+    fn serialize_header(&mut self, path: &[String], array: bool) -> Result<(), ConfigError> {
+        if !self.output.is_empty() {
+            self.output.push_str("\n\n");
+        }
+        self.output.push_str(if array { "[[" } else { "[" });
+        for (i, key) in path.iter().enumerate() {
+            if i > 0 {
+                self.output.push('.');
+            }
+            self.serialize_key(key)?;
+        }
+        self.output.push_str(if array { "]]" } else { "]" });
+        Ok(())
+    }
+
+    fn serialize_key(&mut self, key: &str) -> Result<(), ConfigError> {
+        if self.needs_quotes(key) {
+            self.serialize_string(key)
+        } else {
+            self.output.push_str(key);
+            Ok(())
+        }
+    }
+
     fn serialize_table(
         &mut self,
         table: &BTreeMap<String, Value>,
@@ -563,11 +735,7 @@ impl Serializer {
             first = false;
 
             // Write key
-            if self.needs_quotes(key) {
-                self.serialize_string(key)?;
-            } else {
-                self.output.push_str(key);
-            }
+            self.serialize_key(key)?;
 
             self.output.push_str(" = ");
             self.serialize_value(value)?;
@@ -699,6 +867,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_prefixed_integers_and_separators() {
+        let toml = "hex = 0xDEAD_BEEF\nmode = 0o755\nmask = 0b1010\n\
+                    big = 1_000_000\nneg = -1_024\nf = 6.022_140e2_3";
+        let result = TomlParser::parse(toml).unwrap();
+        let Value::Table(map) = result else {
+            panic!("Expected table");
+        };
+        assert_eq!(map.get("hex"), Some(&Value::Integer(0xDEAD_BEEF)));
+        assert_eq!(map.get("mode"), Some(&Value::Integer(0o755)));
+        assert_eq!(map.get("mask"), Some(&Value::Integer(0b1010)));
+        assert_eq!(map.get("big"), Some(&Value::Integer(1_000_000)));
+        assert_eq!(map.get("neg"), Some(&Value::Integer(-1024)));
+        assert_eq!(map.get("f"), Some(&Value::Float(6.022140e23)));
+
+        for bad in [
+            "n = _1",
+            "n = 1_",
+            "n = 1__0",
+            "n = 1_.5",
+            "n = 1._5",
+            "n = 0x_FF",
+            "n = 0x",
+            "n = -0xFF",
+            "n = 0b102",
+            "n = 0x8000_0000_0000_0000",
+        ] {
+            assert!(TomlParser::parse(bad).is_err(), "{bad}");
+        }
+    }
+
     #[test]
     fn test_boolean() {
         let toml = r#"enabled = true
@@ -817,4 +1016,106 @@ enabled = true"#;
             panic!("Expected tables");
         }
     }
+    #[test]
+    fn test_array_of_tables() {
+        let toml = r#"
+[[networks]]
+ssid = "home"
+priority = 2
+
+[[networks]]
+ssid = "office"
+"#;
+        let parsed = TomlParser::parse(toml).unwrap();
+        let Value::Table(root) = &parsed else {
+            panic!("Expected table");
+        };
+        let Some(Value::Array(networks)) = root.get("networks") else {
+            panic!("Expected networks array");
+        };
+        assert_eq!(networks.len(), 2);
+        let Value::Table(first) = &networks[0] else {
+            panic!("Expected table entry");
+        };
+        assert_eq!(first.get("ssid"), Some(&Value::String(String::from("home"))));
+        assert_eq!(first.get("priority"), Some(&Value::Integer(2)));
+        let Value::Table(second) = &networks[1] else {
+            panic!("Expected table entry");
+        };
+        assert_eq!(second.get("ssid"), Some(&Value::String(String::from("office"))));
+        assert_eq!(second.get("priority"), None);
+
+        let serialized = TomlParser::serialize(&parsed).unwrap();
+        assert!(serialized.contains("[[networks]]"));
+        assert_eq!(TomlParser::parse(&serialized).unwrap(), parsed);
+    }
+
+    #[test]
+    fn test_array_of_tables_followed_by_table() {
+        let toml = r#"
+[[a]]
+x = 1
+
+[b]
+y = 2
+"#;
+        let parsed = TomlParser::parse(toml).unwrap();
+        let Value::Table(root) = &parsed else {
+            panic!("Expected table");
+        };
+        let Some(Value::Array(a)) = root.get("a") else {
+            panic!("Expected a array");
+        };
+        assert_eq!(a.len(), 1);
+        let Value::Table(entry) = &a[0] else {
+            panic!("Expected table entry");
+        };
+        assert_eq!(entry.get("x"), Some(&Value::Integer(1)));
+        assert_eq!(entry.get("y"), None);
+        let Some(Value::Table(b)) = root.get("b") else {
+            panic!("Expected b table");
+        };
+        assert_eq!(b.get("y"), Some(&Value::Integer(2)));
+
+        let serialized = TomlParser::serialize(&parsed).unwrap();
+        assert_eq!(TomlParser::parse(&serialized).unwrap(), parsed);
+    }
+
+    #[test]
+    fn test_serialize_nested_tables_as_sections() {
+        let toml = r#"
+version = 2
+
+[network]
+dhcp = true
+
+[network.proxy]
+host = "proxy.lan"
+port = 3128
+
+[llm.openai]
+model = "gpt-4o"
+
+[[llm.openai.fallbacks]]
+model = "gpt-4o-mini"
+"#;
+        let parsed = TomlParser::parse(toml).unwrap();
+        let serialized = TomlParser::serialize(&parsed).unwrap();
+        assert_eq!(
+            serialized,
+            "version = 2\n\n\
+             [llm.openai]\nmodel = \"gpt-4o\"\n\n\
+             [[llm.openai.fallbacks]]\nmodel = \"gpt-4o-mini\"\n\n\
+             [network]\ndhcp = true\n\n\
+             [network.proxy]\nhost = \"proxy.lan\"\nport = 3128"
+        );
+        assert_eq!(TomlParser::parse(&serialized).unwrap(), parsed);
+
+        // Tables inside plain arrays have no header form
+        let toml = r#"mixed = [1, { a = 2 }]"#;
+        let parsed = TomlParser::parse(toml).unwrap();
+        let serialized = TomlParser::serialize(&parsed).unwrap();
+        assert_eq!(serialized, "mixed = [1, {a = 2}]");
+        assert_eq!(TomlParser::parse(&serialized).unwrap(), parsed);
+    }
 }
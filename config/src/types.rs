@@ -33,6 +33,15 @@ pub struct NetworkConfig {
     pub wifi_ssid: Option<String>,
     pub wifi_password_encrypted: Option<Vec<u8>>,
     pub static_ip: Option<IpConfig>,
+    /// Extra TLS trust anchors as PEM text, e.g. a home-lab CA; validated on load
+    pub tls_extra_ca_pem: Option<String>,
+    /// NTP server (hostname or IPv4 address) used to set the clock
+    pub ntp_server: Option<String>,
+    /// HTTP proxy for all provider traffic
+    pub proxy: Option<ProxyConfig>,
+    /// Resolve `.local` names (e.g. `ollama.local`) with multicast DNS;
+    /// turn off on networks that block multicast
+    pub mdns: bool,
 }
 
 impl Default for NetworkConfig {
@@ -42,10 +51,26 @@ impl Default for NetworkConfig {
             wifi_ssid: None,
             wifi_password_encrypted: None,
             static_ip: None,
+            tls_extra_ca_pem: None,
+            ntp_server: None,
+            proxy: None,
+            mdns: true,
         }
     }
 }
 
+/// HTTP proxy settings; HTTPS is tunneled with CONNECT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyConfig {
+    /// Hostname or IPv4 address
+    pub host: String,
+    pub port: u16,
+    /// Basic authentication user; no authentication when unset
+    pub username: Option<String>,
+    /// Encrypted like API keys
+    pub password_encrypted: Option<Vec<u8>>,
+}
+
 /// Type of network connection
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConnectionType {
@@ -53,15 +78,35 @@ pub enum ConnectionType {
     Wifi,
 }
 
-/// IP configuration (for static IP)
+/// IP configuration (for static IP); when set, DHCP is not used
 #[derive(Debug, Clone)]
 pub struct IpConfig {
     pub ip: [u8; 4],
+    /// Default gateway; 0.0.0.0 for none (local network only)
     pub gateway: [u8; 4],
     pub dns: Vec<[u8; 4]>,
     pub subnet_mask: [u8; 4],
 }
 
+impl IpConfig {
+    /// Prefix length of `subnet_mask`, or `None` if its one bits aren't
+    /// contiguous
+    pub fn prefix_len(&self) -> Option<u8> {
+        let mask = u32::from_be_bytes(self.subnet_mask);
+        let prefix_len = mask.leading_ones();
+        (mask.checked_shl(prefix_len).unwrap_or(0) == 0).then_some(prefix_len as u8)
+    }
+
+    /// Subnet mask for a prefix length of 0-32
+    pub fn mask_from_prefix(prefix_len: u8) -> Option<[u8; 4]> {
+        if prefix_len > 32 {
+            return None;
+        }
+        let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+        Some(mask.to_be_bytes())
+    }
+}
+
 /// Provider configurations for all supported LLM providers
 #[derive(Debug, Clone, Default)]
 pub struct ProviderConfigs {
@@ -69,15 +114,55 @@ pub struct ProviderConfigs {
     pub anthropic: Option<ProviderConfig>,
     pub groq: Option<ProviderConfig>,
     pub xai: Option<ProviderConfig>,
+    pub azure: Option<ProviderConfig>,
+    /// Self-hosted OpenAI-compatible server; `base_url` is required and the
+    /// API key may be empty
+    pub custom: Option<ProviderConfig>,
     pub ollama: Option<LocalProviderConfig>,
     pub local: Option<LocalProviderConfig>,
 }
 
+/// Azure OpenAI `api-version` the wizard fills in when left blank; matches
+/// the llm crate's Azure provider default
+pub const DEFAULT_AZURE_API_VERSION: &str = "2024-06-01";
+
 /// Configuration for a cloud LLM provider
 #[derive(Debug, Clone)]
 pub struct ProviderConfig {
     pub api_key_encrypted: Vec<u8>,
     pub default_model: String,
+    /// Azure OpenAI resource name (`{resource}.openai.azure.com`)
+    pub azure_resource: Option<String>,
+    /// Azure OpenAI deployment name
+    pub azure_deployment: Option<String>,
+    /// Azure OpenAI `api-version` query parameter
+    pub azure_api_version: Option<String>,
+    /// Override for the API host (e.g. a proxy); the provider default when unset
+    pub base_url: Option<String>,
+    /// TCP/TLS connect timeout; the HTTP client default when unset
+    pub connect_timeout_ms: Option<u64>,
+    /// Timeout waiting for response data; the HTTP client default when unset
+    pub read_timeout_ms: Option<u64>,
+    /// SHA-256 fingerprint of the server's certificate; when set it must
+    /// match exactly and replaces chain validation
+    pub pinned_cert_sha256: Option<[u8; 32]>,
+}
+
+impl ProviderConfig {
+    /// Create a provider config with no provider-specific settings
+    pub fn new(api_key_encrypted: Vec<u8>, default_model: String) -> Self {
+        Self {
+            api_key_encrypted,
+            default_model,
+            azure_resource: None,
+            azure_deployment: None,
+            azure_api_version: None,
+            base_url: None,
+            connect_timeout_ms: None,
+            read_timeout_ms: None,
+            pinned_cert_sha256: None,
+        }
+    }
 }
 
 /// Configuration for a local provider (Ollama or bundled model)
@@ -93,8 +178,31 @@ pub struct Preferences {
     pub default_provider: String,
     pub default_model: String,
     pub theme: ThemeChoice,
+    /// Theme color overrides as (color name, hex string) pairs,
+    /// e.g. ("accent_primary", "#FF8800")
+    pub theme_colors: Vec<(String, String)>,
     pub temperature: f32,
     pub stream_responses: bool,
+    /// Default nucleus sampling cutoff; provider default when unset
+    pub top_p: Option<f32>,
+    /// Default stop sequences (providers accept at most four)
+    pub stop_sequences: Vec<String>,
+    /// Default presence penalty; provider default when unset
+    pub presence_penalty: Option<f32>,
+    /// Default frequency penalty; provider default when unset
+    pub frequency_penalty: Option<f32>,
+    /// System prompt that starts every conversation; none when empty
+    pub system_prompt: String,
+    /// Named system prompts that can be switched between in the chat
+    pub personas: Vec<Persona>,
+    /// Log provider requests and responses to serial (keys redacted)
+    pub debug_llm: bool,
+    /// Log TLS handshake and I/O progress to serial (no keys or payloads)
+    pub debug_tls: bool,
+    /// Cap on the saved conversation in bytes; 0 disables saving it
+    pub history_max_bytes: usize,
+    /// Keep the previous conversation when F9 starts a new one
+    pub archive_conversations: bool,
 }
 
 impl Default for Preferences {
@@ -103,8 +211,35 @@ impl Default for Preferences {
             default_provider: String::from("local"),
             default_model: String::from("smollm-360m"),
             theme: ThemeChoice::Dark,
+            theme_colors: Vec::new(),
             temperature: 0.7,
             stream_responses: true,
+            top_p: None,
+            stop_sequences: Vec::new(),
+            presence_penalty: None,
+            frequency_penalty: None,
+            system_prompt: String::new(),
+            personas: Vec::new(),
+            debug_llm: false,
+            debug_tls: false,
+            history_max_bytes: crate::history::DEFAULT_HISTORY_MAX_BYTES,
+            archive_conversations: false,
+        }
+    }
+}
+
+/// A named system prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Persona {
+    pub name: String,
+    pub system_prompt: String,
+}
+
+impl Persona {
+    pub fn new(name: String, system_prompt: String) -> Self {
+        Self {
+            name,
+            system_prompt,
         }
     }
 }
@@ -116,6 +251,28 @@ pub enum ThemeChoice {
     Light,
 }
 
+/// Color names accepted in `preferences.theme_colors`
+pub const THEME_COLOR_NAMES: [&str; 18] = [
+    "background",
+    "surface",
+    "border",
+    "text_primary",
+    "text_secondary",
+    "text_tertiary",
+    "text_disabled",
+    "accent_primary",
+    "accent_success",
+    "accent_warning",
+    "accent_error",
+    "accent_assistant",
+    "accent_code",
+    "provider_openai",
+    "provider_anthropic",
+    "provider_groq",
+    "provider_xai",
+    "provider_local",
+];
+
 /// WiFi network information (used during setup)
 #[derive(Debug, Clone)]
 pub struct WifiNetwork {
@@ -127,6 +284,19 @@ pub struct WifiNetwork {
     pub frequency: u16, // MHz
 }
 
+impl WifiNetwork {
+    /// Signal quality as 0-4 bars
+    pub fn signal_bars(&self) -> u8 {
+        match self.signal_strength {
+            s if s >= -55 => 4,
+            s if s >= -67 => 3,
+            s if s >= -75 => 2,
+            s if s >= -85 => 1,
+            _ => 0,
+        }
+    }
+}
+
 /// WiFi security type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SecurityType {
@@ -134,3 +304,19 @@ pub enum SecurityType {
     WPA2Personal,
     WPA3Personal,
 }
+
+impl SecurityType {
+    /// Whether joining the network needs a passphrase
+    pub fn needs_passphrase(self) -> bool {
+        self != SecurityType::Open
+    }
+
+    /// Short label for network lists
+    pub fn label(self) -> &'static str {
+        match self {
+            SecurityType::Open => "Open",
+            SecurityType::WPA2Personal => "WPA2",
+            SecurityType::WPA3Personal => "WPA3",
+        }
+    }
+}
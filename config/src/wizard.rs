@@ -10,7 +10,8 @@
 //! (via `WizardEvent`) that the caller must handle:
 //!
 //! - `RequestWifiScan` - Caller should scan for WiFi networks and call `set_wifi_networks()`
-//! - `RequestWifiConnect` - Caller should connect to WiFi with provided credentials
+//! - `WifiChosen` - Caller should join the chosen network (passphrase is `None` if open)
+//! - `RequestApiKeyValidation` - Caller should test the key and call `set_api_key_validation()`
 //! - `ConfigReady` - Caller should save the configuration (e.g., to EFI variables)
 //! - `Complete` - Wizard finished successfully
 //!
@@ -35,8 +36,12 @@
 //!             let networks = scan_wifi();
 //!             wizard.set_wifi_networks(networks);
 //!         }
-//!         WizardEvent::RequestWifiConnect { ssid, password } => {
-//!             connect_wifi(&ssid, &password);
+//!         WizardEvent::WifiChosen { ssid, passphrase, .. } => {
+//!             connect_wifi(&ssid, passphrase.as_deref());
+//!         }
+//!         WizardEvent::RequestApiKeyValidation { config, .. } => {
+//!             let result = test_api_key(&config);
+//!             wizard.set_api_key_validation(result);
 //!         }
 //!         WizardEvent::ConfigReady(config) => {
 //!             storage.save(&config)?;
@@ -52,9 +57,13 @@
 extern crate alloc;
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::cmp::Reverse;
 
 use crate::crypto;
-use crate::types::{ConnectionType, MoteConfig, ProviderConfig, WifiNetwork};
+use crate::types::{
+    ConnectionType, MoteConfig, ProviderConfig, SecurityType, WifiNetwork,
+    DEFAULT_AZURE_API_VERSION,
+};
 
 /// Setup wizard state machine
 #[derive(Debug)]
@@ -87,10 +96,16 @@ pub enum WizardState {
     NetworkScan { networks: Vec<WifiNetwork> },
 
     /// WiFi network selection from scanned networks
-    NetworkSelect { selected_index: usize },
+    WifiSelect {
+        networks: Vec<WifiNetwork>,
+        selected_index: usize,
+    },
 
-    /// WiFi password input
-    NetworkPassword { ssid: String },
+    /// WiFi passphrase input for a secured network
+    NetworkPassword {
+        ssid: String,
+        security: SecurityType,
+    },
 
     /// API key configuration selection
     ApiKeyMenu,
@@ -98,9 +113,27 @@ pub enum WizardState {
     /// API key input for specific provider
     ApiKeyInput { provider: ApiKeyProvider },
 
+    /// Azure OpenAI deployment details input (after the API key)
+    AzureDetailsInput { field: AzureField },
+
+    /// Custom server URL and model input (after the optional API key)
+    CustomDetailsInput { field: CustomField },
+
+    /// API key is being checked against the provider
+    ApiKeyValidating { provider: ApiKeyProvider },
+
+    /// API key check finished; `error` is set if it failed
+    ApiKeyValidated {
+        provider: ApiKeyProvider,
+        error: Option<String>,
+    },
+
     /// Ready screen (summary before saving)
     Ready { config: MoteConfig },
 
+    /// System prompt input, reached from the ready screen
+    SystemPromptInput,
+
     /// Completion screen
     Complete,
 }
@@ -112,9 +145,77 @@ pub enum ApiKeyProvider {
     Anthropic,
     Groq,
     XAI,
+    Azure,
+    Custom, // Self-hosted OpenAI-compatible server
     Skip, // Skip to use local model only
 }
 
+impl ApiKeyProvider {
+    /// Model used for a freshly configured provider
+    pub fn default_model(self) -> &'static str {
+        match self {
+            ApiKeyProvider::OpenAI => "gpt-4o",
+            ApiKeyProvider::Anthropic => "claude-sonnet-4-20250514",
+            ApiKeyProvider::Groq => "llama-3.3-70b-versatile",
+            ApiKeyProvider::XAI => "grok-2",
+            // Azure serves whatever the deployment points at
+            ApiKeyProvider::Azure => "",
+            // Entered along with the server URL
+            ApiKeyProvider::Custom => "",
+            ApiKeyProvider::Skip => "",
+        }
+    }
+}
+
+/// Azure OpenAI field currently being entered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AzureField {
+    /// Resource name (`{resource}.openai.azure.com`)
+    Resource,
+    /// Deployment name
+    Deployment,
+    /// API version (empty keeps the default)
+    ApiVersion,
+}
+
+/// Custom server field currently being entered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CustomField {
+    /// Base URL including the version prefix (`http://host:11434/v1`)
+    BaseUrl,
+    /// Model name as the server knows it
+    Model,
+}
+
+/// Shortest WPA passphrase accepted
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Longest WPA passphrase accepted
+pub const MAX_PASSPHRASE_LEN: usize = 63;
+
+/// One row of the network list: lock, SSID, security and signal bars
+///
+/// Only ASCII is used so the line renders with any console font.
+pub fn wifi_network_label(network: &WifiNetwork) -> String {
+    let lock = if network.security.needs_passphrase() {
+        '*'
+    } else {
+        ' '
+    };
+    let bars = network.signal_bars() as usize;
+    let mut signal = String::new();
+    for i in 0..4 {
+        signal.push(if i < bars { '|' } else { '.' });
+    }
+    alloc::format!(
+        "{} {:<24} {:<4} {}",
+        lock,
+        network.ssid,
+        network.security.label(),
+        signal
+    )
+}
+
 /// Events emitted by the wizard
 #[derive(Debug, Clone)]
 pub enum WizardEvent {
@@ -124,8 +225,18 @@ pub enum WizardEvent {
     /// Request WiFi scan
     RequestWifiScan,
 
-    /// Request WiFi connection with credentials
-    RequestWifiConnect { ssid: String, password: String },
+    /// A network was picked; `passphrase` is `None` for open networks
+    WifiChosen {
+        ssid: String,
+        security: SecurityType,
+        passphrase: Option<String>,
+    },
+
+    /// Request a check of the API key just entered for `provider`
+    RequestApiKeyValidation {
+        provider: ApiKeyProvider,
+        config: MoteConfig,
+    },
 
     /// Configuration is ready to be saved
     ConfigReady(MoteConfig),
@@ -206,20 +317,87 @@ impl SetupWizard {
             WizardState::Welcome => self.handle_welcome_input(key),
             WizardState::NetworkTypeSelect => self.handle_network_type_select(key),
             WizardState::NetworkScan { .. } => self.handle_network_scan_input(key),
-            WizardState::NetworkSelect { .. } => self.handle_network_select_input(key),
+            WizardState::WifiSelect { .. } => self.handle_network_select_input(key),
             WizardState::NetworkPassword { .. } => self.handle_password_input(key),
             WizardState::ApiKeyMenu => self.handle_api_key_menu_input(key),
             WizardState::ApiKeyInput { .. } => self.handle_api_key_input(key),
+            WizardState::AzureDetailsInput { field } => {
+                let field = *field;
+                self.handle_azure_details_input(field, key)
+            }
+            WizardState::CustomDetailsInput { field } => {
+                let field = *field;
+                self.handle_custom_details_input(field, key)
+            }
+            WizardState::ApiKeyValidating { .. } => WizardEvent::None,
+            WizardState::ApiKeyValidated { .. } => self.handle_api_key_validated_input(key),
             WizardState::Ready { .. } => self.handle_ready_input(key),
+            WizardState::SystemPromptInput => self.handle_system_prompt_input(key),
             WizardState::Complete => WizardEvent::Complete,
         }
     }
 
     /// Update with WiFi scan results
-    pub fn set_wifi_networks(&mut self, networks: Vec<WifiNetwork>) {
-        self.available_networks = networks.clone();
+    ///
+    /// Networks are listed strongest first.
+    pub fn set_wifi_networks(&mut self, mut networks: Vec<WifiNetwork>) {
+        networks.sort_by_key(|n| Reverse(n.signal_strength));
+        self.available_networks = networks;
         self.selected_network_index = 0;
-        self.state = WizardState::NetworkSelect { selected_index: 0 };
+        self.state = self.wifi_select_state();
+    }
+
+    /// Network list state with the current selection
+    fn wifi_select_state(&self) -> WizardState {
+        WizardState::WifiSelect {
+            networks: self.available_networks.clone(),
+            selected_index: self.selected_network_index,
+        }
+    }
+
+    /// Record the chosen network and move on to provider setup
+    fn choose_wifi(
+        &mut self,
+        ssid: String,
+        security: SecurityType,
+        passphrase: Option<String>,
+    ) -> WizardEvent {
+        self.config.network.wifi_ssid = Some(ssid.clone());
+        self.config.network.wifi_password_encrypted = passphrase
+            .as_deref()
+            .and_then(|p| crypto::encrypt_api_key(p).ok());
+        self.input_buffer.clear();
+        self.cursor_pos = 0;
+        self.state = WizardState::ApiKeyMenu;
+        WizardEvent::WifiChosen {
+            ssid,
+            security,
+            passphrase,
+        }
+    }
+
+    /// Update with the result of an API key check
+    ///
+    /// `result` carries a message to show when the key was not accepted.
+    /// Ignored unless a check is in progress.
+    pub fn set_api_key_validation(&mut self, result: Result<(), String>) {
+        if let WizardState::ApiKeyValidating { provider } = self.state {
+            self.state = WizardState::ApiKeyValidated {
+                provider,
+                error: result.err(),
+            };
+        }
+    }
+
+    /// Start checking the key for the current provider
+    fn request_api_key_validation(&mut self) -> WizardEvent {
+        self.state = WizardState::ApiKeyValidating {
+            provider: self.current_provider,
+        };
+        WizardEvent::RequestApiKeyValidation {
+            provider: self.current_provider,
+            config: self.config.clone(),
+        }
     }
 
     /// Handle welcome screen input
@@ -277,22 +455,34 @@ impl SetupWizard {
                 if self.selected_network_index > 0 {
                     self.selected_network_index -= 1;
                 }
+                self.state = self.wifi_select_state();
                 WizardEvent::None
             }
             Key::Down => {
                 if self.selected_network_index < self.available_networks.len().saturating_sub(1) {
                     self.selected_network_index += 1;
                 }
+                self.state = self.wifi_select_state();
                 WizardEvent::None
             }
+            Key::Char('r') => {
+                self.state = WizardState::NetworkScan {
+                    networks: Vec::new(),
+                };
+                WizardEvent::RequestWifiScan
+            }
             Key::Enter => {
-                if let Some(network) = self.available_networks.get(self.selected_network_index) {
-                    let ssid = network.ssid.clone();
-                    self.config.network.wifi_ssid = Some(ssid.clone());
-                    self.input_buffer.clear();
-                    self.cursor_pos = 0;
-                    self.state = WizardState::NetworkPassword { ssid };
+                let Some(network) = self.available_networks.get(self.selected_network_index) else {
+                    return WizardEvent::None;
+                };
+                let ssid = network.ssid.clone();
+                let security = network.security;
+                if !security.needs_passphrase() {
+                    return self.choose_wifi(ssid, security, None);
                 }
+                self.input_buffer.clear();
+                self.cursor_pos = 0;
+                self.state = WizardState::NetworkPassword { ssid, security };
                 WizardEvent::None
             }
             Key::Esc => {
@@ -319,24 +509,22 @@ impl SetupWizard {
                 WizardEvent::None
             }
             Key::Enter => {
-                if let WizardState::NetworkPassword { ssid } = &self.state {
-                    let password = self.input_buffer.clone();
+                if let WizardState::NetworkPassword { ssid, security } = &self.state {
+                    // WPA passphrases are 8 to 63 characters
+                    if !(MIN_PASSPHRASE_LEN..=MAX_PASSPHRASE_LEN)
+                        .contains(&self.input_buffer.chars().count())
+                    {
+                        return WizardEvent::None;
+                    }
+                    let passphrase = self.input_buffer.clone();
                     let ssid = ssid.clone();
-
-                    // Move to API key menu
-                    self.state = WizardState::ApiKeyMenu;
-                    self.input_buffer.clear();
-                    self.cursor_pos = 0;
-
-                    // Return event to connect
-                    return WizardEvent::RequestWifiConnect { ssid, password };
+                    let security = *security;
+                    return self.choose_wifi(ssid, security, Some(passphrase));
                 }
                 WizardEvent::None
             }
             Key::Esc => {
-                self.state = WizardState::NetworkSelect {
-                    selected_index: self.selected_network_index,
-                };
+                self.state = self.wifi_select_state();
                 self.input_buffer.clear();
                 self.cursor_pos = 0;
                 WizardEvent::None
@@ -384,6 +572,24 @@ impl SetupWizard {
                 };
                 WizardEvent::None
             }
+            Key::Char('5') => {
+                self.current_provider = ApiKeyProvider::Azure;
+                self.input_buffer.clear();
+                self.cursor_pos = 0;
+                self.state = WizardState::ApiKeyInput {
+                    provider: ApiKeyProvider::Azure,
+                };
+                WizardEvent::None
+            }
+            Key::Char('6') => {
+                self.current_provider = ApiKeyProvider::Custom;
+                self.input_buffer.clear();
+                self.cursor_pos = 0;
+                self.state = WizardState::ApiKeyInput {
+                    provider: ApiKeyProvider::Custom,
+                };
+                WizardEvent::None
+            }
             Key::Char('s') | Key::Enter => {
                 // Skip - use local model only
                 self.state = WizardState::Ready {
@@ -394,9 +600,7 @@ impl SetupWizard {
             Key::Esc => {
                 // Go back to network selection
                 if self.config.network.connection_type == ConnectionType::Wifi {
-                    self.state = WizardState::NetworkSelect {
-                        selected_index: self.selected_network_index,
-                    };
+                    self.state = self.wifi_select_state();
                 } else {
                     self.state = WizardState::NetworkTypeSelect;
                 }
@@ -429,19 +633,11 @@ impl SetupWizard {
                 match crypto::encrypt_api_key(&api_key) {
                     Ok(encrypted_key) => {
                         // Determine default model for the provider
-                        let default_model = match self.current_provider {
-                            ApiKeyProvider::OpenAI => "gpt-4o",
-                            ApiKeyProvider::Anthropic => "claude-sonnet-4-20250514",
-                            ApiKeyProvider::Groq => "llama-3.3-70b-versatile",
-                            ApiKeyProvider::XAI => "grok-2",
-                            ApiKeyProvider::Skip => "",
-                        };
+                        let default_model = self.current_provider.default_model();
 
                         // Store encrypted API key in config
-                        let provider_config = ProviderConfig {
-                            api_key_encrypted: encrypted_key,
-                            default_model: String::from(default_model),
-                        };
+                        let provider_config =
+                            ProviderConfig::new(encrypted_key, String::from(default_model));
 
                         match self.current_provider {
                             ApiKeyProvider::OpenAI => {
@@ -456,6 +652,12 @@ impl SetupWizard {
                             ApiKeyProvider::XAI => {
                                 self.config.providers.xai = Some(provider_config);
                             }
+                            ApiKeyProvider::Azure => {
+                                self.config.providers.azure = Some(provider_config);
+                            }
+                            ApiKeyProvider::Custom => {
+                                self.config.providers.custom = Some(provider_config);
+                            }
                             ApiKeyProvider::Skip => {}
                         }
 
@@ -463,10 +665,19 @@ impl SetupWizard {
                         self.input_buffer.clear();
                         self.cursor_pos = 0;
 
-                        // Move to ready state
-                        self.state = WizardState::Ready {
-                            config: self.config.clone(),
-                        };
+                        if self.current_provider == ApiKeyProvider::Azure {
+                            // Azure also needs the resource and deployment
+                            self.state = WizardState::AzureDetailsInput {
+                                field: AzureField::Resource,
+                            };
+                        } else if self.current_provider == ApiKeyProvider::Custom {
+                            // A custom server needs its URL and a model
+                            self.state = WizardState::CustomDetailsInput {
+                                field: CustomField::BaseUrl,
+                            };
+                        } else {
+                            return self.request_api_key_validation();
+                        }
                     }
                     Err(_) => {
                         // Encryption failed - stay in current state
@@ -489,6 +700,165 @@ impl SetupWizard {
         }
     }
 
+    /// Handle Azure OpenAI resource/deployment/api-version input
+    fn handle_azure_details_input(&mut self, field: AzureField, key: Key) -> WizardEvent {
+        match key {
+            Key::Char(ch) => {
+                self.input_buffer.push(ch);
+                self.cursor_pos += 1;
+                WizardEvent::None
+            }
+            Key::Backspace => {
+                if !self.input_buffer.is_empty() && self.cursor_pos > 0 {
+                    self.input_buffer.remove(self.cursor_pos - 1);
+                    self.cursor_pos -= 1;
+                }
+                WizardEvent::None
+            }
+            Key::Enter => {
+                let value = String::from(self.input_buffer.trim());
+                if value.is_empty() && field != AzureField::ApiVersion {
+                    // Resource and deployment are required
+                    return WizardEvent::None;
+                }
+
+                if let Some(azure) = self.config.providers.azure.as_mut() {
+                    match field {
+                        AzureField::Resource => azure.azure_resource = Some(value),
+                        AzureField::Deployment => {
+                            azure.default_model = value.clone();
+                            azure.azure_deployment = Some(value);
+                        }
+                        AzureField::ApiVersion => {
+                            azure.azure_api_version = Some(if value.is_empty() {
+                                String::from(DEFAULT_AZURE_API_VERSION)
+                            } else {
+                                value
+                            });
+                        }
+                    }
+                }
+
+                self.input_buffer.clear();
+                self.cursor_pos = 0;
+                self.state = match field {
+                    AzureField::Resource => WizardState::AzureDetailsInput {
+                        field: AzureField::Deployment,
+                    },
+                    AzureField::Deployment => WizardState::AzureDetailsInput {
+                        field: AzureField::ApiVersion,
+                    },
+                    // The key can only be checked once the deployment is known
+                    AzureField::ApiVersion => return self.request_api_key_validation(),
+                };
+                WizardEvent::None
+            }
+            Key::Esc => {
+                self.input_buffer.clear();
+                self.cursor_pos = 0;
+                self.state = match field {
+                    AzureField::Resource => WizardState::ApiKeyInput {
+                        provider: ApiKeyProvider::Azure,
+                    },
+                    AzureField::Deployment => WizardState::AzureDetailsInput {
+                        field: AzureField::Resource,
+                    },
+                    AzureField::ApiVersion => WizardState::AzureDetailsInput {
+                        field: AzureField::Deployment,
+                    },
+                };
+                WizardEvent::None
+            }
+            _ => WizardEvent::None,
+        }
+    }
+
+    /// Handle custom server URL/model input
+    fn handle_custom_details_input(&mut self, field: CustomField, key: Key) -> WizardEvent {
+        match key {
+            Key::Char(ch) => {
+                self.input_buffer.push(ch);
+                self.cursor_pos += 1;
+                WizardEvent::None
+            }
+            Key::Backspace => {
+                if !self.input_buffer.is_empty() && self.cursor_pos > 0 {
+                    self.input_buffer.remove(self.cursor_pos - 1);
+                    self.cursor_pos -= 1;
+                }
+                WizardEvent::None
+            }
+            Key::Enter => {
+                let value = String::from(self.input_buffer.trim());
+                if value.is_empty() {
+                    // Both the URL and the model are required
+                    return WizardEvent::None;
+                }
+
+                if let Some(custom) = self.config.providers.custom.as_mut() {
+                    match field {
+                        CustomField::BaseUrl => custom.base_url = Some(value),
+                        CustomField::Model => custom.default_model = value,
+                    }
+                }
+
+                self.input_buffer.clear();
+                self.cursor_pos = 0;
+                match field {
+                    CustomField::BaseUrl => {
+                        self.state = WizardState::CustomDetailsInput {
+                            field: CustomField::Model,
+                        };
+                        WizardEvent::None
+                    }
+                    CustomField::Model => self.request_api_key_validation(),
+                }
+            }
+            Key::Esc => {
+                self.input_buffer.clear();
+                self.cursor_pos = 0;
+                self.state = match field {
+                    CustomField::BaseUrl => WizardState::ApiKeyInput {
+                        provider: ApiKeyProvider::Custom,
+                    },
+                    CustomField::Model => WizardState::CustomDetailsInput {
+                        field: CustomField::BaseUrl,
+                    },
+                };
+                WizardEvent::None
+            }
+            _ => WizardEvent::None,
+        }
+    }
+
+    /// Handle the API key check result screen
+    fn handle_api_key_validated_input(&mut self, key: Key) -> WizardEvent {
+        let (provider, failed) = match &self.state {
+            WizardState::ApiKeyValidated { provider, error } => (*provider, error.is_some()),
+            _ => return WizardEvent::None,
+        };
+
+        match key {
+            Key::Enter if failed => {
+                // Re-enter the key
+                self.state = WizardState::ApiKeyInput { provider };
+                WizardEvent::None
+            }
+            // Keep a key that failed the check (e.g. no network yet)
+            Key::Enter | Key::Char('c') | Key::Char('C') => {
+                self.state = WizardState::Ready {
+                    config: self.config.clone(),
+                };
+                WizardEvent::None
+            }
+            Key::Esc => {
+                self.state = WizardState::ApiKeyMenu;
+                WizardEvent::None
+            }
+            _ => WizardEvent::None,
+        }
+    }
+
     /// Handle ready screen
     fn handle_ready_input(&mut self, key: Key) -> WizardEvent {
         match key {
@@ -496,6 +866,12 @@ impl SetupWizard {
                 self.state = WizardState::Complete;
                 WizardEvent::ConfigReady(self.config.clone())
             }
+            Key::Char('p') => {
+                self.input_buffer = self.config.preferences.system_prompt.clone();
+                self.cursor_pos = self.input_buffer.chars().count();
+                self.state = WizardState::SystemPromptInput;
+                WizardEvent::None
+            }
             Key::Esc => {
                 self.state = WizardState::ApiKeyMenu;
                 WizardEvent::None
@@ -503,6 +879,41 @@ impl SetupWizard {
             _ => WizardEvent::None,
         }
     }
+
+    /// Handle system prompt input; empty clears it
+    fn handle_system_prompt_input(&mut self, key: Key) -> WizardEvent {
+        match key {
+            Key::Char(ch) => {
+                self.input_buffer.push(ch);
+                self.cursor_pos += 1;
+                WizardEvent::None
+            }
+            Key::Backspace => {
+                if self.input_buffer.pop().is_some() {
+                    self.cursor_pos -= 1;
+                }
+                WizardEvent::None
+            }
+            Key::Enter => {
+                self.config.preferences.system_prompt = String::from(self.input_buffer.trim());
+                self.input_buffer.clear();
+                self.cursor_pos = 0;
+                self.state = WizardState::Ready {
+                    config: self.config.clone(),
+                };
+                WizardEvent::None
+            }
+            Key::Esc => {
+                self.input_buffer.clear();
+                self.cursor_pos = 0;
+                self.state = WizardState::Ready {
+                    config: self.config.clone(),
+                };
+                WizardEvent::None
+            }
+            _ => WizardEvent::None,
+        }
+    }
 }
 
 impl Default for SetupWizard {
@@ -510,3 +921,247 @@ impl Default for SetupWizard {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wizard_validating(provider: ApiKeyProvider) -> SetupWizard {
+        let mut wizard = SetupWizard::new();
+        wizard.current_provider = provider;
+        wizard.state = WizardState::ApiKeyInput { provider };
+        for ch in "sk-test".chars() {
+            wizard.handle_input(Key::Char(ch));
+        }
+        let event = wizard.handle_input(Key::Enter);
+        assert!(matches!(
+            event,
+            WizardEvent::RequestApiKeyValidation { provider: p, .. } if p == provider
+        ));
+        wizard
+    }
+
+    fn network(ssid: &str, signal_strength: i8, security: SecurityType) -> WifiNetwork {
+        WifiNetwork {
+            ssid: String::from(ssid),
+            bssid: [2, 0, 0, 0, 0, signal_strength as u8],
+            signal_strength,
+            security,
+            channel: 6,
+            frequency: 2437,
+        }
+    }
+
+    /// Wizard showing a synthetic scan: "home" (WPA2) above "cafe" (open)
+    fn wizard_selecting_wifi() -> SetupWizard {
+        let mut wizard = SetupWizard::new();
+        wizard.handle_input(Key::Enter);
+        assert!(matches!(
+            wizard.handle_input(Key::Char('2')),
+            WizardEvent::RequestWifiScan
+        ));
+        wizard.set_wifi_networks(alloc::vec![
+            network("cafe", -80, SecurityType::Open),
+            network("home", -50, SecurityType::WPA2Personal),
+        ]);
+        let WizardState::WifiSelect {
+            networks,
+            selected_index: 0,
+        } = wizard.state()
+        else {
+            panic!("expected the network list");
+        };
+        assert_eq!(networks[0].ssid, "home");
+        wizard
+    }
+
+    #[test]
+    fn test_open_network_is_chosen_without_passphrase() {
+        let mut wizard = wizard_selecting_wifi();
+        wizard.handle_input(Key::Down);
+        assert!(matches!(
+            wizard.state(),
+            WizardState::WifiSelect {
+                selected_index: 1,
+                ..
+            }
+        ));
+        let event = wizard.handle_input(Key::Enter);
+        let WizardEvent::WifiChosen {
+            ssid,
+            security,
+            passphrase,
+        } = event
+        else {
+            panic!("expected a chosen network");
+        };
+        assert_eq!(ssid, "cafe");
+        assert_eq!(security, SecurityType::Open);
+        assert_eq!(passphrase, None);
+        assert!(matches!(wizard.state(), WizardState::ApiKeyMenu));
+        assert_eq!(wizard.config.network.wifi_ssid.as_deref(), Some("cafe"));
+        assert!(wizard.config.network.wifi_password_encrypted.is_none());
+    }
+
+    #[test]
+    fn test_wpa2_network_prompts_for_passphrase() {
+        let mut wizard = wizard_selecting_wifi();
+        assert!(matches!(wizard.handle_input(Key::Enter), WizardEvent::None));
+        assert!(matches!(
+            wizard.state(),
+            WizardState::NetworkPassword {
+                security: SecurityType::WPA2Personal,
+                ..
+            }
+        ));
+
+        // Too short for WPA2
+        for ch in "short".chars() {
+            wizard.handle_input(Key::Char(ch));
+        }
+        assert!(matches!(wizard.handle_input(Key::Enter), WizardEvent::None));
+        for ch in "-but-now-long".chars() {
+            wizard.handle_input(Key::Char(ch));
+        }
+        let event = wizard.handle_input(Key::Enter);
+        let WizardEvent::WifiChosen {
+            ssid,
+            security,
+            passphrase,
+        } = event
+        else {
+            panic!("expected a chosen network");
+        };
+        assert_eq!(ssid, "home");
+        assert_eq!(security, SecurityType::WPA2Personal);
+        assert_eq!(passphrase.as_deref(), Some("short-but-now-long"));
+        let stored = wizard
+            .config
+            .network
+            .wifi_password_encrypted
+            .as_ref()
+            .unwrap();
+        assert_eq!(
+            crypto::decrypt_api_key(stored).unwrap(),
+            "short-but-now-long"
+        );
+
+        // Going back from the provider menu returns to the list
+        wizard.handle_input(Key::Esc);
+        assert!(matches!(wizard.state(), WizardState::WifiSelect { .. }));
+    }
+
+    #[test]
+    fn test_network_label_shows_security_and_signal() {
+        let label = wifi_network_label(&network("home", -60, SecurityType::WPA2Personal));
+        assert!(label.starts_with("* home"));
+        assert!(label.ends_with("WPA2 |||."));
+        let label = wifi_network_label(&network("cafe", -90, SecurityType::Open));
+        assert!(label.starts_with("  cafe"));
+        assert!(label.ends_with("Open ...."));
+    }
+
+    #[test]
+    fn test_key_entry_requests_validation() {
+        let wizard = wizard_validating(ApiKeyProvider::Groq);
+        assert!(matches!(
+            wizard.state(),
+            WizardState::ApiKeyValidating {
+                provider: ApiKeyProvider::Groq
+            }
+        ));
+        assert!(wizard.input_buffer().is_empty());
+    }
+
+    #[test]
+    fn test_valid_key_moves_to_ready() {
+        let mut wizard = wizard_validating(ApiKeyProvider::OpenAI);
+        wizard.set_api_key_validation(Ok(()));
+        assert!(matches!(
+            wizard.state(),
+            WizardState::ApiKeyValidated { error: None, .. }
+        ));
+        wizard.handle_input(Key::Enter);
+        assert!(matches!(wizard.state(), WizardState::Ready { .. }));
+    }
+
+    #[test]
+    fn test_custom_server_asks_for_url_and_model() {
+        let mut wizard = SetupWizard::new();
+        wizard.handle_input(Key::Enter);
+        wizard.handle_input(Key::Char('1'));
+        wizard.handle_input(Key::Char('6'));
+        // No key for a local server
+        wizard.handle_input(Key::Enter);
+        assert!(matches!(
+            wizard.state(),
+            WizardState::CustomDetailsInput {
+                field: CustomField::BaseUrl
+            }
+        ));
+        for ch in "http://192.168.1.10:11434/v1".chars() {
+            wizard.handle_input(Key::Char(ch));
+        }
+        wizard.handle_input(Key::Enter);
+        for ch in "llama3.2".chars() {
+            wizard.handle_input(Key::Char(ch));
+        }
+        let event = wizard.handle_input(Key::Enter);
+        let WizardEvent::RequestApiKeyValidation { provider, config } = event else {
+            panic!("expected a validation request");
+        };
+        assert_eq!(provider, ApiKeyProvider::Custom);
+        let custom = config.providers.custom.unwrap();
+        assert!(custom.api_key_encrypted.is_empty());
+        assert_eq!(custom.base_url.as_deref(), Some("http://192.168.1.10:11434/v1"));
+        assert_eq!(custom.default_model, "llama3.2");
+    }
+
+    #[test]
+    fn test_rejected_key_can_be_retried_or_kept() {
+        let mut wizard = wizard_validating(ApiKeyProvider::Anthropic);
+        wizard.set_api_key_validation(Err(String::from("API key rejected")));
+        wizard.handle_input(Key::Enter);
+        assert!(matches!(
+            wizard.state(),
+            WizardState::ApiKeyInput {
+                provider: ApiKeyProvider::Anthropic
+            }
+        ));
+
+        let mut wizard = wizard_validating(ApiKeyProvider::Anthropic);
+        wizard.set_api_key_validation(Err(String::from("unreachable")));
+        wizard.handle_input(Key::Char('c'));
+        assert!(matches!(wizard.state(), WizardState::Ready { .. }));
+    }
+
+    #[test]
+    fn test_system_prompt_is_set_from_ready_screen() {
+        let mut wizard = SetupWizard::new();
+        wizard.handle_input(Key::Enter);
+        wizard.handle_input(Key::Char('1'));
+        wizard.handle_input(Key::Char('s'));
+        assert!(matches!(wizard.state(), WizardState::Ready { .. }));
+
+        wizard.handle_input(Key::Char('p'));
+        assert!(matches!(wizard.state(), WizardState::SystemPromptInput));
+        for ch in "Be briefx".chars() {
+            wizard.handle_input(Key::Char(ch));
+        }
+        wizard.handle_input(Key::Backspace);
+        wizard.handle_input(Key::Char('.'));
+        wizard.handle_input(Key::Enter);
+        assert!(matches!(wizard.state(), WizardState::Ready { .. }));
+
+        // Editing starts from the current prompt; ESC keeps it
+        wizard.handle_input(Key::Char('p'));
+        assert_eq!(wizard.input_buffer(), "Be brief.");
+        wizard.handle_input(Key::Char('!'));
+        wizard.handle_input(Key::Esc);
+
+        let WizardEvent::ConfigReady(config) = wizard.handle_input(Key::Enter) else {
+            panic!("expected the config");
+        };
+        assert_eq!(config.preferences.system_prompt, "Be brief.");
+    }
+}
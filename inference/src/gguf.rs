@@ -10,6 +10,13 @@ const GGUF_MAGIC: &[u8; 4] = b"GGUF";
 /// Supported GGUF version
 const GGUF_VERSION: u32 = 3;
 
+/// ggml tensor type IDs (`enum ggml_type` in ggml.h) that can be loaded
+pub const GGML_TYPE_F32: u32 = 0;
+pub const GGML_TYPE_F16: u32 = 1;
+pub const GGML_TYPE_Q8_0: u32 = 8;
+pub const GGML_TYPE_Q4_K: u32 = 12;
+pub const GGML_TYPE_Q5_K: u32 = 13;
+
 /// GGUF file parser and container
 ///
 /// This struct parses and provides access to GGUF (GPT-Generated Unified Format) files.
@@ -442,54 +449,36 @@ impl GgufFile {
 
     /// Calculate tensor size in bytes based on dimensions and type
     ///
-    /// Note: For quantized types (Q4_K_M, Q5_K, etc.), the actual size
-    /// calculation is more complex due to block structures. This implementation
-    /// provides a simplified calculation. For production use, you may want to
-    /// implement the full quantized tensor size calculation.
+    /// Quantized types store blocks of 32 or 256 elements; the sizes are
+    /// those of the ggml block structs.
     fn calculate_tensor_size(dimensions: &[u64], tensor_type: u32) -> Result<usize, ParseError> {
         // Calculate total elements
         let elements: u64 = dimensions.iter().product();
 
-        // Get element size based on tensor type
-        // GGUF tensor types (from llama.cpp/ggml.h)
-        // Note: Quantized types have block structures, so this is approximate
-        let element_size = match tensor_type {
-            0 => 4,   // F32 (4 bytes per element)
-            1 => 2,   // F16 (2 bytes per element)
-            2 => 18,  // Q4_0 (block size 32, 18 bytes per block)
-            3 => 20,  // Q4_1 (block size 32, 20 bytes per block)
-            4 => 24,  // Q4_K (block size 32, variable)
-            5 => 22,  // Q5_0 (block size 32, 22 bytes per block)
-            6 => 24,  // Q5_1 (block size 32, 24 bytes per block)
-            7 => 28,  // Q5_K (block size 32, variable)
-            8 => 30,  // Q6_K (block size 32, variable)
-            9 => 34,  // Q8_0 (block size 32, 34 bytes per block)
-            10 => 36, // Q8_1 (block size 32, 36 bytes per block)
-            11 => 18, // Q2_K (block size 256, variable)
-            12 => 20, // Q3_K (block size 256, variable)
-            13 => 24, // Q4_K_M (block size 256, variable)
-            14 => 28, // Q5_K_M (block size 256, variable)
-            15 => 30, // Q6_K_M (block size 256, variable)
+        // (elements per block, bytes per block), from ggml's type traits
+        let (block_size, block_bytes): (u64, usize) = match tensor_type {
+            GGML_TYPE_F32 => (1, 4),
+            GGML_TYPE_F16 => (1, 2),
+            2 => (32, 18),  // Q4_0
+            3 => (32, 20),  // Q4_1
+            6 => (32, 22),  // Q5_0
+            7 => (32, 24),  // Q5_1
+            GGML_TYPE_Q8_0 => (32, crate::tensor::BLOCK_Q8_0_SIZE),
+            9 => (32, 36),  // Q8_1
+            10 => (256, 84),  // Q2_K
+            11 => (256, 110), // Q3_K
+            GGML_TYPE_Q4_K => (256, 144),
+            GGML_TYPE_Q5_K => (256, crate::tensor::BLOCK_Q5K_SIZE),
+            14 => (256, 210), // Q6_K
+            15 => (256, 292), // Q8_K
             _ => {
                 // Default to 4 bytes for unknown types (conservative)
-                4
+                (1, 4)
             }
         };
 
-        // For quantized types, calculate based on blocks
-        // Most quantized types use blocks of 32 or 256 elements
-        let size = if tensor_type >= 2 && tensor_type <= 15 {
-            // Quantized types: calculate based on block structure
-            // This is simplified - actual calculation depends on block size
-            let block_size = if tensor_type >= 11 { 256 } else { 32 };
-            let blocks = (elements + block_size - 1) / block_size; // Ceiling division
-            (blocks as usize) * element_size
-        } else {
-            // Non-quantized types: simple multiplication
-            (elements as usize) * element_size
-        };
-
-        Ok(size)
+        let blocks = elements.div_ceil(block_size);
+        Ok(blocks as usize * block_bytes)
     }
 }
 
@@ -500,6 +489,14 @@ mod tests {
     // Note: These tests would require actual GGUF file data
     // For now, they're placeholders
 
+    #[test]
+    fn test_quantized_tensor_sizes() {
+        let size = |tensor_type| GgufFile::calculate_tensor_size(&[512, 64], tensor_type).unwrap();
+        assert_eq!(size(GGML_TYPE_F32), 512 * 64 * 4);
+        assert_eq!(size(GGML_TYPE_Q8_0), 512 * 64 / 32 * 34);
+        assert_eq!(size(GGML_TYPE_Q5_K), 512 * 64 / 256 * 176);
+    }
+
     #[test]
     fn test_magic_validation() {
         // This would test with invalid magic
@@ -4,6 +4,7 @@ extern crate alloc;
 
 pub mod error;
 pub mod gguf;
+pub mod loader;
 pub mod ops;
 pub mod simd;
 pub mod tensor;
@@ -11,13 +12,17 @@ pub mod tokenizer;
 pub mod transformer;
 pub mod sampling;
 pub mod model;
+pub mod provider;
 
 pub use error::{ModelError, ParseError, TokenizerError};
 pub use gguf::{GgufFile, MetadataValue, TensorInfo};
-pub use tensor::{BlockQ4K, Tensor, TensorData, QK_K};
+pub use ops::RopeScaling;
+pub use tensor::{BlockQ4K, BlockQ5K, BlockQ8_0, Tensor, TensorData, QK8_0, QK_K};
 pub use tokenizer::{SpecialTokens, Tokenizer};
 pub use transformer::{
     EmbeddingWeights, KvCache, ModelConfig, ModelWeights, OutputWeights, Transformer,
     TransformerLayerWeights,
 };
 pub use model::LocalModel;
+pub use provider::{apply_chat_template, LocalProvider, LOCAL_PROVIDER_NAME};
+pub use sampling::SamplingConfig;
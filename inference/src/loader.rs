@@ -0,0 +1,355 @@
+//! Building a model from a parsed GGUF file
+//!
+//! Tensor names follow llama.cpp's llama layout (`token_embd.weight`,
+//! `blk.N.attn_q.weight`, ...). Loading copies or converts every tensor, which
+//! takes a while for a large file, so progress is reported per tensor.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::error::ModelError;
+use crate::gguf::{
+    GgufFile, MetadataValue, GGML_TYPE_F16, GGML_TYPE_F32, GGML_TYPE_Q4_K, GGML_TYPE_Q5_K,
+    GGML_TYPE_Q8_0,
+};
+use crate::ops::RopeScaling;
+use crate::tensor::{f16_to_f32, Tensor, TensorData};
+use crate::transformer::{
+    EmbeddingWeights, ModelConfig, ModelWeights, OutputWeights, TransformerLayerWeights,
+};
+
+/// Tensors read for each transformer block
+const TENSORS_PER_LAYER: usize = 9;
+
+impl ModelConfig {
+    /// Read the model dimensions from GGUF metadata
+    ///
+    /// Keys are prefixed with `general.architecture` (e.g. `llama.block_count`).
+    pub fn from_gguf(gguf: &GgufFile) -> Result<Self, ModelError> {
+        let arch = match gguf.get_metadata("general.architecture") {
+            Some(MetadataValue::String(arch)) => arch.as_str(),
+            _ => "llama",
+        };
+        let key = |name: &str| format!("{}.{}", arch, name);
+        let required = |name: &str| {
+            let key = key(name);
+            metadata_usize(gguf, &key).ok_or(ModelError::MetadataNotFound(key))
+        };
+
+        let hidden_size = required("embedding_length")?;
+        let num_heads = required("attention.head_count")?;
+        if num_heads == 0 || hidden_size % num_heads != 0 {
+            return Err(ModelError::InvalidInput(format!(
+                "{} heads do not divide hidden size {}",
+                num_heads, hidden_size
+            )));
+        }
+        let vocab_size = match gguf.get_metadata("tokenizer.ggml.tokens") {
+            Some(MetadataValue::Array(tokens)) => tokens.len(),
+            _ => required("vocab_size")?,
+        };
+
+        Ok(Self {
+            vocab_size,
+            hidden_size,
+            num_layers: required("block_count")?,
+            num_heads,
+            head_dim: hidden_size / num_heads,
+            intermediate_size: required("feed_forward_length")?,
+            max_seq_len: required("context_length")?,
+            rope_freq_base: metadata_f32(gguf, &key("rope.freq_base")).unwrap_or(10000.0),
+            rope_scaling: rope_scaling(gguf, &key)?,
+            norm_eps: metadata_f32(gguf, &key("attention.layer_norm_rms_epsilon")).unwrap_or(1e-6),
+        })
+    }
+}
+
+impl ModelWeights {
+    /// Load all weights for `config` from `gguf`
+    pub fn from_gguf(gguf: &GgufFile, config: &ModelConfig) -> Result<Self, ModelError> {
+        Self::from_gguf_with_progress(gguf, config, |_, _| {})
+    }
+
+    /// Load all weights, calling `on_progress(tensors_loaded, total_tensors)`
+    /// after each tensor
+    ///
+    /// F32 and F16 projections are converted to f32 and transposed to the
+    /// `(in, out)` layout the F32 kernels use; quantized ones keep ggml's
+    /// row-per-output layout.
+    pub fn from_gguf_with_progress(
+        gguf: &GgufFile,
+        config: &ModelConfig,
+        mut on_progress: impl FnMut(usize, usize),
+    ) -> Result<Self, ModelError> {
+        let tied_output = gguf.get_tensor_info("output.weight").is_none();
+        let total = 1 + usize::from(!tied_output) + TENSORS_PER_LAYER * config.num_layers;
+        let mut loaded = 0;
+        let mut step = || {
+            loaded += 1;
+            on_progress(loaded, total);
+        };
+
+        let hidden = config.hidden_size;
+        let ffn = config.intermediate_size;
+
+        // Rows are looked up directly, so the embedding keeps ggml's layout
+        let embedding = load_matrix(gguf, "token_embd.weight", config.vocab_size, hidden)?;
+        step();
+
+        let mut layers = Vec::with_capacity(config.num_layers);
+        for layer in 0..config.num_layers {
+            let name = |part: &str| format!("blk.{}.{}.weight", layer, part);
+
+            let attention_norm = load_vector(gguf, &name("attn_norm"), hidden)?;
+            step();
+            let q = load_projection(gguf, &name("attn_q"), hidden, hidden)?;
+            step();
+            let k = load_projection(gguf, &name("attn_k"), hidden, hidden)?;
+            step();
+            let v = load_projection(gguf, &name("attn_v"), hidden, hidden)?;
+            step();
+            let attention_qkv = combine_qkv(q, k, v, hidden)?;
+            let attention_output = load_projection(gguf, &name("attn_output"), hidden, hidden)?;
+            step();
+            let ffn_norm = load_vector(gguf, &name("ffn_norm"), hidden)?;
+            step();
+            let ffn_gate = load_projection(gguf, &name("ffn_gate"), hidden, ffn)?;
+            step();
+            let ffn_up = load_projection(gguf, &name("ffn_up"), hidden, ffn)?;
+            step();
+            let ffn_down = load_projection(gguf, &name("ffn_down"), ffn, hidden)?;
+            step();
+
+            layers.push(TransformerLayerWeights {
+                attention_norm,
+                attention_qkv,
+                attention_output,
+                ffn_norm,
+                ffn_gate,
+                ffn_up,
+                ffn_down,
+            });
+        }
+
+        // Models with tied embeddings reuse the embedding matrix
+        let output_name = if tied_output {
+            "token_embd.weight"
+        } else {
+            "output.weight"
+        };
+        let output = load_projection(gguf, output_name, hidden, config.vocab_size)?;
+        if !tied_output {
+            step();
+        }
+
+        Ok(Self {
+            embedding: EmbeddingWeights { weight: embedding },
+            layers,
+            output: OutputWeights { weight: output },
+        })
+    }
+}
+
+/// Integer metadata value, whatever its stored width
+/// RoPE scaling from `rope.scaling.type` / `rope.scaling.factor`, or the
+/// older `rope.scale_linear`
+fn rope_scaling(gguf: &GgufFile, key: &dyn Fn(&str) -> String) -> Result<RopeScaling, ModelError> {
+    let factor = metadata_f32(gguf, &key("rope.scaling.factor"));
+    let scaling = match gguf.get_metadata(&key("rope.scaling.type")) {
+        Some(MetadataValue::String(kind)) => match (kind.as_str(), factor) {
+            ("none", _) => RopeScaling::None,
+            ("linear", Some(factor)) => RopeScaling::Linear { factor },
+            ("ntk" | "ntk-aware", Some(factor)) => RopeScaling::NtkAware { factor },
+            (kind, _) => {
+                return Err(ModelError::InvalidInput(format!(
+                    "Unsupported RoPE scaling '{}'",
+                    kind
+                )))
+            }
+        },
+        _ => match metadata_f32(gguf, &key("rope.scale_linear")) {
+            Some(factor) if factor != 1.0 => RopeScaling::Linear { factor },
+            _ => RopeScaling::None,
+        },
+    };
+    Ok(scaling)
+}
+
+fn metadata_usize(gguf: &GgufFile, key: &str) -> Option<usize> {
+    match gguf.get_metadata(key)? {
+        MetadataValue::UInt32(value) => Some(*value as usize),
+        MetadataValue::UInt64(value) => Some(*value as usize),
+        MetadataValue::Int32(value) => usize::try_from(*value).ok(),
+        MetadataValue::Int64(value) => usize::try_from(*value).ok(),
+        _ => None,
+    }
+}
+
+fn metadata_f32(gguf: &GgufFile, key: &str) -> Option<f32> {
+    match gguf.get_metadata(key)? {
+        MetadataValue::Float32(value) => Some(*value),
+        MetadataValue::Float64(value) => Some(*value as f32),
+        _ => None,
+    }
+}
+
+/// A `rows x cols` matrix in ggml's layout (one row per output)
+///
+/// F32 and F16 are returned as f32; quantized types are kept as they are.
+fn load_matrix(
+    gguf: &GgufFile,
+    name: &str,
+    rows: usize,
+    cols: usize,
+) -> Result<Tensor, ModelError> {
+    let info = gguf
+        .get_tensor_info(name)
+        .ok_or_else(|| ModelError::TensorNotFound(String::from(name)))?;
+    let elements: u64 = info.dimensions.iter().product();
+    if elements != (rows * cols) as u64 {
+        return Err(ModelError::InvalidInput(format!(
+            "{} has {} elements, expected {}x{}",
+            name, elements, rows, cols
+        )));
+    }
+
+    let bytes = gguf.get_tensor(name)?;
+    let shape = alloc::vec![rows, cols];
+    match info.tensor_type {
+        GGML_TYPE_F32 | GGML_TYPE_F16 => {
+            Ok(Tensor::new_f32(to_f32(bytes, info.tensor_type), shape))
+        }
+        GGML_TYPE_Q4_K => Ok(Tensor::new_q4k(bytes.to_vec(), shape)),
+        GGML_TYPE_Q8_0 => Ok(Tensor::new_q8_0(bytes.to_vec(), shape)),
+        GGML_TYPE_Q5_K => Ok(Tensor::new_q5k(bytes.to_vec(), shape)),
+        other => Err(ModelError::InvalidInput(format!(
+            "{} has unsupported ggml type {}",
+            name, other
+        ))),
+    }
+}
+
+/// Projection from `in_dim` to `out_dim`
+fn load_projection(
+    gguf: &GgufFile,
+    name: &str,
+    in_dim: usize,
+    out_dim: usize,
+) -> Result<Tensor, ModelError> {
+    match load_matrix(gguf, name, out_dim, in_dim)? {
+        Tensor {
+            data: TensorData::F32(data),
+            ..
+        } => Ok(Tensor::new_f32(
+            transpose(&data, out_dim, in_dim),
+            alloc::vec![in_dim, out_dim],
+        )),
+        quantized => Ok(quantized),
+    }
+}
+
+/// A norm weight vector of `len` values
+fn load_vector(gguf: &GgufFile, name: &str, len: usize) -> Result<Vec<f32>, ModelError> {
+    match load_matrix(gguf, name, 1, len)?.data {
+        TensorData::F32(data) => Ok(data),
+        data => data.dequantize().ok_or_else(|| {
+            ModelError::InvalidInput(format!("{} must be F32, F16, Q8_0 or Q5_K", name))
+        }),
+    }
+}
+
+/// Merge Q, K and V into the single `(hidden, 3 * hidden)` projection
+fn combine_qkv(q: Tensor, k: Tensor, v: Tensor, hidden: usize) -> Result<Tensor, ModelError> {
+    match (q.data, k.data, v.data) {
+        (TensorData::F32(q), TensorData::F32(k), TensorData::F32(v)) => {
+            // (in, out) layout: each input row gets the three outputs side by side
+            let mut qkv = Vec::with_capacity(3 * hidden * hidden);
+            for row in 0..hidden {
+                for part in [&q, &k, &v] {
+                    qkv.extend_from_slice(&part[row * hidden..(row + 1) * hidden]);
+                }
+            }
+            Ok(Tensor::new_f32(qkv, alloc::vec![hidden, 3 * hidden]))
+        }
+        // Row-per-output layouts stack: Q rows, then K rows, then V rows
+        (TensorData::Q4K(q), TensorData::Q4K(k), TensorData::Q4K(v)) => Ok(Tensor::new_q4k(
+            [q, k, v].concat(),
+            alloc::vec![3 * hidden, hidden],
+        )),
+        (TensorData::Q8_0(q), TensorData::Q8_0(k), TensorData::Q8_0(v)) => Ok(Tensor::new_q8_0(
+            [q, k, v].concat(),
+            alloc::vec![3 * hidden, hidden],
+        )),
+        (TensorData::Q5K(q), TensorData::Q5K(k), TensorData::Q5K(v)) => Ok(Tensor::new_q5k(
+            [q, k, v].concat(),
+            alloc::vec![3 * hidden, hidden],
+        )),
+        _ => Err(ModelError::InvalidInput(String::from(
+            "attn_q, attn_k and attn_v must share a type",
+        ))),
+    }
+}
+
+/// Little-endian F32 or F16 tensor bytes as f32
+fn to_f32(bytes: &[u8], tensor_type: u32) -> Vec<f32> {
+    if tensor_type == GGML_TYPE_F16 {
+        bytes
+            .chunks_exact(2)
+            .map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]])))
+            .collect()
+    } else {
+        bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
+}
+
+fn transpose(matrix: &[f32], rows: usize, cols: usize) -> Vec<f32> {
+    let mut out = alloc::vec![0.0; rows * cols];
+    for r in 0..rows {
+        for c in 0..cols {
+            out[c * rows + r] = matrix[r * cols + c];
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transpose_and_combine_qkv() {
+        // 2x3 -> 3x2
+        assert_eq!(
+            transpose(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 2, 3),
+            [1.0, 4.0, 2.0, 5.0, 3.0, 6.0]
+        );
+
+        let part = |base: f32| {
+            Tensor::new_f32(
+                alloc::vec![base, base + 1.0, base + 2.0, base + 3.0],
+                alloc::vec![2, 2],
+            )
+        };
+        let qkv = combine_qkv(part(0.0), part(10.0), part(20.0), 2).unwrap();
+        assert_eq!(qkv.shape, [2, 6]);
+        match qkv.data {
+            TensorData::F32(data) => assert_eq!(
+                data,
+                [0.0, 1.0, 10.0, 11.0, 20.0, 21.0, 2.0, 3.0, 12.0, 13.0, 22.0, 23.0]
+            ),
+            _ => panic!("expected F32"),
+        }
+
+        let mixed = combine_qkv(
+            part(0.0),
+            Tensor::new_q8_0(alloc::vec![0; 34], alloc::vec![1, 32]),
+            part(0.0),
+            2,
+        );
+        assert!(mixed.is_err());
+    }
+}
@@ -2,20 +2,24 @@
 
 use alloc::vec::Vec;
 use alloc::string::String;
-use alloc::format;
 use crate::transformer::{Transformer, KvCache, ModelConfig, ModelWeights};
 use crate::tokenizer::Tokenizer;
-use crate::sampling::sample;
+use crate::sampling::{sample, SamplingConfig};
 use crate::ops::xorshift64;
 use crate::error::ModelError;
+use crate::gguf::GgufFile;
 
-use llm::{LlmProvider, ModelInfo, Message, Role, GenerationConfig, CompletionResult, FinishReason, LlmError};
+use core::ops::ControlFlow;
+use llm::streaming::{StopSequenceFilter, TokenSink, Utf8StreamDecoder};
+use llm::{FinishReason, Usage};
 
 /// Local LLM model for inference
 pub struct LocalModel {
     transformer: Transformer,
     tokenizer: Tokenizer,
     kv_cache: KvCache,
+    /// Token usage of the most recent generation
+    last_usage: Option<Usage>,
 }
 
 impl LocalModel {
@@ -32,33 +36,25 @@ impl LocalModel {
             transformer: Transformer::new(weights, config),
             tokenizer,
             kv_cache,
+            last_usage: None,
         }
     }
 
-    /// Format messages into a prompt string (ChatML format)
-    fn format_prompt(&self, messages: &[Message]) -> String {
-        let mut prompt = String::new();
-        
-        for msg in messages {
-            let role_str = match msg.role {
-                Role::System => "system",
-                Role::User => "user",
-                Role::Assistant => "assistant",
-            };
-            
-            prompt.push_str("<|im_start|>");
-            prompt.push_str(role_str);
-            prompt.push_str("\n");
-            prompt.push_str(&msg.content);
-            prompt.push_str("<|im_end|>\n");
-        }
-        
-        // Add the start of the assistant's response if the last message wasn't from assistant
-        if messages.last().map(|m| m.role != Role::Assistant).unwrap_or(true) {
-            prompt.push_str("<|im_start|>assistant\n");
-        }
-        
-        prompt
+    /// Load the configuration, tokenizer and weights from a GGUF file
+    pub fn load(gguf: &GgufFile) -> Result<Self, ModelError> {
+        Self::load_with_progress(gguf, |_, _| {})
+    }
+
+    /// Like [`Self::load`], calling `on_progress(tensors_loaded, total_tensors)`
+    /// as weights are converted so a caller can show how far it has got
+    pub fn load_with_progress(
+        gguf: &GgufFile,
+        on_progress: impl FnMut(usize, usize),
+    ) -> Result<Self, ModelError> {
+        let config = ModelConfig::from_gguf(gguf)?;
+        let tokenizer = Tokenizer::from_gguf(gguf)?;
+        let weights = ModelWeights::from_gguf_with_progress(gguf, &config, on_progress)?;
+        Ok(Self::new(weights, config, tokenizer))
     }
 
     /// Generate text based on a prompt
@@ -66,12 +62,10 @@ impl LocalModel {
         &mut self,
         prompt: &str,
         max_tokens: Option<usize>,
-        temperature: f32,
-        top_p: Option<f32>,
-        top_k: Option<usize>,
+        sampling: &SamplingConfig,
         stop_sequences: &[String],
         rng_seed: u64,
-        mut on_token: impl FnMut(&str),
+        mut on_token: impl FnMut(&str) -> ControlFlow<()>,
     ) -> Result<(String, FinishReason), ModelError> {
         // 1. Tokenize prompt
         let tokens = self.tokenizer.encode(prompt);
@@ -81,6 +75,7 @@ impl LocalModel {
 
         // 2. Reset KV cache for new generation
         self.kv_cache.reset();
+        self.last_usage = None;
 
         // 3. Prefill phase
         // Process all prompt tokens except the last one to fill KV cache
@@ -98,14 +93,21 @@ impl LocalModel {
         let max_gen = max_tokens.unwrap_or(self.transformer.config().max_seq_len - tokens.len());
         let mut current_seed = rng_seed;
         let mut finish_reason = FinishReason::Length;
+        // Holds back text that may be the start of a stop sequence, so a
+        // matched sequence is never emitted
+        let mut stop_filter = StopSequenceFilter::new(stop_sequences);
+        // Holds back bytes of a character split across tokens
+        let mut decoder = Utf8StreamDecoder::new();
+        let mut token_bytes = Vec::new();
+        let mut matched_stop = false;
+        let mut sink = TokenSink::new(&mut on_token);
 
         for _ in 0..max_gen {
             // Sample next token
             let next_token = sample(
                 &mut last_logits,
-                temperature,
-                top_p,
-                top_k,
+                sampling,
+                &generated_tokens,
                 current_seed,
             );
             
@@ -118,27 +120,21 @@ impl LocalModel {
                 break;
             }
 
-            // Decode and stream the token
-            let token_str = self.tokenizer.decode(&[next_token]);
-            
-            // Check for stop sequences
-            let mut found_stop = false;
-            for stop in stop_sequences {
-                if token_str.contains(stop) || (generated_text.clone() + &token_str).contains(stop) {
-                    found_stop = true;
-                    break;
-                }
-            }
-            
-            if found_stop {
+            // Decode and stream the token, stopping at a stop sequence
+            token_bytes.clear();
+            self.tokenizer.push_token_bytes(next_token, &mut token_bytes);
+            let mut token_str = String::new();
+            decoder.push(&token_bytes, |text| token_str.push_str(text));
+            generated_tokens.push(next_token);
+            if stop_filter.push(&token_str, &mut generated_text, |text| sink.emit(text)) {
+                matched_stop = true;
                 finish_reason = FinishReason::Stop;
                 break;
             }
-
-            on_token(&token_str);
-            
-            generated_text.push_str(&token_str);
-            generated_tokens.push(next_token);
+            if sink.is_cancelled() {
+                finish_reason = FinishReason::Cancelled;
+                break;
+            }
 
             // Check if we've reached the max sequence length
             if self.kv_cache.current_pos() >= self.transformer.config().max_seq_len {
@@ -150,59 +146,122 @@ impl LocalModel {
             last_logits = self.transformer.forward(&[next_token], &mut self.kv_cache)?;
         }
 
+        if !sink.is_cancelled() {
+            let mut rest = String::new();
+            decoder.finish(|text| rest.push_str(text));
+            if !rest.is_empty() && !matched_stop {
+                stop_filter.push(&rest, &mut generated_text, |text| sink.emit(text));
+            }
+            stop_filter.finish(&mut generated_text, |text| sink.emit(text));
+        }
+        self.last_usage = Some(Usage::new(tokens.len(), generated_tokens.len()));
+
         Ok((generated_text, finish_reason))
     }
-}
 
-impl LlmProvider for LocalModel {
-    fn name(&self) -> &str {
-        "Local (SmolLM)"
+    /// Model configuration (context length, dimensions)
+    pub fn config(&self) -> &ModelConfig {
+        self.transformer.config()
     }
 
-    fn models(&self) -> &[ModelInfo] {
-        // Return empty slice as String static initialization is not possible
-        &[]
+    /// Token usage of the most recent `generate` call
+    pub fn last_usage(&self) -> Option<Usage> {
+        self.last_usage
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::Tensor;
+    use crate::tokenizer::SpecialTokens;
+    use crate::transformer::{EmbeddingWeights, OutputWeights};
+    use crate::RopeScaling;
+    use alloc::collections::BTreeMap;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    /// Model without layers that always follows "a" with "b", "b" with "c",
+    /// "c" with "d" and "d" with "a"
+    fn cycling_model() -> LocalModel {
+        let vocab_size = 4;
+        let mut vocab = BTreeMap::new();
+        for (id, token) in ["a", "b", "c", "d"].iter().enumerate() {
+            vocab.insert(token.to_string(), id as u32);
+        }
+        let special_tokens = SpecialTokens {
+            bos_token: None,
+            eos_token: None,
+            pad_token: None,
+            unk_token: None,
+        };
+        let tokenizer = Tokenizer::new(vocab, Vec::new(), special_tokens);
 
-    fn default_model(&self) -> &str {
-        "smollm-360m"
+        // One-hot embeddings, so the output matrix maps token to next token
+        let mut embedding = vec![0.0; vocab_size * vocab_size];
+        let mut output = vec![0.0; vocab_size * vocab_size];
+        for token in 0..vocab_size {
+            embedding[token * vocab_size + token] = 1.0;
+            output[token * vocab_size + (token + 1) % vocab_size] = 10.0;
+        }
+        let weights = ModelWeights {
+            embedding: EmbeddingWeights {
+                weight: Tensor::new_f32(embedding, vec![vocab_size, vocab_size]),
+            },
+            layers: Vec::new(),
+            output: OutputWeights {
+                weight: Tensor::new_f32(output, vec![vocab_size, vocab_size]),
+            },
+        };
+        let config = ModelConfig {
+            vocab_size,
+            hidden_size: vocab_size,
+            num_layers: 0,
+            num_heads: 1,
+            head_dim: vocab_size,
+            intermediate_size: vocab_size,
+            max_seq_len: 64,
+            rope_freq_base: 10000.0,
+            rope_scaling: RopeScaling::None,
+            norm_eps: 1e-6,
+        };
+        LocalModel::new(weights, config, tokenizer)
     }
 
-    fn complete(
-        &mut self,
-        messages: &[Message],
-        _model: &str,
-        config: &GenerationConfig,
-        on_token: impl FnMut(&str),
-    ) -> Result<CompletionResult, LlmError> {
-        let prompt = self.format_prompt(messages);
-        
-        // Use a fixed seed for reproducibility or a pseudo-random one if we had a clock
-        let seed = 42; 
-
-        match self.generate(
-            &prompt,
-            config.max_tokens,
-            config.temperature,
-            config.top_p,
-            config.top_k,
-            &config.stop_sequences,
-            seed,
-            on_token,
-        ) {
-            Ok((text, finish_reason)) => {
-                Ok(CompletionResult {
-                    text,
-                    tokens_used: Some(self.kv_cache.current_pos()),
-                    finish_reason,
-                })
-            }
-            Err(e) => Err(LlmError::Other(format!("Inference error: {:?}", e))),
+    fn greedy() -> SamplingConfig {
+        SamplingConfig {
+            temperature: 1.0,
+            top_k: Some(1),
+            repetition_penalty: 1.0,
+            ..SamplingConfig::default()
         }
     }
 
-    fn validate_api_key(&self) -> Result<(), LlmError> {
-        // Local model doesn't need an API key
-        Ok(())
+    #[test]
+    fn test_generate_runs_to_max_tokens() {
+        let mut model = cycling_model();
+        let (text, finish) = model
+            .generate("a", Some(6), &greedy(), &[], 1, |_| ControlFlow::Continue(()))
+            .unwrap();
+        assert_eq!(text, "bcdabc");
+        assert_eq!(finish, FinishReason::Length);
+    }
+
+    #[test]
+    fn test_generate_halts_at_stop_sequence() {
+        let mut model = cycling_model();
+        let mut streamed = String::new();
+        let (text, finish) = model
+            .generate("a", Some(20), &greedy(), &["da".to_string()], 1, |token| {
+                streamed.push_str(token);
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+
+        // "bc" is generated, then "d" is held back until "a" completes the stop
+        assert_eq!(text, "bc");
+        assert_eq!(streamed, "bc");
+        assert_eq!(finish, FinishReason::Stop);
+        assert_eq!(model.last_usage(), Some(Usage::new(1, 4)));
     }
 }
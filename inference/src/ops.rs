@@ -1,5 +1,7 @@
+use alloc::vec;
 use alloc::vec::Vec;
 use micromath::F32Ext;
+use crate::simd;
 use crate::tensor::{BlockQ4K, QK_K};
 
 /// SiLU (Sigmoid Linear Unit) activation function: x * sigmoid(x)
@@ -64,14 +66,41 @@ pub fn layer_norm(out: &mut [f32], x: &[f32], weight: &[f32], bias: &[f32], eps:
     }
 }
 
+/// Context-extension scaling applied to RoPE positions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RopeScaling {
+    /// Positions are used as-is
+    None,
+    /// Positions are divided by `factor` (position interpolation)
+    Linear { factor: f32 },
+    /// The frequency base is raised so low frequencies stretch by about
+    /// `factor` while high frequencies barely change
+    NtkAware { factor: f32 },
+}
+
+impl RopeScaling {
+    /// Effective `(position, frequency base)` for a rotation
+    pub fn apply(self, pos: usize, freq_base: f32, head_dim: usize) -> (f32, f32) {
+        match self {
+            RopeScaling::Linear { factor } if factor > 0.0 => (pos as f32 / factor, freq_base),
+            RopeScaling::NtkAware { factor } if factor > 0.0 && head_dim > 2 => {
+                let exponent = head_dim as f32 / (head_dim - 2) as f32;
+                (pos as f32, freq_base * factor.powf(exponent))
+            }
+            _ => (pos as f32, freq_base),
+        }
+    }
+}
+
 /// RoPE (Rotary Positional Embedding)
-pub fn rope(x: &mut [f32], pos: usize, head_dim: usize, freq_base: f32) {
+pub fn rope(x: &mut [f32], pos: usize, head_dim: usize, freq_base: f32, scaling: RopeScaling) {
+    let (pos, freq_base) = scaling.apply(pos, freq_base, head_dim);
     let n_heads = x.len() / head_dim;
     for h in 0..n_heads {
         let head_x = &mut x[h * head_dim..(h + 1) * head_dim];
         for i in 0..head_dim / 2 {
             let freq = 1.0 / freq_base.powf((2 * i) as f32 / head_dim as f32);
-            let val = pos as f32 * freq;
+            let val = pos * freq;
             let f_cos = val.cos();
             let f_sin = val.sin();
 
@@ -97,15 +126,15 @@ pub fn mul(a: &[f32], b: &[f32]) -> Vec<f32> {
 /// Matrix multiplication (F32)
 /// out = A * B
 /// A: (m, k), B: (k, n), out: (m, n)
+///
+/// Each output row accumulates rows of B scaled by A's entries, so the
+/// inner loop runs over contiguous memory in the SIMD kernel.
 pub fn matmul_f32(a: &[f32], b: &[f32], m: usize, n: usize, k: usize) -> Vec<f32> {
-    let mut out = Vec::with_capacity(m * n);
+    let mut out = vec![0.0; m * n];
     for i in 0..m {
-        for j in 0..n {
-            let mut sum = 0.0;
-            for l in 0..k {
-                sum += a[i * k + l] * b[l * n + j];
-            }
-            out.push(sum);
+        let out_row = &mut out[i * n..(i + 1) * n];
+        for l in 0..k {
+            simd::axpy_f32(out_row, a[i * k + l], &b[l * n..(l + 1) * n]);
         }
     }
     out
@@ -265,6 +294,57 @@ mod tests {
         assert_eq!(out[1], 11.0);
     }
 
+    #[test]
+    fn test_matmul_f32_matches_scalar() {
+        // Odd n exercises the SIMD tail
+        let (m, n, k) = (3, 19, 5);
+        let a: Vec<f32> = (0..m * k).map(|i| (i as f32 * 0.31) % 1.5 - 0.7).collect();
+        let b: Vec<f32> = (0..k * n).map(|i| (i as f32 * 0.17) % 2.0 - 1.0).collect();
+
+        let out = matmul_f32(&a, &b, m, n, k);
+        for i in 0..m {
+            for j in 0..n {
+                let expected: f32 = (0..k).map(|l| a[i * k + l] * b[l * n + j]).sum();
+                assert!((out[i * n + j] - expected).abs() < 1e-5);
+            }
+        }
+    }
+
+    fn rotated(pos: usize, freq_base: f32, scaling: RopeScaling) -> Vec<f32> {
+        let mut x: Vec<f32> = (0..16).map(|i| 1.0 + i as f32 * 0.1).collect();
+        rope(&mut x, pos, 8, freq_base, scaling);
+        x
+    }
+
+    #[test]
+    fn test_rope_linear_scaling() {
+        let scaled = rotated(4000, 10000.0, RopeScaling::Linear { factor: 4.0 });
+        assert_ne!(scaled, rotated(4000, 10000.0, RopeScaling::None));
+        // Position 4000 at 4x behaves like position 1000 unscaled
+        assert_eq!(scaled, rotated(1000, 10000.0, RopeScaling::None));
+        assert_eq!(
+            rotated(4000, 10000.0, RopeScaling::Linear { factor: 1.0 }),
+            rotated(4000, 10000.0, RopeScaling::None)
+        );
+    }
+
+    #[test]
+    fn test_rope_ntk_scaling() {
+        let scaled = rotated(4000, 10000.0, RopeScaling::NtkAware { factor: 4.0 });
+        let unscaled = rotated(4000, 10000.0, RopeScaling::None);
+        assert_ne!(scaled, unscaled);
+
+        // The highest frequency (pair 0 of each head) is unaffected
+        for head in 0..2 {
+            assert_eq!(scaled[head * 8], unscaled[head * 8]);
+            assert_eq!(scaled[head * 8 + 4], unscaled[head * 8 + 4]);
+        }
+
+        // Equivalent to raising the base by factor^(d / (d - 2))
+        let base = 10000.0 * 4.0f32.powf(8.0 / 6.0);
+        assert_eq!(scaled, rotated(4000, base, RopeScaling::None));
+    }
+
     #[test]
     fn test_add_mul() {
         let a = [1.0, 2.0];
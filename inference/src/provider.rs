@@ -0,0 +1,179 @@
+//! `LlmProvider` implementation backed by a local model.
+//!
+//! Wraps a [`LocalModel`] so the kernel can chat fully offline through the
+//! same interface as the cloud providers. The model sits behind a shared
+//! lock, so cloned providers reuse the loaded weights instead of copying them.
+
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+use spin::Mutex;
+
+use crate::gguf::{GgufFile, MetadataValue};
+use crate::model::LocalModel;
+use crate::ops::xorshift64;
+use crate::sampling::SamplingConfig;
+
+use llm::providers::ensure_text_only;
+use llm::{CompletionResult, GenerationConfig, LlmError, LlmProvider, Message, ModelInfo, Role};
+
+/// Provider name reported by `LocalProvider::name`
+pub const LOCAL_PROVIDER_NAME: &str = "local";
+
+/// Seed used for the first generation; later calls advance it
+const INITIAL_SEED: u64 = 0x5DEE_CE66_D1CE_4E5B;
+
+/// Offline provider that runs a [`LocalModel`]
+#[derive(Clone)]
+pub struct LocalProvider {
+    model: Arc<Mutex<LocalModel>>,
+    models: Vec<ModelInfo>,
+    seed: u64,
+}
+
+impl LocalProvider {
+    /// Create a provider for `model`, listed under `model_name`
+    pub fn new(model: LocalModel, model_name: String) -> Self {
+        let info = ModelInfo::new(
+            model_name.clone(),
+            model_name,
+            model.config().max_seq_len,
+            true,
+        );
+        Self {
+            model: Arc::new(Mutex::new(model)),
+            models: vec![info],
+            seed: INITIAL_SEED,
+        }
+    }
+
+    /// Create a provider named after the GGUF file's `general.name`
+    pub fn from_gguf(model: LocalModel, gguf: &GgufFile) -> Self {
+        Self::new(model, model_name(gguf.get_metadata("general.name")))
+    }
+}
+
+/// Model name from GGUF metadata, or a generic name if it is missing
+fn model_name(metadata: Option<&MetadataValue>) -> String {
+    match metadata {
+        Some(MetadataValue::String(name)) if !name.trim().is_empty() => name.trim().to_string(),
+        _ => String::from("local-model"),
+    }
+}
+
+/// Format messages into a prompt string (ChatML format)
+///
+/// The prompt ends with an open assistant turn unless the last message is
+/// already from the assistant.
+pub fn apply_chat_template(messages: &[Message]) -> String {
+    let mut prompt = String::new();
+
+    for msg in messages {
+        let role_str = match msg.role {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        };
+
+        prompt.push_str("<|im_start|>");
+        prompt.push_str(role_str);
+        prompt.push('\n');
+        prompt.push_str(&msg.content.text());
+        prompt.push_str("<|im_end|>\n");
+    }
+
+    if messages.last().map(|m| m.role != Role::Assistant).unwrap_or(true) {
+        prompt.push_str("<|im_start|>assistant\n");
+    }
+
+    prompt
+}
+
+impl LlmProvider for LocalProvider {
+    fn name(&self) -> &str {
+        LOCAL_PROVIDER_NAME
+    }
+
+    fn models(&self) -> &[ModelInfo] {
+        &self.models
+    }
+
+    fn default_model(&self) -> &str {
+        &self.models[0].id
+    }
+
+    fn complete(
+        &mut self,
+        messages: &[Message],
+        _model: &str,
+        config: &GenerationConfig,
+        on_token: &mut dyn FnMut(&str) -> ControlFlow<()>,
+    ) -> Result<CompletionResult, LlmError> {
+        ensure_text_only(messages, LOCAL_PROVIDER_NAME)?;
+        let prompt = apply_chat_template(messages);
+        let sampling = SamplingConfig {
+            temperature: config.temperature,
+            top_p: config.top_p,
+            top_k: config.top_k,
+            ..SamplingConfig::default()
+        };
+
+        // Vary the seed between calls so regenerating gives a new answer
+        let seed = self.seed;
+        self.seed = xorshift64(self.seed);
+
+        let mut model = self.model.lock();
+        let (text, finish_reason) = model
+            .generate(
+                &prompt,
+                config.max_tokens,
+                &sampling,
+                &config.stop_sequences,
+                seed,
+                |token| on_token(token),
+            )
+            .map_err(|e| LlmError::Other(alloc::format!("Inference error: {:?}", e)))?;
+
+        let usage = model.last_usage();
+        Ok(CompletionResult {
+            text,
+            tokens_used: usage.map(|u| u.total_tokens),
+            usage,
+            finish_reason,
+        })
+    }
+
+    fn validate_api_key(&self) -> Result<(), LlmError> {
+        // Local models don't need an API key
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chat_template_opens_assistant_turn() {
+        let prompt = apply_chat_template(&[
+            Message::new(Role::System, String::from("Be brief.")),
+            Message::new(Role::User, String::from("Hi")),
+        ]);
+        assert_eq!(
+            prompt,
+            "<|im_start|>system\nBe brief.<|im_end|>\n\
+             <|im_start|>user\nHi<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+    }
+
+    #[test]
+    fn test_model_name_from_metadata() {
+        let name = MetadataValue::String(String::from(" SmolLM 360M "));
+        assert_eq!(model_name(Some(&name)), "SmolLM 360M");
+        assert_eq!(model_name(Some(&MetadataValue::UInt32(1))), "local-model");
+        assert_eq!(model_name(None), "local-model");
+    }
+}
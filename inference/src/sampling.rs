@@ -3,14 +3,88 @@
 use alloc::vec::Vec;
 use crate::ops::{softmax, xorshift64};
 
+/// Sampling parameters for local generation
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplingConfig {
+    pub temperature: f32,
+    pub top_p: Option<f32>,
+    pub top_k: Option<usize>,
+    /// Penalty applied to recently generated tokens (1.0 disables it)
+    pub repetition_penalty: f32,
+    /// How many of the most recent tokens the penalty looks at
+    pub repeat_last_n: usize,
+}
+
+impl SamplingConfig {
+    /// Create a config with the given temperature and default penalties
+    pub fn new(temperature: f32) -> Self {
+        Self {
+            temperature,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            temperature: 0.7,
+            top_p: None,
+            top_k: None,
+            repetition_penalty: 1.1,
+            repeat_last_n: 64,
+        }
+    }
+}
+
+/// Penalize tokens that appear in the last `repeat_last_n` entries of `history`
+///
+/// Positive logits are divided by `penalty` and negative logits multiplied by it,
+/// so a penalty above 1.0 always makes a repeated token less likely.
+pub fn apply_repetition_penalty(
+    logits: &mut [f32],
+    history: &[u32],
+    penalty: f32,
+    repeat_last_n: usize,
+) {
+    if penalty == 1.0 || penalty <= 0.0 || repeat_last_n == 0 {
+        return;
+    }
+
+    let start = history.len().saturating_sub(repeat_last_n);
+    // Each token is penalized once, however often it repeats
+    let mut recent: Vec<u32> = history[start..].to_vec();
+    recent.sort_unstable();
+    recent.dedup();
+
+    for &token in &recent {
+        let Some(logit) = logits.get_mut(token as usize) else {
+            continue;
+        };
+        if *logit > 0.0 {
+            *logit /= penalty;
+        } else {
+            *logit *= penalty;
+        }
+    }
+}
+
 /// Sample a token from logits using various techniques
+///
+/// `history` is the running list of generated tokens, used for the repetition penalty.
 pub fn sample(
     logits: &mut [f32],
-    temperature: f32,
-    top_p: Option<f32>,
-    top_k: Option<usize>,
+    config: &SamplingConfig,
+    history: &[u32],
     rng_seed: u64,
 ) -> u32 {
+    let temperature = config.temperature;
+    let top_p = config.top_p;
+    let top_k = config.top_k;
+
+    // 0. Penalize recently generated tokens
+    apply_repetition_penalty(logits, history, config.repetition_penalty, config.repeat_last_n);
+
     // 1. Apply temperature
     if temperature > 0.0 && temperature != 1.0 {
         for val in logits.iter_mut() {
@@ -96,3 +170,40 @@ pub fn sample(
     // Fallback to the most likely token
     logits.iter().enumerate().max_by(|a, b| a.1.partial_cmp(b.1).unwrap()).unwrap().0 as u32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repetition_penalty_lowers_recent_token() {
+        let mut logits = [2.0, 2.0, 0.5];
+        apply_repetition_penalty(&mut logits, &[0], 1.5, 64);
+        softmax(&mut logits);
+        assert!(logits[0] < logits[1]);
+    }
+
+    #[test]
+    fn test_repetition_penalty_negative_logit() {
+        let mut logits = [-1.0, -1.0];
+        apply_repetition_penalty(&mut logits, &[0], 2.0, 64);
+        assert_eq!(logits[0], -2.0);
+        assert_eq!(logits[1], -1.0);
+    }
+
+    #[test]
+    fn test_repetition_penalty_window() {
+        let mut logits = [1.0, 1.0];
+        // Token 0 is outside the last two tokens, so only token 1 is penalized
+        apply_repetition_penalty(&mut logits, &[0, 1, 1], 2.0, 2);
+        assert_eq!(logits[0], 1.0);
+        assert_eq!(logits[1], 0.5);
+    }
+
+    #[test]
+    fn test_repetition_penalty_ignores_ids_outside_vocab() {
+        let mut logits = [1.0, 1.0];
+        apply_repetition_penalty(&mut logits, &[7, 1, u32::MAX], 2.0, 64);
+        assert_eq!(logits, [1.0, 0.5]);
+    }
+}
@@ -2,34 +2,47 @@
 use core::arch::aarch64::*;
 
 #[cfg(target_arch = "aarch64")]
-pub unsafe fn dot_product_f32_neon(a: &[f32], b: &[f32]) -> f32 {
-    let n = a.len();
-    let mut sumv = vdupq_n_f32(0.0);
+pub fn dot_product_f32_neon(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len().min(b.len());
     let mut i = 0;
 
-    while i + 4 <= n {
-        let va = vld1q_f32(a.as_ptr().add(i));
-        let vb = vld1q_f32(b.as_ptr().add(i));
-        sumv = vfmaq_f32(sumv, va, vb);
-        i += 4;
-    }
-
-    let mut final_sum = vaddvq_f32(sumv);
+    // SAFETY: NEON is mandatory on aarch64 and loads stay below n
+    unsafe {
+        let mut sumv = vdupq_n_f32(0.0);
+        while i + 4 <= n {
+            let va = vld1q_f32(a.as_ptr().add(i));
+            let vb = vld1q_f32(b.as_ptr().add(i));
+            sumv = vfmaq_f32(sumv, va, vb);
+            i += 4;
+        }
 
-    while i < n {
-        final_sum += a[i] * b[i];
-        i += 1;
+        let mut final_sum = vaddvq_f32(sumv);
+        while i < n {
+            final_sum += a[i] * b[i];
+            i += 1;
+        }
+        final_sum
     }
-
-    final_sum
 }
 
 #[cfg(target_arch = "aarch64")]
-pub fn matmul_f32_optimized(out: &mut [f32], a: &[f32], b: &[f32], m: usize, k: usize) {
-    for i in 0..m {
-        let row = &a[i * k..(i + 1) * k];
-        unsafe {
-            out[i] = dot_product_f32_neon(row, b);
+pub fn axpy_f32_neon(out: &mut [f32], alpha: f32, x: &[f32]) {
+    let n = out.len().min(x.len());
+    let mut i = 0;
+
+    // SAFETY: as above
+    unsafe {
+        let va = vdupq_n_f32(alpha);
+        while i + 4 <= n {
+            let vo = vld1q_f32(out.as_ptr().add(i));
+            let vx = vld1q_f32(x.as_ptr().add(i));
+            vst1q_f32(out.as_mut_ptr().add(i), vfmaq_f32(vo, va, vx));
+            i += 4;
         }
     }
+
+    while i < n {
+        out[i] += alpha * x[i];
+        i += 1;
+    }
 }
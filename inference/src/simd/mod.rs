@@ -1,8 +1,161 @@
+//! SIMD kernels for the hot loops in `ops`
+//!
+//! The instruction set is picked once (CPUID on x86_64) and cached, so the
+//! kernels only pay for an atomic load per call. Everything falls back to
+//! the scalar code in [`generic`].
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
 #[cfg(target_arch = "x86_64")]
 pub mod x86_64;
 
 #[cfg(target_arch = "aarch64")]
 pub mod aarch64;
 
-/// Placeholder for generic SIMD or fallback if needed
+/// Scalar fallbacks
 pub mod generic;
+
+/// Instruction set used by the kernels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SimdLevel {
+    Scalar = 1,
+    /// 4 lanes (SSE2, always present on x86_64)
+    Sse = 2,
+    /// 8 lanes with fused multiply-add
+    Avx2 = 3,
+    /// 4 lanes (NEON, always present on aarch64)
+    Neon = 4,
+}
+
+impl SimdLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            SimdLevel::Scalar => "scalar",
+            SimdLevel::Sse => "SSE",
+            SimdLevel::Avx2 => "AVX2+FMA",
+            SimdLevel::Neon => "NEON",
+        }
+    }
+}
+
+/// Not detected yet
+const UNDETECTED: u8 = 0;
+
+static LEVEL: AtomicU8 = AtomicU8::new(UNDETECTED);
+
+/// Detect the best supported instruction set and cache it
+///
+/// Called before the model is used; [`level`] detects lazily if it wasn't.
+pub fn init() -> SimdLevel {
+    let level = detect();
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    level
+}
+
+/// The instruction set the kernels dispatch to
+pub fn level() -> SimdLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        2 => SimdLevel::Sse,
+        3 => SimdLevel::Avx2,
+        4 => SimdLevel::Neon,
+        1 => SimdLevel::Scalar,
+        _ => init(),
+    }
+}
+
+fn detect() -> SimdLevel {
+    #[cfg(target_arch = "x86_64")]
+    {
+        x86_64::detect()
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        SimdLevel::Neon
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        SimdLevel::Scalar
+    }
+}
+
+/// Dot product of `a` and `b` (equal lengths)
+pub fn dot_product_f32(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    match level() {
+        // SAFETY: AVX2 and FMA were detected
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => unsafe { x86_64::dot_product_f32_avx2(a, b) },
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Sse => x86_64::dot_product_f32_sse(a, b),
+        #[cfg(target_arch = "aarch64")]
+        SimdLevel::Neon => aarch64::dot_product_f32_neon(a, b),
+        _ => generic::dot_product_f32(a, b),
+    }
+}
+
+/// `out += alpha * x` (equal lengths)
+pub fn axpy_f32(out: &mut [f32], alpha: f32, x: &[f32]) {
+    debug_assert_eq!(out.len(), x.len());
+    match level() {
+        // SAFETY: AVX2 and FMA were detected
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 => unsafe { x86_64::axpy_f32_avx2(out, alpha, x) },
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Sse => x86_64::axpy_f32_sse(out, alpha, x),
+        #[cfg(target_arch = "aarch64")]
+        SimdLevel::Neon => aarch64::axpy_f32_neon(out, alpha, x),
+        _ => generic::axpy_f32(out, alpha, x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn sample(len: usize, seed: f32) -> Vec<f32> {
+        (0..len)
+            .map(|i| ((i as f32 * 0.37 + seed) % 2.0) - 1.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_kernels_match_scalar() {
+        // Lengths cover empty, tail-only and full-lane-plus-tail cases
+        for len in [0, 3, 8, 13, 67] {
+            let a = sample(len, 0.1);
+            let b = sample(len, 0.7);
+            let expected = generic::dot_product_f32(&a, &b);
+            assert!((dot_product_f32(&a, &b) - expected).abs() < 1e-4);
+
+            let mut out = sample(len, 0.3);
+            let mut expected = out.clone();
+            axpy_f32(&mut out, 0.5, &a);
+            generic::axpy_f32(&mut expected, 0.5, &a);
+            for (x, y) in out.iter().zip(&expected) {
+                assert!((x - y).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_selects_best_available_level() {
+        let selected = init();
+        assert_eq!(level(), selected);
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            let avx2 =
+                std::is_x86_feature_detected!("avx2") && std::is_x86_feature_detected!("fma");
+            let expected = if avx2 {
+                SimdLevel::Avx2
+            } else {
+                SimdLevel::Sse
+            };
+            assert_eq!(selected, expected);
+        }
+    }
+}
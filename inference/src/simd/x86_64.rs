@@ -1,10 +1,44 @@
 #[cfg(target_arch = "x86_64")]
 use core::arch::x86_64::*;
 
+use super::SimdLevel;
+
+/// Pick AVX2+FMA if the CPU has it and the OS saves YMM state, else SSE
+#[cfg(target_arch = "x86_64")]
+pub fn detect() -> SimdLevel {
+    let leaf1 = __cpuid(1);
+    let fma = leaf1.ecx & (1 << 12) != 0;
+    let osxsave = leaf1.ecx & (1 << 27) != 0;
+    let avx = leaf1.ecx & (1 << 28) != 0;
+    if !(fma && osxsave && avx) {
+        return SimdLevel::Sse;
+    }
+
+    // XCR0 must enable both SSE (bit 1) and AVX (bit 2) state
+    let xcr0: u32;
+    // SAFETY: XGETBV is available because OSXSAVE is set
+    unsafe {
+        core::arch::asm!("xgetbv", in("ecx") 0, out("eax") xcr0, out("edx") _, options(nomem, nostack));
+    }
+    if xcr0 & 0b110 != 0b110 {
+        return SimdLevel::Sse;
+    }
+
+    // Leaf 7 exists on every CPU with AVX
+    let leaf7 = __cpuid_count(7, 0);
+    if leaf7.ebx & (1 << 5) != 0 {
+        SimdLevel::Avx2
+    } else {
+        SimdLevel::Sse
+    }
+}
+
+/// # Safety
+/// The CPU must support AVX2 and FMA.
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2,fma")]
 pub unsafe fn dot_product_f32_avx2(a: &[f32], b: &[f32]) -> f32 {
-    let n = a.len();
+    let n = a.len().min(b.len());
     let mut sum = _mm256_setzero_ps();
     let mut i = 0;
 
@@ -30,24 +64,24 @@ pub unsafe fn dot_product_f32_avx2(a: &[f32], b: &[f32]) -> f32 {
 }
 
 #[cfg(target_arch = "x86_64")]
-#[target_feature(enable = "sse4.2")]
-pub unsafe fn dot_product_f32_sse42(a: &[f32], b: &[f32]) -> f32 {
-    let n = a.len();
-    let mut sum = _mm_setzero_ps();
+pub fn dot_product_f32_sse(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len().min(b.len());
     let mut i = 0;
 
-    while i + 4 <= n {
-        let va = _mm_loadu_ps(a.as_ptr().add(i));
-        let vb = _mm_loadu_ps(b.as_ptr().add(i));
-        let prod = _mm_mul_ps(va, vb);
-        sum = _mm_add_ps(sum, prod);
-        i += 4;
-    }
-
-    let mut res = [0.0f32; 4];
-    _mm_storeu_ps(res.as_mut_ptr(), sum);
+    // SAFETY: SSE is part of the x86_64 baseline and loads stay below n
+    let mut final_sum = unsafe {
+        let mut sum = _mm_setzero_ps();
+        while i + 4 <= n {
+            let va = _mm_loadu_ps(a.as_ptr().add(i));
+            let vb = _mm_loadu_ps(b.as_ptr().add(i));
+            sum = _mm_add_ps(sum, _mm_mul_ps(va, vb));
+            i += 4;
+        }
 
-    let mut final_sum = res.iter().sum::<f32>();
+        let mut res = [0.0f32; 4];
+        _mm_storeu_ps(res.as_mut_ptr(), sum);
+        res.iter().sum::<f32>()
+    };
 
     while i < n {
         final_sum += a[i] * b[i];
@@ -57,19 +91,46 @@ pub unsafe fn dot_product_f32_sse42(a: &[f32], b: &[f32]) -> f32 {
     final_sum
 }
 
+/// # Safety
+/// The CPU must support AVX2 and FMA.
 #[cfg(target_arch = "x86_64")]
-pub fn matmul_f32_optimized(out: &mut [f32], a: &[f32], b: &[f32], m: usize, k: usize) {
-    // Check for AVX2 support at runtime or compile time
-    // For now, let's assume we can use AVX2 if the target supports it,
-    // or just provide it as an option.
-
-    for i in 0..m {
-        let row = &a[i * k..(i + 1) * k];
-        // In a real implementation, we'd use cpuid to dispatch.
-        // For this task, I'll just use the scalar one if not sure,
-        // but here's how the call would look:
-        unsafe {
-            out[i] = dot_product_f32_avx2(row, b);
+#[target_feature(enable = "avx2,fma")]
+pub unsafe fn axpy_f32_avx2(out: &mut [f32], alpha: f32, x: &[f32]) {
+    let n = out.len().min(x.len());
+    let va = _mm256_set1_ps(alpha);
+    let mut i = 0;
+
+    while i + 8 <= n {
+        let vo = _mm256_loadu_ps(out.as_ptr().add(i));
+        let vx = _mm256_loadu_ps(x.as_ptr().add(i));
+        _mm256_storeu_ps(out.as_mut_ptr().add(i), _mm256_fmadd_ps(va, vx, vo));
+        i += 8;
+    }
+
+    while i < n {
+        out[i] += alpha * x[i];
+        i += 1;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn axpy_f32_sse(out: &mut [f32], alpha: f32, x: &[f32]) {
+    let n = out.len().min(x.len());
+    let mut i = 0;
+
+    // SAFETY: SSE is part of the x86_64 baseline and accesses stay below n
+    unsafe {
+        let va = _mm_set1_ps(alpha);
+        while i + 4 <= n {
+            let vo = _mm_loadu_ps(out.as_ptr().add(i));
+            let vx = _mm_loadu_ps(x.as_ptr().add(i));
+            _mm_storeu_ps(out.as_mut_ptr().add(i), _mm_add_ps(vo, _mm_mul_ps(va, vx)));
+            i += 4;
         }
     }
+
+    while i < n {
+        out[i] += alpha * x[i];
+        i += 1;
+    }
 }
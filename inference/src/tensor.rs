@@ -1,9 +1,45 @@
 use alloc::vec::Vec;
 
+/// Expands packed blocks of one quantized format to f32
+type DequantizeFn = fn(&[u8]) -> Vec<f32>;
+
 #[derive(Debug, Clone)]
 pub enum TensorData {
     F32(Vec<f32>),
     Q4K(Vec<u8>), // Q4_K_M / block_q4_K
+    Q8_0(Vec<u8>), // block_q8_0
+    Q5K(Vec<u8>), // block_q5_K
+}
+
+impl TensorData {
+    /// The values of a Q8_0 or Q5_K tensor as f32
+    ///
+    /// Those formats are dequantized before use; F32 and Q4_K tensors have
+    /// their own matmul paths and return `None`.
+    pub fn dequantize(&self) -> Option<Vec<f32>> {
+        match self {
+            TensorData::Q8_0(data) => Some(dequantize_q8_0(data)),
+            TensorData::Q5K(data) => Some(dequantize_q5k(data)),
+            TensorData::F32(_) | TensorData::Q4K(_) => None,
+        }
+    }
+
+    /// Row `row` of a Q8_0 or Q5_K matrix with `row_len` columns, as f32
+    ///
+    /// `row_len` must be a multiple of the format's block size.
+    pub fn dequantize_row(&self, row: usize, row_len: usize) -> Option<Vec<f32>> {
+        let (data, block_elems, block_bytes, dequantize): (_, _, _, DequantizeFn) = match self {
+            TensorData::Q8_0(data) => (data, QK8_0, BLOCK_Q8_0_SIZE, dequantize_q8_0),
+            TensorData::Q5K(data) => (data, QK_K, BLOCK_Q5K_SIZE, dequantize_q5k),
+            TensorData::F32(_) | TensorData::Q4K(_) => return None,
+        };
+        if !row_len.is_multiple_of(block_elems) {
+            return None;
+        }
+        let row_bytes = row_len / block_elems * block_bytes;
+        data.get(row * row_bytes..(row + 1) * row_bytes)
+            .map(dequantize)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -27,6 +63,20 @@ impl Tensor {
         }
     }
 
+    pub fn new_q8_0(data: Vec<u8>, shape: Vec<usize>) -> Self {
+        Self {
+            data: TensorData::Q8_0(data),
+            shape,
+        }
+    }
+
+    pub fn new_q5k(data: Vec<u8>, shape: Vec<usize>) -> Self {
+        Self {
+            data: TensorData::Q5K(data),
+            shape,
+        }
+    }
+
     pub fn elements(&self) -> usize {
         self.shape.iter().product()
     }
@@ -43,3 +93,167 @@ pub struct BlockQ4K {
     pub scales: [u8; 12], // scales and mins, quantized with 6 bits
     pub qs: [u8; 128],    // 4-bit quarters
 }
+
+/// block_q8_0 structure from llama.cpp
+/// Block size is 32
+pub const QK8_0: usize = 32;
+
+#[repr(C, packed)]
+pub struct BlockQ8_0 {
+    pub d: u16,       // scale (f16 bits)
+    pub qs: [i8; 32], // quants
+}
+
+/// Size of a block_q8_0 in bytes
+pub const BLOCK_Q8_0_SIZE: usize = core::mem::size_of::<BlockQ8_0>();
+
+/// block_q5_K structure from llama.cpp
+/// Block size is 256 (QK_K)
+#[repr(C, packed)]
+pub struct BlockQ5K {
+    pub d: u16,           // super-block scale (f16 bits)
+    pub dmin: u16,        // super-block scale for quantized mins (f16 bits)
+    pub scales: [u8; 12], // scales and mins, quantized with 6 bits
+    pub qh: [u8; 32],     // high bit of each quant
+    pub qs: [u8; 128],    // low 4 bits of each quant
+}
+
+/// Size of a block_q5_K in bytes
+pub const BLOCK_Q5K_SIZE: usize = core::mem::size_of::<BlockQ5K>();
+
+/// Convert IEEE 754 half-precision bits to f32
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits as u32) & 0x8000) << 16;
+    let exponent = ((bits >> 10) & 0x1F) as u32;
+    let mantissa = (bits & 0x3FF) as u32;
+    let magnitude = match exponent {
+        // Zero or subnormal: mantissa * 2^-24
+        0 => {
+            let value = mantissa as f32 / 16_777_216.0;
+            return if sign != 0 { -value } else { value };
+        }
+        // Infinity or NaN
+        0x1F => 0x7F80_0000 | (mantissa << 13),
+        _ => ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(sign | magnitude)
+}
+
+/// Dequantize consecutive block_q8_0 blocks
+pub fn dequantize_q8_0(data: &[u8]) -> Vec<f32> {
+    let mut out = Vec::with_capacity(data.len() / BLOCK_Q8_0_SIZE * QK8_0);
+    for block in data.chunks_exact(BLOCK_Q8_0_SIZE) {
+        let d = f16_to_f32(u16::from_le_bytes([block[0], block[1]]));
+        out.extend(block[2..].iter().map(|&q| q as i8 as f32 * d));
+    }
+    out
+}
+
+/// Dequantize consecutive block_q5_K blocks
+pub fn dequantize_q5k(data: &[u8]) -> Vec<f32> {
+    let mut out = Vec::with_capacity(data.len() / BLOCK_Q5K_SIZE * QK_K);
+    for block in data.chunks_exact(BLOCK_Q5K_SIZE) {
+        let d = f16_to_f32(u16::from_le_bytes([block[0], block[1]]));
+        let dmin = f16_to_f32(u16::from_le_bytes([block[2], block[3]]));
+        let scales = &block[4..16];
+        let qh = &block[16..48];
+        let qs = &block[48..176];
+
+        // Each 64-value chunk uses 32 bytes of qs (low then high nibbles)
+        // and two bits of every qh byte
+        for chunk in 0..4 {
+            let ql = &qs[chunk * 32..(chunk + 1) * 32];
+            for half in 0..2 {
+                let (scale, min) = scale_min_k4(chunk * 2 + half, scales);
+                let d = d * scale as f32;
+                let m = dmin * min as f32;
+                let high_bit = 1u8 << (chunk * 2 + half);
+                for l in 0..32 {
+                    let low = if half == 0 { ql[l] & 0x0F } else { ql[l] >> 4 };
+                    let high = if qh[l] & high_bit != 0 { 16 } else { 0 };
+                    out.push((low + high) as f32 * d - m);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// 6-bit scale and min of sub-block `j` (get_scale_min_k4 in llama.cpp)
+fn scale_min_k4(j: usize, q: &[u8]) -> (u8, u8) {
+    if j < 4 {
+        (q[j] & 63, q[j + 4] & 63)
+    } else {
+        (
+            (q[j + 4] & 0x0F) | ((q[j - 4] >> 6) << 4),
+            (q[j + 4] >> 4) | ((q[j] >> 6) << 4),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_f16_to_f32() {
+        assert_eq!(f16_to_f32(0x3C00), 1.0);
+        assert_eq!(f16_to_f32(0xC000), -2.0);
+        assert_eq!(f16_to_f32(0x3800), 0.5);
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert_eq!(f16_to_f32(0x0001), 1.0 / 16_777_216.0);
+        assert!(f16_to_f32(0x7C00).is_infinite());
+    }
+
+    #[test]
+    fn test_dequantize_q8_0() {
+        assert_eq!(BLOCK_Q8_0_SIZE, 34);
+        // d = 0.5, quants -16..16
+        let mut block = vec![0x00, 0x38];
+        block.extend((-16i8..16).map(|q| q as u8));
+
+        let values = dequantize_q8_0(&block);
+        assert_eq!(values.len(), QK8_0);
+        for (i, value) in values.iter().enumerate() {
+            let expected = (i as f32 - 16.0) * 0.5;
+            assert!((value - expected).abs() < 1e-6, "{} != {}", value, expected);
+        }
+    }
+
+    #[test]
+    fn test_dequantize_q5k() {
+        assert_eq!(BLOCK_Q5K_SIZE, 176);
+        let mut block = vec![0u8; BLOCK_Q5K_SIZE];
+        block[0..2].copy_from_slice(&0x3C00u16.to_le_bytes()); // d = 1.0
+        block[2..4].copy_from_slice(&0x3800u16.to_le_bytes()); // dmin = 0.5
+        // Sub-block 0: scale 2, min 4; sub-block 1: scale 1, min 0
+        block[4] = 2;
+        block[8] = 4;
+        block[5] = 1;
+        // First quant byte: low nibble 3 (sub-block 0), high nibble 5 (sub-block 1)
+        block[48] = 0x53;
+        // High bit set for value 0 of sub-block 1
+        block[16] = 0b10;
+
+        let values = dequantize_q5k(&block);
+        assert_eq!(values.len(), QK_K);
+        assert!((values[0] - (3.0 * 2.0 - 4.0 * 0.5)).abs() < 1e-6);
+        assert!((values[1] - (-2.0)).abs() < 1e-6);
+        assert!((values[32] - (5.0 + 16.0)).abs() < 1e-6);
+        assert_eq!(values[33], 0.0);
+    }
+
+    #[test]
+    fn test_dequantize_row() {
+        let mut data = Vec::new();
+        for d in [0x3C00u16, 0x4000] {
+            data.extend_from_slice(&d.to_le_bytes());
+            data.extend([1u8; 32]);
+        }
+        let tensor = TensorData::Q8_0(data);
+        assert_eq!(tensor.dequantize_row(1, 32), Some(vec![2.0; 32]));
+        assert_eq!(tensor.dequantize_row(2, 32), None);
+        assert_eq!(TensorData::F32(vec![1.0]).dequantize(), None);
+    }
+}
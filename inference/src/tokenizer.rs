@@ -43,6 +43,15 @@ pub struct Tokenizer {
     special_tokens: SpecialTokens,
 }
 
+/// Raw byte of a `<0xXX>` byte token
+fn parse_byte_token(token: &str) -> Option<u8> {
+    let hex = token.strip_prefix("<0x")?.strip_suffix('>')?;
+    if hex.len() != 2 {
+        return None;
+    }
+    u8::from_str_radix(hex, 16).ok()
+}
+
 /// Special tokens used by the tokenizer
 #[derive(Debug, Clone)]
 pub struct SpecialTokens {
@@ -286,29 +295,38 @@ impl Tokenizer {
     /// # Returns
     /// The decoded text string
     pub fn decode(&self, token_ids: &[u32]) -> String {
-        let mut result = String::new();
+        let mut bytes = Vec::new();
 
         for &token_id in token_ids {
             // Stop decoding if we encounter EOS token
             if self.special_tokens.eos_token == Some(token_id) {
                 break;
             }
+            self.push_token_bytes(token_id, &mut bytes);
+        }
 
-            // Skip BOS and PAD tokens in output
-            if self.special_tokens.bos_token == Some(token_id)
-                || self.special_tokens.pad_token == Some(token_id)
-            {
-                continue;
-            }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
 
-            // Include all other tokens (including UNK if it appears in the sequence)
-            if let Some(token) = self.id_to_token.get(&token_id) {
-                result.push_str(token);
-            }
-            // Skip unknown token IDs that aren't in vocabulary
+    /// Append the bytes `token_id` stands for to `out`
+    ///
+    /// Byte tokens (`<0xXX>`) give a single raw byte, which may be part of a
+    /// multi-byte UTF-8 sequence. BOS, EOS, PAD and IDs outside the
+    /// vocabulary give nothing; UNK is included if it appears.
+    pub fn push_token_bytes(&self, token_id: u32, out: &mut Vec<u8>) {
+        if self.special_tokens.bos_token == Some(token_id)
+            || self.special_tokens.eos_token == Some(token_id)
+            || self.special_tokens.pad_token == Some(token_id)
+        {
+            return;
         }
 
-        result
+        if let Some(token) = self.id_to_token.get(&token_id) {
+            match parse_byte_token(token) {
+                Some(byte) => out.push(byte),
+                None => out.extend_from_slice(token.as_bytes()),
+            }
+        }
     }
 
     /// Check if a token ID is a special token (BOS, EOS, PAD, or UNK)
@@ -407,4 +425,28 @@ mod tests {
         assert_eq!(tokenizer.id_to_token_str(1), Some("world"));
         assert_eq!(tokenizer.id_to_token_str(999), None);
     }
+
+    #[test]
+    fn test_byte_tokens_decode_to_raw_bytes() {
+        let mut vocab = BTreeMap::new();
+        vocab.insert("hi".to_string(), 0);
+        // "é" is C3 A9, "€" is E2 82 AC
+        for (id, byte) in [0xC3u8, 0xA9, 0xE2, 0x82, 0xAC].iter().enumerate() {
+            vocab.insert(format!("<0x{:02X}>", byte), id as u32 + 1);
+        }
+        let special_tokens = SpecialTokens {
+            bos_token: None,
+            eos_token: None,
+            pad_token: None,
+            unk_token: None,
+        };
+        let tokenizer = Tokenizer::new(vocab, Vec::new(), special_tokens);
+
+        let mut bytes = Vec::new();
+        tokenizer.push_token_bytes(0, &mut bytes);
+        tokenizer.push_token_bytes(3, &mut bytes);
+        assert_eq!(bytes, b"hi\xE2");
+
+        assert_eq!(tokenizer.decode(&[0, 1, 2]), "hié");
+    }
 }
@@ -1,7 +1,7 @@
 use alloc::vec::Vec;
 use alloc::vec;
 use alloc::format;
-use crate::ops::{matmul_f32, matmul_q4k, add, mul, rms_norm, rope, silu, softmax};
+use crate::ops::{matmul_f32, matmul_q4k, add, mul, rms_norm, rope, silu, softmax, RopeScaling};
 use crate::tensor::{Tensor, TensorData};
 use crate::error::ModelError;
 use micromath::F32Ext;
@@ -17,6 +17,8 @@ pub struct ModelConfig {
     pub intermediate_size: usize,
     pub max_seq_len: usize,
     pub rope_freq_base: f32,
+    /// Position scaling for running beyond the trained context
+    pub rope_scaling: RopeScaling,
     pub norm_eps: f32,
 }
 
@@ -32,6 +34,7 @@ impl ModelConfig {
             intermediate_size: 2816,
             max_seq_len: 2048,
             rope_freq_base: 10000.0,
+            rope_scaling: RopeScaling::None,
             norm_eps: 1e-6,
         }
     }
@@ -103,6 +106,10 @@ pub struct KvCache {
     head_dim: usize,
     /// Number of heads
     num_heads: usize,
+    /// Most positions kept once the cache slides; `None` stops at `max_seq_len`
+    window_size: Option<usize>,
+    /// Positions evicted since the last reset
+    evicted: usize,
 }
 
 impl KvCache {
@@ -125,8 +132,61 @@ impl KvCache {
             max_seq_len,
             head_dim,
             num_heads,
+            window_size: None,
+            evicted: 0,
         }
     }
+
+    /// Keep only the most recent `window` positions (at most `max_seq_len`)
+    ///
+    /// With a window, [`Self::make_room`] drops the oldest positions instead
+    /// of letting the cache fill up. `None` restores the fixed-size cache.
+    pub fn set_window(&mut self, window: Option<usize>) {
+        self.window_size = window.map(|window| window.clamp(1, self.max_seq_len));
+    }
+
+    /// The configured sliding window, if any
+    pub fn window(&self) -> Option<usize> {
+        self.window_size
+    }
+
+    /// Evict the oldest positions so `incoming` more fit in the window
+    ///
+    /// Kept entries move to the front of the cache. Their keys were rotated
+    /// at their original positions, and [`Self::rope_pos`] keeps counting
+    /// from there, so relative RoPE distances are unchanged.
+    ///
+    /// # Returns
+    /// The number of positions evicted
+    pub fn make_room(&mut self, incoming: usize) -> usize {
+        let Some(window) = self.window_size else {
+            return 0;
+        };
+        let evict = (self.current_pos + incoming)
+            .saturating_sub(window)
+            .min(self.current_pos);
+        if evict == 0 {
+            return 0;
+        }
+
+        let stride = self.num_heads * self.head_dim;
+        let kept = (self.current_pos - evict) * stride;
+        for layer in 0..self.num_layers {
+            let from = evict * stride;
+            self.k_cache[layer].copy_within(from..from + kept, 0);
+            self.v_cache[layer].copy_within(from..from + kept, 0);
+        }
+        self.current_pos -= evict;
+        self.evicted += evict;
+        evict
+    }
+
+    /// Position of the next token in the sequence, for RoPE
+    ///
+    /// Equals [`Self::current_pos`] until a window evicts entries.
+    pub fn rope_pos(&self) -> usize {
+        self.evicted + self.current_pos
+    }
     
     /// Append K and V vectors for a specific layer at the current position
     pub fn append(&mut self, layer: usize, k: &[f32], v: &[f32]) {
@@ -199,6 +259,7 @@ impl KvCache {
     /// Reset cache (for new sequence)
     pub fn reset(&mut self) {
         self.current_pos = 0;
+        self.evicted = 0;
         for layer in 0..self.num_layers {
             self.k_cache[layer].fill(0.0);
             self.v_cache[layer].fill(0.0);
@@ -232,6 +293,12 @@ impl Transformer {
             return Err(ModelError::InvalidInput("Empty token sequence".into()));
         }
         
+        // Slide the cache window (if any) so the new tokens fit
+        kv_cache.make_room(seq_len);
+        if kv_cache.window().is_some_and(|window| seq_len > window) {
+            return Err(ModelError::InvalidInput("Token sequence longer than the KV cache window".into()));
+        }
+        
         // 1. Embedding lookup
         let mut x = self.embedding_lookup(tokens)?;
         
@@ -276,6 +343,18 @@ impl Transformer {
                 // Quantized embeddings would need dequantization
                 return Err(ModelError::InvalidInput("Quantized embeddings not yet supported".into()));
             }
+            data @ (TensorData::Q8_0(_) | TensorData::Q5K(_)) => {
+                // Only the looked-up rows are dequantized
+                for &token_id in tokens {
+                    if token_id as usize >= self.config.vocab_size {
+                        return Err(ModelError::InvalidInput(format!("Token ID {} out of range", token_id)));
+                    }
+                    let row = data.dequantize_row(token_id as usize, hidden_size).ok_or_else(|| {
+                        ModelError::InvalidInput("Embedding weight out of bounds".into())
+                    })?;
+                    embeddings.extend_from_slice(&row);
+                }
+            }
         }
         
         Ok(embeddings)
@@ -361,12 +440,12 @@ impl Transformer {
         let mut k_rope = k;
         
         for pos in 0..seq_len {
-            let abs_pos = kv_cache.current_pos() + pos;
+            let abs_pos = kv_cache.rope_pos() + pos;
             let q_pos = &mut q_rope[pos * hidden_size..(pos + 1) * hidden_size];
             let k_pos = &mut k_rope[pos * hidden_size..(pos + 1) * hidden_size];
             
-            rope(q_pos, abs_pos, head_dim, self.config.rope_freq_base);
-            rope(k_pos, abs_pos, head_dim, self.config.rope_freq_base);
+            rope(q_pos, abs_pos, head_dim, self.config.rope_freq_base, self.config.rope_scaling);
+            rope(k_pos, abs_pos, head_dim, self.config.rope_freq_base, self.config.rope_scaling);
         }
         
         // 4. Get cached K and V (for previous positions)
@@ -499,6 +578,14 @@ impl Transformer {
                     "Quantized QKV projection requires proper weight format or specialized matmul".into()
                 ));
             }
+            data @ (TensorData::Q8_0(_) | TensorData::Q5K(_)) => {
+                // Dequantize, then as F32
+                let weight = data.dequantize().unwrap_or_default();
+                // Quantized rows are outputs; the F32 kernel wants (in, out)
+                let weight = self.transpose_matrix(&weight, 3 * hidden_size, hidden_size);
+                let qkv = matmul_f32(x, &weight, seq_len, 3 * hidden_size, hidden_size);
+                Ok(qkv)
+            }
         }
     }
     
@@ -524,6 +611,13 @@ impl Transformer {
                 let out = self.transpose_matrix(&out_transposed, hidden_size, seq_len);
                 Ok(out)
             }
+            data @ (TensorData::Q8_0(_) | TensorData::Q5K(_)) => {
+                // Dequantize, then as F32
+                let weight = data.dequantize().unwrap_or_default();
+                let weight = self.transpose_matrix(&weight, hidden_size, hidden_size);
+                let out = matmul_f32(x, &weight, seq_len, hidden_size, hidden_size);
+                Ok(out)
+            }
         }
     }
     
@@ -578,6 +672,13 @@ impl Transformer {
                 let out = self.transpose_matrix(&out_transposed, out_dim, seq_len);
                 Ok(out)
             }
+            data @ (TensorData::Q8_0(_) | TensorData::Q5K(_)) => {
+                // Dequantize, then as F32
+                let weight_data = data.dequantize().unwrap_or_default();
+                let weight_data = self.transpose_matrix(&weight_data, out_dim, in_dim);
+                let out = matmul_f32(x, &weight_data, seq_len, out_dim, in_dim);
+                Ok(out)
+            }
         }
     }
     
@@ -606,6 +707,14 @@ impl Transformer {
                 let logits = matmul_q4k(weight, last_token, vocab_size, 1, hidden_size);
                 Ok(logits)
             }
+            data @ (TensorData::Q8_0(_) | TensorData::Q5K(_)) => {
+                // Dequantize, then as F32
+                let weight = data.dequantize().unwrap_or_default();
+                let weight = self.transpose_matrix(&weight, vocab_size, hidden_size);
+                let last_token = &x[(seq_len - 1) * hidden_size..];
+                let logits = matmul_f32(last_token, &weight, 1, vocab_size, hidden_size);
+                Ok(logits)
+            }
         }
     }
     
@@ -626,3 +735,39 @@ impl Transformer {
         &self.config
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kv_cache_window_evicts_oldest() {
+        let mut cache = KvCache::new(1, 8, 1, 2);
+        cache.set_window(Some(4));
+
+        for token in 0..6 {
+            let value = [token as f32; 2];
+            cache.make_room(1);
+            cache.append(0, &value, &value);
+            cache.advance();
+        }
+
+        // Tokens 0 and 1 are gone; 2..=5 remain, oldest first
+        assert_eq!(cache.current_pos(), 4);
+        assert_eq!(cache.rope_pos(), 6);
+        assert_eq!(cache.get_k(0, 0, 4), &[2.0, 2.0, 3.0, 3.0, 4.0, 4.0, 5.0, 5.0]);
+        assert_eq!(cache.get_v(0, 3, 4), &[5.0, 5.0]);
+
+        cache.reset();
+        assert_eq!(cache.rope_pos(), 0);
+    }
+
+    #[test]
+    fn test_kv_cache_without_window_does_not_slide() {
+        let mut cache = KvCache::new(1, 2, 1, 1);
+        cache.advance();
+        cache.advance();
+        assert_eq!(cache.make_room(1), 0);
+        assert_eq!(cache.current_pos(), 2);
+    }
+}
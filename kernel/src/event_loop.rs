@@ -6,7 +6,43 @@
 use crate::GLOBAL_STATE;
 use crate::init;
 use shared::timer;
-use network::poll_network_stack;
+use network::{network_poll_delay_ms, poll_network_stack};
+
+/// Longest wait between frames (~60 FPS)
+const FRAME_MS: u64 = 16;
+
+/// Loop iterations between heartbeats
+const HEARTBEAT_ITERATIONS: u64 = 300;
+
+/// Time spent waiting versus running, reported with the heartbeat
+#[derive(Default)]
+struct IdleStats {
+    /// When the current reporting window started
+    window_start_ms: i64,
+    /// Time spent in `wait_for_work` this window
+    waited_ms: i64,
+    /// Waits skipped because the network stack had work due
+    skipped_waits: u64,
+}
+
+impl IdleStats {
+    /// Log the idle share of the window on serial and start a new one
+    fn report(&mut self, now_ms: i64) {
+        let elapsed = now_ms - self.window_start_ms;
+        if elapsed > 0 {
+            crate::serial::println(&alloc::format!(
+                "Event loop: idle {}% of {} ms, {} waits skipped for network work",
+                self.waited_ms * 100 / elapsed,
+                elapsed,
+                self.skipped_waits
+            ));
+        }
+        *self = Self {
+            window_start_ms: now_ms,
+            ..Self::default()
+        };
+    }
+}
 
 /// Main event loop
 ///
@@ -14,18 +50,21 @@ use network::poll_network_stack;
 /// 1. Handles keyboard input
 /// 2. Polls the network stack
 /// 3. Updates the screen
-/// 4. Sleeps briefly to maintain ~60 FPS
+/// 4. Waits up to ~16ms (60 FPS) for the next frame, or less if the network
+///    stack has a timer due sooner
 ///
 /// This function never returns.
 pub fn main_loop() -> ! {
     crate::serial::println("Event loop starting...");
     crate::serial::println("Type in this terminal or click QEMU window and type there");
     let mut loop_count: u64 = 0;
+    let mut idle = IdleStats::default();
 
     loop {
         // Heartbeat every ~5 seconds (300 iterations at 16ms each)
-        if loop_count % 300 == 0 {
+        if loop_count % HEARTBEAT_ITERATIONS == 0 {
             crate::serial::println("Event loop heartbeat");
+            idle.report(init::get_time_ms());
         }
         loop_count = loop_count.wrapping_add(1);
 
@@ -35,6 +74,9 @@ pub fn main_loop() -> ! {
         // Poll network stack
         poll_network();
 
+        // Animate the generation spinner
+        crate::screen::tick_spinner();
+
         // Update screen - this might be slow/blocking
         if loop_count == 1 {
             crate::serial::println("First screen update...");
@@ -44,8 +86,19 @@ pub fn main_loop() -> ! {
             crate::serial::println("First screen update done");
         }
 
-        // Sleep for ~16ms to maintain ~60 FPS
-        sleep_ms(16);
+        // Slow work requested by input runs after the frame showing its spinner
+        crate::input::run_pending_key_check();
+
+        // Wait for the next frame, unless the network stack needs polling
+        // before then (TCP retransmits, DHCP renewals, ...)
+        let now = init::get_time_ms();
+        let wait_ms = network_poll_delay_ms(now).map_or(FRAME_MS, |delay| delay.min(FRAME_MS));
+        if wait_ms == 0 {
+            idle.skipped_waits += 1;
+            continue;
+        }
+        wait_for_work(wait_ms);
+        idle.waited_ms += init::get_time_ms() - now;
     }
 }
 
@@ -58,6 +111,26 @@ fn poll_network() {
     let _ = poll_network_stack(timestamp_ms);
 }
 
+/// Wait until the next frame is due or the NIC interrupts
+///
+/// With interrupt-driven receive, and a ticking timer to bound the wait,
+/// the CPU halts between interrupts (the keyboard wakes it too); otherwise
+/// this is a plain sleep and the network is polled once per frame.
+fn wait_for_work(ms: u64) {
+    #[cfg(target_arch = "x86_64")]
+    if init::timer_irq_active() && network::drivers::interrupts::irq_active() {
+        let deadline = init::get_time_ms() + ms as i64;
+        while !network::drivers::interrupts::take_wakeup() && init::get_time_ms() < deadline {
+            // `sti` takes effect after `hlt` starts, so no wake-up is missed
+            unsafe {
+                core::arch::asm!("sti; hlt", options(nomem, nostack));
+            }
+        }
+        return;
+    }
+    sleep_ms(ms);
+}
+
 /// Sleep for the specified number of milliseconds
 ///
 /// # Arguments
@@ -6,10 +6,58 @@
 use alloc::boxed::Box;
 use alloc::format;
 use alloc::string::{String, ToString};
-use config::{decrypt_api_key, MoteConfig};
-use llm::{AnthropicClient, GroqClient, LlmProvider, OpenAiClient, XaiClient};
-use network::{init_network_stack, NetworkStack, NetError};
+use alloc::vec::Vec;
+use config::{decrypt_api_key, MoteConfig, NetworkConfig, ProviderConfig};
+use core::sync::atomic::{AtomicBool, Ordering};
+use inference::{GgufFile, LocalModel, LocalProvider, ModelError};
+use llm::{
+    AnthropicClient, AzureOpenAiClient, GroqClient, LlmProvider, ModelInfo, OpenAiClient,
+    OpenAiCompatClient, XaiClient,
+};
+use network::http::{DEFAULT_CONNECT_TIMEOUT_MS, DEFAULT_READ_TIMEOUT_MS};
+use network::{
+    Connectivity, HttpClient, NetError, NetworkStack, NetworkStatus, PUBLIC_DNS_SERVERS,
+};
 use smoltcp::wire::Ipv4Address;
+use spin::Mutex;
+
+/// Local model available to the "local" provider, once one has been loaded
+static LOCAL_MODEL: Mutex<Option<LocalProvider>> = Mutex::new(None);
+
+/// Make a loaded local model available to `init_provider`
+///
+/// Providers created afterwards share the model's weights, so switching
+/// away from and back to "local" does not reload it.
+pub fn install_local_model(provider: LocalProvider) {
+    let simd = inference::simd::init();
+    crate::serial::println(&format!("moteOS: local inference using {}", simd.name()));
+    *LOCAL_MODEL.lock() = Some(provider);
+}
+
+/// Load a GGUF model image and install it as the "local" provider
+///
+/// Draws a progress bar on `screen` while the weights are converted, since
+/// a large model takes long enough to look like a hang.
+pub fn load_local_model(screen: &mut tui::Screen, data: Vec<u8>) -> Result<(), ModelError> {
+    crate::serial::println("moteOS: loading local model...");
+    screen.clear();
+    crate::screen::draw_progress(screen, "Loading model", 0, 1);
+
+    let gguf = GgufFile::parse(data)?;
+    let mut last_percent = 0;
+    let model = LocalModel::load_with_progress(&gguf, |loaded, total| {
+        // Redraw only when the percentage changes
+        let percent = loaded * 100 / total.max(1);
+        if percent != last_percent {
+            last_percent = percent;
+            crate::screen::draw_progress(screen, "Loading model", loaded, total);
+        }
+    })?;
+
+    install_local_model(LocalProvider::from_gguf(model, &gguf));
+    crate::serial::println("moteOS: local model loaded");
+    Ok(())
+}
 
 /// Initialize the heap allocator
 ///
@@ -31,6 +79,55 @@ pub fn init_heap(heap_start: usize, heap_size: usize) {
     }
 }
 
+/// Use PCIe enhanced configuration access if ACPI describes it
+///
+/// Without an MCFG table, x86_64 falls back to port I/O and other
+/// architectures find no PCI devices. Must run before any NIC driver probes.
+pub fn init_pci(boot_info: &shared::BootInfo) {
+    let Some(rsdp_addr) = boot_info.rsdp_addr else {
+        crate::serial::println("moteOS: no ACPI RSDP, using legacy PCI access");
+        return;
+    };
+    // SAFETY: the firmware's ACPI tables are still identity-mapped
+    match unsafe { network::pci::find_ecam_region(rsdp_addr) } {
+        Some(region) => {
+            crate::serial::println(&format!(
+                "moteOS: PCI ECAM at 0x{:x}, buses {}-{}",
+                region.base, region.start_bus, region.end_bus
+            ));
+            network::pci::set_ecam_region(Some(region));
+        }
+        None => crate::serial::println("moteOS: no MCFG table, using legacy PCI access"),
+    }
+}
+
+/// Reserve the DMA pool and route network driver buffers through it
+///
+/// Must run after `init_heap` and before any NIC driver is created.
+pub fn init_dma(boot_info: &shared::BootInfo) {
+    // SAFETY: the UEFI mapping is still identity, and the heap range is the
+    // one just handed to the allocator
+    let reserved = unsafe {
+        shared::init_dma(
+            &boot_info.memory_map,
+            boot_info.heap_start,
+            boot_info.heap_size,
+        )
+    };
+    match reserved {
+        Some((start, size)) => {
+            crate::serial::println(&format!(
+                "moteOS: DMA pool at 0x{:x}, {} KiB",
+                start,
+                size / 1024
+            ));
+            // SAFETY: called once, before any driver allocates
+            unsafe { network::set_dma_allocator(shared::alloc_dma, shared::free_dma) };
+        }
+        None => crate::serial::println("moteOS: no room for a DMA pool; using the heap"),
+    }
+}
+
 /// Initialize network stack
 ///
 /// Sets up the network stack based on configuration.
@@ -52,50 +149,52 @@ pub fn init_network(config: &MoteConfig) -> Result<NetworkStack, NetError> {
     // Try to detect and initialize a network driver
     // Priority: virtio-net (for VMs) > e1000 > RTL8139
     
+    // Determine IP configuration: a static one skips DHCP entirely,
+    // otherwise DHCP configures the stack later
+    let static_config = config.network.static_ip.as_ref().map(static_ip_config);
+    let proxy = http_proxy(&config.network);
+    let ip_config = static_config
+        .as_ref()
+        .map(|static_config| (static_config.ip, static_config.prefix_len));
+
+    // Resolvers: the public ones. Those configured with a static IP, or
+    // from a DHCP lease, are put in front when that config is applied.
+    let dns_servers = PUBLIC_DNS_SERVERS;
+    
     // Try virtio-net first (common in QEMU/KVM)
     #[cfg(target_arch = "x86_64")]
     {
         use network::drivers::virtio::VirtioNet;
         
-        match VirtioNet::new() {
+        let init_virtio = || -> Result<Box<dyn NetworkDriver>, NetError> {
+            let mut driver = VirtioNet::new()?;
+            driver.init()?;
+            Ok(Box::new(driver))
+        };
+        
+        match init_virtio() {
             Ok(driver) => {
-                let driver: Box<dyn NetworkDriver> = Box::new(driver);
-                
-                // Determine IP configuration
-                let ip_config = if let Some(static_ip) = &config.network.static_ip {
-                    // Use static IP configuration
-                    let ip = Ipv4Address::new(
-                        static_ip.ip[0],
-                        static_ip.ip[1],
-                        static_ip.ip[2],
-                        static_ip.ip[3],
-                    );
-                    // Calculate prefix length from subnet mask
-                    let prefix = subnet_mask_to_prefix(&static_ip.subnet_mask);
-                    Some((ip, prefix))
-                } else {
-                    // Use DHCP (will be configured later)
-                    None
-                };
-                
                 // Create the network stack
                 // Note: We'll store this in KernelState for polling
                 // HTTP clients will use the global network stack
                 // Since we can't easily share the stack, we'll initialize the global
                 // with the same driver. In a full implementation, we'd use Arc or similar.
-                let stack = NetworkStack::new(driver, ip_config)?;
+                let mut stack = NetworkStack::new(driver, ip_config)?;
                 
-                // Also initialize the global network stack for HTTP client access
-                // Note: This creates a second driver instance which may not work
-                // if the PCI device can only be initialized once.
-                // For now, we'll attempt it and handle errors gracefully.
-                // In production, we'd use shared ownership (Arc) or a different design.
-                if let Ok(global_driver) = VirtioNet::new() {
-                    let global_driver: Box<dyn NetworkDriver> = Box::new(global_driver);
+                // Also initialize the global network stack for HTTP client access.
+                // This creates a second driver instance; initializing it
+                // takes over the virtqueues, so it is done last and it is the
+                // one the event loop polls (and that gets the interrupt).
+                if let Ok(global_driver) = init_virtio() {
                     // Try to initialize global stack (HTTP clients use this)
                     let _ = network::init_network_stack(global_driver, ip_config);
                     // If this fails, HTTP clients won't work, but polling will
                 }
+                set_dns_servers(&mut stack, &dns_servers);
+                apply_static_config(&mut stack, static_config.as_ref());
+                set_http_proxy(&mut stack, proxy.as_ref());
+                set_mdns_enabled(&mut stack, config.network.mdns);
+                crate::serial::println("moteOS: network driver: virtio-net");
                 
                 // Start DHCP if not using static IP
                 if ip_config.is_none() {
@@ -110,38 +209,229 @@ pub fn init_network(config: &MoteConfig) -> Result<NetworkStack, NetError> {
         }
     }
     
+    // Then the Intel e1000 (QEMU's default NIC, VirtualBox, VMware)
+    #[cfg(target_arch = "x86_64")]
+    {
+        use network::drivers::e1000::E1000;
+        
+        let init_e1000 = || -> Result<(Box<dyn NetworkDriver>, &'static str), NetError> {
+            let mut driver = E1000::new()?;
+            driver.init()?;
+            let model = driver.model();
+            Ok((Box::new(driver), model))
+        };
+        
+        if let Ok((driver, model)) = init_e1000() {
+            let mut stack = NetworkStack::new(driver, ip_config)?;
+            
+            // As with virtio-net, the global stack (polled by the event loop
+            // and used by HTTP clients) gets its own instance; initializing
+            // it takes over the descriptor rings, so it is done last
+            if let Ok((global_driver, _)) = init_e1000() {
+                let _ = network::init_network_stack(global_driver, ip_config);
+            }
+            set_dns_servers(&mut stack, &dns_servers);
+            apply_static_config(&mut stack, static_config.as_ref());
+            set_http_proxy(&mut stack, proxy.as_ref());
+            set_mdns_enabled(&mut stack, config.network.mdns);
+            crate::serial::println(&format!("moteOS: network driver: Intel {}", model));
+            
+            return Ok(stack);
+        }
+    }
+    
+    // Finally the Realtek RTL8139
+    #[cfg(target_arch = "x86_64")]
+    {
+        use network::drivers::rtl8139::Rtl8139;
+        
+        let init_rtl8139 = || -> Result<Box<dyn NetworkDriver>, NetError> {
+            let mut driver = Rtl8139::new()?;
+            driver.init()?;
+            Ok(Box::new(driver))
+        };
+        
+        if let Ok(driver) = init_rtl8139() {
+            let mut stack = NetworkStack::new(driver, ip_config)?;
+            
+            // Same as e1000: the global stack's instance is initialized last
+            if let Ok(global_driver) = init_rtl8139() {
+                let _ = network::init_network_stack(global_driver, ip_config);
+            }
+            set_dns_servers(&mut stack, &dns_servers);
+            apply_static_config(&mut stack, static_config.as_ref());
+            set_http_proxy(&mut stack, proxy.as_ref());
+            set_mdns_enabled(&mut stack, config.network.mdns);
+            crate::serial::println("moteOS: network driver: Realtek RTL8139");
+            
+            return Ok(stack);
+        }
+    }
+    
     // No network driver found
     // Return error - network is optional, so this is acceptable
     Err(NetError::DriverError("No network driver available".into()))
 }
 
-/// Convert subnet mask to prefix length
+/// Whether the platform's timer interrupt is ticking (set via set_timer_irq_active)
+static TIMER_IRQ_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Record that the platform has routed and unmasked its timer interrupt
 ///
-/// # Arguments
+/// Until then nothing is guaranteed to wake a halted CPU, so the event
+/// loop must not halt between frames.
+pub fn set_timer_irq_active(active: bool) {
+    TIMER_IRQ_ACTIVE.store(active, Ordering::Release);
+}
+
+/// Whether timer interrupts advance the clock and wake a halted CPU
+pub fn timer_irq_active() -> bool {
+    TIMER_IRQ_ACTIVE.load(Ordering::Acquire)
+}
+
+/// Routes a NIC's legacy IRQ line to `handle_network_irq`
 ///
-/// * `mask` - Subnet mask as [u8; 4]
+/// Returns `false` if the line can't be used, e.g. because the platform
+/// has no interrupt controller set up.
+pub type NetworkIrqInstaller = Option<fn(line: u8) -> bool>;
+
+/// Installer registered by the platform (set via set_network_irq_installer)
+static mut NETWORK_IRQ_INSTALLER: NetworkIrqInstaller = None;
+
+/// Register how to route the NIC interrupt
 ///
-/// # Returns
+/// # Safety
+/// Modifies global state without synchronization; call during boot,
+/// before `kernel_main`.
+pub unsafe fn set_network_irq_installer(installer: NetworkIrqInstaller) {
+    NETWORK_IRQ_INSTALLER = installer;
+}
+
+/// Service a NIC interrupt; for the platform's IRQ handler, which sends EOI
 ///
-/// Prefix length (0-32)
-fn subnet_mask_to_prefix(mask: &[u8; 4]) -> u8 {
-    let mut prefix = 0;
-    for &byte in mask.iter() {
-        if byte == 0xFF {
-            prefix += 8;
-        } else {
-            // Count leading ones in the byte
-            let mut remaining = byte;
-            while remaining & 0x80 != 0 {
-                prefix += 1;
-                remaining <<= 1;
-            }
-            break;
+/// Returns `false` if the interrupt came from another device on the line.
+#[cfg(target_arch = "x86_64")]
+pub fn handle_network_irq() -> bool {
+    network::drivers::interrupts::handle_virtio_net_irq()
+}
+
+/// Switch the global stack's driver to interrupt-driven receive if possible
+///
+/// Without an installer, or if the driver or line doesn't support it, the
+/// event loop keeps polling on its timer.
+pub fn enable_network_interrupts() -> bool {
+    let Some(install) = (unsafe { NETWORK_IRQ_INSTALLER }) else {
+        return false;
+    };
+    let mut global = network::get_network_stack();
+    let Some(stack) = global.as_mut() else {
+        return false;
+    };
+
+    match stack.enable_driver_interrupts() {
+        Ok(line) if install(line) => {
+            crate::serial::println(&format!("moteOS: network receive on IRQ {}", line));
+            true
+        }
+        Ok(line) => {
+            stack.disable_driver_interrupts();
+            crate::serial::println(&format!(
+                "moteOS: could not route IRQ {}, polling the network",
+                line
+            ));
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+/// Give `stack` and the global stack the same list of DNS servers
+fn set_dns_servers(stack: &mut NetworkStack, servers: &[Ipv4Address]) {
+    stack.set_dns_servers(servers);
+    if let Some(global) = network::get_network_stack().as_mut() {
+        global.set_dns_servers(servers);
+    }
+}
+
+/// Give `stack` and the global stack the configured address, default
+/// route and DNS servers; with DHCP (`None`) there is nothing to apply
+fn apply_static_config(stack: &mut NetworkStack, static_config: Option<&network::IpConfig>) {
+    let Some(static_config) = static_config else {
+        return;
+    };
+    if let Err(err) = stack.apply_static_config(static_config) {
+        crate::serial::println(&format!("moteOS: static IP configuration failed: {:?}", err));
+        return;
+    }
+    if let Some(global) = network::get_network_stack().as_mut() {
+        let _ = global.apply_static_config(static_config);
+    }
+    crate::serial::println(&format!(
+        "moteOS: static IP {}/{}",
+        static_config.ip, static_config.prefix_len
+    ));
+}
+
+/// Give `stack` and the global stack the same HTTP proxy
+fn set_http_proxy(stack: &mut NetworkStack, proxy: Option<&network::HttpProxy>) {
+    stack.set_http_proxy(proxy.cloned());
+    if let Some(global) = network::get_network_stack().as_mut() {
+        global.set_http_proxy(proxy.cloned());
+    }
+    if let Some(proxy) = proxy {
+        crate::serial::println(&format!("moteOS: HTTP proxy {}:{}", proxy.host, proxy.port));
+    }
+}
+
+/// Resolve `.local` names with mDNS on `stack` and the global stack, or not
+fn set_mdns_enabled(stack: &mut NetworkStack, enabled: bool) {
+    stack.set_mdns_enabled(enabled);
+    if let Some(global) = network::get_network_stack().as_mut() {
+        global.set_mdns_enabled(enabled);
+    }
+    if !enabled {
+        crate::serial::println("moteOS: mDNS disabled, .local names use unicast DNS");
+    }
+}
+
+/// The configured HTTP proxy, with its password decrypted
+fn http_proxy(network: &NetworkConfig) -> Option<network::HttpProxy> {
+    let proxy = network.proxy.as_ref()?;
+    let password = match proxy.password_encrypted.as_deref().map(decrypt_api_key) {
+        Some(Ok(password)) => password,
+        Some(Err(_)) => {
+            crate::serial::println("moteOS: could not decrypt the proxy password");
+            String::new()
         }
+        None => String::new(),
+    };
+    let credentials = proxy
+        .username
+        .as_deref()
+        .map(|username| (username, password.as_str()));
+    Some(network::HttpProxy::new(&proxy.host, proxy.port, credentials))
+}
+
+/// The network stack's form of a configured static IP
+///
+/// A gateway of 0.0.0.0 means no default route. The config loader rejects
+/// non-contiguous masks; should one get through, only the host itself is
+/// treated as on-link.
+fn static_ip_config(static_ip: &config::IpConfig) -> network::IpConfig {
+    let prefix_len = static_ip.prefix_len().unwrap_or(32);
+    let mut ip_config = network::IpConfig::new(Ipv4Address::from_bytes(&static_ip.ip), prefix_len);
+    if static_ip.gateway != [0; 4] {
+        ip_config = ip_config.with_gateway(Ipv4Address::from_bytes(&static_ip.gateway));
     }
-    prefix
+    for dns in &static_ip.dns {
+        ip_config.add_dns(Ipv4Address::from_bytes(dns));
+    }
+    ip_config
 }
 
+/// Resolver of last resort, tried after DHCP-provided and configured servers
+const FALLBACK_DNS_SERVER: Ipv4Address = PUBLIC_DNS_SERVERS[0];
+
 /// Get DNS server from network config or use default
 ///
 /// Returns the first DNS server from the config, or a default (8.8.8.8) if none is configured.
@@ -157,7 +447,7 @@ fn get_dns_server(network: Option<&mut NetworkStack>) -> Ipv4Address {
     }
     
     // Default to Google DNS
-    Ipv4Address::new(8, 8, 8, 8)
+    FALLBACK_DNS_SERVER
 }
 
 /// Get current time in milliseconds
@@ -170,6 +460,156 @@ pub fn get_time_ms() -> i64 {
     shared::timer::get_ticks() as i64 * 10 // Assume 100Hz = 10ms per tick
 }
 
+/// Start the wall clock from the firmware time read at boot, or the RTC
+///
+/// With the `full-tls` feature the wall clock is also the certificate
+/// validation time; until it is set (here or by [`sync_network_time`]) TLS
+/// falls back to its build date.
+pub fn init_wall_clock(boot_time_unix: Option<u64>) {
+    #[cfg(feature = "full-tls")]
+    unsafe {
+        network::set_tls_time_source(wall_clock_secs);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    let boot_time_unix = boot_time_unix.or_else(|| {
+        crate::serial::println("moteOS: firmware time unavailable, reading the RTC");
+        Some(crate::rtc::now().unix_seconds())
+    });
+
+    let Some(boot_time_unix) = boot_time_unix else {
+        crate::serial::println("moteOS: firmware time unavailable");
+        return;
+    };
+    shared::timer::set_wall_clock(boot_time_unix);
+}
+
+/// How long to wait for the NTP server's reply
+const NTP_TIMEOUT_MS: i64 = 3000;
+
+/// How long to wait for each ping reply
+const PING_TIMEOUT_MS: i64 = 2000;
+
+/// Set the wall clock from the configured NTP server
+///
+/// The firmware clock may be unset or drifted; NTP time replaces it when the
+/// server answers. Failure leaves the current wall clock alone.
+pub fn sync_network_time(config: &MoteConfig, stack: &mut NetworkStack) {
+    let server = config.network.ntp_server.as_deref();
+    match stack.sntp_query(server, NTP_TIMEOUT_MS, get_time_ms, Some(sleep_ms)) {
+        Ok(unix_ms) => shared::timer::set_wall_clock(unix_ms as u64 / 1000),
+        Err(err) => crate::serial::println(&format!(
+            "moteOS: NTP sync with {} failed: {}",
+            server.unwrap_or(network::ntp::DEFAULT_NTP_SERVER),
+            err
+        )),
+    }
+}
+
+/// How long the connectivity check waits to connect, and then for an answer
+const CONNECTIVITY_TIMEOUT_MS: i64 = 5000;
+
+/// Find out whether the network sends us to a captive portal
+///
+/// Only worth asking with a routable address: a link-local one never
+/// reaches the check URL. Returns `None` when the check was skipped.
+pub fn check_connectivity(stack: &mut NetworkStack) -> Option<Connectivity> {
+    if stack.network_status() != NetworkStatus::Online {
+        return None;
+    }
+    let client = HttpClient::new(get_dns_server(Some(&mut *stack)))
+        .with_timeouts(CONNECTIVITY_TIMEOUT_MS, CONNECTIVITY_TIMEOUT_MS);
+    let (mut now, mut sleep) = (get_time_ms, sleep_ms);
+    let connectivity = client.check_connectivity(stack, &mut now, Some(&mut sleep));
+    match &connectivity {
+        Connectivity::Ok => {}
+        Connectivity::CaptivePortal(location) => crate::serial::println(&format!(
+            "moteOS: captive portal detected (login at {})",
+            location.as_deref().unwrap_or("unknown URL")
+        )),
+        Connectivity::NoInternet => {
+            crate::serial::println("moteOS: connectivity check failed, no internet access")
+        }
+    }
+    Some(connectivity)
+}
+
+/// Ping `target` (an IPv4 address or host name) `count` times
+pub fn ping_host(
+    stack: &mut NetworkStack,
+    target: &str,
+    count: u16,
+) -> Result<network::PingStats, NetError> {
+    let address = match target.parse::<Ipv4Address>() {
+        Ok(address) => address,
+        Err(()) => stack.resolve(target, FALLBACK_DNS_SERVER, 5000, get_time_ms, Some(sleep_ms))?,
+    };
+    stack.ping(address, count, PING_TIMEOUT_MS, get_time_ms, Some(sleep_ms))
+}
+
+/// Current wall-clock time in seconds since the Unix epoch
+#[cfg(feature = "full-tls")]
+fn wall_clock_secs() -> u64 {
+    shared::timer::wall_clock_secs().unwrap_or(0)
+}
+
+/// Register extra trust anchors and pinned certificates from the config
+///
+/// The config was validated on load, so decoding errors are not expected.
+#[cfg(feature = "full-tls")]
+pub fn configure_tls(config: &MoteConfig) {
+    let callback: network::TlsLogCallback = if config.preferences.debug_tls {
+        Some(log_tls)
+    } else {
+        None
+    };
+    // SAFETY: called during boot, before any TLS connection is opened
+    unsafe {
+        network::set_tls_log_callback(callback);
+    }
+
+    if let Some(pem) = &config.network.tls_extra_ca_pem {
+        match config::decode_pem_certificates(pem, "network.tls.extra_ca_pem") {
+            Ok(certificates) => network::set_tls_extra_roots(certificates),
+            Err(e) => crate::serial::println(&format!("moteOS: extra CA ignored: {:?}", e)),
+        }
+    }
+
+    let providers = &config.providers;
+    let cloud = [
+        ("api.openai.com", &providers.openai),
+        ("api.anthropic.com", &providers.anthropic),
+        ("api.groq.com", &providers.groq),
+        ("api.x.ai", &providers.xai),
+        ("", &providers.azure),
+        ("", &providers.custom),
+    ];
+    for (default_host, provider) in cloud {
+        let Some(provider) = provider else {
+            continue;
+        };
+        let Some(fingerprint) = provider.pinned_cert_sha256 else {
+            continue;
+        };
+        let host = match (&provider.base_url, &provider.azure_resource) {
+            (Some(base_url), _) => network::http::parse_url(base_url)
+                .map(|url| url.host.to_string())
+                .unwrap_or_default(),
+            (None, Some(resource)) => format!("{}.openai.azure.com", resource),
+            (None, None) => default_host.to_string(),
+        };
+        if host.is_empty() {
+            continue;
+        }
+        network::pin_tls_certificate(&host, fingerprint);
+    }
+}
+
+#[cfg(feature = "full-tls")]
+fn log_tls(level: &str, message: &str) {
+    crate::serial::println(&format!("TLS {}: {}", level, message));
+}
+
 /// Sleep for the specified number of milliseconds
 ///
 /// This uses the timer's sleep function.
@@ -177,6 +617,56 @@ pub fn sleep_ms(ms: i64) {
     shared::timer::sleep_ms(ms as u64);
 }
 
+/// Validated `base_url` override for a provider, if one is configured
+///
+/// `name` is the provider's table in the config, used to name a bad setting.
+fn configured_base_url(
+    provider_config: &ProviderConfig,
+    name: &str,
+) -> Result<Option<String>, String> {
+    provider_config
+        .base_url
+        .as_deref()
+        .map(|base_url| {
+            llm::providers::parse_base_url(base_url, &format!("providers.{}.base_url", name))
+                .map_err(|e| e.to_string())
+        })
+        .transpose()
+}
+
+/// HTTP (connect, read) timeouts for a provider, falling back to the defaults
+fn configured_timeouts(provider_config: &ProviderConfig) -> (i64, i64) {
+    let connect_timeout_ms = provider_config
+        .connect_timeout_ms
+        .map_or(DEFAULT_CONNECT_TIMEOUT_MS, |ms| ms as i64);
+    let read_timeout_ms = provider_config
+        .read_timeout_ms
+        .map_or(DEFAULT_READ_TIMEOUT_MS, |ms| ms as i64);
+    (connect_timeout_ms, read_timeout_ms)
+}
+
+/// Model list for a self-hosted server until its own list is fetched
+fn single_model(model: &str) -> Vec<ModelInfo> {
+    if model.is_empty() {
+        return Vec::new();
+    }
+    Vec::from([ModelInfo::new(model.to_string(), model.to_string(), 0, true)])
+}
+
+/// Forward provider request/response logs to serial, or stop forwarding
+fn set_llm_logging(enabled: bool) {
+    let callback: llm::LlmLogCallback = if enabled { Some(log_llm) } else { None };
+    // SAFETY: providers are only used from the event loop, and none is
+    // running a request while it is being replaced
+    unsafe {
+        llm::set_llm_log_callback(callback);
+    }
+}
+
+fn log_llm(message: &str) {
+    crate::serial::println(&format!("LLM: {}", message));
+}
+
 /// Initialize LLM provider from configuration
 ///
 /// Creates and returns the configured LLM provider along with its name and default model.
@@ -195,6 +685,7 @@ pub fn init_provider(
 ) -> Result<(Box<dyn LlmProvider>, String, String), String> {
     let provider_name = &config.preferences.default_provider;
     let dns_server = get_dns_server(network);
+    set_llm_logging(config.preferences.debug_llm);
     
     match provider_name.as_str() {
         "openai" => {
@@ -207,7 +698,12 @@ pub fn init_provider(
             let api_key = decrypt_api_key(&provider_config.api_key_encrypted)
                 .map_err(|_| "Failed to decrypt OpenAI API key")?;
             
-            let client = OpenAiClient::new(api_key, dns_server, get_time_ms, Some(sleep_ms));
+            let mut client = OpenAiClient::new(api_key, dns_server, get_time_ms, Some(sleep_ms));
+            if let Some(base_url) = configured_base_url(provider_config, "openai")? {
+                client = client.with_base_url(base_url);
+            }
+            let (connect_timeout_ms, read_timeout_ms) = configured_timeouts(provider_config);
+            let client = client.with_timeouts(connect_timeout_ms, read_timeout_ms);
             let model = provider_config.default_model.clone();
             
             Ok((Box::new(client), "OpenAI".to_string(), model))
@@ -223,7 +719,12 @@ pub fn init_provider(
             let api_key = decrypt_api_key(&provider_config.api_key_encrypted)
                 .map_err(|_| "Failed to decrypt Anthropic API key")?;
             
-            let client = AnthropicClient::new(api_key, dns_server, get_time_ms, Some(sleep_ms));
+            let mut client = AnthropicClient::new(api_key, dns_server, get_time_ms, Some(sleep_ms));
+            if let Some(base_url) = configured_base_url(provider_config, "anthropic")? {
+                client = client.with_base_url(base_url);
+            }
+            let (connect_timeout_ms, read_timeout_ms) = configured_timeouts(provider_config);
+            let client = client.with_timeouts(connect_timeout_ms, read_timeout_ms);
             let model = provider_config.default_model.clone();
             
             Ok((Box::new(client), "Anthropic".to_string(), model))
@@ -239,7 +740,12 @@ pub fn init_provider(
             let api_key = decrypt_api_key(&provider_config.api_key_encrypted)
                 .map_err(|_| "Failed to decrypt Groq API key")?;
             
-            let client = GroqClient::new(api_key, dns_server, get_time_ms, Some(sleep_ms));
+            let mut client = GroqClient::new(api_key, dns_server, get_time_ms, Some(sleep_ms));
+            if let Some(base_url) = configured_base_url(provider_config, "groq")? {
+                client = client.with_base_url(base_url);
+            }
+            let (connect_timeout_ms, read_timeout_ms) = configured_timeouts(provider_config);
+            let client = client.with_timeouts(connect_timeout_ms, read_timeout_ms);
             let model = provider_config.default_model.clone();
             
             Ok((Box::new(client), "Groq".to_string(), model))
@@ -255,16 +761,131 @@ pub fn init_provider(
             let api_key = decrypt_api_key(&provider_config.api_key_encrypted)
                 .map_err(|_| "Failed to decrypt xAI API key")?;
             
-            let client = XaiClient::new(api_key, dns_server, get_time_ms, Some(sleep_ms));
+            let mut client = XaiClient::new(api_key, dns_server, get_time_ms, Some(sleep_ms));
+            if let Some(base_url) = configured_base_url(provider_config, "xai")? {
+                client = client.with_base_url(base_url);
+            }
+            let (connect_timeout_ms, read_timeout_ms) = configured_timeouts(provider_config);
+            let client = client.with_timeouts(connect_timeout_ms, read_timeout_ms);
             let model = provider_config.default_model.clone();
             
             Ok((Box::new(client), "xAI".to_string(), model))
         }
         
-        "local" | "ollama" => {
-            // TODO: Implement local provider initialization
-            // For now, return an error
-            Err("Local provider not yet implemented".to_string())
+        "azure" => {
+            let provider_config = config
+                .providers
+                .azure
+                .as_ref()
+                .ok_or("Azure OpenAI provider not configured")?;
+            
+            let api_key = decrypt_api_key(&provider_config.api_key_encrypted)
+                .map_err(|_| "Failed to decrypt Azure OpenAI API key")?;
+            
+            let resource = provider_config
+                .azure_resource
+                .clone()
+                .ok_or("Azure OpenAI resource not configured")?;
+            let deployment = provider_config
+                .azure_deployment
+                .clone()
+                .ok_or("Azure OpenAI deployment not configured")?;
+            let api_version = provider_config
+                .azure_api_version
+                .clone()
+                .unwrap_or_else(|| llm::providers::azure::DEFAULT_API_VERSION.to_string());
+            
+            let mut client = AzureOpenAiClient::new(
+                api_key,
+                resource,
+                deployment.clone(),
+                api_version,
+                dns_server,
+                get_time_ms,
+                Some(sleep_ms),
+            );
+            if let Some(base_url) = configured_base_url(provider_config, "azure")? {
+                client = client.with_base_url(base_url);
+            }
+            let (connect_timeout_ms, read_timeout_ms) = configured_timeouts(provider_config);
+            let client = client.with_timeouts(connect_timeout_ms, read_timeout_ms);
+            
+            Ok((Box::new(client), "Azure".to_string(), deployment))
+        }
+        
+        "local" => {
+            config
+                .providers
+                .local
+                .as_ref()
+                .ok_or("Local provider not configured")?;
+
+            let provider = LOCAL_MODEL
+                .lock()
+                .clone()
+                .ok_or("No local model loaded")?;
+            let model = provider.default_model().to_string();
+            Ok((Box::new(provider), "local".to_string(), model))
+        }
+
+        "custom" => {
+            let provider_config = config
+                .providers
+                .custom
+                .as_ref()
+                .ok_or("Custom provider not configured")?;
+
+            // An empty key is fine for servers without authentication
+            let api_key = decrypt_api_key(&provider_config.api_key_encrypted)
+                .map_err(|_| "Failed to decrypt custom provider API key")?;
+            let base_url = configured_base_url(provider_config, "custom")?
+                .ok_or("Custom provider base_url not configured")?;
+            let model = provider_config.default_model.clone();
+
+            let client = OpenAiCompatClient::new(
+                base_url,
+                api_key,
+                single_model(&model),
+                dns_server,
+                get_time_ms,
+                Some(sleep_ms),
+            );
+            let (connect_timeout_ms, read_timeout_ms) = configured_timeouts(provider_config);
+            let client = client.with_timeouts(connect_timeout_ms, read_timeout_ms);
+
+            Ok((Box::new(client), "Custom".to_string(), model))
+        }
+
+        "ollama" => {
+            let provider_config = config
+                .providers
+                .ollama
+                .as_ref()
+                .ok_or("Ollama provider not configured")?;
+
+            // Ollama serves the OpenAI API under /v1 and needs no key
+            let endpoint = llm::providers::parse_base_url(
+                &provider_config.endpoint,
+                "providers.ollama.endpoint",
+            )
+            .map_err(|e| e.to_string())?;
+            let base_url = if endpoint.ends_with("/v1") {
+                endpoint
+            } else {
+                format!("{}/v1", endpoint)
+            };
+            let model = provider_config.default_model.clone();
+
+            let client = OpenAiCompatClient::new(
+                base_url,
+                String::new(),
+                single_model(&model),
+                dns_server,
+                get_time_ms,
+                Some(sleep_ms),
+            );
+
+            Ok((Box::new(client), "Ollama".to_string(), model))
         }
         
         _ => {
@@ -272,7 +893,14 @@ pub fn init_provider(
             // Try to initialize OpenAI as fallback
             if let Some(provider_config) = &config.providers.openai {
                 if let Ok(api_key) = decrypt_api_key(&provider_config.api_key_encrypted) {
-                    let client = OpenAiClient::new(api_key, dns_server, get_time_ms, Some(sleep_ms));
+                    let mut client =
+                        OpenAiClient::new(api_key, dns_server, get_time_ms, Some(sleep_ms));
+                    if let Some(base_url) = configured_base_url(provider_config, "openai")? {
+                        client = client.with_base_url(base_url);
+                    }
+                    let (connect_timeout_ms, read_timeout_ms) =
+                        configured_timeouts(provider_config);
+                    let client = client.with_timeouts(connect_timeout_ms, read_timeout_ms);
                     let model = provider_config.default_model.clone();
                     return Ok((Box::new(client), "OpenAI".to_string(), model));
                 }
@@ -282,3 +910,59 @@ pub fn init_provider(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use alloc::vec;
+    use network::drivers::loopback::LoopbackDriver;
+
+    #[test]
+    fn test_static_ip_config_reaches_the_stack() {
+        let static_ip = config::IpConfig {
+            ip: [192, 168, 1, 20],
+            gateway: [192, 168, 1, 1],
+            dns: vec![[9, 9, 9, 9], [1, 1, 1, 1]],
+            subnet_mask: [255, 255, 255, 0],
+        };
+        let ip_config = static_ip_config(&static_ip);
+        assert_eq!(ip_config.prefix_len, 24);
+        assert_eq!(ip_config.gateway, Some(Ipv4Address::new(192, 168, 1, 1)));
+
+        // As init_network sets the stack up
+        let driver = LoopbackDriver::new([0x02, 0, 0, 0, 0, 0x01]);
+        let mut stack =
+            NetworkStack::new(Box::new(driver), Some((ip_config.ip, ip_config.prefix_len)))
+                .unwrap();
+        stack.set_dns_servers(&PUBLIC_DNS_SERVERS);
+        stack.apply_static_config(&ip_config).unwrap();
+
+        assert_eq!(
+            stack.interface().ipv4_addr(),
+            Some(Ipv4Address::new(192, 168, 1, 20))
+        );
+        assert_eq!(
+            stack.dns_servers(),
+            [
+                Ipv4Address::new(9, 9, 9, 9),
+                Ipv4Address::new(1, 1, 1, 1),
+                Ipv4Address::new(8, 8, 8, 8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_static_ip_config_without_gateway() {
+        let static_ip = config::IpConfig {
+            ip: [10, 0, 0, 5],
+            gateway: [0; 4],
+            dns: Vec::new(),
+            subnet_mask: [255, 0, 0, 0],
+        };
+        let ip_config = static_ip_config(&static_ip);
+        assert_eq!(ip_config.prefix_len, 8);
+        assert_eq!(ip_config.gateway, None);
+        assert!(ip_config.dns.is_empty());
+    }
+}
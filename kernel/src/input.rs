@@ -7,13 +7,38 @@ use crate::GLOBAL_STATE;
 use crate::serial;
 use alloc::format;
 use alloc::string::{String, ToString};
-use alloc::vec::Vec;
-use config::{Key, WizardEvent};
+use core::ops::ControlFlow;
+use core::sync::atomic::{AtomicBool, Ordering};
+use config::{
+    encrypt_api_key, ApiKeyProvider, ConfigStorage, EfiConfigStorage, Key, MoteConfig,
+    ProviderConfig, WizardEvent,
+};
 #[cfg(target_arch = "x86_64")]
 use crate::ps2;
-use llm::{GenerationConfig, Message, Role};
+use llm::{FinishReason, GenerationConfig, LlmError, Message, Role};
+use crate::Overlay;
+use tui::screens::{
+    ConfigEvent, ConfigScreen, HelpEvent, HelpScreen, ModelEntry, ModelSelectEvent,
+    ModelSelectScreen, ProviderEntry, ProviderSelectEvent, ProviderSelectScreen,
+};
 use tui::types::Key as TuiKey;
 
+/// Providers whose API key can be edited from the configuration screen
+const CONFIGURABLE_PROVIDERS: [(&str, &str, ApiKeyProvider); 6] = [
+    ("openai", "OpenAI", ApiKeyProvider::OpenAI),
+    ("anthropic", "Anthropic", ApiKeyProvider::Anthropic),
+    ("groq", "Groq", ApiKeyProvider::Groq),
+    ("xai", "xAI", ApiKeyProvider::XAI),
+    ("azure", "Azure OpenAI", ApiKeyProvider::Azure),
+    ("custom", "Custom server", ApiKeyProvider::Custom),
+];
+
+/// Tokens kept free in the context window for the model's response
+const RESPONSE_TOKEN_RESERVE: usize = 1024;
+
+/// Set when Esc is pressed while a response is being generated
+static CANCEL_REQUESTED: AtomicBool = AtomicBool::new(false);
+
 /// Handle keyboard input
 ///
 /// Reads keyboard input and processes it based on the current application state.
@@ -56,6 +81,20 @@ fn read_keyboard() -> Option<Key> {
     read_serial_key()
 }
 
+/// Check for Esc while a response is streaming
+///
+/// The provider call blocks the event loop, so the token callback polls the
+/// keyboard itself. Other keys pressed meanwhile are dropped, as chat input
+/// is ignored during generation anyway.
+fn cancel_requested() -> bool {
+    while let Some(key) = read_keyboard() {
+        if matches!(key, Key::Esc) {
+            CANCEL_REQUESTED.store(true, Ordering::Relaxed);
+        }
+    }
+    CANCEL_REQUESTED.load(Ordering::Relaxed)
+}
+
 fn read_serial_key() -> Option<Key> {
     let byte = serial::read_byte()?;
     // Filter out 0xFF - this is noise when no data is available
@@ -140,15 +179,28 @@ fn process_key(key: Key) {
                     // TODO: Trigger WiFi scan
                     serial::println("Wizard: WiFi scan requested");
                 }
-                WizardEvent::RequestWifiConnect { ssid, password } => {
+                WizardEvent::WifiChosen { ssid, security, .. } => {
                     // TODO: Connect to WiFi
-                    serial::println(&format!("Wizard: WiFi connect to {}", ssid));
+                    serial::println(&format!("Wizard: WiFi connect to {} ({})", ssid, security.label()));
+                }
+                WizardEvent::RequestApiKeyValidation { provider, mut config } => {
+                    // Checked from the event loop once the spinner is on screen
+                    if let Some((id, _, _)) =
+                        CONFIGURABLE_PROVIDERS.iter().find(|(_, _, kind)| *kind == provider)
+                    {
+                        config.preferences.default_provider = id.to_string();
+                        kernel_state.pending_key_check = Some(config);
+                    } else {
+                        kernel_state.wizard.set_api_key_validation(Ok(()));
+                    }
                 }
                 WizardEvent::ConfigReady(config) => {
                     // Save the configuration
                     serial::println("Wizard: Config ready, saving...");
                     kernel_state.config = config;
-                    // TODO: Persist to EFI storage
+                    if let Err(e) = persist_config(kernel_state) {
+                        serial::println(&format!("Wizard: failed to save config: {}", e));
+                    }
                 }
                 WizardEvent::Complete => {
                     // Wizard completed - transition to chat screen
@@ -182,60 +234,39 @@ fn process_key(key: Key) {
         // Convert key to TUI key format
         let tui_key = convert_key(key);
 
+        // While an overlay is open it receives every key
+        if kernel_state.overlay.is_some() {
+            handle_overlay_key(kernel_state, tui_key);
+            return;
+        }
+
         // Handle special function keys
         match tui_key {
             TuiKey::F1 => {
-                // Show help - add help message to chat
-                kernel_state.chat_screen.add_message(
-                    tui::widgets::MessageRole::System,
-                    String::from(
-                        "moteOS Help:\n\
-                        F1: Show this help\n\
-                        F2: Switch LLM provider\n\
-                        F3: Switch model (cycles through models)\n\
-                        F4: Show current config\n\
-                        F9: Start new chat (clears conversation)\n\
-                        F10: Shutdown\n\
-                        PageUp/PageDown: Scroll conversation\n\
-                        Enter: Send message"
-                    ),
-                );
+                // Show the keybinding help
+                kernel_state.overlay = Some(Overlay::Help(HelpScreen::new()));
                 crate::screen::mark_dirty();
             }
             TuiKey::F2 => {
-                // Switch provider
-                switch_provider(kernel_state);
+                // Pick a provider
+                open_provider_select(kernel_state);
             }
             TuiKey::F3 => {
-                // Switch model - cycle through models for current provider
-                switch_model(kernel_state);
+                // Pick a model for the current provider
+                open_model_select(kernel_state);
             }
             TuiKey::F4 => {
-                // Show current config in chat
-                let config_info = format!(
-                    "Current Configuration:\n\
-                    Provider: {}\n\
-                    Model: {}\n\
-                    Temperature: {:.1}\n\
-                    Stream: {}",
-                    kernel_state.current_provider_name,
-                    kernel_state.current_model,
-                    kernel_state.config.preferences.temperature,
-                    if kernel_state.config.preferences.stream_responses { "Yes" } else { "No" }
-                );
-                kernel_state.chat_screen.add_message(
-                    tui::widgets::MessageRole::System,
-                    config_info,
-                );
-                crate::screen::mark_dirty();
+                // Open the configuration screen
+                open_config_screen(kernel_state);
             }
             TuiKey::F9 => {
-                // Clear conversation (new chat)
-                kernel_state.conversation.clear();
-                kernel_state.chat_screen = tui::screens::ChatScreen::new(
-                    kernel_state.current_provider_name.clone(),
-                    kernel_state.current_model.clone(),
-                );
+                // Clear conversation (new chat), archiving the old one
+                kernel_state.new_conversation();
+                crate::screen::mark_dirty();
+            }
+            TuiKey::F12 => {
+                // Toggle the network statistics panel
+                kernel_state.show_net_stats = !kernel_state.show_net_stats;
                 crate::screen::mark_dirty();
             }
             TuiKey::F10 => {
@@ -246,9 +277,9 @@ fn process_key(key: Key) {
                 // Handle message submission through chat screen
                 let event = kernel_state.chat_screen.handle_input(tui_key);
                 if let tui::screens::ChatEvent::MessageSubmitted = event {
-                    let message_text = kernel_state.chat_screen.input().get_text().to_string();
+                    let message_text = kernel_state.chat_screen.last_submitted().to_string();
                     if !message_text.trim().is_empty() {
-                        send_message(kernel_state, message_text);
+                        submit_message(kernel_state, message_text);
                     }
                 }
             }
@@ -257,11 +288,14 @@ fn process_key(key: Key) {
                 let event = kernel_state.chat_screen.handle_input(tui_key);
                 match event {
                     tui::screens::ChatEvent::MessageSubmitted => {
-                        let message_text = kernel_state.chat_screen.input().get_text().to_string();
+                        let message_text = kernel_state.chat_screen.last_submitted().to_string();
                         if !message_text.trim().is_empty() {
-                            send_message(kernel_state, message_text);
+                            submit_message(kernel_state, message_text);
                         }
                     }
+                    tui::screens::ChatEvent::CyclePersona => {
+                        cycle_persona(kernel_state);
+                    }
                     _ => {
                         // Other events are handled by the chat screen itself
                     }
@@ -271,91 +305,351 @@ fn process_key(key: Key) {
     }
 }
 
-/// Switch to a different model for the current provider
+/// Check the API key entered in the setup wizard, if a check is pending
 ///
-/// Cycles through available models for the current LLM provider.
-fn switch_model(kernel_state: &mut crate::KernelState) {
-    let models = kernel_state.current_provider.models();
-    if models.is_empty() {
-        // No models available
-        kernel_state.chat_screen.add_message(
-            tui::widgets::MessageRole::System,
-            String::from("No models available for this provider."),
-        );
-        crate::screen::mark_dirty();
+/// Makes a small request to the provider and hands the result back to the
+/// wizard. Called from the event loop after the screen update, so the
+/// "checking" spinner is visible while the request blocks.
+pub fn run_pending_key_check() {
+    let mut state = GLOBAL_STATE.lock();
+    let Some(ref mut kernel_state) = *state else {
         return;
+    };
+    let Some(config) = kernel_state.pending_key_check.take() else {
+        return;
+    };
+
+    let result = crate::init::init_provider(&config, kernel_state.network.as_mut()).and_then(
+        |(client, name, _)| {
+            client
+                .validate_api_key()
+                .map_err(|e| describe_key_check_error(&name, &e))
+        },
+    );
+    if let Err(ref e) = result {
+        serial::println(&format!("Wizard: API key check failed: {}", e));
     }
 
-    // Find current model index
-    let current_idx = models
+    kernel_state.wizard.set_api_key_validation(result);
+    crate::screen::mark_dirty();
+}
+
+/// Explain why an API key check failed
+fn describe_key_check_error(provider: &str, error: &LlmError) -> String {
+    match error {
+        LlmError::RetriesExhausted { last, .. } => describe_key_check_error(provider, last),
+        LlmError::NetworkError(_) | LlmError::Timeout | LlmError::Transport { .. } => {
+            format!("Could not reach {} - check the network connection", provider)
+        }
+        LlmError::InvalidApiKey => {
+            format!("API key rejected by {}", provider)
+        }
+        other => format!("{} check failed: {}", provider, other),
+    }
+}
+
+/// List the cloud providers, marking those with a stored API key
+fn provider_entries(config: &mut MoteConfig) -> alloc::vec::Vec<ProviderEntry> {
+    CONFIGURABLE_PROVIDERS
         .iter()
-        .position(|m| m.id == kernel_state.current_model)
-        .unwrap_or(0);
+        .map(|(id, label, _)| {
+            let configured = provider_slot(config, id).is_some_and(|slot| slot.is_some());
+            ProviderEntry::new(id, label, configured)
+        })
+        .collect()
+}
 
-    // Cycle to next model
-    let next_idx = (current_idx + 1) % models.len();
-    let next_model = &models[next_idx];
+/// Open the configuration screen over the chat
+fn open_config_screen(kernel_state: &mut crate::KernelState) {
+    let providers = provider_entries(&mut kernel_state.config);
+    kernel_state.overlay = Some(Overlay::Config(ConfigScreen::new(providers)));
+    crate::screen::mark_dirty();
+}
 
-    // Update model
-    kernel_state.current_model = next_model.id.to_string();
-    kernel_state.chat_screen.set_model(kernel_state.current_model.clone());
+/// Open the provider selection screen over the chat
+fn open_provider_select(kernel_state: &mut crate::KernelState) {
+    let providers = provider_entries(&mut kernel_state.config);
+    let current = kernel_state.config.preferences.default_provider.clone();
+    kernel_state.overlay = Some(Overlay::ProviderSelect(ProviderSelectScreen::new(
+        providers, &current,
+    )));
+    crate::screen::mark_dirty();
+}
+
+/// Route a key to the open overlay
+fn handle_overlay_key(kernel_state: &mut crate::KernelState, key: TuiKey) {
+    match kernel_state.overlay.as_mut() {
+        Some(Overlay::Help(help)) => {
+            if help.handle_input(key) == HelpEvent::Close {
+                kernel_state.overlay = None;
+            }
+        }
+        Some(Overlay::ProviderSelect(provider_select)) => match provider_select.handle_input(key) {
+            ProviderSelectEvent::Selected(provider) => {
+                kernel_state.overlay = None;
+                switch_provider(kernel_state, &provider);
+            }
+            ProviderSelectEvent::Close => kernel_state.overlay = None,
+            ProviderSelectEvent::None => {}
+        },
+        Some(Overlay::ModelSelect(model_select)) => match model_select.handle_input(key) {
+            ModelSelectEvent::Selected(model) => {
+                kernel_state.overlay = None;
+                select_model(kernel_state, model);
+            }
+            ModelSelectEvent::Close => kernel_state.overlay = None,
+            ModelSelectEvent::None => {}
+        },
+        Some(Overlay::Config(config_screen)) => {
+            let event = config_screen.handle_input(key);
+            handle_config_event(kernel_state, event);
+        }
+        None => return,
+    }
+
+    crate::screen::mark_dirty();
+}
+
+/// Act on an event from the configuration screen
+fn handle_config_event(kernel_state: &mut crate::KernelState, event: ConfigEvent) {
+    match event {
+        ConfigEvent::ApiKeySet { provider, key } => {
+            let result = set_api_key(kernel_state, &provider, &key);
+            if let Some(Overlay::Config(config_screen)) = kernel_state.overlay.as_mut() {
+                match result {
+                    Ok(()) => {
+                        config_screen.set_configured(&provider, true);
+                        config_screen.set_notice(String::from("API key saved"));
+                    }
+                    Err(e) => {
+                        config_screen.set_notice(format!("Could not save: {}", e));
+                    }
+                }
+            }
+        }
+        ConfigEvent::Close => {
+            kernel_state.overlay = None;
+        }
+        ConfigEvent::None => {}
+    }
+}
+
+/// Get the config slot for a cloud provider by id
+fn provider_slot<'a>(
+    config: &'a mut MoteConfig,
+    provider: &str,
+) -> Option<&'a mut Option<ProviderConfig>> {
+    match provider {
+        "openai" => Some(&mut config.providers.openai),
+        "anthropic" => Some(&mut config.providers.anthropic),
+        "groq" => Some(&mut config.providers.groq),
+        "xai" => Some(&mut config.providers.xai),
+        "azure" => Some(&mut config.providers.azure),
+        "custom" => Some(&mut config.providers.custom),
+        _ => None,
+    }
+}
+
+/// Store a new API key for a provider and persist the configuration
+///
+/// The key is applied to the in-memory config even if saving fails, and the
+/// active provider is re-initialized so the new key takes effect immediately.
+fn set_api_key(
+    kernel_state: &mut crate::KernelState,
+    provider: &str,
+    key: &str,
+) -> Result<(), String> {
+    let encrypted = encrypt_api_key(key).map_err(|e| format!("{:?}", e))?;
+    let default_model = CONFIGURABLE_PROVIDERS
+        .iter()
+        .find(|(id, _, _)| *id == provider)
+        .map(|(_, _, kind)| kind.default_model())
+        .unwrap_or("");
+    let slot = provider_slot(&mut kernel_state.config, provider)
+        .ok_or_else(|| format!("Unknown provider: {}", provider))?;
+    match slot {
+        Some(existing) => existing.api_key_encrypted = encrypted,
+        None => *slot = Some(ProviderConfig::new(encrypted, String::from(default_model))),
+    }
+
+    if kernel_state.config.preferences.default_provider == provider {
+        if let Ok((client, name, model)) =
+            crate::init::init_provider(&kernel_state.config, kernel_state.network.as_mut())
+        {
+            kernel_state.current_provider = client;
+            kernel_state.current_provider_name = name.clone();
+            kernel_state.current_model = model.clone();
+            kernel_state.chat_screen.set_provider(name);
+            kernel_state.chat_screen.set_model(model);
+        }
+    }
+
+    persist_config(kernel_state)
+}
+
+/// Save the configuration through the EFI config storage
+fn persist_config(kernel_state: &crate::KernelState) -> Result<(), String> {
+    let mut storage = EfiConfigStorage::new(kernel_state.system_table);
+    storage
+        .save(&kernel_state.config.to_value())
+        .map_err(|e| format!("{:?}", e))
+}
+
+/// Open the model selection screen for the current provider
+///
+/// Asks the provider for its live model list first; the client caches it,
+/// and the built-in list is used when offline or the request fails.
+fn open_model_select(kernel_state: &mut crate::KernelState) {
+    if let Some(stack) = network::get_network_stack().as_mut() {
+        if let Err(e) = kernel_state.current_provider.fetch_models(stack) {
+            serial::println(&format!("Models: using built-in list ({})", e));
+        }
+    }
+
+    let models = kernel_state
+        .current_provider
+        .models()
+        .iter()
+        .map(|m| ModelEntry::new(&m.id, &m.name, m.context_length))
+        .collect();
+    kernel_state.overlay = Some(Overlay::ModelSelect(ModelSelectScreen::new(
+        models,
+        &kernel_state.current_model,
+    )));
+    crate::screen::mark_dirty();
+}
+
+/// Make `model` the active model and show it in the chat header
+fn select_model(kernel_state: &mut crate::KernelState, model: String) {
+    let name = kernel_state
+        .current_provider
+        .models()
+        .iter()
+        .find(|m| m.id == model)
+        .map(|m| m.name.clone())
+        .unwrap_or_else(|| model.clone());
 
-    // Notify user
-    let msg = format!("Switched to model: {}", next_model.name);
+    kernel_state.current_model = model;
+    kernel_state.chat_screen.set_model(kernel_state.current_model.clone());
     kernel_state.chat_screen.add_message(
         tui::widgets::MessageRole::System,
-        msg,
+        format!("Switched to model: {}", name),
     );
     crate::screen::mark_dirty();
 }
 
-/// Switch to a different LLM provider
+/// Make `provider` the active LLM provider
 ///
-/// Cycles through available providers or allows selection.
-fn switch_provider(kernel_state: &mut crate::KernelState) {
-    // TODO: Implement provider switching UI
-    // For now, just cycle through available providers
-    let providers = ["openai", "anthropic", "groq", "xai"];
-    let current_idx = providers
-        .iter()
-        .position(|p| *p == kernel_state.current_provider_name.to_lowercase())
-        .unwrap_or(0);
-    let next_idx = (current_idx + 1) % providers.len();
-    let next_provider = providers[next_idx];
-
-    // Temporarily update config to use the next provider
+/// Rebuilds the client from the stored configuration. On success the choice
+/// becomes the default and is persisted; on failure the current provider is
+/// kept and the error is shown in the status bar.
+fn switch_provider(kernel_state: &mut crate::KernelState, provider: &str) {
     let mut temp_config = kernel_state.config.clone();
-    temp_config.preferences.default_provider = next_provider.to_string();
+    temp_config.preferences.default_provider = provider.to_string();
 
-    // Try to initialize the next provider
     match crate::init::init_provider(&temp_config, kernel_state.network.as_mut()) {
-        Ok((provider, name, model)) => {
-            kernel_state.current_provider = provider;
+        Ok((client, name, model)) => {
+            kernel_state.current_provider = client;
             kernel_state.current_provider_name = name.clone();
             kernel_state.current_model = model.clone();
             kernel_state.chat_screen.set_provider(name.clone());
             kernel_state.chat_screen.set_model(model.clone());
-            // Update config to persist the change
-            kernel_state.config.preferences.default_provider = next_provider.to_string();
+            kernel_state
+                .chat_screen
+                .set_status(tui::screens::ConnectionStatus::Connected);
+
+            kernel_state.config.preferences.default_provider = provider.to_string();
+            if let Err(e) = persist_config(kernel_state) {
+                serial::println(&format!("Config: could not save provider choice ({})", e));
+            }
 
-            // Notify user
-            let msg = format!("Switched to provider: {} ({})", name, model);
             kernel_state.chat_screen.add_message(
                 tui::widgets::MessageRole::System,
-                msg,
+                format!("Switched to provider: {} ({})", name, model),
             );
-            crate::screen::mark_dirty();
         }
         Err(e) => {
-            // Failed to switch - notify user
-            let msg = format!("Failed to switch to {}: {}", next_provider, e);
             kernel_state.chat_screen.add_message(
                 tui::widgets::MessageRole::System,
-                msg,
+                format!("Failed to switch to {}: {}", provider, e),
             );
-            crate::screen::mark_dirty();
+            kernel_state
+                .chat_screen
+                .set_status(tui::screens::ConnectionStatus::Error(e.to_string()));
         }
     }
+    crate::screen::mark_dirty();
+}
+
+/// Switch to the next persona and start a new conversation with it
+///
+/// Cycles through the configured personas and then back to the default
+/// system prompt.
+fn cycle_persona(kernel_state: &mut crate::KernelState) {
+    let count = kernel_state.config.preferences.personas.len();
+    if count == 0 {
+        kernel_state.chat_screen.add_message(
+            tui::widgets::MessageRole::System,
+            String::from("No personas configured - add them under [preferences.personas]"),
+        );
+        crate::screen::mark_dirty();
+        return;
+    }
+
+    kernel_state.persona = match kernel_state.persona {
+        None => Some(0),
+        Some(i) if i + 1 < count => Some(i + 1),
+        Some(_) => None,
+    };
+    kernel_state.new_conversation();
+
+    let name = match kernel_state.persona {
+        Some(i) => kernel_state.config.preferences.personas[i].name.clone(),
+        None => String::from("default"),
+    };
+    kernel_state.chat_screen.add_message(
+        tui::widgets::MessageRole::System,
+        format!("Persona: {} (new conversation)", name),
+    );
+    crate::screen::mark_dirty();
+}
+
+/// Run a chat command, or send the text to the model
+///
+/// Commands are debugging aids and are not documented in the help screen.
+fn submit_message(kernel_state: &mut crate::KernelState, text: String) {
+    let mut words = text.split_whitespace();
+    match words.next() {
+        Some("/ping") => {
+            let target = words.next().unwrap_or("1.1.1.1");
+            let count = words.next().and_then(|n| n.parse().ok()).unwrap_or(4);
+            run_ping(kernel_state, target, count);
+        }
+        _ => send_message(kernel_state, text),
+    }
+}
+
+/// Ping `target` and show the results in the chat
+fn run_ping(kernel_state: &mut crate::KernelState, target: &str, count: u16) {
+    kernel_state.chat_screen.add_message(
+        tui::widgets::MessageRole::User,
+        format!("/ping {} {}", target, count),
+    );
+    let reply = match kernel_state.network.as_mut() {
+        None => String::from("Ping: no network"),
+        Some(stack) => match crate::init::ping_host(stack, target, count) {
+            Ok(stats) => {
+                let rtts: alloc::vec::Vec<String> =
+                    stats.rtts_ms.iter().map(|rtt| format!("{} ms", rtt)).collect();
+                format!("Ping {}: {}\nReplies: {}", target, stats, rtts.join(", "))
+            }
+            Err(e) => format!("Ping {} failed: {}", target, e),
+        },
+    };
+    kernel_state
+        .chat_screen
+        .add_message(tui::widgets::MessageRole::Assistant, reply);
+    crate::screen::mark_dirty();
 }
 
 /// Send a message to the LLM
@@ -382,8 +676,25 @@ fn send_message(kernel_state: &mut crate::KernelState, text: String) {
         .chat_screen
         .add_message(tui::widgets::MessageRole::User, text.clone());
 
+    // The prompt may have changed since the conversation started
+    let system_prompt = String::from(kernel_state.system_prompt());
+    llm::context::apply_system_prompt(&mut kernel_state.conversation, &system_prompt);
+
+    // Drop the oldest messages if the conversation outgrew the model
+    let removed = trim_conversation(kernel_state);
+    if removed > 0 {
+        kernel_state.chat_screen.add_message(
+            tui::widgets::MessageRole::System,
+            format!(
+                "Conversation trimmed: {} older message(s) no longer sent to the model",
+                removed
+            ),
+        );
+    }
+
     // Mark as generating
     kernel_state.is_generating = true;
+    kernel_state.chat_screen.set_generating(true);
     kernel_state
         .chat_screen
         .set_status(tui::screens::ConnectionStatus::Connected);
@@ -396,20 +707,41 @@ fn send_message(kernel_state: &mut crate::KernelState, text: String) {
 
     // Generate response with streaming
     let mut response_text = String::new();
+    let preferences = &kernel_state.config.preferences;
     let config = GenerationConfig {
-        temperature: kernel_state.config.preferences.temperature,
+        temperature: preferences.temperature,
         max_tokens: None,
-        stop_sequences: Vec::new(),
-        top_p: None,
+        stop_sequences: preferences.stop_sequences.clone(),
+        top_p: preferences.top_p,
         top_k: None,
+        presence_penalty: preferences.presence_penalty,
+        frequency_penalty: preferences.frequency_penalty,
+        response_format: None,
     };
 
+    CANCEL_REQUESTED.store(false, Ordering::Relaxed);
     let mut on_token = |token: &str| {
         // Stream token to chat screen
         response_text.push_str(token);
         kernel_state
             .chat_screen
             .update_last_message(&response_text);
+
+        // The event loop is blocked until the response completes, so the
+        // spinner is advanced per token here
+        if kernel_state.chat_screen.tick_spinner() {
+            kernel_state
+                .chat_screen
+                .render_header_only(&mut kernel_state.screen);
+            kernel_state.screen.present();
+        }
+
+        // Esc stops the response here, keeping what has arrived so far
+        if cancel_requested() {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
     };
     let result = kernel_state.current_provider.complete(
         &kernel_state.conversation,
@@ -420,6 +752,8 @@ fn send_message(kernel_state: &mut crate::KernelState, text: String) {
 
     // Mark as no longer generating
     kernel_state.is_generating = false;
+    kernel_state.chat_screen.set_generating(false);
+    crate::screen::mark_dirty();
 
     // Handle result
     match result {
@@ -429,19 +763,132 @@ fn send_message(kernel_state: &mut crate::KernelState, text: String) {
                 Role::Assistant,
                 completion_result.text.clone(),
             ));
+            if completion_result.finish_reason == FinishReason::Cancelled {
+                kernel_state.chat_screen.add_message(
+                    tui::widgets::MessageRole::System,
+                    String::from("Response cancelled"),
+                );
+            }
+
+            // Track token usage for the status bar
+            if let Some(usage) = completion_result.usage {
+                kernel_state
+                    .chat_screen
+                    .add_token_usage(usage.prompt_tokens, usage.completion_tokens);
+
+                // Add the estimated cost to the session total, when priced
+                let cost_micros = kernel_state
+                    .current_provider
+                    .models()
+                    .iter()
+                    .find(|model| model.id == kernel_state.current_model)
+                    .and_then(|model| model.cost_micros(&usage));
+                if let Some(cost_micros) = cost_micros {
+                    kernel_state.cumulative_cost_usd_micros = kernel_state
+                        .cumulative_cost_usd_micros
+                        .saturating_add(cost_micros);
+                    kernel_state
+                        .chat_screen
+                        .set_session_cost(kernel_state.cumulative_cost_usd_micros);
+                }
+                crate::screen::mark_dirty();
+            }
 
             // Update status
             kernel_state
                 .chat_screen
                 .set_status(tui::screens::ConnectionStatus::Connected);
+
+            // Name the conversation after its first exchange
+            if kernel_state.title.is_none() {
+                generate_title(kernel_state);
+            }
+            kernel_state.save_conversation();
         }
         Err(e) => {
-            // Show error
-            let error_msg = format!("Error: {:?}", e);
+            // Explain the failure in the conversation and flag it in the header
+            kernel_state.chat_screen.add_message(
+                tui::widgets::MessageRole::System,
+                describe_llm_error(&e),
+            );
             kernel_state
                 .chat_screen
-                .set_status(tui::screens::ConnectionStatus::Error(error_msg));
+                .set_status(tui::screens::ConnectionStatus::Error(e.to_string()));
+            crate::screen::mark_dirty();
+        }
+    }
+}
+
+/// Ask the provider for a conversation title and show it in the header
+///
+/// Failures are ignored; the header keeps its default title and the next
+/// exchange tries again.
+fn generate_title(kernel_state: &mut crate::KernelState) {
+    let Some(first_user_message) = kernel_state
+        .conversation
+        .iter()
+        .find(|message| message.role == Role::User)
+        .map(|message| message.content.text().into_owned())
+    else {
+        return;
+    };
+
+    if let Ok(title) = kernel_state
+        .current_provider
+        .generate_title(&kernel_state.current_model, &first_user_message)
+    {
+        kernel_state.chat_screen.set_title(title.clone());
+        kernel_state.title = Some(title);
+        crate::screen::mark_dirty();
+    }
+}
+
+/// Trim the conversation to the current model's context window
+///
+/// Leaves room for the response. Models without a known context length are
+/// left alone. Returns the number of messages removed.
+fn trim_conversation(kernel_state: &mut crate::KernelState) -> usize {
+    let context_length = kernel_state
+        .current_provider
+        .models()
+        .iter()
+        .find(|m| m.id == kernel_state.current_model)
+        .map_or(0, |m| m.context_length);
+    if context_length == 0 {
+        return 0;
+    }
+
+    let limit = context_length.saturating_sub(RESPONSE_TOKEN_RESERVE);
+    llm::context::truncate_to_fit(&mut kernel_state.conversation, limit)
+}
+
+/// Turn a provider error into a message that tells the user what to do next
+fn describe_llm_error(error: &LlmError) -> String {
+    match error {
+        LlmError::RetriesExhausted { last, .. } => describe_llm_error(last),
+        LlmError::InvalidApiKey => String::from("API key rejected — press F4 to update"),
+        LlmError::RateLimited { retry_after_ms: Some(ms) } => format!(
+            "Rate limited by the provider — try again in {} s",
+            ms.div_ceil(1000)
+        ),
+        LlmError::RateLimited {
+            retry_after_ms: None,
+        } => String::from("Rate limited by the provider — wait a moment and try again"),
+        LlmError::ContextLengthExceeded => {
+            String::from("Conversation is too long for this model — press F9 to start a new chat")
+        }
+        LlmError::ModelNotFound | LlmError::InvalidModel(_) => {
+            String::from("Model not available — press F3 to pick another model")
+        }
+        LlmError::ServerError(_) => {
+            String::from("The provider is having problems — try again later or press F2 to switch")
         }
+        LlmError::Transport { code, .. } => format!(
+            "Network error {} — {}",
+            network::error_code_label(*code),
+            network::error_hint(*code)
+        ),
+        other => format!("Request failed: {}", other),
     }
 }
 
@@ -1,5 +1,5 @@
 #![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_main)]
 
 //! moteOS Kernel - Main entry point and event loop
 //!
@@ -19,7 +19,14 @@ use alloc::string::String;
 #[cfg(not(feature = "uefi-minimal"))]
 use alloc::vec::Vec;
 #[cfg(not(feature = "uefi-minimal"))]
-use config::{decrypt_api_key, ConfigStorage, EfiConfigStorage, MoteConfig, SetupWizard};
+use config::{
+    decrypt_api_key, ConfigStorage, EfiConfigStorage, MoteConfig, RuntimeSystemTable, SetupWizard,
+};
+#[cfg(not(feature = "uefi-minimal"))]
+use config::{
+    ConversationStore, StoredMessage, StoredRole, ARCHIVE_VARIABLE_NAME,
+    CONVERSATION_VARIABLE_NAME,
+};
 use core::panic::PanicInfo;
 #[cfg(not(feature = "uefi-minimal"))]
 use llm::{GenerationConfig, LlmProvider, Message, Role};
@@ -32,7 +39,7 @@ use shared::{BootInfo, Color, Rect};
 #[cfg(not(feature = "uefi-minimal"))]
 use spin::Mutex;
 #[cfg(not(feature = "uefi-minimal"))]
-use tui::{screens::ChatScreen, Screen, Theme, DARK_THEME, LIGHT_THEME};
+use tui::{screens::{ChatScreen, ConfigScreen, HelpScreen, ModelSelectScreen, ProviderSelectScreen}, Screen};
 #[cfg(not(feature = "uefi-minimal"))]
 use tui::font::Font;
 
@@ -61,7 +68,7 @@ impl LlmProvider for NullProvider {
         _messages: &[Message],
         _model: &str,
         _config: &GenerationConfig,
-        _on_token: &mut dyn FnMut(&str),
+        _on_token: &mut dyn FnMut(&str) -> core::ops::ControlFlow<()>,
     ) -> Result<CompletionResult, LlmError> {
         Err(LlmError::Other(String::from(
             "LLM provider not configured",
@@ -85,6 +92,9 @@ pub mod input;
 #[cfg(target_arch = "x86_64")]
 pub mod ps2;
 #[cfg(not(feature = "uefi-minimal"))]
+#[cfg(target_arch = "x86_64")]
+pub mod rtc;
+#[cfg(not(feature = "uefi-minimal"))]
 pub mod screen;
 #[cfg(all(not(feature = "uefi-minimal"), feature = "full-tls"))]
 pub mod tls_test;
@@ -116,12 +126,43 @@ pub struct KernelState {
     pub chat_screen: ChatScreen,
     /// Current conversation messages
     pub conversation: Vec<Message>,
+    /// Short title of the conversation, generated after the first exchange
+    pub title: Option<String>,
+    /// Estimated spend across all conversations this session, in micro-dollars
+    pub cumulative_cost_usd_micros: u64,
     /// Whether setup has been completed
     pub setup_complete: bool,
     /// Whether we're currently generating a response
     pub is_generating: bool,
     /// Setup wizard (used during initial configuration)
     pub wizard: SetupWizard,
+    /// Dialog shown over the chat screen, if any
+    pub overlay: Option<Overlay>,
+    /// Config whose API key the setup wizard is waiting to have checked
+    pub pending_key_check: Option<MoteConfig>,
+    /// Index of the active persona in `config.preferences.personas`, or
+    /// `None` for the default system prompt
+    pub persona: Option<usize>,
+    /// Whether the network counters are shown in the corner (F12)
+    pub show_net_stats: bool,
+    /// UEFI system table for EFI variable storage; `None` when the firmware
+    /// gave none, in which case nothing is persisted
+    pub system_table: Option<RuntimeSystemTable>,
+}
+
+/// Dialog drawn over the chat screen
+///
+/// While an overlay is open it receives every key press.
+#[cfg(not(feature = "uefi-minimal"))]
+pub enum Overlay {
+    /// Keybinding help (F1)
+    Help(HelpScreen),
+    /// Provider selection (F2)
+    ProviderSelect(ProviderSelectScreen),
+    /// Model selection (F3)
+    ModelSelect(ModelSelectScreen),
+    /// API key configuration (F4)
+    Config(ConfigScreen),
 }
 
 #[cfg(not(feature = "uefi-minimal"))]
@@ -137,7 +178,7 @@ impl KernelState {
         setup_complete: bool,
     ) -> Self {
         let chat_screen = ChatScreen::new(provider_name.clone(), model.clone());
-        Self {
+        let mut state = Self {
             screen,
             network,
             config,
@@ -146,10 +187,150 @@ impl KernelState {
             current_model: model,
             chat_screen,
             conversation: Vec::new(),
+            title: None,
+            cumulative_cost_usd_micros: 0,
             setup_complete,
             is_generating: false,
             wizard: SetupWizard::new(),
+            overlay: None,
+            pending_key_check: None,
+            persona: None,
+            show_net_stats: false,
+            system_table: None,
+        };
+        state.reset_conversation();
+        state
+    }
+
+    /// Persist through EFI variables reached via `system_table`
+    pub fn with_system_table(mut self, system_table: Option<RuntimeSystemTable>) -> Self {
+        self.system_table = system_table;
+        self
+    }
+
+    /// System prompt of the active persona, or the configured default
+    pub fn system_prompt(&self) -> &str {
+        let preferences = &self.config.preferences;
+        match self.persona.and_then(|i| preferences.personas.get(i)) {
+            Some(persona) => &persona.system_prompt,
+            None => &preferences.system_prompt,
+        }
+    }
+
+    /// Start a new conversation, seeded with the active system prompt
+    pub fn reset_conversation(&mut self) {
+        self.conversation.clear();
+        self.title = None;
+        let prompt = self.system_prompt();
+        if !prompt.trim().is_empty() {
+            let message = Message::new(Role::System, String::from(prompt));
+            self.conversation.push(message);
+        }
+        self.chat_screen = ChatScreen::new(
+            self.current_provider_name.clone(),
+            self.current_model.clone(),
+        );
+        self.chat_screen.set_session_cost(self.cumulative_cost_usd_micros);
+        self.chat_screen.set_clock(shared::timer::wall_clock_secs);
+    }
+
+    /// Archive the current conversation if configured, then start a new one
+    pub fn new_conversation(&mut self) {
+        if self.config.preferences.archive_conversations {
+            self.store_conversation(ARCHIVE_VARIABLE_NAME);
+        }
+        self.reset_conversation();
+        self.save_conversation();
+    }
+
+    /// Save the conversation so it survives a reboot
+    pub fn save_conversation(&self) {
+        self.store_conversation(CONVERSATION_VARIABLE_NAME);
+    }
+
+    /// Restore the conversation saved before the last reboot
+    ///
+    /// # Returns
+    /// The number of messages restored
+    pub fn restore_conversation(&mut self) -> usize {
+        let Some(store) = self.conversation_store(CONVERSATION_VARIABLE_NAME) else {
+            return 0;
+        };
+        let stored = match store.load() {
+            Ok(stored) => stored,
+            Err(e) => {
+                serial::println(&alloc::format!("moteOS: saved conversation ignored: {:?}", e));
+                return 0;
+            }
+        };
+        if stored.is_empty() {
+            return 0;
+        }
+
+        self.conversation = stored
+            .into_iter()
+            .map(|message| {
+                let role = match message.role {
+                    StoredRole::System => Role::System,
+                    StoredRole::User => Role::User,
+                    StoredRole::Assistant => Role::Assistant,
+                };
+                Message::new(role, message.content)
+            })
+            .collect();
+        for message in &self.conversation {
+            let role = match message.role {
+                Role::System => continue,
+                Role::User => tui::widgets::MessageRole::User,
+                Role::Assistant => tui::widgets::MessageRole::Assistant,
+            };
+            self.chat_screen
+                .add_message(role, message.content.text().into_owned());
         }
+        self.conversation.len()
+    }
+
+    /// Write the conversation to the EFI variable `variable`
+    fn store_conversation(&self, variable: &'static str) {
+        let Some(mut store) = self.conversation_store(variable) else {
+            return;
+        };
+        let messages: Vec<StoredMessage> = self
+            .conversation
+            .iter()
+            .map(|message| {
+                let role = match message.role {
+                    Role::System => StoredRole::System,
+                    Role::User => StoredRole::User,
+                    Role::Assistant => StoredRole::Assistant,
+                };
+                StoredMessage::new(role, message.content.text().into_owned())
+            })
+            .collect();
+        match store.save(&messages) {
+            Ok(0) => {}
+            Ok(dropped) => serial::println(&alloc::format!(
+                "moteOS: {} old messages left out of the saved conversation",
+                dropped
+            )),
+            Err(e) => serial::println(&alloc::format!(
+                "moteOS: failed to save conversation: {:?}",
+                e
+            )),
+        }
+    }
+
+    /// Conversation store for `variable`; none when saving is disabled
+    fn conversation_store(
+        &self,
+        variable: &'static str,
+    ) -> Option<ConversationStore<EfiConfigStorage>> {
+        let max_bytes = self.config.preferences.history_max_bytes;
+        if max_bytes == 0 {
+            return None;
+        }
+        let storage = EfiConfigStorage::with_variable_name(self.system_table, variable);
+        Some(ConversationStore::new(storage, max_bytes))
     }
 }
 
@@ -196,6 +377,10 @@ pub extern "C" fn kernel_main(boot_info: BootInfo) -> ! {
     // Initialize heap allocator
     init::init_heap(boot_info.heap_start, boot_info.heap_size);
     serial::println("moteOS: heap ok");
+    init::init_dma(&boot_info);
+    init::init_pci(&boot_info);
+
+    init::init_wall_clock(boot_info.boot_time_unix);
 
     // Initialize PS/2 keyboard driver
     serial::println("moteOS: initializing PS/2...");
@@ -205,21 +390,41 @@ pub extern "C" fn kernel_main(boot_info: BootInfo) -> ! {
 
     // Load configuration
     serial::println("moteOS: loading config...");
-    let config_storage = EfiConfigStorage::new(None);
+    // SAFETY: the bootloader passes the table `exit_boot_services` returned
+    let system_table = boot_info
+        .system_table_addr
+        .and_then(|addr| unsafe { config::runtime_system_table(addr) });
+    let config_storage = EfiConfigStorage::new(system_table);
     let setup_complete = config_storage.exists();
     let config = match config_storage.load() {
-        Ok(Some(_value)) => {
-            // TODO: Deserialize config value into MoteConfig.
+        Ok(Some(value)) => MoteConfig::from_value(&value).unwrap_or_else(|err| {
+            serial::println(&alloc::format!(
+                "moteOS: config unreadable, using defaults: {:?}",
+                err
+            ));
             MoteConfig::default()
-        }
+        }),
         Ok(None) | Err(_) => MoteConfig::default(),
     };
+    // Shown in the chat once the screen is up
+    let config_problems: Vec<String> = match config.validate() {
+        Ok(()) => Vec::new(),
+        Err(problems) => problems
+            .into_iter()
+            .map(|problem| match problem {
+                config::ConfigError::Validation(msg) => msg,
+                other => alloc::format!("{:?}", other),
+            })
+            .collect(),
+    };
+    for problem in &config_problems {
+        serial::println(&alloc::format!("moteOS: config problem: {}", problem));
+    }
+    #[cfg(feature = "full-tls")]
+    init::configure_tls(&config);
 
     // Initialize framebuffer and screen
-    let theme = match config.preferences.theme {
-        config::ThemeChoice::Dark => &DARK_THEME,
-        config::ThemeChoice::Light => &LIGHT_THEME,
-    };
+    let theme = screen::build_theme(&config.preferences);
     let mut screen = unsafe { Screen::new(boot_info.framebuffer.into(), theme) };
     if let Ok(font) = unsafe { Font::load_psf(DEFAULT_FONT_BYTES) } {
         // Leak the font to keep a 'static reference for the screen.
@@ -232,6 +437,12 @@ pub extern "C" fn kernel_main(boot_info: BootInfo) -> ! {
     // Initialize network (if configured)
     serial::println("moteOS: initializing network...");
     let mut network = init::init_network(&config).ok();
+    let mut connectivity = None;
+    if let Some(stack) = network.as_mut() {
+        init::sync_network_time(&config, stack);
+        connectivity = init::check_connectivity(stack);
+    }
+    init::enable_network_interrupts();
     serial::println("moteOS: network init done");
 
     // Initialize LLM provider
@@ -252,15 +463,18 @@ pub extern "C" fn kernel_main(boot_info: BootInfo) -> ! {
     serial::println("moteOS: setting up global state...");
     {
         let mut state = GLOBAL_STATE.lock();
-        *state = Some(KernelState::new(
-            screen,
-            network,
-            config,
-            provider,
-            provider_name,
-            model,
-            setup_complete,
-        ));
+        *state = Some(
+            KernelState::new(
+                screen,
+                network,
+                config,
+                provider,
+                provider_name,
+                model,
+                setup_complete,
+            )
+            .with_system_table(system_table),
+        );
     }
 
     // Seed the chat UI with a brief welcome so the screen isn't empty.
@@ -271,7 +485,30 @@ pub extern "C" fn kernel_main(boot_info: BootInfo) -> ! {
                 tui::widgets::MessageRole::Assistant,
                 String::from("Welcome to moteOS. Type a message to get started."),
             );
-            if let Some(err) = provider_error {
+            let restored = kernel_state.restore_conversation();
+            if restored > 0 {
+                serial::println(&alloc::format!(
+                    "moteOS: restored {} messages from the last session",
+                    restored
+                ));
+            }
+            if let Some(network::Connectivity::CaptivePortal(location)) = connectivity {
+                // Providers can't be reached until someone logs in, so
+                // explain that rather than showing their TLS failures
+                kernel_state
+                    .chat_screen
+                    .set_status(tui::screens::ConnectionStatus::Error(String::from(
+                        "captive portal",
+                    )));
+                let mut message =
+                    String::from("Captive portal detected — this network requires browser login");
+                if let Some(location) = location {
+                    message.push_str(&alloc::format!(" ({})", location));
+                }
+                kernel_state
+                    .chat_screen
+                    .add_message(tui::widgets::MessageRole::Assistant, message);
+            } else if let Some(err) = provider_error {
                 kernel_state
                     .chat_screen
                     .set_status(tui::screens::ConnectionStatus::Error(err));
@@ -282,6 +519,17 @@ pub extern "C" fn kernel_main(boot_info: BootInfo) -> ! {
                     ),
                 );
             }
+            if !config_problems.is_empty() {
+                let mut message =
+                    String::from("The saved configuration has problems; open Config (F4) to fix:");
+                for problem in &config_problems {
+                    message.push_str("\n- ");
+                    message.push_str(problem);
+                }
+                kernel_state
+                    .chat_screen
+                    .add_message(tui::widgets::MessageRole::System, message);
+            }
         }
     }
 
@@ -293,7 +541,8 @@ pub extern "C" fn kernel_main(boot_info: BootInfo) -> ! {
 /// Panic handler
 ///
 /// Called when the kernel panics. Prints panic information to the
-/// framebuffer and halts the CPU.
+/// framebuffer and halts the CPU. Host tests use std's.
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     // TODO: Print panic message to framebuffer
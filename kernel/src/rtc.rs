@@ -0,0 +1,52 @@
+//! CMOS real-time clock (x86_64)
+//!
+//! Reads the battery-backed wall clock through ports 0x70/0x71. It is the
+//! fallback when the firmware did not report the time at boot. moteOS
+//! assumes the RTC keeps UTC.
+
+use shared::timer::DateTime;
+
+/// CMOS register select port
+const CMOS_ADDRESS: u16 = 0x70;
+/// CMOS data port
+const CMOS_DATA: u16 = 0x71;
+
+/// Time registers: seconds, minutes, hours, day of month, month, year
+const TIME_REGISTERS: [u8; 6] = [0x00, 0x02, 0x04, 0x07, 0x08, 0x09];
+/// Status register A; bit 7 is set while the RTC updates its registers
+const STATUS_A: u8 = 0x0A;
+/// Status register B; data mode (BCD/binary) and 12/24-hour format
+const STATUS_B: u8 = 0x0B;
+
+/// Current date and time from the RTC
+///
+/// The registers change while an update is in progress, so they are read
+/// until two consecutive reads agree.
+pub fn now() -> DateTime {
+    let mut previous = read_time_registers();
+    loop {
+        let current = read_time_registers();
+        if current == previous {
+            let status_b = unsafe { read_register(STATUS_B) };
+            return DateTime::from_cmos(current, status_b);
+        }
+        previous = current;
+    }
+}
+
+/// Read the time registers once no update is in progress
+fn read_time_registers() -> [u8; 6] {
+    unsafe {
+        while read_register(STATUS_A) & 0x80 != 0 {
+            core::hint::spin_loop();
+        }
+        TIME_REGISTERS.map(|register| read_register(register))
+    }
+}
+
+unsafe fn read_register(register: u8) -> u8 {
+    let value: u8;
+    core::arch::asm!("out dx, al", in("dx") CMOS_ADDRESS, in("al") register);
+    core::arch::asm!("in al, dx", out("al") value, in("dx") CMOS_DATA);
+    value
+}
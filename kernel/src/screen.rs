@@ -4,10 +4,15 @@
 //! application state, including rendering chat messages, input, and UI elements.
 
 extern crate alloc;
+use alloc::boxed::Box;
 use alloc::format;
 use alloc::string::String;
 use crate::GLOBAL_STATE;
-use config::{ApiKeyProvider, WizardState};
+use config::{
+    wifi_network_label, ApiKeyProvider, AzureField, CustomField, Preferences, ThemeChoice,
+    WizardState,
+};
+use tui::{Theme, DARK_THEME, LIGHT_THEME};
 #[cfg(target_arch = "x86_64")]
 use crate::ps2;
 
@@ -26,6 +31,7 @@ pub fn update_screen() {
             // Render chat screen
             render_chat_screen(kernel_state);
         }
+        kernel_state.screen.present();
     }
 }
 
@@ -35,6 +41,21 @@ static NEEDS_FULL_REDRAW: core::sync::atomic::AtomicBool = core::sync::atomic::A
 /// Track if we need to update (redraw without clear - for input changes)
 static NEEDS_UPDATE: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
 
+/// Track if only the chat header changed (spinner animation)
+static NEEDS_HEADER_UPDATE: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+/// Local time the network statistics panel was last drawn
+static NET_STATS_DRAWN_MS: core::sync::atomic::AtomicI64 = core::sync::atomic::AtomicI64::new(0);
+
+/// How often the network statistics panel is refreshed
+const NET_STATS_REFRESH_MS: i64 = 1000;
+
+/// Width of the network statistics panel, in characters
+const NET_STATS_COLUMNS: usize = 28;
+
+/// Half-transparent black laid over the chat while an overlay is open
+const OVERLAY_DIM: tui::Color = tui::Color::new_rgba(0, 0, 0, 128);
+
 /// Mark screen as needing full redraw (clear + redraw)
 pub fn mark_dirty() {
     NEEDS_FULL_REDRAW.store(true, core::sync::atomic::Ordering::Relaxed);
@@ -45,6 +66,86 @@ pub fn mark_needs_update() {
     NEEDS_UPDATE.store(true, core::sync::atomic::Ordering::Relaxed);
 }
 
+/// Advance the generation spinner, once per event loop iteration
+///
+/// Only the header is redrawn when the spinner glyph changes.
+pub fn tick_spinner() {
+    let mut state = GLOBAL_STATE.lock();
+    if let Some(ref mut kernel_state) = *state {
+        if kernel_state.chat_screen.tick_spinner() {
+            NEEDS_HEADER_UPDATE.store(true, core::sync::atomic::Ordering::Relaxed);
+        }
+    }
+}
+
+/// Build the theme selected in `preferences`, applying any custom colors
+///
+/// Unknown color names and malformed hex values are logged and skipped.
+pub fn build_theme(preferences: &Preferences) -> &'static Theme {
+    let base = match preferences.theme {
+        ThemeChoice::Dark => &DARK_THEME,
+        ThemeChoice::Light => &LIGHT_THEME,
+    };
+    if preferences.theme_colors.is_empty() {
+        return base;
+    }
+
+    let mut theme = *base;
+    for (name, hex) in &preferences.theme_colors {
+        let Some(slot) = theme_color_mut(&mut theme, name) else {
+            crate::serial::println(&format!("moteOS: unknown theme color '{}'", name));
+            continue;
+        };
+        match shared::Color::from_hex(hex) {
+            Ok(color) => *slot = tui::Color::new_rgba(color.r, color.g, color.b, color.a),
+            Err(_) => crate::serial::println(&format!(
+                "moteOS: invalid hex '{}' for theme color '{}'",
+                hex, name
+            )),
+        }
+    }
+    // The screen keeps a 'static theme; this is built once at boot
+    Box::leak(Box::new(theme))
+}
+
+/// Theme field for a color name as written in the config file
+fn theme_color_mut<'a>(theme: &'a mut Theme, name: &str) -> Option<&'a mut tui::Color> {
+    Some(match name {
+        "background" => &mut theme.background,
+        "surface" => &mut theme.surface,
+        "border" => &mut theme.border,
+        "text_primary" => &mut theme.text_primary,
+        "text_secondary" => &mut theme.text_secondary,
+        "text_tertiary" => &mut theme.text_tertiary,
+        "text_disabled" => &mut theme.text_disabled,
+        "accent_primary" => &mut theme.accent_primary,
+        "accent_success" => &mut theme.accent_success,
+        "accent_warning" => &mut theme.accent_warning,
+        "accent_error" => &mut theme.accent_error,
+        "accent_assistant" => &mut theme.accent_assistant,
+        "accent_code" => &mut theme.accent_code,
+        "provider_openai" => &mut theme.provider_openai,
+        "provider_anthropic" => &mut theme.provider_anthropic,
+        "provider_groq" => &mut theme.provider_groq,
+        "provider_xai" => &mut theme.provider_xai,
+        "provider_local" => &mut theme.provider_local,
+        _ => return None,
+    })
+}
+
+/// Display name of a provider in the setup wizard
+fn provider_label(provider: ApiKeyProvider) -> &'static str {
+    match provider {
+        ApiKeyProvider::OpenAI => "OpenAI",
+        ApiKeyProvider::Anthropic => "Anthropic",
+        ApiKeyProvider::Groq => "Groq",
+        ApiKeyProvider::XAI => "xAI",
+        ApiKeyProvider::Azure => "Azure OpenAI",
+        ApiKeyProvider::Custom => "Custom server",
+        ApiKeyProvider::Skip => "Skip",
+    }
+}
+
 /// Render the setup wizard screen
 ///
 /// Displays the setup wizard UI for initial configuration.
@@ -102,21 +203,26 @@ fn render_setup_wizard(kernel_state: &mut crate::KernelState) {
             draw_centered(&mut kernel_state.screen, center_y, "Scanning for WiFi networks...", theme.text_primary);
             draw_centered(&mut kernel_state.screen, center_y + char_height * 2, "Press ESC to go back", theme.text_tertiary);
         }
-        WizardState::NetworkSelect { selected_index } => {
+        WizardState::WifiSelect { ref networks, selected_index } => {
             draw_centered(&mut kernel_state.screen, center_y - char_height * 5, "Select WiFi Network", theme.text_primary);
 
-            let networks = kernel_state.wizard.available_networks();
+            if networks.is_empty() {
+                draw_centered(&mut kernel_state.screen, center_y, "No networks found", theme.text_secondary);
+            }
+            // Keep the selection inside the five visible rows
+            let first = selected_index.saturating_sub(4);
             let start_y = center_y - char_height * 2;
-            for (i, network) in networks.iter().take(5).enumerate() {
+            for (row, network) in networks.iter().skip(first).take(5).enumerate() {
+                let i = first + row;
                 let prefix = if i == selected_index { "> " } else { "  " };
-                let line = format!("{}{}", prefix, network.ssid);
+                let line = format!("{}{}", prefix, wifi_network_label(network));
                 let color = if i == selected_index { theme.accent_primary } else { theme.text_secondary };
-                draw_centered(&mut kernel_state.screen, start_y + i * char_height, &line, color);
+                draw_centered(&mut kernel_state.screen, start_y + row * char_height, &line, color);
             }
 
-            draw_centered(&mut kernel_state.screen, center_y + char_height * 4, "Use UP/DOWN to select, ENTER to confirm", theme.text_tertiary);
+            draw_centered(&mut kernel_state.screen, center_y + char_height * 4, "Use UP/DOWN to select, ENTER to confirm, R to rescan", theme.text_tertiary);
         }
-        WizardState::NetworkPassword { ref ssid } => {
+        WizardState::NetworkPassword { ref ssid, .. } => {
             let title = format!("Enter password for: {}", ssid);
             draw_centered(&mut kernel_state.screen, center_y - char_height * 2, &title, theme.text_primary);
 
@@ -133,23 +239,20 @@ fn render_setup_wizard(kernel_state: &mut crate::KernelState) {
             draw_centered(&mut kernel_state.screen, center_y - char_height, "[2] Anthropic", theme.text_secondary);
             draw_centered(&mut kernel_state.screen, center_y, "[3] Groq", theme.text_secondary);
             draw_centered(&mut kernel_state.screen, center_y + char_height, "[4] xAI", theme.text_secondary);
-            draw_centered(&mut kernel_state.screen, center_y + char_height * 3, "[S] Skip (use local model only)", theme.text_secondary);
-            draw_centered(&mut kernel_state.screen, center_y + char_height * 5, "Press ESC to go back", theme.text_tertiary);
+            draw_centered(&mut kernel_state.screen, center_y + char_height * 2, "[5] Azure OpenAI", theme.text_secondary);
+            draw_centered(&mut kernel_state.screen, center_y + char_height * 3, "[6] Custom server (Ollama, llama.cpp, ...)", theme.text_secondary);
+            draw_centered(&mut kernel_state.screen, center_y + char_height * 4, "[S] Skip (use local model only)", theme.text_secondary);
+            draw_centered(&mut kernel_state.screen, center_y + char_height * 6, "Press ESC to go back", theme.text_tertiary);
         }
         WizardState::ApiKeyInput { ref provider } => {
-            let provider_name = match provider {
-                ApiKeyProvider::OpenAI => "OpenAI",
-                ApiKeyProvider::Anthropic => "Anthropic",
-                ApiKeyProvider::Groq => "Groq",
-                ApiKeyProvider::XAI => "xAI",
-                ApiKeyProvider::Skip => "Skip",
-            };
-            let title = format!("Enter {} API Key", provider_name);
+            let title = format!("Enter {} API Key", provider_label(*provider));
             draw_centered(&mut kernel_state.screen, center_y - char_height * 2, &title, theme.text_primary);
 
             // Show API key input (masked)
             let input = kernel_state.wizard.input_buffer();
-            let masked: String = if input.is_empty() {
+            let masked: String = if input.is_empty() && *provider == ApiKeyProvider::Custom {
+                String::from("(leave empty if the server needs no key)")
+            } else if input.is_empty() {
                 String::from("(type your API key)")
             } else {
                 "*".repeat(input.len())
@@ -158,10 +261,66 @@ fn render_setup_wizard(kernel_state: &mut crate::KernelState) {
 
             draw_centered(&mut kernel_state.screen, center_y + char_height * 3, "Press ENTER to save, ESC to go back", theme.text_tertiary);
         }
+        WizardState::AzureDetailsInput { field } => {
+            let (title, hint) = match field {
+                AzureField::Resource => ("Enter Azure resource name", "(e.g. contoso for contoso.openai.azure.com)"),
+                AzureField::Deployment => ("Enter Azure deployment name", "(type your deployment name)"),
+                AzureField::ApiVersion => ("Enter Azure API version", "(leave empty for default)"),
+            };
+            draw_centered(&mut kernel_state.screen, center_y - char_height * 2, title, theme.text_primary);
+
+            let input = kernel_state.wizard.input_buffer();
+            let shown = if input.is_empty() { String::from(hint) } else { String::from(input) };
+            draw_centered(&mut kernel_state.screen, center_y, &shown, theme.text_secondary);
+
+            draw_centered(&mut kernel_state.screen, center_y + char_height * 3, "Press ENTER to continue, ESC to go back", theme.text_tertiary);
+        }
+        WizardState::CustomDetailsInput { field } => {
+            let (title, hint) = match field {
+                CustomField::BaseUrl => ("Enter server URL", "(e.g. http://192.168.1.10:11434/v1)"),
+                CustomField::Model => ("Enter model name", "(e.g. llama3.2)"),
+            };
+            draw_centered(&mut kernel_state.screen, center_y - char_height * 2, title, theme.text_primary);
+
+            let input = kernel_state.wizard.input_buffer();
+            let shown = if input.is_empty() { String::from(hint) } else { String::from(input) };
+            draw_centered(&mut kernel_state.screen, center_y, &shown, theme.text_secondary);
+
+            draw_centered(&mut kernel_state.screen, center_y + char_height * 3, "Press ENTER to continue, ESC to go back", theme.text_tertiary);
+        }
+        WizardState::ApiKeyValidating { provider } => {
+            let spinner = ["|", "/", "-", "\\"][(crate::init::get_time_ms() / 100) as usize % 4];
+            let title = format!("{} Checking {} API key...", spinner, provider_label(provider));
+            draw_centered(&mut kernel_state.screen, center_y, &title, theme.text_primary);
+        }
+        WizardState::ApiKeyValidated { provider, ref error } => match error {
+            None => {
+                let title = format!("{} API key works", provider_label(provider));
+                draw_centered(&mut kernel_state.screen, center_y - char_height, &title, theme.accent_success);
+                draw_centered(&mut kernel_state.screen, center_y + char_height * 2, "Press ENTER to continue", theme.text_tertiary);
+            }
+            Some(message) => {
+                draw_centered(&mut kernel_state.screen, center_y - char_height * 2, "API key check failed", theme.accent_error);
+                draw_centered(&mut kernel_state.screen, center_y, message, theme.text_secondary);
+                draw_centered(&mut kernel_state.screen, center_y + char_height * 3, "ENTER: re-enter key  C: keep it anyway  ESC: back", theme.text_tertiary);
+            }
+        },
         WizardState::Ready { .. } => {
             draw_centered(&mut kernel_state.screen, center_y - char_height * 2, "Setup Complete!", theme.accent_success);
             draw_centered(&mut kernel_state.screen, center_y, "Press ENTER to save and start moteOS", theme.text_primary);
-            draw_centered(&mut kernel_state.screen, center_y + char_height * 2, "Press ESC to go back and make changes", theme.text_tertiary);
+            draw_centered(&mut kernel_state.screen, center_y + char_height, "Press P to set a system prompt", theme.text_secondary);
+            draw_centered(&mut kernel_state.screen, center_y + char_height * 3, "Press ESC to go back and make changes", theme.text_tertiary);
+        }
+        WizardState::SystemPromptInput => {
+            draw_centered(&mut kernel_state.screen, center_y - char_height * 2, "System prompt (sent before every conversation)", theme.text_primary);
+            let input = kernel_state.wizard.input_buffer();
+            let shown: String = if input.is_empty() {
+                String::from("(leave empty for none)")
+            } else {
+                String::from(input)
+            };
+            draw_centered(&mut kernel_state.screen, center_y, &shown, theme.text_secondary);
+            draw_centered(&mut kernel_state.screen, center_y + char_height * 3, "Press ENTER to keep, ESC to cancel", theme.text_tertiary);
         }
         WizardState::Complete => {
             draw_centered(&mut kernel_state.screen, center_y, "Starting moteOS...", theme.text_primary);
@@ -176,28 +335,190 @@ fn render_chat_screen(kernel_state: &mut crate::KernelState) {
     // Check if we need any redraw
     let needs_full = NEEDS_FULL_REDRAW.swap(false, core::sync::atomic::Ordering::Relaxed);
     let needs_update = NEEDS_UPDATE.swap(false, core::sync::atomic::Ordering::Relaxed);
+    let needs_header = NEEDS_HEADER_UPDATE.swap(false, core::sync::atomic::Ordering::Relaxed);
+
+    // The statistics panel refreshes on its own, without a full redraw
+    if kernel_state.show_net_stats && !needs_full && kernel_state.overlay.is_none() {
+        let drawn = NET_STATS_DRAWN_MS.load(core::sync::atomic::Ordering::Relaxed);
+        if crate::init::get_time_ms() - drawn >= NET_STATS_REFRESH_MS {
+            render_net_stats(kernel_state);
+        }
+    }
 
+    if !needs_full && !needs_update && !needs_header {
+        return;
+    }
+
+    // The spinner only touches the header (hidden while an overlay is open)
+    if needs_header && !needs_full && kernel_state.overlay.is_none() {
+        kernel_state.chat_screen.render_header_only(&mut kernel_state.screen);
+    }
     if !needs_full && !needs_update {
         return;
     }
 
     // For partial updates (input changes), only redraw the input area
+    // (or the open overlay, which owns the input while open)
     if needs_update && !needs_full {
-        kernel_state.chat_screen.render_input_only(&mut kernel_state.screen);
+        if kernel_state.overlay.is_some() {
+            render_overlay(kernel_state);
+        } else {
+            kernel_state.chat_screen.render_input_only(&mut kernel_state.screen);
+        }
         return;
     }
 
     // Full redraw: clear and render everything
     kernel_state.screen.clear();
 
-    // Update connection status based on network state
-    let status = if kernel_state.network.is_some() {
-        tui::screens::ConnectionStatus::Connected
-    } else {
+    // Update connection status based on network state, keeping any
+    // provider error visible until the next successful request or switch
+    let link_local_only = kernel_state
+        .network
+        .as_ref()
+        .is_some_and(|net| net.network_status() == network::NetworkStatus::LinkLocalOnly);
+    let status = kernel_state.chat_screen.status().clone();
+    if kernel_state.network.is_none() {
+        kernel_state
+            .chat_screen
+            .set_status(tui::screens::ConnectionStatus::Disconnected);
+    } else if link_local_only {
+        // Without a DHCP lease only LAN providers can answer; say so
+        if !matches!(status, tui::screens::ConnectionStatus::Error(_)) {
+            kernel_state
+                .chat_screen
+                .set_status(tui::screens::ConnectionStatus::LinkLocalOnly);
+        }
+    } else if matches!(
+        status,
         tui::screens::ConnectionStatus::Disconnected
-    };
-    kernel_state.chat_screen.set_status(status);
+            | tui::screens::ConnectionStatus::LinkLocalOnly
+    ) {
+        kernel_state
+            .chat_screen
+            .set_status(tui::screens::ConnectionStatus::Connected);
+    }
 
     // Render the full chat screen
     kernel_state.chat_screen.render(&mut kernel_state.screen);
+
+    // Overlays are drawn on top of the dimmed chat
+    if kernel_state.overlay.is_some() {
+        let bounds = kernel_state.screen.bounds();
+        kernel_state.screen.fill_rect_blended(bounds, OVERLAY_DIM);
+        render_overlay(kernel_state);
+    } else if kernel_state.show_net_stats {
+        render_net_stats(kernel_state);
+    }
+}
+
+/// Draw the network counters in the top-right corner
+fn render_net_stats(kernel_state: &mut crate::KernelState) {
+    NET_STATS_DRAWN_MS.store(crate::init::get_time_ms(), core::sync::atomic::Ordering::Relaxed);
+    let Some((char_width, char_height)) = kernel_state.screen.char_size() else {
+        return;
+    };
+
+    let mut lines = alloc::vec![String::from("Network")];
+    match kernel_state.network.as_ref() {
+        None => lines.push(String::from("not initialized")),
+        Some(stack) => {
+            let stats = stack.stats();
+            lines.push(format!(
+                "TX {} pkts {} KiB, {} err",
+                stats.tx_packets,
+                stats.tx_bytes / 1024,
+                stats.tx_errors
+            ));
+            lines.push(format!(
+                "RX {} pkts {} KiB, {} drop",
+                stats.rx_packets,
+                stats.rx_bytes / 1024,
+                stats.rx_drops
+            ));
+            lines.push(format!(
+                "DNS {}  TCP {}  TLS {}",
+                stats.dns_queries, stats.tcp_connections, stats.tls_handshakes
+            ));
+            if let Some(error) = &stats.last_error {
+                lines.push(error.chars().take(NET_STATS_COLUMNS).collect());
+            }
+        }
+    }
+
+    // Below the chat header, inside the right edge
+    let bounds = kernel_state.screen.bounds();
+    let width = ((NET_STATS_COLUMNS + 2) * char_width).min(bounds.width);
+    let height = ((lines.len() + 1) * char_height).min(bounds.height);
+    let rect = tui::Rect::new(
+        bounds.width.saturating_sub(width + 4 * char_width),
+        4 * char_height,
+        width,
+        height,
+    );
+    let theme = kernel_state.screen.theme();
+    let screen = &mut kernel_state.screen;
+    screen.fill_rect(rect, theme.surface);
+    screen.draw_box(rect, tui::screen::BoxStyle::Single, theme.border);
+    for (i, line) in lines.iter().enumerate() {
+        let color = if i == 0 { theme.text_primary } else { theme.text_secondary };
+        screen.draw_text(
+            rect.x + char_width,
+            rect.y + char_height / 2 + i * char_height,
+            line,
+            color,
+        );
+    }
+}
+
+/// Draw a centered progress bar with a label above it
+///
+/// For boot-time work that runs before the event loop, such as loading a
+/// local model; the bar is presented immediately.
+pub fn draw_progress(screen: &mut tui::Screen, label: &str, done: usize, total: usize) {
+    let Some((char_width, char_height)) = screen.char_size() else {
+        return;
+    };
+    let theme = screen.theme();
+    let bounds = screen.bounds();
+
+    let width = (bounds.width / 2).max(char_width * 10);
+    let x = bounds.width.saturating_sub(width) / 2;
+    let y = bounds.height / 2;
+    let percent = if total == 0 { 100 } else { done.min(total) * 100 / total };
+
+    // Label and percentage on the line above the bar
+    let text = format!("{} {}%", label, percent);
+    let text_x = bounds.width.saturating_sub(text.chars().count() * char_width) / 2;
+    screen.fill_rect(tui::Rect::new(x, y - 2 * char_height, width, char_height), theme.background);
+    screen.draw_text(text_x, y - 2 * char_height, &text, theme.text_primary);
+
+    let bar = tui::Rect::new(x, y, width, char_height);
+    screen.fill_rect(bar, theme.surface);
+    screen.draw_box(bar, tui::screen::BoxStyle::Single, theme.border);
+    let filled = (width.saturating_sub(4)) * percent / 100;
+    if filled > 0 {
+        screen.fill_rect(
+            tui::Rect::new(x + 2, y + 2, filled, char_height.saturating_sub(4)),
+            theme.accent_primary,
+        );
+    }
+    screen.present();
+}
+
+/// Render the open overlay (help, config, ...) if there is one
+fn render_overlay(kernel_state: &mut crate::KernelState) {
+    match kernel_state.overlay.as_mut() {
+        Some(crate::Overlay::Help(help)) => help.render(&mut kernel_state.screen),
+        Some(crate::Overlay::ProviderSelect(provider_select)) => {
+            provider_select.render(&mut kernel_state.screen)
+        }
+        Some(crate::Overlay::ModelSelect(model_select)) => {
+            model_select.render(&mut kernel_state.screen)
+        }
+        Some(crate::Overlay::Config(config_screen)) => {
+            config_screen.render(&mut kernel_state.screen)
+        }
+        None => {}
+    }
 }
@@ -0,0 +1,154 @@
+//! Context window management.
+//!
+//! Long conversations eventually exceed a model's context window. The helpers
+//! here estimate how many tokens a conversation uses and drop the oldest
+//! non-system messages until it fits again.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::types::{Message, Role};
+
+/// Tokens added per message for role markers and separators.
+pub const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Estimate the token count of `text` with the chars/4 heuristic.
+///
+/// This is close enough for English text with BPE tokenizers and errs on the
+/// high side for short strings.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Estimate the tokens a message uses, including per-message overhead.
+pub fn estimate_message_tokens(message: &Message) -> usize {
+    estimate_tokens(&message.content.text()) + MESSAGE_OVERHEAD_TOKENS
+}
+
+/// Trim `messages` to fit in `limit` tokens using the heuristic estimate.
+///
+/// See [`truncate_to_fit_with`] for the trimming rules.
+pub fn truncate_to_fit(messages: &mut Vec<Message>, limit: usize) -> usize {
+    truncate_to_fit_with(messages, limit, &mut estimate_tokens)
+}
+
+/// Trim `messages` to fit in `limit` tokens, counting with `count_tokens`.
+///
+/// Pass an exact counter (e.g. a tokenizer's `encode(..).len()`) when one is
+/// available. The oldest non-system messages are removed first; system
+/// messages and the latest message are always kept, so the result may still
+/// exceed `limit` if those alone are too large.
+///
+/// # Returns
+///
+/// The number of messages removed.
+pub fn truncate_to_fit_with(
+    messages: &mut Vec<Message>,
+    limit: usize,
+    count_tokens: &mut dyn FnMut(&str) -> usize,
+) -> usize {
+    let mut costs: Vec<usize> = messages
+        .iter()
+        .map(|m| count_tokens(&m.content.text()) + MESSAGE_OVERHEAD_TOKENS)
+        .collect();
+    let mut total: usize = costs.iter().sum();
+    let mut removed = 0;
+
+    let mut i = 0;
+    while total > limit && i + 1 < messages.len() {
+        if messages[i].role == Role::System {
+            i += 1;
+            continue;
+        }
+        total -= costs.remove(i);
+        messages.remove(i);
+        removed += 1;
+    }
+
+    removed
+}
+
+/// Make `prompt` the conversation's leading system message.
+///
+/// A leading system message is replaced, otherwise one is inserted; a blank
+/// `prompt` removes it. Providers without a system role fold it into the
+/// request themselves.
+pub fn apply_system_prompt(messages: &mut Vec<Message>, prompt: &str) {
+    let has_prompt = messages.first().is_some_and(|m| m.role == Role::System);
+    if prompt.trim().is_empty() {
+        if has_prompt {
+            messages.remove(0);
+        }
+        return;
+    }
+    let message = Message::new(Role::System, String::from(prompt));
+    if has_prompt {
+        messages[0] = message;
+    } else {
+        messages.insert(0, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn message(role: Role, len: usize) -> Message {
+        Message::new(role, "x".repeat(len))
+    }
+
+    #[test]
+    fn test_estimate_tokens_rounds_up() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abc"), 1);
+        assert_eq!(estimate_tokens("abcdefgh"), 2);
+    }
+
+    #[test]
+    fn test_truncation_keeps_system_and_latest() {
+        let mut messages = vec![
+            message(Role::System, 40),
+            message(Role::User, 40),
+            message(Role::Assistant, 40),
+            message(Role::User, 40),
+        ];
+        // Each message costs 10 + 4 tokens; only two fit
+        let removed = truncate_to_fit(&mut messages, 30);
+        assert_eq!(removed, 2);
+        assert_eq!(messages[0].role, Role::System);
+        assert_eq!(messages[1].role, Role::User);
+        assert_eq!(messages.len(), 2);
+    }
+
+    #[test]
+    fn test_truncation_uses_custom_counter() {
+        let mut messages = vec![
+            Message::new(Role::User, String::from("one two")),
+            Message::new(Role::Assistant, String::from("three")),
+        ];
+        let mut words = |text: &str| text.split_whitespace().count();
+        assert_eq!(truncate_to_fit_with(&mut messages, 100, &mut words), 0);
+        assert_eq!(truncate_to_fit_with(&mut messages, 6, &mut words), 1);
+        assert_eq!(messages[0].content, "three");
+    }
+
+    #[test]
+    fn test_system_prompt_leads_the_conversation() {
+        let mut messages = vec![message(Role::User, 3), message(Role::Assistant, 3)];
+        apply_system_prompt(&mut messages, "Be brief.");
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].role, Role::System);
+        assert_eq!(messages[0].content.text(), "Be brief.");
+
+        // Replaced rather than stacked
+        apply_system_prompt(&mut messages, "Answer in French.");
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].content.text(), "Answer in French.");
+        assert_eq!(messages[1].role, Role::User);
+
+        apply_system_prompt(&mut messages, "  ");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, Role::User);
+    }
+}
@@ -1,7 +1,10 @@
 extern crate alloc;
 
-use alloc::string::String;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
 use core::fmt;
+use miniserde::Deserialize;
+use network::{error_code_label, HttpError, HttpResponse};
 
 /// Errors that can occur when interacting with LLM providers.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -10,10 +13,6 @@ pub enum LlmError {
     NetworkError(String),
     /// HTTP error with status code and response body.
     HttpError { status: u16, body: String },
-    /// Authentication error (invalid API key, etc.)
-    AuthError(String),
-    /// Rate limit error with optional retry-after seconds.
-    RateLimitError { retry_after: Option<u64> },
     /// Invalid model identifier.
     InvalidModel(String),
     /// Error parsing response or request data.
@@ -22,6 +21,26 @@ pub enum LlmError {
     Timeout,
     /// Other error with description.
     Other(String),
+    /// A retriable error persisted after every retry attempt.
+    RetriesExhausted { attempts: u32, last: Box<LlmError> },
+    /// The API key is missing or the provider rejected it.
+    InvalidApiKey,
+    /// The provider is rate limiting requests; retry after the given delay if known.
+    RateLimited { retry_after_ms: Option<u64> },
+    /// The conversation no longer fits in the model's context window.
+    ContextLengthExceeded,
+    /// The requested model (or Azure deployment) does not exist.
+    ModelNotFound,
+    /// The provider failed on its side (5xx or overloaded).
+    ServerError(String),
+    /// The provider can't handle the message content (e.g. images).
+    UnsupportedContent(String),
+    /// The request failed below HTTP; `code` is the stable `NET-xxx` number.
+    Transport {
+        code: u16,
+        retriable: bool,
+        message: String,
+    },
 }
 
 impl fmt::Display for LlmError {
@@ -31,18 +50,190 @@ impl fmt::Display for LlmError {
             LlmError::HttpError { status, body } => {
                 write!(f, "HTTP error {}: {}", status, body)
             }
-            LlmError::AuthError(msg) => write!(f, "Authentication error: {}", msg),
-            LlmError::RateLimitError { retry_after } => {
-                if let Some(seconds) = retry_after {
-                    write!(f, "Rate limit exceeded. Retry after {} seconds", seconds)
-                } else {
-                    write!(f, "Rate limit exceeded")
-                }
-            }
             LlmError::InvalidModel(model) => write!(f, "Invalid model: {}", model),
             LlmError::ParseError(msg) => write!(f, "Parse error: {}", msg),
             LlmError::Timeout => write!(f, "Request timed out"),
             LlmError::Other(msg) => write!(f, "Error: {}", msg),
+            LlmError::RetriesExhausted { attempts, last } => {
+                write!(f, "{} (gave up after {} attempts)", last, attempts)
+            }
+            LlmError::InvalidApiKey => write!(f, "API key rejected"),
+            LlmError::RateLimited { retry_after_ms } => {
+                if let Some(ms) = retry_after_ms {
+                    write!(f, "Rate limited. Retry after {} ms", ms)
+                } else {
+                    write!(f, "Rate limited")
+                }
+            }
+            LlmError::ContextLengthExceeded => write!(f, "Context length exceeded"),
+            LlmError::ModelNotFound => write!(f, "Model not found"),
+            LlmError::ServerError(msg) => write!(f, "Server error: {}", msg),
+            LlmError::UnsupportedContent(msg) => write!(f, "Unsupported content: {}", msg),
+            LlmError::Transport { code, message, .. } => {
+                write!(f, "{}: {}", error_code_label(*code), message)
+            }
+        }
+    }
+}
+
+/// Error envelope shared by OpenAI-compatible APIs and Anthropic.
+///
+/// OpenAI: `{"error":{"type":..,"code":..,"message":..}}`
+/// Anthropic: `{"type":"error","error":{"type":..,"message":..}}`
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    error: ErrorBody,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+    code: Option<String>,
+    message: Option<String>,
+}
+
+impl From<HttpError> for LlmError {
+    fn from(error: HttpError) -> Self {
+        LlmError::Transport {
+            code: error.error_code(),
+            retriable: error.is_retriable(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl LlmError {
+    /// Whether sending the same request again may succeed.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            LlmError::Transport { retriable, .. } => *retriable,
+            LlmError::Timeout
+            | LlmError::RateLimited { .. }
+            | LlmError::ServerError(_) => true,
+            _ => false,
         }
     }
+
+    /// Map an HTTP error response to a typed error.
+    ///
+    /// Uses the `Retry-After` header (in seconds) for rate limits.
+    pub fn from_response(response: &HttpResponse) -> LlmError {
+        let retry_after_ms = response
+            .header("Retry-After")
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(|secs| secs.saturating_mul(1000));
+        let body = core::str::from_utf8(&response.body).unwrap_or("<non-utf8 body>");
+        LlmError::from_status_and_body(response.status, body, retry_after_ms)
+    }
+
+    /// Map an HTTP status and JSON error body to a typed error.
+    ///
+    /// The error `code` and `type` are checked first (both OpenAI and
+    /// Anthropic vocabularies); the status code decides when they are absent
+    /// or unknown.
+    pub fn from_status_and_body(status: u16, body: &str, retry_after_ms: Option<u64>) -> LlmError {
+        let parsed = miniserde::json::from_str::<ErrorEnvelope>(body).ok();
+        let (error_type, code, message) = match &parsed {
+            Some(envelope) => (
+                envelope.error.error_type.as_deref().unwrap_or(""),
+                envelope.error.code.as_deref().unwrap_or(""),
+                envelope.error.message.as_deref().unwrap_or(""),
+            ),
+            None => ("", "", ""),
+        };
+
+        match (code, error_type) {
+            ("invalid_api_key", _) | (_, "authentication_error") | (_, "permission_error") => {
+                return LlmError::InvalidApiKey
+            }
+            ("context_length_exceeded", _) => return LlmError::ContextLengthExceeded,
+            ("model_not_found", _) | ("DeploymentNotFound", _) | (_, "not_found_error") => {
+                return LlmError::ModelNotFound
+            }
+            ("rate_limit_exceeded", _) | (_, "rate_limit_error") => {
+                return LlmError::RateLimited { retry_after_ms }
+            }
+            (_, "overloaded_error") | (_, "api_error") => {
+                return LlmError::ServerError(message.to_string())
+            }
+            // Anthropic reports an oversized prompt as a plain invalid request
+            (_, "invalid_request_error") if message.contains("prompt is too long") => {
+                return LlmError::ContextLengthExceeded
+            }
+            _ => {}
+        }
+
+        let detail = if message.is_empty() { body } else { message };
+        match status {
+            401 | 403 => LlmError::InvalidApiKey,
+            404 => LlmError::ModelNotFound,
+            // insufficient_quota is also a 429 but waiting will not help
+            429 if code != "insufficient_quota" => LlmError::RateLimited { retry_after_ms },
+            500..=599 => LlmError::ServerError(detail.to_string()),
+            _ => LlmError::HttpError {
+                status,
+                body: detail.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openai_context_length_error() {
+        let body = r#"{"error":{"message":"This model's maximum context length is 8192 tokens.","type":"invalid_request_error","param":"messages","code":"context_length_exceeded"}}"#;
+        assert_eq!(
+            LlmError::from_status_and_body(400, body, None),
+            LlmError::ContextLengthExceeded
+        );
+    }
+
+    #[test]
+    fn openai_invalid_key_and_missing_model() {
+        let body = r#"{"error":{"message":"Incorrect API key provided","type":"invalid_request_error","param":null,"code":"invalid_api_key"}}"#;
+        assert_eq!(LlmError::from_status_and_body(401, body, None), LlmError::InvalidApiKey);
+        let body = r#"{"error":{"message":"The model `gpt-9` does not exist","type":"invalid_request_error","param":null,"code":"model_not_found"}}"#;
+        assert_eq!(LlmError::from_status_and_body(404, body, None), LlmError::ModelNotFound);
+    }
+
+    #[test]
+    fn anthropic_error_types() {
+        let body = r#"{"type":"error","error":{"type":"rate_limit_error","message":"slow down"}}"#;
+        assert_eq!(
+            LlmError::from_status_and_body(429, body, Some(3000)),
+            LlmError::RateLimited { retry_after_ms: Some(3000) }
+        );
+        let body = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert_eq!(
+            LlmError::from_status_and_body(529, body, None),
+            LlmError::ServerError("Overloaded".into())
+        );
+    }
+
+    #[test]
+    fn transport_errors_keep_code_and_classification() {
+        let error = LlmError::from(HttpError::Net(network::NetError::DnsServerFailure));
+        assert!(error.is_retriable());
+        assert_eq!(error.to_string(), "NET-034: network error: DNS server failure");
+
+        let error = LlmError::from(HttpError::Net(network::NetError::CertificateExpired));
+        assert!(!error.is_retriable());
+        assert!(matches!(error, LlmError::Transport { code: 57, .. }));
+    }
+
+    #[test]
+    fn unparseable_body_falls_back_to_status() {
+        assert_eq!(
+            LlmError::from_status_and_body(502, "Bad Gateway", None),
+            LlmError::ServerError("Bad Gateway".into())
+        );
+        assert_eq!(
+            LlmError::from_status_and_body(400, "nope", None),
+            LlmError::HttpError { status: 400, body: "nope".into() }
+        );
+    }
 }
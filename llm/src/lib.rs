@@ -7,14 +7,32 @@
 
 extern crate alloc;
 
+pub mod context;
 pub mod error;
+pub mod logging;
 pub mod providers;
+pub mod retry;
 pub mod streaming;
+pub mod title;
 pub mod types;
 
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+use network::NetworkStack;
+
 pub use error::LlmError;
-pub use providers::{AnthropicClient, GroqClient, OpenAiClient, XaiClient};
-pub use types::{CompletionResult, FinishReason, GenerationConfig, Message, ModelInfo, Role};
+pub use logging::{set_llm_log_callback, LlmLogCallback};
+pub use retry::RetryPolicy;
+pub use title::generate_title;
+pub use providers::{
+    AnthropicClient, AzureOpenAiClient, GroqClient, OpenAiClient, OpenAiCompatClient, XaiClient,
+};
+pub use types::{
+    cosine_similarity, CompletionResult, ContentPart, FinishReason, GenerationConfig, Message,
+    MessageContent, ModelInfo, ResponseFormat, Role, Usage, DEFAULT_EMBEDDING_BATCH_SIZE,
+    MAX_STOP_SEQUENCES,
+};
 
 /// Trait for LLM providers.
 ///
@@ -48,13 +66,15 @@ pub trait LlmProvider: Send {
     ///
     /// The `on_token` callback is called for each token as it's generated,
     /// enabling streaming responses. For non-streaming providers, this may be
-    /// called once with the complete response.
+    /// called once with the complete response. Returning
+    /// `ControlFlow::Break(())` stops generation; the provider then returns
+    /// the text so far with `FinishReason::Cancelled`.
     fn complete(
         &mut self,
         messages: &[Message],
         model: &str,
         config: &GenerationConfig,
-        on_token: &mut dyn FnMut(&str),
+        on_token: &mut dyn FnMut(&str) -> ControlFlow<()>,
     ) -> Result<CompletionResult, LlmError>;
 
     /// Validate that the API key is valid and the provider is accessible.
@@ -63,4 +83,106 @@ pub trait LlmProvider: Send {
     ///
     /// Returns `Ok(())` if the API key is valid, or an `LlmError` if validation fails.
     fn validate_api_key(&self) -> Result<(), LlmError>;
+
+    /// Refresh the model list from the provider's API.
+    ///
+    /// On success the result is cached, so later calls to `models()` return
+    /// it. Providers without a listing endpoint return their static list.
+    fn fetch_models(&mut self, stack: &mut NetworkStack) -> Result<Vec<ModelInfo>, LlmError> {
+        let _ = stack;
+        Ok(self.models().to_vec())
+    }
+
+    /// Ask the model for a short title summarizing the first user message.
+    ///
+    /// See [`title::generate_title`].
+    fn generate_title(
+        &mut self,
+        model: &str,
+        first_user_message: &str,
+    ) -> Result<String, LlmError> {
+        title::generate_title(self, model, first_user_message)
+    }
+}
+
+/// Trait for providers that can turn text into embedding vectors.
+///
+/// Kept separate from [`LlmProvider`] since most chat providers have no
+/// embeddings endpoint.
+pub trait EmbeddingProvider: Send {
+    /// Embed each of `texts` with `model`, returning one vector per text in
+    /// the same order.
+    ///
+    /// Large inputs are split into several requests of at most the
+    /// provider's configured batch size.
+    fn embed(&mut self, texts: &[&str], model: &str) -> Result<Vec<Vec<f32>>, LlmError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use smoltcp::wire::Ipv4Address;
+
+    fn time_ms() -> i64 {
+        0
+    }
+
+    #[test]
+    fn providers_are_usable_as_trait_objects() {
+        let dns = Ipv4Address::new(8, 8, 8, 8);
+        let mut providers: Vec<Box<dyn LlmProvider>> = Vec::new();
+        providers.push(Box::new(OpenAiClient::new(String::new(), dns, time_ms, None)));
+        providers.push(Box::new(AnthropicClient::new(String::new(), dns, time_ms, None)));
+        providers.push(Box::new(GroqClient::new(String::new(), dns, time_ms, None)));
+        providers.push(Box::new(XaiClient::new(String::new(), dns, time_ms, None)));
+
+        let messages = [Message::new(Role::User, String::from("hi"))];
+        let config = GenerationConfig::default();
+        let mut tokens = 0usize;
+
+        for provider in providers.iter_mut() {
+            let model = String::from(provider.default_model());
+            let mut on_token = |_: &str| {
+                tokens += 1;
+                ControlFlow::Continue(())
+            };
+            // Empty API keys are rejected before any network access
+            let result = provider.complete(&messages, &model, &config, &mut on_token);
+            assert!(matches!(result, Err(LlmError::InvalidApiKey)));
+        }
+        assert_eq!(tokens, 0);
+    }
+
+    #[test]
+    fn cosine_similarity_ranks_parallel_vectors_highest() {
+        assert!((cosine_similarity(&[1.0, 2.0], &[2.0, 4.0]) - 1.0).abs() < 1e-3);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-3);
+        assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-3);
+        // Unusable pairs score zero instead of NaN
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn embedding_requires_an_api_key() {
+        let dns = Ipv4Address::new(8, 8, 8, 8);
+        let mut client = OpenAiClient::new(String::new(), dns, time_ms, None);
+        let result = client.embed(&["hello"], "text-embedding-3-small");
+        assert!(matches!(result, Err(LlmError::InvalidApiKey)));
+    }
+
+    #[test]
+    fn known_models_are_priced_in_micro_dollars() {
+        let dns = Ipv4Address::new(8, 8, 8, 8);
+        let client = OpenAiClient::new(String::new(), dns, time_ms, None);
+        let gpt_4o = client.models().iter().find(|m| m.id == "gpt-4o").unwrap();
+        // 1000 tokens at $2.50/M in plus 1000 at $10/M out
+        assert_eq!(gpt_4o.cost_micros(&Usage::new(1_000, 1_000)), Some(12_500));
+
+        let unpriced = ModelInfo::new("x".into(), "x".into(), 0, true);
+        assert_eq!(unpriced.cost_micros(&Usage::new(1_000, 1_000)), None);
+    }
 }
@@ -0,0 +1,115 @@
+//! Request/response logging for debugging providers.
+//!
+//! Nothing is logged until a callback is registered with
+//! [`set_llm_log_callback`]. Credential header values are replaced with
+//! `***` before a line is built, so logs are safe to share.
+
+use alloc::format;
+use alloc::string::String;
+
+/// Logging callback type for provider HTTP traffic
+pub type LlmLogCallback = Option<fn(message: &str)>;
+
+/// Global LLM logging callback (set via set_llm_log_callback)
+static mut LLM_LOG_CALLBACK: LlmLogCallback = None;
+
+/// Bytes of the response body included in a log line.
+pub const LOG_BODY_PREVIEW_BYTES: usize = 256;
+
+/// Headers whose values are never logged.
+const REDACTED_HEADERS: [&str; 3] = ["authorization", "x-api-key", "api-key"];
+
+/// Set the global LLM logging callback
+///
+/// # Safety
+/// This function is unsafe because it modifies global state without synchronization.
+/// It should only be called while no provider request is in flight.
+pub unsafe fn set_llm_log_callback(callback: LlmLogCallback) {
+    LLM_LOG_CALLBACK = callback;
+}
+
+fn callback() -> LlmLogCallback {
+    unsafe { LLM_LOG_CALLBACK }
+}
+
+/// Log an outgoing request, with credential headers redacted.
+pub(crate) fn log_request(method: &str, url: &str, headers: &[(&str, &str)]) {
+    if let Some(callback) = callback() {
+        callback(&format_request(method, url, headers));
+    }
+}
+
+/// Log a response status and the start of its body.
+pub(crate) fn log_response(url: &str, status: u16, body: &[u8]) {
+    if let Some(callback) = callback() {
+        callback(&format_response(url, status, body));
+    }
+}
+
+/// Header value as it should appear in a log line.
+pub fn redact_header<'a>(name: &str, value: &'a str) -> &'a str {
+    if REDACTED_HEADERS
+        .iter()
+        .any(|redacted| name.eq_ignore_ascii_case(redacted))
+    {
+        "***"
+    } else {
+        value
+    }
+}
+
+/// One-line description of a request: method, URL and headers.
+pub fn format_request(method: &str, url: &str, headers: &[(&str, &str)]) -> String {
+    let mut line = format!("{} {}", method, url);
+    for (name, value) in headers {
+        line.push_str(&format!(" [{}: {}]", name, redact_header(name, value)));
+    }
+    line
+}
+
+/// One-line description of a response: status and a body preview.
+pub fn format_response(url: &str, status: u16, body: &[u8]) -> String {
+    let preview = &body[..body.len().min(LOG_BODY_PREVIEW_BYTES)];
+    let mut line = format!(
+        "{} -> {} ({} bytes): {}",
+        url,
+        status,
+        body.len(),
+        String::from_utf8_lossy(preview)
+    );
+    if body.len() > LOG_BODY_PREVIEW_BYTES {
+        line.push('…');
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn credentials_are_redacted() {
+        let line = format_request(
+            "POST",
+            "https://api.example.com/v1/chat",
+            &[
+                ("Authorization", "Bearer sk-secret"),
+                ("x-api-key", "sk-ant-secret"),
+                ("Accept", "text/event-stream"),
+            ],
+        );
+        assert!(!line.contains("secret"));
+        assert!(line.contains("[Authorization: ***]"));
+        assert!(line.contains("[x-api-key: ***]"));
+        assert!(line.contains("[Accept: text/event-stream]"));
+    }
+
+    #[test]
+    fn response_body_is_truncated() {
+        let body = vec![b'a'; LOG_BODY_PREVIEW_BYTES + 10];
+        let line = format_response("https://api.example.com", 200, &body);
+        assert!(line.starts_with("https://api.example.com -> 200 (266 bytes): "));
+        assert!(line.ends_with('…'));
+    }
+}
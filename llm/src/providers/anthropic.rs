@@ -3,14 +3,19 @@
 
 extern crate alloc;
 
-use crate::streaming::for_each_sse_data;
-use crate::types::{CompletionResult, FinishReason, GenerationConfig, Message, ModelInfo, Role};
+use crate::retry::{post_json_with_retry, stream_sse_with_retry, JsonPost, RetryPolicy};
+use crate::streaming::TokenSink;
+use crate::types::{
+    CompletionResult, ContentPart, FinishReason, GenerationConfig, Message, MessageContent,
+    ModelInfo, ResponseFormat, Role, Usage,
+};
 use crate::{LlmError, LlmProvider};
 use alloc::format;
-use alloc::string::{String, ToString};
+use alloc::string::String;
 use alloc::vec::Vec;
+use core::ops::ControlFlow;
 use miniserde::Deserialize;
-use network::{get_network_stack, HttpClient};
+use network::HttpClient;
 use smoltcp::wire::Ipv4Address;
 
 const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
@@ -22,11 +27,31 @@ const SUPPORTED_MODELS: [&str; 3] = [
     "claude-haiku-3-5-20241022",
 ];
 
+/// Appended to the system prompt for `ResponseFormat::JsonObject`, since the
+/// Messages API has no JSON mode of its own
+const JSON_MODE_INSTRUCTION: &str =
+    "Respond with a single valid JSON object and nothing else: no prose, no code fences.";
+
 #[derive(Deserialize)]
 struct AnthropicStreamEvent {
     #[serde(rename = "type")]
     event_type: String,
     delta: Option<AnthropicDelta>,
+    /// Set on `message_start`; carries the input token count.
+    message: Option<AnthropicMessageStart>,
+    /// Set on `message_delta`; carries the output token count.
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicMessageStart {
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicUsage {
+    input_tokens: Option<usize>,
+    output_tokens: Option<usize>,
 }
 
 #[derive(Deserialize)]
@@ -43,6 +68,7 @@ pub struct AnthropicClient {
     anthropic_version: String,
     get_time_ms: fn() -> i64,
     sleep_ms: Option<fn(i64)>,
+    retry_policy: RetryPolicy,
     models: Vec<ModelInfo>,
 }
 
@@ -77,19 +103,22 @@ impl AnthropicClient {
                 "Claude Sonnet 4".into(),
                 200_000,
                 true,
-            ),
+            )
+                .with_pricing(3_000_000, 15_000_000),
             ModelInfo::new(
                 "claude-opus-4-20250514".into(),
                 "Claude Opus 4".into(),
                 200_000,
                 true,
-            ),
+            )
+                .with_pricing(15_000_000, 75_000_000),
             ModelInfo::new(
                 "claude-haiku-3-5-20241022".into(),
                 "Claude Haiku 3.5".into(),
                 200_000,
                 true,
-            ),
+            )
+                .with_pricing(800_000, 4_000_000),
         ]);
 
         Self {
@@ -99,10 +128,29 @@ impl AnthropicClient {
             anthropic_version,
             get_time_ms,
             sleep_ms,
+            retry_policy: RetryPolicy::default(),
             models,
         }
     }
 
+    /// Override the retry policy used for completion requests
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Send requests to `base_url` (e.g. a proxy) instead of the default host
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    /// Override the HTTP connect and read timeouts
+    pub fn with_timeouts(mut self, connect_timeout_ms: i64, read_timeout_ms: i64) -> Self {
+        self.http_client = self.http_client.with_timeouts(connect_timeout_ms, read_timeout_ms);
+        self
+    }
+
     fn endpoint_url(&self) -> String {
         let base = self.base_url.trim_end_matches('/');
         format!("{base}{MESSAGES_PATH}")
@@ -131,10 +179,10 @@ impl LlmProvider for AnthropicClient {
         messages: &[Message],
         model: &str,
         config: &GenerationConfig,
-        mut on_token: &mut dyn FnMut(&str),
+        on_token: &mut dyn FnMut(&str) -> ControlFlow<()>,
     ) -> Result<CompletionResult, LlmError> {
         if self.api_key.trim().is_empty() {
-            return Err(LlmError::AuthError("missing API key".into()));
+            return Err(LlmError::InvalidApiKey);
         }
         if !Self::is_supported_model(model) {
             return Err(LlmError::InvalidModel(model.into()));
@@ -149,43 +197,19 @@ impl LlmProvider for AnthropicClient {
             ("Accept", "text/event-stream"),
         ];
 
-        let mut guard = get_network_stack();
-        let stack = guard
-            .as_mut()
-            .ok_or_else(|| LlmError::NetworkError("network stack not initialized".into()))?;
-
-        let response = self
-            .http_client
-            .post_json(stack, &url, &body, &headers, self.get_time_ms, self.sleep_ms)
-            .map_err(|e| LlmError::NetworkError(e.to_string()))?;
-
-        if response.status == 401 || response.status == 403 {
-            return Err(LlmError::AuthError("unauthorized".into()));
-        }
-        if response.status == 429 {
-            let retry_after = response
-                .header("Retry-After")
-                .and_then(|v| v.trim().parse::<u64>().ok());
-            return Err(LlmError::RateLimitError { retry_after });
-        }
-        if response.status >= 400 {
-            let body_str = core::str::from_utf8(&response.body)
-                .map(|s| s.to_string())
-                .unwrap_or_else(|_| "<non-utf8 body>".into());
-            return Err(LlmError::HttpError {
-                status: response.status,
-                body: body_str,
-            });
-        }
-
-        let body_str = core::str::from_utf8(&response.body)
-            .map_err(|e| LlmError::ParseError(format!("invalid utf-8 SSE body: {e}")))?;
-
         let mut full_text = String::new();
         let mut finish_reason = FinishReason::Stop;
+        let mut input_tokens: Option<usize> = None;
+        let mut output_tokens: Option<usize> = None;
         let mut done = false;
-
-        for_each_sse_data(body_str, |data| {
+        let mut sink = TokenSink::new(on_token);
+
+        let post = JsonPost {
+            url: &url,
+            body: &body,
+            headers: &headers,
+        };
+        let mut on_event = |data: &str, sink: &mut TokenSink| {
             if done {
                 return;
             }
@@ -201,23 +225,86 @@ impl LlmProvider for AnthropicClient {
                         return;
                     }
                     let Some(text) = delta.text.as_deref() else { return };
-                    on_token(text);
+                    sink.emit(text);
                     full_text.push_str(text);
                 }
+                "message_start" => {
+                    if let Some(u) = event.message.and_then(|m| m.usage) {
+                        input_tokens = u.input_tokens.or(input_tokens);
+                        output_tokens = u.output_tokens.or(output_tokens);
+                    }
+                }
+                "message_delta" => {
+                    if let Some(u) = event.usage {
+                        output_tokens = u.output_tokens.or(output_tokens);
+                    }
+                }
                 "message_stop" => {
                     finish_reason = FinishReason::Stop;
                     done = true;
                 }
                 _ => {}
             }
-        });
+        };
+        let response = stream_sse_with_retry(
+            &self.http_client,
+            &post,
+            self.get_time_ms,
+            self.sleep_ms,
+            &self.retry_policy,
+            &mut |data| {
+                on_event(data, &mut sink);
+                sink.control_flow()
+            },
+        )?;
 
-        Ok(CompletionResult::new(full_text, None, finish_reason))
+        if response.status >= 400 {
+            return Err(LlmError::from_response(&response));
+        }
+        if sink.is_cancelled() {
+            finish_reason = FinishReason::Cancelled;
+        }
+
+        let usage = if input_tokens.is_some() || output_tokens.is_some() {
+            Some(Usage::new(input_tokens.unwrap_or(0), output_tokens.unwrap_or(0)))
+        } else {
+            None
+        };
+
+        Ok(CompletionResult::new(full_text, None, finish_reason).with_usage(usage))
     }
 
     fn validate_api_key(&self) -> Result<(), LlmError> {
         if self.api_key.trim().is_empty() {
-            return Err(LlmError::AuthError("missing API key".into()));
+            return Err(LlmError::InvalidApiKey);
+        }
+
+        // Anthropic has no free endpoint, so ask for a single token
+        let messages = [Message::new(Role::User, String::from("Hi"))];
+        let config = GenerationConfig {
+            max_tokens: Some(1),
+            ..GenerationConfig::default()
+        };
+        let body = build_anthropic_request_body(&messages, self.default_model(), &config, false);
+
+        let headers = [
+            ("x-api-key", self.api_key.as_str()),
+            ("anthropic-version", self.anthropic_version.as_str()),
+            ("Accept", "application/json"),
+        ];
+
+        let response = post_json_with_retry(
+            &self.http_client,
+            &self.endpoint_url(),
+            &body,
+            &headers,
+            self.get_time_ms,
+            self.sleep_ms,
+            &RetryPolicy::none(),
+        )?;
+
+        if response.status >= 400 {
+            return Err(LlmError::from_response(&response));
         }
         Ok(())
     }
@@ -230,16 +317,30 @@ fn build_anthropic_request_body(
     stream: bool,
 ) -> String {
     let mut system = String::new();
-    let mut non_system: Vec<&Message> = Vec::new();
+    // The API requires alternating roles, so consecutive messages from the
+    // same role are merged into one turn
+    let mut turns: Vec<(Role, Vec<&MessageContent>)> = Vec::new();
     for message in messages {
         if message.role == Role::System {
             if !system.is_empty() {
                 system.push('\n');
             }
-            system.push_str(&message.content);
+            system.push_str(&message.content.text());
+        } else if let Some((_, contents)) = turns
+            .last_mut()
+            .filter(|(role, _)| *role == message.role)
+        {
+            contents.push(&message.content);
         } else {
-            non_system.push(message);
+            turns.push((message.role, Vec::from([&message.content])));
+        }
+    }
+    // `ResponseFormat::Text` is the default and needs nothing
+    if config.response_format == Some(ResponseFormat::JsonObject) {
+        if !system.is_empty() {
+            system.push('\n');
         }
+        system.push_str(JSON_MODE_INSTRUCTION);
     }
 
     let max_tokens = config.max_tokens.unwrap_or(1024);
@@ -257,28 +358,40 @@ fn build_anthropic_request_body(
     }
 
     out.push_str(",\"messages\":[");
-    for (i, message) in non_system.iter().enumerate() {
+    for (i, (role, contents)) in turns.iter().enumerate() {
         if i != 0 {
             out.push(',');
         }
         out.push_str("{\"role\":\"");
-        out.push_str(match message.role {
+        out.push_str(match role {
             Role::User => "user",
             Role::Assistant => "assistant",
             Role::System => "user",
         });
-        out.push_str("\",\"content\":\"");
-        push_json_escaped(&mut out, &message.content);
-        out.push_str("\"}");
+        out.push_str("\",\"content\":");
+        push_turn_content(&mut out, contents);
+        out.push('}');
     }
     out.push(']');
 
     out.push_str(",\"temperature\":");
     out.push_str(&format!("{}", config.temperature));
 
-    if !config.stop_sequences.is_empty() {
+    if let Some(top_p) = config.top_p {
+        out.push_str(",\"top_p\":");
+        out.push_str(&format!("{}", top_p));
+    }
+
+    if let Some(top_k) = config.top_k {
+        out.push_str(",\"top_k\":");
+        out.push_str(&format!("{}", top_k));
+    }
+
+    // Anthropic has no presence/frequency penalties; those settings are dropped.
+    let stop_sequences = config.active_stop_sequences();
+    if !stop_sequences.is_empty() {
         out.push_str(",\"stop_sequences\":[");
-        for (i, stop) in config.stop_sequences.iter().enumerate() {
+        for (i, stop) in stop_sequences.iter().enumerate() {
             if i != 0 {
                 out.push(',');
             }
@@ -295,6 +408,75 @@ fn build_anthropic_request_body(
     out
 }
 
+/// Write the content of a turn made of one or more merged messages.
+///
+/// Text-only turns become a single string, joined with blank lines; a turn
+/// with images becomes one array holding every message's blocks.
+fn push_turn_content(out: &mut String, contents: &[&MessageContent]) {
+    if let [content] = contents {
+        push_message_content(out, content);
+    } else if contents.iter().all(|content| !content.has_images()) {
+        let texts: Vec<_> = contents.iter().map(|content| content.text()).collect();
+        out.push('"');
+        push_json_escaped(out, &texts.join("\n\n"));
+        out.push('"');
+    } else {
+        out.push('[');
+        for (i, content) in contents.iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            match content {
+                MessageContent::Text(text) => push_text_block(out, text),
+                MessageContent::Parts(parts) => push_content_blocks(out, parts),
+            }
+        }
+        out.push(']');
+    }
+}
+
+/// Write message content as a JSON string, or as an array of content
+/// blocks (`text` / base64 `image`) for vision models.
+fn push_message_content(out: &mut String, content: &MessageContent) {
+    match content {
+        MessageContent::Text(text) => {
+            out.push('"');
+            push_json_escaped(out, text);
+            out.push('"');
+        }
+        MessageContent::Parts(parts) => {
+            out.push('[');
+            push_content_blocks(out, parts);
+            out.push(']');
+        }
+    }
+}
+
+fn push_text_block(out: &mut String, text: &str) {
+    out.push_str("{\"type\":\"text\",\"text\":\"");
+    push_json_escaped(out, text);
+    out.push_str("\"}");
+}
+
+/// Write content blocks, comma-separated, without the enclosing brackets.
+fn push_content_blocks(out: &mut String, parts: &[ContentPart]) {
+    for (i, part) in parts.iter().enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+        match part {
+            ContentPart::Text(text) => push_text_block(out, text),
+            ContentPart::ImageBase64 { mime, data } => {
+                out.push_str("{\"type\":\"image\",\"source\":{\"type\":\"base64\",\"media_type\":\"");
+                push_json_escaped(out, mime);
+                out.push_str("\",\"data\":\"");
+                push_json_escaped(out, data);
+                out.push_str("\"}}");
+            }
+        }
+    }
+}
+
 fn push_json_escaped(out: &mut String, s: &str) {
     for ch in s.chars() {
         match ch {
@@ -322,3 +504,125 @@ fn nibble_to_hex(n: u8) -> char {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn request_body_uses_stop_sequences_field() {
+        let mut config = GenerationConfig::new();
+        config.stop_sequences = Vec::from([String::from("END")]);
+        config.frequency_penalty = Some(1.0);
+        let body = build_anthropic_request_body(&[], "claude", &config, true);
+        assert!(body.contains("\"stop_sequences\":[\"END\"]"));
+        assert!(!body.contains("\"stop\":"));
+        assert!(!body.contains("penalty"));
+    }
+
+    #[test]
+    fn request_body_lifts_system_prompt() {
+        let messages = [
+            Message::new(Role::System, String::from("Be brief.")),
+            Message::new(Role::User, String::from("Hi")),
+        ];
+        let body = build_anthropic_request_body(&messages, "claude", &GenerationConfig::new(), false);
+        assert!(body.contains("\"system\":\"Be brief.\""));
+        assert!(!body.contains("\"role\":\"system\""));
+        assert!(body.contains("\"messages\":[{\"role\":\"user\""));
+    }
+
+    #[test]
+    fn request_body_asks_for_json_in_system_prompt() {
+        let messages = [
+            Message::new(Role::System, String::from("Be brief.")),
+            Message::new(Role::User, String::from("Hi")),
+        ];
+        let mut config = GenerationConfig::new();
+        let body = build_anthropic_request_body(&messages, "claude", &config, false);
+        assert!(!body.contains("JSON"));
+
+        config.response_format = Some(ResponseFormat::JsonObject);
+        let body = build_anthropic_request_body(&messages, "claude", &config, false);
+        let expected = format!("\"system\":\"Be brief.\\n{}\"", JSON_MODE_INSTRUCTION);
+        assert!(body.contains(&expected));
+        // Not an API field
+        assert!(!body.contains("response_format"));
+
+        config.response_format = Some(ResponseFormat::Text);
+        let body = build_anthropic_request_body(&messages, "claude", &config, false);
+        assert!(!body.contains("JSON"));
+    }
+
+    #[test]
+    fn request_body_always_sends_max_tokens() {
+        let body = build_anthropic_request_body(&[], "claude", &GenerationConfig::new(), false);
+        assert!(body.contains("\"max_tokens\":1024"));
+
+        let mut config = GenerationConfig::new();
+        config.max_tokens = Some(50);
+        let body = build_anthropic_request_body(&[], "claude", &config, false);
+        assert!(body.contains("\"max_tokens\":50"));
+    }
+
+    #[test]
+    fn request_body_merges_consecutive_roles() {
+        let messages = [
+            Message::new(Role::System, String::from("Be brief.")),
+            Message::new(Role::User, String::from("Hi")),
+            Message::new(Role::User, String::from("Anyone there?")),
+            Message::new(Role::Assistant, String::from("Yes.")),
+            Message::new(Role::System, String::from("Use French.")),
+            Message::new(Role::User, String::from("Bonjour")),
+        ];
+        let body = build_anthropic_request_body(&messages, "claude", &GenerationConfig::new(), false);
+        assert!(body.contains("\"system\":\"Be brief.\\nUse French.\""));
+        assert!(body.contains(
+            "\"messages\":[{\"role\":\"user\",\"content\":\"Hi\\n\\nAnyone there?\"},\
+             {\"role\":\"assistant\",\"content\":\"Yes.\"},\
+             {\"role\":\"user\",\"content\":\"Bonjour\"}]"
+        ));
+    }
+
+    #[test]
+    fn request_body_merges_image_turns_into_blocks() {
+        let messages = [
+            Message::new(Role::User, String::from("Look:")),
+            Message::with_parts(
+                Role::User,
+                vec![ContentPart::ImageBase64 {
+                    mime: String::from("image/png"),
+                    data: String::from("AA=="),
+                }],
+            ),
+        ];
+        let body = build_anthropic_request_body(&messages, "claude", &GenerationConfig::new(), false);
+        assert!(body.contains(
+            "\"content\":[{\"type\":\"text\",\"text\":\"Look:\"},\
+             {\"type\":\"image\",\"source\":{\"type\":\"base64\",\
+             \"media_type\":\"image/png\",\"data\":\"AA==\"}}]"
+        ));
+    }
+
+    #[test]
+    fn request_body_serializes_image_blocks() {
+        let messages = [Message::with_parts(
+            Role::User,
+            vec![
+                ContentPart::Text(String::from("What is this?")),
+                ContentPart::ImageBase64 {
+                    mime: String::from("image/png"),
+                    data: String::from("iVBORw0KGgo="),
+                },
+            ],
+        )];
+        let body =
+            build_anthropic_request_body(&messages, "claude", &GenerationConfig::new(), false);
+        assert!(body.contains(
+            "\"content\":[{\"type\":\"text\",\"text\":\"What is this?\"},\
+             {\"type\":\"image\",\"source\":{\"type\":\"base64\",\
+             \"media_type\":\"image/png\",\"data\":\"iVBORw0KGgo=\"}}]"
+        ));
+    }
+}
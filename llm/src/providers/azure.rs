@@ -0,0 +1,283 @@
+#![allow(unused_attributes)]
+#![no_std]
+
+extern crate alloc;
+
+use crate::providers::ensure_text_only;
+use crate::providers::openai_compat::{apply_chunk_to_text, build_request_body};
+use crate::retry::{post_json_with_retry, stream_sse_with_retry, JsonPost, RetryPolicy};
+use crate::streaming::{StopSequenceFilter, TokenSink};
+use crate::types::{CompletionResult, FinishReason, GenerationConfig, Message, ModelInfo, Role};
+use crate::{LlmError, LlmProvider};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+use network::{url_encode_component, HttpClient, QueryBuilder};
+use smoltcp::wire::Ipv4Address;
+
+pub const DEFAULT_API_VERSION: &str = "2024-06-01";
+
+/// Azure OpenAI client.
+///
+/// Azure routes requests by deployment rather than by model, so the model
+/// argument to `complete` is only informational; the deployment decides
+/// which model actually serves the request.
+pub struct AzureOpenAiClient {
+    api_key: String,
+    http_client: HttpClient,
+    resource: String,
+    deployment: String,
+    api_version: String,
+    /// Replaces the resource host when set
+    base_url: Option<String>,
+    get_time_ms: fn() -> i64,
+    sleep_ms: Option<fn(i64)>,
+    retry_policy: RetryPolicy,
+    models: Vec<ModelInfo>,
+}
+
+impl AzureOpenAiClient {
+    pub fn new(
+        api_key: String,
+        resource: String,
+        deployment: String,
+        api_version: String,
+        dns_server: Ipv4Address,
+        get_time_ms: fn() -> i64,
+        sleep_ms: Option<fn(i64)>,
+    ) -> Self {
+        let api_version = if api_version.trim().is_empty() {
+            DEFAULT_API_VERSION.into()
+        } else {
+            api_version
+        };
+
+        // The only "model" an Azure client can serve is its deployment.
+        let models = Vec::from([ModelInfo::new(
+            deployment.clone(),
+            deployment.clone(),
+            128_000,
+            true,
+        )]);
+
+        Self {
+            api_key,
+            http_client: HttpClient::new(dns_server),
+            resource,
+            deployment,
+            api_version,
+            base_url: None,
+            get_time_ms,
+            sleep_ms,
+            retry_policy: RetryPolicy::default(),
+            models,
+        }
+    }
+
+    pub fn resource(&self) -> &str {
+        &self.resource
+    }
+
+    pub fn deployment(&self) -> &str {
+        &self.deployment
+    }
+
+    pub fn api_version(&self) -> &str {
+        &self.api_version
+    }
+
+    /// Override the retry policy used for completion requests
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Send requests to `base_url` (e.g. a proxy) instead of
+    /// `https://{resource}.openai.azure.com`
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = Some(base_url);
+        self
+    }
+
+    /// Override the HTTP connect and read timeouts
+    pub fn with_timeouts(mut self, connect_timeout_ms: i64, read_timeout_ms: i64) -> Self {
+        self.http_client = self.http_client.with_timeouts(connect_timeout_ms, read_timeout_ms);
+        self
+    }
+
+    fn endpoint_url(&self) -> String {
+        match &self.base_url {
+            Some(base_url) => format!(
+                "{}{}",
+                base_url.trim_end_matches('/'),
+                deployment_path(&self.deployment, &self.api_version)
+            ),
+            None => endpoint_url(&self.resource, &self.deployment, &self.api_version),
+        }
+    }
+}
+
+/// Build the chat completions URL for an Azure OpenAI deployment.
+pub fn endpoint_url(resource: &str, deployment: &str, api_version: &str) -> String {
+    format!(
+        "https://{resource}.openai.azure.com{}",
+        deployment_path(deployment, api_version)
+    )
+}
+
+fn deployment_path(deployment: &str, api_version: &str) -> String {
+    format!(
+        "/openai/deployments/{}/chat/completions{}",
+        url_encode_component(deployment),
+        QueryBuilder::new()
+            .append("api-version", api_version)
+            .build()
+    )
+}
+
+impl LlmProvider for AzureOpenAiClient {
+    fn name(&self) -> &str {
+        "Azure OpenAI"
+    }
+
+    fn models(&self) -> &[ModelInfo] {
+        &self.models
+    }
+
+    fn default_model(&self) -> &str {
+        &self.deployment
+    }
+
+    fn complete(
+        &mut self,
+        messages: &[Message],
+        model: &str,
+        config: &GenerationConfig,
+        on_token: &mut dyn FnMut(&str) -> ControlFlow<()>,
+    ) -> Result<CompletionResult, LlmError> {
+        if self.api_key.trim().is_empty() {
+            return Err(LlmError::InvalidApiKey);
+        }
+        ensure_text_only(messages, self.name())?;
+        if self.resource.trim().is_empty() || self.deployment.trim().is_empty() {
+            return Err(LlmError::Other(
+                "Azure resource and deployment must be configured".into(),
+            ));
+        }
+
+        let url = self.endpoint_url();
+        let body = build_request_body(messages, model, config, true);
+
+        let headers = [
+            ("api-key", self.api_key.as_str()),
+            ("Accept", "text/event-stream"),
+        ];
+
+        let mut full_text = String::new();
+        let mut finish_reason = FinishReason::Stop;
+        let mut usage = None;
+        let mut stop_filter = StopSequenceFilter::new(config.active_stop_sequences());
+        let mut done = false;
+        let mut sink = TokenSink::new(on_token);
+
+        let post = JsonPost {
+            url: &url,
+            body: &body,
+            headers: &headers,
+        };
+        let response = stream_sse_with_retry(
+            &self.http_client,
+            &post,
+            self.get_time_ms,
+            self.sleep_ms,
+            &self.retry_policy,
+            &mut |data| {
+                apply_chunk_to_text(
+                    data,
+                    &mut full_text,
+                    &mut finish_reason,
+                    &mut usage,
+                    &mut stop_filter,
+                    &mut done,
+                    |text| sink.emit(text),
+                );
+                sink.control_flow()
+            },
+        )?;
+
+        if response.status >= 400 {
+            return Err(LlmError::from_response(&response));
+        }
+        if sink.is_cancelled() {
+            finish_reason = FinishReason::Cancelled;
+        } else {
+            stop_filter.finish(&mut full_text, |text| sink.emit(text));
+        }
+
+        Ok(CompletionResult::new(full_text, None, finish_reason).with_usage(usage))
+    }
+
+    fn validate_api_key(&self) -> Result<(), LlmError> {
+        if self.api_key.trim().is_empty() {
+            return Err(LlmError::InvalidApiKey);
+        }
+        if self.resource.trim().is_empty() || self.deployment.trim().is_empty() {
+            return Err(LlmError::Other(
+                "Azure resource and deployment must be configured".into(),
+            ));
+        }
+
+        // Deployments have no cheap listing call, so ask for a single token
+        let messages = [Message::new(Role::User, String::from("Hi"))];
+        let config = GenerationConfig {
+            max_tokens: Some(1),
+            ..GenerationConfig::default()
+        };
+        let body = build_request_body(&messages, &self.deployment, &config, false);
+
+        let headers = [
+            ("api-key", self.api_key.as_str()),
+            ("Accept", "application/json"),
+        ];
+
+        let response = post_json_with_retry(
+            &self.http_client,
+            &self.endpoint_url(),
+            &body,
+            &headers,
+            self.get_time_ms,
+            self.sleep_ms,
+            &RetryPolicy::none(),
+        )?;
+
+        if response.status >= 400 {
+            return Err(LlmError::from_response(&response));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_url_uses_resource_deployment_and_version() {
+        let url = endpoint_url("contoso", "gpt4o-prod", "2024-06-01");
+        assert_eq!(
+            url,
+            "https://contoso.openai.azure.com/openai/deployments/gpt4o-prod/chat/completions?api-version=2024-06-01"
+        );
+    }
+
+    #[test]
+    fn endpoint_url_encodes_deployment_and_version() {
+        let url = endpoint_url("contoso", "gpt 4o/prod", "2024-06-01 preview");
+        assert_eq!(
+            url,
+            "https://contoso.openai.azure.com/openai/deployments/gpt%204o%2Fprod/chat/completions?api-version=2024-06-01%20preview"
+        );
+        assert!(network::parse_url(&url).is_ok());
+    }
+}
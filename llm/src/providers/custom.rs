@@ -0,0 +1,215 @@
+#![allow(unused_attributes)]
+#![no_std]
+
+extern crate alloc;
+
+use crate::providers::openai_compat::{
+    apply_chunk_to_text, build_request_body, fetch_model_list_at, validate_at,
+};
+use crate::retry::{stream_sse_with_retry, JsonPost, RetryPolicy};
+use crate::streaming::{StopSequenceFilter, TokenSink};
+use crate::types::{CompletionResult, FinishReason, GenerationConfig, Message, ModelInfo};
+use crate::{LlmError, LlmProvider};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+use network::{bearer, HttpClient, NetworkStack};
+use smoltcp::wire::Ipv4Address;
+
+/// Paths below the base URL, which already carries the API version
+/// (e.g. `http://192.168.1.10:11434/v1`)
+const CHAT_COMPLETIONS_PATH: &str = "/chat/completions";
+const MODELS_PATH: &str = "/models";
+
+/// Client for a self-hosted server speaking the OpenAI chat completions API
+/// (Ollama, llama.cpp, vLLM, LM Studio, ...).
+///
+/// Requests carry a bearer token only when an API key is set, so local
+/// servers without authentication work with an empty key.
+pub struct OpenAiCompatClient {
+    base_url: String,
+    api_key: Option<String>,
+    http_client: HttpClient,
+    get_time_ms: fn() -> i64,
+    sleep_ms: Option<fn(i64)>,
+    retry_policy: RetryPolicy,
+    models: Vec<ModelInfo>,
+    /// Whether `models` holds the list fetched from the server
+    models_fetched: bool,
+}
+
+impl OpenAiCompatClient {
+    /// Create a client for `base_url`, including any version prefix such as `/v1`
+    ///
+    /// An empty `api_key` sends requests without authentication. `models` is
+    /// shown until the server's own list has been fetched.
+    pub fn new(
+        base_url: String,
+        api_key: String,
+        models: Vec<ModelInfo>,
+        dns_server: Ipv4Address,
+        get_time_ms: fn() -> i64,
+        sleep_ms: Option<fn(i64)>,
+    ) -> Self {
+        let api_key = Some(api_key).filter(|key| !key.trim().is_empty());
+        Self {
+            base_url,
+            api_key,
+            http_client: HttpClient::new(dns_server),
+            get_time_ms,
+            sleep_ms,
+            retry_policy: RetryPolicy::default(),
+            models,
+            models_fetched: false,
+        }
+    }
+
+    /// Override the retry policy used for completion requests
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Override the HTTP connect and read timeouts
+    pub fn with_timeouts(mut self, connect_timeout_ms: i64, read_timeout_ms: i64) -> Self {
+        self.http_client = self.http_client.with_timeouts(connect_timeout_ms, read_timeout_ms);
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url.trim_end_matches('/'), path)
+    }
+}
+
+impl LlmProvider for OpenAiCompatClient {
+    fn name(&self) -> &str {
+        "Custom"
+    }
+
+    fn models(&self) -> &[ModelInfo] {
+        &self.models
+    }
+
+    fn default_model(&self) -> &str {
+        self.models.first().map_or("", |model| model.id.as_str())
+    }
+
+    fn complete(
+        &mut self,
+        messages: &[Message],
+        model: &str,
+        config: &GenerationConfig,
+        on_token: &mut dyn FnMut(&str) -> ControlFlow<()>,
+    ) -> Result<CompletionResult, LlmError> {
+        let url = self.url(CHAT_COMPLETIONS_PATH);
+        let body = build_request_body(messages, model, config, true);
+
+        let auth_header = self.api_key.as_deref().map(bearer);
+        let mut headers = Vec::from([("Accept", "text/event-stream")]);
+        if let Some(auth_header) = auth_header.as_deref() {
+            headers.push(("Authorization", auth_header));
+        }
+
+        let mut full_text = String::new();
+        let mut finish_reason = FinishReason::Stop;
+        let mut usage = None;
+        let mut stop_filter = StopSequenceFilter::new(config.active_stop_sequences());
+        let mut done = false;
+        let mut sink = TokenSink::new(on_token);
+
+        let post = JsonPost {
+            url: &url,
+            body: &body,
+            headers: &headers,
+        };
+        let response = stream_sse_with_retry(
+            &self.http_client,
+            &post,
+            self.get_time_ms,
+            self.sleep_ms,
+            &self.retry_policy,
+            &mut |data| {
+                apply_chunk_to_text(
+                    data,
+                    &mut full_text,
+                    &mut finish_reason,
+                    &mut usage,
+                    &mut stop_filter,
+                    &mut done,
+                    |text| sink.emit(text),
+                );
+                sink.control_flow()
+            },
+        )?;
+
+        if response.status >= 400 {
+            return Err(LlmError::from_response(&response));
+        }
+        if sink.is_cancelled() {
+            finish_reason = FinishReason::Cancelled;
+        } else {
+            stop_filter.finish(&mut full_text, |text| sink.emit(text));
+        }
+
+        Ok(CompletionResult::new(full_text, None, finish_reason).with_usage(usage))
+    }
+
+    /// Check that the server answers; a key is only sent when one is set
+    fn validate_api_key(&self) -> Result<(), LlmError> {
+        validate_at(
+            &self.http_client,
+            &self.url(MODELS_PATH),
+            self.api_key.as_deref(),
+            self.get_time_ms,
+            self.sleep_ms,
+        )
+    }
+
+    fn fetch_models(&mut self, stack: &mut NetworkStack) -> Result<Vec<ModelInfo>, LlmError> {
+        if self.models_fetched {
+            return Ok(self.models.clone());
+        }
+        let models = fetch_model_list_at(
+            &self.http_client,
+            stack,
+            &self.url(MODELS_PATH),
+            self.api_key.as_deref(),
+            &self.models,
+            self.get_time_ms,
+            self.sleep_ms,
+        )?;
+        self.models = models.clone();
+        self.models_fetched = true;
+        Ok(models)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time_ms() -> i64 {
+        0
+    }
+
+    #[test]
+    fn empty_key_sends_no_auth_and_paths_follow_base_url() {
+        let models = Vec::from([ModelInfo::new("llama3.2".into(), "llama3.2".into(), 0, true)]);
+        let dns = Ipv4Address::new(8, 8, 8, 8);
+        let client = OpenAiCompatClient::new(
+            "http://192.168.1.10:11434/v1/".into(),
+            "  ".into(),
+            models,
+            dns,
+            time_ms,
+            None,
+        );
+        assert!(client.api_key.is_none());
+        assert_eq!(client.default_model(), "llama3.2");
+        assert_eq!(
+            client.url(CHAT_COMPLETIONS_PATH),
+            "http://192.168.1.10:11434/v1/chat/completions"
+        );
+    }
+}
@@ -1,10 +1,72 @@
 pub mod anthropic;
+pub mod azure;
+pub mod custom;
 pub mod groq;
 pub mod openai;
 pub mod openai_compat;
 pub mod xai;
 
 pub use anthropic::AnthropicClient;
+pub use azure::AzureOpenAiClient;
+pub use custom::OpenAiCompatClient;
 pub use groq::GroqClient;
 pub use openai::OpenAiClient;
 pub use xai::XaiClient;
+
+use crate::error::LlmError;
+use crate::types::Message;
+use alloc::format;
+use alloc::string::String;
+
+/// Check a configured base URL, returning it without a trailing slash.
+///
+/// `config_key` names the offending setting in the error (e.g.
+/// "providers.openai.base_url").
+pub fn parse_base_url(base_url: &str, config_key: &str) -> Result<String, LlmError> {
+    let invalid = |reason: &str| {
+        LlmError::Other(format!(
+            "invalid {} '{}': {}",
+            config_key, base_url, reason
+        ))
+    };
+    let trimmed = base_url.trim().trim_end_matches('/');
+    if trimmed.chars().any(char::is_whitespace) {
+        return Err(invalid("contains whitespace"));
+    }
+    let parsed = network::parse_url(trimmed).map_err(|e| invalid(&format!("{}", e)))?;
+    if parsed.host.is_empty() {
+        return Err(invalid("missing host"));
+    }
+    if parsed.path_and_query.contains('?') {
+        return Err(invalid("must not contain a query string"));
+    }
+    Ok(String::from(trimmed))
+}
+
+/// Reject conversations containing images, for providers without vision support.
+pub fn ensure_text_only(messages: &[Message], provider: &str) -> Result<(), LlmError> {
+    if messages.iter().any(|m| m.content.has_images()) {
+        return Err(LlmError::UnsupportedContent(format!(
+            "{} does not accept image input",
+            provider
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_url_is_normalized_or_rejected_with_key() {
+        assert_eq!(
+            parse_base_url("https://proxy.local:8443/", "providers.openai.base_url").unwrap(),
+            "https://proxy.local:8443"
+        );
+        let err = parse_base_url("ftp://proxy.local", "providers.groq.base_url").unwrap_err();
+        assert!(matches!(&err, LlmError::Other(msg) if msg.contains("providers.groq.base_url")));
+        assert!(parse_base_url("https://", "providers.xai.base_url").is_err());
+        assert!(parse_base_url("https://a.b/?x=1", "providers.xai.base_url").is_err());
+    }
+}
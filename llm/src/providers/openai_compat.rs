@@ -3,15 +3,71 @@
 
 extern crate alloc;
 
-use crate::types::{FinishReason, GenerationConfig, Message, Role};
+use crate::logging::{log_request, log_response};
+use crate::streaming::StopSequenceFilter;
+use crate::types::{
+    ContentPart, FinishReason, GenerationConfig, Message, MessageContent, ModelInfo, Role, Usage,
+};
+use crate::LlmError;
 use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use miniserde::Deserialize;
+use network::{HttpClient, HttpResponse, NetworkStack, RequestBuilder};
+
+pub const MODELS_PATH: &str = "/v1/models";
+
+/// Body limit for model lists, which can outgrow the client's default on
+/// servers that host many models
+const MODEL_LIST_MAX_BODY_BYTES: usize = 8 * 1024 * 1024;
+
+/// Model ids containing these fragments are not chat models.
+const NON_CHAT_MODEL_MARKERS: [&str; 12] = [
+    "embedding",
+    "whisper",
+    "tts",
+    "dall-e",
+    "davinci",
+    "babbage",
+    "moderation",
+    "transcribe",
+    "realtime",
+    "audio",
+    "image",
+    "guard",
+];
 
 #[derive(Deserialize)]
 pub struct ChatCompletionChunk {
     pub choices: Vec<ChatCompletionChoice>,
+    /// Present on the final chunk when `stream_options.include_usage` is set.
+    pub usage: Option<ChatCompletionUsage>,
+    /// Groq reports usage here on the final chunk.
+    pub x_groq: Option<GroqExtension>,
+}
+
+#[derive(Deserialize)]
+pub struct ChatCompletionUsage {
+    pub prompt_tokens: Option<usize>,
+    pub completion_tokens: Option<usize>,
+    pub total_tokens: Option<usize>,
+}
+
+impl ChatCompletionUsage {
+    fn to_usage(&self) -> Usage {
+        let prompt = self.prompt_tokens.unwrap_or(0);
+        let completion = self.completion_tokens.unwrap_or(0);
+        Usage {
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            total_tokens: self.total_tokens.unwrap_or(prompt + completion),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct GroqExtension {
+    pub usage: Option<ChatCompletionUsage>,
 }
 
 #[derive(Deserialize)]
@@ -25,11 +81,230 @@ pub struct ChatCompletionDelta {
     pub content: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct ModelList {
+    pub data: Vec<ModelListEntry>,
+}
+
+#[derive(Deserialize)]
+pub struct ModelListEntry {
+    pub id: String,
+    /// Groq reports the context window; OpenAI and xAI do not.
+    pub context_window: Option<usize>,
+    /// Groq marks decommissioned models as inactive.
+    pub active: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct EmbeddingList {
+    pub data: Vec<EmbeddingEntry>,
+}
+
+#[derive(Deserialize)]
+pub struct EmbeddingEntry {
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
+/// Build a `/v1/embeddings` request body for `texts`.
+pub fn build_embedding_request_body(texts: &[&str], model: &str) -> String {
+    let mut out = String::new();
+    out.push_str("{\"model\":\"");
+    push_json_escaped(&mut out, model);
+    out.push_str("\",\"input\":[");
+    for (i, text) in texts.iter().enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+        out.push('"');
+        push_json_escaped(&mut out, text);
+        out.push('"');
+    }
+    out.push_str("]}");
+    out
+}
+
+/// Parse a `/v1/embeddings` response into vectors ordered by input index.
+///
+/// `expected` is the number of inputs sent; a response with a different
+/// count is rejected.
+pub fn parse_embedding_response(body: &str, expected: usize) -> Result<Vec<Vec<f32>>, LlmError> {
+    let mut list = miniserde::json::from_str::<EmbeddingList>(body)
+        .map_err(|_| LlmError::ParseError("invalid embeddings response".into()))?;
+    if list.data.len() != expected {
+        return Err(LlmError::ParseError(format!(
+            "expected {} embeddings, got {}",
+            expected,
+            list.data.len()
+        )));
+    }
+    list.data.sort_by_key(|entry| entry.index);
+    Ok(list.data.into_iter().map(|entry| entry.embedding).collect())
+}
+
+/// Whether a model id from `/v1/models` looks like a chat completion model.
+pub fn is_chat_model(id: &str) -> bool {
+    !NON_CHAT_MODEL_MARKERS.iter().any(|marker| id.contains(marker))
+}
+
+/// Parse a `/v1/models` response into chat-capable models.
+///
+/// Names and context lengths are taken from `known` when the id matches,
+/// so the static metadata is kept for models we already describe.
+pub fn parse_model_list(body: &str, known: &[ModelInfo]) -> Result<Vec<ModelInfo>, LlmError> {
+    let list = miniserde::json::from_str::<ModelList>(body)
+        .map_err(|_| LlmError::ParseError("invalid model list".into()))?;
+
+    let mut models: Vec<ModelInfo> = list
+        .data
+        .into_iter()
+        .filter(|entry| entry.active != Some(false) && is_chat_model(&entry.id))
+        .map(|entry| match known.iter().find(|m| m.id == entry.id) {
+            Some(info) => info.clone(),
+            None => ModelInfo::new(
+                entry.id.clone(),
+                entry.id,
+                entry.context_window.unwrap_or(0),
+                true,
+            ),
+        })
+        .collect();
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(models)
+}
+
+/// GET `{base_url}/v1/models` with a bearer token and parse the result.
+pub fn fetch_model_list(
+    http_client: &HttpClient,
+    stack: &mut NetworkStack,
+    base_url: &str,
+    api_key: &str,
+    known: &[ModelInfo],
+    get_time_ms: fn() -> i64,
+    sleep_ms: Option<fn(i64)>,
+) -> Result<Vec<ModelInfo>, LlmError> {
+    if api_key.trim().is_empty() {
+        return Err(LlmError::InvalidApiKey);
+    }
+    fetch_model_list_at(
+        http_client,
+        stack,
+        &models_url(base_url),
+        Some(api_key),
+        known,
+        get_time_ms,
+        sleep_ms,
+    )
+}
+
+/// GET a full models URL and parse the result.
+///
+/// Servers that need no key (e.g. a local Ollama) are queried without an
+/// `Authorization` header when `api_key` is `None`.
+pub fn fetch_model_list_at(
+    http_client: &HttpClient,
+    stack: &mut NetworkStack,
+    url: &str,
+    api_key: Option<&str>,
+    known: &[ModelInfo],
+    get_time_ms: fn() -> i64,
+    sleep_ms: Option<fn(i64)>,
+) -> Result<Vec<ModelInfo>, LlmError> {
+    let response = request_model_list(http_client, stack, url, api_key, get_time_ms, sleep_ms)?;
+
+    let body = core::str::from_utf8(&response.body)
+        .map_err(|e| LlmError::ParseError(format!("invalid utf-8 model list: {e}")))?;
+    let models = parse_model_list(body, known)?;
+    if models.is_empty() {
+        return Err(LlmError::ParseError("no chat models listed".into()));
+    }
+    Ok(models)
+}
+
+/// Check an API key by listing models, which costs no tokens.
+///
+/// Uses the global network stack. An unreachable host comes back as
+/// `LlmError::NetworkError`, a rejected key as `LlmError::InvalidApiKey`.
+pub fn validate_api_key(
+    http_client: &HttpClient,
+    base_url: &str,
+    api_key: &str,
+    get_time_ms: fn() -> i64,
+    sleep_ms: Option<fn(i64)>,
+) -> Result<(), LlmError> {
+    if api_key.trim().is_empty() {
+        return Err(LlmError::InvalidApiKey);
+    }
+    validate_at(http_client, &models_url(base_url), Some(api_key), get_time_ms, sleep_ms)
+}
+
+/// Check that a full models URL answers, optionally with a bearer token.
+pub fn validate_at(
+    http_client: &HttpClient,
+    url: &str,
+    api_key: Option<&str>,
+    get_time_ms: fn() -> i64,
+    sleep_ms: Option<fn(i64)>,
+) -> Result<(), LlmError> {
+    let mut stack_guard = network::get_network_stack();
+    let stack = stack_guard
+        .as_mut()
+        .ok_or_else(|| LlmError::NetworkError("network stack not initialized".into()))?;
+    request_model_list(http_client, stack, url, api_key, get_time_ms, sleep_ms)?;
+    Ok(())
+}
+
+fn models_url(base_url: &str) -> String {
+    format!("{}{MODELS_PATH}", base_url.trim_end_matches('/'))
+}
+
+/// GET a models URL, mapping error statuses to `LlmError`.
+fn request_model_list(
+    http_client: &HttpClient,
+    stack: &mut NetworkStack,
+    url: &str,
+    api_key: Option<&str>,
+    get_time_ms: fn() -> i64,
+    sleep_ms: Option<fn(i64)>,
+) -> Result<HttpResponse, LlmError> {
+    let mut request = RequestBuilder::get(url)
+        .header("Accept", "application/json")
+        .max_body_bytes(MODEL_LIST_MAX_BODY_BYTES);
+    if let Some(key) = api_key {
+        request = request.bearer_auth(key);
+    }
+
+    let mut get_time_ms = get_time_ms;
+    let mut sleep_ms = sleep_ms;
+    log_request("GET", url, &request.headers());
+    let response = request
+        .send(http_client, stack, &mut get_time_ms, sleep_ms.as_mut(), None)
+        .map_err(LlmError::from)?;
+    log_response(url, response.status, &response.body);
+
+    if response.status >= 400 {
+        return Err(LlmError::from_response(&response));
+    }
+    Ok(response)
+}
+
 pub fn build_request_body(
     messages: &[Message],
     model: &str,
     config: &GenerationConfig,
     stream: bool,
+) -> String {
+    build_request_body_with_usage(messages, model, config, stream, false)
+}
+
+/// Like `build_request_body`, optionally asking for a usage block in the final
+/// stream chunk (`stream_options.include_usage`, OpenAI-specific).
+pub fn build_request_body_with_usage(
+    messages: &[Message],
+    model: &str,
+    config: &GenerationConfig,
+    stream: bool,
+    include_usage: bool,
 ) -> String {
     let mut out = String::new();
     out.push_str("{\"model\":\"");
@@ -42,9 +317,9 @@ pub fn build_request_body(
         }
         out.push_str("{\"role\":\"");
         out.push_str(role_to_str(message.role));
-        out.push_str("\",\"content\":\"");
-        push_json_escaped(&mut out, &message.content);
-        out.push_str("\"}");
+        out.push_str("\",\"content\":");
+        push_message_content(&mut out, &message.content);
+        out.push('}');
     }
     out.push_str("],\"temperature\":");
     out.push_str(&format!("{}", config.temperature));
@@ -64,9 +339,26 @@ pub fn build_request_body(
         out.push_str(&format!("{}", top_k));
     }
 
-    if !config.stop_sequences.is_empty() {
+    if let Some(presence_penalty) = config.presence_penalty {
+        out.push_str(",\"presence_penalty\":");
+        out.push_str(&format!("{}", presence_penalty));
+    }
+
+    if let Some(frequency_penalty) = config.frequency_penalty {
+        out.push_str(",\"frequency_penalty\":");
+        out.push_str(&format!("{}", frequency_penalty));
+    }
+
+    if let Some(response_format) = config.response_format {
+        out.push_str(",\"response_format\":{\"type\":\"");
+        out.push_str(response_format.as_str());
+        out.push_str("\"}");
+    }
+
+    let stop_sequences = config.active_stop_sequences();
+    if !stop_sequences.is_empty() {
         out.push_str(",\"stop\":[");
-        for (i, stop) in config.stop_sequences.iter().enumerate() {
+        for (i, stop) in stop_sequences.iter().enumerate() {
             if i != 0 {
                 out.push(',');
             }
@@ -79,16 +371,25 @@ pub fn build_request_body(
 
     out.push_str(",\"stream\":");
     out.push_str(if stream { "true" } else { "false" });
+    if stream && include_usage {
+        out.push_str(",\"stream_options\":{\"include_usage\":true}");
+    }
     out.push('}');
     out
 }
 
+/// Apply one streamed chunk, passing content through `stop_filter`.
+///
+/// Once the filter sees a stop sequence the finish reason is pinned to
+/// `Stop` and further content is dropped; usage is still recorded.
 pub fn apply_chunk_to_text(
     data: &str,
     full_text: &mut String,
     finish_reason: &mut FinishReason,
+    usage: &mut Option<Usage>,
+    stop_filter: &mut StopSequenceFilter,
     done: &mut bool,
-    mut on_token: impl FnMut(&str),
+    on_token: impl FnMut(&str),
 ) {
     if *done {
         return;
@@ -104,11 +405,18 @@ pub fn apply_chunk_to_text(
         return;
     };
 
+    if let Some(u) = chunk.usage.as_ref() {
+        *usage = Some(u.to_usage());
+    } else if let Some(u) = chunk.x_groq.as_ref().and_then(|x| x.usage.as_ref()) {
+        *usage = Some(u.to_usage());
+    }
+
+    // The usage-only chunk has an empty choices array
     let Some(choice) = chunk.choices.first() else {
         return;
     };
 
-    if let Some(reason) = choice.finish_reason.as_deref() {
+    if let Some(reason) = choice.finish_reason.as_deref().filter(|_| !stop_filter.is_stopped()) {
         *finish_reason = match reason {
             "stop" => FinishReason::Stop,
             "length" => FinishReason::Length,
@@ -118,8 +426,44 @@ pub fn apply_chunk_to_text(
     }
 
     if let Some(content) = choice.delta.content.as_deref() {
-        on_token(content);
-        full_text.push_str(content);
+        if stop_filter.push(content, full_text, on_token) {
+            *finish_reason = FinishReason::Stop;
+        }
+    }
+}
+
+/// Write message content as a JSON string, or as an array of typed parts
+/// (`text` / `image_url` with a data URL) for vision models.
+fn push_message_content(out: &mut String, content: &MessageContent) {
+    match content {
+        MessageContent::Text(text) => {
+            out.push('"');
+            push_json_escaped(out, text);
+            out.push('"');
+        }
+        MessageContent::Parts(parts) => {
+            out.push('[');
+            for (i, part) in parts.iter().enumerate() {
+                if i != 0 {
+                    out.push(',');
+                }
+                match part {
+                    ContentPart::Text(text) => {
+                        out.push_str("{\"type\":\"text\",\"text\":\"");
+                        push_json_escaped(out, text);
+                        out.push_str("\"}");
+                    }
+                    ContentPart::ImageBase64 { mime, data } => {
+                        out.push_str("{\"type\":\"image_url\",\"image_url\":{\"url\":\"data:");
+                        push_json_escaped(out, mime);
+                        out.push_str(";base64,");
+                        push_json_escaped(out, data);
+                        out.push_str("\"}}");
+                    }
+                }
+            }
+            out.push(']');
+        }
     }
 }
 
@@ -158,3 +502,156 @@ fn nibble_to_hex(n: u8) -> char {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ResponseFormat;
+    use alloc::vec;
+
+    #[test]
+    fn usage_chunk_is_recorded() {
+        let mut text = String::new();
+        let mut reason = FinishReason::Stop;
+        let mut usage = None;
+        let mut done = false;
+        let mut stop = StopSequenceFilter::new(&[]);
+        let chunk = r#"{"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":3,"total_tokens":12}}"#;
+        apply_chunk_to_text(chunk, &mut text, &mut reason, &mut usage, &mut stop, &mut done, |_| {});
+        assert_eq!(usage, Some(Usage::new(9, 3)));
+    }
+
+    #[test]
+    fn groq_usage_is_recorded() {
+        let mut text = String::new();
+        let mut reason = FinishReason::Stop;
+        let mut usage = None;
+        let mut done = false;
+        let mut stop = StopSequenceFilter::new(&[]);
+        let chunk = r#"{"choices":[{"delta":{},"finish_reason":"stop"}],"x_groq":{"usage":{"prompt_tokens":4,"completion_tokens":6,"total_tokens":10}}}"#;
+        apply_chunk_to_text(chunk, &mut text, &mut reason, &mut usage, &mut stop, &mut done, |_| {});
+        assert_eq!(usage, Some(Usage::new(4, 6)));
+    }
+
+    #[test]
+    fn stop_sequence_truncates_and_pins_finish_reason() {
+        let stops = [String::from("\n\nUser:")];
+        let mut text = String::new();
+        let mut reason = FinishReason::Stop;
+        let mut usage = None;
+        let mut done = false;
+        let mut stop = StopSequenceFilter::new(&stops);
+        let chunks = [
+            r#"{"choices":[{"delta":{"content":"Sure.\n\nUs"},"finish_reason":null}]}"#,
+            r#"{"choices":[{"delta":{"content":"er: more"},"finish_reason":null}]}"#,
+            r#"{"choices":[{"delta":{},"finish_reason":"length"}]}"#,
+        ];
+        for chunk in chunks {
+            apply_chunk_to_text(chunk, &mut text, &mut reason, &mut usage, &mut stop, &mut done, |_| {});
+        }
+        stop.finish(&mut text, |_| {});
+        assert_eq!(text, "Sure.");
+        assert_eq!(reason, FinishReason::Stop);
+    }
+
+    #[test]
+    fn embeddings_are_ordered_by_index() {
+        let body = build_embedding_request_body(&["a", "say \"hi\""], "text-embedding-3-small");
+        assert_eq!(
+            body,
+            r#"{"model":"text-embedding-3-small","input":["a","say \"hi\""]}"#
+        );
+
+        let response = r#"{"object":"list","data":[
+            {"object":"embedding","index":1,"embedding":[0.5,-1.0]},
+            {"object":"embedding","index":0,"embedding":[0.25,0.0]}
+        ],"model":"text-embedding-3-small"}"#;
+        let vectors = parse_embedding_response(response, 2).unwrap();
+        assert_eq!(vectors, vec![vec![0.25, 0.0], vec![0.5, -1.0]]);
+        assert!(parse_embedding_response(response, 3).is_err());
+    }
+
+    #[test]
+    fn request_body_includes_penalties_and_caps_stops() {
+        let mut config = GenerationConfig::new();
+        config.presence_penalty = Some(0.5);
+        config.frequency_penalty = Some(1.0);
+        config.stop_sequences = ["a", "b", "c", "d", "e"].iter().map(|s| String::from(*s)).collect();
+        let body = build_request_body(&[], "m", &config, false);
+        assert!(body.contains("\"presence_penalty\":0.5"));
+        assert!(body.contains("\"frequency_penalty\":1"));
+        assert!(body.contains("\"stop\":[\"a\",\"b\",\"c\",\"d\"]"));
+    }
+
+    #[test]
+    fn request_body_omits_unset_fields() {
+        let body = build_request_body(&[], "m", &GenerationConfig::new(), false);
+        assert!(!body.contains("top_p"));
+        assert!(!body.contains("penalty"));
+        assert!(!body.contains("stop"));
+        assert!(!body.contains("response_format"));
+    }
+
+    #[test]
+    fn request_body_includes_response_format() {
+        let mut config = GenerationConfig::new();
+        config.response_format = Some(ResponseFormat::JsonObject);
+        let body = build_request_body(&[], "m", &config, false);
+        assert!(body.contains(",\"response_format\":{\"type\":\"json_object\"},"));
+        let body = build_request_body_with_usage(&[], "m", &config, true, true);
+        assert!(body.contains("\"response_format\":{\"type\":\"json_object\"}"));
+
+        config.response_format = Some(ResponseFormat::Text);
+        let body = build_request_body(&[], "m", &config, false);
+        assert!(body.contains("\"response_format\":{\"type\":\"text\"}"));
+    }
+
+    #[test]
+    fn request_body_serializes_image_parts_as_data_urls() {
+        let messages = [
+            Message::new(Role::System, String::from("Describe images.")),
+            Message::with_parts(
+                Role::User,
+                vec![
+                    ContentPart::Text(String::from("What is this?")),
+                    ContentPart::ImageBase64 {
+                        mime: String::from("image/jpeg"),
+                        data: String::from("/9j/4AAQ"),
+                    },
+                ],
+            ),
+        ];
+        let body = build_request_body(&messages, "m", &GenerationConfig::new(), false);
+        assert!(body.contains("{\"role\":\"system\",\"content\":\"Describe images.\"}"));
+        assert!(body.contains(
+            "\"content\":[{\"type\":\"text\",\"text\":\"What is this?\"},\
+             {\"type\":\"image_url\",\"image_url\":{\"url\":\"data:image/jpeg;base64,/9j/4AAQ\"}}]"
+        ));
+        assert!(crate::providers::ensure_text_only(&messages, "Groq").is_err());
+        assert!(crate::providers::ensure_text_only(&messages[..1], "Groq").is_ok());
+    }
+
+    #[test]
+    fn model_list_keeps_chat_models_and_known_metadata() {
+        let known = [ModelInfo::new("gpt-4o".into(), "GPT-4o".into(), 128_000, true)];
+        let body = r#"{"object":"list","data":[
+            {"id":"text-embedding-3-small","object":"model"},
+            {"id":"gpt-4o","object":"model"},
+            {"id":"gpt-4.1-mini","object":"model"},
+            {"id":"whisper-1","object":"model"},
+            {"id":"old-model","object":"model","active":false,"context_window":4096}
+        ]}"#;
+        let models = parse_model_list(body, &known).unwrap();
+        let ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["gpt-4.1-mini", "gpt-4o"]);
+        assert_eq!(models[1].name, "GPT-4o");
+        assert_eq!(models[1].context_length, 128_000);
+    }
+
+    #[test]
+    fn model_list_uses_groq_context_window() {
+        let body = r#"{"data":[{"id":"llama-3.3-70b-versatile","context_window":131072,"active":true}]}"#;
+        let models = parse_model_list(body, &[]).unwrap();
+        assert_eq!(models[0].context_length, 131_072);
+    }
+}
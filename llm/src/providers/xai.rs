@@ -3,14 +3,19 @@
 
 extern crate alloc;
 
-use crate::providers::openai_compat::{apply_chunk_to_text, build_request_body};
-use crate::streaming::for_each_sse_data;
+use crate::providers::ensure_text_only;
+use crate::providers::openai_compat::{self, 
+    apply_chunk_to_text, build_request_body_with_usage, fetch_model_list,
+};
+use crate::retry::{stream_sse_with_retry, JsonPost, RetryPolicy};
+use crate::streaming::{StopSequenceFilter, TokenSink};
 use crate::types::{CompletionResult, FinishReason, GenerationConfig, Message, ModelInfo};
 use crate::{LlmError, LlmProvider};
 use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use network::{get_network_stack, HttpClient};
+use core::ops::ControlFlow;
+use network::{bearer, HttpClient, NetworkStack};
 use smoltcp::wire::Ipv4Address;
 
 const DEFAULT_BASE_URL: &str = "https://api.x.ai";
@@ -23,7 +28,10 @@ pub struct XaiClient {
     base_url: String,
     get_time_ms: fn() -> i64,
     sleep_ms: Option<fn(i64)>,
+    retry_policy: RetryPolicy,
     models: Vec<ModelInfo>,
+    /// Whether `models` holds the list fetched from the API
+    models_fetched: bool,
 }
 
 impl XaiClient {
@@ -44,8 +52,10 @@ impl XaiClient {
         sleep_ms: Option<fn(i64)>,
     ) -> Self {
         let models = Vec::from([
-            ModelInfo::new("grok-2".into(), "Grok 2".into(), 128_000, true),
-            ModelInfo::new("grok-2-mini".into(), "Grok 2 Mini".into(), 128_000, true),
+            ModelInfo::new("grok-2".into(), "Grok 2".into(), 128_000, true)
+                .with_pricing(2_000_000, 10_000_000),
+            ModelInfo::new("grok-2-mini".into(), "Grok 2 Mini".into(), 128_000, true)
+                .with_pricing(200_000, 1_000_000),
         ]);
 
         Self {
@@ -54,17 +64,37 @@ impl XaiClient {
             base_url,
             get_time_ms,
             sleep_ms,
+            retry_policy: RetryPolicy::default(),
             models,
+            models_fetched: false,
         }
     }
 
+    /// Override the retry policy used for completion requests
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Send requests to `base_url` (e.g. a proxy) instead of the default host
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    /// Override the HTTP connect and read timeouts
+    pub fn with_timeouts(mut self, connect_timeout_ms: i64, read_timeout_ms: i64) -> Self {
+        self.http_client = self.http_client.with_timeouts(connect_timeout_ms, read_timeout_ms);
+        self
+    }
+
     fn endpoint_url(&self) -> String {
         let base = self.base_url.trim_end_matches('/');
         format!("{base}{CHAT_COMPLETIONS_PATH}")
     }
 
-    fn is_supported_model(model: &str) -> bool {
-        SUPPORTED_MODELS.iter().any(|m| *m == model)
+    fn is_supported_model(&self, model: &str) -> bool {
+        SUPPORTED_MODELS.contains(&model) || self.models.iter().any(|m| m.id == model)
     }
 }
 
@@ -86,75 +116,93 @@ impl LlmProvider for XaiClient {
         messages: &[Message],
         model: &str,
         config: &GenerationConfig,
-        mut on_token: &mut dyn FnMut(&str),
+        on_token: &mut dyn FnMut(&str) -> ControlFlow<()>,
     ) -> Result<CompletionResult, LlmError> {
         if self.api_key.trim().is_empty() {
-            return Err(LlmError::AuthError("missing API key".into()));
+            return Err(LlmError::InvalidApiKey);
         }
-        if !Self::is_supported_model(model) {
+        ensure_text_only(messages, self.name())?;
+        if !self.is_supported_model(model) {
             return Err(LlmError::InvalidModel(model.into()));
         }
 
         let url = self.endpoint_url();
-        let body = build_request_body(messages, model, config, true);
+        let body = build_request_body_with_usage(messages, model, config, true, true);
 
-        let auth_header = format!("Bearer {}", self.api_key);
+        let auth_header = bearer(&self.api_key);
         let headers = [
             ("Authorization", auth_header.as_str()),
             ("Accept", "text/event-stream"),
         ];
 
-        let mut guard = get_network_stack();
-        let stack = guard
-            .as_mut()
-            .ok_or_else(|| LlmError::NetworkError("network stack not initialized".into()))?;
-
-        let response = self
-            .http_client
-            .post_json(stack, &url, &body, &headers, self.get_time_ms, self.sleep_ms)
-            .map_err(|e| LlmError::NetworkError(e.to_string()))?;
-
-        if response.status == 401 || response.status == 403 {
-            return Err(LlmError::AuthError("unauthorized".into()));
-        }
-        if response.status == 429 {
-            let retry_after = response
-                .header("Retry-After")
-                .and_then(|v| v.trim().parse::<u64>().ok());
-            return Err(LlmError::RateLimitError { retry_after });
-        }
-        if response.status >= 400 {
-            let body_str = core::str::from_utf8(&response.body)
-                .map(|s| s.to_string())
-                .unwrap_or_else(|_| "<non-utf8 body>".into());
-            return Err(LlmError::HttpError {
-                status: response.status,
-                body: body_str,
-            });
-        }
-
-        let body_str = core::str::from_utf8(&response.body)
-            .map_err(|e| LlmError::ParseError(format!("invalid utf-8 SSE body: {e}")))?;
-
         let mut full_text = String::new();
         let mut finish_reason = FinishReason::Stop;
+        let mut usage = None;
+        let mut stop_filter = StopSequenceFilter::new(config.active_stop_sequences());
         let mut done = false;
+        let mut sink = TokenSink::new(on_token);
+
+        let post = JsonPost {
+            url: &url,
+            body: &body,
+            headers: &headers,
+        };
+        let response = stream_sse_with_retry(
+            &self.http_client,
+            &post,
+            self.get_time_ms,
+            self.sleep_ms,
+            &self.retry_policy,
+            &mut |data| {
+                apply_chunk_to_text(
+                    data,
+                    &mut full_text,
+                    &mut finish_reason,
+                    &mut usage,
+                    &mut stop_filter,
+                    &mut done,
+                    |text| sink.emit(text),
+                );
+                sink.control_flow()
+            },
+        )?;
 
-        for_each_sse_data(body_str, |data| {
-            apply_chunk_to_text(data, &mut full_text, &mut finish_reason, &mut done, &mut on_token);
-        });
+        if response.status >= 400 {
+            return Err(LlmError::from_response(&response));
+        }
+        if sink.is_cancelled() {
+            finish_reason = FinishReason::Cancelled;
+        } else {
+            stop_filter.finish(&mut full_text, |text| sink.emit(text));
+        }
 
-        Ok(CompletionResult::new(
-            full_text,
-            None,
-            finish_reason,
-        ))
+        Ok(CompletionResult::new(full_text, None, finish_reason).with_usage(usage))
     }
 
     fn validate_api_key(&self) -> Result<(), LlmError> {
-        if self.api_key.trim().is_empty() {
-            return Err(LlmError::AuthError("missing API key".into()));
+        openai_compat::validate_api_key(
+            &self.http_client,
+            &self.base_url,
+            &self.api_key,
+            self.get_time_ms,
+            self.sleep_ms,
+        )
+    }
+    fn fetch_models(&mut self, stack: &mut NetworkStack) -> Result<Vec<ModelInfo>, LlmError> {
+        if self.models_fetched {
+            return Ok(self.models.clone());
         }
-        Ok(())
+        let models = fetch_model_list(
+            &self.http_client,
+            stack,
+            &self.base_url,
+            &self.api_key,
+            &self.models,
+            self.get_time_ms,
+            self.sleep_ms,
+        )?;
+        self.models = models.clone();
+        self.models_fetched = true;
+        Ok(models)
     }
 }
@@ -0,0 +1,289 @@
+extern crate alloc;
+
+use crate::logging::{log_request, log_response};
+use crate::streaming::SseBody;
+use crate::LlmError;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+use network::{get_network_stack, HttpClient, HttpResponse, NetworkStack, RequestOptions};
+
+/// Retry policy for completion requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt (0 disables retrying).
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on each subsequent retry.
+    pub base_delay_ms: i64,
+    /// Upper bound for a single backoff delay (Retry-After is honored up to
+    /// `MAX_RETRY_AFTER_MS`).
+    pub max_delay_ms: i64,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub const fn none() -> Self {
+        Self {
+            max_retries: 0,
+            base_delay_ms: 0,
+            max_delay_ms: 0,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 8_000,
+        }
+    }
+}
+
+/// Longest wait a server's `Retry-After` can impose before a retry.
+///
+/// Rate limits usually ask for seconds; anything past this is treated as a
+/// bad header rather than stalling the kernel for hours.
+pub const MAX_RETRY_AFTER_MS: i64 = 60_000;
+
+/// Whether a response status is worth retrying.
+///
+/// Only rate limits and transient server errors qualify; client errors such as
+/// 400 (e.g. context length exceeded) or 401 would fail the same way again.
+pub fn is_retriable_status(status: u16) -> bool {
+    matches!(status, 429 | 500 | 502 | 503)
+}
+
+/// Parse a `Retry-After` header given in seconds.
+pub fn retry_after_secs(response: &HttpResponse) -> Option<u64> {
+    response
+        .header("Retry-After")
+        .and_then(|v| v.trim().parse::<u64>().ok())
+}
+
+/// Compute the delay before retry number `retry` (starting at 0).
+///
+/// A `Retry-After` value takes precedence, up to `MAX_RETRY_AFTER_MS`.
+/// Otherwise the delay grows exponentially from `base_delay_ms`, capped at
+/// `max_delay_ms`, with up to 25% jitter derived from `jitter_seed` so
+/// concurrent clients spread out.
+pub fn backoff_delay_ms(
+    policy: &RetryPolicy,
+    retry: u32,
+    retry_after: Option<u64>,
+    jitter_seed: i64,
+) -> i64 {
+    if let Some(secs) = retry_after {
+        return i64::try_from(secs)
+            .unwrap_or(i64::MAX)
+            .saturating_mul(1000)
+            .min(MAX_RETRY_AFTER_MS);
+    }
+
+    let exp = policy
+        .base_delay_ms
+        .saturating_mul(1i64 << retry.min(20))
+        .min(policy.max_delay_ms);
+    let jitter_range = exp / 4;
+    if jitter_range <= 0 {
+        return exp;
+    }
+
+    let mut x = jitter_seed as u64 ^ 0x9E37_79B9_7F4A_7C15;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    exp - jitter_range + (x % (jitter_range as u64 * 2 + 1)) as i64
+}
+
+/// Error describing a retriable status once retries are exhausted.
+pub fn status_error(response: &HttpResponse) -> LlmError {
+    LlmError::from_response(response)
+}
+
+/// POST a JSON body through the global network stack, retrying on 429/5xx.
+///
+/// Non-retriable responses (including errors such as 401 or 400) are returned
+/// unchanged so the caller can map them. If every attempt hits a retriable
+/// status, `LlmError::RetriesExhausted` wraps the last error.
+pub fn post_json_with_retry(
+    http_client: &HttpClient,
+    url: &str,
+    body: &str,
+    headers: &[(&str, &str)],
+    get_time_ms: fn() -> i64,
+    sleep_ms: Option<fn(i64)>,
+    policy: &RetryPolicy,
+) -> Result<HttpResponse, LlmError> {
+    send_with_retry(get_time_ms, sleep_ms, policy, |stack| {
+        log_request("POST", url, headers);
+        let response = http_client
+            .post_json(stack, url, body, headers, get_time_ms, sleep_ms)
+            .map_err(LlmError::from)?;
+        log_response(url, response.status, &response.body);
+        Ok(response)
+    })
+}
+
+/// A JSON POST for [`stream_sse_with_retry`]
+pub struct JsonPost<'a> {
+    pub url: &'a str,
+    pub body: &'a str,
+    pub headers: &'a [(&'a str, &'a str)],
+}
+
+/// POST a JSON body like `post_json_with_retry`, passing the data of each
+/// Server-Sent Event in the response to `on_event` as it arrives.
+///
+/// Returning `Break` from `on_event` stops reading and closes the
+/// connection. Error responses reach `on_event` too, but carry no events;
+/// the returned response holds the start of their body for
+/// `LlmError::from_response`. A successful response comes back bodiless.
+pub fn stream_sse_with_retry(
+    http_client: &HttpClient,
+    post: &JsonPost<'_>,
+    get_time_ms: fn() -> i64,
+    sleep_ms: Option<fn(i64)>,
+    policy: &RetryPolicy,
+    on_event: &mut dyn FnMut(&str) -> ControlFlow<()>,
+) -> Result<HttpResponse, LlmError> {
+    let mut headers: Vec<(&str, &str)> = post.headers.to_vec();
+    if !headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("Content-Type"))
+    {
+        headers.push(("Content-Type", "application/json"));
+    }
+
+    send_with_retry(get_time_ms, sleep_ms, policy, |stack| {
+        log_request("POST", post.url, &headers);
+        // A fresh parser per attempt, so a retried error body cannot run
+        // into the next response's first line
+        let mut body = SseBody::new();
+        let options = RequestOptions::new()
+            .method("POST")
+            .headers(&headers)
+            .body(post.body.as_bytes())
+            .unbounded_stream();
+        let mut get_time_ms = get_time_ms;
+        let mut sleep_ms = sleep_ms;
+        let mut response = http_client
+            .request_streaming(
+                stack,
+                post.url,
+                &mut get_time_ms,
+                sleep_ms.as_mut(),
+                options,
+                &mut |data| body.push(data, on_event),
+            )
+            .map_err(LlmError::from)?;
+        body.finish(on_event);
+        if response.status >= 400 {
+            response.body = body.into_head();
+        }
+        log_response(post.url, response.status, &response.body);
+        Ok(response)
+    })
+}
+
+/// Run `attempt` against the global network stack until it returns a
+/// response that is not worth retrying, or `policy` runs out.
+fn send_with_retry(
+    get_time_ms: fn() -> i64,
+    sleep_ms: Option<fn(i64)>,
+    policy: &RetryPolicy,
+    mut attempt: impl FnMut(&mut NetworkStack) -> Result<HttpResponse, LlmError>,
+) -> Result<HttpResponse, LlmError> {
+    let mut attempts: u32 = 0;
+    loop {
+        attempts += 1;
+
+        let response = {
+            let mut guard = get_network_stack();
+            let stack = guard
+                .as_mut()
+                .ok_or_else(|| LlmError::NetworkError("network stack not initialized".into()))?;
+            attempt(stack)?
+        };
+
+        if !is_retriable_status(response.status) {
+            return Ok(response);
+        }
+
+        if attempts > policy.max_retries {
+            let last = status_error(&response);
+            if attempts == 1 {
+                return Err(last);
+            }
+            return Err(LlmError::RetriesExhausted {
+                attempts,
+                last: Box::new(last),
+            });
+        }
+
+        let delay = backoff_delay_ms(
+            policy,
+            attempts - 1,
+            retry_after_secs(&response),
+            get_time_ms(),
+        );
+        wait_ms(delay, get_time_ms, sleep_ms);
+    }
+}
+
+fn wait_ms(delay_ms: i64, get_time_ms: fn() -> i64, sleep_ms: Option<fn(i64)>) {
+    if delay_ms <= 0 {
+        return;
+    }
+    match sleep_ms {
+        Some(sleep) => sleep(delay_ms),
+        None => {
+            let deadline = get_time_ms().saturating_add(delay_ms);
+            while get_time_ms() < deadline {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retriable_statuses() {
+        assert!(is_retriable_status(429));
+        assert!(is_retriable_status(503));
+        assert!(!is_retriable_status(400));
+        assert!(!is_retriable_status(401));
+    }
+
+    #[test]
+    fn backoff_honors_retry_after() {
+        let policy = RetryPolicy::default();
+        assert_eq!(backoff_delay_ms(&policy, 0, Some(2), 123), 2000);
+    }
+
+    #[test]
+    fn backoff_caps_huge_retry_after() {
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            backoff_delay_ms(&policy, 0, Some(86_400), 123),
+            MAX_RETRY_AFTER_MS
+        );
+        assert_eq!(
+            backoff_delay_ms(&policy, 0, Some(u64::MAX), 123),
+            MAX_RETRY_AFTER_MS
+        );
+    }
+
+    #[test]
+    fn backoff_grows_and_caps() {
+        let policy = RetryPolicy::default();
+        let first = backoff_delay_ms(&policy, 0, None, 7);
+        assert!((375..=625).contains(&first));
+        let capped = backoff_delay_ms(&policy, 10, None, 7);
+        assert!((6_000..=10_000).contains(&capped));
+    }
+}
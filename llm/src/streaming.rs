@@ -1,34 +1,537 @@
 extern crate alloc;
 
 use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
 
 /// Iterate Server-Sent Events payloads (`data: ...`) from a response body.
 ///
-/// This supports multi-line `data:` fields and dispatches an event when a blank line is reached.
+/// A whole-body wrapper around [`SseParser`]; an event not followed by a
+/// blank line is still dispatched at the end of the body.
 pub fn for_each_sse_data(body: &str, mut on_data: impl FnMut(&str)) {
-    let mut data_buf = String::new();
+    let mut parser = SseParser::new();
+    parser.feed(body.as_bytes(), &mut on_data);
+    parser.finish(&mut on_data);
+}
+
+/// Sentinel OpenAI-style streams send as the last event.
+pub const SSE_DONE: &str = "[DONE]";
+
+/// Incremental Server-Sent Events parser.
+///
+/// Bytes may arrive split anywhere, including mid-line. An event's `data:`
+/// lines are joined with `\n` and dispatched once the blank line ending the
+/// event is seen. Comment lines (`:` prefix) and other fields are ignored.
+/// The `[DONE]` sentinel is passed on and ends parsing.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    /// Bytes of the current, unterminated line
+    line: Vec<u8>,
+    /// Data of the event being collected
+    data: String,
+    has_data: bool,
+    done: bool,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the `[DONE]` sentinel has been seen; later input is ignored.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Feed received bytes, calling `on_event` for each completed event.
+    pub fn feed(&mut self, bytes: &[u8], mut on_event: impl FnMut(&str)) {
+        let mut rest = bytes;
+        while !self.done {
+            let Some(newline) = rest.iter().position(|&b| b == b'\n') else {
+                self.line.extend_from_slice(rest);
+                return;
+            };
+            self.line.extend_from_slice(&rest[..newline]);
+            rest = &rest[newline + 1..];
+            let line = core::mem::take(&mut self.line);
+            self.process_line(&line, &mut on_event);
+        }
+    }
 
-    for raw_line in body.lines() {
-        let line = raw_line.trim_end_matches('\r');
+    /// End of stream: dispatch an event left without its closing blank line.
+    pub fn finish(&mut self, mut on_event: impl FnMut(&str)) {
+        if self.done {
+            return;
+        }
+        let line = core::mem::take(&mut self.line);
+        if !line.is_empty() {
+            self.process_line(&line, &mut on_event);
+        }
+        self.dispatch(&mut on_event);
+    }
 
+    fn process_line(&mut self, line: &[u8], on_event: &mut impl FnMut(&str)) {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
         if line.is_empty() {
-            if !data_buf.is_empty() {
-                let data = data_buf.trim_end_matches('\n');
-                on_data(data);
-                data_buf.clear();
+            self.dispatch(on_event);
+            return;
+        }
+        if line.starts_with(b":") {
+            return;
+        }
+
+        let (field, value) = match line.iter().position(|&b| b == b':') {
+            Some(colon) => (&line[..colon], &line[colon + 1..]),
+            None => (line, &line[line.len()..]),
+        };
+        if field != b"data" {
+            return;
+        }
+        // Only a single leading space is part of the field separator
+        let value = value.strip_prefix(b" ").unwrap_or(value);
+        if self.has_data {
+            self.data.push('\n');
+        }
+        self.data.push_str(&String::from_utf8_lossy(value));
+        self.has_data = true;
+    }
+
+    fn dispatch(&mut self, on_event: &mut impl FnMut(&str)) {
+        if !self.has_data {
+            return;
+        }
+        let data = core::mem::take(&mut self.data);
+        self.has_data = false;
+        on_event(&data);
+        if data == SSE_DONE {
+            self.done = true;
+        }
+    }
+}
+
+/// Splits a streamed response body into Server-Sent Events as it arrives.
+///
+/// Each event's data goes to a callback that can stop the stream. The start
+/// of the body is kept as well, so an error response can still be reported
+/// from it.
+#[derive(Debug, Default)]
+pub struct SseBody {
+    parser: SseParser,
+    /// First `SSE_BODY_KEPT_BYTES` of the body
+    head: Vec<u8>,
+    stopped: bool,
+}
+
+/// Body bytes an [`SseBody`] keeps for error reporting.
+pub const SSE_BODY_KEPT_BYTES: usize = 16 * 1024;
+
+impl SseBody {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed received bytes; `Break` once `on_event` has asked to stop.
+    pub fn push(
+        &mut self,
+        bytes: &[u8],
+        on_event: &mut dyn FnMut(&str) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let room = SSE_BODY_KEPT_BYTES.saturating_sub(self.head.len());
+        self.head.extend_from_slice(&bytes[..bytes.len().min(room)]);
+
+        let stopped = &mut self.stopped;
+        self.parser.feed(bytes, |data| {
+            if !*stopped && on_event(data).is_break() {
+                *stopped = true;
+            }
+        });
+        if self.stopped {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    /// End of the body: dispatch an event left without its closing blank line.
+    pub fn finish(&mut self, on_event: &mut dyn FnMut(&str) -> ControlFlow<()>) {
+        if !self.stopped {
+            self.parser.finish(|data| {
+                let _ = on_event(data);
+            });
+        }
+    }
+
+    /// The start of the body, up to `SSE_BODY_KEPT_BYTES`.
+    pub fn into_head(self) -> Vec<u8> {
+        self.head
+    }
+}
+
+/// Client-side stop sequence enforcement for streamed text.
+///
+/// Some models ignore the `stop` request field. Text is passed through as it
+/// arrives, except for a tail that could still turn into a stop sequence,
+/// which is held back until the next chunk (or `finish`) decides it.
+pub struct StopSequenceFilter<'a> {
+    stops: &'a [String],
+    pending: String,
+    stopped: bool,
+}
+
+impl<'a> StopSequenceFilter<'a> {
+    pub fn new(stops: &'a [String]) -> Self {
+        Self {
+            stops,
+            pending: String::new(),
+            stopped: false,
+        }
+    }
+
+    /// Whether a stop sequence has been seen; later text is discarded.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Feed a streamed chunk, appending released text to `out` and `on_token`.
+    ///
+    /// Returns `true` if this chunk completed a stop sequence.
+    pub fn push(&mut self, chunk: &str, out: &mut String, mut on_token: impl FnMut(&str)) -> bool {
+        if self.stopped {
+            return false;
+        }
+        if self.stops.iter().all(|s| s.is_empty()) {
+            on_token(chunk);
+            out.push_str(chunk);
+            return false;
+        }
+
+        self.pending.push_str(chunk);
+
+        let first_match = self
+            .stops
+            .iter()
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| self.pending.find(s.as_str()))
+            .min();
+        if let Some(idx) = first_match {
+            let kept = &self.pending[..idx];
+            if !kept.is_empty() {
+                on_token(kept);
+                out.push_str(kept);
+            }
+            self.pending.clear();
+            self.stopped = true;
+            return true;
+        }
+
+        let release = self.pending.len() - self.partial_match_len();
+        if release > 0 {
+            let released: String = self.pending.drain(..release).collect();
+            on_token(&released);
+            out.push_str(&released);
+        }
+        false
+    }
+
+    /// Release any held-back text at the end of the stream.
+    pub fn finish(&mut self, out: &mut String, mut on_token: impl FnMut(&str)) {
+        if !self.stopped && !self.pending.is_empty() {
+            on_token(&self.pending);
+            out.push_str(&self.pending);
+        }
+        self.pending.clear();
+    }
+
+    /// Length of the longest suffix of `pending` that starts some stop sequence.
+    fn partial_match_len(&self) -> usize {
+        self.pending
+            .char_indices()
+            .map(|(i, _)| i)
+            .find(|&i| {
+                let tail = &self.pending[i..];
+                self.stops.iter().any(|s| s.starts_with(tail))
+            })
+            .map_or(0, |i| self.pending.len() - i)
+    }
+}
+
+/// Forwards streamed text to a caller's `on_token` callback and remembers
+/// whether it asked to stop.
+///
+/// Once the callback returns `ControlFlow::Break`, later text is dropped and
+/// providers finish with `FinishReason::Cancelled`.
+pub struct TokenSink<'a> {
+    on_token: &'a mut dyn FnMut(&str) -> ControlFlow<()>,
+    cancelled: bool,
+}
+
+impl<'a> TokenSink<'a> {
+    pub fn new(on_token: &'a mut dyn FnMut(&str) -> ControlFlow<()>) -> Self {
+        Self {
+            on_token,
+            cancelled: false,
+        }
+    }
+
+    /// Pass `text` to the callback unless generation was already cancelled.
+    pub fn emit(&mut self, text: &str) {
+        if !self.cancelled && (self.on_token)(text).is_break() {
+            self.cancelled = true;
+        }
+    }
+
+    /// Whether the callback asked to stop.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    /// `Break` once cancelled, so the rest of the stream is not read.
+    pub fn control_flow(&self) -> ControlFlow<()> {
+        if self.cancelled {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+/// Incremental UTF-8 decoder for text that arrives in arbitrary byte chunks.
+///
+/// A multi-byte character split across two reads is held back until the rest
+/// of it arrives, so only complete code points are emitted. Invalid bytes are
+/// replaced with U+FFFD and decoding carries on after them.
+#[derive(Debug, Default)]
+pub struct Utf8StreamDecoder {
+    /// Start of an incomplete sequence from the end of the last chunk
+    pending: Vec<u8>,
+}
+
+impl Utf8StreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode a chunk, passing any complete text to `on_text`.
+    pub fn push(&mut self, bytes: &[u8], mut on_text: impl FnMut(&str)) {
+        let joined;
+        let mut rest = if self.pending.is_empty() {
+            bytes
+        } else {
+            let mut buf = core::mem::take(&mut self.pending);
+            buf.extend_from_slice(bytes);
+            joined = buf;
+            &joined[..]
+        };
+
+        let mut text = String::new();
+        loop {
+            match core::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    text.push_str(core::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        None => {
+                            // Incomplete sequence at the end; wait for more bytes
+                            self.pending.extend_from_slice(after);
+                            break;
+                        }
+                    }
+                }
             }
-            continue;
         }
 
-        if let Some(rest) = line.strip_prefix("data:") {
-            let payload = rest.trim_start();
-            data_buf.push_str(payload);
-            data_buf.push('\n');
+        if !text.is_empty() {
+            on_text(&text);
+        }
+    }
+
+    /// End the stream; a dangling incomplete sequence becomes U+FFFD.
+    pub fn finish(&mut self, mut on_text: impl FnMut(&str)) {
+        if !self.pending.is_empty() {
+            self.pending.clear();
+            on_text("\u{FFFD}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    fn decode_chunks(chunks: &[&[u8]]) -> Vec<String> {
+        let mut decoder = Utf8StreamDecoder::new();
+        let mut pieces = Vec::new();
+        for chunk in chunks {
+            decoder.push(chunk, |t| pieces.push(String::from(t)));
+        }
+        decoder.finish(|t| pieces.push(String::from(t)));
+        pieces
+    }
+
+    #[test]
+    fn utf8_split_at_every_boundary_is_reassembled() {
+        // 2-, 3- and 4-byte sequences between ASCII
+        let text = "aé€😀b";
+        let bytes = text.as_bytes();
+        for split in 0..=bytes.len() {
+            let (head, tail) = bytes.split_at(split);
+            let pieces = decode_chunks(&[head, tail]);
+            assert_eq!(pieces.concat(), text, "split at {}", split);
+            assert!(!pieces.concat().contains('\u{FFFD}'));
+        }
+
+        // One byte at a time only ever emits whole characters
+        let chunks: Vec<&[u8]> = bytes.chunks(1).collect();
+        let pieces = decode_chunks(&chunks);
+        assert_eq!(pieces, vec!["a", "é", "€", "😀", "b"]);
+    }
+
+    #[test]
+    fn utf8_invalid_bytes_become_replacement_characters() {
+        assert_eq!(decode_chunks(&[b"a\xFFb"]).concat(), "a\u{FFFD}b");
+        // A truncated sequence followed by ASCII, across chunks
+        assert_eq!(decode_chunks(&[b"\xE2\x82", b"x"]).concat(), "\u{FFFD}x");
+        // A sequence still incomplete when the stream ends
+        assert_eq!(decode_chunks(&[b"ok\xF0\x9F"]).concat(), "ok\u{FFFD}");
+    }
+
+    #[test]
+    fn stop_sequence_split_across_chunks_is_truncated() {
+        let stops = vec![String::from("END")];
+        let mut filter = StopSequenceFilter::new(&stops);
+        let mut out = String::new();
+        let mut tokens: Vec<String> = Vec::new();
+        for chunk in ["Hello E", "N", "D and more"] {
+            filter.push(chunk, &mut out, |t| tokens.push(t.into()));
         }
+        filter.finish(&mut out, |t| tokens.push(t.into()));
+        assert!(filter.is_stopped());
+        assert_eq!(out, "Hello ");
+        assert_eq!(tokens.concat(), "Hello ");
+    }
+
+    #[test]
+    fn held_back_prefix_is_released_when_it_diverges() {
+        let stops = vec![String::from("###")];
+        let mut filter = StopSequenceFilter::new(&stops);
+        let mut out = String::new();
+        filter.push("a #", &mut out, |_| {});
+        assert_eq!(out, "a ");
+        filter.push("b", &mut out, |_| {});
+        filter.finish(&mut out, |_| {});
+        assert!(!filter.is_stopped());
+        assert_eq!(out, "a #b");
     }
 
-    if !data_buf.is_empty() {
-        let data = data_buf.trim_end_matches('\n');
-        on_data(data);
+    const SSE_STREAM: &str = ": keep-alive\r\n\
+        data: {\"a\":1}\r\n\r\n\
+        event: message\n\
+        data: first line\n\
+        data:second line\n\
+        id: 7\n\n\
+        data: caf\u{e9} \u{1F600}\n\n\
+        data: [DONE]\n\n\
+        data: ignored\n\n";
+
+    fn parse_in_chunks(stream: &[u8], split_at: &[usize]) -> Vec<String> {
+        let mut parser = SseParser::new();
+        let mut events: Vec<String> = Vec::new();
+        let mut start = 0;
+        for &end in split_at.iter().chain([stream.len()].iter()) {
+            parser.feed(&stream[start..end], |e| events.push(e.into()));
+            start = end;
+        }
+        parser.finish(|e| events.push(e.into()));
+        events
+    }
+
+    #[test]
+    fn sse_events_survive_any_split_point() {
+        let stream = SSE_STREAM.as_bytes();
+        let whole = parse_in_chunks(stream, &[]);
+        assert_eq!(
+            whole,
+            [
+                "{\"a\":1}",
+                "first line\nsecond line",
+                "caf\u{e9} \u{1F600}",
+                SSE_DONE
+            ]
+        );
+        for i in 0..=stream.len() {
+            assert_eq!(parse_in_chunks(stream, &[i]), whole, "split at {}", i);
+        }
+        // One byte at a time, splitting every multi-byte character too
+        let every_byte: Vec<usize> = (1..stream.len()).collect();
+        assert_eq!(parse_in_chunks(stream, &every_byte), whole);
+    }
+
+    #[test]
+    fn sse_done_stops_parsing_and_unterminated_event_is_flushed() {
+        let mut parser = SseParser::new();
+        let mut events: Vec<String> = Vec::new();
+        parser.feed(b"data: [DONE]\n\ndata: late\n\n", |e| events.push(e.into()));
+        assert!(parser.is_done());
+        assert_eq!(events, [SSE_DONE]);
+
+        let mut events: Vec<String> = Vec::new();
+        for_each_sse_data("data: tail", |e| events.push(e.into()));
+        assert_eq!(events, ["tail"]);
+    }
+
+    #[test]
+    fn sse_body_stops_after_break_and_keeps_its_head() {
+        let mut body = SseBody::new();
+        let mut events: Vec<String> = Vec::new();
+        let mut on_event = |data: &str| {
+            events.push(data.into());
+            if data == "stop" {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        };
+        let flow = body.push(b"data: a\n\nda", &mut on_event);
+        assert!(flow.is_continue());
+        let flow = body.push(b"ta: stop\n\ndata: b\n\n", &mut on_event);
+        assert!(flow.is_break());
+        body.finish(&mut on_event);
+        assert_eq!(events, ["a", "stop"]);
+        assert_eq!(body.into_head(), b"data: a\n\ndata: stop\n\ndata: b\n\n");
+
+        let mut body = SseBody::new();
+        let error = vec![b'x'; SSE_BODY_KEPT_BYTES + 10];
+        let flow = body.push(&error, &mut |_| ControlFlow::Continue(()));
+        assert!(flow.is_continue());
+        assert_eq!(body.into_head().len(), SSE_BODY_KEPT_BYTES);
+    }
+
+    #[test]
+    fn token_sink_drops_text_after_break() {
+        let mut seen: Vec<String> = Vec::new();
+        let mut on_token = |t: &str| {
+            seen.push(t.into());
+            if seen.len() == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        };
+        let mut sink = TokenSink::new(&mut on_token);
+        for chunk in ["a", "b", "c"] {
+            sink.emit(chunk);
+        }
+        assert!(sink.is_cancelled());
+        assert_eq!(seen, ["a", "b"]);
     }
 }
@@ -0,0 +1,96 @@
+//! Conversation titles.
+//!
+//! After the first exchange the kernel asks the model for a short title,
+//! used in the chat header and (later) for naming saved conversations.
+
+use alloc::string::String;
+use core::ops::ControlFlow;
+
+use crate::types::{GenerationConfig, Message, Role};
+use crate::{LlmError, LlmProvider};
+
+/// System prompt used to ask for a title.
+pub const TITLE_SYSTEM_PROMPT: &str =
+    "Summarize the user's message in 5 words or fewer. Reply with the title only.";
+
+/// Token budget for the title request.
+pub const TITLE_MAX_TOKENS: usize = 16;
+
+/// Longest title kept, in characters.
+pub const MAX_TITLE_CHARS: usize = 48;
+
+/// Ask `provider` for a short title summarizing `first_user_message`.
+///
+/// The reply is cleaned up with [`clean_title`]; an empty reply is reported
+/// as a parse error.
+pub fn generate_title<P: LlmProvider + ?Sized>(
+    provider: &mut P,
+    model: &str,
+    first_user_message: &str,
+) -> Result<String, LlmError> {
+    let messages = [
+        Message::new(Role::System, String::from(TITLE_SYSTEM_PROMPT)),
+        Message::new(Role::User, String::from(first_user_message)),
+    ];
+    let config = GenerationConfig {
+        temperature: 0.3,
+        max_tokens: Some(TITLE_MAX_TOKENS),
+        ..GenerationConfig::new()
+    };
+
+    let result = provider.complete(
+        &messages,
+        model,
+        &config,
+        &mut |_| ControlFlow::Continue(()),
+    )?;
+    let title = clean_title(&result.text);
+    if title.is_empty() {
+        return Err(LlmError::ParseError(String::from("empty title")));
+    }
+    Ok(title)
+}
+
+/// Tidy a model-written title: first line only, without surrounding quotes,
+/// a "Title:" prefix or trailing punctuation, capped at `MAX_TITLE_CHARS`.
+pub fn clean_title(raw: &str) -> String {
+    let line = raw.trim().lines().next().unwrap_or("").trim();
+    let line = line
+        .strip_prefix("Title:")
+        .or_else(|| line.strip_prefix("title:"))
+        .unwrap_or(line)
+        .trim();
+    let line = line
+        .trim_matches(|c| matches!(c, '"' | '\'' | '*' | '`'))
+        .trim_end_matches(['.', '!', ':'])
+        .trim();
+
+    let mut title: String = line.chars().take(MAX_TITLE_CHARS).collect();
+    if line.chars().count() > MAX_TITLE_CHARS {
+        title.truncate(title.trim_end().len());
+        title.push('…');
+    }
+    title
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_title_strips_quotes_prefix_and_punctuation() {
+        assert_eq!(
+            clean_title("\"Rust borrow checker help.\""),
+            "Rust borrow checker help"
+        );
+        assert_eq!(
+            clean_title("Title: Weekend trip ideas\nmore"),
+            "Weekend trip ideas"
+        );
+        assert_eq!(clean_title("   "), "");
+
+        let long = clean_title(&"word ".repeat(20));
+        assert!(long.ends_with('…'));
+        assert!(long.chars().count() <= MAX_TITLE_CHARS + 1);
+    }
+}
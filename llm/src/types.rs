@@ -1,20 +1,101 @@
 extern crate alloc;
 
+use alloc::borrow::Cow;
 use alloc::string::String;
 use alloc::vec::Vec;
+use micromath::F32Ext;
 
 /// Represents a message in a conversation with an LLM.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Message {
     pub role: Role,
-    pub content: String,
+    pub content: MessageContent,
 }
 
 impl Message {
-    /// Create a new message with the given role and content.
+    /// Create a new text message with the given role and content.
     pub fn new(role: Role, content: String) -> Self {
-        Self { role, content }
+        Self {
+            role,
+            content: MessageContent::Text(content),
+        }
     }
+
+    /// Create a message made of several parts (e.g. text plus images).
+    pub fn with_parts(role: Role, parts: Vec<ContentPart>) -> Self {
+        Self {
+            role,
+            content: MessageContent::Parts(parts),
+        }
+    }
+}
+
+/// The body of a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageContent {
+    /// Plain text.
+    Text(String),
+    /// A sequence of parts, for multimodal (vision) requests.
+    Parts(Vec<ContentPart>),
+}
+
+impl MessageContent {
+    /// The text of the message; text parts are joined with newlines and
+    /// images are skipped.
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            MessageContent::Text(text) => Cow::Borrowed(text),
+            MessageContent::Parts(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    if let ContentPart::Text(part_text) = part {
+                        if !text.is_empty() {
+                            text.push('\n');
+                        }
+                        text.push_str(part_text);
+                    }
+                }
+                Cow::Owned(text)
+            }
+        }
+    }
+
+    /// Whether any part is an image.
+    pub fn has_images(&self) -> bool {
+        match self {
+            MessageContent::Text(_) => false,
+            MessageContent::Parts(parts) => parts
+                .iter()
+                .any(|part| matches!(part, ContentPart::ImageBase64 { .. })),
+        }
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        MessageContent::Text(String::from(text))
+    }
+}
+
+impl PartialEq<&str> for MessageContent {
+    fn eq(&self, other: &&str) -> bool {
+        matches!(self, MessageContent::Text(text) if text == other)
+    }
+}
+
+/// One part of a multimodal message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentPart {
+    /// A run of text.
+    Text(String),
+    /// An image, base64-encoded, with its MIME type (e.g. "image/png").
+    ImageBase64 { mime: String, data: String },
 }
 
 /// Represents the role of a message in a conversation.
@@ -28,6 +109,30 @@ pub enum Role {
     Assistant,
 }
 
+/// Maximum number of stop sequences sent to a provider.
+///
+/// OpenAI rejects requests with more than four; extra entries are ignored.
+pub const MAX_STOP_SEQUENCES: usize = 4;
+
+/// Output format requested from the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// Free-form text (the provider default)
+    Text,
+    /// A single JSON object ("JSON mode")
+    JsonObject,
+}
+
+impl ResponseFormat {
+    /// Value of `response_format.type` in OpenAI-compatible requests
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ResponseFormat::Text => "text",
+            ResponseFormat::JsonObject => "json_object",
+        }
+    }
+}
+
 /// Configuration for text generation parameters.
 #[derive(Debug, Clone, PartialEq)]
 pub struct GenerationConfig {
@@ -36,12 +141,23 @@ pub struct GenerationConfig {
     /// Maximum number of tokens to generate. None means no limit.
     pub max_tokens: Option<usize>,
     /// Sequences that will stop generation when encountered.
+    /// Only the first `MAX_STOP_SEQUENCES` are used.
     pub stop_sequences: Vec<String>,
     /// Top-p (nucleus) sampling parameter (0.0-1.0).
     /// Samples from tokens with cumulative probability up to this value.
     pub top_p: Option<f32>,
     /// Top-k sampling parameter. Only sample from the top K most likely tokens.
     pub top_k: Option<usize>,
+    /// Presence penalty (-2.0-2.0). Positive values discourage tokens that
+    /// have appeared at all so far.
+    pub presence_penalty: Option<f32>,
+    /// Frequency penalty (-2.0-2.0). Positive values discourage tokens in
+    /// proportion to how often they have appeared so far.
+    pub frequency_penalty: Option<f32>,
+    /// Output format. None leaves it to the provider (plain text).
+    /// Anthropic has no JSON mode; `JsonObject` becomes a system-prompt
+    /// instruction there, and the local model ignores the setting.
+    pub response_format: Option<ResponseFormat>,
 }
 
 impl GenerationConfig {
@@ -53,6 +169,9 @@ impl GenerationConfig {
             stop_sequences: Vec::new(),
             top_p: None,
             top_k: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            response_format: None,
         }
     }
 
@@ -63,6 +182,12 @@ impl GenerationConfig {
             ..Self::new()
         }
     }
+
+    /// The stop sequences that are actually sent (at most `MAX_STOP_SEQUENCES`).
+    pub fn active_stop_sequences(&self) -> &[String] {
+        let len = self.stop_sequences.len().min(MAX_STOP_SEQUENCES);
+        &self.stop_sequences[..len]
+    }
 }
 
 impl Default for GenerationConfig {
@@ -82,6 +207,10 @@ pub struct ModelInfo {
     pub context_length: usize,
     /// Whether this model supports streaming responses.
     pub supports_streaming: bool,
+    /// Price of prompt tokens in micro-dollars per million tokens, if known.
+    pub input_price_per_mtok: Option<u64>,
+    /// Price of completion tokens in micro-dollars per million tokens, if known.
+    pub output_price_per_mtok: Option<u64>,
 }
 
 impl ModelInfo {
@@ -92,6 +221,47 @@ impl ModelInfo {
             name,
             context_length,
             supports_streaming,
+            input_price_per_mtok: None,
+            output_price_per_mtok: None,
+        }
+    }
+
+    /// Set the input/output prices, in micro-dollars per million tokens
+    /// (e.g. `2_500_000` for $2.50/M).
+    pub fn with_pricing(mut self, input_price_per_mtok: u64, output_price_per_mtok: u64) -> Self {
+        self.input_price_per_mtok = Some(input_price_per_mtok);
+        self.output_price_per_mtok = Some(output_price_per_mtok);
+        self
+    }
+
+    /// Cost of a request in micro-dollars, or `None` if the price is unknown.
+    ///
+    /// Integer arithmetic only; fractions of a micro-dollar are dropped.
+    pub fn cost_micros(&self, usage: &Usage) -> Option<u64> {
+        let input = (usage.prompt_tokens as u64).saturating_mul(self.input_price_per_mtok?);
+        let output = (usage.completion_tokens as u64).saturating_mul(self.output_price_per_mtok?);
+        Some(input.saturating_add(output) / 1_000_000)
+    }
+}
+
+/// Token usage reported by a provider for a single request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Tokens consumed by the prompt (input).
+    pub prompt_tokens: usize,
+    /// Tokens generated in the completion (output).
+    pub completion_tokens: usize,
+    /// Total tokens billed for the request.
+    pub total_tokens: usize,
+}
+
+impl Usage {
+    /// Create a usage record; the total is the sum of prompt and completion tokens.
+    pub fn new(prompt_tokens: usize, completion_tokens: usize) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
         }
     }
 }
@@ -103,6 +273,8 @@ pub struct CompletionResult {
     pub text: String,
     /// Number of tokens used in the completion (if available).
     pub tokens_used: Option<usize>,
+    /// Detailed token usage (if the provider reported it).
+    pub usage: Option<Usage>,
     /// Reason why the generation stopped.
     pub finish_reason: FinishReason,
 }
@@ -113,9 +285,34 @@ impl CompletionResult {
         Self {
             text,
             tokens_used,
+            usage: None,
             finish_reason,
         }
     }
+
+    /// Attach token usage; `tokens_used` is set to the total when present.
+    pub fn with_usage(mut self, usage: Option<Usage>) -> Self {
+        if let Some(usage) = usage {
+            self.tokens_used = Some(usage.total_tokens);
+        }
+        self.usage = usage;
+        self
+    }
+
+    /// Prompt (input) tokens, if the provider reported usage.
+    pub fn prompt_tokens(&self) -> Option<usize> {
+        self.usage.map(|usage| usage.prompt_tokens)
+    }
+
+    /// Completion (output) tokens, if the provider reported usage.
+    pub fn completion_tokens(&self) -> Option<usize> {
+        self.usage.map(|usage| usage.completion_tokens)
+    }
+
+    /// Total billed tokens, if the provider reported usage.
+    pub fn total_tokens(&self) -> Option<usize> {
+        self.usage.map(|usage| usage.total_tokens)
+    }
 }
 
 /// Reason why text generation stopped.
@@ -127,6 +324,45 @@ pub enum FinishReason {
     Length,
     /// Generation stopped due to content filtering.
     ContentFilter,
+    /// Generation was stopped by the caller's `on_token` callback.
+    Cancelled,
     /// Generation stopped for another reason (with description).
     Other(String),
 }
+
+/// Default number of texts sent in one embeddings request.
+pub const DEFAULT_EMBEDDING_BATCH_SIZE: usize = 64;
+
+/// Cosine similarity of two embedding vectors, in `-1.0..=1.0`.
+///
+/// Returns `0.0` when the lengths differ or either vector is all zeros, so
+/// unusable pairs rank last rather than producing NaN.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let mut dot = 0.0f32;
+    let mut norm_a = 0.0f32;
+    let mut norm_b = 0.0f32;
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / sqrt(norm_a * norm_b)
+}
+
+/// Square root of a positive `x` without std
+///
+/// micromath's estimate is only good to a few percent, so it is refined
+/// with two Newton steps.
+fn sqrt(x: f32) -> f32 {
+    let mut root = F32Ext::sqrt(x);
+    for _ in 0..2 {
+        root = (root + x / root) / 2.0;
+    }
+    root
+}
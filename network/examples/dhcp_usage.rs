@@ -42,10 +42,8 @@
 //!                 println!("DNS {}: {}", i + 1, dns);
 //!             }
 //!
-//!             // Apply the configuration
-//!             stack.apply_dhcp_config(&config)
-//!                 .expect("Failed to apply DHCP config");
-//!
+//!             // poll() has already applied the configuration, and keeps
+//!             // renewing the lease as long as it is called
 //!             break;
 //!         }
 //!     }
@@ -16,8 +16,8 @@
 extern crate alloc;
 
 use alloc::vec::Vec;
-use smoltcp::socket::dhcpv4::{Event, Socket};
-use smoltcp::wire::Ipv4Address;
+use smoltcp::socket::dhcpv4::{Config, Event, Socket};
+use smoltcp::wire::{DhcpPacket, DhcpRepr, Ipv4Address};
 
 pub type DhcpSocket = Socket<'static>;
 
@@ -90,6 +90,121 @@ impl core::fmt::Display for DhcpState {
     }
 }
 
+/// Lease length assumed when the server sends none (smoltcp's default)
+const DEFAULT_LEASE_SECS: u32 = 120;
+
+/// Timers of a DHCP lease, on the same millisecond clock passed to `poll`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DhcpLease {
+    /// When the ACK granting (or extending) the lease was processed
+    pub acquired_at_ms: i64,
+    /// T1: from here on the lease is renewed with the server that granted it
+    pub renew_at_ms: i64,
+    /// T2: from here on any server may extend the lease
+    pub rebind_at_ms: i64,
+    /// The address must be given up here
+    pub expires_at_ms: i64,
+}
+
+impl DhcpLease {
+    /// Lease timers from the durations in a DHCP ACK
+    ///
+    /// Missing T1 and T2 default to 50% and 87.5% of the lease (RFC 2131
+    /// section 4.4.5). When only one is given, the other is derived the way
+    /// smoltcp does, so these timers match when the socket acts on them.
+    pub fn from_durations(
+        now_ms: i64,
+        lease_secs: Option<u32>,
+        renew_secs: Option<u32>,
+        rebind_secs: Option<u32>,
+    ) -> Self {
+        let lease = lease_secs.unwrap_or(DEFAULT_LEASE_SECS) as i64 * 1000;
+        let (renew, rebind) = match (renew_secs, rebind_secs) {
+            (Some(renew), Some(rebind)) => (renew as i64 * 1000, rebind as i64 * 1000),
+            (None, None) => (lease / 2, lease * 7 / 8),
+            (Some(renew), None) => {
+                let renew = renew as i64 * 1000;
+                (renew, renew + (lease - renew) * 3 / 4)
+            }
+            (None, Some(rebind)) => {
+                let rebind = rebind as i64 * 1000;
+                ((lease / 2).min(rebind), rebind)
+            }
+        };
+        Self {
+            acquired_at_ms: now_ms,
+            renew_at_ms: now_ms + renew,
+            rebind_at_ms: now_ms + rebind,
+            expires_at_ms: now_ms + lease,
+        }
+    }
+
+    /// Lease timers from the raw DHCP ACK
+    pub fn from_ack(now_ms: i64, packet: &DhcpPacket<&[u8]>) -> Self {
+        match DhcpRepr::parse(packet) {
+            Ok(repr) => Self::from_durations(
+                now_ms,
+                repr.lease_duration,
+                repr.renew_duration,
+                repr.rebind_duration,
+            ),
+            Err(_) => Self::from_durations(now_ms, None, None, None),
+        }
+    }
+
+    /// Milliseconds until the lease expires (0 once it has)
+    pub fn remaining_ms(&self, now_ms: i64) -> i64 {
+        (self.expires_at_ms - now_ms).max(0)
+    }
+
+    /// Where the client is in the lease's life at `now_ms`
+    pub fn state_at(&self, now_ms: i64) -> DhcpState {
+        if now_ms < self.renew_at_ms {
+            DhcpState::Configured
+        } else if now_ms < self.rebind_at_ms {
+            DhcpState::Renewing
+        } else if now_ms < self.expires_at_ms {
+            DhcpState::Rebinding
+        } else {
+            DhcpState::Discovering
+        }
+    }
+}
+
+/// A change reported by the DHCP socket
+#[derive(Debug, Clone, PartialEq)]
+pub enum DhcpUpdate {
+    /// A lease was granted or extended
+    Configured(IpConfig, DhcpLease),
+    /// The lease was lost (expired or refused); discovery starts over
+    Deconfigured,
+}
+
+/// Take the socket's pending change, if any
+///
+/// Lease timers are only available when the socket was given a receive
+/// packet buffer; otherwise smoltcp's default lease length is assumed.
+pub fn poll_update(socket: &mut DhcpSocket, now_ms: i64) -> Option<DhcpUpdate> {
+    match socket.poll()? {
+        Event::Configured(config) => {
+            let lease = match &config.packet {
+                Some(packet) => DhcpLease::from_ack(now_ms, packet),
+                None => DhcpLease::from_durations(now_ms, None, None, None),
+            };
+            Some(DhcpUpdate::Configured(ip_config_from(&config), lease))
+        }
+        Event::Deconfigured => Some(DhcpUpdate::Deconfigured),
+    }
+}
+
+/// IpConfig from the configuration smoltcp reports
+fn ip_config_from(config: &Config) -> IpConfig {
+    let mut ip_config = IpConfig::new(config.address.address(), config.address.prefix_len());
+    ip_config.gateway = config.router;
+    ip_config.dns.extend(config.dns_servers.iter().copied());
+    ip_config
+}
+
 /// Extract IP configuration from DHCP socket after successful acquisition
 ///
 /// # Arguments
@@ -100,25 +215,7 @@ impl core::fmt::Display for DhcpState {
 /// * `None` - No configuration available yet
 pub fn extract_config(socket: &mut DhcpSocket) -> Option<IpConfig> {
     match socket.poll()? {
-        Event::Configured(config) => {
-    // Extract IP address and prefix length
-            let ip = config.address.address();
-            let prefix_len = config.address.prefix_len();
-
-    let mut ip_config = IpConfig::new(ip, prefix_len);
-
-    // Extract gateway (router)
-    if let Some(router) = config.router {
-        ip_config.gateway = Some(router);
-    }
-
-    // Extract DNS servers
-            for dns in config.dns_servers.iter() {
-            ip_config.dns.push(*dns);
-            }
-
-            Some(ip_config)
-        }
+        Event::Configured(config) => Some(ip_config_from(&config)),
         Event::Deconfigured => None,
     }
 }
@@ -192,6 +289,33 @@ mod tests {
         assert_eq!(config.dns[1], dns2);
     }
 
+    #[test]
+    fn test_lease_default_timers() {
+        let lease = DhcpLease::from_durations(1_000, Some(3600), None, None);
+        assert_eq!(lease.renew_at_ms, 1_000 + 1_800_000);
+        assert_eq!(lease.rebind_at_ms, 1_000 + 3_150_000);
+        assert_eq!(lease.expires_at_ms, 1_000 + 3_600_000);
+
+        let only_t1 = DhcpLease::from_durations(0, Some(1000), Some(200), None);
+        assert_eq!(only_t1.rebind_at_ms, 800_000);
+        let only_t2 = DhcpLease::from_durations(0, Some(1000), None, Some(300));
+        assert_eq!(only_t2.renew_at_ms, 300_000);
+
+        let no_lease = DhcpLease::from_durations(0, None, None, None);
+        assert_eq!(no_lease.expires_at_ms, 120_000);
+    }
+
+    #[test]
+    fn test_lease_state_and_remaining_time() {
+        let lease = DhcpLease::from_durations(0, Some(100), Some(50), Some(80));
+        assert_eq!(lease.state_at(0), DhcpState::Configured);
+        assert_eq!(lease.state_at(50_000), DhcpState::Renewing);
+        assert_eq!(lease.state_at(80_000), DhcpState::Rebinding);
+        assert_eq!(lease.state_at(100_000), DhcpState::Discovering);
+        assert_eq!(lease.remaining_ms(40_000), 60_000);
+        assert_eq!(lease.remaining_ms(200_000), 0);
+    }
+
     #[test]
     fn test_dhcp_state_display() {
         assert_eq!(format!("{}", DhcpState::Init), "Init");
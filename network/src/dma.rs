@@ -0,0 +1,107 @@
+// DMA memory for network drivers
+//
+// Devices need buffers that are physically contiguous and whose physical
+// address is known. The platform registers an allocator (the kernel uses
+// `shared::alloc_dma`, which reserves pages from the boot memory map);
+// without one, buffers come from the heap and identity mapping is assumed,
+// which is only correct before the kernel remaps memory.
+
+use crate::error::NetError;
+use alloc::string::ToString;
+
+/// Allocates `(len, align)` and returns `(virtual address, physical address)`
+pub type DmaAllocFn = fn(usize, usize) -> Option<(usize, u64)>;
+
+/// Frees `(physical address, len)` returned by a `DmaAllocFn`
+pub type DmaFreeFn = fn(u64, usize) -> bool;
+
+/// Global DMA allocator (set via set_dma_allocator)
+static mut DMA_ALLOCATOR: Option<(DmaAllocFn, DmaFreeFn)> = None;
+
+/// Set the allocator used for descriptor rings and packet buffers
+///
+/// # Safety
+/// This function is unsafe because it modifies global state without synchronization.
+/// It must be called before any driver is initialized; buffers allocated
+/// earlier would be freed through the wrong allocator.
+pub unsafe fn set_dma_allocator(alloc: DmaAllocFn, free: DmaFreeFn) {
+    DMA_ALLOCATOR = Some((alloc, free));
+}
+
+/// A zeroed buffer shared with a device
+pub(crate) struct DmaBuffer {
+    /// Kernel virtual address
+    pub ptr: *mut u8,
+    /// Address the device uses
+    pub phys: u64,
+    /// Requested length
+    pub len: usize,
+    /// Alignment, kept to rebuild the heap layout on free
+    align: usize,
+}
+
+impl DmaBuffer {
+    /// Allocate `len` zeroed bytes aligned to `align`
+    pub fn alloc(len: usize, align: usize) -> Result<Self, NetError> {
+        if len == 0 {
+            return Err(NetError::QueueError("Empty DMA allocation".to_string()));
+        }
+
+        if let Some((alloc, _)) = unsafe { DMA_ALLOCATOR } {
+            let (virt, phys) = alloc(len, align).ok_or_else(|| {
+                NetError::QueueError(format!("DMA pool exhausted ({} bytes)", len))
+            })?;
+            return Ok(Self {
+                ptr: virt as *mut u8,
+                phys,
+                len,
+                align,
+            });
+        }
+
+        let layout = core::alloc::Layout::from_size_align(len, align)
+            .map_err(|_| NetError::QueueError("Invalid DMA buffer layout".to_string()))?;
+        // SAFETY: the layout has a nonzero size
+        let ptr = unsafe { alloc::alloc::alloc_zeroed(layout) };
+        if ptr.is_null() {
+            return Err(NetError::QueueError(
+                "Failed to allocate DMA buffer".to_string(),
+            ));
+        }
+        Ok(Self {
+            ptr,
+            phys: ptr as u64,
+            len,
+            align,
+        })
+    }
+
+    /// Release the buffer
+    ///
+    /// # Safety
+    /// The device must no longer access the buffer.
+    pub unsafe fn free(self) {
+        if let Some((_, free)) = DMA_ALLOCATOR {
+            free(self.phys, self.len);
+        } else if let Ok(layout) = core::alloc::Layout::from_size_align(self.len, self.align) {
+            alloc::alloc::dealloc(self.ptr, layout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heap_fallback_is_zeroed_and_aligned() {
+        let buffer = DmaBuffer::alloc(1538, 4096).unwrap();
+        assert_eq!(buffer.ptr as usize % 4096, 0);
+        assert_eq!(buffer.phys, buffer.ptr as u64);
+        let bytes = unsafe { core::slice::from_raw_parts(buffer.ptr, buffer.len) };
+        assert!(bytes.iter().all(|&b| b == 0));
+        unsafe { buffer.free() };
+
+        assert!(DmaBuffer::alloc(0, 16).is_err());
+    }
+}
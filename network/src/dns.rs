@@ -161,6 +161,13 @@ impl DnsHeader {
     pub fn recursion_available(&self) -> bool {
         (self.flags & 0x0080) != 0
     }
+
+    /// Check if the message was truncated to fit a UDP datagram (TC bit)
+    ///
+    /// The full answer has to be fetched again over TCP.
+    pub fn is_truncated(&self) -> bool {
+        (self.flags & 0x0200) != 0
+    }
 }
 
 /// Encode a domain name in DNS format
@@ -433,13 +440,225 @@ impl DnsResponse {
 
     /// Get the first IPv4 address from the response
     pub fn first_ipv4(&self) -> Option<[u8; 4]> {
+        self.first_ipv4_with_ttl().map(|(ip, _)| ip)
+    }
+
+    /// Get the first IPv4 address from the response along with its TTL
+    pub fn first_ipv4_with_ttl(&self) -> Option<([u8; 4], u32)> {
         for answer in &self.answers {
             if let Some(ip) = answer.as_ipv4() {
-                return Some(ip);
+                return Some((ip, answer.ttl));
             }
         }
         None
     }
+
+    /// The first A record for `hostname`, with its TTL
+    ///
+    /// mDNS responders announce all their records in one packet and answer
+    /// other hosts' queries too, so the name has to match, not just the ID.
+    pub fn ipv4_for(&self, hostname: &str) -> Option<([u8; 4], u32)> {
+        let wanted = hostname.strip_suffix('.').unwrap_or(hostname);
+        self.answers
+            .iter()
+            .filter(|answer| answer.name.eq_ignore_ascii_case(wanted))
+            .find_map(|answer| Some((answer.as_ipv4()?, answer.ttl)))
+    }
+}
+
+/// Maximum number of hostnames kept in a `DnsCache`
+pub const DNS_CACHE_CAPACITY: usize = 16;
+
+/// TTL used when an answer's TTL is zero or implausibly large (seconds)
+pub const DEFAULT_DNS_TTL_SECS: u32 = 60;
+
+/// Longest TTL we believe (one week, in seconds)
+const MAX_DNS_TTL_SECS: u32 = 7 * 24 * 60 * 60;
+
+/// Clamp a record TTL to something usable for caching
+///
+/// Zero, values with the top bit set (RFC 2181 §8) and anything over a week
+/// fall back to `DEFAULT_DNS_TTL_SECS`.
+pub fn effective_ttl(ttl: u32) -> u32 {
+    if ttl == 0 || ttl > MAX_DNS_TTL_SECS {
+        DEFAULT_DNS_TTL_SECS
+    } else {
+        ttl
+    }
+}
+
+/// A cached A record
+#[derive(Debug, Clone)]
+struct DnsCacheEntry {
+    hostname: String,
+    ip: [u8; 4],
+    /// Time after which the entry is stale (ms, same clock as lookups)
+    expires_at_ms: i64,
+    /// Time of the last insert or hit, used for eviction
+    last_used_ms: i64,
+}
+
+/// Lookup counters for a `DnsCache`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DnsCacheStats {
+    /// Lookups answered from the cache
+    pub hits: u64,
+    /// Lookups that found no fresh entry
+    pub misses: u64,
+    /// Hostnames currently cached
+    pub entries: usize,
+}
+
+/// Small hostname → IPv4 cache honoring record TTLs
+///
+/// Hostnames are compared case-insensitively. When full, expired entries
+/// are dropped first, then the least recently used one.
+#[derive(Debug, Clone)]
+pub struct DnsCache {
+    entries: Vec<DnsCacheEntry>,
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl DnsCache {
+    /// Create an empty cache holding at most `capacity` hostnames
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            capacity,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Look up a hostname, returning its address if the entry is still fresh
+    pub fn get(&mut self, hostname: &str, now_ms: i64) -> Option<[u8; 4]> {
+        let ip = self.lookup(hostname, now_ms);
+        if ip.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        ip
+    }
+
+    fn lookup(&mut self, hostname: &str, now_ms: i64) -> Option<[u8; 4]> {
+        let index = self
+            .entries
+            .iter()
+            .position(|e| e.hostname.eq_ignore_ascii_case(hostname))?;
+        if now_ms >= self.entries[index].expires_at_ms {
+            self.entries.swap_remove(index);
+            return None;
+        }
+        let entry = &mut self.entries[index];
+        entry.last_used_ms = now_ms;
+        Some(entry.ip)
+    }
+
+    /// Record a resolved address with the TTL from its answer
+    pub fn insert(&mut self, hostname: &str, ip: [u8; 4], ttl_secs: u32, now_ms: i64) {
+        if self.capacity == 0 {
+            return;
+        }
+        let expires_at_ms = now_ms + effective_ttl(ttl_secs) as i64 * 1000;
+
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|e| e.hostname.eq_ignore_ascii_case(hostname))
+        {
+            entry.ip = ip;
+            entry.expires_at_ms = expires_at_ms;
+            entry.last_used_ms = now_ms;
+            return;
+        }
+
+        if self.entries.len() >= self.capacity {
+            self.entries.retain(|e| now_ms < e.expires_at_ms);
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, e)| e.last_used_ms)
+                .map(|(i, _)| i)
+            {
+                self.entries.swap_remove(oldest);
+            }
+        }
+
+        self.entries.push(DnsCacheEntry {
+            hostname: hostname.to_ascii_lowercase(),
+            ip,
+            expires_at_ms,
+            last_used_ms: now_ms,
+        });
+    }
+
+    /// Drop every cached entry; the hit/miss counters are kept
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Hit/miss counters since the cache was created
+    pub fn stats(&self) -> DnsCacheStats {
+        DnsCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+        }
+    }
+
+    /// Number of cached hostnames (including any not yet found to be stale)
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Default for DnsCache {
+    fn default() -> Self {
+        Self::new(DNS_CACHE_CAPACITY)
+    }
+}
+
+/// Frame a DNS message for TCP: a 2-byte big-endian length, then the message
+pub fn frame_tcp_message(message: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(message.len() + 2);
+    framed.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    framed.extend_from_slice(message);
+    framed
+}
+
+/// The first DNS message in `stream`, once it has been fully received
+///
+/// Returns `None` while the length prefix or the message is incomplete.
+pub fn parse_tcp_frame(stream: &[u8]) -> Option<&[u8]> {
+    let length = u16::from_be_bytes([*stream.first()?, *stream.get(1)?]) as usize;
+    stream.get(2..2 + length)
+}
+
+/// Interval after which an unanswered query is sent again
+pub const DNS_RETRANSMIT_MS: i64 = 1500;
+
+/// Transaction ID for a retransmitted query
+///
+/// Each retransmission gets a fresh ID so a late reply to an earlier attempt
+/// can still be told apart from a spoofed one. Never returns 0.
+pub fn next_transaction_id(previous: u16) -> u16 {
+    // 16-bit xorshift
+    let mut id = if previous == 0 { 0xACE1 } else { previous };
+    id ^= id << 7;
+    id ^= id >> 9;
+    id ^= id << 8;
+    id
 }
 
 /// Build a complete DNS query packet
@@ -457,10 +676,139 @@ pub fn build_query(hostname: &str, transaction_id: u16) -> Vec<u8> {
     packet
 }
 
+/// mDNS multicast group (RFC 6762)
+pub const MDNS_GROUP: [u8; 4] = [224, 0, 0, 251];
+
+/// mDNS port, for both queries and answers
+pub const MDNS_PORT: u16 = 5353;
+
+/// Default wait for an mDNS answer; hosts on the link answer quickly or not
+/// at all
+pub const MDNS_TIMEOUT_MS: i64 = 1000;
+
+/// Interval after which an unanswered mDNS query is sent again
+pub const MDNS_RETRANSMIT_MS: i64 = 250;
+
+/// Question class bit asking responders to answer by unicast (RFC 6762 5.4)
+const MDNS_UNICAST_RESPONSE: u16 = 0x8000;
+
+/// Whether `hostname` belongs to the link-local `.local` domain
+pub fn is_mdns_name(hostname: &str) -> bool {
+    let name = hostname.strip_suffix('.').unwrap_or(hostname);
+    let Some(split) = name.len().checked_sub(".local".len()) else {
+        return false;
+    };
+    split > 0 && name.is_char_boundary(split) && name[split..].eq_ignore_ascii_case(".local")
+}
+
+/// Build an mDNS A query for `hostname`
+///
+/// Multicast queries carry ID 0 and no recursion flag; the question asks
+/// for a unicast answer so it arrives even if the multicast reply is
+/// filtered.
+pub fn build_mdns_query(hostname: &str) -> Vec<u8> {
+    let mut header = DnsHeader::new_query(0);
+    header.flags = 0;
+    let mut packet = Vec::new();
+    packet.extend_from_slice(&header.to_bytes());
+    packet.extend_from_slice(&encode_domain_name(hostname));
+    packet.extend_from_slice(&(QueryType::A as u16).to_be_bytes());
+    packet.extend_from_slice(&(QueryClass::IN as u16 | MDNS_UNICAST_RESPONSE).to_be_bytes());
+    packet
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_next_transaction_id_is_fresh() {
+        let mut ids = vec![0u16];
+        for _ in 0..16 {
+            let id = next_transaction_id(*ids.last().unwrap());
+            assert_ne!(id, 0);
+            assert!(!ids.contains(&id));
+            ids.push(id);
+        }
+    }
+
+    /// A response to `build_query("example.com", id)` with one A record
+    fn a_response(id: u16, flags: u16) -> Vec<u8> {
+        let mut response = build_query("example.com", id);
+        response[2..4].copy_from_slice(&flags.to_be_bytes());
+        response[6..8].copy_from_slice(&1u16.to_be_bytes());
+        // Name pointer to the question, type A, class IN, TTL 300, 4 bytes
+        response.extend_from_slice(&[0xC0, 0x0C, 0, 1, 0, 1, 0, 0, 1, 44, 0, 4]);
+        response.extend_from_slice(&[93, 184, 216, 34]);
+        response
+    }
+
+    #[test]
+    fn test_mdns_names_and_query() {
+        assert!(is_mdns_name("ollama.local"));
+        assert!(is_mdns_name("Ollama.LOCAL."));
+        assert!(!is_mdns_name("local"));
+        assert!(!is_mdns_name(".local"));
+        assert!(!is_mdns_name("example.com"));
+        assert!(!is_mdns_name("notlocal"));
+
+        let query = build_mdns_query("ollama.local");
+        assert_eq!(&query[..4], &[0, 0, 0, 0]);
+        // QTYPE A, QCLASS IN with the unicast-response bit
+        assert_eq!(&query[query.len() - 4..], &[0, 1, 0x80, 1]);
+
+        // An announcement listing another host before the one asked for;
+        // the record class carries the cache-flush bit
+        let mut response = query.clone();
+        response[2..4].copy_from_slice(&0x8400u16.to_be_bytes());
+        response[4..6].copy_from_slice(&0u16.to_be_bytes());
+        response[6..8].copy_from_slice(&2u16.to_be_bytes());
+        response.truncate(12);
+        response.extend_from_slice(&encode_domain_name("printer.local"));
+        response.extend_from_slice(&[0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4, 192, 168, 1, 9]);
+        response.extend_from_slice(&encode_domain_name("OLLAMA.local"));
+        response.extend_from_slice(&[0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4, 192, 168, 1, 50]);
+        let response = DnsResponse::from_bytes(&response).unwrap();
+        assert_eq!(
+            response.ipv4_for("ollama.local"),
+            Some(([192, 168, 1, 50], 120))
+        );
+        assert_eq!(response.ipv4_for("nas.local"), None);
+    }
+
+    #[test]
+    fn test_truncated_udp_response_needs_tcp() {
+        // QR, RD, RA and TC set; answers that did not fit were dropped
+        let mut truncated = build_query("example.com", 0x1234);
+        truncated[2..4].copy_from_slice(&0x8380u16.to_be_bytes());
+        let response = DnsResponse::from_bytes(&truncated).unwrap();
+        assert!(response.header.is_truncated());
+        assert!(response.first_ipv4_with_ttl().is_none());
+
+        let complete = DnsResponse::from_bytes(&a_response(0x1234, 0x8180)).unwrap();
+        assert!(!complete.header.is_truncated());
+    }
+
+    #[test]
+    fn test_tcp_framing() {
+        let message = a_response(0x1234, 0x8180);
+        let framed = frame_tcp_message(&message);
+        assert_eq!(framed[..2], (message.len() as u16).to_be_bytes());
+
+        // Incomplete until the last byte arrives
+        for received in 0..framed.len() {
+            assert_eq!(parse_tcp_frame(&framed[..received]), None);
+        }
+        let mut stream = framed.clone();
+        stream.extend_from_slice(&[0, 12]); // start of a following message
+        let parsed = parse_tcp_frame(&stream).unwrap();
+        assert_eq!(parsed, &message[..]);
+
+        let response = DnsResponse::from_bytes(parsed).unwrap();
+        assert_eq!(response.header.id, 0x1234);
+        assert_eq!(response.first_ipv4_with_ttl(), Some(([93, 184, 216, 34], 300)));
+    }
+
     #[test]
     fn test_encode_domain_name() {
         let encoded = encode_domain_name("example.com");
@@ -517,4 +865,45 @@ mod tests {
         assert_eq!(ResponseCode::from_u8(3), Some(ResponseCode::NameError));
         assert_eq!(ResponseCode::from_u8(99), None);
     }
+
+    #[test]
+    fn test_dns_cache_expiry_and_ttl() {
+        let mut cache = DnsCache::new(4);
+        cache.insert("API.example.com", [1, 2, 3, 4], 30, 1_000);
+        assert_eq!(cache.get("api.example.com", 30_999), Some([1, 2, 3, 4]));
+        assert_eq!(cache.get("api.example.com", 31_000), None);
+        assert!(cache.is_empty());
+
+        // A zero TTL falls back to the default
+        cache.insert("example.com", [5, 6, 7, 8], 0, 0);
+        let default_ms = DEFAULT_DNS_TTL_SECS as i64 * 1000;
+        assert_eq!(cache.get("example.com", default_ms - 1), Some([5, 6, 7, 8]));
+        assert_eq!(effective_ttl(u32::MAX), DEFAULT_DNS_TTL_SECS);
+
+        cache.clear();
+        assert_eq!(cache.get("example.com", 0), None);
+    }
+
+    #[test]
+    fn test_dns_cache_evicts_least_recently_used() {
+        let mut cache = DnsCache::new(2);
+        cache.insert("a.com", [1, 1, 1, 1], 300, 0);
+        cache.insert("b.com", [2, 2, 2, 2], 300, 10);
+        // Touch a.com so b.com becomes the eviction candidate
+        assert!(cache.get("a.com", 20).is_some());
+        cache.insert("c.com", [3, 3, 3, 3], 300, 30);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("a.com", 40).is_some());
+        assert!(cache.get("b.com", 40).is_none());
+        assert!(cache.get("c.com", 40).is_some());
+        assert_eq!(
+            cache.stats(),
+            DnsCacheStats {
+                hits: 3,
+                misses: 1,
+                entries: 2
+            }
+        );
+    }
 }
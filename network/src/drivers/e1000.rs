@@ -0,0 +1,577 @@
+// e1000 driver implementation
+// Implements the Intel 82540EM (e1000) network driver, the default NIC of
+// QEMU's `-device e1000`, and the 82574L (e1000e, QEMU's `-device e1000e`
+// and many laptops), which is programmed the same way apart from EEPROM reads
+
+use crate::drivers::NetworkDriver;
+use crate::error::NetError;
+use crate::pci::{
+    find_pci_device, PciDevice, E1000E_82574L_DEVICE_ID, E1000_DEVICE_ID, INTEL_VENDOR_ID,
+};
+use core::ptr;
+use spin::Mutex;
+extern crate alloc;
+use alloc::string::ToString;
+
+/// Register offsets (from BAR0)
+const REG_CTRL: usize = 0x0000;
+const REG_STATUS: usize = 0x0008;
+const REG_EERD: usize = 0x0014;
+const REG_ICR: usize = 0x00C0;
+const REG_IMC: usize = 0x00D8;
+const REG_RCTL: usize = 0x0100;
+const REG_TCTL: usize = 0x0400;
+const REG_TIPG: usize = 0x0410;
+const REG_RDBAL: usize = 0x2800;
+const REG_RDBAH: usize = 0x2804;
+const REG_RDLEN: usize = 0x2808;
+const REG_RDH: usize = 0x2810;
+const REG_RDT: usize = 0x2818;
+const REG_TDBAL: usize = 0x3800;
+const REG_TDBAH: usize = 0x3804;
+const REG_TDLEN: usize = 0x3808;
+const REG_TDH: usize = 0x3810;
+const REG_TDT: usize = 0x3818;
+const REG_MTA: usize = 0x5200;
+const REG_RAL0: usize = 0x5400;
+const REG_RAH0: usize = 0x5404;
+
+/// Number of multicast table array entries
+const MTA_ENTRIES: usize = 128;
+
+/// CTRL register bits
+const CTRL_ASDE: u32 = 1 << 5; // Auto-speed detection
+const CTRL_SLU: u32 = 1 << 6; // Set link up
+const CTRL_RST: u32 = 1 << 26; // Device reset
+
+/// STATUS register bits
+const STATUS_LU: u32 = 1 << 1; // Link up
+
+/// EERD register bits
+const EERD_START: u32 = 1 << 0;
+const EERD_DONE: u32 = 1 << 4;
+/// The 82574L moves the done bit and widens the address field
+const EERD_DONE_82574: u32 = 1 << 1;
+
+/// Supported devices, in probe order
+const DEVICE_IDS: [u16; 2] = [E1000_DEVICE_ID, E1000E_82574L_DEVICE_ID];
+
+/// RAH register bits
+const RAH_AV: u32 = 1 << 31; // Address valid
+
+/// RCTL register bits
+const RCTL_EN: u32 = 1 << 1;
+const RCTL_BAM: u32 = 1 << 15; // Accept broadcast
+const RCTL_SECRC: u32 = 1 << 26; // Strip Ethernet CRC
+// Buffer size 2048 is BSIZE = 00 with BSEX = 0, so no bits are needed
+
+/// TCTL register bits
+const TCTL_EN: u32 = 1 << 1;
+const TCTL_PSP: u32 = 1 << 3; // Pad short packets
+const TCTL_CT: u32 = 0x10 << 4; // Collision threshold
+const TCTL_COLD: u32 = 0x40 << 12; // Collision distance (full duplex)
+
+/// Inter-packet gap recommended for 802.3 (IPGT=10, IPGR1=8, IPGR2=6)
+const TIPG_DEFAULT: u32 = 10 | (8 << 10) | (6 << 20);
+
+/// Descriptor status bits
+const DESC_STATUS_DD: u8 = 1 << 0; // Descriptor done
+const RX_STATUS_EOP: u8 = 1 << 1; // End of packet
+
+/// TX descriptor command bits
+const TX_CMD_EOP: u8 = 1 << 0; // End of packet
+const TX_CMD_IFCS: u8 = 1 << 1; // Insert FCS
+const TX_CMD_RS: u8 = 1 << 3; // Report status
+
+/// PCI command register bits
+const PCI_COMMAND_OFFSET: u8 = 0x04;
+const PCI_COMMAND_MEMORY: u32 = 1 << 1;
+const PCI_COMMAND_BUS_MASTER: u32 = 1 << 2;
+
+/// Ring sizes (ring byte length must be a multiple of 128)
+const NUM_RX_DESC: usize = 32;
+const NUM_TX_DESC: usize = 16;
+
+/// Size of each packet buffer
+const BUFFER_SIZE: usize = 2048;
+
+/// Largest frame we send (without FCS)
+const MAX_FRAME_SIZE: usize = 1514;
+
+/// Iterations to wait for reset or an EEPROM read before giving up
+const SPIN_LIMIT: usize = 100_000;
+
+/// Legacy receive descriptor
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RxDesc {
+    addr: u64,
+    length: u16,
+    checksum: u16,
+    status: u8,
+    errors: u8,
+    special: u16,
+}
+
+/// Legacy transmit descriptor
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct TxDesc {
+    addr: u64,
+    length: u16,
+    cso: u8,
+    cmd: u8,
+    status: u8,
+    css: u8,
+    special: u16,
+}
+
+/// A descriptor ring and its packet buffers
+struct Ring<T> {
+    /// Descriptor array (16-byte aligned)
+    desc: *mut T,
+    /// One buffer per descriptor
+    buffers: *mut u8,
+    /// Number of descriptors
+    len: usize,
+}
+
+impl<T> Ring<T> {
+    /// Allocate a zeroed ring of `len` descriptors with a buffer for each
+    fn new(len: usize) -> Result<Self, NetError> {
+        let desc_layout = core::alloc::Layout::from_size_align(core::mem::size_of::<T>() * len, 128)
+            .map_err(|_| NetError::QueueError("Invalid descriptor ring layout".to_string()))?;
+        let buf_layout = core::alloc::Layout::from_size_align(BUFFER_SIZE * len, 16)
+            .map_err(|_| NetError::QueueError("Invalid buffer layout".to_string()))?;
+
+        unsafe {
+            let desc = alloc::alloc::alloc_zeroed(desc_layout) as *mut T;
+            if desc.is_null() {
+                return Err(NetError::QueueError(
+                    "Failed to allocate descriptor ring".to_string(),
+                ));
+            }
+            let buffers = alloc::alloc::alloc_zeroed(buf_layout);
+            if buffers.is_null() {
+                alloc::alloc::dealloc(desc as *mut u8, desc_layout);
+                return Err(NetError::QueueError(
+                    "Failed to allocate packet buffers".to_string(),
+                ));
+            }
+            Ok(Ring { desc, buffers, len })
+        }
+    }
+
+    /// Pointer to the buffer for descriptor `index`
+    fn buffer(&self, index: usize) -> *mut u8 {
+        unsafe { self.buffers.add(index * BUFFER_SIZE) }
+    }
+
+    /// Size of the descriptor array in bytes
+    fn byte_len(&self) -> usize {
+        core::mem::size_of::<T>() * self.len
+    }
+}
+
+/// Intel 82540EM (e1000) and 82574L (e1000e) driver
+pub struct E1000 {
+    /// PCI device information
+    pci_device: PciDevice,
+    /// MMIO base address (BAR0)
+    mmio_base: usize,
+    /// MAC address
+    mac_address: [u8; 6],
+    /// Receive ring
+    rx_ring: Option<Ring<RxDesc>>,
+    /// Transmit ring
+    tx_ring: Option<Ring<TxDesc>>,
+    /// Next RX descriptor to check
+    rx_next: usize,
+    /// Next TX descriptor to fill
+    tx_next: usize,
+    /// Initialized flag
+    initialized: bool,
+}
+
+// SAFETY: E1000 is only used behind a global lock; callers must ensure no
+// concurrent access to raw pointers across threads.
+unsafe impl Send for E1000 {}
+
+impl E1000 {
+    /// Create a new e1000 driver instance
+    ///
+    /// This scans for an 82540EM or 82574L PCI device and maps its
+    /// registers. Call [`E1000::init`] before sending or receiving.
+    pub fn new() -> Result<Self, NetError> {
+        let pci_device = DEVICE_IDS
+            .iter()
+            .find_map(|&device_id| find_pci_device(INTEL_VENDOR_ID, device_id))
+            .ok_or(NetError::DeviceNotFound)?;
+
+        // BAR0 is the memory-mapped register window
+        if pci_device.bars[0] & 1 != 0 {
+            return Err(NetError::PciError("BAR0 is not memory-mapped".to_string()));
+        }
+        let mmio_base = pci_device.get_bar(0) as usize;
+        if mmio_base == 0 {
+            return Err(NetError::PciError("BAR0 is invalid".to_string()));
+        }
+
+        Ok(E1000 {
+            pci_device,
+            mmio_base,
+            mac_address: [0; 6],
+            rx_ring: None,
+            tx_ring: None,
+            rx_next: 0,
+            tx_next: 0,
+            initialized: false,
+        })
+    }
+
+    /// Initialize the device: reset, read the MAC, and set up both rings
+    pub fn init(&mut self) -> Result<(), NetError> {
+        // Enable MMIO decoding and DMA
+        let command = self.pci_device.read_config_dword(PCI_COMMAND_OFFSET);
+        self.pci_device.write_config_dword(
+            PCI_COMMAND_OFFSET,
+            command | PCI_COMMAND_MEMORY | PCI_COMMAND_BUS_MASTER,
+        );
+
+        // Mask interrupts, reset, then mask again (reset re-enables them)
+        self.write_reg(REG_IMC, 0xFFFF_FFFF);
+        self.write_reg(REG_CTRL, self.read_reg(REG_CTRL) | CTRL_RST);
+        self.wait_for(|dev| dev.read_reg(REG_CTRL) & CTRL_RST == 0)
+            .ok_or_else(|| NetError::DriverError("e1000 reset timed out".to_string()))?;
+        self.write_reg(REG_IMC, 0xFFFF_FFFF);
+        let _ = self.read_reg(REG_ICR);
+
+        // Bring the link up
+        self.write_reg(REG_CTRL, self.read_reg(REG_CTRL) | CTRL_SLU | CTRL_ASDE);
+
+        self.mac_address = self.read_mac_address()?;
+
+        // Clear the multicast table
+        for i in 0..MTA_ENTRIES {
+            self.write_reg(REG_MTA + i * 4, 0);
+        }
+
+        self.init_rx()?;
+        self.init_tx()?;
+
+        self.initialized = true;
+        Ok(())
+    }
+
+    /// Read the MAC address from RAL0/RAH0, falling back to the EEPROM
+    fn read_mac_address(&mut self) -> Result<[u8; 6], NetError> {
+        let ral = self.read_reg(REG_RAL0);
+        let rah = self.read_reg(REG_RAH0);
+        if rah & RAH_AV != 0 {
+            let mut mac = [0u8; 6];
+            mac[..4].copy_from_slice(&ral.to_le_bytes());
+            mac[4..].copy_from_slice(&rah.to_le_bytes()[..2]);
+            return Ok(mac);
+        }
+
+        // EEPROM words 0-2 hold the MAC address
+        let mut mac = [0u8; 6];
+        for word in 0..3 {
+            let value = self.read_eeprom(word as u8)?;
+            mac[word * 2..word * 2 + 2].copy_from_slice(&value.to_le_bytes());
+        }
+
+        // Program the receive address filter so unicast frames are accepted
+        let ral = u32::from_le_bytes([mac[0], mac[1], mac[2], mac[3]]);
+        let rah = u32::from_le_bytes([mac[4], mac[5], 0, 0]) | RAH_AV;
+        self.write_reg(REG_RAL0, ral);
+        self.write_reg(REG_RAH0, rah);
+        Ok(mac)
+    }
+
+    /// Model name, for logs
+    pub fn model(&self) -> &'static str {
+        if self.is_82574() {
+            "82574L (e1000e)"
+        } else {
+            "82540EM (e1000)"
+        }
+    }
+
+    fn is_82574(&self) -> bool {
+        self.pci_device.device_id == E1000E_82574L_DEVICE_ID
+    }
+
+    /// Read a 16-bit word from the EEPROM
+    fn read_eeprom(&mut self, address: u8) -> Result<u16, NetError> {
+        let (request, done) = eerd_request(address, self.is_82574());
+        self.write_reg(REG_EERD, request);
+        let value = self
+            .wait_for(|dev| dev.read_reg(REG_EERD) & done != 0)
+            .map(|_| self.read_reg(REG_EERD))
+            .ok_or_else(|| NetError::DriverError("EEPROM read timed out".to_string()))?;
+        Ok((value >> 16) as u16)
+    }
+
+    /// Set up the receive ring and enable the receiver
+    fn init_rx(&mut self) -> Result<(), NetError> {
+        let ring = Ring::<RxDesc>::new(NUM_RX_DESC)?;
+        for i in 0..ring.len {
+            unsafe {
+                ptr::write_volatile(
+                    ring.desc.add(i),
+                    RxDesc {
+                        addr: self.virt_to_phys(ring.buffer(i) as usize),
+                        length: 0,
+                        checksum: 0,
+                        status: 0,
+                        errors: 0,
+                        special: 0,
+                    },
+                );
+            }
+        }
+
+        let phys = self.virt_to_phys(ring.desc as usize);
+        self.write_reg(REG_RDBAL, phys as u32);
+        self.write_reg(REG_RDBAH, (phys >> 32) as u32);
+        self.write_reg(REG_RDLEN, ring.byte_len() as u32);
+        self.write_reg(REG_RDH, 0);
+        // All descriptors but one are owned by the hardware
+        self.write_reg(REG_RDT, (ring.len - 1) as u32);
+        self.write_reg(REG_RCTL, RCTL_EN | RCTL_BAM | RCTL_SECRC);
+
+        self.rx_ring = Some(ring);
+        self.rx_next = 0;
+        Ok(())
+    }
+
+    /// Set up the transmit ring and enable the transmitter
+    fn init_tx(&mut self) -> Result<(), NetError> {
+        let ring = Ring::<TxDesc>::new(NUM_TX_DESC)?;
+        for i in 0..ring.len {
+            unsafe {
+                ptr::write_volatile(
+                    ring.desc.add(i),
+                    TxDesc {
+                        addr: self.virt_to_phys(ring.buffer(i) as usize),
+                        length: 0,
+                        cso: 0,
+                        cmd: 0,
+                        // Mark as done so the first send can use it
+                        status: DESC_STATUS_DD,
+                        css: 0,
+                        special: 0,
+                    },
+                );
+            }
+        }
+
+        let phys = self.virt_to_phys(ring.desc as usize);
+        self.write_reg(REG_TDBAL, phys as u32);
+        self.write_reg(REG_TDBAH, (phys >> 32) as u32);
+        self.write_reg(REG_TDLEN, ring.byte_len() as u32);
+        self.write_reg(REG_TDH, 0);
+        self.write_reg(REG_TDT, 0);
+        self.write_reg(REG_TIPG, TIPG_DEFAULT);
+        self.write_reg(REG_TCTL, TCTL_EN | TCTL_PSP | TCTL_CT | TCTL_COLD);
+
+        self.tx_ring = Some(ring);
+        self.tx_next = 0;
+        Ok(())
+    }
+
+    /// Spin until `done` returns true, up to `SPIN_LIMIT` iterations
+    fn wait_for(&self, done: impl Fn(&Self) -> bool) -> Option<()> {
+        for _ in 0..SPIN_LIMIT {
+            if done(self) {
+                return Some(());
+            }
+            core::hint::spin_loop();
+        }
+        None
+    }
+
+    /// Read a 32-bit device register
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { ptr::read_volatile((self.mmio_base + offset) as *const u32) }
+    }
+
+    /// Write a 32-bit device register
+    fn write_reg(&mut self, offset: usize, value: u32) {
+        unsafe {
+            ptr::write_volatile((self.mmio_base + offset) as *mut u32, value);
+        }
+    }
+
+    /// Convert virtual address to physical address
+    ///
+    /// Note: This is a simplified version. In a real implementation,
+    /// you would need to use proper page table translation.
+    fn virt_to_phys(&self, virt: usize) -> u64 {
+        // For now, assume identity mapping
+        virt as u64
+    }
+
+    /// Get the interrupt line for this device
+    pub fn interrupt_line(&self) -> u8 {
+        self.pci_device.interrupt_line
+    }
+}
+
+impl NetworkDriver for E1000 {
+    fn send(&mut self, packet: &[u8]) -> Result<(), NetError> {
+        if !self.initialized {
+            return Err(NetError::DeviceNotInitialized);
+        }
+
+        if packet.len() > MAX_FRAME_SIZE {
+            return Err(NetError::InvalidPacket("Packet too large".to_string()));
+        }
+
+        if packet.is_empty() {
+            return Err(NetError::InvalidPacket("Packet is empty".to_string()));
+        }
+
+        let ring = self
+            .tx_ring
+            .as_ref()
+            .ok_or_else(|| NetError::QueueError("TX ring not initialized".to_string()))?;
+        let index = self.tx_next;
+
+        unsafe {
+            let desc = ring.desc.add(index);
+            // The hardware sets DD once it has finished with a descriptor
+            if ptr::read_volatile(ptr::addr_of!((*desc).status)) & DESC_STATUS_DD == 0 {
+                return Err(NetError::QueueError("TX ring full".to_string()));
+            }
+
+            ptr::copy_nonoverlapping(packet.as_ptr(), ring.buffer(index), packet.len());
+            ptr::write_volatile(ptr::addr_of_mut!((*desc).length), packet.len() as u16);
+            ptr::write_volatile(
+                ptr::addr_of_mut!((*desc).cmd),
+                TX_CMD_EOP | TX_CMD_IFCS | TX_CMD_RS,
+            );
+            ptr::write_volatile(ptr::addr_of_mut!((*desc).status), 0);
+        }
+
+        self.tx_next = (index + 1) % NUM_TX_DESC;
+        // Advancing the tail hands the descriptor to the hardware
+        self.write_reg(REG_TDT, self.tx_next as u32);
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<alloc::vec::Vec<u8>>, NetError> {
+        if !self.initialized {
+            return Err(NetError::DeviceNotInitialized);
+        }
+
+        let ring = self
+            .rx_ring
+            .as_ref()
+            .ok_or_else(|| NetError::QueueError("RX ring not initialized".to_string()))?;
+        let index = self.rx_next;
+
+        let packet = unsafe {
+            let desc = ring.desc.add(index);
+            let status = ptr::read_volatile(ptr::addr_of!((*desc).status));
+            if status & DESC_STATUS_DD == 0 {
+                return Ok(None);
+            }
+
+            let length = ptr::read_volatile(ptr::addr_of!((*desc).length)) as usize;
+            let errors = ptr::read_volatile(ptr::addr_of!((*desc).errors));
+
+            // Frames spanning several buffers can't occur with 2048-byte
+            // buffers and long packets disabled; drop them if they do
+            let packet = if status & RX_STATUS_EOP != 0 && errors == 0 && length <= BUFFER_SIZE {
+                let mut packet = alloc::vec::Vec::with_capacity(length);
+                packet.extend_from_slice(core::slice::from_raw_parts(ring.buffer(index), length));
+                Some(packet)
+            } else {
+                None
+            };
+
+            // Give the descriptor back to the hardware
+            ptr::write_volatile(ptr::addr_of_mut!((*desc).status), 0);
+            packet
+        };
+
+        self.rx_next = (index + 1) % NUM_RX_DESC;
+        self.write_reg(REG_RDT, index as u32);
+        Ok(packet)
+    }
+
+    fn mac_address(&self) -> [u8; 6] {
+        self.mac_address
+    }
+
+    fn is_link_up(&self) -> bool {
+        if !self.initialized {
+            return false;
+        }
+
+        self.read_reg(REG_STATUS) & STATUS_LU != 0
+    }
+
+    fn poll(&mut self) -> Result<(), NetError> {
+        if !self.initialized {
+            return Err(NetError::DeviceNotInitialized);
+        }
+
+        // Reading ICR acknowledges any pending interrupt causes; received
+        // frames are picked up by receive() and TX descriptors are reclaimed
+        // lazily in send()
+        let _ = self.read_reg(REG_ICR);
+        Ok(())
+    }
+}
+
+/// EERD value that starts a read of `address`, and the bit that signals
+/// its completion
+fn eerd_request(address: u8, is_82574: bool) -> (u32, u32) {
+    if is_82574 {
+        (((address as u32) << 2) | EERD_START, EERD_DONE_82574)
+    } else {
+        (((address as u32) << 8) | EERD_START, EERD_DONE)
+    }
+}
+
+// Global e1000 instance (protected by mutex)
+static E1000_NET: Mutex<Option<E1000>> = Mutex::new(None);
+
+/// Initialize the e1000 driver
+///
+/// This should be called once during system initialization.
+pub fn init_e1000() -> Result<(), NetError> {
+    let mut driver = E1000::new()?;
+    driver.init()?;
+
+    let mut global = E1000_NET.lock();
+    *global = Some(driver);
+    Ok(())
+}
+
+/// Get the global e1000 driver instance
+pub fn get_e1000() -> Option<spin::MutexGuard<'static, Option<E1000>>> {
+    Some(E1000_NET.lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptor_layout() {
+        assert_eq!(core::mem::size_of::<RxDesc>(), 16);
+        assert_eq!(core::mem::size_of::<TxDesc>(), 16);
+        assert_eq!((NUM_RX_DESC * 16) % 128, 0);
+        assert_eq!((NUM_TX_DESC * 16) % 128, 0);
+    }
+
+    #[test]
+    fn test_eerd_request_layout() {
+        assert_eq!(eerd_request(2, false), (0x0201, 1 << 4));
+        assert_eq!(eerd_request(2, true), (0x0009, 1 << 1));
+    }
+}
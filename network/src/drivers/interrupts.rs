@@ -1,64 +1,160 @@
 // Interrupt handling for network drivers
+//
+// The platform owns the IDT and the interrupt controller; it routes the
+// NIC's IRQ line to a handler that calls `handle_virtio_net_irq` and then
+// sends EOI. The handler never takes a driver lock: it records completed
+// receive descriptors in a pre-allocated queue and raises a wake-up flag
+// that the main loop checks between `hlt`s.
 
-use crate::drivers::virtio::{get_virtio_net, VirtioNet};
-use crate::error::NetError;
-extern crate alloc;
-use alloc::string::ToString;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
-/// Virtio-net interrupt handler
-///
-/// This function should be registered with the interrupt system to handle
-/// virtio-net device interrupts.
+/// Capacity of the completion queue; at least the number of RX descriptors
+pub const COMPLETION_QUEUE_LEN: usize = 256;
+
+/// Single-producer, single-consumer queue of used-ring entries
 ///
-/// # Safety
-/// This function must only be called from an interrupt handler context.
-#[cfg(target_arch = "x86_64")]
-pub unsafe extern "C" fn virtio_net_interrupt_handler(
-    _stack_frame: x86_64::structures::idt::InterruptStackFrame,
-) {
-    // Get the virtio-net driver instance
-    if let Some(mut driver_guard) = get_virtio_net() {
-        if let Some(ref mut driver) = driver_guard.as_mut() {
-            // Handle the interrupt
-            let _ = driver.handle_interrupt();
+/// The interrupt handler pushes `(descriptor id, length)` pairs and the
+/// driver pops them in `receive()`. Storage is fixed, so the handler never
+/// allocates.
+pub struct CompletionQueue {
+    entries: [AtomicU64; COMPLETION_QUEUE_LEN],
+    /// Next slot to pop (consumer)
+    head: AtomicUsize,
+    /// Next slot to push (producer)
+    tail: AtomicUsize,
+}
+
+impl CompletionQueue {
+    pub const fn new() -> Self {
+        Self {
+            entries: [const { AtomicU64::new(0) }; COMPLETION_QUEUE_LEN],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add an entry; `false` if the queue is full
+    pub fn push(&self, id: u32, len: u32) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) >= COMPLETION_QUEUE_LEN {
+            return false;
+        }
+        self.entries[tail % COMPLETION_QUEUE_LEN]
+            .store(((id as u64) << 32) | len as u64, Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Remove the oldest entry
+    pub fn pop(&self) -> Option<(u32, u32)> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
         }
+        let entry = self.entries[head % COMPLETION_QUEUE_LEN].load(Ordering::Relaxed);
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(((entry >> 32) as u32, entry as u32))
     }
 
-    // Acknowledge the interrupt
-    // For PCI devices, we may need to send EOI to the interrupt controller
-    // This depends on whether MSI-X or legacy interrupts are used
-    x86_64::instructions::port::Port::new(0x20).write(0x20u8);
+    /// Number of entries waiting
+    pub fn len(&self) -> usize {
+        self.tail
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop all entries (only while the producer is stopped)
+    pub fn clear(&self) {
+        self.head
+            .store(self.tail.load(Ordering::Acquire), Ordering::Release);
+    }
 }
 
-/// Register virtio-net interrupt handler
-///
-/// This function registers the virtio-net interrupt handler with the interrupt system.
-/// It should be called after the virtio-net driver is initialized.
-///
-/// # Arguments
-/// * `interrupt_line` - The interrupt line (IRQ) for the virtio-net device
+impl Default for CompletionQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Completed virtio-net receive descriptors, filled by the IRQ handler
+pub(crate) static RX_COMPLETIONS: CompletionQueue = CompletionQueue::new();
+
+/// Set by the IRQ handler, cleared by the main loop
+static NET_WAKEUP: AtomicBool = AtomicBool::new(false);
+
+/// Whether a NIC is currently in interrupt-driven mode
+static IRQ_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Interrupts serviced, for diagnostics
+static IRQ_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Virtio-net interrupt service routine
 ///
-/// # Safety
-/// This function modifies the interrupt descriptor table and must be called
-/// during system initialization.
+/// Called by the platform's handler for the line returned by
+/// `NetworkDriver::enable_interrupts`. Reading the ISR status register
+/// acknowledges the device.
 ///
-/// # Note
-/// In a real implementation, this would need to be integrated with the boot/interrupts
-/// module to actually register the handler in the IDT. This is a placeholder interface.
-#[cfg(target_arch = "x86_64")]
-pub unsafe fn register_virtio_net_interrupt(interrupt_line: u8) -> Result<(), NetError> {
-    // Map interrupt line to interrupt vector
-    // For PCI devices, interrupt_line is typically in the range 0-15
-    // We map it to interrupt vector 32 + interrupt_line
-    let interrupt_vector = 32 + interrupt_line as usize;
-
-    if interrupt_vector >= 256 {
-        return Err(NetError::PciError("Invalid interrupt line".to_string()));
+/// # Returns
+/// `false` if the interrupt came from another device sharing the line
+pub fn handle_virtio_net_irq() -> bool {
+    if !crate::drivers::virtio::service_interrupt() {
+        return false;
     }
+    IRQ_COUNT.fetch_add(1, Ordering::Relaxed);
+    NET_WAKEUP.store(true, Ordering::Release);
+    true
+}
 
-    // Note: In a real implementation, we would need access to the IDT
-    // The actual registration would be done by the boot/interrupts module
-    // This function serves as a placeholder to show the intended interface
+/// Whether the NIC interrupted since the last call
+pub fn take_wakeup() -> bool {
+    NET_WAKEUP.swap(false, Ordering::Acquire)
+}
 
-    Ok(())
+/// Whether receive is interrupt-driven; if not, the stack must be polled
+pub fn irq_active() -> bool {
+    IRQ_ACTIVE.load(Ordering::Acquire)
+}
+
+pub(crate) fn set_irq_active(active: bool) {
+    IRQ_ACTIVE.store(active, Ordering::Release);
+}
+
+/// Number of network interrupts serviced
+pub fn irq_count() -> u64 {
+    IRQ_COUNT.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_queue_wraps() {
+        let queue = CompletionQueue::new();
+        assert_eq!(queue.pop(), None);
+
+        // Push and pop past the end of the storage
+        for round in 0..3u32 {
+            for i in 0..200u32 {
+                assert!(queue.push(round * 1000 + i, i + 60));
+            }
+            for i in 0..200u32 {
+                assert_eq!(queue.pop(), Some((round * 1000 + i, i + 60)));
+            }
+        }
+
+        for i in 0..COMPLETION_QUEUE_LEN as u32 {
+            assert!(queue.push(i, 0));
+        }
+        assert!(!queue.push(0, 0));
+        assert_eq!(queue.len(), COMPLETION_QUEUE_LEN);
+        queue.clear();
+        assert_eq!(queue.pop(), None);
+    }
 }
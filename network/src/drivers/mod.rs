@@ -1,7 +1,13 @@
 // Network driver implementations
 
+#[cfg(target_arch = "x86_64")]
+pub mod e1000;
 #[cfg(target_arch = "x86_64")]
 pub mod interrupts;
+#[cfg(any(test, feature = "loopback"))]
+pub mod loopback;
+#[cfg(target_arch = "x86_64")]
+pub mod rtl8139;
 #[cfg(target_arch = "x86_64")]
 pub mod virtio;
 
@@ -52,4 +58,17 @@ pub trait NetworkDriver: Send {
     /// * `Ok(())` if polling succeeded
     /// * `Err(NetError)` if an error occurred
     fn poll(&mut self) -> Result<(), NetError>;
+
+    /// Switch receive to interrupt-driven mode
+    ///
+    /// # Returns
+    /// * `Ok(line)` - the legacy IRQ line to route to
+    ///   `interrupts::handle_virtio_net_irq`
+    /// * `Err(NetError::NotSupported)` if the driver can only be polled
+    fn enable_interrupts(&mut self) -> Result<u8, NetError> {
+        Err(NetError::NotSupported)
+    }
+
+    /// Return to polling, e.g. when the IRQ line could not be routed
+    fn disable_interrupts(&mut self) {}
 }
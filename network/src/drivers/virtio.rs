@@ -1,11 +1,16 @@
 // virtio-net driver implementation
 // Implements the virtio-net network device driver for QEMU/KVM VMs
 
+use crate::dma::DmaBuffer;
+use crate::drivers::interrupts::{set_irq_active, RX_COMPLETIONS};
 use crate::drivers::NetworkDriver;
 use crate::error::NetError;
-use crate::pci::{find_pci_device, PciDevice, VIRTIO_NET_DEVICE_ID, VIRTIO_VENDOR_ID};
+use crate::pci::{
+    find_pci_device, PciDevice, VIRTIO_NET_DEVICE_ID, VIRTIO_NET_MODERN_DEVICE_ID, VIRTIO_VENDOR_ID,
+};
 use core::ptr;
 use spin::Mutex;
+use x86_64::instructions::port::Port;
 extern crate alloc;
 use alloc::string::ToString;
 
@@ -17,25 +22,55 @@ const VIRTIO_STATUS_FEATURES_OK: u8 = 8;
 const VIRTIO_STATUS_DEVICE_NEEDS_RESET: u8 = 64;
 const VIRTIO_STATUS_FAILED: u8 = 128;
 
-/// Virtio configuration space offsets
-const VIRTIO_PCI_CONFIG_OFFSET: u16 = 0x100;
-
-/// Virtio queue selector register offset
+/// Legacy virtio header register offsets (I/O space, BAR0)
+const VIRTIO_PCI_DEVICE_FEATURES: u16 = 0x00; // 32 bits
+const VIRTIO_PCI_DRIVER_FEATURES: u16 = 0x04; // 32 bits
+const VIRTIO_PCI_QUEUE_PFN: u16 = 0x08;
+const VIRTIO_PCI_QUEUE_NUM: u16 = 0x0C;
 const VIRTIO_PCI_QUEUE_SEL: u16 = 0x0E;
-/// Virtio queue size register offset
-const VIRTIO_PCI_QUEUE_NUM: u16 = 0x10;
-/// Virtio queue address register offset (64-bit, split into low/high)
-const VIRTIO_PCI_QUEUE_PFN: u16 = 0x0C;
-/// Virtio queue notify register offset
 const VIRTIO_PCI_QUEUE_NOTIFY: u16 = 0x10;
-/// Virtio device status register offset
 const VIRTIO_PCI_STATUS: u16 = 0x12;
-/// Virtio device features register offset
-const VIRTIO_PCI_DEVICE_FEATURES: u16 = 0x00;
-/// Virtio driver features register offset
-const VIRTIO_PCI_DRIVER_FEATURES: u16 = 0x04;
-/// Virtio MSI-X configuration register offset
-const VIRTIO_PCI_MSIX_CONFIG: u16 = 0x11;
+const VIRTIO_PCI_ISR: u16 = 0x13;
+/// Device-specific configuration follows the header (MSI-X disabled)
+const VIRTIO_PCI_CONFIG_OFFSET: u16 = 0x14;
+
+/// Vendor-specific PCI capability ID, used for the virtio 1.0 structures
+const PCI_CAP_ID_VNDR: u8 = 0x09;
+
+/// virtio 1.0 PCI capability types (`cfg_type`)
+const VIRTIO_PCI_CAP_COMMON_CFG: u8 = 1;
+const VIRTIO_PCI_CAP_NOTIFY_CFG: u8 = 2;
+const VIRTIO_PCI_CAP_ISR_CFG: u8 = 3;
+const VIRTIO_PCI_CAP_DEVICE_CFG: u8 = 4;
+
+/// ISR status bit: a virtqueue has new used entries (reading clears it)
+const VIRTIO_ISR_QUEUE: u8 = 1;
+
+/// Offset of `notify_off_multiplier` in the notify capability
+const VIRTIO_PCI_NOTIFY_MULTIPLIER_OFFSET: u8 = 16;
+
+/// virtio 1.0 common configuration offsets (memory space)
+const COMMON_DEVICE_FEATURE_SELECT: usize = 0x00;
+const COMMON_DEVICE_FEATURE: usize = 0x04;
+const COMMON_DRIVER_FEATURE_SELECT: usize = 0x08;
+const COMMON_DRIVER_FEATURE: usize = 0x0C;
+const COMMON_DEVICE_STATUS: usize = 0x14;
+const COMMON_QUEUE_SELECT: usize = 0x16;
+const COMMON_QUEUE_SIZE: usize = 0x18;
+const COMMON_QUEUE_ENABLE: usize = 0x1C;
+const COMMON_QUEUE_NOTIFY_OFF: usize = 0x1E;
+const COMMON_QUEUE_DESC: usize = 0x20;
+const COMMON_QUEUE_DRIVER: usize = 0x28;
+const COMMON_QUEUE_DEVICE: usize = 0x30;
+
+/// PCI command register bits
+const PCI_COMMAND_OFFSET: u8 = 0x04;
+const PCI_COMMAND_IO: u32 = 1 << 0;
+const PCI_COMMAND_MEMORY: u32 = 1 << 1;
+const PCI_COMMAND_BUS_MASTER: u32 = 1 << 2;
+
+/// Alignment of the used ring; a page satisfies both legacy and 1.0 devices
+const VIRTQ_USED_ALIGN: usize = 4096;
 
 /// Virtio-net specific configuration offsets (from config space base)
 const VIRTIO_NET_CONFIG_MAC: u16 = 0x00; // 6 bytes
@@ -69,6 +104,66 @@ const VIRTQ_USED_F_NO_NOTIFY: u16 = 1;
 /// Size of virtqueue (must be power of 2)
 const VIRTQUEUE_SIZE: u16 = 256;
 
+/// Largest Ethernet frame (without FCS) the stack hands to `send`
+const MAX_FRAME_SIZE: usize = 1526;
+
+/// Size of `VirtioNetHdr` with the `num_buffers` field
+const VIRTIO_NET_HDR_SIZE: usize = core::mem::size_of::<VirtioNetHdr>();
+
+/// Header that precedes every frame on a virtio-net queue
+///
+/// We negotiate neither checksum offload nor GSO, so the header is all
+/// zeroes on transmit and ignored on receive.
+#[repr(C, packed)]
+#[derive(Default)]
+struct VirtioNetHdr {
+    flags: u8,
+    gso_type: u8,
+    hdr_len: u16,
+    gso_size: u16,
+    csum_start: u16,
+    csum_offset: u16,
+    /// Only present with VERSION_1 or MRG_RXBUF
+    num_buffers: u16,
+}
+
+/// Length of the virtio-net header for the negotiated features
+///
+/// The legacy header is 10 bytes; `num_buffers` is appended when
+/// MRG_RXBUF is negotiated and is always present on 1.0 devices.
+fn virtio_net_hdr_len(features: u64) -> usize {
+    if features & (VIRTIO_F_VERSION_1 | VIRTIO_NET_F_MRG_RXBUF) != 0 {
+        VIRTIO_NET_HDR_SIZE
+    } else {
+        VIRTIO_NET_HDR_SIZE - 2
+    }
+}
+
+/// The frame that follows the virtio-net header in a received buffer
+fn strip_virtio_net_hdr(buffer: &[u8], hdr_len: usize) -> Option<&[u8]> {
+    buffer.get(hdr_len..).filter(|frame| !frame.is_empty())
+}
+
+/// Device-writable header and frame descriptors for an RX buffer
+fn rx_chain(phys: u64, size: usize, hdr_len: usize) -> [(u64, u32, u16); 2] {
+    [
+        (phys, hdr_len as u32, VIRTQ_DESC_F_WRITE),
+        (
+            phys + hdr_len as u64,
+            (size - hdr_len) as u32,
+            VIRTQ_DESC_F_WRITE,
+        ),
+    ]
+}
+
+/// Whether a virtio-net function has no legacy interface
+///
+/// Transitional devices use the 0.9.5 device ID and revision 0; anything
+/// else (the 1.0 ID, or a nonzero revision) is modern-only.
+fn is_non_transitional(device_id: u16, revision_id: u8) -> bool {
+    device_id == VIRTIO_NET_MODERN_DEVICE_ID || revision_id != 0
+}
+
 /// Virtqueue descriptor structure
 #[repr(C, packed)]
 struct VirtqDesc {
@@ -103,6 +198,17 @@ struct VirtqUsed {
     avail_event: u16, // Only if VIRTIO_F_EVENT_IDX
 }
 
+/// Byte offset of the used ring and total size of a virtqueue of `size` entries
+///
+/// The descriptor table and available ring are contiguous; the used ring
+/// starts on the next `VIRTQ_USED_ALIGN` boundary.
+fn virtqueue_layout(size: u16) -> (usize, usize) {
+    let desc_size = core::mem::size_of::<VirtqDesc>() * size as usize;
+    let avail_size = core::mem::size_of::<VirtqAvail>();
+    let used_offset = (desc_size + avail_size).next_multiple_of(VIRTQ_USED_ALIGN);
+    (used_offset, used_offset + core::mem::size_of::<VirtqUsed>())
+}
+
 /// Virtqueue structure
 struct Virtqueue {
     /// Descriptor table
@@ -111,6 +217,8 @@ struct Virtqueue {
     avail: *mut VirtqAvail,
     /// Used ring
     used: *mut VirtqUsed,
+    /// Physical address of the descriptor table (start of the queue memory)
+    phys: u64,
     /// Queue size
     size: u16,
     /// Next free descriptor index
@@ -119,6 +227,8 @@ struct Virtqueue {
     last_used_idx: u16,
     /// Descriptor indices for pending packets
     pending: alloc::vec::Vec<u16>,
+    /// Queue notify offset (virtio 1.0 only)
+    notify_off: u16,
 }
 
 impl Virtqueue {
@@ -129,19 +239,17 @@ impl Virtqueue {
     ///
     /// # Arguments
     /// * `memory_base` - Base address of pre-allocated memory (must be page-aligned)
-    unsafe fn new(size: u16, memory_base: *mut u8) -> Result<Self, NetError> {
-        // Calculate sizes
-        let desc_size = core::mem::size_of::<VirtqDesc>() * size as usize;
-        let avail_size = core::mem::size_of::<VirtqAvail>();
-        let used_size = core::mem::size_of::<VirtqUsed>();
-
+    /// * `phys_base` - Physical address of `memory_base`
+    unsafe fn new(size: u16, memory_base: *mut u8, phys_base: u64) -> Result<Self, NetError> {
         // Calculate offsets
+        let desc_size = core::mem::size_of::<VirtqDesc>() * size as usize;
+        let (used_offset, total_size) = virtqueue_layout(size);
         let desc = memory_base as *mut VirtqDesc;
         let avail = memory_base.add(desc_size) as *mut VirtqAvail;
-        let used = memory_base.add(desc_size + avail_size) as *mut VirtqUsed;
+        let used = memory_base.add(used_offset) as *mut VirtqUsed;
 
         // Zero out memory
-        ptr::write_bytes(memory_base, 0, desc_size + avail_size + used_size);
+        ptr::write_bytes(memory_base, 0, total_size);
 
         // Initialize available ring
         (*avail).flags = 0;
@@ -155,10 +263,12 @@ impl Virtqueue {
             desc,
             avail,
             used,
+            phys: phys_base,
             size,
             next_free: 0,
             last_used_idx: 0,
             pending: alloc::vec::Vec::new(),
+            notify_off: 0,
         })
     }
 
@@ -206,20 +316,56 @@ impl Virtqueue {
         Ok(idx)
     }
 
-    /// Notify the device about new buffers
-    unsafe fn notify(&mut self, queue_index: u16, io_base: usize) {
-        // Add descriptor to available ring
+    /// Add a chain of buffers linked with `VIRTQ_DESC_F_NEXT`
+    ///
+    /// # Arguments
+    /// * `parts` - `(addr, len, flags)` for each buffer, in order
+    ///
+    /// # Returns
+    /// The index of the head descriptor, which identifies the chain in the
+    /// available and used rings
+    unsafe fn add_chain(&mut self, parts: &[(u64, u32, u16)]) -> Result<u16, NetError> {
+        if parts.is_empty() || parts.len() > self.size as usize {
+            return Err(NetError::QueueError(format!(
+                "Invalid descriptor chain length {}",
+                parts.len()
+            )));
+        }
+
+        let head = self.next_free;
+        let mut prev: Option<u16> = None;
+        for &(addr, len, flags) in parts {
+            let idx = self.add_buffer(addr, len, flags)?;
+            if let Some(prev) = prev {
+                let desc = &mut *self.desc.add(prev as usize);
+                desc.flags |= VIRTQ_DESC_F_NEXT;
+                desc.next = idx;
+            }
+            prev = Some(idx);
+        }
+        Ok(head)
+    }
+
+    /// Make a descriptor chain available to the device
+    ///
+    /// The device isn't told until `notify`.
+    unsafe fn publish(&mut self, head: u16) {
         let avail = &mut *self.avail;
         let ring_idx = (avail.idx % self.size) as usize;
-        avail.ring[ring_idx] = self.next_free.wrapping_sub(1);
+        avail.ring[ring_idx] = head;
+
+        // The ring entry must be visible before the index
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
         avail.idx = avail.idx.wrapping_add(1);
+    }
 
+    /// Notify the device about new buffers
+    unsafe fn notify(&mut self, queue_index: u16, transport: &Transport) {
         // Memory barrier to ensure writes are visible
         core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
 
         // Notify device
-        let notify_addr = io_base + (VIRTIO_PCI_QUEUE_NOTIFY as usize);
-        ptr::write_volatile(notify_addr as *mut u16, queue_index);
+        transport.notify(queue_index, self.notify_off);
     }
 
     /// Check for used buffers
@@ -241,26 +387,344 @@ impl Virtqueue {
     }
 }
 
+/// A virtio 1.0 vendor-specific PCI capability
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct VirtioPciCap {
+    /// Structure type (`VIRTIO_PCI_CAP_*`)
+    cfg_type: u8,
+    /// BAR holding the structure
+    bar: u8,
+    /// Offset of the structure within the BAR
+    offset: u32,
+    /// Length of the structure
+    length: u32,
+}
+
+impl VirtioPciCap {
+    /// Parse the first four dwords of a vendor-specific capability
+    fn parse(dwords: [u32; 4]) -> Option<Self> {
+        if dwords[0] as u8 != PCI_CAP_ID_VNDR {
+            return None;
+        }
+        let bar = dwords[1] as u8;
+        if bar > 5 {
+            return None;
+        }
+        Some(Self {
+            cfg_type: (dwords[0] >> 24) as u8,
+            bar,
+            offset: dwords[2],
+            length: dwords[3],
+        })
+    }
+}
+
+/// Memory-mapped virtio 1.0 register blocks
+#[derive(Debug, Clone, Copy)]
+struct ModernRegs {
+    /// Common configuration
+    common: usize,
+    /// Base of the queue notify area
+    notify: usize,
+    /// Bytes between the notify addresses of consecutive `queue_notify_off`s
+    notify_off_multiplier: u32,
+    /// ISR status byte
+    isr: usize,
+    /// Device-specific configuration (virtio-net config)
+    device: usize,
+}
+
+/// How the device's registers are reached
+#[derive(Debug, Clone, Copy)]
+enum Transport {
+    /// Legacy (virtio 0.9.5) header in I/O space
+    Legacy { io_base: u16 },
+    /// virtio 1.0 structures in memory, located through PCI capabilities
+    Modern(ModernRegs),
+}
+
+impl Transport {
+    /// Locate the virtio 1.0 structures, or `None` if the device lacks them
+    fn find_modern(pci_device: &PciDevice) -> Option<ModernRegs> {
+        let mut common = None;
+        let mut notify = None;
+        let mut isr = None;
+        let mut device = None;
+
+        for cap in pci_device.capabilities() {
+            if cap.id != PCI_CAP_ID_VNDR {
+                continue;
+            }
+            let mut dwords = [0u32; 4];
+            for (i, dword) in dwords.iter_mut().enumerate() {
+                *dword = pci_device.read_config_dword(cap.offset + (i as u8) * 4);
+            }
+            let Some(virtio_cap) = VirtioPciCap::parse(dwords) else {
+                continue;
+            };
+            let base = pci_device.get_bar(virtio_cap.bar as usize) as usize;
+            if base == 0 || virtio_cap.length == 0 {
+                continue;
+            }
+            let addr = base + virtio_cap.offset as usize;
+
+            // The first capability of each type is the preferred one
+            match virtio_cap.cfg_type {
+                VIRTIO_PCI_CAP_COMMON_CFG if common.is_none() => common = Some(addr),
+                VIRTIO_PCI_CAP_NOTIFY_CFG if notify.is_none() => {
+                    let multiplier = pci_device
+                        .read_config_dword(cap.offset + VIRTIO_PCI_NOTIFY_MULTIPLIER_OFFSET);
+                    notify = Some((addr, multiplier));
+                }
+                VIRTIO_PCI_CAP_ISR_CFG if isr.is_none() => isr = Some(addr),
+                VIRTIO_PCI_CAP_DEVICE_CFG if device.is_none() => device = Some(addr),
+                _ => {}
+            }
+        }
+
+        let (notify, notify_off_multiplier) = notify?;
+        Some(ModernRegs {
+            common: common?,
+            notify,
+            notify_off_multiplier,
+            isr: isr?,
+            device: device?,
+        })
+    }
+
+    fn is_modern(&self) -> bool {
+        matches!(self, Transport::Modern(_))
+    }
+
+    fn read_status(&self) -> u8 {
+        match *self {
+            Transport::Legacy { io_base } => unsafe {
+                Port::<u8>::new(io_base + VIRTIO_PCI_STATUS).read()
+            },
+            Transport::Modern(regs) => unsafe {
+                ptr::read_volatile((regs.common + COMMON_DEVICE_STATUS) as *const u8)
+            },
+        }
+    }
+
+    fn write_status(&self, status: u8) {
+        match *self {
+            Transport::Legacy { io_base } => unsafe {
+                Port::<u8>::new(io_base + VIRTIO_PCI_STATUS).write(status)
+            },
+            Transport::Modern(regs) => unsafe {
+                ptr::write_volatile((regs.common + COMMON_DEVICE_STATUS) as *mut u8, status)
+            },
+        }
+    }
+
+    /// Read and clear the ISR status, deasserting a legacy interrupt
+    fn read_isr(&self) -> u8 {
+        match *self {
+            Transport::Legacy { io_base } => unsafe {
+                Port::<u8>::new(io_base + VIRTIO_PCI_ISR).read()
+            },
+            Transport::Modern(regs) => unsafe { ptr::read_volatile(regs.isr as *const u8) },
+        }
+    }
+
+    /// Read the feature bits offered by the device
+    ///
+    /// Legacy devices only have 32 feature bits.
+    fn device_features(&self) -> u64 {
+        match *self {
+            Transport::Legacy { io_base } => unsafe {
+                Port::<u32>::new(io_base + VIRTIO_PCI_DEVICE_FEATURES).read() as u64
+            },
+            Transport::Modern(regs) => unsafe {
+                let select = (regs.common + COMMON_DEVICE_FEATURE_SELECT) as *mut u32;
+                let feature = (regs.common + COMMON_DEVICE_FEATURE) as *const u32;
+                ptr::write_volatile(select, 0);
+                let low = ptr::read_volatile(feature) as u64;
+                ptr::write_volatile(select, 1);
+                let high = ptr::read_volatile(feature) as u64;
+                low | (high << 32)
+            },
+        }
+    }
+
+    fn write_driver_features(&self, features: u64) {
+        match *self {
+            Transport::Legacy { io_base } => unsafe {
+                Port::<u32>::new(io_base + VIRTIO_PCI_DRIVER_FEATURES).write(features as u32)
+            },
+            Transport::Modern(regs) => unsafe {
+                let select = (regs.common + COMMON_DRIVER_FEATURE_SELECT) as *mut u32;
+                let feature = (regs.common + COMMON_DRIVER_FEATURE) as *mut u32;
+                ptr::write_volatile(select, 0);
+                ptr::write_volatile(feature, features as u32);
+                ptr::write_volatile(select, 1);
+                ptr::write_volatile(feature, (features >> 32) as u32);
+            },
+        }
+    }
+
+    /// Read a byte of virtio-net configuration space
+    fn read_config_u8(&self, offset: u16) -> u8 {
+        match *self {
+            Transport::Legacy { io_base } => unsafe {
+                Port::<u8>::new(io_base + VIRTIO_PCI_CONFIG_OFFSET + offset).read()
+            },
+            Transport::Modern(regs) => unsafe {
+                ptr::read_volatile((regs.device + offset as usize) as *const u8)
+            },
+        }
+    }
+
+    /// Read a 16-bit field of virtio-net configuration space
+    fn read_config_u16(&self, offset: u16) -> u16 {
+        match *self {
+            Transport::Legacy { io_base } => unsafe {
+                Port::<u16>::new(io_base + VIRTIO_PCI_CONFIG_OFFSET + offset).read()
+            },
+            Transport::Modern(regs) => unsafe {
+                ptr::read_volatile((regs.device + offset as usize) as *const u16)
+            },
+        }
+    }
+
+    /// Hand a virtqueue to the device
+    ///
+    /// `desc`, `avail` and `used` are the physical addresses of the three
+    /// parts of the queue. Returns the queue's notify offset.
+    fn setup_queue(
+        &self,
+        queue_index: u16,
+        size: u16,
+        desc: u64,
+        avail: u64,
+        used: u64,
+    ) -> Result<u16, NetError> {
+        match *self {
+            Transport::Legacy { io_base } => unsafe {
+                Port::<u16>::new(io_base + VIRTIO_PCI_QUEUE_SEL).write(queue_index);
+
+                // Legacy queue sizes are fixed by the device
+                let device_size = Port::<u16>::new(io_base + VIRTIO_PCI_QUEUE_NUM).read();
+                if device_size != size {
+                    return Err(NetError::QueueError(format!(
+                        "Queue {} has size {}, expected {}",
+                        queue_index, device_size, size
+                    )));
+                }
+
+                // The legacy layout is implied by the page frame number
+                if (desc & 0xFFF) != 0 {
+                    return Err(NetError::QueueError("Queue not page-aligned".to_string()));
+                }
+                let pfn = desc >> 12;
+                if pfn == 0 || pfn > u32::MAX as u64 {
+                    return Err(NetError::QueueError(
+                        "Invalid page frame number".to_string(),
+                    ));
+                }
+                Port::<u32>::new(io_base + VIRTIO_PCI_QUEUE_PFN).write(pfn as u32);
+                Ok(0)
+            },
+            Transport::Modern(regs) => unsafe {
+                let common = regs.common;
+                ptr::write_volatile((common + COMMON_QUEUE_SELECT) as *mut u16, queue_index);
+
+                let max_size = ptr::read_volatile((common + COMMON_QUEUE_SIZE) as *const u16);
+                if max_size == 0 {
+                    return Err(NetError::QueueError(format!(
+                        "Queue {} is not available",
+                        queue_index
+                    )));
+                }
+                if max_size < size {
+                    return Err(NetError::QueueError(format!(
+                        "Queue {} holds at most {} entries, need {}",
+                        queue_index, max_size, size
+                    )));
+                }
+
+                ptr::write_volatile((common + COMMON_QUEUE_SIZE) as *mut u16, size);
+                ptr::write_volatile((common + COMMON_QUEUE_DESC) as *mut u64, desc);
+                ptr::write_volatile((common + COMMON_QUEUE_DRIVER) as *mut u64, avail);
+                ptr::write_volatile((common + COMMON_QUEUE_DEVICE) as *mut u64, used);
+                let notify_off =
+                    ptr::read_volatile((common + COMMON_QUEUE_NOTIFY_OFF) as *const u16);
+                ptr::write_volatile((common + COMMON_QUEUE_ENABLE) as *mut u16, 1);
+                Ok(notify_off)
+            },
+        }
+    }
+
+    /// Tell the device that a queue has new buffers
+    fn notify(&self, queue_index: u16, notify_off: u16) {
+        match *self {
+            Transport::Legacy { io_base } => unsafe {
+                Port::<u16>::new(io_base + VIRTIO_PCI_QUEUE_NOTIFY).write(queue_index)
+            },
+            Transport::Modern(regs) => unsafe {
+                let addr = regs.notify + notify_off as usize * regs.notify_off_multiplier as usize;
+                ptr::write_volatile(addr as *mut u16, queue_index)
+            },
+        }
+    }
+}
+
+/// What the interrupt handler needs to find received frames
+struct IrqSource {
+    transport: Transport,
+    /// RX used ring
+    used: usize,
+    /// RX queue size
+    size: u16,
+    /// Next used-ring entry the handler hasn't collected
+    last_used: u16,
+}
+
+/// Set while a driver is in interrupt-driven mode. Only changed with
+/// interrupts disabled, so the handler never spins on it.
+static IRQ_SOURCE: Mutex<Option<IrqSource>> = Mutex::new(None);
+
+/// Acknowledge a virtio-net interrupt and collect completed RX descriptors
+///
+/// Returns `false` if the device didn't raise it (the line may be shared).
+pub(crate) fn service_interrupt() -> bool {
+    let mut guard = IRQ_SOURCE.lock();
+    let Some(source) = guard.as_mut() else {
+        return false;
+    };
+
+    let isr = source.transport.read_isr();
+    if isr & VIRTIO_ISR_QUEUE != 0 {
+        let used = source.used as *const VirtqUsed;
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+        // SAFETY: the ring stays allocated while the source is registered;
+        // the struct is packed, so fields are read unaligned
+        let idx = unsafe { ptr::read_volatile(ptr::addr_of!((*used).idx)) };
+        while source.last_used != idx {
+            let slot = (source.last_used % source.size) as usize;
+            let elem = unsafe { ptr::read_unaligned(ptr::addr_of!((*used).ring[slot])) };
+            if !RX_COMPLETIONS.push(elem.id, elem.len) {
+                break;
+            }
+            source.last_used = source.last_used.wrapping_add(1);
+        }
+    }
+    isr != 0
+}
+
 /// RX buffer information
 struct RxBuffer {
-    /// Physical address
-    phys: u64,
-    /// Virtual address
-    ptr: *mut u8,
-    /// Buffer size
-    size: usize,
+    /// Header and frame memory
+    memory: DmaBuffer,
     /// Descriptor index in the queue
     desc_idx: u16,
 }
 
 /// TX buffer information
 struct TxBuffer {
-    /// Physical address
-    phys: u64,
-    /// Virtual address
-    ptr: *mut u8,
-    /// Buffer size
-    size: usize,
+    /// Header and frame memory
+    memory: DmaBuffer,
     /// Descriptor index in the queue
     desc_idx: u16,
 }
@@ -269,10 +733,8 @@ struct TxBuffer {
 pub struct VirtioNet {
     /// PCI device information
     pci_device: PciDevice,
-    /// I/O base address (BAR0)
-    io_base: usize,
-    /// Configuration space base address
-    config_base: usize,
+    /// Register access (legacy I/O ports or virtio 1.0 MMIO)
+    transport: Transport,
     /// MAC address
     mac_address: [u8; 6],
     /// Receive queue
@@ -283,8 +745,24 @@ pub struct VirtioNet {
     rx_buffers: alloc::vec::Vec<RxBuffer>,
     /// TX buffer pool with descriptor mapping
     tx_buffers: alloc::vec::Vec<TxBuffer>,
+    /// Ring memory for the RX and TX queues
+    queue_memory: alloc::vec::Vec<DmaBuffer>,
+    /// Length of the virtio-net header for the negotiated features
+    hdr_len: usize,
     /// Initialized flag
     initialized: bool,
+    /// RX completions come from the interrupt handler, not the used ring
+    rx_irq: bool,
+}
+
+impl Drop for VirtioNet {
+    fn drop(&mut self) {
+        // Stop the device before handing its memory back
+        if !self.queue_memory.is_empty() {
+            self.write_status(0);
+        }
+        self.release_memory();
+    }
 }
 
 // SAFETY: VirtioNet is only used behind a global lock; callers must ensure no
@@ -296,34 +774,63 @@ impl VirtioNet {
     ///
     /// This will scan for a virtio-net PCI device and initialize it.
     pub fn new() -> Result<Self, NetError> {
-        // Find virtio-net PCI device
+        // Find virtio-net PCI device (transitional or modern-only)
         let pci_device = find_pci_device(VIRTIO_VENDOR_ID, VIRTIO_NET_DEVICE_ID)
+            .or_else(|| find_pci_device(VIRTIO_VENDOR_ID, VIRTIO_NET_MODERN_DEVICE_ID))
             .ok_or(NetError::DeviceNotFound)?;
 
-        // Get BAR0 (I/O base)
-        let io_base = pci_device.get_bar(0) as usize;
-        if io_base == 0 {
-            return Err(NetError::PciError("BAR0 is invalid".to_string()));
-        }
-
-        // Get configuration space base (BAR0 + offset)
-        let config_base = io_base + VIRTIO_PCI_CONFIG_OFFSET as usize;
+        // Prefer the virtio 1.0 interface; only transitional devices may fall
+        // back to the legacy I/O BAR0
+        let transport = match Transport::find_modern(&pci_device) {
+            Some(regs) => Transport::Modern(regs),
+            None if is_non_transitional(pci_device.device_id, pci_device.revision_id) => {
+                return Err(NetError::PciError(
+                    "Modern-only virtio device without virtio capabilities".to_string(),
+                ));
+            }
+            None => {
+                if (pci_device.bars[0] & 1) == 0 {
+                    return Err(NetError::PciError(
+                        "No virtio capabilities and BAR0 is not an I/O BAR".to_string(),
+                    ));
+                }
+                let io_base = (pci_device.bars[0] & !0x3) as u16;
+                if io_base == 0 {
+                    return Err(NetError::PciError("BAR0 is invalid".to_string()));
+                }
+                Transport::Legacy { io_base }
+            }
+        };
 
         Ok(VirtioNet {
             pci_device,
-            io_base,
-            config_base,
+            transport,
             mac_address: [0; 6],
             rx_queue: None,
             tx_queue: None,
             rx_buffers: alloc::vec::Vec::new(),
             tx_buffers: alloc::vec::Vec::new(),
+            queue_memory: alloc::vec::Vec::new(),
+            hdr_len: virtio_net_hdr_len(0),
             initialized: false,
+            rx_irq: false,
         })
     }
 
     /// Initialize the virtio-net device
     pub fn init(&mut self) -> Result<(), NetError> {
+        // Enable register decoding and DMA
+        let command = self.pci_device.read_config_dword(PCI_COMMAND_OFFSET) & 0xFFFF;
+        let decode = if self.transport.is_modern() {
+            PCI_COMMAND_MEMORY
+        } else {
+            PCI_COMMAND_IO
+        };
+        self.pci_device.write_config_dword(
+            PCI_COMMAND_OFFSET,
+            command | decode | PCI_COMMAND_BUS_MASTER,
+        );
+
         // Reset device
         self.write_status(0);
 
@@ -336,10 +843,20 @@ impl VirtioNet {
         // Read device features
         let device_features = self.read_device_features();
 
-        // Negotiate features (we support basic features)
-        let driver_features =
-            device_features & (VIRTIO_NET_F_MAC | VIRTIO_NET_F_STATUS | VIRTIO_F_VERSION_1);
+        // Negotiate features (we support basic features). The 1.0
+        // interface requires VERSION_1; the legacy one can't express it.
+        let mut driver_features = device_features & (VIRTIO_NET_F_MAC | VIRTIO_NET_F_STATUS);
+        if self.transport.is_modern() {
+            if (device_features & VIRTIO_F_VERSION_1) == 0 {
+                self.write_status(VIRTIO_STATUS_FAILED);
+                return Err(NetError::VirtioError(
+                    "Device does not offer VIRTIO_F_VERSION_1".to_string(),
+                ));
+            }
+            driver_features |= VIRTIO_F_VERSION_1;
+        }
         self.write_driver_features(driver_features);
+        self.hdr_len = virtio_net_hdr_len(driver_features);
 
         // Set features OK
         self.write_status(
@@ -354,12 +871,10 @@ impl VirtioNet {
         }
 
         // Read MAC address from configuration space
-        unsafe {
-            for i in 0..6 {
-                self.mac_address[i] = ptr::read_volatile(
-                    (self.config_base + VIRTIO_NET_CONFIG_MAC as usize + i) as *const u8,
-                );
-            }
+        for i in 0..6 {
+            self.mac_address[i] = self
+                .transport
+                .read_config_u8(VIRTIO_NET_CONFIG_MAC + i as u16);
         }
 
         // Initialize queues
@@ -379,48 +894,28 @@ impl VirtioNet {
 
     /// Initialize virtqueues
     fn init_queues(&mut self) -> Result<(), NetError> {
-        // Allocate memory for queues (must be page-aligned)
-        // Calculate required memory per queue
-        let desc_size = core::mem::size_of::<VirtqDesc>() * VIRTQUEUE_SIZE as usize;
-        let avail_size = core::mem::size_of::<VirtqAvail>();
-        let used_size = core::mem::size_of::<VirtqUsed>();
-        let queue_size = desc_size + avail_size + used_size;
-
-        // Allocate RX queue memory
-        let rx_memory = unsafe {
-            let layout = core::alloc::Layout::from_size_align(queue_size, 4096)
-                .map_err(|_| NetError::QueueError("Invalid RX queue layout".to_string()))?;
-            let ptr = alloc::alloc::alloc_zeroed(layout);
-            if ptr.is_null() {
-                return Err(NetError::QueueError(
-                    "Failed to allocate RX queue memory".to_string(),
-                ));
-            }
-            ptr
-        };
-
-        // Allocate TX queue memory
-        let tx_memory = unsafe {
-            let layout = core::alloc::Layout::from_size_align(queue_size, 4096)
-                .map_err(|_| NetError::QueueError("Invalid TX queue layout".to_string()))?;
-            let ptr = alloc::alloc::alloc_zeroed(layout);
-            if ptr.is_null() {
-                return Err(NetError::QueueError(
-                    "Failed to allocate TX queue memory".to_string(),
-                ));
-            }
-            ptr
-        };
+        // The device was reset, so memory from a previous init is unused
+        self.release_memory();
+
+        // Allocate memory for queues (must be page-aligned and physically
+        // contiguous)
+        let (_, queue_size) = virtqueue_layout(VIRTQUEUE_SIZE);
+        let rx_memory = DmaBuffer::alloc(queue_size, 4096)?;
+        let (rx_ptr, rx_phys) = (rx_memory.ptr, rx_memory.phys);
+        self.queue_memory.push(rx_memory);
+        let tx_memory = DmaBuffer::alloc(queue_size, 4096)?;
+        let (tx_ptr, tx_phys) = (tx_memory.ptr, tx_memory.phys);
+        self.queue_memory.push(tx_memory);
 
         // Initialize RX queue
         unsafe {
-            let rx_queue = Virtqueue::new(VIRTQUEUE_SIZE, rx_memory)?;
-            self.setup_queue(VIRTIO_NET_RX_QUEUE, &rx_queue)?;
+            let mut rx_queue = Virtqueue::new(VIRTQUEUE_SIZE, rx_ptr, rx_phys)?;
+            self.setup_queue(VIRTIO_NET_RX_QUEUE, &mut rx_queue)?;
             self.rx_queue = Some(rx_queue);
 
             // Initialize TX queue
-            let tx_queue = Virtqueue::new(VIRTQUEUE_SIZE, tx_memory)?;
-            self.setup_queue(VIRTIO_NET_TX_QUEUE, &tx_queue)?;
+            let mut tx_queue = Virtqueue::new(VIRTQUEUE_SIZE, tx_ptr, tx_phys)?;
+            self.setup_queue(VIRTIO_NET_TX_QUEUE, &mut tx_queue)?;
             self.tx_queue = Some(tx_queue);
         }
 
@@ -434,93 +929,73 @@ impl VirtioNet {
     ///
     /// # Errors
     /// Returns `NetError::QueueError` if queue setup fails
-    unsafe fn setup_queue(&mut self, queue_index: u16, queue: &Virtqueue) -> Result<(), NetError> {
+    unsafe fn setup_queue(
+        &mut self,
+        queue_index: u16,
+        queue: &mut Virtqueue,
+    ) -> Result<(), NetError> {
         if queue.desc.is_null() {
             return Err(NetError::QueueError(
                 "Queue descriptor table is null".to_string(),
             ));
         }
-
-        // Select queue
-        self.write_u16(VIRTIO_PCI_QUEUE_SEL, queue_index);
-
-        // Set queue size
         if queue.size == 0 {
             return Err(NetError::QueueError("Queue size is zero".to_string()));
         }
-        self.write_u16(VIRTIO_PCI_QUEUE_NUM, queue.size);
 
-        // Get physical address of queue
-        let queue_phys = self.virt_to_phys(queue.desc as usize);
-        if queue_phys == 0 {
+        // Get physical addresses of the queue parts
+        let (used_offset, _) = virtqueue_layout(queue.size);
+        let desc = queue.phys;
+        let avail = desc + (core::mem::size_of::<VirtqDesc>() * queue.size as usize) as u64;
+        let used = desc + used_offset as u64;
+        if desc == 0 {
             return Err(NetError::QueueError(
                 "Failed to get physical address of queue".to_string(),
             ));
         }
 
-        // Verify alignment (must be page-aligned)
-        if (queue_phys & 0xFFF) != 0 {
-            return Err(NetError::QueueError("Queue not page-aligned".to_string()));
-        }
-
-        // Set queue address (PFN = physical frame number)
-        let pfn = queue_phys >> 12; // Page frame number
-        if pfn == 0 {
-            return Err(NetError::QueueError(
-                "Invalid page frame number".to_string(),
-            ));
-        }
-        self.write_u32(VIRTIO_PCI_QUEUE_PFN, pfn as u32);
-
+        queue.notify_off =
+            self.transport
+                .setup_queue(queue_index, queue.size, desc, avail, used)?;
         Ok(())
     }
 
     /// Allocate RX buffers
     fn allocate_rx_buffers(&mut self) -> Result<(), NetError> {
-        // Allocate buffers for receiving packets
-        // Each buffer is 1526 bytes (max Ethernet frame size)
-        const BUFFER_SIZE: usize = 1526;
+        // Allocate buffers for receiving packets. Each holds the
+        // virtio-net header followed by a full frame.
+        const BUFFER_SIZE: usize = VIRTIO_NET_HDR_SIZE + MAX_FRAME_SIZE;
         const NUM_BUFFERS: usize = 32;
+        let hdr_len = self.hdr_len;
 
         if let Some(ref mut rx_queue) = self.rx_queue {
             for _ in 0..NUM_BUFFERS {
-                let layout = core::alloc::Layout::from_size_align(BUFFER_SIZE, 16)
-                    .map_err(|_| NetError::QueueError("Invalid buffer layout".to_string()))?;
+                let memory = DmaBuffer::alloc(BUFFER_SIZE, 16)?;
 
                 unsafe {
-                    let ptr = alloc::alloc::alloc_zeroed(layout);
-                    if ptr.is_null() {
-                        return Err(NetError::QueueError(
-                            "Failed to allocate RX buffer".to_string(),
-                        ));
-                    }
-
-                    let phys = ptr as u64;
-
-                    // Add buffer to RX queue and get descriptor index
-                    let desc_idx = rx_queue
-                        .add_buffer(phys, BUFFER_SIZE as u32, VIRTQ_DESC_F_WRITE)
-                        .map_err(|e| {
+                    // Post the header and frame as separate descriptors;
+                    // legacy devices without ANY_LAYOUT require it
+                    let chain = rx_chain(memory.phys, BUFFER_SIZE, hdr_len);
+                    let desc_idx = match rx_queue.add_chain(&chain) {
+                        Ok(desc_idx) => desc_idx,
+                        Err(e) => {
                             // Clean up on error
-                            alloc::alloc::dealloc(ptr, layout);
-                            e
-                        })?;
+                            memory.free();
+                            return Err(e);
+                        }
+                    };
 
                     // Store buffer with descriptor mapping
-                    self.rx_buffers.push(RxBuffer {
-                        phys,
-                        ptr,
-                        size: BUFFER_SIZE,
-                        desc_idx,
-                    });
+                    self.rx_buffers.push(RxBuffer { memory, desc_idx });
 
                     rx_queue.pending.push(desc_idx);
+                    rx_queue.publish(desc_idx);
                 }
             }
 
             // Notify device about RX buffers
             unsafe {
-                rx_queue.notify(VIRTIO_NET_RX_QUEUE, self.io_base);
+                rx_queue.notify(VIRTIO_NET_RX_QUEUE, &self.transport);
             }
         } else {
             return Err(NetError::QueueError("RX queue not initialized".to_string()));
@@ -531,69 +1006,48 @@ impl VirtioNet {
 
     /// Read device status
     fn read_status(&self) -> u8 {
-        unsafe { ptr::read_volatile((self.io_base + VIRTIO_PCI_STATUS as usize) as *const u8) }
+        self.transport.read_status()
     }
 
     /// Write device status
     fn write_status(&mut self, status: u8) {
-        unsafe {
-            ptr::write_volatile(
-                (self.io_base + VIRTIO_PCI_STATUS as usize) as *mut u8,
-                status,
-            );
-        }
+        self.transport.write_status(status);
     }
 
     /// Read device features
     fn read_device_features(&self) -> u64 {
-        unsafe {
-            let low = ptr::read_volatile(
-                (self.io_base + VIRTIO_PCI_DEVICE_FEATURES as usize) as *const u32,
-            ) as u64;
-            let high = ptr::read_volatile(
-                (self.io_base + VIRTIO_PCI_DEVICE_FEATURES as usize + 4) as *const u32,
-            ) as u64;
-            low | (high << 32)
-        }
+        self.transport.device_features()
     }
 
     /// Write driver features
     fn write_driver_features(&mut self, features: u64) {
-        unsafe {
-            ptr::write_volatile(
-                (self.io_base + VIRTIO_PCI_DRIVER_FEATURES as usize) as *mut u32,
-                features as u32,
-            );
-            ptr::write_volatile(
-                (self.io_base + VIRTIO_PCI_DRIVER_FEATURES as usize + 4) as *mut u32,
-                (features >> 32) as u32,
-            );
-        }
+        self.transport.write_driver_features(features);
     }
 
-    /// Write a 16-bit value to I/O space
-    fn write_u16(&mut self, offset: u16, value: u16) {
-        unsafe {
-            ptr::write_volatile((self.io_base + offset as usize) as *mut u16, value);
+    /// Free the queues and every RX/TX buffer
+    ///
+    /// The device must be reset (or never set up) so it no longer
+    /// accesses them.
+    fn release_memory(&mut self) {
+        if self.rx_irq {
+            self.disable_interrupts();
         }
-    }
-
-    /// Write a 32-bit value to I/O space
-    fn write_u32(&mut self, offset: u16, value: u32) {
+        self.rx_queue = None;
+        self.tx_queue = None;
+        // SAFETY: the device was reset, so nothing references the memory
         unsafe {
-            ptr::write_volatile((self.io_base + offset as usize) as *mut u32, value);
+            for buffer in self.rx_buffers.drain(..) {
+                buffer.memory.free();
+            }
+            for buffer in self.tx_buffers.drain(..) {
+                buffer.memory.free();
+            }
+            for memory in self.queue_memory.drain(..) {
+                memory.free();
+            }
         }
     }
 
-    /// Convert virtual address to physical address
-    ///
-    /// Note: This is a simplified version. In a real implementation,
-    /// you would need to use proper page table translation.
-    fn virt_to_phys(&self, virt: usize) -> u64 {
-        // For now, assume identity mapping
-        virt as u64
-    }
-
     /// Handle interrupt from the virtio device
     ///
     /// This should be called from the interrupt handler when a virtio-net interrupt occurs.
@@ -606,16 +1060,8 @@ impl VirtioNet {
             return Err(NetError::DeviceNotInitialized);
         }
 
-        // Check for received packets
-        // Note: The actual packet data retrieval is done in receive() method
-        // This just acknowledges that packets are available
-        if let Some(ref mut rx_queue) = self.rx_queue {
-            unsafe {
-                // Check if there are any used buffers (packets received)
-                // The actual processing happens in receive() method
-                let _ = rx_queue.get_used();
-            }
-        }
+        // Received frames are left for receive(); consuming used-ring
+        // entries here would lose them
 
         // Check for transmitted packets and free buffers
         if let Some(ref mut tx_queue) = self.tx_queue {
@@ -637,13 +1083,7 @@ impl VirtioNet {
                         let buffer = self.tx_buffers.remove(buf_pos);
 
                         // Deallocate the buffer
-                        let layout = core::alloc::Layout::from_size_align(buffer.size, 16)
-                            .map_err(|_| {
-                                NetError::QueueError(
-                                    "Invalid TX buffer layout for deallocation".to_string(),
-                                )
-                            })?;
-                        alloc::alloc::dealloc(buffer.ptr, layout);
+                        buffer.memory.free();
                     }
                 }
             }
@@ -664,7 +1104,7 @@ impl NetworkDriver for VirtioNet {
             return Err(NetError::DeviceNotInitialized);
         }
 
-        if packet.len() > 1526 {
+        if packet.len() > MAX_FRAME_SIZE {
             return Err(NetError::InvalidPacket("Packet too large".to_string()));
         }
 
@@ -672,48 +1112,41 @@ impl NetworkDriver for VirtioNet {
             return Err(NetError::InvalidPacket("Packet is empty".to_string()));
         }
 
-        // Allocate buffer for TX
-        let layout = core::alloc::Layout::from_size_align(packet.len(), 16)
-            .map_err(|_| NetError::QueueError("Invalid TX buffer layout".to_string()))?;
+        // Allocate buffer for TX: a zeroed header followed by the frame
+        let hdr_len = self.hdr_len;
+        let memory = DmaBuffer::alloc(hdr_len + packet.len(), 16)?;
 
         unsafe {
-            let tx_buf = alloc::alloc::alloc(layout);
-            if tx_buf.is_null() {
-                return Err(NetError::QueueError(
-                    "Failed to allocate TX buffer".to_string(),
-                ));
-            }
-
-            // Copy packet to buffer
-            ptr::copy_nonoverlapping(packet.as_ptr(), tx_buf, packet.len());
+            // Copy packet to buffer after the header
+            ptr::copy_nonoverlapping(packet.as_ptr(), memory.ptr.add(hdr_len), packet.len());
 
-            let phys = self.virt_to_phys(tx_buf as usize);
+            let phys = memory.phys;
 
             // Add to TX queue
             if let Some(ref mut tx_queue) = self.tx_queue {
-                let desc_idx = tx_queue
-                    .add_buffer(phys, packet.len() as u32, 0)
-                    .map_err(|e| {
+                let desc_idx = match tx_queue.add_chain(&[
+                    (phys, hdr_len as u32, 0),
+                    (phys + hdr_len as u64, packet.len() as u32, 0),
+                ]) {
+                    Ok(desc_idx) => desc_idx,
+                    Err(e) => {
                         // Clean up on error
-                        alloc::alloc::dealloc(tx_buf, layout);
-                        e
-                    })?;
+                        memory.free();
+                        return Err(e);
+                    }
+                };
 
                 tx_queue.pending.push(desc_idx);
 
                 // Store buffer info with descriptor mapping for later cleanup
-                self.tx_buffers.push(TxBuffer {
-                    phys,
-                    ptr: tx_buf,
-                    size: packet.len(),
-                    desc_idx,
-                });
+                self.tx_buffers.push(TxBuffer { memory, desc_idx });
 
                 // Notify device
-                tx_queue.notify(VIRTIO_NET_TX_QUEUE, self.io_base);
+                tx_queue.publish(desc_idx);
+                tx_queue.notify(VIRTIO_NET_TX_QUEUE, &self.transport);
             } else {
                 // Clean up on error
-                alloc::alloc::dealloc(tx_buf, layout);
+                memory.free();
                 return Err(NetError::QueueError("TX queue not initialized".to_string()));
             }
         }
@@ -729,7 +1162,14 @@ impl NetworkDriver for VirtioNet {
         // Check for used buffers in RX queue
         if let Some(ref mut rx_queue) = self.rx_queue {
             unsafe {
-                if let Some((used_id, len)) = rx_queue.get_used() {
+                // In interrupt mode the handler has already collected the
+                // used-ring entries
+                let completion = if self.rx_irq {
+                    RX_COMPLETIONS.pop()
+                } else {
+                    rx_queue.get_used()
+                };
+                if let Some((used_id, len)) = completion {
                     let desc_id = used_id as u16;
 
                     // Find the buffer that corresponds to this descriptor ID
@@ -744,28 +1184,27 @@ impl NetworkDriver for VirtioNet {
                     let buffer = &self.rx_buffers[buffer_idx];
 
                     // Validate length
-                    if len as usize > buffer.size {
+                    if len as usize > buffer.memory.len {
                         return Err(NetError::InvalidPacket(
                             "Received packet exceeds buffer size".to_string(),
                         ));
                     }
 
-                    // Create packet vector safely
-                    // Allocate uninitialized memory, then immediately copy valid data
-                    let mut packet = alloc::vec::Vec::with_capacity(len as usize);
-                    unsafe {
-                        // Safety: set_len() is safe here because:
-                        // 1. We set the length to exactly the amount we'll copy
-                        // 2. We immediately copy valid data from buffer.ptr into the vector
-                        // 3. The buffer.ptr is guaranteed to be valid (allocated in allocate_rx_buffers)
-                        // 4. len is validated to be <= buffer.size above
-                        packet.set_len(len as usize);
-                        ptr::copy_nonoverlapping(buffer.ptr, packet.as_mut_ptr(), len as usize);
-                    }
+                    // The used length covers the virtio-net header too
+                    // Safety: buffer.memory is valid for its length
+                    // (allocated in allocate_rx_buffers) and len is validated
+                    // to be no larger above
+                    let written = core::slice::from_raw_parts(buffer.memory.ptr, len as usize);
+                    let packet =
+                        strip_virtio_net_hdr(written, self.hdr_len).map(|frame| frame.to_vec());
 
                     // Re-add buffer to queue with new descriptor index
                     let new_desc_idx = rx_queue
-                        .add_buffer(buffer.phys, buffer.size as u32, VIRTQ_DESC_F_WRITE)
+                        .add_chain(&rx_chain(
+                            buffer.memory.phys,
+                            buffer.memory.len,
+                            self.hdr_len,
+                        ))
                         .map_err(|e| {
                             NetError::QueueError(format!("Failed to re-add RX buffer: {:?}", e))
                         })?;
@@ -780,9 +1219,11 @@ impl NetworkDriver for VirtioNet {
                     rx_queue.pending.push(new_desc_idx);
 
                     // Notify device about the new buffer
-                    rx_queue.notify(VIRTIO_NET_RX_QUEUE, self.io_base);
+                    rx_queue.publish(new_desc_idx);
+                    rx_queue.notify(VIRTIO_NET_RX_QUEUE, &self.transport);
 
-                    return Ok(Some(packet));
+                    // A buffer holding only a header carries no frame
+                    return Ok(packet);
                 }
             }
         }
@@ -800,12 +1241,58 @@ impl NetworkDriver for VirtioNet {
         }
 
         // Read link status from configuration space
-        unsafe {
-            let status = ptr::read_volatile(
-                (self.config_base + VIRTIO_NET_CONFIG_STATUS as usize) as *const u16,
-            );
-            (status & 1) != 0 // Bit 0 indicates link up
+        let status = self.transport.read_config_u16(VIRTIO_NET_CONFIG_STATUS);
+        (status & 1) != 0 // Bit 0 indicates link up
+    }
+
+    fn enable_interrupts(&mut self) -> Result<u8, NetError> {
+        if !self.initialized {
+            return Err(NetError::DeviceNotInitialized);
         }
+        let rx_queue = self
+            .rx_queue
+            .as_ref()
+            .ok_or_else(|| NetError::QueueError("RX queue not initialized".to_string()))?;
+        let line = self.interrupt_line();
+        if line == 0 || line >= 16 {
+            return Err(NetError::PciError(format!(
+                "No usable legacy IRQ line ({})",
+                line
+            )));
+        }
+
+        let source = IrqSource {
+            transport: self.transport,
+            used: rx_queue.used as usize,
+            size: rx_queue.size,
+            last_used: rx_queue.last_used_idx,
+        };
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            RX_COMPLETIONS.clear();
+            *IRQ_SOURCE.lock() = Some(source);
+        });
+        self.rx_irq = true;
+        set_irq_active(true);
+        Ok(line)
+    }
+
+    fn disable_interrupts(&mut self) {
+        if !self.rx_irq {
+            return;
+        }
+        // Resume polling from the oldest entry the handler collected but
+        // receive() hasn't taken yet
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let source = IRQ_SOURCE.lock().take();
+            if let (Some(source), Some(rx_queue)) = (source, self.rx_queue.as_mut()) {
+                rx_queue.last_used_idx = source
+                    .last_used
+                    .wrapping_sub(RX_COMPLETIONS.len() as u16);
+            }
+            RX_COMPLETIONS.clear();
+        });
+        self.rx_irq = false;
+        set_irq_active(false);
     }
 
     fn poll(&mut self) -> Result<(), NetError> {
@@ -832,13 +1319,7 @@ impl NetworkDriver for VirtioNet {
                         let buffer = self.tx_buffers.remove(buf_pos);
 
                         // Deallocate the buffer
-                        let layout = core::alloc::Layout::from_size_align(buffer.size, 16)
-                            .map_err(|_| {
-                                NetError::QueueError(
-                                    "Invalid TX buffer layout for deallocation".to_string(),
-                                )
-                            })?;
-                        alloc::alloc::dealloc(buffer.ptr, layout);
+                        buffer.memory.free();
                     } else {
                         // Descriptor ID not found in buffers - this is an error condition
                         // Log it but don't fail the poll operation
@@ -871,3 +1352,91 @@ pub fn init_virtio_net() -> Result<(), NetError> {
 pub fn get_virtio_net() -> Option<spin::MutexGuard<'static, Option<VirtioNet>>> {
     Some(VIRTIO_NET.lock())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_virtio_pci_cap() {
+        // cap_vndr, cap_next, cap_len, cfg_type=notify | bar 4 | offset | length
+        let cap = VirtioPciCap::parse([0x0214_4009, 4, 0x3000, 0x1000]).unwrap();
+        assert_eq!(cap.cfg_type, VIRTIO_PCI_CAP_NOTIFY_CFG);
+        assert_eq!(cap.bar, 4);
+        assert_eq!(cap.offset, 0x3000);
+        assert_eq!(cap.length, 0x1000);
+
+        // MSI-X capability and an out-of-range BAR are rejected
+        assert!(VirtioPciCap::parse([0x0001_0011, 0, 0, 0]).is_none());
+        assert!(VirtioPciCap::parse([0x0110_0009, 6, 0, 0x38]).is_none());
+    }
+
+    #[test]
+    fn test_non_transitional_detection() {
+        assert!(!is_non_transitional(VIRTIO_NET_DEVICE_ID, 0));
+        assert!(is_non_transitional(VIRTIO_NET_DEVICE_ID, 1));
+        assert!(is_non_transitional(VIRTIO_NET_MODERN_DEVICE_ID, 1));
+    }
+
+    #[test]
+    fn test_virtio_net_hdr() {
+        assert_eq!(VIRTIO_NET_HDR_SIZE, 12);
+        assert_eq!(virtio_net_hdr_len(0), 10);
+        assert_eq!(virtio_net_hdr_len(VIRTIO_NET_F_MRG_RXBUF), 12);
+        assert_eq!(
+            virtio_net_hdr_len(VIRTIO_F_VERSION_1 | VIRTIO_NET_F_MAC),
+            12
+        );
+
+        let mut buffer = [0u8; 12 + 60];
+        buffer[12] = 0xAA;
+        let frame = strip_virtio_net_hdr(&buffer, 12).unwrap();
+        assert_eq!(frame.len(), 60);
+        assert_eq!(frame[0], 0xAA);
+        assert_eq!(strip_virtio_net_hdr(&buffer[..10], 10), None);
+        assert_eq!(strip_virtio_net_hdr(&buffer[..8], 10), None);
+    }
+
+    #[test]
+    fn test_descriptor_chain_links_buffers() {
+        let (_, total) = virtqueue_layout(VIRTQUEUE_SIZE);
+        let layout = core::alloc::Layout::from_size_align(total, 4096).unwrap();
+        unsafe {
+            let memory = alloc::alloc::alloc(layout);
+            let mut queue = Virtqueue::new(VIRTQUEUE_SIZE, memory, memory as u64).unwrap();
+
+            // Start one short of the end so the chain wraps
+            queue.next_free = VIRTQUEUE_SIZE - 1;
+            let head = queue.add_chain(&rx_chain(0x1000, 1538, 12)).unwrap();
+            assert_eq!(head, VIRTQUEUE_SIZE - 1);
+
+            let first = &*queue.desc.add(head as usize);
+            let (flags, next, len) = (first.flags, first.next, first.len);
+            assert_eq!(flags, VIRTQ_DESC_F_WRITE | VIRTQ_DESC_F_NEXT);
+            assert_eq!(next, 0);
+            assert_eq!(len, 12);
+
+            let second = &*queue.desc;
+            let (flags, addr, len) = (second.flags, second.addr, second.len);
+            assert_eq!(flags, VIRTQ_DESC_F_WRITE);
+            assert_eq!(addr, 0x100c);
+            assert_eq!(len, 1526);
+
+            queue.publish(head);
+            let avail = &*queue.avail;
+            let (idx, entry) = (avail.idx, avail.ring[0]);
+            assert_eq!(idx, 1);
+            assert_eq!(entry, head);
+
+            alloc::alloc::dealloc(memory, layout);
+        }
+    }
+
+    #[test]
+    fn test_virtqueue_layout_aligns_used_ring() {
+        let (used_offset, total) = virtqueue_layout(VIRTQUEUE_SIZE);
+        assert_eq!(used_offset % VIRTQ_USED_ALIGN, 0);
+        assert!(used_offset >= core::mem::size_of::<VirtqDesc>() * VIRTQUEUE_SIZE as usize);
+        assert_eq!(total, used_offset + core::mem::size_of::<VirtqUsed>());
+    }
+}
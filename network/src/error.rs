@@ -2,6 +2,7 @@
 
 extern crate alloc;
 
+use alloc::format;
 use alloc::string::String;
 
 /// Network-related errors
@@ -33,9 +34,13 @@ pub enum NetError {
 
     DhcpNotConfigured,
 
+    /// Every link-local address tried was already in use on the link
+    LinkLocalConflict { attempts: u32 },
+
     DnsError(String),
 
-    DnsTimeout,
+    /// No answer after `attempts` queries spread over `servers` resolvers
+    DnsTimeout { attempts: u32, servers: u32 },
 
     DnsMalformedResponse(String),
 
@@ -43,6 +48,12 @@ pub enum NetError {
 
     DnsServerFailure,
 
+    NtpError(String),
+
+    NtpTimeout,
+
+    PingError(String),
+
     TlsError(String),
 
     TlsHandshakeFailed(String),
@@ -57,6 +68,8 @@ pub enum NetError {
 
     TlsProtocolError(String),
 
+    CertificateExpired,
+
     TcpConnectionFailed(String),
 
     TcpSocketNotFound,
@@ -64,6 +77,16 @@ pub enum NetError {
     TcpSendBufferFull,
 
     TcpReceiveError,
+
+    TcpTimeout,
+
+    UdpError(String),
+
+    UdpPortInUse(u16),
+
+    UdpSendBufferFull,
+
+    UdpSocketNotFound,
 }
 
 impl core::fmt::Display for NetError {
@@ -82,11 +105,21 @@ impl core::fmt::Display for NetError {
             NetError::DhcpTimeout(s) => write!(f, "DHCP timeout: {s}"),
             NetError::DhcpConfigFailed(s) => write!(f, "DHCP configuration failed: {s}"),
             NetError::DhcpNotConfigured => write!(f, "DHCP not configured"),
+            NetError::LinkLocalConflict { attempts } => write!(
+                f,
+                "No free link-local address after {attempts} attempts"
+            ),
             NetError::DnsError(s) => write!(f, "DNS error: {s}"),
-            NetError::DnsTimeout => write!(f, "DNS timeout"),
+            NetError::DnsTimeout { attempts, servers } => write!(
+                f,
+                "DNS timeout after {attempts} attempts to {servers} server(s)"
+            ),
             NetError::DnsMalformedResponse(s) => write!(f, "DNS malformed response: {s}"),
             NetError::DnsNameNotFound => write!(f, "DNS name not found"),
             NetError::DnsServerFailure => write!(f, "DNS server failure"),
+            NetError::NtpError(s) => write!(f, "NTP error: {s}"),
+            NetError::NtpTimeout => write!(f, "NTP timeout"),
+            NetError::PingError(s) => write!(f, "Ping error: {s}"),
             NetError::TlsError(s) => write!(f, "TLS error: {s}"),
             NetError::TlsHandshakeFailed(s) => write!(f, "TLS handshake failed: {s}"),
             NetError::TlsCertificateError(s) => {
@@ -96,10 +129,209 @@ impl core::fmt::Display for NetError {
             NetError::TlsUnsupportedCipherSuite => write!(f, "TLS unsupported cipher suite"),
             NetError::TlsConnectionClosed => write!(f, "TLS connection closed"),
             NetError::TlsProtocolError(s) => write!(f, "TLS protocol error: {s}"),
+            NetError::CertificateExpired => write!(
+                f,
+                "TLS certificate expired or not yet valid (check the system clock)"
+            ),
             NetError::TcpConnectionFailed(s) => write!(f, "TCP connection failed: {s}"),
             NetError::TcpSocketNotFound => write!(f, "TCP socket not found"),
             NetError::TcpSendBufferFull => write!(f, "TCP send buffer full"),
             NetError::TcpReceiveError => write!(f, "TCP receive error"),
+            NetError::TcpTimeout => write!(f, "TCP timeout"),
+            NetError::UdpError(s) => write!(f, "UDP error: {s}"),
+            NetError::UdpPortInUse(port) => write!(f, "UDP port {port} already in use"),
+            NetError::UdpSendBufferFull => write!(f, "UDP send buffer full"),
+            NetError::UdpSocketNotFound => write!(f, "UDP socket not found"),
+        }
+    }
+}
+
+impl NetError {
+    /// Whether the same operation may succeed if tried again
+    ///
+    /// Timeouts, unresponsive servers and dropped or refused connections are
+    /// retriable; certificate, configuration and "not supported" errors
+    /// will fail the same way every time.
+    pub fn is_retriable(&self) -> bool {
+        matches!(
+            self,
+            NetError::QueueError(_)
+                | NetError::DhcpTimeout(_)
+                | NetError::DnsTimeout { .. }
+                | NetError::DnsServerFailure
+                | NetError::NtpTimeout
+                | NetError::TlsConnectionClosed
+                | NetError::TcpConnectionFailed(_)
+                | NetError::TcpSendBufferFull
+                | NetError::TcpReceiveError
+                | NetError::TcpTimeout
+                | NetError::UdpSendBufferFull
+        )
+    }
+
+    /// Stable number for this kind of error, shown as `NET-042`
+    ///
+    /// Codes are grouped by area (see [`error_hint`]) and never reused.
+    pub fn error_code(&self) -> u16 {
+        match self {
+            NetError::DriverError(_) => 1,
+            NetError::PciError(_) => 2,
+            NetError::VirtioError(_) => 3,
+            NetError::QueueError(_) => 4,
+            NetError::InvalidPacket(_) => 5,
+            NetError::DeviceNotFound => 6,
+            NetError::DeviceNotInitialized => 7,
+            NetError::BufferTooSmall => 8,
+            NetError::NotSupported => 9,
+            NetError::SmoltcpError(_) => 10,
+            NetError::DhcpTimeout(_) => 20,
+            NetError::DhcpConfigFailed(_) => 21,
+            NetError::DhcpNotConfigured => 22,
+            NetError::LinkLocalConflict { .. } => 23,
+            NetError::DnsError(_) => 30,
+            NetError::DnsTimeout { .. } => 31,
+            NetError::DnsMalformedResponse(_) => 32,
+            NetError::DnsNameNotFound => 33,
+            NetError::DnsServerFailure => 34,
+            NetError::NtpError(_) => 40,
+            NetError::NtpTimeout => 41,
+            NetError::PingError(_) => 45,
+            NetError::TlsError(_) => 50,
+            NetError::TlsHandshakeFailed(_) => 51,
+            NetError::TlsCertificateError(_) => 52,
+            NetError::TlsInvalidServerName(_) => 53,
+            NetError::TlsUnsupportedCipherSuite => 54,
+            NetError::TlsConnectionClosed => 55,
+            NetError::TlsProtocolError(_) => 56,
+            NetError::CertificateExpired => 57,
+            NetError::TcpConnectionFailed(_) => 60,
+            NetError::TcpSocketNotFound => 61,
+            NetError::TcpSendBufferFull => 62,
+            NetError::TcpReceiveError => 63,
+            NetError::TcpTimeout => 64,
+            NetError::UdpError(_) => 70,
+            NetError::UdpPortInUse(_) => 71,
+            NetError::UdpSendBufferFull => 72,
+            NetError::UdpSocketNotFound => 73,
+        }
+    }
+}
+
+/// Format an error code the way it is shown to users, e.g. `NET-042`
+pub fn error_code_label(code: u16) -> String {
+    format!("NET-{code:03}")
+}
+
+/// One-line advice for an error code, by area
+///
+/// 1-19 device, 20-29 DHCP, 30-39 DNS, 40-49 NTP and ping, 50-59 TLS,
+/// 60-69 TCP, 70-79 UDP, 80-89 HTTP.
+pub fn error_hint(code: u16) -> &'static str {
+    match code {
+        1..=19 => "check that the network card is present and supported",
+        20..=29 => "check the cable or Wi-Fi and the DHCP server, or set a static address",
+        30..=39 => "check the DNS servers in the network settings",
+        40..=49 => "the time server or host did not answer",
+        52 | 57 => "check the system clock and the server's certificate",
+        50..=59 => "the secure connection failed; the server may not support it",
+        60..=69 => "the server may be down or unreachable; try again",
+        70..=79 => "too many sockets or datagrams in flight; try again",
+        80..=89 => "check the provider URL and proxy settings",
+        _ => "try again; if it persists, check the network settings",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    fn every_variant() -> Vec<NetError> {
+        let s = || "detail".to_string();
+        vec![
+            NetError::DriverError(s()),
+            NetError::PciError(s()),
+            NetError::VirtioError(s()),
+            NetError::QueueError(s()),
+            NetError::InvalidPacket(s()),
+            NetError::DeviceNotFound,
+            NetError::DeviceNotInitialized,
+            NetError::BufferTooSmall,
+            NetError::NotSupported,
+            NetError::SmoltcpError(s()),
+            NetError::DhcpTimeout(s()),
+            NetError::DhcpConfigFailed(s()),
+            NetError::DhcpNotConfigured,
+            NetError::LinkLocalConflict { attempts: 10 },
+            NetError::DnsError(s()),
+            NetError::DnsTimeout {
+                attempts: 3,
+                servers: 2,
+            },
+            NetError::DnsMalformedResponse(s()),
+            NetError::DnsNameNotFound,
+            NetError::DnsServerFailure,
+            NetError::NtpError(s()),
+            NetError::NtpTimeout,
+            NetError::PingError(s()),
+            NetError::TlsError(s()),
+            NetError::TlsHandshakeFailed(s()),
+            NetError::TlsCertificateError(s()),
+            NetError::TlsInvalidServerName(s()),
+            NetError::TlsUnsupportedCipherSuite,
+            NetError::TlsConnectionClosed,
+            NetError::TlsProtocolError(s()),
+            NetError::CertificateExpired,
+            NetError::TcpConnectionFailed(s()),
+            NetError::TcpSocketNotFound,
+            NetError::TcpSendBufferFull,
+            NetError::TcpReceiveError,
+            NetError::TcpTimeout,
+            NetError::UdpError(s()),
+            NetError::UdpPortInUse(5353),
+            NetError::UdpSendBufferFull,
+            NetError::UdpSocketNotFound,
+        ]
+    }
+
+    #[test]
+    fn test_codes_are_unique_and_messages_readable() {
+        let errors = every_variant();
+        let mut codes: Vec<u16> = errors.iter().map(NetError::error_code).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert!(codes.iter().all(|&code| code > 0 && code < 80));
+
+        for error in &errors {
+            let message = error.to_string();
+            assert!(!message.is_empty() && message.is_ascii(), "{message}");
+            assert!(!message.contains("0x"), "{message}");
+        }
+        assert_eq!(
+            error_code_label(NetError::DnsServerFailure.error_code()),
+            "NET-034"
+        );
+    }
+
+    #[test]
+    fn test_retriable_classification() {
+        assert!(NetError::DnsServerFailure.is_retriable());
+        assert!(NetError::DnsTimeout {
+            attempts: 1,
+            servers: 1
         }
+        .is_retriable());
+        assert!(NetError::TcpConnectionFailed("reset".into()).is_retriable());
+        assert!(!NetError::TlsCertificateError("bad chain".into()).is_retriable());
+        assert!(!NetError::CertificateExpired.is_retriable());
+        assert!(!NetError::DhcpConfigFailed("no address".into()).is_retriable());
+        assert!(!NetError::DnsNameNotFound.is_retriable());
+        assert_eq!(
+            error_hint(NetError::CertificateExpired.error_code()),
+            error_hint(NetError::TlsCertificateError("x".into()).error_code())
+        );
     }
 }
@@ -0,0 +1,453 @@
+// DEFLATE (RFC 1951) and gzip (RFC 1952) decompression
+//
+// A small table-free inflater in the style of zlib's `puff`: Huffman codes
+// are decoded one bit at a time from canonical code counts. It is slower
+// than a table-driven decoder but tiny, which suits API responses of a few
+// hundred kilobytes.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InflateError {
+    /// Input ended before the stream did
+    Truncated,
+    /// Decompressed data would exceed the caller's limit
+    TooLarge,
+    /// Malformed stream
+    Invalid(&'static str),
+}
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InflateError::Truncated => write!(f, "truncated stream"),
+            InflateError::TooLarge => write!(f, "decompressed size over limit"),
+            InflateError::Invalid(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// Longest Huffman code in DEFLATE
+const MAX_BITS: usize = 15;
+
+/// Base lengths and extra bits for length symbols 257..=285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Base distances and extra bits for distance symbols 0..=29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order in which code length code lengths are sent
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// gzip header flags
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+/// Reads bits least-significant first, as DEFLATE packs them
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            bit_buf: 0,
+            bit_count: 0,
+        }
+    }
+
+    /// Read `n` (at most 16) bits
+    fn bits(&mut self, n: u32) -> Result<u32, InflateError> {
+        while self.bit_count < n {
+            let byte = *self.data.get(self.pos).ok_or(InflateError::Truncated)?;
+            self.pos += 1;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1u32 << n) - 1);
+        self.bit_buf >>= n;
+        self.bit_count -= n;
+        Ok(value)
+    }
+
+    /// Drop the rest of the current byte
+    fn align_to_byte(&mut self) {
+        // Bytes are only loaded on demand, so fewer than 8 bits are buffered
+        self.bit_buf = 0;
+        self.bit_count = 0;
+    }
+}
+
+/// Canonical Huffman code: number of codes per length and the symbols
+/// ordered by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+
+        // Incomplete codes are allowed (a lone distance code is common);
+        // over-subscribed ones cannot be decoded
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(InflateError::Invalid("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader<'_>) -> Result<u16, InflateError> {
+        // `code` is the bits read so far; `first` the first code of that
+        // length; `index` the position of `first` in `symbols`
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::Invalid("invalid Huffman code"))
+    }
+}
+
+/// Decompress a raw DEFLATE stream, failing once the output would exceed
+/// `max_len` bytes
+pub fn inflate(data: &[u8], max_len: usize) -> Result<Vec<u8>, InflateError> {
+    let mut reader = BitReader::new(data);
+    inflate_blocks(&mut reader, max_len)
+}
+
+/// Decompress a gzip member, checking its CRC-32 and length trailer
+pub fn gunzip(data: &[u8], max_len: usize) -> Result<Vec<u8>, InflateError> {
+    if data.len() < 10 {
+        return Err(InflateError::Truncated);
+    }
+    if data[0..2] != [0x1f, 0x8b] {
+        return Err(InflateError::Invalid("not a gzip stream"));
+    }
+    if data[2] != 8 {
+        return Err(InflateError::Invalid("unsupported gzip compression method"));
+    }
+    let flags = data[3];
+    if flags & 0xE0 != 0 {
+        return Err(InflateError::Invalid("reserved gzip flags set"));
+    }
+
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let xlen = data.get(pos..pos + 2).ok_or(InflateError::Truncated)?;
+        pos += 2 + u16::from_le_bytes([xlen[0], xlen[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let rest = data.get(pos..).ok_or(InflateError::Truncated)?;
+            let end = rest
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or(InflateError::Truncated)?;
+            pos += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    let compressed = data.get(pos..).ok_or(InflateError::Truncated)?;
+    let mut reader = BitReader::new(compressed);
+    let out = inflate_blocks(&mut reader, max_len)?;
+    reader.align_to_byte();
+
+    let trailer = compressed
+        .get(reader.pos..reader.pos + 8)
+        .ok_or(InflateError::Truncated)?;
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if crc32(&out) != crc {
+        return Err(InflateError::Invalid("gzip CRC mismatch"));
+    }
+    if out.len() as u32 != size {
+        return Err(InflateError::Invalid("gzip length mismatch"));
+    }
+    Ok(out)
+}
+
+fn inflate_blocks(reader: &mut BitReader<'_>, max_len: usize) -> Result<Vec<u8>, InflateError> {
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(reader, &mut out, max_len)?,
+            1 => {
+                let (lit, dist) = fixed_codes()?;
+                compressed_block(reader, &mut out, max_len, &lit, &dist)?;
+            }
+            2 => {
+                let (lit, dist) = dynamic_codes(reader)?;
+                compressed_block(reader, &mut out, max_len, &lit, &dist)?;
+            }
+            _ => return Err(InflateError::Invalid("reserved block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn stored_block(
+    reader: &mut BitReader<'_>,
+    out: &mut Vec<u8>,
+    max_len: usize,
+) -> Result<(), InflateError> {
+    reader.align_to_byte();
+    let len = reader.bits(16)? as usize;
+    let nlen = reader.bits(16)? as usize;
+    if len != !nlen & 0xFFFF {
+        return Err(InflateError::Invalid("stored block length mismatch"));
+    }
+    let data = reader
+        .data
+        .get(reader.pos..reader.pos + len)
+        .ok_or(InflateError::Truncated)?;
+    if out.len() + len > max_len {
+        return Err(InflateError::TooLarge);
+    }
+    out.extend_from_slice(data);
+    reader.pos += len;
+    Ok(())
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), InflateError> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader<'_>) -> Result<(Huffman, Huffman), InflateError> {
+    let nlen = reader.bits(5)? as usize + 257;
+    let ndist = reader.bits(5)? as usize + 1;
+    let ncode = reader.bits(4)? as usize + 4;
+    if nlen > 286 || ndist > 30 {
+        return Err(InflateError::Invalid("too many length or distance codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..ncode] {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = [0u8; 286 + 30];
+    let mut index = 0;
+    while index < nlen + ndist {
+        let symbol = code_length_code.decode(reader)?;
+        if symbol < 16 {
+            lengths[index] = symbol as u8;
+            index += 1;
+            continue;
+        }
+        let (value, repeat) = match symbol {
+            16 => {
+                if index == 0 {
+                    return Err(InflateError::Invalid("repeat with no previous length"));
+                }
+                (lengths[index - 1], 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > nlen + ndist {
+            return Err(InflateError::Invalid("code lengths overrun"));
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+
+    if lengths[256] == 0 {
+        return Err(InflateError::Invalid("missing end-of-block code"));
+    }
+    Ok((
+        Huffman::new(&lengths[..nlen])?,
+        Huffman::new(&lengths[nlen..nlen + ndist])?,
+    ))
+}
+
+fn compressed_block(
+    reader: &mut BitReader<'_>,
+    out: &mut Vec<u8>,
+    max_len: usize,
+    lit: &Huffman,
+    dist: &Huffman,
+) -> Result<(), InflateError> {
+    loop {
+        let symbol = lit.decode(reader)? as usize;
+        if symbol < 256 {
+            if out.len() >= max_len {
+                return Err(InflateError::TooLarge);
+            }
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(InflateError::Invalid("invalid length symbol"));
+        }
+        let len = LENGTH_BASE[symbol] as usize + reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+        let symbol = dist.decode(reader)? as usize;
+        if symbol >= DIST_BASE.len() {
+            return Err(InflateError::Invalid("invalid distance symbol"));
+        }
+        let distance =
+            DIST_BASE[symbol] as usize + reader.bits(DIST_EXTRA[symbol] as u32)? as usize;
+        if distance > out.len() {
+            return Err(InflateError::Invalid("distance too far back"));
+        }
+        if out.len() + len > max_len {
+            return Err(InflateError::TooLarge);
+        }
+        // Byte by byte: the copy may overlap its own output
+        let start = out.len() - distance;
+        for i in 0..len {
+            let byte = out[start + i];
+            out.push(byte);
+        }
+    }
+}
+
+/// CRC-32 (IEEE 802.3), as used by the gzip trailer
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// gzip of "hello, hello, hello moteOS" (fixed Huffman block)
+    const HELLO_GZ: [u8; 36] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0xd7, 0x51, 0xc8, 0x40, 0xa2, 0x14, 0x72, 0xf3, 0x4b, 0x52, 0xfd, 0x83, 0x01, 0x10, 0x0c,
+        0x76, 0xc7, 0x1a, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_block_types() {
+        assert_eq!(
+            gunzip(&HELLO_GZ, 1024).unwrap(),
+            b"hello, hello, hello moteOS"
+        );
+
+        let stored = [
+            0x01, 0x07, 0x00, 0xf8, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x21,
+        ];
+        assert_eq!(inflate(&stored, 1024).unwrap(), b"stored!");
+
+        let dynamic = [
+            0x0d, 0x8a, 0xb1, 0x09, 0x00, 0x00, 0x0c, 0x83, 0x6e, 0x15, 0x87, 0x3e, 0xe0, 0xff,
+            0x34, 0x2e, 0x82, 0x28, 0x47, 0x43, 0x9a, 0xe9, 0x5c, 0x18, 0x19, 0x2e, 0x9f, 0xb6,
+            0x83, 0x07,
+        ];
+        assert_eq!(
+            inflate(&dynamic, 1024).unwrap(),
+            b"cagattttcatattatgcagaaaatctacttcgcctgata"
+        );
+
+        // Optional file name in the header
+        let mut named = HELLO_GZ[..10].to_vec();
+        named[3] = FNAME;
+        named.extend_from_slice(b"hello.txt\0");
+        named.extend_from_slice(&HELLO_GZ[10..]);
+        assert_eq!(gunzip(&named, 1024).unwrap(), b"hello, hello, hello moteOS");
+
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_malformed_streams_are_errors() {
+        assert_eq!(gunzip(&HELLO_GZ, 10), Err(InflateError::TooLarge));
+        assert!(gunzip(b"plain text, not gzip", 1024).is_err());
+        assert!(inflate(&[0x07], 1024).is_err()); // reserved block type
+
+        let mut bad_crc = HELLO_GZ;
+        bad_crc[28] ^= 1;
+        assert_eq!(
+            gunzip(&bad_crc, 1024),
+            Err(InflateError::Invalid("gzip CRC mismatch"))
+        );
+
+        // Every truncation and single-byte corruption fails cleanly
+        for len in 0..HELLO_GZ.len() {
+            assert!(gunzip(&HELLO_GZ[..len], 1024).is_err());
+        }
+        for i in 0..HELLO_GZ.len() {
+            for bit in 0..8 {
+                let mut corrupt = HELLO_GZ;
+                corrupt[i] ^= 1 << bit;
+                let _ = gunzip(&corrupt, 1024);
+            }
+        }
+    }
+}
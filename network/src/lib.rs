@@ -6,22 +6,45 @@
 #[macro_use]
 extern crate alloc;
 
+pub mod base64;
 pub mod dhcp;
 pub mod dns;
+pub mod dma;
 pub mod drivers;
 pub mod error;
 pub mod http;
+#[cfg(feature = "gzip")]
+pub mod inflate;
+pub mod linklocal;
+pub mod ntp;
 pub mod pci;
+pub mod ping;
 pub mod stack;
+pub mod stats;
+pub mod tcp;
 #[cfg(feature = "tls")]
 pub mod tls;
 
 // Re-export commonly used types
-pub use dhcp::{DhcpState, IpConfig};
-pub use dns::{build_query, DnsResponse};
+pub use dhcp::{DhcpLease, DhcpState, IpConfig};
+pub use dns::{build_query, DnsCacheStats, DnsResponse};
+pub use dma::{set_dma_allocator, DmaAllocFn, DmaFreeFn};
 pub use drivers::NetworkDriver;
-pub use error::NetError;
-pub use http::{parse_url, HttpClient, HttpError, HttpResponse, ParsedUrl, Scheme};
-pub use stack::{get_network_stack, init_network_stack, poll_network_stack, NetworkStack};
+pub use error::{error_code_label, error_hint, NetError};
+pub use ping::PingStats;
+pub use stats::NetStats;
+pub use tcp::TcpStream;
+pub use http::{
+    basic, bearer, parse_url, url_encode_component, Connectivity, HttpClient, HttpConnectionPool,
+    HttpError, HttpProxy, HttpResponse, HttpVersion, ParsedUrl, QueryBuilder, RequestBuilder,
+    RequestOptions, Scheme, CONNECTIVITY_CHECK_URL,
+};
+pub use stack::{
+    get_network_stack, init_network_stack, network_poll_delay_ms, poll_network_stack, NetworkStack,
+    NetworkStatus, UdpBufferSizes, UdpEndpointHandle, PUBLIC_DNS_SERVERS,
+};
 #[cfg(feature = "tls")]
-pub use tls::{set_tls_log_callback, TlsConnection, TlsLogCallback};
+pub use tls::{
+    pin_tls_certificate, set_tls_cipher_suite, set_tls_extra_roots, set_tls_log_callback,
+    set_tls_time_source, TlsCipherSuite, TlsConnection, TlsLogCallback, TlsTimeSource,
+};
@@ -0,0 +1,152 @@
+//! IPv4 link-local addressing (RFC 3927)
+//!
+//! When no DHCP server answers, the stack claims an address in
+//! 169.254.0.0/16 so hosts on the same link (e.g. an Ollama server found over
+//! mDNS) stay reachable. Candidates are derived from the MAC address, so a
+//! machine tends to get the same address on every boot, and each one is
+//! ARP-probed before use.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use smoltcp::wire::{
+    ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol,
+    EthernetRepr, Ipv4Address,
+};
+
+/// Prefix length of the link-local network
+pub const LINK_LOCAL_PREFIX_LEN: u8 = 16;
+
+/// ARP probes sent for each candidate before claiming it
+pub const PROBE_NUM: u32 = 3;
+
+/// Time between probes, and after the last one before claiming
+pub const PROBE_INTERVAL_MS: i64 = 1000;
+
+/// Announcements sent once an address is claimed
+pub const ANNOUNCE_NUM: u32 = 2;
+
+/// Candidates tried before giving up
+pub const MAX_CONFLICTS: u32 = 10;
+
+/// Whether `ip` is in 169.254.0.0/16
+pub fn is_link_local(ip: Ipv4Address) -> bool {
+    ip.0[0] == 169 && ip.0[1] == 254
+}
+
+/// Candidate address number `attempt` for the interface with `mac`
+///
+/// Spread over 169.254.1.0 to 169.254.254.255; the first and last /24 are
+/// reserved. The same MAC and attempt always give the same address.
+pub fn candidate_address(mac: &[u8; 6], attempt: u32) -> Ipv4Address {
+    // FNV-1a over the MAC and attempt number
+    let mut hash: u32 = 0x811C_9DC5;
+    for byte in mac.iter().chain(attempt.to_be_bytes().iter()) {
+        hash ^= u32::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    let host = hash % (254 * 256);
+    Ipv4Address::new(169, 254, (host / 256) as u8 + 1, (host % 256) as u8)
+}
+
+/// Broadcast ARP request from `mac` for `target`
+fn arp_frame(mac: &[u8; 6], sender: Ipv4Address, target: Ipv4Address) -> Vec<u8> {
+    let source = EthernetAddress::from_bytes(mac);
+    let ethernet = EthernetRepr {
+        src_addr: source,
+        dst_addr: EthernetAddress::BROADCAST,
+        ethertype: EthernetProtocol::Arp,
+    };
+    let arp = ArpRepr::EthernetIpv4 {
+        operation: ArpOperation::Request,
+        source_hardware_addr: source,
+        source_protocol_addr: sender,
+        target_hardware_addr: EthernetAddress([0; 6]),
+        target_protocol_addr: target,
+    };
+
+    let mut buffer = vec![0u8; ethernet.buffer_len() + arp.buffer_len()];
+    let mut frame = EthernetFrame::new_unchecked(&mut buffer[..]);
+    ethernet.emit(&mut frame);
+    arp.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()));
+    buffer
+}
+
+/// ARP probe for `candidate`: "who has it?", sent from 0.0.0.0
+pub fn build_probe(mac: &[u8; 6], candidate: Ipv4Address) -> Vec<u8> {
+    arp_frame(mac, Ipv4Address::UNSPECIFIED, candidate)
+}
+
+/// ARP announcement claiming `address` for `mac`
+pub fn build_announcement(mac: &[u8; 6], address: Ipv4Address) -> Vec<u8> {
+    arp_frame(mac, address, address)
+}
+
+/// Whether `frame` shows another host using or probing for `candidate`
+///
+/// Our own frames (looped back by some links) never count.
+pub fn is_conflict(frame: &[u8], mac: &[u8; 6], candidate: Ipv4Address) -> bool {
+    let Ok(ethernet) = EthernetFrame::new_checked(frame) else {
+        return false;
+    };
+    if ethernet.ethertype() != EthernetProtocol::Arp {
+        return false;
+    }
+    let Ok(packet) = ArpPacket::new_checked(ethernet.payload()) else {
+        return false;
+    };
+    let Ok(ArpRepr::EthernetIpv4 {
+        operation,
+        source_hardware_addr,
+        source_protocol_addr,
+        target_protocol_addr,
+        ..
+    }) = ArpRepr::parse(&packet)
+    else {
+        return false;
+    };
+    if source_hardware_addr == EthernetAddress::from_bytes(mac) {
+        return false;
+    }
+    // Someone already has it, or is probing for it at the same time
+    source_protocol_addr == candidate
+        || (operation == ArpOperation::Request
+            && source_protocol_addr.is_unspecified()
+            && target_protocol_addr == candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+    const OTHER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+
+    #[test]
+    fn test_candidates_stay_in_the_usable_range() {
+        for attempt in 0..1000 {
+            let ip = candidate_address(&MAC, attempt);
+            assert!(is_link_local(ip));
+            assert!((1..=254).contains(&ip.0[2]), "{ip}");
+        }
+        assert_eq!(candidate_address(&MAC, 0), candidate_address(&MAC, 0));
+        assert_ne!(candidate_address(&MAC, 0), candidate_address(&MAC, 1));
+        assert_ne!(candidate_address(&MAC, 0), candidate_address(&OTHER_MAC, 0));
+    }
+
+    #[test]
+    fn test_conflicts_come_from_other_hosts_only() {
+        let candidate = candidate_address(&MAC, 0);
+        let elsewhere = Ipv4Address::new(169, 254, 7, 7);
+        let conflicts = |frame: Vec<u8>| is_conflict(&frame, &MAC, candidate);
+
+        // Another host probing for, or announcing, the same address
+        assert!(conflicts(build_probe(&OTHER_MAC, candidate)));
+        assert!(conflicts(build_announcement(&OTHER_MAC, candidate)));
+        // Our own probe, and traffic about other addresses
+        assert!(!conflicts(build_probe(&MAC, candidate)));
+        assert!(!conflicts(build_probe(&OTHER_MAC, elsewhere)));
+        assert!(!conflicts(build_announcement(&OTHER_MAC, elsewhere)));
+        assert!(!conflicts(vec![0u8; 10]));
+    }
+}
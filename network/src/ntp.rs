@@ -0,0 +1,155 @@
+//! SNTP client for moteOS
+//!
+//! A minimal Simple Network Time Protocol client (RFC 4330): one 48-byte
+//! request over UDP, one reply, and the server's transmit timestamp taken
+//! as the current time. Accuracy is limited to the network round trip,
+//! which is plenty for certificate validity checks and chat timestamps.
+//!
+//! The socket handling lives in `NetworkStack::ntp_sync`; this module only
+//! builds and parses packets.
+
+extern crate alloc;
+
+use crate::error::NetError;
+use alloc::format;
+
+/// Server used when none is configured
+pub const DEFAULT_NTP_SERVER: &str = "pool.ntp.org";
+
+/// UDP port NTP servers listen on
+pub const NTP_PORT: u16 = 123;
+
+/// Size of an NTP packet without extension fields
+pub const NTP_PACKET_LEN: usize = 48;
+
+/// Seconds from the NTP epoch (1900-01-01) to the Unix epoch (1970-01-01)
+pub const NTP_UNIX_EPOCH_OFFSET: u64 = 2_208_988_800;
+
+/// LI = 0 (no warning), VN = 4, Mode = 3 (client)
+const CLIENT_FLAGS: u8 = 0x23;
+
+/// Mode field value of a server reply
+const MODE_SERVER: u8 = 4;
+
+/// Build a client request
+///
+/// `nonce` is sent as the transmit timestamp; the server echoes it back as
+/// the originate timestamp, which ties the reply to this request.
+pub fn build_request(nonce: u64) -> [u8; NTP_PACKET_LEN] {
+    let mut packet = [0u8; NTP_PACKET_LEN];
+    packet[0] = CLIENT_FLAGS;
+    packet[40..48].copy_from_slice(&nonce.to_be_bytes());
+    packet
+}
+
+/// Parse a server reply to the request sent with `nonce`
+///
+/// # Returns
+/// * `Ok(u64)` - The server's transmit time in milliseconds since the Unix epoch
+/// * `Err(NetError)` - The reply is malformed, unrelated, or a kiss-o'-death
+pub fn parse_response(packet: &[u8], nonce: u64) -> Result<u64, NetError> {
+    if packet.len() < NTP_PACKET_LEN {
+        return Err(NetError::NtpError(format!(
+            "reply too short ({} bytes)",
+            packet.len()
+        )));
+    }
+    let mode = packet[0] & 0x07;
+    if mode != MODE_SERVER {
+        return Err(NetError::NtpError(format!("unexpected mode {}", mode)));
+    }
+    // Stratum 0 is a kiss-o'-death: the server refuses to serve us
+    if packet[1] == 0 {
+        let code = core::str::from_utf8(&packet[12..16]).unwrap_or("????");
+        return Err(NetError::NtpError(format!(
+            "server sent kiss code {}",
+            code
+        )));
+    }
+    let originate = u64::from_be_bytes(packet[24..32].try_into().unwrap());
+    if originate != nonce {
+        return Err(NetError::NtpError("reply does not match request".into()));
+    }
+
+    let seconds = u32::from_be_bytes(packet[40..44].try_into().unwrap()) as u64;
+    let fraction = u32::from_be_bytes(packet[44..48].try_into().unwrap()) as u64;
+    if seconds == 0 && fraction == 0 {
+        return Err(NetError::NtpError("server did not send a time".into()));
+    }
+    Ok(ntp_to_unix_ms(seconds, fraction))
+}
+
+/// Convert an NTP timestamp to milliseconds since the Unix epoch
+///
+/// Seconds below the Unix epoch offset are taken to be in NTP era 1, which
+/// starts in February 2036.
+fn ntp_to_unix_ms(seconds: u64, fraction: u64) -> u64 {
+    let seconds = if seconds >= NTP_UNIX_EPOCH_OFFSET {
+        seconds
+    } else {
+        seconds + (1 << 32)
+    };
+    (seconds - NTP_UNIX_EPOCH_OFFSET) * 1000 + ((fraction * 1000) >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server_reply(nonce: u64, seconds: u32, fraction: u32) -> [u8; NTP_PACKET_LEN] {
+        let mut packet = [0u8; NTP_PACKET_LEN];
+        packet[0] = 0x24; // VN 4, mode 4
+        packet[1] = 2; // stratum
+        packet[24..32].copy_from_slice(&nonce.to_be_bytes());
+        packet[40..44].copy_from_slice(&seconds.to_be_bytes());
+        packet[44..48].copy_from_slice(&fraction.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_build_request() {
+        let packet = build_request(0x0102_0304_0506_0708);
+        assert_eq!(packet[0], 0x23);
+        assert_eq!(packet[40..48], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(packet[1..40].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        // 2026-10-01 00:00:00.5 UTC
+        let seconds = (1_790_812_800 + NTP_UNIX_EPOCH_OFFSET) as u32;
+        let reply = server_reply(42, seconds, 0x8000_0000);
+        assert_eq!(parse_response(&reply, 42).unwrap(), 1_790_812_800_500);
+    }
+
+    #[test]
+    fn test_parse_response_after_2036() {
+        // 2040-01-01 00:00:00 UTC wraps into NTP era 1
+        let seconds = (2_208_988_800u64 + NTP_UNIX_EPOCH_OFFSET - (1 << 32)) as u32;
+        let reply = server_reply(7, seconds, 0);
+        assert_eq!(parse_response(&reply, 7).unwrap(), 2_208_988_800_000);
+    }
+
+    #[test]
+    fn test_parse_response_rejects_bad_replies() {
+        let seconds = (1_790_812_800 + NTP_UNIX_EPOCH_OFFSET) as u32;
+        let good = server_reply(42, seconds, 0);
+
+        assert!(parse_response(&good[..40], 42).is_err());
+        assert!(parse_response(&good, 43).is_err());
+
+        let mut client_mode = good;
+        client_mode[0] = 0x23;
+        assert!(parse_response(&client_mode, 42).is_err());
+
+        let mut kiss = good;
+        kiss[1] = 0;
+        kiss[12..16].copy_from_slice(b"RATE");
+        match parse_response(&kiss, 42) {
+            Err(NetError::NtpError(message)) => assert!(message.contains("RATE")),
+            other => panic!("expected kiss-o'-death error, got {:?}", other),
+        }
+
+        assert!(parse_response(&server_reply(42, 0, 0), 42).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+// PCI Express enhanced configuration access (ECAM, also called MMCONFIG)
+//
+// The ACPI MCFG table gives the physical base of a memory window holding
+// the 4 KiB configuration space of every function, including the extended
+// space past the first 256 bytes that port I/O cannot reach. ACPI tables
+// and the window are accessed through their physical addresses, which
+// assumes the identity mapping UEFI leaves in place.
+
+use spin::Mutex;
+
+/// Registered ECAM window (set via set_ecam_region)
+static ECAM_REGION: Mutex<Option<EcamRegion>> = Mutex::new(None);
+
+/// Length of the common ACPI table header
+const SDT_HEADER_LEN: usize = 36;
+
+/// MCFG entries follow the header and 8 reserved bytes
+const MCFG_ENTRIES_OFFSET: usize = SDT_HEADER_LEN + 8;
+
+/// Length of one MCFG allocation entry
+const MCFG_ENTRY_LEN: usize = 16;
+
+/// Larger tables are treated as corrupt rather than read
+const MAX_TABLE_LEN: usize = 64 * 1024;
+
+/// One MCFG allocation: configuration space for a range of buses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EcamRegion {
+    /// Physical address of bus 0's configuration space
+    pub base: u64,
+    /// PCI segment group
+    pub segment: u16,
+    /// First bus decoded by the window
+    pub start_bus: u8,
+    /// Last bus decoded by the window
+    pub end_bus: u8,
+}
+
+impl EcamRegion {
+    /// Physical address of the dword holding `offset` in a function's
+    /// configuration space, or None if the window doesn't cover it
+    pub fn address(&self, bus: u8, device: u8, function: u8, offset: u16) -> Option<u64> {
+        if bus < self.start_bus || bus > self.end_bus || device > 31 || function > 7 {
+            return None;
+        }
+        if offset > 0xFFF {
+            return None;
+        }
+        Some(
+            self.base
+                + ((bus as u64) << 20)
+                + ((device as u64) << 15)
+                + ((function as u64) << 12)
+                + (offset & 0xFFC) as u64,
+        )
+    }
+}
+
+/// Route configuration space accesses through `region`, or back to the
+/// legacy mechanism with None
+pub fn set_ecam_region(region: Option<EcamRegion>) {
+    *ECAM_REGION.lock() = region;
+}
+
+/// The registered ECAM window, if any
+pub fn ecam_region() -> Option<EcamRegion> {
+    *ECAM_REGION.lock()
+}
+
+/// Extract the segment 0 allocation from an MCFG table
+pub fn parse_mcfg(table: &[u8]) -> Option<EcamRegion> {
+    if table.len() < MCFG_ENTRIES_OFFSET || &table[..4] != b"MCFG" {
+        return None;
+    }
+    let length = read_u32(table, 4) as usize;
+    if length > table.len() || !checksum_ok(&table[..length]) {
+        return None;
+    }
+    table[MCFG_ENTRIES_OFFSET..length]
+        .chunks_exact(MCFG_ENTRY_LEN)
+        .map(|entry| EcamRegion {
+            base: read_u64(entry, 0),
+            segment: u16::from_le_bytes([entry[8], entry[9]]),
+            start_bus: entry[10],
+            end_bus: entry[11],
+        })
+        .find(|region| region.segment == 0 && region.start_bus <= region.end_bus)
+}
+
+/// Find the ECAM window by following the RSDP to the MCFG table
+///
+/// Uses the XSDT when the RSDP is ACPI 2.0 or later, otherwise the RSDT.
+///
+/// # Safety
+/// `rsdp_addr` must be the firmware-provided RSDP, and the ACPI tables must
+/// be identity-mapped and readable.
+pub unsafe fn find_ecam_region(rsdp_addr: usize) -> Option<EcamRegion> {
+    if rsdp_addr == 0 {
+        return None;
+    }
+    let rsdp = physical_slice(rsdp_addr, 20);
+    if &rsdp[..8] != b"RSD PTR " || !checksum_ok(rsdp) {
+        return None;
+    }
+
+    let (root_addr, entry_len) = if rsdp[15] >= 2 {
+        (read_u64(physical_slice(rsdp_addr, 36), 24) as usize, 8)
+    } else {
+        (read_u32(rsdp, 16) as usize, 4)
+    };
+    let root = system_table(root_addr)?;
+
+    root[SDT_HEADER_LEN..]
+        .chunks_exact(entry_len)
+        .map(|entry| match entry_len {
+            8 => read_u64(entry, 0) as usize,
+            _ => read_u32(entry, 0) as usize,
+        })
+        .filter_map(|address| system_table(address))
+        .find(|table| &table[..4] == b"MCFG")
+        .and_then(parse_mcfg)
+}
+
+/// The ACPI table at `address`, if its length and checksum are sane
+///
+/// # Safety
+/// Same requirements as `find_ecam_region`.
+unsafe fn system_table(address: usize) -> Option<&'static [u8]> {
+    if address == 0 {
+        return None;
+    }
+    let length = read_u32(physical_slice(address, SDT_HEADER_LEN), 4) as usize;
+    if !(SDT_HEADER_LEN..=MAX_TABLE_LEN).contains(&length) {
+        return None;
+    }
+    let table = physical_slice(address, length);
+    checksum_ok(table).then_some(table)
+}
+
+/// # Safety
+/// `len` bytes at physical address `address` must be mapped and readable.
+unsafe fn physical_slice(address: usize, len: usize) -> &'static [u8] {
+    core::slice::from_raw_parts(address as *const u8, len)
+}
+
+/// ACPI checksums make all bytes of a structure sum to zero
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    /// Set `bytes[at]` so that `bytes` sums to zero
+    fn fix_checksum(bytes: &mut [u8], at: usize) {
+        bytes[at] = 0;
+        let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        bytes[at] = 0u8.wrapping_sub(sum);
+    }
+
+    fn table(signature: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0u8; SDT_HEADER_LEN];
+        bytes[..4].copy_from_slice(signature);
+        bytes.extend_from_slice(body);
+        let len = bytes.len() as u32;
+        bytes[4..8].copy_from_slice(&len.to_le_bytes());
+        fix_checksum(&mut bytes, 9);
+        bytes
+    }
+
+    fn mcfg(entries: &[(u64, u16, u8, u8)]) -> Vec<u8> {
+        let mut body = vec![0u8; 8];
+        for &(base, segment, start, end) in entries {
+            body.extend_from_slice(&base.to_le_bytes());
+            body.extend_from_slice(&segment.to_le_bytes());
+            body.extend_from_slice(&[start, end, 0, 0, 0, 0]);
+        }
+        table(b"MCFG", &body)
+    }
+
+    #[test]
+    fn test_parse_mcfg_picks_segment_zero() {
+        let bytes = mcfg(&[(0xE000_0000, 1, 0, 0xFF), (0xB000_0000, 0, 0, 0x3F)]);
+        let region = parse_mcfg(&bytes).unwrap();
+        assert_eq!(
+            region,
+            EcamRegion {
+                base: 0xB000_0000,
+                segment: 0,
+                start_bus: 0,
+                end_bus: 0x3F
+            }
+        );
+        assert_eq!(
+            region.address(1, 2, 3, 0x104),
+            Some(0xB010_0000 + (2 << 15) + (3 << 12) + 0x104)
+        );
+        assert_eq!(region.address(0, 0, 0, 0x103), Some(0xB000_0100));
+        assert_eq!(region.address(0x40, 0, 0, 0), None);
+        assert_eq!(region.address(0, 0, 0, 0x1000), None);
+
+        let mut corrupt = bytes.clone();
+        corrupt[MCFG_ENTRIES_OFFSET] ^= 1;
+        assert_eq!(parse_mcfg(&corrupt), None);
+        assert_eq!(parse_mcfg(&table(b"APIC", &[0; 16])), None);
+    }
+
+    #[test]
+    fn test_find_ecam_region_through_xsdt() {
+        let mcfg = mcfg(&[(0xB000_0000, 0, 0, 0xFF)]);
+        let apic = table(b"APIC", &[0; 8]);
+
+        let mut xsdt_body = Vec::new();
+        xsdt_body.extend_from_slice(&(apic.as_ptr() as u64).to_le_bytes());
+        xsdt_body.extend_from_slice(&(mcfg.as_ptr() as u64).to_le_bytes());
+        let xsdt = table(b"XSDT", &xsdt_body);
+
+        let mut rsdp = vec![0u8; 36];
+        rsdp[..8].copy_from_slice(b"RSD PTR ");
+        rsdp[15] = 2;
+        rsdp[24..32].copy_from_slice(&(xsdt.as_ptr() as u64).to_le_bytes());
+        // The original checksum covers the ACPI 1.0 part only
+        fix_checksum(&mut rsdp[..20], 8);
+        let region = unsafe { find_ecam_region(rsdp.as_ptr() as usize) }.unwrap();
+        assert_eq!(region.base, 0xB000_0000);
+
+        let mut bad = rsdp.clone();
+        bad[0] = b'X';
+        assert_eq!(unsafe { find_ecam_region(bad.as_ptr() as usize) }, None);
+        assert_eq!(unsafe { find_ecam_region(0) }, None);
+    }
+}
@@ -2,12 +2,109 @@
 
 use crate::error::NetError;
 
+mod ecam;
+
+pub use ecam::{ecam_region, find_ecam_region, parse_mcfg, set_ecam_region, EcamRegion};
+
 /// PCI vendor ID for Red Hat (virtio devices)
 pub const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
 
 /// PCI device ID for virtio-net
 pub const VIRTIO_NET_DEVICE_ID: u16 = 0x1000;
 
+/// PCI device ID for modern-only (non-transitional) virtio-net
+pub const VIRTIO_NET_MODERN_DEVICE_ID: u16 = 0x1041;
+
+/// PCI vendor ID for Intel
+pub const INTEL_VENDOR_ID: u16 = 0x8086;
+
+/// PCI device ID for the 82540EM (e1000) NIC
+pub const E1000_DEVICE_ID: u16 = 0x100E;
+
+/// PCI device ID for the 82574L (e1000e) NIC
+pub const E1000E_82574L_DEVICE_ID: u16 = 0x10D3;
+
+/// PCI vendor ID for Realtek
+pub const REALTEK_VENDOR_ID: u16 = 0x10EC;
+
+/// PCI device ID for the RTL8139 NIC
+pub const RTL8139_DEVICE_ID: u16 = 0x8139;
+
+/// Status register bit: the capability list pointer is valid
+const PCI_STATUS_CAP_LIST: u16 = 1 << 4;
+
+/// Offset of the status register
+const PCI_STATUS_OFFSET: u8 = 0x06;
+
+/// Offset of the capability list pointer
+const PCI_CAP_POINTER_OFFSET: u8 = 0x34;
+
+/// Most capabilities a well-formed list can hold (guards against loops)
+const PCI_MAX_CAPABILITIES: usize = 48;
+
+/// Offset of the dword holding the header type (bits 16-23)
+const PCI_HEADER_TYPE_DWORD: u16 = 0x0C;
+
+/// Header type bit: the device implements functions 1-7
+const PCI_MULTI_FUNCTION: u32 = 0x80 << 16;
+
+/// Read a dword from a function's configuration space
+///
+/// Uses ECAM when a region is registered, otherwise (on x86_64) ports
+/// 0xCF8/0xCFC, which only reach the first 256 bytes. Accesses no method
+/// can serve read as all ones, like an absent device.
+pub fn read_config(bus: u8, device: u8, function: u8, offset: u16) -> u32 {
+    if let Some(address) = ecam_region().and_then(|r| r.address(bus, device, function, offset)) {
+        // SAFETY: the ECAM window is device memory described by ACPI
+        return unsafe { core::ptr::read_volatile(address as usize as *const u32) };
+    }
+    #[cfg(target_arch = "x86_64")]
+    if offset < 256 {
+        unsafe {
+            x86_64::instructions::port::Port::<u32>::new(0xCF8)
+                .write(legacy_address(bus, device, function, offset));
+            return x86_64::instructions::port::Port::<u32>::new(0xCFC).read();
+        }
+    }
+    u32::MAX
+}
+
+/// Write a dword to a function's configuration space (see `read_config`)
+pub fn write_config(bus: u8, device: u8, function: u8, offset: u16, value: u32) {
+    if let Some(address) = ecam_region().and_then(|r| r.address(bus, device, function, offset)) {
+        // SAFETY: the ECAM window is device memory described by ACPI
+        unsafe { core::ptr::write_volatile(address as usize as *mut u32, value) };
+        return;
+    }
+    #[cfg(target_arch = "x86_64")]
+    if offset < 256 {
+        unsafe {
+            x86_64::instructions::port::Port::<u32>::new(0xCF8)
+                .write(legacy_address(bus, device, function, offset));
+            x86_64::instructions::port::Port::<u32>::new(0xCFC).write(value);
+        }
+    }
+}
+
+/// Port 0xCF8 address for a dword of configuration space
+#[cfg(target_arch = "x86_64")]
+fn legacy_address(bus: u8, device: u8, function: u8, offset: u16) -> u32 {
+    (1u32 << 31)
+        | ((bus as u32) << 16)
+        | ((device as u32) << 11)
+        | ((function as u32) << 8)
+        | (offset as u32 & 0xFC)
+}
+
+/// An entry in a device's PCI capability list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciCapability {
+    /// Capability ID (e.g. 0x09 for vendor-specific)
+    pub id: u8,
+    /// Offset of the capability in configuration space
+    pub offset: u8,
+}
+
 /// PCI configuration space address
 ///
 /// On x86_64, PCI configuration space is accessed via I/O ports 0xCF8 (address) and 0xCFC (data)
@@ -35,6 +132,8 @@ pub struct PciDevice {
     pub subclass: u8,
     /// Programming interface
     pub prog_if: u8,
+    /// Revision ID
+    pub revision_id: u8,
     /// Base address registers
     pub bars: [u32; 6],
     /// Interrupt line
@@ -43,41 +142,16 @@ pub struct PciDevice {
 
 impl PciDevice {
     /// Read a 32-bit value from PCI configuration space
-    #[cfg(target_arch = "x86_64")]
     pub fn read_config_dword(&self, offset: u8) -> u32 {
-        let address = (1u32 << 31)
-            | ((self.bus as u32) << 16)
-            | ((self.device as u32) << 11)
-            | ((self.function as u32) << 8)
-            | (offset as u32 & 0xFC);
-
-        unsafe {
-            // Write address to 0xCF8
-            x86_64::instructions::port::Port::<u32>::new(0xCF8).write(address);
-            // Read data from 0xCFC
-            x86_64::instructions::port::Port::<u32>::new(0xCFC).read()
-        }
+        read_config(self.bus, self.device, self.function, offset as u16)
     }
 
     /// Write a 32-bit value to PCI configuration space
-    #[cfg(target_arch = "x86_64")]
     pub fn write_config_dword(&self, offset: u8, value: u32) {
-        let address = (1u32 << 31)
-            | ((self.bus as u32) << 16)
-            | ((self.device as u32) << 11)
-            | ((self.function as u32) << 8)
-            | (offset as u32 & 0xFC);
-
-        unsafe {
-            // Write address to 0xCF8
-            x86_64::instructions::port::Port::<u32>::new(0xCF8).write(address);
-            // Write data to 0xCFC
-            x86_64::instructions::port::Port::<u32>::new(0xCFC).write(value);
-        }
+        write_config(self.bus, self.device, self.function, offset as u16, value)
     }
 
     /// Read a 16-bit value from PCI configuration space
-    #[cfg(target_arch = "x86_64")]
     pub fn read_config_word(&self, offset: u8) -> u16 {
         let dword = self.read_config_dword(offset);
         if offset & 2 == 0 {
@@ -88,13 +162,30 @@ impl PciDevice {
     }
 
     /// Read a 8-bit value from PCI configuration space
-    #[cfg(target_arch = "x86_64")]
     pub fn read_config_byte(&self, offset: u8) -> u8 {
         let dword = self.read_config_dword(offset);
         let shift = (offset & 3) * 8;
         ((dword >> shift) & 0xFF) as u8
     }
 
+    /// Walk the capability list in configuration space
+    ///
+    /// Returns an empty list if the device has no capabilities.
+    pub fn capabilities(&self) -> alloc::vec::Vec<PciCapability> {
+        let mut capabilities = alloc::vec::Vec::new();
+        if self.read_config_word(PCI_STATUS_OFFSET) & PCI_STATUS_CAP_LIST == 0 {
+            return capabilities;
+        }
+
+        let mut offset = self.read_config_byte(PCI_CAP_POINTER_OFFSET) & 0xFC;
+        while offset != 0 && capabilities.len() < PCI_MAX_CAPABILITIES {
+            let id = self.read_config_byte(offset);
+            capabilities.push(PciCapability { id, offset });
+            offset = self.read_config_byte(offset + 1) & 0xFC;
+        }
+        capabilities
+    }
+
     /// Get the base address register at index `index`
     pub fn get_bar(&self, index: usize) -> u64 {
         if index >= 6 {
@@ -121,107 +212,71 @@ impl PciDevice {
 
 /// Scan PCI bus for devices
 ///
+/// Covers the buses of the ECAM region if one is registered, otherwise all
+/// 256 through the legacy ports (nothing off x86_64). Functions 1-7 are
+/// only probed on multi-function devices.
+///
 /// # Returns
 /// A vector of all discovered PCI devices
-#[cfg(target_arch = "x86_64")]
 pub fn scan_pci_bus() -> alloc::vec::Vec<PciDevice> {
     let mut devices = alloc::vec::Vec::new();
 
-    // Scan all buses (0-255)
-    for bus in 0..=255 {
-        // Scan all devices (0-31)
+    let (first_bus, last_bus) = match ecam_region() {
+        Some(region) => (region.start_bus, region.end_bus),
+        None if cfg!(target_arch = "x86_64") => (0, 255),
+        None => return devices,
+    };
+
+    for bus in first_bus..=last_bus {
         for device in 0..=31 {
-            // Scan all functions (0-7)
-            for function in 0..=7 {
-                // Read vendor ID (offset 0x00)
-                let vendor_id = unsafe {
-                    let address = (1u32 << 31)
-                        | ((bus as u32) << 16)
-                        | ((device as u32) << 11)
-                        | ((function as u32) << 8);
-                    x86_64::instructions::port::Port::<u32>::new(0xCF8).write(address);
-                    x86_64::instructions::port::Port::<u32>::new(0xCFC).read() as u16
-                };
-
-                // If vendor ID is 0xFFFF, device doesn't exist
-                if vendor_id == 0xFFFF {
-                    continue;
-                }
-
-                // Read device ID (offset 0x02)
-                let device_id = unsafe {
-                    let address = (1u32 << 31)
-                        | ((bus as u32) << 16)
-                        | ((device as u32) << 11)
-                        | ((function as u32) << 8)
-                        | 0x00;
-                    x86_64::instructions::port::Port::<u32>::new(0xCF8).write(address);
-                    let dword = x86_64::instructions::port::Port::<u32>::new(0xCFC).read();
-                    ((dword >> 16) & 0xFFFF) as u16
-                };
-
-                // Read class code (offset 0x08-0x0B)
-                let class_reg = unsafe {
-                    let address = (1u32 << 31)
-                        | ((bus as u32) << 16)
-                        | ((device as u32) << 11)
-                        | ((function as u32) << 8)
-                        | 0x08;
-                    x86_64::instructions::port::Port::<u32>::new(0xCF8).write(address);
-                    x86_64::instructions::port::Port::<u32>::new(0xCFC).read()
-                };
-
-                let class_code = ((class_reg >> 24) & 0xFF) as u8;
-                let subclass = ((class_reg >> 16) & 0xFF) as u8;
-                let prog_if = ((class_reg >> 8) & 0xFF) as u8;
-
-                // Read BARs (offset 0x10-0x27)
-                let mut bars = [0u32; 6];
-                for i in 0..6 {
-                    bars[i] = unsafe {
-                        let address = (1u32 << 31)
-                            | ((bus as u32) << 16)
-                            | ((device as u32) << 11)
-                            | ((function as u32) << 8)
-                            | (0x10u32 + (i as u32 * 4));
-                        x86_64::instructions::port::Port::<u32>::new(0xCF8).write(address);
-                        x86_64::instructions::port::Port::<u32>::new(0xCFC).read()
-                    };
-                }
-
-                // Read interrupt line (offset 0x3C)
-                let interrupt_reg = unsafe {
-                    let address = (1u32 << 31)
-                        | ((bus as u32) << 16)
-                        | ((device as u32) << 11)
-                        | ((function as u32) << 8)
-                        | 0x3C;
-                    x86_64::instructions::port::Port::<u32>::new(0xCF8).write(address);
-                    x86_64::instructions::port::Port::<u32>::new(0xCFC).read()
-                };
-                let interrupt_line = (interrupt_reg & 0xFF) as u8;
-
-                let pci_device = PciDevice {
-                    bus,
-                    device,
-                    function,
-                    vendor_id,
-                    device_id,
-                    class_code,
-                    subclass,
-                    prog_if,
-                    bars,
-                    interrupt_line,
-                };
-
-                devices.push(pci_device);
+            let Some(first) = probe_function(bus, device, 0) else {
+                continue;
+            };
+            devices.push(first);
+
+            let header = read_config(bus, device, 0, PCI_HEADER_TYPE_DWORD);
+            if header & PCI_MULTI_FUNCTION == 0 {
+                continue;
             }
+            devices.extend((1..=7).filter_map(|function| probe_function(bus, device, function)));
         }
     }
 
     devices
 }
 
+/// Read a function's identification, BARs and interrupt line
+fn probe_function(bus: u8, device: u8, function: u8) -> Option<PciDevice> {
+    let read = |offset: u16| read_config(bus, device, function, offset);
+
+    // Vendor ID 0xFFFF means nothing answered
+    let id = read(0x00);
+    let vendor_id = (id & 0xFFFF) as u16;
+    if vendor_id == 0xFFFF {
+        return None;
+    }
+
+    let class_reg = read(0x08);
+    let mut bars = [0u32; 6];
+    for (i, bar) in bars.iter_mut().enumerate() {
+        *bar = read(0x10 + i as u16 * 4);
+    }
+
+    Some(PciDevice {
+        bus,
+        device,
+        function,
+        vendor_id,
+        device_id: (id >> 16) as u16,
+        class_code: (class_reg >> 24) as u8,
+        subclass: (class_reg >> 16) as u8,
+        prog_if: (class_reg >> 8) as u8,
+        revision_id: class_reg as u8,
+        bars,
+        interrupt_line: read(0x3C) as u8,
+    })
+}
+
 /// Find a PCI device by vendor and device ID
 ///
 /// # Arguments
@@ -232,17 +287,51 @@ pub fn scan_pci_bus() -> alloc::vec::Vec<PciDevice> {
 /// * `Some(PciDevice)` if found
 /// * `None` if not found
 pub fn find_pci_device(vendor_id: u16, device_id: u16) -> Option<PciDevice> {
-    #[cfg(target_arch = "x86_64")]
-    {
+    scan_pci_bus()
+        .into_iter()
+        .find(|d| d.vendor_id == vendor_id && d.device_id == device_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_ecam_scan_skips_functions_of_single_function_devices() {
+        // Configuration space for bus 0: 32 devices x 8 functions x 4 KiB
+        let mut memory = vec![u32::MAX; 32 * 8 * 1024];
+        let base = memory.as_mut_ptr() as u64;
+        let mut function = |device: usize, function: usize, id: u32, header: u32| {
+            let config = &mut memory[(device * 8 + function) * 1024..][..1024];
+            config[0] = id;
+            config[0x08 / 4] = 0x0200_0001;
+            config[0x0C / 4] = header << 16;
+            config[0x3C / 4] = 11;
+        };
+        function(0, 0, 0x1000_1AF4, 0x00);
+        // Single-function devices may still decode every function number
+        function(0, 1, 0x1000_1AF4, 0x00);
+        function(3, 0, 0x100E_8086, 0x80);
+        function(3, 2, 0x10D3_8086, 0x00);
+
+        set_ecam_region(Some(EcamRegion {
+            base,
+            segment: 0,
+            start_bus: 0,
+            end_bus: 0,
+        }));
         let devices = scan_pci_bus();
-        devices
-            .into_iter()
-            .find(|d| d.vendor_id == vendor_id && d.device_id == device_id)
-    }
+        let found = find_pci_device(INTEL_VENDOR_ID, E1000E_82574L_DEVICE_ID);
+        set_ecam_region(None);
 
-    #[cfg(not(target_arch = "x86_64"))]
-    {
-        // ARM64 and other architectures would use different PCI access methods
-        None
+        let ids: alloc::vec::Vec<_> = devices
+            .iter()
+            .map(|d| (d.device, d.function, d.device_id))
+            .collect();
+        assert_eq!(ids, [(0, 0, 0x1000), (3, 0, 0x100E), (3, 2, 0x10D3)]);
+        assert_eq!(devices[0].class_code, 0x02);
+        assert_eq!(devices[0].interrupt_line, 11);
+        assert_eq!(found.map(|d| (d.device, d.function)), Some((3, 2)));
     }
 }
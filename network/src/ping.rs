@@ -0,0 +1,139 @@
+//! ICMP echo (ping) for moteOS
+//!
+//! Used to tell a broken local network apart from an unreachable provider.
+//! The socket handling lives in `NetworkStack::ping`; this module builds
+//! and parses echo packets and summarises the results.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::wire::{Icmpv4Packet, Icmpv4Repr};
+
+/// Payload carried by each echo request
+pub const PING_PAYLOAD: &[u8] = b"moteOS ping";
+
+/// Length of an echo request, ICMP header included
+pub fn echo_request_len() -> usize {
+    echo_request(0, 0).buffer_len()
+}
+
+fn echo_request(ident: u16, seq_no: u16) -> Icmpv4Repr<'static> {
+    Icmpv4Repr::EchoRequest {
+        ident,
+        seq_no,
+        data: PING_PAYLOAD,
+    }
+}
+
+/// Write an echo request into `buffer` (at least [`echo_request_len`] bytes)
+pub fn emit_echo_request(buffer: &mut [u8], ident: u16, seq_no: u16) {
+    let mut packet = Icmpv4Packet::new_unchecked(buffer);
+    echo_request(ident, seq_no).emit(&mut packet, &ChecksumCapabilities::default());
+}
+
+/// Sequence number of `packet` if it is an echo reply for `ident`
+pub fn parse_echo_reply(packet: &[u8], ident: u16) -> Option<u16> {
+    let packet = Icmpv4Packet::new_checked(packet).ok()?;
+    match Icmpv4Repr::parse(&packet, &ChecksumCapabilities::default()).ok()? {
+        Icmpv4Repr::EchoReply {
+            ident: reply_ident,
+            seq_no,
+            ..
+        } if reply_ident == ident => Some(seq_no),
+        _ => None,
+    }
+}
+
+/// Results of a ping run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PingStats {
+    /// Echo requests sent
+    pub transmitted: u16,
+    /// Round-trip time of each reply, in milliseconds, in arrival order
+    pub rtts_ms: Vec<u64>,
+}
+
+impl PingStats {
+    /// Number of replies received
+    pub fn received(&self) -> u16 {
+        self.rtts_ms.len() as u16
+    }
+
+    /// Number of requests that got no reply
+    pub fn lost(&self) -> u16 {
+        self.transmitted.saturating_sub(self.received())
+    }
+
+    pub fn min_ms(&self) -> Option<u64> {
+        self.rtts_ms.iter().copied().min()
+    }
+
+    pub fn max_ms(&self) -> Option<u64> {
+        self.rtts_ms.iter().copied().max()
+    }
+
+    pub fn avg_ms(&self) -> Option<u64> {
+        let total: u64 = self.rtts_ms.iter().sum();
+        (!self.rtts_ms.is_empty()).then(|| total / self.rtts_ms.len() as u64)
+    }
+}
+
+impl core::fmt::Display for PingStats {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} sent, {} received, {} lost",
+            self.transmitted,
+            self.received(),
+            self.lost()
+        )?;
+        if let (Some(min), Some(avg), Some(max)) = (self.min_ms(), self.avg_ms(), self.max_ms()) {
+            write!(f, "; rtt min/avg/max = {}/{}/{} ms", min, avg, max)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use smoltcp::wire::Icmpv4Message;
+
+    #[test]
+    fn test_echo_request_roundtrip() {
+        let mut buffer = vec![0u8; echo_request_len()];
+        emit_echo_request(&mut buffer, 0x1234, 3);
+
+        // A request is not a reply
+        assert_eq!(parse_echo_reply(&buffer, 0x1234), None);
+
+        // Turn it into the reply a host would send (type 0, checksum redone)
+        let mut packet = Icmpv4Packet::new_unchecked(&mut buffer[..]);
+        packet.set_msg_type(Icmpv4Message::EchoReply);
+        packet.fill_checksum();
+        assert_eq!(parse_echo_reply(&buffer, 0x1234), Some(3));
+        assert_eq!(parse_echo_reply(&buffer, 0x4321), None);
+    }
+
+    #[test]
+    fn test_ping_stats() {
+        let stats = PingStats {
+            transmitted: 4,
+            rtts_ms: vec![12, 20, 16],
+        };
+        assert_eq!(stats.lost(), 1);
+        assert_eq!(
+            stats.to_string(),
+            "4 sent, 3 received, 1 lost; rtt min/avg/max = 12/16/20 ms"
+        );
+
+        let silent = PingStats {
+            transmitted: 2,
+            rtts_ms: Vec::new(),
+        };
+        assert_eq!(silent.avg_ms(), None);
+        assert_eq!(silent.to_string(), "2 sent, 0 received, 2 lost");
+    }
+}
@@ -5,17 +5,27 @@
 
 extern crate alloc;
 
-use crate::dhcp::{self, DhcpState, IpConfig};
-use crate::dns::{self, DnsResponse, ResponseCode};
+use crate::dhcp::{self, DhcpLease, DhcpState, DhcpUpdate, IpConfig};
+use crate::http::HttpProxy;
+use crate::dns::{self, DnsCache, DnsCacheStats, DnsResponse, ResponseCode};
 use crate::drivers::NetworkDriver;
 use crate::error::NetError;
+use crate::linklocal;
+use crate::ntp;
+use crate::ping::{self, PingStats};
+use crate::stats::NetStats;
+use crate::tcp::{self, TcpStream};
 use alloc::boxed::Box;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use smoltcp::iface::{Config, Interface, Route, SocketSet};
+use core::sync::atomic::{AtomicU16, Ordering};
+use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
 use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
 use smoltcp::socket::dhcpv4::{self, Socket as DhcpSocket};
+use smoltcp::socket::icmp::{self, Socket as IcmpSocket};
+use smoltcp::socket::tcp::{Socket as TcpSocket, State as TcpState};
 use smoltcp::socket::udp::{self, PacketMetadata, Socket as UdpSocket, UdpMetadata};
+use smoltcp::socket::Socket;
 use smoltcp::time::Instant;
 use smoltcp::wire::{
     EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address, Ipv4Cidr,
@@ -25,11 +35,22 @@ use spin::Mutex;
 /// Device wrapper that adapts our NetworkDriver trait to smoltcp's Device trait
 struct DeviceWrapper {
     driver: Box<dyn NetworkDriver>,
+    /// Traffic counters, updated as frames pass through
+    stats: NetStats,
+    /// Link-local address being probed; ARP frames are checked against it
+    arp_watch: Option<Ipv4Address>,
+    /// Another host claimed `arp_watch`
+    arp_conflict: bool,
 }
 
 impl DeviceWrapper {
     fn new(driver: Box<dyn NetworkDriver>) -> Self {
-        Self { driver }
+        Self {
+            driver,
+            stats: NetStats::default(),
+            arp_watch: None,
+            arp_conflict: false,
+        }
     }
 }
 
@@ -50,6 +71,7 @@ impl RxToken for RxTokenWrapper {
 /// TX token implementation for smoltcp
 struct TxTokenWrapper<'a> {
     driver: &'a mut Box<dyn NetworkDriver>,
+    stats: &'a mut NetStats,
 }
 
 impl<'a> TxToken for TxTokenWrapper<'a> {
@@ -62,8 +84,9 @@ impl<'a> TxToken for TxTokenWrapper<'a> {
         let result = f(&mut buffer);
 
         // Send the packet through the driver
-        // Ignore errors here as smoltcp doesn't have a good way to propagate them
-        let _ = self.driver.send(&buffer);
+        // Errors are only counted, as smoltcp doesn't have a good way to propagate them
+        let sent = self.driver.send(&buffer).is_ok();
+        self.stats.record_tx(len, sent);
 
         result
     }
@@ -82,14 +105,27 @@ impl Device for DeviceWrapper {
     fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
         // Try to receive a packet from the driver
         match self.driver.receive() {
-            Ok(Some(packet)) => Some((
-                RxTokenWrapper { buffer: packet },
-                TxTokenWrapper {
-                    driver: &mut self.driver,
-                },
-            )),
+            Ok(Some(packet)) => {
+                self.stats.record_rx(packet.len());
+                if let Some(candidate) = self.arp_watch {
+                    let mac = self.driver.mac_address();
+                    if linklocal::is_conflict(&packet, &mac, candidate) {
+                        self.arp_conflict = true;
+                    }
+                }
+                Some((
+                    RxTokenWrapper { buffer: packet },
+                    TxTokenWrapper {
+                        driver: &mut self.driver,
+                        stats: &mut self.stats,
+                    },
+                ))
+            }
             Ok(None) => None,
-            Err(_) => None,
+            Err(_) => {
+                self.stats.rx_drops += 1;
+                None
+            }
         }
     }
 
@@ -97,6 +133,7 @@ impl Device for DeviceWrapper {
         // Always allow transmission
         Some(TxTokenWrapper {
             driver: &mut self.driver,
+            stats: &mut self.stats,
         })
     }
 
@@ -109,6 +146,101 @@ impl Device for DeviceWrapper {
     }
 }
 
+/// Public resolvers tried after the DHCP-provided and configured ones
+pub const PUBLIC_DNS_SERVERS: [Ipv4Address; 2] =
+    [Ipv4Address::new(8, 8, 8, 8), Ipv4Address::new(1, 1, 1, 1)];
+
+/// Local ports handed out by `udp_open(0)`
+///
+/// Kept below the range outgoing TCP connections use (49152 and up), so a
+/// DNS query never takes the port an HTTP connection is about to bind.
+const UDP_EPHEMERAL_PORT_START: u16 = 32768;
+const UDP_EPHEMERAL_PORT_END: u16 = 49151;
+
+/// Next candidate for `udp_open(0)`
+static NEXT_UDP_EPHEMERAL_PORT: AtomicU16 = AtomicU16::new(UDP_EPHEMERAL_PORT_START);
+
+/// Next port in the UDP ephemeral range, wrapping at its end
+fn next_udp_ephemeral_port() -> u16 {
+    let port = NEXT_UDP_EPHEMERAL_PORT.fetch_add(1, Ordering::Relaxed);
+    if !(UDP_EPHEMERAL_PORT_START..=UDP_EPHEMERAL_PORT_END).contains(&port) {
+        NEXT_UDP_EPHEMERAL_PORT.store(UDP_EPHEMERAL_PORT_START + 1, Ordering::Relaxed);
+        return UDP_EPHEMERAL_PORT_START;
+    }
+    port
+}
+
+/// Buffer space of a UDP socket, per direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdpBufferSizes {
+    /// Datagrams that can be queued
+    pub packets: usize,
+    /// Payload bytes that can be queued, shared by those datagrams
+    pub bytes: usize,
+}
+
+impl Default for UdpBufferSizes {
+    fn default() -> Self {
+        Self {
+            packets: 4,
+            bytes: 1024,
+        }
+    }
+}
+
+/// DNS answers rarely exceed 512 bytes without EDNS; truncated ones go to TCP
+const DNS_UDP_BUFFERS: UdpBufferSizes = UdpBufferSizes {
+    packets: 4,
+    bytes: 1024,
+};
+
+/// One 48-byte SNTP exchange at a time
+const NTP_UDP_BUFFERS: UdpBufferSizes = UdpBufferSizes {
+    packets: 2,
+    bytes: 256,
+};
+
+/// A UDP socket opened with [`NetworkStack::udp_open`]
+///
+/// Only valid for the stack that opened it, until [`NetworkStack::udp_close`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdpEndpointHandle {
+    handle: SocketHandle,
+    local_port: u16,
+}
+
+impl UdpEndpointHandle {
+    /// Port the socket is bound to
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+}
+
+/// How far the interface's addressing gets us
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkStatus {
+    /// The link is down
+    NoLink,
+    /// No address yet (DHCP still running, or it failed without a fallback)
+    Unconfigured,
+    /// Only a 169.254.x.y address: hosts on the link are reachable, the
+    /// internet is not
+    LinkLocalOnly,
+    /// A DHCP or static address
+    Online,
+}
+
+impl core::fmt::Display for NetworkStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            NetworkStatus::NoLink => write!(f, "no link"),
+            NetworkStatus::Unconfigured => write!(f, "no address"),
+            NetworkStatus::LinkLocalOnly => write!(f, "no internet \u{2014} link-local only"),
+            NetworkStatus::Online => write!(f, "online"),
+        }
+    }
+}
+
 /// Network stack using smoltcp
 ///
 /// This struct provides TCP/IP networking functionality by integrating
@@ -122,6 +254,31 @@ pub struct NetworkStack {
     device: DeviceWrapper,
     /// DHCP socket handle (if DHCP is enabled)
     dhcp_handle: Option<smoltcp::iface::SocketHandle>,
+    /// Configuration from the current DHCP lease, as applied to the interface
+    dhcp_config: Option<IpConfig>,
+    /// Timers of the current DHCP lease
+    dhcp_lease: Option<DhcpLease>,
+    /// Last DHCP state reported to `dhcp_state_callback`
+    dhcp_state: DhcpState,
+    /// Called whenever `dhcp_state` changes
+    dhcp_state_callback: Option<fn(DhcpState)>,
+    /// Recently resolved hostnames
+    dns_cache: DnsCache,
+    /// Resolvers to try in order (DHCP-provided first, then configured ones)
+    dns_servers: Vec<Ipv4Address>,
+    /// Unix time minus local time in milliseconds, once NTP has answered
+    ntp_offset_ms: Option<i64>,
+    /// Proxy for `HttpClient`s that don't set their own
+    http_proxy: Option<HttpProxy>,
+    /// Resolve `.local` names with multicast DNS
+    mdns_enabled: bool,
+    /// Claim a link-local address when `dhcp_acquire` times out
+    link_local_fallback: bool,
+    /// The link-local address in use, until DHCP or a static address replaces it
+    link_local: Option<Ipv4Address>,
+    /// Closed TCP sockets still finishing their close, with the time they
+    /// get reset at (set on the first poll after they were handed over)
+    closing_tcp: Vec<(SocketHandle, Option<i64>)>,
 }
 
 impl NetworkStack {
@@ -186,6 +343,18 @@ impl NetworkStack {
             sockets,
             device,
             dhcp_handle: None,
+            dhcp_config: None,
+            dhcp_lease: None,
+            dhcp_state: DhcpState::Init,
+            dhcp_state_callback: None,
+            dns_cache: DnsCache::default(),
+            dns_servers: Vec::new(),
+            ntp_offset_ms: None,
+            http_proxy: None,
+            mdns_enabled: true,
+            link_local_fallback: true,
+            link_local: None,
+            closing_tcp: Vec::new(),
         })
     }
 
@@ -196,6 +365,7 @@ impl NetworkStack {
     /// - Handle TCP state machine
     /// - Send outgoing packets
     /// - Process timeouts
+    /// - Renew the DHCP lease and apply any configuration change
     ///
     /// # Arguments
     /// * `timestamp` - Current timestamp in milliseconds since boot
@@ -208,15 +378,144 @@ impl NetworkStack {
         let timestamp = Instant::from_millis(timestamp_ms);
 
         // Poll the driver first
-        self.device.driver.poll()?;
+        if let Err(error) = self.device.driver.poll() {
+            self.device.stats.record_error(&error);
+            return Err(error);
+        }
 
         // Poll the smoltcp interface
         let _ = self
             .iface
             .poll(timestamp, &mut self.device, &mut self.sockets);
+
+        self.reap_closing_tcp(timestamp_ms);
+        self.process_dhcp(timestamp_ms)
+    }
+
+    /// Keep a closing TCP socket until its close finishes, then remove it
+    ///
+    /// See [`crate::tcp::TcpStream::close_in_background`].
+    pub(crate) fn linger_tcp_socket(&mut self, handle: SocketHandle) {
+        self.closing_tcp.push((handle, None));
+    }
+
+    /// Remove lingering TCP sockets that are done, and reset the ones that
+    /// have had `TCP_CLOSE_LINGER_MS`
+    fn reap_closing_tcp(&mut self, now_ms: i64) {
+        let sockets = &mut self.sockets;
+        self.closing_tcp.retain_mut(|(handle, deadline)| {
+            let socket = sockets.get_mut::<TcpSocket>(*handle);
+            match socket.state() {
+                TcpState::Closed | TcpState::TimeWait => {
+                    sockets.remove(*handle);
+                    return false;
+                }
+                _ => {}
+            }
+            match deadline {
+                None => *deadline = Some(now_ms + tcp::TCP_CLOSE_LINGER_MS),
+                // Removed on the next poll, once the reset has gone out
+                Some(deadline) if now_ms >= *deadline => socket.abort(),
+                Some(_) => {}
+            }
+            true
+        });
+    }
+
+    /// Milliseconds until the stack next needs polling, if anything is pending
+    ///
+    /// `Some(0)` means work is due now; `None` means nothing is scheduled and
+    /// only a received packet can create work. Retransmits, keep-alives and
+    /// DHCP renewals are all covered.
+    pub fn poll_delay_ms(&mut self, timestamp_ms: i64) -> Option<u64> {
+        self.iface
+            .poll_delay(Instant::from_millis(timestamp_ms), &self.sockets)
+            .map(|delay| delay.total_millis())
+    }
+
+    /// Act on DHCP socket events and track the lease
+    ///
+    /// smoltcp renews at T1, rebinds at T2 and starts over at expiry; this
+    /// applies the resulting configuration and reports state changes.
+    fn process_dhcp(&mut self, now_ms: i64) -> Result<(), NetError> {
+        let Some(handle) = self.dhcp_handle else {
+            return Ok(());
+        };
+        let socket = self.sockets.get_mut::<DhcpSocket>(handle);
+        match dhcp::poll_update(socket, now_ms) {
+            Some(DhcpUpdate::Configured(config, lease)) => {
+                self.dhcp_lease = Some(lease);
+                // Renewals that keep the same address need no reconfiguration
+                if self.dhcp_config.as_ref() != Some(&config) {
+                    self.apply_dhcp_config(&config)?;
+                    self.dhcp_config = Some(config);
+                }
+            }
+            Some(DhcpUpdate::Deconfigured) => {
+                self.dhcp_lease = None;
+                if self.dhcp_config.take().is_some() {
+                    self.clear_ip_config();
+                }
+            }
+            None => {}
+        }
+
+        let state = match self.dhcp_lease {
+            Some(lease) => lease.state_at(now_ms),
+            None => DhcpState::Discovering,
+        };
+        self.set_dhcp_state(state);
         Ok(())
     }
 
+    /// Record a DHCP state and report it if it changed
+    fn set_dhcp_state(&mut self, state: DhcpState) {
+        if self.dhcp_state != state {
+            self.dhcp_state = state;
+            if let Some(callback) = self.dhcp_state_callback {
+                callback(state);
+            }
+        }
+    }
+
+    /// Drop the address and default route of a lost lease
+    fn clear_ip_config(&mut self) {
+        self.iface.update_ip_addrs(|ip_addrs| {
+            ip_addrs.clear();
+            let _ = ip_addrs.push(IpCidr::new(IpAddress::Ipv4(Ipv4Address::UNSPECIFIED), 0));
+        });
+        self.iface.routes_mut().remove_default_ipv4_route();
+        self.dns_cache.clear();
+    }
+
+    /// Traffic and connection counters
+    pub fn stats(&self) -> &NetStats {
+        &self.device.stats
+    }
+
+    /// Zero all counters and forget the last error
+    pub fn reset_stats(&mut self) {
+        self.device.stats = NetStats::default();
+    }
+
+    /// Put the driver in interrupt-driven receive mode
+    ///
+    /// Returns the IRQ line the platform must route to
+    /// `drivers::interrupts::handle_virtio_net_irq`.
+    pub fn enable_driver_interrupts(&mut self) -> Result<u8, NetError> {
+        self.device.driver.enable_interrupts()
+    }
+
+    /// Go back to polling the driver
+    pub fn disable_driver_interrupts(&mut self) {
+        self.device.driver.disable_interrupts();
+    }
+
+    /// Counters, for the socket helpers to update
+    pub(crate) fn stats_mut(&mut self) -> &mut NetStats {
+        &mut self.device.stats
+    }
+
     /// Get a reference to the interface
     pub fn interface(&self) -> &Interface {
         &self.iface
@@ -262,42 +561,49 @@ impl NetworkStack {
             return Ok(());
         }
 
-        // Create DHCP socket
-        let dhcp_socket = dhcp::create_socket();
+        // Create DHCP socket; keeping the raw ACK gives us the lease timers
+        let mut dhcp_socket = dhcp::create_socket();
+        dhcp_socket.set_receive_packet_buffer(Box::leak(vec![0u8; 1500].into_boxed_slice()));
 
         // Add socket to the socket set
         let dhcp_handle = self.sockets.add(dhcp_socket);
         self.dhcp_handle = Some(dhcp_handle);
+        self.set_dhcp_state(DhcpState::Discovering);
 
         Ok(())
     }
 
+    /// Call `callback` whenever the DHCP state changes
+    ///
+    /// Reports discovery, acquisition, renewal (T1), rebinding (T2) and loss
+    /// of the lease, e.g. to update a connection indicator.
+    pub fn set_dhcp_state_callback(&mut self, callback: fn(DhcpState)) {
+        self.dhcp_state_callback = Some(callback);
+    }
+
+    /// Timers of the current DHCP lease, e.g. to show the time remaining
+    pub fn dhcp_lease_info(&self) -> Option<DhcpLease> {
+        self.dhcp_lease
+    }
+
     /// Get the current DHCP state
     ///
     /// # Returns
     /// * `Some(DhcpState)` - Current DHCP state if DHCP is running
     /// * `None` - DHCP is not running
-    pub fn dhcp_state(&mut self) -> Option<DhcpState> {
-        if let Some(handle) = self.dhcp_handle {
-            let socket = self.sockets.get_mut::<DhcpSocket>(handle);
-            Some(dhcp::socket_to_state(socket))
-        } else {
-            None
-        }
+    pub fn dhcp_state(&self) -> Option<DhcpState> {
+        self.dhcp_handle.map(|_| self.dhcp_state)
     }
 
     /// Get the current DHCP configuration
     ///
+    /// `poll()` applies it to the interface as soon as it is acquired.
+    ///
     /// # Returns
-    /// * `Some(IpConfig)` - IP configuration if DHCP has acquired one
-    /// * `None` - No configuration available yet
-    pub fn dhcp_config(&mut self) -> Option<IpConfig> {
-        if let Some(handle) = self.dhcp_handle {
-            let socket = self.sockets.get_mut::<DhcpSocket>(handle);
-            dhcp::extract_config(socket)
-        } else {
-            None
-        }
+    /// * `Some(IpConfig)` - IP configuration if DHCP holds a lease
+    /// * `None` - No lease yet, or it was lost
+    pub fn dhcp_config(&self) -> Option<IpConfig> {
+        self.dhcp_config.clone()
     }
 
     /// Acquire IP configuration from DHCP (blocking with timeout)
@@ -307,6 +613,11 @@ impl NetworkStack {
     /// 2. Polls until configuration is acquired (with timeout)
     /// 3. Applies the configuration to the interface
     ///
+    /// On timeout a link-local address is claimed (see
+    /// [`NetworkStack::link_local_configure`]) unless that fallback is off;
+    /// DHCP keeps running and a later lease replaces it. The timeout is still
+    /// returned as an error, and `network_status()` tells the two apart.
+    ///
     /// **Note**: This method blocks until DHCP completes or timeout occurs.
     /// The caller must provide a time source and optionally a sleep function
     /// to avoid busy-waiting. For non-blocking operation, use start_dhcp() +
@@ -367,18 +678,26 @@ impl NetworkStack {
             // Poll the network stack with current timestamp
             self.poll(current_time)?;
 
-            // Check if we have configuration
+            // Check if we have configuration (poll() has applied it)
             if let Some(config) = self.dhcp_config() {
-                // Apply the configuration
-                self.apply_dhcp_config(&config)?;
                 return Ok(config);
             }
 
             // Check for timeout
             if current_time - start_time > timeout_ms {
-                return Err(NetError::DhcpTimeout(
-                    "DHCP configuration not acquired within timeout".into(),
-                ));
+                let mut message = String::from("DHCP configuration not acquired within timeout");
+                if self.link_local_fallback && self.link_local.is_none() {
+                    if let Ok(ip) = self.link_local_configure(&mut get_time_ms, sleep_ms.as_mut()) {
+                        if self.link_local == Some(ip) {
+                            message.push_str(&format!("; using link-local address {ip}"));
+                        }
+                    }
+                }
+                if let Some(config) = self.dhcp_config() {
+                    // The lease arrived while probing
+                    return Ok(config);
+                }
+                return Err(NetError::DhcpTimeout(message));
             }
 
             // Sleep/yield to avoid 100% CPU usage
@@ -405,6 +724,19 @@ impl NetworkStack {
     /// * `Ok(())` - Configuration applied successfully
     /// * `Err(NetError)` - Failed to apply configuration
     pub fn apply_dhcp_config(&mut self, config: &IpConfig) -> Result<(), NetError> {
+        // Answers obtained on the previous network may not apply here
+        self.dns_cache.clear();
+        self.link_local = None;
+
+        // Resolvers from DHCP take precedence over configured ones
+        let mut dns_servers: Vec<Ipv4Address> = Vec::new();
+        for server in config.dns.iter().chain(&self.dns_servers) {
+            if !dns_servers.contains(server) {
+                dns_servers.push(*server);
+            }
+        }
+        self.dns_servers = dns_servers;
+
         // Update IP address
         let mut ip_failed = false;
         self.iface.update_ip_addrs(|ip_addrs| {
@@ -446,12 +778,341 @@ impl NetworkStack {
         if let Some(handle) = self.dhcp_handle.take() {
             self.sockets.remove(handle);
         }
+        self.dhcp_config = None;
+        self.dhcp_lease = None;
+        self.dhcp_state = DhcpState::Init;
+    }
+
+    /// Configure the interface from a static configuration, without DHCP
+    ///
+    /// Stops DHCP if it is running, then sets the address, the default route
+    /// (when `config.gateway` is set) and puts `config.dns` in front of the
+    /// configured DNS servers.
+    pub fn apply_static_config(&mut self, config: &IpConfig) -> Result<(), NetError> {
+        self.stop_dhcp();
+        self.apply_dhcp_config(config)
+    }
+
+    /// Claim a link-local (169.254.x.y/16) address, RFC 3927 style
+    ///
+    /// Candidates derived from the MAC address are ARP-probed in turn; the
+    /// first one nobody answers for is configured without a default route,
+    /// so only hosts on the link are reachable, and announced. DHCP, if
+    /// running, carries on and a lease replaces the address. Blocks for about
+    /// `PROBE_NUM + 1` probe intervals per candidate.
+    ///
+    /// Returns the lease's address instead if DHCP succeeds while probing.
+    pub fn link_local_configure<F, S>(
+        &mut self,
+        mut get_time_ms: F,
+        mut sleep_ms: Option<S>,
+    ) -> Result<Ipv4Address, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        let mac = self.mac_address();
+        for attempt in 0..linklocal::MAX_CONFLICTS {
+            let candidate = linklocal::candidate_address(&mac, attempt);
+            self.device.arp_watch = Some(candidate);
+            self.device.arp_conflict = false;
+
+            for probe in 0..=linklocal::PROBE_NUM {
+                if probe < linklocal::PROBE_NUM {
+                    self.send_frame(&linklocal::build_probe(&mac, candidate));
+                }
+                let deadline = get_time_ms() + linklocal::PROBE_INTERVAL_MS;
+                loop {
+                    let now = get_time_ms();
+                    self.poll(now)?;
+                    if self.device.arp_conflict || self.dhcp_config.is_some() || now >= deadline {
+                        break;
+                    }
+                    match sleep_ms {
+                        Some(ref mut sleep_fn) => sleep_fn(10),
+                        None => core::sync::atomic::compiler_fence(Ordering::SeqCst),
+                    }
+                }
+                if self.device.arp_conflict || self.dhcp_config.is_some() {
+                    break;
+                }
+            }
+            self.device.arp_watch = None;
+
+            if let Some(config) = &self.dhcp_config {
+                return Ok(config.ip);
+            }
+            if self.device.arp_conflict {
+                continue;
+            }
+
+            self.apply_dhcp_config(&IpConfig::new(candidate, linklocal::LINK_LOCAL_PREFIX_LEN))?;
+            self.iface.routes_mut().remove_default_ipv4_route();
+            self.link_local = Some(candidate);
+            for _ in 0..linklocal::ANNOUNCE_NUM {
+                self.send_frame(&linklocal::build_announcement(&mac, candidate));
+            }
+            return Ok(candidate);
+        }
+        Err(NetError::LinkLocalConflict {
+            attempts: linklocal::MAX_CONFLICTS,
+        })
+    }
+
+    /// Send a frame built outside smoltcp straight to the driver
+    fn send_frame(&mut self, frame: &[u8]) {
+        let sent = self.device.driver.send(frame).is_ok();
+        self.device.stats.record_tx(frame.len(), sent);
+    }
+
+    /// Whether `dhcp_acquire` claims a link-local address on timeout (default on)
+    pub fn set_link_local_fallback(&mut self, enabled: bool) {
+        self.link_local_fallback = enabled;
+    }
+
+    /// The link-local address in use, if DHCP failed and one was claimed
+    pub fn link_local_address(&self) -> Option<Ipv4Address> {
+        self.link_local
+    }
+
+    /// How far the interface's addressing gets us, for status displays
+    pub fn network_status(&self) -> NetworkStatus {
+        if !self.is_link_up() {
+            NetworkStatus::NoLink
+        } else if self.link_local.is_some() {
+            NetworkStatus::LinkLocalOnly
+        } else if self
+            .iface
+            .ipv4_addr()
+            .is_some_and(|ip| !ip.is_unspecified())
+        {
+            NetworkStatus::Online
+        } else {
+            NetworkStatus::Unconfigured
+        }
+    }
+
+    /// Set the DNS servers tried by `resolve`, in order
+    ///
+    /// Servers later learned from DHCP are put in front of these.
+    pub fn set_dns_servers(&mut self, servers: &[Ipv4Address]) {
+        self.dns_servers = servers.to_vec();
+    }
+
+    /// The DNS servers tried by `resolve`, in order
+    pub fn dns_servers(&self) -> &[Ipv4Address] {
+        &self.dns_servers
+    }
+
+    /// Send HTTP requests through `proxy` (or directly with `None`)
+    ///
+    /// Applies to every `HttpClient` on this stack that wasn't given its own
+    /// proxy with `HttpClient::with_proxy`.
+    pub fn set_http_proxy(&mut self, proxy: Option<HttpProxy>) {
+        self.http_proxy = proxy;
+    }
+
+    /// The proxy HTTP requests go through, if any
+    pub fn http_proxy(&self) -> Option<&HttpProxy> {
+        self.http_proxy.as_ref()
+    }
+
+    /// Resolve `.local` names with multicast DNS (the default)
+    ///
+    /// Turn this off on networks that block multicast; `.local` names then
+    /// go to the unicast DNS servers like any other name.
+    pub fn set_mdns_enabled(&mut self, enabled: bool) {
+        self.mdns_enabled = enabled;
+    }
+
+    /// Whether `.local` names are resolved with multicast DNS
+    pub fn mdns_enabled(&self) -> bool {
+        self.mdns_enabled
+    }
+
+    /// Receive datagrams sent to the IPv4 multicast `group`
+    ///
+    /// Announces the membership with an IGMP report, so switches that snoop
+    /// IGMP forward the group's traffic to this host.
+    pub fn join_multicast_group(
+        &mut self,
+        group: Ipv4Address,
+        timestamp_ms: i64,
+    ) -> Result<(), NetError> {
+        self.iface
+            .join_multicast_group(&mut self.device, group, Instant::from_millis(timestamp_ms))
+            .map(|_| ())
+            .map_err(|error| NetError::SmoltcpError(format!("Failed to join {group}: {error:?}")))
+    }
+
+    /// Stop receiving `group`'s datagrams, sending an IGMP leave
+    pub fn leave_multicast_group(&mut self, group: Ipv4Address, timestamp_ms: i64) {
+        let _ = self.iface.leave_multicast_group(
+            &mut self.device,
+            group,
+            Instant::from_millis(timestamp_ms),
+        );
+    }
+
+    /// Forget all cached DNS answers
+    ///
+    /// Useful after the network configuration (and so the DNS server) changes.
+    pub fn clear_dns_cache(&mut self) {
+        self.dns_cache.clear();
+    }
+
+    /// Forget all cached DNS answers (same as `clear_dns_cache`)
+    pub fn flush_dns_cache(&mut self) {
+        self.clear_dns_cache();
+    }
+
+    /// DNS cache hit/miss counters, e.g. for a debug overlay
+    pub fn dns_cache_stats(&self) -> DnsCacheStats {
+        self.dns_cache.stats()
+    }
+
+    /// Open a UDP socket with default buffers
+    ///
+    /// Binds to `local_port`, or to a free port from the ephemeral range
+    /// (32768-49151) if it is 0.
+    ///
+    /// # Returns
+    /// * `Ok(UdpEndpointHandle)` - Socket to use with `udp_send_to`/`udp_recv_from`
+    /// * `Err(NetError::UdpPortInUse)` - Another UDP socket has `local_port`
+    pub fn udp_open(&mut self, local_port: u16) -> Result<UdpEndpointHandle, NetError> {
+        self.udp_open_with_buffers(local_port, UdpBufferSizes::default())
+    }
+
+    /// Open a UDP socket with room for `buffers` in each direction
+    ///
+    /// See [`Self::udp_open`] for how `local_port` is chosen.
+    pub fn udp_open_with_buffers(
+        &mut self,
+        local_port: u16,
+        buffers: UdpBufferSizes,
+    ) -> Result<UdpEndpointHandle, NetError> {
+        let local_port = if local_port == 0 {
+            self.free_udp_port()?
+        } else if self.udp_port_in_use(local_port) {
+            return Err(NetError::UdpPortInUse(local_port));
+        } else {
+            local_port
+        };
+
+        let packet_buffer = || {
+            udp::PacketBuffer::new(
+                vec![PacketMetadata::EMPTY; buffers.packets],
+                vec![0u8; buffers.bytes],
+            )
+        };
+        let mut udp_socket = UdpSocket::new(packet_buffer(), packet_buffer());
+        if let Err(error) = udp_socket.bind(local_port) {
+            return Err(NetError::UdpError(format!(
+                "Failed to bind port {local_port}: {error}"
+            )));
+        }
+        let handle = self.sockets.add(udp_socket);
+        Ok(UdpEndpointHandle { handle, local_port })
+    }
+
+    /// Queue a datagram for `remote:port`
+    ///
+    /// It goes out on the next [`Self::poll`].
+    ///
+    /// # Returns
+    /// * `Err(NetError::UdpSendBufferFull)` - Poll and try again
+    pub fn udp_send_to(
+        &mut self,
+        endpoint: UdpEndpointHandle,
+        data: &[u8],
+        remote: Ipv4Address,
+        port: u16,
+    ) -> Result<(), NetError> {
+        let udp_socket = self.udp_socket(endpoint)?;
+        let remote = IpEndpoint::new(IpAddress::Ipv4(remote), port);
+        udp_socket
+            .send_slice(data, remote)
+            .map_err(|error| match error {
+                udp::SendError::BufferFull => NetError::UdpSendBufferFull,
+                udp::SendError::Unaddressable => {
+                    NetError::UdpError(format!("Cannot send to {remote}"))
+                }
+            })
+    }
+
+    /// Take the next received datagram, copying it into `buf`
+    ///
+    /// Datagrams from IPv6 senders are dropped.
+    ///
+    /// # Returns
+    /// * `Ok(Some((len, sender, port)))` - A datagram of `len` bytes
+    /// * `Ok(None)` - Nothing received yet
+    /// * `Err(NetError::BufferTooSmall)` - The datagram didn't fit and was dropped
+    pub fn udp_recv_from(
+        &mut self,
+        endpoint: UdpEndpointHandle,
+        buf: &mut [u8],
+    ) -> Result<Option<(usize, Ipv4Address, u16)>, NetError> {
+        let udp_socket = self.udp_socket(endpoint)?;
+        loop {
+            match udp_socket.recv_slice(buf) {
+                Ok((len, meta)) => match meta.endpoint.addr {
+                    IpAddress::Ipv4(sender) => return Ok(Some((len, sender, meta.endpoint.port))),
+                    IpAddress::Ipv6(_) => continue,
+                },
+                Err(udp::RecvError::Exhausted) => return Ok(None),
+                Err(udp::RecvError::Truncated) => return Err(NetError::BufferTooSmall),
+            }
+        }
+    }
+
+    /// Close a UDP socket, freeing its port and buffers
+    ///
+    /// Closing a handle twice does nothing.
+    pub fn udp_close(&mut self, endpoint: UdpEndpointHandle) {
+        if self.udp_socket(endpoint).is_ok() {
+            self.sockets.remove(endpoint.handle);
+        }
+    }
+
+    /// The smoltcp socket behind `endpoint`, if it is still open
+    fn udp_socket(
+        &mut self,
+        endpoint: UdpEndpointHandle,
+    ) -> Result<&mut UdpSocket<'static>, NetError> {
+        self.sockets
+            .iter_mut()
+            .find_map(|(handle, socket)| match socket {
+                Socket::Udp(udp_socket) if handle == endpoint.handle => Some(udp_socket),
+                _ => None,
+            })
+            .ok_or(NetError::UdpSocketNotFound)
+    }
+
+    fn udp_port_in_use(&self, port: u16) -> bool {
+        self.sockets.iter().any(|(_, socket)| {
+            matches!(socket, Socket::Udp(udp_socket) if udp_socket.endpoint().port == port)
+        })
+    }
+
+    /// A free port from the UDP ephemeral range
+    fn free_udp_port(&self) -> Result<u16, NetError> {
+        for _ in UDP_EPHEMERAL_PORT_START..=UDP_EPHEMERAL_PORT_END {
+            let port = next_udp_ephemeral_port();
+            if !self.udp_port_in_use(port) {
+                return Ok(port);
+            }
+        }
+        Err(NetError::UdpError("No free ephemeral port".into()))
     }
 
     /// Resolve a hostname to an IPv4 address using DNS
     ///
     /// This method creates a UDP socket, sends a DNS query to the specified
-    /// DNS server, and waits for a response.
+    /// DNS server, and waits for a response. An unanswered query is sent
+    /// again every [`dns::DNS_RETRANSMIT_MS`] with a fresh transaction ID.
+    /// Answers are cached for their TTL, so repeated lookups of the same host
+    /// return without a query.
     ///
     /// **Note**: This method blocks until DNS resolution completes or timeout occurs.
     /// The caller must provide a time source and optionally a sleep function
@@ -491,6 +1152,35 @@ impl NetworkStack {
         hostname: &str,
         dns_server: Ipv4Address,
         timeout_ms: i64,
+        get_time_ms: F,
+        sleep_ms: Option<S>,
+    ) -> Result<Ipv4Address, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        self.dns_resolve_multi(hostname, &[dns_server], timeout_ms, get_time_ms, sleep_ms)
+    }
+
+    /// Resolve a hostname, trying each DNS server in turn
+    ///
+    /// A server that times out or reports a server failure is skipped in
+    /// favor of the next one; any other answer (including "no such name")
+    /// is final. `timeout_ms` applies to each server separately.
+    ///
+    /// `.local` names are asked of the link with multicast DNS instead,
+    /// waiting at most [`dns::MDNS_TIMEOUT_MS`], unless that is turned off
+    /// with [`Self::set_mdns_enabled`].
+    ///
+    /// # Returns
+    /// * `Ok(Ipv4Address)` - The first successful answer
+    /// * `Err(NetError)` - The error from the last server tried; a timeout
+    ///   counts the queries sent to every server that was tried
+    pub fn dns_resolve_multi<F, S>(
+        &mut self,
+        hostname: &str,
+        servers: &[Ipv4Address],
+        timeout_ms: i64,
         mut get_time_ms: F,
         mut sleep_ms: Option<S>,
     ) -> Result<Ipv4Address, NetError>
@@ -498,33 +1188,92 @@ impl NetworkStack {
         F: FnMut() -> i64,
         S: FnMut(i64),
     {
-        // Generate a transaction ID (use current time as pseudo-random)
-        let transaction_id = (get_time_ms() & 0xFFFF) as u16;
-
-        // Build DNS query packet
-        let query = dns::build_query(hostname, transaction_id);
-
-        // Create UDP socket with buffers
-        let rx_buffer =
-            udp::PacketBuffer::new(Vec::from([PacketMetadata::EMPTY; 4]), vec![0u8; 1024]);
-        let tx_buffer =
-            udp::PacketBuffer::new(Vec::from([PacketMetadata::EMPTY; 4]), vec![0u8; 1024]);
-
-        let mut udp_socket = UdpSocket::new(rx_buffer, tx_buffer);
+        if let Some(ip) = self.dns_cache.get(hostname, get_time_ms()) {
+            return Ok(Ipv4Address::from_bytes(&ip));
+        }
+        if self.mdns_enabled && dns::is_mdns_name(hostname) {
+            let timeout_ms = timeout_ms.min(dns::MDNS_TIMEOUT_MS);
+            let result = self.mdns_query(hostname, timeout_ms, &mut get_time_ms, sleep_ms.as_mut());
+            if let Err(error) = &result {
+                self.device.stats.record_error(error);
+            }
+            return result;
+        }
 
-        // Bind to an ephemeral port (use transaction_id as source port for simplicity)
-        let local_port = 49152 + (transaction_id % 16384);
-        let bind_endpoint = IpEndpoint::new(IpAddress::Ipv4(Ipv4Address::UNSPECIFIED), local_port);
+        let mut last_error = NetError::DnsError("No DNS servers configured".into());
+        let mut total_attempts = 0;
+        for (tried, &server) in servers.iter().enumerate() {
+            match self.dns_query(hostname, server, timeout_ms, &mut get_time_ms, sleep_ms.as_mut()) {
+                Ok(ip) => return Ok(ip),
+                Err(NetError::DnsTimeout { attempts, .. }) => {
+                    total_attempts += attempts;
+                    last_error = NetError::DnsTimeout {
+                        attempts: total_attempts,
+                        servers: tried as u32 + 1,
+                    };
+                }
+                Err(error @ NetError::DnsServerFailure) => {
+                    last_error = error;
+                }
+                Err(error) => {
+                    self.device.stats.record_error(&error);
+                    return Err(error);
+                }
+            }
+        }
+        self.device.stats.record_error(&last_error);
+        Err(last_error)
+    }
 
-        if udp_socket.bind(bind_endpoint).is_err() {
-            return Err(NetError::DnsError("Failed to bind UDP socket".into()));
+    /// Resolve a hostname using the stack's DNS servers, then `fallback`,
+    /// then [`PUBLIC_DNS_SERVERS`]
+    pub fn resolve<F, S>(
+        &mut self,
+        hostname: &str,
+        fallback: Ipv4Address,
+        timeout_ms: i64,
+        get_time_ms: F,
+        sleep_ms: Option<S>,
+    ) -> Result<Ipv4Address, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        let mut servers = self.dns_servers.clone();
+        for server in core::iter::once(fallback).chain(PUBLIC_DNS_SERVERS) {
+            if !servers.contains(&server) {
+                servers.push(server);
+            }
         }
+        self.dns_resolve_multi(hostname, &servers, timeout_ms, get_time_ms, sleep_ms)
+    }
 
-        // Add socket to socket set
-        let udp_handle = self.sockets.add(udp_socket);
+    /// Send an A query to `dns_server` and wait for its answer, resending it
+    /// every [`dns::DNS_RETRANSMIT_MS`] until `timeout_ms` runs out
+    fn dns_query<F, S>(
+        &mut self,
+        hostname: &str,
+        dns_server: Ipv4Address,
+        timeout_ms: i64,
+        get_time_ms: &mut F,
+        mut sleep_ms: Option<&mut S>,
+    ) -> Result<Ipv4Address, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        // Generate a transaction ID (use current time as pseudo-random)
+        let mut transaction_id = (get_time_ms() & 0xFFFF) as u16;
+        // IDs of every query sent; a late answer to any of them is accepted
+        let mut sent_ids: Vec<u16> = Vec::new();
+
+        let udp = self
+            .udp_open_with_buffers(0, DNS_UDP_BUFFERS)
+            .map_err(|_| NetError::DnsError("Failed to bind UDP socket".into()))?;
+        let mut datagram = vec![0u8; DNS_UDP_BUFFERS.bytes];
 
         let start_time = get_time_ms();
-        let mut query_sent = false;
+        let mut last_sent: Option<i64> = None;
 
         // DNS resolution loop
         let result = loop {
@@ -533,17 +1282,25 @@ impl NetworkStack {
             // Poll the network stack
             self.poll(current_time)?;
 
-            // Get the UDP socket
-            let udp_socket = self.sockets.get_mut::<UdpSocket>(udp_handle);
-
-            // Send DNS query if not sent yet
-            if !query_sent && udp_socket.can_send() {
-                let dns_endpoint = IpEndpoint::new(IpAddress::Ipv4(dns_server), 53);
-
-                match udp_socket.send_slice(&query, dns_endpoint) {
+            // Send the query, or resend it under a fresh ID if it went unanswered
+            let due = last_sent.is_none_or(|sent| current_time - sent >= dns::DNS_RETRANSMIT_MS);
+            if due {
+                let id = if last_sent.is_some() {
+                    dns::next_transaction_id(transaction_id)
+                } else {
+                    transaction_id
+                };
+                let query = dns::build_query(hostname, id);
+
+                match self.udp_send_to(udp, &query, dns_server, 53) {
                     Ok(()) => {
-                        query_sent = true;
+                        transaction_id = id;
+                        sent_ids.push(transaction_id);
+                        last_sent = Some(current_time);
+                        self.device.stats.dns_queries += 1;
                     }
+                    // Still queued from the last attempt; try again after polling
+                    Err(NetError::UdpSendBufferFull) => {}
                     Err(_) => {
                         break Err(NetError::DnsError("Failed to send DNS query".into()));
                     }
@@ -551,58 +1308,25 @@ impl NetworkStack {
             }
 
             // Check for DNS response
-            if query_sent && udp_socket.can_recv() {
-                match udp_socket.recv() {
-                    Ok((data, _endpoint)) => {
+            if last_sent.is_some() {
+                match self.udp_recv_from(udp, &mut datagram) {
+                    Ok(Some((len, _server, _port))) => {
                         // Parse DNS response
-                        match DnsResponse::from_bytes(data) {
+                        match DnsResponse::from_bytes(&datagram[..len]) {
                             Ok(response) => {
-                                // Verify transaction ID matches
-                                if response.header.id != transaction_id {
+                                // Verify the answer belongs to one of our queries
+                                if !sent_ids.contains(&response.header.id) {
                                     // Wrong transaction ID, continue waiting
                                     continue;
                                 }
-
-                                // Check response code
-                                let rcode = response.header.rcode();
-                                if let Some(response_code) = ResponseCode::from_u8(rcode) {
-                                    match response_code {
-                                        ResponseCode::NoError => {
-                                            // Extract IP address from response
-                                            if let Some(ip_bytes) = response.first_ipv4() {
-                                                let ip = Ipv4Address::from_bytes(&ip_bytes);
-                                                break Ok(ip);
-                                            } else {
-                                                break Err(NetError::DnsError(
-                                                    "No A record in response".into(),
-                                                ));
-                                            }
-                                        }
-                                        ResponseCode::NameError => {
-                                            break Err(NetError::DnsNameNotFound);
-                                        }
-                                        ResponseCode::ServerFailure => {
-                                            break Err(NetError::DnsServerFailure);
-                                        }
-                                        _ => {
-                                            break Err(NetError::DnsError(format!(
-                                                "DNS error code: {:?}",
-                                                response_code
-                                            )));
-                                        }
-                                    }
-                                } else {
-                                    break Err(NetError::DnsMalformedResponse(
-                                        "Invalid response code".into(),
-                                    ));
-                                }
+                                break Ok(response);
                             }
                             Err(e) => {
                                 break Err(NetError::DnsMalformedResponse(e.into()));
                             }
                         }
                     }
-                    Err(_) => {
+                    Ok(None) | Err(_) => {
                         // No data available yet, continue
                     }
                 }
@@ -610,7 +1334,10 @@ impl NetworkStack {
 
             // Check for timeout
             if current_time - start_time > timeout_ms {
-                break Err(NetError::DnsTimeout);
+                break Err(NetError::DnsTimeout {
+                    attempts: sent_ids.len() as u32,
+                    servers: 1,
+                });
             }
 
             // Sleep/yield to avoid 100% CPU usage
@@ -622,11 +1349,413 @@ impl NetworkStack {
             }
         };
 
-        // Clean up: remove UDP socket from socket set
-        self.sockets.remove(udp_handle);
+        self.udp_close(udp);
+
+        let mut response = result?;
+        if response.header.is_truncated() {
+            // The full answer did not fit in a datagram; ask again over TCP
+            response = self.dns_query_tcp(
+                hostname,
+                dns_server,
+                transaction_id,
+                timeout_ms,
+                get_time_ms,
+                sleep_ms,
+            )?;
+        }
+        self.answer_from_response(hostname, &response, get_time_ms())
+    }
+
+    /// Ask the link for `hostname`'s address with multicast DNS
+    ///
+    /// Listens on the mDNS port when no other socket has it and joins the
+    /// mDNS group while waiting, so both unicast and multicast answers are
+    /// seen. The query is repeated every [`dns::MDNS_RETRANSMIT_MS`]; the
+    /// first A record for `hostname` wins.
+    fn mdns_query<F, S>(
+        &mut self,
+        hostname: &str,
+        timeout_ms: i64,
+        get_time_ms: &mut F,
+        mut sleep_ms: Option<&mut S>,
+    ) -> Result<Ipv4Address, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        let group = Ipv4Address::from_bytes(&dns::MDNS_GROUP);
+        let udp = match self.udp_open_with_buffers(dns::MDNS_PORT, DNS_UDP_BUFFERS) {
+            Ok(udp) => udp,
+            // Some other mDNS user has the port; answers to our unicast
+            // request still reach an ephemeral one
+            Err(NetError::UdpPortInUse(_)) => self.udp_open_with_buffers(0, DNS_UDP_BUFFERS)?,
+            Err(error) => return Err(error),
+        };
+        let start_time = get_time_ms();
+        // Without the group only unicast answers arrive, which is usually enough
+        let joined = self.join_multicast_group(group, start_time).is_ok();
+
+        let query = dns::build_mdns_query(hostname);
+        let mut datagram = vec![0u8; DNS_UDP_BUFFERS.bytes];
+        let mut attempts = 0;
+        let mut last_sent: Option<i64> = None;
+        let result = loop {
+            let current_time = get_time_ms();
+            if let Err(error) = self.poll(current_time) {
+                break Err(error);
+            }
+
+            let due = last_sent.is_none_or(|sent| current_time - sent >= dns::MDNS_RETRANSMIT_MS);
+            if due {
+                match self.udp_send_to(udp, &query, group, dns::MDNS_PORT) {
+                    Ok(()) => {
+                        attempts += 1;
+                        last_sent = Some(current_time);
+                        self.device.stats.dns_queries += 1;
+                    }
+                    Err(NetError::UdpSendBufferFull) => {}
+                    Err(_) => break Err(NetError::DnsError("Failed to send mDNS query".into())),
+                }
+            }
+
+            // Other hosts' queries and answers arrive too; skip those
+            if let Ok(Some((len, _, _))) = self.udp_recv_from(udp, &mut datagram) {
+                let answer = DnsResponse::from_bytes(&datagram[..len])
+                    .ok()
+                    .and_then(|response| response.ipv4_for(hostname));
+                if let Some((ip, ttl)) = answer {
+                    self.dns_cache.insert(hostname, ip, ttl, current_time);
+                    break Ok(Ipv4Address::from_bytes(&ip));
+                }
+                continue;
+            }
+
+            if current_time - start_time > timeout_ms {
+                break Err(NetError::DnsTimeout {
+                    attempts,
+                    servers: 1,
+                });
+            }
+
+            if let Some(ref mut sleep_fn) = sleep_ms {
+                sleep_fn(10);
+            } else {
+                core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+            }
+        };
+
+        self.udp_close(udp);
+        if joined {
+            self.leave_multicast_group(group, get_time_ms());
+        }
+        result
+    }
+
+    /// Send an A query to `dns_server` over TCP and wait for the whole answer
+    ///
+    /// Used when the UDP answer came back truncated, e.g. for hosts with many
+    /// A records behind a CDN.
+    fn dns_query_tcp<F, S>(
+        &mut self,
+        hostname: &str,
+        dns_server: Ipv4Address,
+        transaction_id: u16,
+        timeout_ms: i64,
+        get_time_ms: &mut F,
+        mut sleep_ms: Option<&mut S>,
+    ) -> Result<DnsResponse, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        let query = dns::frame_tcp_message(&dns::build_query(hostname, transaction_id));
+        let mut tcp = TcpStream::connect(
+            self,
+            dns_server,
+            53,
+            timeout_ms,
+            get_time_ms,
+            sleep_ms.as_deref_mut(),
+        )?;
+
+        let mut stream = Vec::new();
+        let result = tcp
+            .write_all(self, &query, timeout_ms, get_time_ms, sleep_ms.as_deref_mut())
+            .map_err(dns_tcp_error)
+            .and_then(|()| loop {
+                if let Some(message) = dns::parse_tcp_frame(&stream) {
+                    break DnsResponse::from_bytes(message)
+                        .map_err(|e| NetError::DnsMalformedResponse(e.into()));
+                }
+                let mut buf = [0u8; 512];
+                match tcp.read(self, &mut buf, timeout_ms, get_time_ms, sleep_ms.as_deref_mut()) {
+                    Ok(0) => {
+                        break Err(NetError::DnsMalformedResponse(
+                            "TCP connection closed mid-answer".into(),
+                        ))
+                    }
+                    Ok(n) => stream.extend_from_slice(&buf[..n]),
+                    Err(error) => break Err(dns_tcp_error(error)),
+                }
+            });
+        tcp.close_in_background(self);
+
+        let response = result?;
+        if response.header.id != transaction_id {
+            return Err(NetError::DnsMalformedResponse(
+                "TCP answer does not match query".into(),
+            ));
+        }
+        Ok(response)
+    }
+
+    /// Turn a DNS answer into an address, caching it for its TTL
+    fn answer_from_response(
+        &mut self,
+        hostname: &str,
+        response: &DnsResponse,
+        now_ms: i64,
+    ) -> Result<Ipv4Address, NetError> {
+        let rcode = response.header.rcode();
+        let Some(response_code) = ResponseCode::from_u8(rcode) else {
+            return Err(NetError::DnsMalformedResponse(
+                "Invalid response code".into(),
+            ));
+        };
+        match response_code {
+            ResponseCode::NoError => {
+                // Extract IP address from response
+                let Some((ip_bytes, ttl)) = response.first_ipv4_with_ttl() else {
+                    return Err(NetError::DnsError("No A record in response".into()));
+                };
+                self.dns_cache.insert(hostname, ip_bytes, ttl, now_ms);
+                Ok(Ipv4Address::from_bytes(&ip_bytes))
+            }
+            ResponseCode::NameError => Err(NetError::DnsNameNotFound),
+            ResponseCode::ServerFailure => Err(NetError::DnsServerFailure),
+            _ => Err(NetError::DnsError(format!(
+                "DNS error code: {:?}",
+                response_code
+            ))),
+        }
+    }
+
+    /// Ask an SNTP server, by name or IPv4 address, for the current time
+    ///
+    /// `server` defaults to [`ntp::DEFAULT_NTP_SERVER`]. Names are looked up
+    /// with [`Self::resolve`]; the lookup and the query each get
+    /// `timeout_ms`. On success the offset is kept as by [`Self::ntp_sync`].
+    ///
+    /// # Returns
+    /// * `Ok(i64)` - Current time in milliseconds since the Unix epoch
+    /// * `Err(NetError)` - The name did not resolve or no usable reply came
+    pub fn sntp_query<F, S>(
+        &mut self,
+        server: Option<&str>,
+        timeout_ms: i64,
+        mut get_time_ms: F,
+        mut sleep_ms: Option<S>,
+    ) -> Result<i64, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        let server = server.unwrap_or(ntp::DEFAULT_NTP_SERVER);
+        let address = match server.parse::<Ipv4Address>() {
+            Ok(address) => address,
+            Err(()) => self.resolve(
+                server,
+                PUBLIC_DNS_SERVERS[0],
+                timeout_ms,
+                &mut get_time_ms,
+                sleep_ms.as_mut(),
+            )?,
+        };
+        let unix_ms = self.ntp_sync(address, timeout_ms, get_time_ms, sleep_ms)?;
+        Ok(unix_ms as i64)
+    }
+
+    /// Ask an NTP server for the time and remember the offset to local time
+    ///
+    /// Sends one SNTP request to `server` and waits up to `timeout_ms` for
+    /// the reply. The offset is measured against the midpoint of the round
+    /// trip, so [`Self::unix_time_ms`] is accurate to about half of it.
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - Current time in milliseconds since the Unix epoch
+    /// * `Err(NetError)` - No usable reply; the stored offset is unchanged
+    pub fn ntp_sync<F, S>(
+        &mut self,
+        server: Ipv4Address,
+        timeout_ms: i64,
+        mut get_time_ms: F,
+        mut sleep_ms: Option<S>,
+    ) -> Result<u64, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        let start_time = get_time_ms();
+        // Local time doubles as the nonce the server echoes back
+        let nonce = start_time as u64;
+        let request = ntp::build_request(nonce);
 
+        let udp = self
+            .udp_open_with_buffers(0, NTP_UDP_BUFFERS)
+            .map_err(|_| NetError::NtpError("Failed to bind UDP socket".into()))?;
+        let mut datagram = [0u8; 256];
+
+        let mut sent_at: Option<i64> = None;
+        let result = loop {
+            let current_time = get_time_ms();
+            if let Err(error) = self.poll(current_time) {
+                break Err(error);
+            }
+
+            if sent_at.is_none() {
+                match self.udp_send_to(udp, &request, server, ntp::NTP_PORT) {
+                    Ok(()) => sent_at = Some(current_time),
+                    Err(NetError::UdpSendBufferFull) => {}
+                    Err(_) => break Err(NetError::NtpError("Failed to send NTP request".into())),
+                }
+            }
+
+            if let (Some(sent), Ok(Some((len, _, _)))) =
+                (sent_at, self.udp_recv_from(udp, &mut datagram))
+            {
+                // Replies that fail to parse are ignored; the right one may follow
+                if let Ok(unix_ms) = ntp::parse_response(&datagram[..len], nonce) {
+                    let midpoint = sent + (current_time - sent) / 2;
+                    self.ntp_offset_ms = Some(unix_ms as i64 - midpoint);
+                    break Ok(unix_ms + (current_time - midpoint) as u64);
+                }
+            }
+
+            if current_time - start_time > timeout_ms {
+                break Err(NetError::NtpTimeout);
+            }
+
+            if let Some(ref mut sleep_fn) = sleep_ms {
+                sleep_fn(10);
+            } else {
+                core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+            }
+        };
+
+        self.udp_close(udp);
         result
     }
+
+    /// Send `count` ICMP echo requests to `target`, one at a time
+    ///
+    /// Each request waits up to `timeout_ms` for its reply before the next
+    /// one is sent; unanswered requests count as lost rather than failing
+    /// the run.
+    ///
+    /// # Returns
+    /// * `Ok(PingStats)` - Round-trip times and loss
+    /// * `Err(NetError)` - The echo requests could not be sent
+    pub fn ping<F, S>(
+        &mut self,
+        target: Ipv4Address,
+        count: u16,
+        timeout_ms: i64,
+        mut get_time_ms: F,
+        mut sleep_ms: Option<S>,
+    ) -> Result<PingStats, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        let start_time = get_time_ms();
+        let ident = start_time as u16;
+        let request_len = ping::echo_request_len();
+
+        let rx_buffer = icmp::PacketBuffer::new(
+            Vec::from([icmp::PacketMetadata::EMPTY; 4]),
+            vec![0u8; 512],
+        );
+        let tx_buffer = icmp::PacketBuffer::new(
+            Vec::from([icmp::PacketMetadata::EMPTY; 4]),
+            vec![0u8; 512],
+        );
+        let mut icmp_socket = IcmpSocket::new(rx_buffer, tx_buffer);
+        if icmp_socket.bind(icmp::Endpoint::Ident(ident)).is_err() {
+            return Err(NetError::PingError("Failed to bind ICMP socket".into()));
+        }
+        let icmp_handle = self.sockets.add(icmp_socket);
+
+        let mut stats = PingStats::default();
+        let mut seq_no: u16 = 0;
+        let mut sent_at: Option<i64> = None;
+        let result = loop {
+            let current_time = get_time_ms();
+            if let Err(error) = self.poll(current_time) {
+                break Err(error);
+            }
+
+            let icmp_socket = self.sockets.get_mut::<IcmpSocket>(icmp_handle);
+            match sent_at {
+                None if stats.transmitted == count => break Ok(()),
+                None => {
+                    if icmp_socket.can_send() {
+                        match icmp_socket.send(request_len, IpAddress::Ipv4(target)) {
+                            Ok(buffer) => ping::emit_echo_request(buffer, ident, seq_no),
+                            Err(_) => {
+                                break Err(NetError::PingError(
+                                    "Failed to send echo request".into(),
+                                ))
+                            }
+                        }
+                        stats.transmitted += 1;
+                        sent_at = Some(current_time);
+                    }
+                }
+                Some(sent) => {
+                    // Late replies to earlier requests are dropped here
+                    while let Ok((data, _)) = icmp_socket.recv() {
+                        if ping::parse_echo_reply(data, ident) == Some(seq_no) {
+                            stats.rtts_ms.push((current_time - sent) as u64);
+                            sent_at = None;
+                        }
+                    }
+                    if current_time - sent > timeout_ms {
+                        sent_at = None;
+                    }
+                    if sent_at.is_none() {
+                        seq_no = seq_no.wrapping_add(1);
+                    }
+                }
+            }
+
+            if let Some(ref mut sleep_fn) = sleep_ms {
+                sleep_fn(1);
+            } else {
+                core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+            }
+        };
+
+        self.sockets.remove(icmp_handle);
+        result.map(|()| stats)
+    }
+
+    /// Current Unix time in milliseconds, if an NTP server has answered
+    ///
+    /// `now_ms` is the local time on the same clock passed to `ntp_sync`.
+    pub fn unix_time_ms(&self, now_ms: i64) -> Option<u64> {
+        self.ntp_offset_ms.map(|offset| (now_ms + offset).max(0) as u64)
+    }
+}
+
+/// Map a failed DNS-over-TCP exchange to the error `dns_query` reports
+fn dns_tcp_error(error: NetError) -> NetError {
+    match error {
+        NetError::TcpTimeout => NetError::DnsTimeout {
+            attempts: 1,
+            servers: 1,
+        },
+        other => other,
+    }
 }
 
 /// Global network stack instance (protected by mutex)
@@ -671,3 +1800,268 @@ pub fn poll_network_stack(timestamp_ms: i64) -> Result<(), NetError> {
         Err(NetError::DeviceNotInitialized)
     }
 }
+
+/// Milliseconds until the global stack next needs polling
+///
+/// `None` if nothing is scheduled or the stack isn't initialized.
+pub fn network_poll_delay_ms(timestamp_ms: i64) -> Option<u64> {
+    NETWORK_STACK
+        .lock()
+        .as_mut()
+        .and_then(|stack| stack.poll_delay_ms(timestamp_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::loopback::LoopbackDriver;
+
+    #[test]
+    fn test_static_config_sets_address_and_route() {
+        let driver = LoopbackDriver::new([0x02, 0, 0, 0, 0, 0x01]);
+        let ip = Ipv4Address::new(192, 168, 1, 20);
+        let mut stack = NetworkStack::new(Box::new(driver), Some((ip, 24))).unwrap();
+        stack.set_dns_servers(&[Ipv4Address::new(8, 8, 8, 8)]);
+        stack.start_dhcp().unwrap();
+
+        let gateway = Ipv4Address::new(192, 168, 1, 1);
+        let mut config = IpConfig::new(ip, 24).with_gateway(gateway);
+        config.add_dns(Ipv4Address::new(1, 1, 1, 1));
+        stack.apply_static_config(&config).unwrap();
+
+        assert_eq!(
+            stack.interface().ip_addrs(),
+            [IpCidr::new(IpAddress::Ipv4(ip), 24)]
+        );
+        let mut has_default_route = false;
+        stack.interface_mut().routes_mut().update(|routes| {
+            has_default_route = routes.iter().any(|route| {
+                route.cidr == IpCidr::new(IpAddress::Ipv4(Ipv4Address::UNSPECIFIED), 0)
+                    && route.via_router == IpAddress::Ipv4(gateway)
+            });
+        });
+        assert!(has_default_route);
+        assert_eq!(
+            stack.dns_servers(),
+            [Ipv4Address::new(1, 1, 1, 1), Ipv4Address::new(8, 8, 8, 8)]
+        );
+        assert_eq!(stack.dhcp_state(), None);
+        assert!(stack.dhcp_config().is_none());
+    }
+
+    #[test]
+    fn test_static_dns_is_listed_once() {
+        let driver = LoopbackDriver::new([0x02, 0, 0, 0, 0, 0x01]);
+        let ip = Ipv4Address::new(192, 168, 1, 20);
+        let mut stack = NetworkStack::new(Box::new(driver), Some((ip, 24))).unwrap();
+        stack.set_dns_servers(&PUBLIC_DNS_SERVERS);
+
+        let mut config = IpConfig::new(ip, 24);
+        config.add_dns(Ipv4Address::new(9, 9, 9, 9));
+        config.add_dns(Ipv4Address::new(1, 1, 1, 1));
+        config.add_dns(Ipv4Address::new(9, 9, 9, 9));
+        stack.apply_static_config(&config).unwrap();
+        // Applying it again changes nothing
+        stack.apply_static_config(&config).unwrap();
+
+        assert_eq!(
+            stack.dns_servers(),
+            [
+                Ipv4Address::new(9, 9, 9, 9),
+                Ipv4Address::new(1, 1, 1, 1),
+                Ipv4Address::new(8, 8, 8, 8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_poll_delay_reflects_pending_work() {
+        let driver = LoopbackDriver::new([0x02, 0, 0, 0, 0, 0x01]);
+        let ip = Ipv4Address::new(192, 168, 1, 20);
+        let mut stack = NetworkStack::new(Box::new(driver), Some((ip, 24))).unwrap();
+        assert_eq!(stack.poll_delay_ms(0), None);
+
+        // A DHCP client wants to send its DISCOVER right away, then waits
+        stack.start_dhcp().unwrap();
+        assert_eq!(stack.poll_delay_ms(0), Some(0));
+        let _ = stack.poll(0);
+        let delay = stack.poll_delay_ms(0).expect("DHCP retry is scheduled");
+        assert!(delay > 0);
+        assert_eq!(stack.poll_delay_ms(delay as i64), Some(0));
+    }
+
+    #[test]
+    fn test_udp_round_trip_over_loopback() {
+        let driver = LoopbackDriver::new([0x02, 0, 0, 0, 0, 0x01]);
+        let ip = Ipv4Address::new(192, 168, 1, 20);
+        let mut stack = NetworkStack::new(Box::new(driver), Some((ip, 24))).unwrap();
+
+        let server = stack.udp_open(5353).unwrap();
+        assert_eq!(server.local_port(), 5353);
+        assert!(matches!(
+            stack.udp_open(5353),
+            Err(NetError::UdpPortInUse(5353))
+        ));
+        let client = stack
+            .udp_open_with_buffers(
+                0,
+                UdpBufferSizes {
+                    packets: 2,
+                    bytes: 64,
+                },
+            )
+            .unwrap();
+        assert!((UDP_EPHEMERAL_PORT_START..=UDP_EPHEMERAL_PORT_END).contains(&client.local_port()));
+
+        let mut buf = [0u8; 64];
+        assert!(matches!(stack.udp_recv_from(server, &mut buf), Ok(None)));
+        stack.udp_send_to(client, b"ping", ip, 5353).unwrap();
+        let mut received = None;
+        for now in 0..20 {
+            stack.poll(now).unwrap();
+            received = stack.udp_recv_from(server, &mut buf).unwrap();
+            if received.is_some() {
+                break;
+            }
+        }
+        assert_eq!(received, Some((4, ip, client.local_port())));
+        assert_eq!(&buf[..4], b"ping");
+
+        // The client's buffers hold 64 bytes, so a longer datagram can't be queued
+        assert!(matches!(
+            stack.udp_send_to(client, &[0u8; 65], ip, 5353),
+            Err(NetError::UdpSendBufferFull)
+        ));
+
+        stack.udp_close(server);
+        stack.udp_close(server);
+        assert!(matches!(
+            stack.udp_recv_from(server, &mut buf),
+            Err(NetError::UdpSocketNotFound)
+        ));
+        assert_eq!(stack.udp_open(5353).unwrap().local_port(), 5353);
+    }
+
+    #[test]
+    fn test_mdns_resolves_local_names_on_the_link() {
+        use crate::drivers::loopback::PairedDriver;
+        use core::cell::{Cell, RefCell};
+
+        let (client_end, responder_end) =
+            PairedDriver::pair([0x02, 0, 0, 0, 0, 0x01], [0x02, 0, 0, 0, 0, 0x02]);
+        let client_ip = Ipv4Address::new(192, 168, 1, 20);
+        let responder_ip = Ipv4Address::new(192, 168, 1, 50);
+        let mut client = NetworkStack::new(Box::new(client_end), Some((client_ip, 24))).unwrap();
+        let mut responder =
+            NetworkStack::new(Box::new(responder_end), Some((responder_ip, 24))).unwrap();
+        let group = Ipv4Address::from_bytes(&dns::MDNS_GROUP);
+        responder.join_multicast_group(group, 0).unwrap();
+        let listener = responder.udp_open(dns::MDNS_PORT).unwrap();
+
+        // Answer every query for ollama.local, by unicast as asked
+        let responder = RefCell::new(responder);
+        let queries = Cell::new(0);
+        let clock = Cell::new(0i64);
+        let mut sleep = |ms: i64| {
+            clock.set(clock.get() + ms);
+            let mut responder = responder.borrow_mut();
+            responder.poll(clock.get()).unwrap();
+            let mut buf = [0u8; 512];
+            while let Some((len, sender, port)) =
+                responder.udp_recv_from(listener, &mut buf).unwrap()
+            {
+                queries.set(queries.get() + 1);
+                let mut answer = buf[..len].to_vec();
+                answer[2..4].copy_from_slice(&0x8400u16.to_be_bytes());
+                answer[6..8].copy_from_slice(&1u16.to_be_bytes());
+                // Name pointer to the question, type A, class IN with cache flush, TTL 120
+                answer.extend_from_slice(&[0xC0, 0x0C, 0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4]);
+                answer.extend_from_slice(&responder_ip.0);
+                responder
+                    .udp_send_to(listener, &answer, sender, port)
+                    .unwrap();
+            }
+            responder.poll(clock.get()).unwrap();
+        };
+
+        let ip = client
+            .dns_resolve_multi("ollama.local", &[], 5000, || clock.get(), Some(&mut sleep))
+            .unwrap();
+        assert_eq!(ip, responder_ip);
+        assert!(queries.get() >= 1);
+        assert!(clock.get() < dns::MDNS_TIMEOUT_MS);
+
+        // With mDNS off the name goes to the (here missing) unicast servers
+        client.clear_dns_cache();
+        client.set_mdns_enabled(false);
+        let result =
+            client.dns_resolve_multi("ollama.local", &[], 5000, || clock.get(), Some(&mut sleep));
+        assert!(matches!(result, Err(NetError::DnsError(_))));
+    }
+
+    #[test]
+    fn test_link_local_fallback_after_dhcp_timeout() {
+        use crate::drivers::loopback::PairedDriver;
+        use core::cell::{Cell, RefCell};
+
+        let mac = [0x02, 0, 0, 0, 0, 0x01];
+        let peer_mac = [0x02, 0, 0, 0, 0, 0x02];
+        let (client_end, peer_end) = PairedDriver::pair(mac, peer_mac);
+        let mut client = NetworkStack::new(Box::new(client_end), None).unwrap();
+        assert_eq!(client.network_status(), NetworkStatus::Unconfigured);
+
+        // The peer already uses the first candidate and defends it
+        let taken = linklocal::candidate_address(&mac, 0);
+        let claimed = linklocal::candidate_address(&mac, 1);
+        let peer = RefCell::new(peer_end);
+        let announced = Cell::new(false);
+        let clock = Cell::new(0i64);
+        let mut sleep = |ms: i64| {
+            clock.set(clock.get() + ms);
+            let mut peer = peer.borrow_mut();
+            while let Some(frame) = peer.receive().unwrap() {
+                if linklocal::is_conflict(&frame, &peer_mac, taken) {
+                    peer.send(&linklocal::build_announcement(&peer_mac, taken))
+                        .unwrap();
+                }
+                if frame == linklocal::build_announcement(&mac, claimed) {
+                    announced.set(true);
+                }
+            }
+        };
+
+        // Nobody answers DHCP
+        let result = client.dhcp_acquire(1000, || clock.get(), Some(&mut sleep));
+        assert!(matches!(result, Err(NetError::DhcpTimeout(_))));
+        sleep(0);
+        assert_eq!(client.link_local_address(), Some(claimed));
+        assert_eq!(client.interface().ipv4_addr(), Some(claimed));
+        assert_eq!(client.network_status(), NetworkStatus::LinkLocalOnly);
+        assert!(announced.get());
+        assert_eq!(client.dhcp_state(), Some(DhcpState::Discovering));
+
+        // A later configuration replaces the link-local address
+        let config = IpConfig::new(Ipv4Address::new(192, 168, 1, 20), 24)
+            .with_gateway(Ipv4Address::new(192, 168, 1, 1));
+        client.apply_dhcp_config(&config).unwrap();
+        assert_eq!(client.link_local_address(), None);
+        assert_eq!(client.network_status(), NetworkStatus::Online);
+    }
+
+    #[test]
+    fn test_udp_ephemeral_ports_skip_bound_ones() {
+        let driver = LoopbackDriver::new([0x02, 0, 0, 0, 0, 0x01]);
+        let mut stack = NetworkStack::new(Box::new(driver), None).unwrap();
+        let first = stack.udp_open(0).unwrap();
+        // Bind the port the allocator hands out next, which must then be skipped
+        let mut taken = first.local_port() + 1;
+        if taken > UDP_EPHEMERAL_PORT_END {
+            taken = UDP_EPHEMERAL_PORT_START;
+        }
+        let _ = stack.udp_open(taken);
+        let second = stack.udp_open(0).unwrap();
+        assert_ne!(second.local_port(), first.local_port());
+        assert_ne!(second.local_port(), taken);
+        assert!(second.local_port() < 49152);
+    }
+}
@@ -0,0 +1,74 @@
+//! Network statistics counters
+//!
+//! Kept by `NetworkStack` so a user can tell whether packets are flowing
+//! at all when a provider seems unreachable.
+
+extern crate alloc;
+
+use crate::error::NetError;
+use alloc::string::{String, ToString};
+
+/// Counters since the stack was created or last reset
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetStats {
+    /// Frames handed to the driver
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+    /// Frames the driver refused to send
+    pub tx_errors: u64,
+    /// Frames received from the driver
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    /// Receive attempts the driver failed
+    pub rx_drops: u64,
+    /// DNS queries sent, retransmissions included
+    pub dns_queries: u64,
+    /// TCP connections established
+    pub tcp_connections: u64,
+    /// TLS handshakes completed
+    pub tls_handshakes: u64,
+    /// Most recent error reported by the stack
+    pub last_error: Option<String>,
+}
+
+impl NetStats {
+    pub(crate) fn record_tx(&mut self, len: usize, sent: bool) {
+        if sent {
+            self.tx_packets += 1;
+            self.tx_bytes += len as u64;
+        } else {
+            self.tx_errors += 1;
+        }
+    }
+
+    pub(crate) fn record_rx(&mut self, len: usize) {
+        self.rx_packets += 1;
+        self.rx_bytes += len as u64;
+    }
+
+    pub(crate) fn record_error(&mut self, error: &NetError) {
+        self.last_error = Some(error.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters() {
+        let mut stats = NetStats::default();
+        stats.record_tx(60, true);
+        stats.record_tx(1514, true);
+        stats.record_tx(60, false);
+        stats.record_rx(42);
+        stats.record_error(&NetError::DnsNameNotFound);
+
+        assert_eq!(
+            (stats.tx_packets, stats.tx_bytes, stats.tx_errors),
+            (2, 1574, 1)
+        );
+        assert_eq!((stats.rx_packets, stats.rx_bytes), (1, 42));
+        assert_eq!(stats.last_error.as_deref(), Some("DNS name not found"));
+    }
+}
@@ -0,0 +1,539 @@
+//! Blocking TCP connections on top of [`NetworkStack`]
+//!
+//! smoltcp sockets only make progress when the stack is polled, so every
+//! blocking call here takes the same `get_time_ms`/`sleep_ms` pair as the
+//! rest of the crate and polls until it is done or its timeout runs out.
+//! HTTP, TLS and DNS over TCP all sit on [`TcpStream`].
+//!
+//! Timeouts are in milliseconds on the `get_time_ms` clock and bound each call
+//! on its own: a `read` waits up to `timeout_ms` for the first byte and then
+//! returns what has arrived, a `write_all` gets `timeout_ms` to queue all of
+//! its data. Running out of time is [`NetError::TcpTimeout`] and leaves the
+//! connection usable.
+
+extern crate alloc;
+
+use crate::error::NetError;
+use crate::stack::NetworkStack;
+use core::sync::atomic::{AtomicU16, Ordering};
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::tcp::{self, Socket as TcpSocket, State as TcpState};
+use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address};
+
+/// Size of each socket's receive and transmit buffer, unless the caller
+/// picks one with [`TcpStream::connect_with_buffer_size`]
+pub const TCP_BUFFER_SIZE: usize = 8192;
+
+/// How long a socket closed with [`TcpStream::close_in_background`] may take
+/// to finish its close before it is reset
+pub const TCP_CLOSE_LINGER_MS: i64 = 10_000;
+
+/// Next local port for outgoing TCP connections
+static NEXT_EPHEMERAL_PORT: AtomicU16 = AtomicU16::new(EPHEMERAL_PORT_START);
+
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// A local port for a new outgoing connection, cycling through the IANA
+/// dynamic range so concurrent connections to one server don't collide
+fn next_ephemeral_port() -> u16 {
+    let port = NEXT_EPHEMERAL_PORT.fetch_add(1, Ordering::Relaxed);
+    if port < EPHEMERAL_PORT_START {
+        // Wrapped past 65535
+        NEXT_EPHEMERAL_PORT.store(EPHEMERAL_PORT_START + 1, Ordering::Relaxed);
+        return EPHEMERAL_PORT_START;
+    }
+    port
+}
+
+/// An outgoing TCP connection driven by polling the stack
+///
+/// The socket lives in the stack's socket set until the stream is closed;
+/// dropping a stream without closing it leaks the socket.
+pub struct TcpStream {
+    handle: SocketHandle,
+}
+
+impl TcpStream {
+    /// Connect to `ip:port`, counting the connection (or its failure) in the
+    /// stack's stats
+    ///
+    /// Fails with [`NetError::TcpConnectionFailed`] if the peer refuses or
+    /// the handshake doesn't finish within `timeout_ms`.
+    pub fn connect<F, S>(
+        stack: &mut NetworkStack,
+        ip: Ipv4Address,
+        port: u16,
+        timeout_ms: i64,
+        get_time_ms: &mut F,
+        sleep_ms: Option<&mut S>,
+    ) -> Result<Self, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        Self::connect_with_buffer_size(
+            stack,
+            ip,
+            port,
+            TCP_BUFFER_SIZE,
+            timeout_ms,
+            get_time_ms,
+            sleep_ms,
+        )
+    }
+
+    /// [`Self::connect`] with `buffer_size` bytes for each direction, e.g.
+    /// to fit a whole TLS record
+    pub fn connect_with_buffer_size<F, S>(
+        stack: &mut NetworkStack,
+        ip: Ipv4Address,
+        port: u16,
+        buffer_size: usize,
+        timeout_ms: i64,
+        get_time_ms: &mut F,
+        sleep_ms: Option<&mut S>,
+    ) -> Result<Self, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        let result = Self::establish(
+            stack,
+            ip,
+            port,
+            buffer_size,
+            timeout_ms,
+            get_time_ms,
+            sleep_ms,
+        );
+        match &result {
+            Ok(_) => stack.stats_mut().tcp_connections += 1,
+            Err(error) => stack.stats_mut().record_error(error),
+        }
+        result
+    }
+
+    fn establish<F, S>(
+        stack: &mut NetworkStack,
+        ip: Ipv4Address,
+        port: u16,
+        buffer_size: usize,
+        timeout_ms: i64,
+        get_time_ms: &mut F,
+        mut sleep_ms: Option<&mut S>,
+    ) -> Result<Self, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        let rx = tcp::SocketBuffer::new(vec![0u8; buffer_size]);
+        let tx = tcp::SocketBuffer::new(vec![0u8; buffer_size]);
+        let handle = stack.sockets_mut().add(TcpSocket::new(rx, tx));
+        let stream = Self { handle };
+
+        let remote = IpEndpoint::new(IpAddress::Ipv4(ip), port);
+        {
+            // smoltcp requires `&mut Context` for connect; `NetworkStack` doesn't expose a safe
+            // way to borrow the interface context and socket set simultaneously.
+            let ctx_ptr = stack.interface_mut().context() as *mut _;
+            let sock = stack.sockets_mut().get_mut::<TcpSocket>(handle);
+            // SAFETY: `iface` and `sockets` are disjoint fields of `NetworkStack`, and the raw
+            // pointer is only used for the duration of this call (no aliasing escapes).
+            let connected = unsafe { sock.connect(&mut *ctx_ptr, remote, next_ephemeral_port()) };
+            if let Err(e) = connected {
+                stack.sockets_mut().remove(handle);
+                return Err(NetError::TcpConnectionFailed(format!("{:?}", e)));
+            }
+        }
+
+        let start = get_time_ms();
+        loop {
+            let now = get_time_ms();
+            if let Err(error) = stack.poll(now) {
+                stream.abort(stack);
+                return Err(error);
+            }
+
+            match stream.socket(stack).state() {
+                TcpState::Established => return Ok(stream),
+                TcpState::Closed | TcpState::Closing | TcpState::CloseWait => {
+                    stream.abort(stack);
+                    return Err(NetError::TcpConnectionFailed("Connection closed".into()));
+                }
+                _ => {}
+            }
+
+            if now - start > timeout_ms {
+                stream.abort(stack);
+                return Err(NetError::TcpConnectionFailed("Connection timeout".into()));
+            }
+
+            pause(&mut sleep_ms, 10);
+        }
+    }
+
+    /// Queue all of `data` for sending
+    ///
+    /// Returns once the last byte is in the socket's transmit buffer, not
+    /// when the peer has acknowledged it; [`Self::close`] waits for that.
+    pub fn write_all<F, S>(
+        &mut self,
+        stack: &mut NetworkStack,
+        mut data: &[u8],
+        timeout_ms: i64,
+        get_time_ms: &mut F,
+        mut sleep_ms: Option<&mut S>,
+    ) -> Result<(), NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        let start = get_time_ms();
+        while !data.is_empty() {
+            let now = get_time_ms();
+            stack.poll(now)?;
+            if now - start > timeout_ms {
+                return Err(NetError::TcpTimeout);
+            }
+
+            let sock = self.socket_mut(stack);
+            if !sock.may_send() {
+                return Err(NetError::TcpConnectionFailed("Connection closed".into()));
+            }
+            if !sock.can_send() {
+                pause(&mut sleep_ms, 1);
+                continue;
+            }
+
+            let sent = sock
+                .send_slice(data)
+                .map_err(|_| NetError::TcpSendBufferFull)?;
+            data = &data[sent..];
+        }
+        Ok(())
+    }
+
+    /// Read whatever has arrived into `buf`, waiting up to `timeout_ms` for
+    /// the first byte
+    ///
+    /// `Ok(0)` means the peer has closed its side and everything it sent has
+    /// been read.
+    pub fn read<F, S>(
+        &mut self,
+        stack: &mut NetworkStack,
+        buf: &mut [u8],
+        timeout_ms: i64,
+        get_time_ms: &mut F,
+        mut sleep_ms: Option<&mut S>,
+    ) -> Result<usize, NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        let start = get_time_ms();
+        loop {
+            let now = get_time_ms();
+            stack.poll(now)?;
+
+            let sock = self.socket_mut(stack);
+            if sock.can_recv() {
+                return sock.recv_slice(buf).map_err(|_| NetError::TcpReceiveError);
+            }
+            if !sock.may_recv() {
+                return Ok(0);
+            }
+
+            if now - start > timeout_ms {
+                return Err(NetError::TcpTimeout);
+            }
+            pause(&mut sleep_ms, 1);
+        }
+    }
+
+    /// Copy received data into `buf` without consuming it
+    ///
+    /// Doesn't poll the stack or wait; returns 0 if nothing is buffered.
+    pub fn peek(&self, stack: &mut NetworkStack, buf: &mut [u8]) -> Result<usize, NetError> {
+        self.socket_mut(stack)
+            .peek_slice(buf)
+            .map_err(|_| NetError::TcpReceiveError)
+    }
+
+    /// Whether [`Self::read`] would return at once, with data or end of stream
+    ///
+    /// Only looks at what the last poll of the stack delivered.
+    pub fn readable(&self, stack: &NetworkStack) -> bool {
+        let sock = self.socket(stack);
+        sock.can_recv() || !sock.may_recv()
+    }
+
+    /// Our address and port, while the connection exists
+    pub fn local_endpoint(&self, stack: &NetworkStack) -> Option<IpEndpoint> {
+        self.socket(stack).local_endpoint()
+    }
+
+    /// The peer's address and port, while the connection exists
+    pub fn remote_endpoint(&self, stack: &NetworkStack) -> Option<IpEndpoint> {
+        self.socket(stack).remote_endpoint()
+    }
+
+    /// Whether the connection is still established in both directions
+    pub fn is_open(&self, stack: &NetworkStack) -> bool {
+        self.socket(stack).state() == TcpState::Established
+    }
+
+    /// Close the connection and wait for the close to finish
+    ///
+    /// Sends our FIN after any data still queued and polls until the peer
+    /// has acknowledged it and sent its own, or `timeout_ms` runs out. `Ok`
+    /// means everything written reached the peer. On timeout the connection
+    /// is reset instead; that is only an error if our data or FIN was still
+    /// unacknowledged. Either way the socket is gone afterwards.
+    pub fn close<F, S>(
+        self,
+        stack: &mut NetworkStack,
+        timeout_ms: i64,
+        get_time_ms: &mut F,
+        mut sleep_ms: Option<&mut S>,
+    ) -> Result<(), NetError>
+    where
+        F: FnMut() -> i64,
+        S: FnMut(i64),
+    {
+        self.socket_mut(stack).close();
+
+        let start = get_time_ms();
+        loop {
+            let now = get_time_ms();
+            if let Err(error) = stack.poll(now) {
+                self.abort(stack);
+                return Err(error);
+            }
+
+            match self.socket(stack).state() {
+                TcpState::Closed | TcpState::TimeWait => {
+                    stack.sockets_mut().remove(self.handle);
+                    return Ok(());
+                }
+                // Our side is done; only the peer's FIN is missing
+                TcpState::FinWait2 if now - start > timeout_ms => {
+                    self.abort(stack);
+                    return Ok(());
+                }
+                _ if now - start > timeout_ms => {
+                    self.abort(stack);
+                    return Err(NetError::TcpTimeout);
+                }
+                _ => {}
+            }
+            pause(&mut sleep_ms, 1);
+        }
+    }
+
+    /// Start closing the connection and return at once
+    ///
+    /// The stack finishes the close as it is polled and frees the socket once
+    /// it is done, resetting connections that take longer than
+    /// [`TCP_CLOSE_LINGER_MS`]. For callers that can't block, such as a pool
+    /// evicting idle connections.
+    pub fn close_in_background(self, stack: &mut NetworkStack) {
+        self.socket_mut(stack).close();
+        stack.linger_tcp_socket(self.handle);
+    }
+
+    /// Reset the connection and free its socket
+    pub fn abort(self, stack: &mut NetworkStack) {
+        self.socket_mut(stack).abort();
+        // Let the reset go out before the socket disappears
+        stack.linger_tcp_socket(self.handle);
+    }
+
+    fn socket<'a>(&self, stack: &'a NetworkStack) -> &'a TcpSocket<'static> {
+        stack.sockets().get::<TcpSocket>(self.handle)
+    }
+
+    fn socket_mut<'a>(&self, stack: &'a mut NetworkStack) -> &'a mut TcpSocket<'static> {
+        stack.sockets_mut().get_mut::<TcpSocket>(self.handle)
+    }
+}
+
+/// Yield between polls, or just keep the loop from being optimized away
+fn pause<S: FnMut(i64)>(sleep_ms: &mut Option<&mut S>, ms: i64) {
+    match sleep_ms {
+        Some(sleep_fn) => sleep_fn(ms),
+        None => core::sync::atomic::compiler_fence(Ordering::SeqCst),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::loopback::PairedDriver;
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    use core::cell::{Cell, RefCell};
+
+    const CLIENT_IP: Ipv4Address = Ipv4Address([192, 168, 1, 20]);
+    const SERVER_IP: Ipv4Address = Ipv4Address([192, 168, 1, 50]);
+    const PORT: u16 = 7;
+
+    /// A client stack and a server stack listening on `PORT`
+    fn pair() -> (NetworkStack, NetworkStack, SocketHandle) {
+        let (client_end, server_end) =
+            PairedDriver::pair([0x02, 0, 0, 0, 0, 0x01], [0x02, 0, 0, 0, 0, 0x02]);
+        let client = NetworkStack::new(Box::new(client_end), Some((CLIENT_IP, 24))).unwrap();
+        let mut server = NetworkStack::new(Box::new(server_end), Some((SERVER_IP, 24))).unwrap();
+        let mut socket = TcpSocket::new(
+            tcp::SocketBuffer::new(vec![0u8; TCP_BUFFER_SIZE]),
+            tcp::SocketBuffer::new(vec![0u8; TCP_BUFFER_SIZE]),
+        );
+        socket.listen(PORT).unwrap();
+        let listener = server.sockets_mut().add(socket);
+        (client, server, listener)
+    }
+
+    #[test]
+    fn test_round_trip_and_graceful_close() {
+        let (mut client, server, listener) = pair();
+
+        // Echo server that records what it got and closes after the client
+        let server = RefCell::new(server);
+        let received = RefCell::new(Vec::new());
+        let echo = Cell::new(true);
+        let clock = Cell::new(0i64);
+        let mut sleep = |ms: i64| {
+            clock.set(clock.get() + ms);
+            let mut server = server.borrow_mut();
+            server.poll(clock.get()).unwrap();
+            let socket = server.sockets_mut().get_mut::<TcpSocket>(listener);
+            let mut buf = [0u8; 2048];
+            while socket.can_recv() {
+                let n = socket.recv_slice(&mut buf).unwrap();
+                received.borrow_mut().extend_from_slice(&buf[..n]);
+                if echo.get() {
+                    socket.send_slice(&buf[..n]).unwrap();
+                }
+            }
+            if socket.state() == TcpState::CloseWait {
+                socket.close();
+            }
+            server.poll(clock.get()).unwrap();
+        };
+        let mut now = || clock.get();
+
+        let mut stream = TcpStream::connect(
+            &mut client,
+            SERVER_IP,
+            PORT,
+            1000,
+            &mut now,
+            Some(&mut sleep),
+        )
+        .unwrap();
+        assert!(stream.is_open(&client));
+        assert_eq!(
+            stream.remote_endpoint(&client),
+            Some(IpEndpoint::new(IpAddress::Ipv4(SERVER_IP), PORT))
+        );
+        let local = stream.local_endpoint(&client).unwrap();
+        assert_eq!(local.addr, IpAddress::Ipv4(CLIENT_IP));
+        assert!(local.port >= EPHEMERAL_PORT_START);
+        assert_eq!(client.stats().tcp_connections, 1);
+
+        stream
+            .write_all(&mut client, b"ping", 1000, &mut now, Some(&mut sleep))
+            .unwrap();
+        while !stream.readable(&client) {
+            sleep(1);
+            client.poll(now()).unwrap();
+        }
+        // Peeking leaves the data for `read`
+        let mut buf = [0u8; 16];
+        assert_eq!(stream.peek(&mut client, &mut buf).unwrap(), 4);
+        assert!(stream.readable(&client));
+        let n = stream
+            .read(&mut client, &mut buf, 1000, &mut now, Some(&mut sleep))
+            .unwrap();
+        assert_eq!(&buf[..n], b"ping");
+
+        // Nothing more is coming: the read times out, the connection lives on
+        let result = stream.read(&mut client, &mut buf, 50, &mut now, Some(&mut sleep));
+        assert!(matches!(result, Err(NetError::TcpTimeout)));
+        assert!(stream.is_open(&client));
+
+        // Data queued right before the close still reaches the server
+        echo.set(false);
+        let payload = vec![0x5A; 6000];
+        stream
+            .write_all(&mut client, &payload, 1000, &mut now, Some(&mut sleep))
+            .unwrap();
+        stream
+            .close(&mut client, 1000, &mut now, Some(&mut sleep))
+            .unwrap();
+        assert_eq!(received.borrow().len(), 4 + payload.len());
+        assert_eq!(client.sockets().iter().count(), 0);
+    }
+
+    #[test]
+    fn test_background_close_finishes_or_resets() {
+        let (mut client, server, listener) = pair();
+        let server = RefCell::new(server);
+        let server_up = Cell::new(true);
+        let clock = Cell::new(0i64);
+        let mut sleep = |ms: i64| {
+            clock.set(clock.get() + ms);
+            if server_up.get() {
+                let mut server = server.borrow_mut();
+                server.poll(clock.get()).unwrap();
+                let socket = server.sockets_mut().get_mut::<TcpSocket>(listener);
+                if socket.state() == TcpState::CloseWait {
+                    socket.close();
+                }
+                server.poll(clock.get()).unwrap();
+            }
+        };
+        let mut now = || clock.get();
+
+        // The socket stays until the close completes, then the stack frees it
+        let stream = TcpStream::connect(
+            &mut client,
+            SERVER_IP,
+            PORT,
+            1000,
+            &mut now,
+            Some(&mut sleep),
+        )
+        .unwrap();
+        stream.close_in_background(&mut client);
+        assert_eq!(client.sockets().iter().count(), 1);
+        for _ in 0..100 {
+            sleep(1);
+            client.poll(now()).unwrap();
+        }
+        assert_eq!(client.sockets().iter().count(), 0);
+
+        // A peer that went silent gets reset once the linger time is up
+        server
+            .borrow_mut()
+            .sockets_mut()
+            .get_mut::<TcpSocket>(listener)
+            .listen(PORT)
+            .unwrap();
+        let stream = TcpStream::connect(
+            &mut client,
+            SERVER_IP,
+            PORT,
+            1000,
+            &mut now,
+            Some(&mut sleep),
+        )
+        .unwrap();
+        server_up.set(false);
+        stream.close_in_background(&mut client);
+        while now() <= TCP_CLOSE_LINGER_MS + 1000 {
+            sleep(100);
+            client.poll(now()).unwrap();
+        }
+        assert_eq!(client.sockets().iter().count(), 0);
+    }
+}
@@ -6,8 +6,10 @@
 //! # Features
 //! - TLS 1.3 handshake
 //! - Certificate verification with webpki and embedded root CAs
-//! - AES-128-GCM and AES-256-GCM cipher suites
+//! - TLS_AES_128_GCM_SHA256, or TLS_AES_256_GCM_SHA384 through
+//!   [`set_tls_cipher_suite`] (one suite is offered per handshake)
 //! - Blocking I/O interface compatible with smoltcp
+//! - Handshake keys drawn from the CPU's RDRAND instruction (x86_64 only)
 //!
 //! # Example
 //! ```no_run
@@ -31,11 +33,11 @@
 //! )?;
 //!
 //! // Send HTTP request
-//! tls.write(stack, b"GET / HTTP/1.1\r\nHost: api.openai.com\r\n\r\n", get_time_ms, Some(sleep_ms))?;
+//! tls.write(stack, b"GET / HTTP/1.1\r\nHost: api.openai.com\r\n\r\n", 30000, get_time_ms, Some(sleep_ms))?;
 //!
 //! // Read response
 //! let mut buffer = [0u8; 1024];
-//! let len = tls.read(stack, &mut buffer, get_time_ms, Some(sleep_ms))?;
+//! let len = tls.read(stack, &mut buffer, 30000, get_time_ms, Some(sleep_ms))?;
 //! # Ok(())
 //! # }
 //! ```
@@ -44,38 +46,124 @@
 
 extern crate alloc;
 
+mod sigalgs;
+
 use crate::error::NetError;
 use crate::stack::NetworkStack;
+use crate::tcp::TcpStream;
 use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use embedded_tls::blocking::{TlsConfig, TlsConnection as EmbeddedTlsConnection, TlsContext};
-use embedded_tls::{TlsError as EmbeddedTlsError, TlsVerifier};
-use smoltcp::iface::SocketHandle;
-use smoltcp::socket::tcp::{self, Socket as TcpSocket, State as TcpState};
-use smoltcp::wire::{IpAddress, IpEndpoint, Ipv4Address};
-use webpki::{DnsNameRef, EndEntityCert, Time, TlsServerTrustAnchors};
+use core::cell::RefCell;
+use core::future::{poll_fn, Future};
+use core::num::NonZeroU32;
+use core::pin::pin;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::task::{Context, Poll, Waker};
+use embedded_tls::{
+    Aes128GcmSha256, Aes256GcmSha384, CertificateEntryRef, CertificateRef, CertificateVerifyRef,
+    CryptoProvider, CryptoRngCore, TlsCipherSuite as SuiteParams, TlsConfig,
+    TlsConnection as EmbeddedTlsConnection, TlsContext, TlsError as EmbeddedTlsError, TlsVerifier,
+};
+use ouroboros::self_referencing;
+use rand_core::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+use smoltcp::wire::Ipv4Address;
+use spin::Mutex;
+use webpki::types::{CertificateDer, ServerName, TrustAnchor, UnixTime};
+use webpki::{EndEntityCert, KeyUsage};
 use x509_parser::prelude::*;
 
-/// Maximum TLS record size (16KB as recommended by embedded-tls)
-const TLS_RECORD_BUFFER_SIZE: usize = 16384;
-
-/// TCP receive buffer size (must be large enough for TLS records)
-const TCP_RX_BUFFER_SIZE: usize = 16384;
+/// Record buffer size: the largest encrypted TLS record (16KB of data
+/// plus 256 bytes of expansion)
+const TLS_RECORD_BUFFER_SIZE: usize = 16640;
 
-/// TCP transmit buffer size
-const TCP_TX_BUFFER_SIZE: usize = 16384;
+/// TCP buffer size in each direction (must be large enough for TLS records)
+const TCP_BUFFER_SIZE: usize = 16384;
 
 /// Embedded Mozilla root CA certificates
 ///
 /// This includes the Mozilla CA Certificate Store for certificate verification.
 /// The webpki-roots crate provides these certificates in a no_std compatible format.
-static TLS_SERVER_ROOTS: &TlsServerTrustAnchors = &webpki_roots::TLS_SERVER_ROOTS;
+static TLS_SERVER_ROOTS: &[TrustAnchor<'static>] = webpki_roots::TLS_SERVER_ROOTS;
+
+/// TLS 1.3 cipher suite offered in the ClientHello
+///
+/// embedded-tls offers exactly one suite per handshake, so the server must
+/// support the chosen one. Every TLS 1.3 server implements
+/// TLS_AES_128_GCM_SHA256 (RFC 8446, section 9.1), which makes it the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TlsCipherSuite {
+    #[default]
+    Aes128GcmSha256,
+    Aes256GcmSha384,
+}
+
+/// Cipher suite for new connections, a [`TlsCipherSuite`] discriminant
+static TLS_CIPHER_SUITE: AtomicU8 = AtomicU8::new(TlsCipherSuite::Aes128GcmSha256 as u8);
+
+/// Choose the cipher suite offered by connections opened from now on
+pub fn set_tls_cipher_suite(suite: TlsCipherSuite) {
+    TLS_CIPHER_SUITE.store(suite as u8, Ordering::Relaxed);
+}
+
+fn tls_cipher_suite() -> TlsCipherSuite {
+    match TLS_CIPHER_SUITE.load(Ordering::Relaxed) {
+        suite if suite == TlsCipherSuite::Aes256GcmSha384 as u8 => TlsCipherSuite::Aes256GcmSha384,
+        _ => TlsCipherSuite::Aes128GcmSha256,
+    }
+}
+
+/// IANA name of the TLS 1.3 cipher suite with `code_point`
+fn cipher_suite_name(code_point: u16) -> &'static str {
+    match code_point {
+        0x1301 => "TLS_AES_128_GCM_SHA256",
+        0x1302 => "TLS_AES_256_GCM_SHA384",
+        0x1303 => "TLS_CHACHA20_POLY1305_SHA256",
+        _ => "unknown cipher suite",
+    }
+}
+
+/// Extra root certificates (DER) trusted in addition to the Mozilla roots
+static EXTRA_ROOTS: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+/// Pinned SHA-256 certificate fingerprints by hostname
+static PINNED_CERTIFICATES: Mutex<Vec<(String, [u8; 32])>> = Mutex::new(Vec::new());
+
+/// Trust these root certificates (DER) in addition to the embedded Mozilla roots
+///
+/// Replaces any previously registered extra roots.
+pub fn set_tls_extra_roots(certificates: Vec<Vec<u8>>) {
+    *EXTRA_ROOTS.lock() = certificates;
+}
+
+/// Pin the certificate presented by `hostname` to a SHA-256 fingerprint
+///
+/// A pinned host skips chain validation, but its end-entity certificate
+/// must match the fingerprint exactly.
+pub fn pin_tls_certificate(hostname: &str, sha256: [u8; 32]) {
+    let mut pins = PINNED_CERTIFICATES.lock();
+    pins.retain(|(host, _)| !host.eq_ignore_ascii_case(hostname));
+    pins.push((hostname.to_string(), sha256));
+}
+
+/// Pinned fingerprint for `hostname`, if any
+fn pinned_certificate(hostname: &str) -> Option<[u8; 32]> {
+    PINNED_CERTIFICATES
+        .lock()
+        .iter()
+        .find(|(host, _)| host.eq_ignore_ascii_case(hostname))
+        .map(|(_, sha256)| *sha256)
+}
 
 /// Logging callback type for TLS operations
 /// 
 /// This allows external code to receive log messages about TLS handshake
 /// and certificate verification. The callback receives log level and message.
+/// Messages carry progress only (hosts, certificate names, byte counts),
+/// never key material or application data.
 pub type TlsLogCallback = Option<fn(level: &str, message: &str)>;
 
 /// Global TLS logging callback (set via set_tls_log_callback)
@@ -90,6 +178,29 @@ pub unsafe fn set_tls_log_callback(callback: TlsLogCallback) {
     TLS_LOG_CALLBACK = callback;
 }
 
+/// Source of the current time in seconds since the Unix epoch
+pub type TlsTimeSource = Option<fn() -> u64>;
+
+/// Global certificate validation time source (set via set_tls_time_source)
+static mut TLS_TIME_SOURCE: TlsTimeSource = None;
+
+/// Lower bound for the current time when no time source is registered or the
+/// registered one has not been set yet
+/// (2026-10-01 00:00:00 UTC, around when this build was cut)
+const BUILD_DATE_UNIX_SECS: u64 = 1_790_812_800;
+
+/// Set when the last certificate was rejected for its validity period
+static CERT_EXPIRED: AtomicBool = AtomicBool::new(false);
+
+/// Set the time source used to check certificate validity periods
+///
+/// # Safety
+/// This function is unsafe because it modifies global state without synchronization.
+/// It should only be called during initialization before any TLS operations.
+pub unsafe fn set_tls_time_source(source: fn() -> u64) {
+    TLS_TIME_SOURCE = Some(source);
+}
+
 /// Internal logging function for TLS operations
 fn tls_log(level: &str, message: &str) {
     unsafe {
@@ -105,16 +216,16 @@ fn tls_log(level: &str, message: &str) {
 /// It handles the TLS handshake, encryption/decryption, and manages the
 /// underlying TCP socket.
 pub struct TlsConnection {
-    /// Handle to the TCP socket in the network stack
-    tcp_handle: SocketHandle,
-    /// TLS read record buffer (16KB)
-    read_buffer: Box<[u8; TLS_RECORD_BUFFER_SIZE]>,
-    /// TLS write record buffer (16KB)
-    write_buffer: Box<[u8; TLS_RECORD_BUFFER_SIZE]>,
+    /// The TCP connection the records travel over
+    tcp: TcpStream,
+    /// TLS session established by the handshake and reused by every read and write
+    session: Box<dyn Session>,
     /// Hostname for SNI (Server Name Indication)
     hostname: String,
     /// Whether the TLS handshake is complete
     handshake_complete: bool,
+    /// A read or write failed; the session can't pick up where it stopped
+    broken: bool,
 }
 
 impl TlsConnection {
@@ -169,111 +280,56 @@ impl TlsConnection {
         F: FnMut() -> i64,
         S: FnMut(i64),
     {
-        // Create TCP socket
-        let tcp_handle = Self::create_tcp_socket(stack)?;
-
-        // Connect TCP socket
-        Self::tcp_connect(
+        let tcp = TcpStream::connect_with_buffer_size(
             stack,
-            tcp_handle,
             ip,
             port,
+            TCP_BUFFER_SIZE,
             timeout_ms,
             &mut get_time_ms,
-            &mut sleep_ms,
+            sleep_ms.as_mut(),
         )?;
+        tls_log("INFO", &alloc::format!("TCP connected to {}:{}", ip, port));
 
-        // Allocate TLS buffers on heap (16KB each)
-        let read_buffer = Box::new([0u8; TLS_RECORD_BUFFER_SIZE]);
-        let write_buffer = Box::new([0u8; TLS_RECORD_BUFFER_SIZE]);
-
-        let mut connection = TlsConnection {
-            tcp_handle,
-            read_buffer,
-            write_buffer,
-            hostname: hostname.to_string(),
-            handshake_complete: false,
-        };
-
-        // Perform TLS handshake
-        connection.perform_handshake(stack, timeout_ms, get_time_ms, sleep_ms)?;
-
-        Ok(connection)
-    }
-
-    /// Create a new TCP socket in the network stack
-    fn create_tcp_socket(stack: &mut NetworkStack) -> Result<SocketHandle, NetError> {
-        // Create TCP socket buffers
-        let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0u8; TCP_RX_BUFFER_SIZE]);
-        let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0u8; TCP_TX_BUFFER_SIZE]);
-
-        let tcp_socket = TcpSocket::new(tcp_rx_buffer, tcp_tx_buffer);
-
-        // Add socket to the socket set
-        let handle = stack.sockets_mut().add(tcp_socket);
-
-        Ok(handle)
+        Self::connect_over(stack, hostname, tcp, timeout_ms, get_time_ms, sleep_ms)
     }
 
-    /// Connect the TCP socket to the remote server
-    fn tcp_connect<F, S>(
+    /// Run the TLS handshake on an already connected TCP stream, such as a
+    /// tunnel opened through an HTTP proxy
+    ///
+    /// `hostname` is used for SNI and certificate verification. The stream
+    /// is closed if the handshake fails.
+    pub fn connect_over<F, S>(
         stack: &mut NetworkStack,
-        handle: SocketHandle,
-        ip: Ipv4Address,
-        port: u16,
+        hostname: &str,
+        tcp: TcpStream,
         timeout_ms: i64,
-        get_time_ms: &mut F,
-        sleep_ms: &mut Option<S>,
-    ) -> Result<(), NetError>
+        get_time_ms: F,
+        sleep_ms: Option<S>,
+    ) -> Result<Self, NetError>
     where
         F: FnMut() -> i64,
         S: FnMut(i64),
     {
-        let remote_endpoint = IpEndpoint::new(IpAddress::Ipv4(ip), port);
-
-        // Initiate connection
-        {
-            let tcp_socket = stack.sockets_mut().get_mut::<TcpSocket>(handle);
-            tcp_socket
-                .connect(stack.interface().context(), remote_endpoint, 49152)
-                .map_err(|e| NetError::TcpConnectionFailed(format!("{:?}", e)))?;
-        }
-
-        let start_time = get_time_ms();
-
-        // Wait for connection to be established
-        loop {
-            let current_time = get_time_ms();
-
-            // Poll the network stack
-            stack.poll(current_time)?;
-
-            // Check connection state
-            let tcp_socket = stack.sockets().get::<TcpSocket>(handle);
-            match tcp_socket.state() {
-                TcpState::Established => {
-                    return Ok(());
-                }
-                TcpState::Closed | TcpState::Closing | TcpState::CloseWait => {
-                    return Err(NetError::TcpConnectionFailed("Connection closed".into()));
-                }
-                _ => {
-                    // Still connecting
-                }
-            }
-
-            // Check for timeout
-            if current_time - start_time > timeout_ms {
-                return Err(NetError::TcpConnectionFailed("Connection timeout".into()));
-            }
+        let mut connection = TlsConnection {
+            tcp,
+            session: new_session(tls_cipher_suite()),
+            hostname: hostname.to_string(),
+            handshake_complete: false,
+            broken: false,
+        };
 
-            // Sleep to avoid busy waiting
-            if let Some(ref mut sleep_fn) = sleep_ms {
-                sleep_fn(10);
-            } else {
-                core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+        // Perform TLS handshake
+        match connection.perform_handshake(stack, timeout_ms, get_time_ms, sleep_ms) {
+            Ok(()) => stack.stats_mut().tls_handshakes += 1,
+            Err(error) => {
+                stack.stats_mut().record_error(&error);
+                connection.tcp.close_in_background(stack);
+                return Err(error);
             }
         }
+
+        Ok(connection)
     }
 
     /// Perform TLS 1.3 handshake with the server
@@ -283,6 +339,8 @@ impl TlsConnection {
     /// - Certificate chain validation
     /// - Hostname verification
     /// - Certificate expiration check
+    ///
+    /// Each TCP read and write of the handshake waits at most `timeout_ms`.
     fn perform_handshake<F, S>(
         &mut self,
         stack: &mut NetworkStack,
@@ -295,48 +353,57 @@ impl TlsConnection {
         S: FnMut(i64),
     {
         tls_log("INFO", &alloc::format!("Starting TLS handshake with {}", self.hostname));
-        
-        // Create TLS configuration
-        let config = TlsConfig::new()
-            .with_server_name(&self.hostname)
-            .enable_rsa_signatures();
 
-        tls_log("DEBUG", "TLS config created with SNI and RSA signatures enabled");
+        let Some(mut rng) = TlsRng::new() else {
+            tls_log("ERROR", "No hardware random number generator for the handshake keys");
+            return Err(NetError::TlsHandshakeFailed(
+                "no hardware random number generator".into(),
+            ));
+        };
 
-        // Create TCP adapter for embedded-tls
-        let mut tcp_adapter = TcpSocketAdapter {
+        let mut transport = TcpTransport {
             stack,
-            handle: self.tcp_handle,
+            stream: &mut self.tcp,
+            timeout_ms,
             get_time_ms: &mut get_time_ms,
             sleep_ms: &mut sleep_ms,
         };
 
-        // Create WebPKI verifier for certificate validation
-        let mut verifier = WebPkiVerifier::new();
-        verifier.set_hostname_verification(&self.hostname);
-        
-        tls_log("DEBUG", "WebPKI verifier created, hostname verification enabled");
-
-        // Create TLS context with proper certificate verification
-        let context = TlsContext::new(&config, &mut verifier);
-
-        // Create TLS connection
-        let mut tls = EmbeddedTlsConnection::new(
-            &mut tcp_adapter,
-            &mut *self.read_buffer,
-            &mut *self.write_buffer,
-        );
-
         tls_log("INFO", "Initiating TLS handshake...");
-
-        // Perform handshake (blocking)
-        match tls.open(context) {
-            Ok(_) => {
-                tls_log("INFO", "TLS handshake completed successfully");
+        CERT_EXPIRED.store(false, Ordering::Relaxed);
+
+        // Perform handshake (blocking); the session keeps the negotiated keys
+        let result = self.session.open(&self.hostname, &mut rng, &mut transport);
+        if let Some(error) = rng.failure {
+            // Whatever the handshake did, it ran on substitute bytes
+            tls_log("ERROR", &alloc::format!("TLS handshake failed: {}", error));
+            return Err(NetError::TlsHandshakeFailed(format!(
+                "random number generator failed: {}",
+                error
+            )));
+        }
+        match result {
+            Ok(()) => {
+                tls_log(
+                    "INFO",
+                    &alloc::format!(
+                        "TLS handshake with {} complete ({})",
+                        self.hostname,
+                        self.session.cipher_suite()
+                    ),
+                );
                 self.handshake_complete = true;
                 Ok(())
             }
-            Err(e) => {
+            Err(SessionError::Transport(e)) => {
+                tls_log("ERROR", &alloc::format!("TLS handshake failed: {:?}", e));
+                Err(e)
+            }
+            Err(_) if CERT_EXPIRED.load(Ordering::Relaxed) => {
+                tls_log("ERROR", "TLS handshake failed: certificate outside its validity period");
+                Err(NetError::CertificateExpired)
+            }
+            Err(SessionError::Tls(e)) => {
                 tls_log("ERROR", &alloc::format!("TLS handshake failed: {:?}", e));
                 Err(NetError::TlsHandshakeFailed(format!("{:?}", e)))
             }
@@ -350,18 +417,20 @@ impl TlsConnection {
     /// # Arguments
     /// * `stack` - Mutable reference to the network stack
     /// * `data` - Data to send
+    /// * `timeout_ms` - Limit for each TCP write of the record
     /// * `get_time_ms` - Function to get current time in milliseconds
     /// * `sleep_ms` - Optional function to sleep/yield
     ///
     /// # Returns
     /// * `Ok(usize)` - Number of bytes written
-    /// * `Err(NetError)` - Write failed
+    /// * `Err(NetError)` - Write failed; [`NetError::TcpTimeout`] if the
+    ///   server stopped taking data
     ///
     /// # Example
     /// ```no_run
     /// # use network::{NetworkStack, TlsConnection};
     /// # fn example(tls: &mut TlsConnection, stack: &mut NetworkStack, get_time_ms: impl FnMut() -> i64, sleep_ms: impl FnMut(i64)) -> Result<(), network::NetError> {
-    /// tls.write(stack, b"GET / HTTP/1.1\r\n", get_time_ms, Some(sleep_ms))?;
+    /// tls.write(stack, b"GET / HTTP/1.1\r\n", 30000, get_time_ms, Some(sleep_ms))?;
     /// # Ok(())
     /// # }
     /// ```
@@ -369,6 +438,7 @@ impl TlsConnection {
         &mut self,
         stack: &mut NetworkStack,
         data: &[u8],
+        timeout_ms: i64,
         mut get_time_ms: F,
         mut sleep_ms: Option<S>,
     ) -> Result<usize, NetError>
@@ -376,39 +446,29 @@ impl TlsConnection {
         F: FnMut() -> i64,
         S: FnMut(i64),
     {
-        if !self.handshake_complete {
-            return Err(NetError::TlsError("Handshake not complete".into()));
-        }
+        self.check_usable()?;
 
-        // TODO: This is a placeholder implementation
-        // The TLS connection state should be maintained between calls
-        // rather than recreated for each write operation.
-        // This will be refactored to store the TlsConnection in the struct.
-
-        // Create TLS connection for write operation
-        let config = TlsConfig::new()
-            .with_server_name(&self.hostname)
-            .enable_rsa_signatures();
-
-        let mut tcp_adapter = TcpSocketAdapter {
+        let mut transport = TcpTransport {
             stack,
-            handle: self.tcp_handle,
+            stream: &mut self.tcp,
+            timeout_ms,
             get_time_ms: &mut get_time_ms,
             sleep_ms: &mut sleep_ms,
         };
 
-        let mut verifier = WebPkiVerifier::new();
-        let context = TlsContext::new(&config, &mut verifier);
-
-        let mut tls = EmbeddedTlsConnection::new(
-            &mut tcp_adapter,
-            &mut *self.read_buffer,
-            &mut *self.write_buffer,
-        );
-
-        // Write data through TLS
-        tls.write(data)
-            .map_err(|e| NetError::TlsError(format!("Write failed: {:?}", e)))
+        // Write data through the established session and push the record out
+        let written = match self.session.write(data, &mut transport) {
+            Ok(written) => written,
+            Err(error) => {
+                self.broken = true;
+                return Err(match error {
+                    SessionError::Tls(e) => NetError::TlsError(format!("Write failed: {:?}", e)),
+                    SessionError::Transport(e) => e,
+                });
+            }
+        };
+        tls_log("DEBUG", &alloc::format!("Wrote {} bytes to {}", written, self.hostname));
+        Ok(written)
     }
 
     /// Read data from the TLS connection
@@ -418,19 +478,21 @@ impl TlsConnection {
     /// # Arguments
     /// * `stack` - Mutable reference to the network stack
     /// * `buffer` - Buffer to read data into
+    /// * `timeout_ms` - Limit for each TCP read while waiting for a record
     /// * `get_time_ms` - Function to get current time in milliseconds
     /// * `sleep_ms` - Optional function to sleep/yield
     ///
     /// # Returns
     /// * `Ok(usize)` - Number of bytes read (0 indicates connection closed)
-    /// * `Err(NetError)` - Read failed
+    /// * `Err(NetError)` - Read failed; [`NetError::TcpTimeout`] if no data
+    ///   arrived in time
     ///
     /// # Example
     /// ```no_run
     /// # use network::{NetworkStack, TlsConnection};
     /// # fn example(tls: &mut TlsConnection, stack: &mut NetworkStack, get_time_ms: impl FnMut() -> i64, sleep_ms: impl FnMut(i64)) -> Result<(), network::NetError> {
     /// let mut buffer = [0u8; 1024];
-    /// let len = tls.read(stack, &mut buffer, get_time_ms, Some(sleep_ms))?;
+    /// let len = tls.read(stack, &mut buffer, 30000, get_time_ms, Some(sleep_ms))?;
     /// # Ok(())
     /// # }
     /// ```
@@ -438,6 +500,7 @@ impl TlsConnection {
         &mut self,
         stack: &mut NetworkStack,
         buffer: &mut [u8],
+        timeout_ms: i64,
         mut get_time_ms: F,
         mut sleep_ms: Option<S>,
     ) -> Result<usize, NetError>
@@ -445,39 +508,33 @@ impl TlsConnection {
         F: FnMut() -> i64,
         S: FnMut(i64),
     {
-        if !self.handshake_complete {
-            return Err(NetError::TlsError("Handshake not complete".into()));
-        }
+        self.check_usable()?;
 
-        // TODO: This is a placeholder implementation
-        // The TLS connection state should be maintained between calls
-        // rather than recreated for each read operation.
-        // This will be refactored to store the TlsConnection in the struct.
-
-        // Create TLS connection for read operation
-        let config = TlsConfig::new()
-            .with_server_name(&self.hostname)
-            .enable_rsa_signatures();
-
-        let mut tcp_adapter = TcpSocketAdapter {
+        let mut transport = TcpTransport {
             stack,
-            handle: self.tcp_handle,
+            stream: &mut self.tcp,
+            timeout_ms,
             get_time_ms: &mut get_time_ms,
             sleep_ms: &mut sleep_ms,
         };
 
-        let mut verifier = WebPkiVerifier::new();
-        let context = TlsContext::new(&config, &mut verifier);
-
-        let mut tls = EmbeddedTlsConnection::new(
-            &mut tcp_adapter,
-            &mut *self.read_buffer,
-            &mut *self.write_buffer,
-        );
-
-        // Read data through TLS
-        tls.read(buffer)
-            .map_err(|e| NetError::TlsError(format!("Read failed: {:?}", e)))
+        // Read data through the established session
+        let read = match self.session.read(buffer, &mut transport) {
+            Ok(read) => read,
+            Err(error) => {
+                self.broken = true;
+                return match error {
+                    // close_notify from the server
+                    SessionError::Tls(EmbeddedTlsError::ConnectionClosed) => Ok(0),
+                    SessionError::Tls(e) => {
+                        Err(NetError::TlsError(format!("Read failed: {:?}", e)))
+                    }
+                    SessionError::Transport(e) => Err(e),
+                };
+            }
+        };
+        tls_log("DEBUG", &alloc::format!("Read {} bytes from {}", read, self.hostname));
+        Ok(read)
     }
 
     /// Close the TLS connection
@@ -487,12 +544,8 @@ impl TlsConnection {
     /// # Arguments
     /// * `stack` - Mutable reference to the network stack
     pub fn close(self, stack: &mut NetworkStack) {
-        // Close TCP socket
-        let tcp_socket = stack.sockets_mut().get_mut::<TcpSocket>(self.tcp_handle);
-        tcp_socket.close();
-
-        // Remove socket from socket set
-        stack.sockets_mut().remove(self.tcp_handle);
+        // The stack finishes the TCP close and frees the socket as it is polled
+        self.tcp.close_in_background(stack);
     }
 
     /// Check if the connection is still open
@@ -504,192 +557,556 @@ impl TlsConnection {
     /// * `true` - Connection is open and ready
     /// * `false` - Connection is closed or handshake incomplete
     pub fn is_open(&self, stack: &NetworkStack) -> bool {
-        if !self.handshake_complete {
+        if !self.handshake_complete || self.broken {
             return false;
         }
 
-        let tcp_socket = stack.sockets().get::<TcpSocket>(self.tcp_handle);
-        matches!(tcp_socket.state(), TcpState::Established)
+        self.tcp.is_open(stack)
+    }
+
+    /// Fail reads and writes before the handshake or after a failed call
+    fn check_usable(&self) -> Result<(), NetError> {
+        if !self.handshake_complete {
+            return Err(NetError::TlsError("Handshake not complete".into()));
+        }
+        if self.broken {
+            return Err(NetError::TlsConnectionClosed);
+        }
+        Ok(())
     }
 }
 
-/// Adapter that allows embedded-tls to use our TCP socket
+/// Error from a [`Session`] call
+#[derive(Debug)]
+enum SessionError {
+    /// embedded-tls rejected the handshake or a record
+    Tls(EmbeddedTlsError),
+    /// The TCP connection failed underneath
+    Transport(NetError),
+}
+
+/// An open TLS session, whichever cipher suite it negotiated
 ///
-/// This implements the embedded-io traits that embedded-tls expects,
-/// bridging between our smoltcp-based TCP sockets and embedded-tls's
-/// blocking I/O interface.
-struct TcpSocketAdapter<'a, F, S>
-where
-    F: FnMut() -> i64,
-    S: FnMut(i64),
-{
-    stack: &'a mut NetworkStack,
-    handle: SocketHandle,
-    get_time_ms: &'a mut F,
-    sleep_ms: &'a mut Option<S>,
+/// Every call borrows the transport it runs over, so the network stack is
+/// only held for the duration of one read, write or handshake.
+trait Session {
+    fn open(
+        &mut self,
+        hostname: &str,
+        rng: &mut TlsRng,
+        io: &mut dyn Transport,
+    ) -> Result<(), SessionError>;
+    fn write(&mut self, data: &[u8], io: &mut dyn Transport) -> Result<usize, SessionError>;
+    fn read(&mut self, buf: &mut [u8], io: &mut dyn Transport) -> Result<usize, SessionError>;
+    /// IANA name of the session's cipher suite
+    fn cipher_suite(&self) -> &'static str;
 }
 
-impl<'a, F, S> embedded_io::ErrorType for TcpSocketAdapter<'a, F, S>
-where
-    F: FnMut() -> i64,
-    S: FnMut(i64),
-{
-    type Error = TcpAdapterError;
+fn new_session(suite: TlsCipherSuite) -> Box<dyn Session> {
+    match suite {
+        TlsCipherSuite::Aes128GcmSha256 => Box::new(TlsSession::<Aes128GcmSha256>::new()),
+        TlsCipherSuite::Aes256GcmSha384 => Box::new(TlsSession::<Aes256GcmSha384>::new()),
+    }
 }
 
-impl<'a, F, S> embedded_io::Read for TcpSocketAdapter<'a, F, S>
-where
-    F: FnMut() -> i64,
-    S: FnMut(i64),
-{
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        // Poll until data is available
-        loop {
-            let current_time = (self.get_time_ms)();
-            self.stack.poll(current_time).map_err(|_| TcpAdapterError)?;
+/// Bytes queued between an embedded-tls connection and the TCP stream
+#[derive(Default)]
+struct Pipes {
+    /// Received from the server, not yet taken by the connection
+    inbound: VecDeque<u8>,
+    /// Written by the connection, not yet sent to the server
+    outbound: Vec<u8>,
+    /// The server closed the stream; reads past `inbound` see end of file
+    closed: bool,
+}
 
-            let tcp_socket = self.stack.sockets_mut().get_mut::<TcpSocket>(self.handle);
+/// Socket owned by the embedded-tls connection of a [`TlsSession`]
+///
+/// Writes are queued and reads wait until [`drive`] has moved bytes from
+/// the TCP stream into the inbound pipe.
+struct PipeSocket {
+    pipes: Rc<RefCell<Pipes>>,
+}
 
-            if tcp_socket.can_recv() {
-                return tcp_socket.recv_slice(buf).map_err(|_| TcpAdapterError);
-            }
+impl embedded_io::ErrorType for PipeSocket {
+    type Error = core::convert::Infallible;
+}
 
-            // Check if connection is closed
-            if !tcp_socket.is_open() {
-                return Ok(0);
+impl embedded_io_async::Read for PipeSocket {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        poll_fn(|_| {
+            let mut pipes = self.pipes.borrow_mut();
+            if pipes.inbound.is_empty() && !pipes.closed {
+                return Poll::Pending;
             }
-
-            // Sleep to avoid busy waiting
-            if let Some(ref mut sleep_fn) = self.sleep_ms {
-                sleep_fn(1);
-            } else {
-                core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+            let len = buf.len().min(pipes.inbound.len());
+            for (slot, byte) in buf.iter_mut().zip(pipes.inbound.drain(..len)) {
+                *slot = byte;
             }
+            Poll::Ready(Ok(len))
+        })
+        .await
+    }
+}
+
+impl embedded_io_async::Write for PipeSocket {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.pipes.borrow_mut().outbound.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// An embedded-tls connection together with the record buffers it borrows
+#[self_referencing]
+struct SessionCell<C: SuiteParams + 'static> {
+    read_buffer: Vec<u8>,
+    write_buffer: Vec<u8>,
+    #[borrows(mut read_buffer, mut write_buffer)]
+    #[not_covariant]
+    tls: EmbeddedTlsConnection<'this, PipeSocket, C>,
+}
+
+/// TLS session over cipher suite `C`
+///
+/// embedded-tls keeps the key schedule and record sequence numbers inside its
+/// connection object, so one object has to serve the whole session. That
+/// object owns its socket, a pair of in-memory pipes; each call polls the
+/// connection and moves bytes between the pipes and the transport it was
+/// given until the connection is done.
+struct TlsSession<C: SuiteParams + 'static> {
+    cell: SessionCell<C>,
+    pipes: Rc<RefCell<Pipes>>,
+}
+
+impl<C: SuiteParams + 'static> TlsSession<C> {
+    /// Allocate the record buffers and an unopened connection
+    fn new() -> Self {
+        let pipes = Rc::new(RefCell::new(Pipes::default()));
+        let socket = PipeSocket {
+            pipes: pipes.clone(),
+        };
+        let cell = SessionCell::new(
+            vec![0; TLS_RECORD_BUFFER_SIZE],
+            vec![0; TLS_RECORD_BUFFER_SIZE],
+            |read_buffer, write_buffer| {
+                EmbeddedTlsConnection::new(socket, read_buffer, write_buffer)
+            },
+        );
+        Self { cell, pipes }
+    }
+}
+
+impl<C: SuiteParams + 'static> Session for TlsSession<C> {
+    fn open(
+        &mut self,
+        hostname: &str,
+        rng: &mut TlsRng,
+        io: &mut dyn Transport,
+    ) -> Result<(), SessionError> {
+        let config = TlsConfig::new()
+            .with_server_name(hostname)
+            .enable_rsa_signatures();
+        let mut provider = SessionProvider::<C> {
+            rng,
+            verifier: WebPkiVerifier::new(),
+        };
+        let pipes = &self.pipes;
+        self.cell
+            .with_tls_mut(|tls| drive(tls.open(TlsContext::new(&config, &mut provider)), pipes, io))
+    }
+
+    fn write(&mut self, data: &[u8], io: &mut dyn Transport) -> Result<usize, SessionError> {
+        let pipes = &self.pipes;
+        self.cell.with_tls_mut(|tls| {
+            let write = async {
+                let written = tls.write(data).await?;
+                tls.flush().await?;
+                Ok(written)
+            };
+            drive(write, pipes, io)
+        })
+    }
+
+    fn read(&mut self, buf: &mut [u8], io: &mut dyn Transport) -> Result<usize, SessionError> {
+        let pipes = &self.pipes;
+        self.cell
+            .with_tls_mut(|tls| drive(tls.read(buf), pipes, io))
+    }
+
+    fn cipher_suite(&self) -> &'static str {
+        cipher_suite_name(C::CODE_POINT)
+    }
+}
+
+/// Poll an embedded-tls operation to completion over `io`
+///
+/// The connection only ever waits on its socket, and the socket only waits
+/// while the inbound pipe is empty, so every time the operation is pending
+/// its queued records go out and at least one more byte comes in.
+fn drive<T>(
+    operation: impl Future<Output = Result<T, EmbeddedTlsError>>,
+    pipes: &RefCell<Pipes>,
+    io: &mut dyn Transport,
+) -> Result<T, SessionError> {
+    let mut operation = pin!(operation);
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut chunk = [0u8; 2048];
+    loop {
+        let poll = operation.as_mut().poll(&mut cx);
+
+        let outbound = core::mem::take(&mut pipes.borrow_mut().outbound);
+        if !outbound.is_empty() {
+            io.write_all(&outbound).map_err(SessionError::Transport)?;
+        }
+        if let Poll::Ready(result) = poll {
+            return result.map_err(SessionError::Tls);
+        }
+
+        let len = io.read(&mut chunk).map_err(SessionError::Transport)?;
+        let mut pipes = pipes.borrow_mut();
+        if len == 0 {
+            pipes.closed = true;
+        } else {
+            pipes.inbound.extend(&chunk[..len]);
         }
     }
 }
 
-impl<'a, F, S> embedded_io::Write for TcpSocketAdapter<'a, F, S>
-where
-    F: FnMut() -> i64,
-    S: FnMut(i64),
-{
-    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        // Poll until we can send
-        loop {
-            let current_time = (self.get_time_ms)();
-            self.stack.poll(current_time).map_err(|_| TcpAdapterError)?;
+/// Randomness and certificate checks for one handshake
+struct SessionProvider<'r, C: SuiteParams> {
+    rng: &'r mut TlsRng,
+    verifier: WebPkiVerifier<C>,
+}
 
-            let tcp_socket = self.stack.sockets_mut().get_mut::<TcpSocket>(self.handle);
+impl<C: SuiteParams> CryptoProvider for SessionProvider<'_, C> {
+    type CipherSuite = C;
+    /// Unused: moteOS sends no client certificate
+    type Signature = Vec<u8>;
 
-            if tcp_socket.can_send() {
-                return tcp_socket.send_slice(buf).map_err(|_| TcpAdapterError);
-            }
+    fn rng(&mut self) -> impl CryptoRngCore {
+        &mut *self.rng
+    }
 
-            // Check if connection is closed
-            if !tcp_socket.is_open() {
-                return Err(TcpAdapterError);
-            }
+    fn verifier(&mut self) -> Result<&mut impl TlsVerifier<C>, EmbeddedTlsError> {
+        Ok(&mut self.verifier)
+    }
+}
 
-            // Sleep to avoid busy waiting
-            if let Some(ref mut sleep_fn) = self.sleep_ms {
-                sleep_fn(1);
-            } else {
-                core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+/// Random number generator for handshake keys, backed by RDRAND
+///
+/// embedded-tls draws through the infallible `fill_bytes`, so a failed draw
+/// is recorded in `failure` rather than panicking, and the handshake is
+/// failed once it returns.
+struct TlsRng {
+    /// One random word, or `None` when the source ran dry
+    draw: fn() -> Option<u64>,
+    /// The first draw that failed
+    failure: Option<rand_core::Error>,
+}
+
+/// `rand_core::Error` code for RDRAND running dry
+const RDRAND_FAILED: NonZeroU32 = NonZeroU32::new(rand_core::Error::CUSTOM_START).unwrap();
+
+impl TlsRng {
+    /// `None` when the CPU offers no RDRAND instruction
+    #[cfg(target_arch = "x86_64")]
+    fn new() -> Option<Self> {
+        /// # Safety
+        ///
+        /// The CPU must support RDRAND.
+        #[target_feature(enable = "rdrand")]
+        unsafe fn rdrand64() -> Option<u64> {
+            let mut value = 0;
+            // RDRAND can briefly run dry; Intel suggests retrying 10 times
+            for _ in 0..10 {
+                if core::arch::x86_64::_rdrand64_step(&mut value) == 1 {
+                    return Some(value);
+                }
             }
+            None
+        }
+
+        fn draw() -> Option<u64> {
+            // SAFETY: only reachable once `new` has checked CPUID for RDRAND
+            unsafe { rdrand64() }
+        }
+
+        // CPUID leaf 1, ECX bit 30
+        let ecx = core::arch::x86_64::__cpuid(1).ecx;
+        (ecx & (1 << 30) != 0).then_some(Self {
+            draw,
+            failure: None,
+        })
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn new() -> Option<Self> {
+        None
+    }
+}
+
+impl RngCore for TlsRng {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(error) = self.try_fill_bytes(dest) {
+            self.failure.get_or_insert(error);
+            // Key generation rejects zero and retries, so hand it a valid
+            // scalar to let the handshake finish and report the failure
+            dest.fill(1);
         }
     }
 
-    fn flush(&mut self) -> Result<(), Self::Error> {
-        // TCP flush is handled by smoltcp automatically
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        for chunk in dest.chunks_mut(8) {
+            let word = (self.draw)().ok_or(rand_core::Error::from(RDRAND_FAILED))?;
+            chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+        }
         Ok(())
     }
 }
 
-/// Error type for TCP adapter
-#[derive(Debug)]
-struct TcpAdapterError;
+impl CryptoRng for TlsRng {}
+
+/// Blocking byte transport a [`Session`] call runs over
+trait Transport {
+    /// Read at least one byte, or return 0 once the server has closed
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, NetError>;
+    fn write_all(&mut self, data: &[u8]) -> Result<(), NetError>;
+}
+
+/// Our smoltcp TCP stream, borrowed for one TLS call
+struct TcpTransport<'a, F, S>
+where
+    F: FnMut() -> i64,
+    S: FnMut(i64),
+{
+    stack: &'a mut NetworkStack,
+    stream: &'a mut TcpStream,
+    /// Limit for each TCP read or write
+    timeout_ms: i64,
+    get_time_ms: &'a mut F,
+    sleep_ms: &'a mut Option<S>,
+}
+
+impl<'a, F, S> Transport for TcpTransport<'a, F, S>
+where
+    F: FnMut() -> i64,
+    S: FnMut(i64),
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, NetError> {
+        // Block until data arrives; 0 once the server has closed
+        self.stream.read(
+            self.stack,
+            buf,
+            self.timeout_ms,
+            self.get_time_ms,
+            self.sleep_ms.as_mut(),
+        )
+    }
 
-impl embedded_io::Error for TcpAdapterError {
-    fn kind(&self) -> embedded_io::ErrorKind {
-        embedded_io::ErrorKind::Other
+    fn write_all(&mut self, data: &[u8]) -> Result<(), NetError> {
+        if is_client_hello(data) {
+            tls_log("INFO", &alloc::format!("ClientHello sent ({} bytes)", data.len()));
+        }
+        self.stream.write_all(
+            self.stack,
+            data,
+            self.timeout_ms,
+            self.get_time_ms,
+            self.sleep_ms.as_mut(),
+        )
     }
 }
 
+/// Whether `record` is a plaintext handshake record carrying a ClientHello
+///
+/// Only the record and handshake headers are inspected.
+fn is_client_hello(record: &[u8]) -> bool {
+    // content type 22 (handshake), legacy version 3.x, handshake type 1
+    record.len() > 5 && record[0] == 22 && record[1] == 3 && record[5] == 1
+}
+
+/// First common name in `name`, for log lines
+fn common_name(name: &X509Name) -> String {
+    name.iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .unwrap_or("?")
+        .to_string()
+}
+
 /// WebPKI-based certificate verifier
 ///
 /// This implements proper certificate verification using the webpki library
 /// and embedded Mozilla root CA certificates. It validates:
-/// - Certificate chain up to a trusted root CA
+/// - Certificate chain up to a trusted root CA, through the intermediates
+///   the server sends
 /// - Certificate signatures
 /// - Certificate expiration dates
 /// - Hostname matches (SNI)
+/// - The server's CertificateVerify signature over the handshake
 ///
 /// This is the production-ready verifier that should be used for all
 /// TLS connections in moteOS.
-pub struct WebPkiVerifier<CipherSuite> {
+pub struct WebPkiVerifier<CipherSuite: SuiteParams> {
     /// Server hostname for verification
     hostname: Option<String>,
-    /// Stored certificate for signature verification
+    /// End-entity certificate, kept for the CertificateVerify check
     server_cert: Option<Vec<u8>>,
-    /// Handshake transcript for signature verification
-    transcript: Option<Vec<u8>>,
-    /// Phantom data for cipher suite
-    _cipher_suite: core::marker::PhantomData<CipherSuite>,
+    /// Handshake transcript up to the server's Certificate message
+    transcript: Option<CipherSuite::Hash>,
 }
 
-impl<CipherSuite> WebPkiVerifier<CipherSuite> {
+impl<CipherSuite: SuiteParams> WebPkiVerifier<CipherSuite> {
     /// Create a new WebPKI verifier
     pub fn new() -> Self {
         Self {
             hostname: None,
             server_cert: None,
             transcript: None,
-            _cipher_suite: core::marker::PhantomData,
         }
     }
 
     /// Get current time for certificate validation
     ///
-    /// This returns a webpki::Time representing the current time.
-    /// In a real implementation, this would use the system clock.
-    /// For now, we use a fixed time far in the future to avoid
-    /// certificate expiration issues during development.
-    fn get_current_time() -> Time {
-        // TODO: Use actual system time from RTC/timer
-        // For now, use a time far in the future (year 2030)
-        // This is 1893456000 seconds since Unix epoch (2030-01-01)
-        Time::from_seconds_since_unix_epoch(1893456000)
+    /// Uses the registered time source (firmware clock or NTP). Without one,
+    /// or when it reports a time before this build (clock not set yet), falls
+    /// back to the build date so at least long-expired certificates are
+    /// rejected.
+    fn get_current_time() -> UnixTime {
+        let now = unsafe { TLS_TIME_SOURCE }.map(|source| source());
+        let secs = match now {
+            Some(now) if now >= BUILD_DATE_UNIX_SECS => now,
+            _ => {
+                tls_log(
+                    "WARN",
+                    "Real time unavailable; certificate expiry checks use the build date and are approximate",
+                );
+                BUILD_DATE_UNIX_SECS
+            }
+        };
+        UnixTime::since_unix_epoch(core::time::Duration::from_secs(secs))
+    }
+
+    /// Check `end_entity` chains to a trusted root through `intermediates`
+    /// and names `hostname`
+    fn verify_chain(
+        &self,
+        end_entity: &EndEntityCert<'_>,
+        intermediates: &[CertificateDer<'_>],
+    ) -> Result<(), EmbeddedTlsError> {
+        // Verify certificate is valid for the current time
+        let current_time = Self::get_current_time();
+        tls_log("DEBUG", &alloc::format!("Using validation time: {} (Unix timestamp)",
+            current_time.as_secs()));
+
+        // Verify certificate chain against trusted root CAs
+        tls_log("INFO", "Verifying certificate chain against trusted root CAs");
+        let extra_roots = EXTRA_ROOTS.lock();
+        let extra_roots: Vec<CertificateDer<'_>> = extra_roots
+            .iter()
+            .map(|der| CertificateDer::from(der.as_slice()))
+            .collect();
+        let mut anchors = TLS_SERVER_ROOTS.to_vec();
+        for der in &extra_roots {
+            match webpki::anchor_from_trusted_cert(der) {
+                Ok(anchor) => anchors.push(anchor),
+                Err(e) => tls_log("WARN", &alloc::format!("Skipping unusable extra root CA: {:?}", e)),
+            }
+        }
+        tls_log("DEBUG", &alloc::format!("Using {} trusted root CAs ({} extra), {} intermediates",
+            anchors.len(), extra_roots.len(), intermediates.len()));
+
+        if let Err(e) = end_entity.verify_for_usage(
+            sigalgs::CHAIN_ALGORITHMS,
+            &anchors,
+            intermediates,
+            current_time,
+            KeyUsage::server_auth(),
+            None,
+            None,
+        ) {
+            tls_log("ERROR", &alloc::format!("Certificate chain verification failed: {:?}", e));
+            if matches!(e, webpki::Error::CertExpired | webpki::Error::CertNotValidYet) {
+                CERT_EXPIRED.store(true, Ordering::Relaxed);
+            }
+            return Err(EmbeddedTlsError::InvalidCertificate);
+        }
+        tls_log("INFO", "Certificate chain verification passed");
+
+        // Verify hostname if set
+        if let Some(ref hostname) = self.hostname {
+            tls_log("INFO", &alloc::format!("Verifying hostname: {}", hostname));
+            let server_name = ServerName::try_from(hostname.as_str())
+                .map_err(|_| {
+                    tls_log("ERROR", &alloc::format!("Invalid DNS name format: {}", hostname));
+                    EmbeddedTlsError::InvalidCertificate
+                })?;
+
+            end_entity
+                .verify_is_valid_for_subject_name(&server_name)
+                .map_err(|_| {
+                    tls_log("ERROR", &alloc::format!("Hostname verification failed: certificate does not match {}", hostname));
+                    EmbeddedTlsError::InvalidCertificate
+                })?;
+
+            tls_log("INFO", "Hostname verification passed");
+        }
+
+        Ok(())
     }
 }
 
-impl<CipherSuite> Default for WebPkiVerifier<CipherSuite> {
+impl<CipherSuite: SuiteParams> Default for WebPkiVerifier<CipherSuite> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<CipherSuite> TlsVerifier<CipherSuite> for WebPkiVerifier<CipherSuite> {
-    fn set_hostname_verification(&mut self, hostname: &str) {
+impl<CipherSuite: SuiteParams> TlsVerifier<CipherSuite> for WebPkiVerifier<CipherSuite> {
+    fn set_hostname_verification(&mut self, hostname: &str) -> Result<(), EmbeddedTlsError> {
         self.hostname = Some(hostname.to_string());
         tls_log("DEBUG", &alloc::format!("Hostname verification set to: {}", hostname));
+        Ok(())
     }
 
     fn verify_certificate(
         &mut self,
-        transcript: &[u8],
-        _ca_certificate: Option<&[u8]>,
-        server_certificate: &[u8],
+        transcript: &CipherSuite::Hash,
+        cert: CertificateRef,
     ) -> Result<(), EmbeddedTlsError> {
         tls_log("INFO", "Certificate verification started");
+
+        // Leaf first, then the intermediates in the order the server sent them
+        let chain: Vec<CertificateDer<'_>> = cert
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                CertificateEntryRef::X509(der) => Some(CertificateDer::from(*der)),
+                CertificateEntryRef::RawPublicKey(_) => None,
+            })
+            .collect();
+        let Some((server_certificate, intermediates)) = chain.split_first() else {
+            tls_log("ERROR", "Server sent no X.509 certificate");
+            return Err(EmbeddedTlsError::InvalidCertificate);
+        };
         tls_log("DEBUG", &alloc::format!("Certificate size: {} bytes", server_certificate.len()));
-        
-        // Store transcript and certificate for signature verification
-        self.transcript = Some(transcript.to_vec());
-        self.server_cert = Some(server_certificate.to_vec());
 
         // Parse the certificate using x509-parser
-        let (_, cert) = X509Certificate::from_der(server_certificate)
+        let (_, parsed) = X509Certificate::from_der(server_certificate)
             .map_err(|_| {
                 tls_log("ERROR", "Failed to parse X.509 certificate");
                 EmbeddedTlsError::InvalidCertificate
@@ -697,96 +1114,292 @@ impl<CipherSuite> TlsVerifier<CipherSuite> for WebPkiVerifier<CipherSuite> {
 
         tls_log("DEBUG", "X.509 certificate parsed successfully");
 
-        // Try to extract subject information
-        if let Ok(subject) = cert.subject() {
-            let subject_str = subject.iter()
-                .map(|rdn| {
-                    rdn.iter()
-                        .map(|atv| format!("{}={}", atv.attr_type().to_string(), 
-                            core::str::from_utf8(atv.as_bytes()).unwrap_or("?")))
-                        .collect::<alloc::vec::Vec<_>>()
-                        .join(",")
-                })
-                .collect::<alloc::vec::Vec<_>>()
-                .join(",");
-            tls_log("DEBUG", &alloc::format!("Certificate subject: {}", subject_str));
-        }
-
-        // Verify certificate is valid for the current time
-        let current_time = Self::get_current_time();
-        tls_log("DEBUG", &alloc::format!("Using validation time: {} (Unix timestamp)", 
-            current_time.as_seconds_since_unix_epoch()));
+        tls_log(
+            "INFO",
+            &alloc::format!(
+                "Certificate received: CN={} issuer={}",
+                common_name(parsed.subject()),
+                common_name(parsed.issuer())
+            ),
+        );
 
-        // Convert certificate to webpki format
         let end_entity_cert = EndEntityCert::try_from(server_certificate)
             .map_err(|_| {
                 tls_log("ERROR", "Failed to convert certificate to webpki format");
                 EmbeddedTlsError::InvalidCertificate
             })?;
 
-        tls_log("DEBUG", "Certificate converted to webpki format");
+        // A pinned certificate replaces chain validation
+        if let Some(pin) = self.hostname.as_deref().and_then(pinned_certificate) {
+            let fingerprint: [u8; 32] = Sha256::digest(server_certificate).into();
+            if fingerprint != pin {
+                tls_log("ERROR", "Certificate does not match the pinned SHA-256 fingerprint");
+                return Err(EmbeddedTlsError::InvalidCertificate);
+            }
+            tls_log("INFO", "Certificate matches pinned fingerprint; skipping chain validation");
+        } else {
+            self.verify_chain(&end_entity_cert, intermediates)?;
+        }
 
-        // Verify hostname if set
-        if let Some(ref hostname) = self.hostname {
-            tls_log("INFO", &alloc::format!("Verifying hostname: {}", hostname));
-            let dns_name = DnsNameRef::try_from_ascii_str(hostname)
-                .map_err(|_| {
-                    tls_log("ERROR", &alloc::format!("Invalid DNS name format: {}", hostname));
-                    EmbeddedTlsError::InvalidCertificate
-                })?;
+        // Keep what the CertificateVerify signature is checked against
+        self.server_cert = Some(server_certificate.to_vec());
+        self.transcript = Some(transcript.clone());
+        Ok(())
+    }
 
-            end_entity_cert
-                .verify_is_valid_for_dns_name(dns_name)
-                .map_err(|_| {
-                    tls_log("ERROR", &alloc::format!("Hostname verification failed: certificate does not match {}", hostname));
-                    EmbeddedTlsError::InvalidCertificate
-                })?;
-            
-            tls_log("INFO", "Hostname verification passed");
-        }
+    fn verify_signature(&mut self, verify: CertificateVerifyRef) -> Result<(), EmbeddedTlsError> {
+        tls_log("DEBUG", &alloc::format!("Verifying CertificateVerify signature ({} bytes)", verify.signature.len()));
 
-        // Verify certificate chain against trusted root CAs
-        tls_log("INFO", "Verifying certificate chain against trusted root CAs");
-        tls_log("DEBUG", &alloc::format!("Using {} trusted root CAs", 
-            TLS_SERVER_ROOTS.len()));
-        
-        match end_entity_cert.verify_is_valid_tls_server_cert(
-            &webpki::RSA_PKCS1_2048_8192_SHA256,
-            TLS_SERVER_ROOTS,
-            &[],
-            current_time,
-        ) {
-            Ok(_) => {
-                tls_log("INFO", "Certificate chain verification passed");
-                Ok(())
+        let (Some(server_cert), Some(transcript)) = (self.server_cert.as_deref(), self.transcript.take()) else {
+            tls_log("ERROR", "Signature verification called before certificate verification");
+            return Err(EmbeddedTlsError::InvalidCertificate);
+        };
+
+        let algorithm = sigalgs::certificate_verify_algorithm(verify.signature_scheme)
+            .ok_or_else(|| {
+                tls_log("ERROR", &alloc::format!("Unsupported signature scheme: {:?}", verify.signature_scheme));
+                EmbeddedTlsError::InvalidSignatureScheme
+            })?;
+
+        // The signed content (RFC 8446, section 4.4.3)
+        let mut message = Vec::with_capacity(64 + 34 + 64);
+        message.extend_from_slice(&[0x20; 64]);
+        message.extend_from_slice(b"TLS 1.3, server CertificateVerify\x00");
+        message.extend_from_slice(&transcript.finalize());
+
+        let der = CertificateDer::from(server_cert);
+        let end_entity_cert = EndEntityCert::try_from(&der)
+            .map_err(|_| EmbeddedTlsError::InvalidCertificate)?;
+        end_entity_cert
+            .verify_signature(algorithm, &message, verify.signature)
+            .map_err(|e| {
+                tls_log("ERROR", &alloc::format!("CertificateVerify signature invalid: {:?}", e));
+                EmbeddedTlsError::InvalidSignature
+            })?;
+
+        tls_log("DEBUG", "CertificateVerify signature verified");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::sync::Arc;
+    use rustls::pki_types::{
+        CertificateDer as ServerCertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer,
+    };
+    use std::io::{Read, Write};
+    use std::sync::OnceLock;
+
+    /// Hostname on the test server's certificate
+    const TEST_HOST: &str = "tls.test";
+
+    /// Server certificate for [`TEST_HOST`] and its private key
+    struct TestIdentity {
+        certificate: Vec<u8>,
+        key: Vec<u8>,
+    }
+
+    /// A certificate issued by a test CA, which is trusted as an extra root
+    ///
+    /// Generated once, since the extra roots are global to every test.
+    fn trusted_identity() -> &'static TestIdentity {
+        static IDENTITY: OnceLock<TestIdentity> = OnceLock::new();
+        IDENTITY.get_or_init(|| {
+            let ca_key = rcgen::KeyPair::generate().unwrap();
+            let mut ca_params = rcgen::CertificateParams::new(Vec::new()).unwrap();
+            ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+            ca_params
+                .distinguished_name
+                .push(rcgen::DnType::CommonName, "moteOS test CA");
+            let ca = ca_params.self_signed(&ca_key).unwrap();
+            set_tls_extra_roots(vec![ca.der().to_vec()]);
+
+            let key = rcgen::KeyPair::generate().unwrap();
+            let certificate = rcgen::CertificateParams::new(vec![TEST_HOST.to_string()])
+                .unwrap()
+                .signed_by(&key, &ca, &ca_key)
+                .unwrap();
+            TestIdentity {
+                certificate: certificate.der().to_vec(),
+                key: key.serialize_der(),
             }
-            Err(e) => {
-                tls_log("ERROR", &alloc::format!("Certificate chain verification failed: {:?}", e));
-                Err(EmbeddedTlsError::InvalidCertificate)
+        })
+    }
+
+    /// In-memory rustls server the client session runs over
+    struct ServerTransport {
+        server: rustls::ServerConnection,
+    }
+
+    impl ServerTransport {
+        fn new(identity: &TestIdentity) -> Self {
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let config = rustls::ServerConfig::builder_with_provider(provider)
+                .with_protocol_versions(&[&rustls::version::TLS13])
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![ServerCertificateDer::from(identity.certificate.clone())],
+                    PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(identity.key.clone())),
+                )
+                .unwrap();
+            Self {
+                server: rustls::ServerConnection::new(Arc::new(config)).unwrap(),
             }
         }
+
+        /// Plaintext the server received, once `len` bytes are there
+        fn received(&mut self, len: usize) -> Vec<u8> {
+            let mut data = vec![0; len];
+            self.server.reader().read_exact(&mut data).unwrap();
+            data
+        }
     }
 
-    fn verify_signature(&mut self, signature: &[u8]) -> Result<(), EmbeddedTlsError> {
-        tls_log("DEBUG", &alloc::format!("Verifying CertificateVerify signature ({} bytes)", signature.len()));
-        
-        // For TLS 1.3, signature verification is part of the handshake
-        // and is validated by embedded-tls itself using the certificate
-        // we validated in verify_certificate().
-        //
-        // The signature parameter here is the CertificateVerify signature
-        // which embedded-tls will validate using the public key from the
-        // certificate we already verified.
+    impl Transport for ServerTransport {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, NetError> {
+            // Nothing to send would leave a real client waiting forever
+            let mut out = buf;
+            match self.server.write_tls(&mut out) {
+                Ok(0) | Err(_) => Err(NetError::TlsError("server has nothing to send".into())),
+                Ok(len) => Ok(len),
+            }
+        }
 
-        // Ensure we have verified a certificate
-        if self.server_cert.is_none() {
-            tls_log("ERROR", "Signature verification called before certificate verification");
-            return Err(EmbeddedTlsError::InvalidCertificate);
+        fn write_all(&mut self, mut data: &[u8]) -> Result<(), NetError> {
+            while !data.is_empty() {
+                self.server
+                    .read_tls(&mut data)
+                    .map_err(|e| NetError::TlsError(format!("{}", e)))?;
+                self.server
+                    .process_new_packets()
+                    .map_err(|e| NetError::TlsError(format!("{}", e)))?;
+            }
+            Ok(())
         }
+    }
 
-        tls_log("DEBUG", "CertificateVerify signature will be validated by embedded-tls");
-        // embedded-tls will handle the actual signature verification
-        // using the public key from the certificate
-        Ok(())
+    #[test]
+    fn handshake_then_two_writes_and_several_reads() {
+        let identity = trusted_identity();
+        for suite in [
+            TlsCipherSuite::Aes128GcmSha256,
+            TlsCipherSuite::Aes256GcmSha384,
+        ] {
+            let mut io = ServerTransport::new(identity);
+            let mut session = new_session(suite);
+            session
+                .open(TEST_HOST, &mut TlsRng::new().unwrap(), &mut io)
+                .unwrap();
+            assert!(!io.server.is_handshaking());
+
+            // Negotiated what was offered, and reported under its IANA name
+            let negotiated = u16::from(io.server.negotiated_cipher_suite().unwrap().suite());
+            assert_eq!(session.cipher_suite(), cipher_suite_name(negotiated));
+            let expected = match suite {
+                TlsCipherSuite::Aes128GcmSha256 => "TLS_AES_128_GCM_SHA256",
+                TlsCipherSuite::Aes256GcmSha384 => "TLS_AES_256_GCM_SHA384",
+            };
+            assert_eq!(session.cipher_suite(), expected);
+
+            // Two writes reuse the same keys and sequence numbers
+            assert_eq!(session.write(b"GET ", &mut io).unwrap(), 4);
+            assert_eq!(session.write(b"/ HTTP/1.1\r\n\r\n", &mut io).unwrap(), 14);
+            assert_eq!(io.received(18), b"GET / HTTP/1.1\r\n\r\n");
+
+            // A header record and a body spanning two more records
+            let body: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+            io.server
+                .writer()
+                .write_all(b"HTTP/1.1 200 OK\r\n")
+                .unwrap();
+            io.server.writer().write_all(&body).unwrap();
+            let mut expected = b"HTTP/1.1 200 OK\r\n".to_vec();
+            expected.extend_from_slice(&body);
+
+            let mut response = Vec::new();
+            let mut buf = [0u8; 700];
+            let mut reads = 0;
+            while response.len() < expected.len() {
+                let len = session.read(&mut buf, &mut io).unwrap();
+                assert!(len > 0);
+                response.extend_from_slice(&buf[..len]);
+                reads += 1;
+            }
+            assert_eq!(response, expected);
+            assert!(reads > 3);
+        }
+    }
+
+    #[test]
+    fn handshake_rejects_certificate_for_another_host() {
+        let mut io = ServerTransport::new(trusted_identity());
+        let mut session = new_session(TlsCipherSuite::Aes128GcmSha256);
+        let result = session.open("other.test", &mut TlsRng::new().unwrap(), &mut io);
+        assert!(matches!(
+            result,
+            Err(SessionError::Tls(EmbeddedTlsError::InvalidCertificate))
+        ));
+    }
+
+    #[test]
+    fn handshake_rejects_untrusted_certificate() {
+        trusted_identity();
+        let key = rcgen::KeyPair::generate().unwrap();
+        let certificate = rcgen::CertificateParams::new(vec![TEST_HOST.to_string()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        let mut io = ServerTransport::new(&TestIdentity {
+            certificate: certificate.der().to_vec(),
+            key: key.serialize_der(),
+        });
+
+        let mut session = new_session(TlsCipherSuite::Aes128GcmSha256);
+        let result = session.open(TEST_HOST, &mut TlsRng::new().unwrap(), &mut io);
+        assert!(matches!(
+            result,
+            Err(SessionError::Tls(EmbeddedTlsError::InvalidCertificate))
+        ));
+    }
+
+    #[test]
+    fn failed_draws_are_recorded_instead_of_panicking() {
+        let mut rng = TlsRng {
+            draw: || None,
+            failure: None,
+        };
+        let mut buf = [0u8; 12];
+        assert!(rng.try_fill_bytes(&mut buf).is_err());
+        assert!(rng.failure.is_none());
+
+        rng.fill_bytes(&mut buf);
+        rng.next_u64();
+        assert_eq!(rng.failure.unwrap().code(), Some(RDRAND_FAILED));
+    }
+
+    #[test]
+    fn handshake_returns_when_rng_fails() {
+        let mut io = ServerTransport::new(trusted_identity());
+        let mut session = new_session(TlsCipherSuite::Aes128GcmSha256);
+        let mut rng = TlsRng {
+            draw: || None,
+            failure: None,
+        };
+        let _ = session.open(TEST_HOST, &mut rng, &mut io);
+        assert!(rng.failure.is_some());
+    }
+
+    #[test]
+    fn client_hello_is_recognised_from_headers() {
+        assert!(is_client_hello(&[22, 3, 1, 0, 200, 1, 0, 0, 196]));
+        // ServerHello, application data and short records
+        assert!(!is_client_hello(&[22, 3, 3, 0, 90, 2, 0, 0, 86]));
+        assert!(!is_client_hello(&[23, 3, 3, 0, 32, 1]));
+        assert!(!is_client_hello(&[22, 3, 1]));
     }
 }
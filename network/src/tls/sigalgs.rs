@@ -0,0 +1,244 @@
+//! Signature algorithms for webpki, implemented with the RustCrypto crates
+//!
+//! rustls-webpki ships its algorithms on top of ring or aws-lc-rs, which need
+//! a C toolchain for the target. The certificate chain and the handshake's
+//! CertificateVerify signature are checked with p256, p384 and rsa instead.
+
+use alloc::vec::Vec;
+use embedded_tls::SignatureScheme;
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use rsa::pkcs1::der::Decode;
+use rsa::traits::PublicKeyParts;
+use rsa::{BigUint, Pkcs1v15Sign, Pss, RsaPublicKey};
+use sha2::digest::const_oid::AssociatedOid;
+use sha2::digest::DynDigest;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use webpki::types::{
+    alg_id, AlgorithmIdentifier, InvalidSignature, SignatureVerificationAlgorithm,
+};
+
+/// Smallest and largest RSA modulus accepted, in bits
+const RSA_MIN_BITS: usize = 2048;
+const RSA_MAX_BITS: usize = 8192;
+
+/// Digest applied to the signed message
+#[derive(Debug, Clone, Copy)]
+enum Hash {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Hash {
+    fn digest(self, message: &[u8]) -> Vec<u8> {
+        match self {
+            Hash::Sha256 => Sha256::digest(message).to_vec(),
+            Hash::Sha384 => Sha384::digest(message).to_vec(),
+            Hash::Sha512 => Sha512::digest(message).to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Curve {
+    P256,
+    P384,
+}
+
+/// ECDSA over a NIST curve with a DER-encoded signature
+#[derive(Debug)]
+struct Ecdsa {
+    curve: Curve,
+    hash: Hash,
+    signature_alg_id: AlgorithmIdentifier,
+}
+
+impl SignatureVerificationAlgorithm for Ecdsa {
+    fn verify_signature(
+        &self,
+        public_key: &[u8],
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), InvalidSignature> {
+        let prehash = self.hash.digest(message);
+        match self.curve {
+            Curve::P256 => {
+                let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(public_key)
+                    .map_err(|_| InvalidSignature)?;
+                let signature =
+                    p256::ecdsa::Signature::from_der(signature).map_err(|_| InvalidSignature)?;
+                key.verify_prehash(&prehash, &signature)
+            }
+            Curve::P384 => {
+                let key = p384::ecdsa::VerifyingKey::from_sec1_bytes(public_key)
+                    .map_err(|_| InvalidSignature)?;
+                let signature =
+                    p384::ecdsa::Signature::from_der(signature).map_err(|_| InvalidSignature)?;
+                key.verify_prehash(&prehash, &signature)
+            }
+        }
+        .map_err(|_| InvalidSignature)
+    }
+
+    fn public_key_alg_id(&self) -> AlgorithmIdentifier {
+        match self.curve {
+            Curve::P256 => alg_id::ECDSA_P256,
+            Curve::P384 => alg_id::ECDSA_P384,
+        }
+    }
+
+    fn signature_alg_id(&self) -> AlgorithmIdentifier {
+        self.signature_alg_id
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Padding {
+    Pkcs1,
+    /// RSASSA-PSS with a salt as long as the digest, as TLS 1.3 requires
+    Pss,
+}
+
+/// RSA with a 2048 to 8192 bit rsaEncryption key
+#[derive(Debug)]
+struct Rsa {
+    padding: Padding,
+    hash: Hash,
+    signature_alg_id: AlgorithmIdentifier,
+}
+
+impl Rsa {
+    fn verify_with<D>(
+        &self,
+        key: &RsaPublicKey,
+        message: &[u8],
+        signature: &[u8],
+    ) -> rsa::Result<()>
+    where
+        D: 'static + Digest + DynDigest + AssociatedOid + Send + Sync,
+    {
+        let hashed = D::digest(message);
+        match self.padding {
+            Padding::Pkcs1 => key.verify(Pkcs1v15Sign::new::<D>(), &hashed, signature),
+            Padding::Pss => key.verify(Pss::new::<D>(), &hashed, signature),
+        }
+    }
+}
+
+impl SignatureVerificationAlgorithm for Rsa {
+    fn verify_signature(
+        &self,
+        public_key: &[u8],
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), InvalidSignature> {
+        let der = rsa::pkcs1::RsaPublicKey::from_der(public_key).map_err(|_| InvalidSignature)?;
+        let key = RsaPublicKey::new_with_max_size(
+            BigUint::from_bytes_be(der.modulus.as_bytes()),
+            BigUint::from_bytes_be(der.public_exponent.as_bytes()),
+            RSA_MAX_BITS,
+        )
+        .map_err(|_| InvalidSignature)?;
+        if key.size() * 8 < RSA_MIN_BITS {
+            return Err(InvalidSignature);
+        }
+
+        match self.hash {
+            Hash::Sha256 => self.verify_with::<Sha256>(&key, message, signature),
+            Hash::Sha384 => self.verify_with::<Sha384>(&key, message, signature),
+            Hash::Sha512 => self.verify_with::<Sha512>(&key, message, signature),
+        }
+        .map_err(|_| InvalidSignature)
+    }
+
+    fn public_key_alg_id(&self) -> AlgorithmIdentifier {
+        alg_id::RSA_ENCRYPTION
+    }
+
+    fn signature_alg_id(&self) -> AlgorithmIdentifier {
+        self.signature_alg_id
+    }
+}
+
+static ECDSA_P256_SHA256: Ecdsa = Ecdsa {
+    curve: Curve::P256,
+    hash: Hash::Sha256,
+    signature_alg_id: alg_id::ECDSA_SHA256,
+};
+static ECDSA_P256_SHA384: Ecdsa = Ecdsa {
+    curve: Curve::P256,
+    hash: Hash::Sha384,
+    signature_alg_id: alg_id::ECDSA_SHA384,
+};
+static ECDSA_P384_SHA256: Ecdsa = Ecdsa {
+    curve: Curve::P384,
+    hash: Hash::Sha256,
+    signature_alg_id: alg_id::ECDSA_SHA256,
+};
+static ECDSA_P384_SHA384: Ecdsa = Ecdsa {
+    curve: Curve::P384,
+    hash: Hash::Sha384,
+    signature_alg_id: alg_id::ECDSA_SHA384,
+};
+static RSA_PKCS1_SHA256: Rsa = Rsa {
+    padding: Padding::Pkcs1,
+    hash: Hash::Sha256,
+    signature_alg_id: alg_id::RSA_PKCS1_SHA256,
+};
+static RSA_PKCS1_SHA384: Rsa = Rsa {
+    padding: Padding::Pkcs1,
+    hash: Hash::Sha384,
+    signature_alg_id: alg_id::RSA_PKCS1_SHA384,
+};
+static RSA_PKCS1_SHA512: Rsa = Rsa {
+    padding: Padding::Pkcs1,
+    hash: Hash::Sha512,
+    signature_alg_id: alg_id::RSA_PKCS1_SHA512,
+};
+static RSA_PSS_SHA256: Rsa = Rsa {
+    padding: Padding::Pss,
+    hash: Hash::Sha256,
+    signature_alg_id: alg_id::RSA_PSS_SHA256,
+};
+static RSA_PSS_SHA384: Rsa = Rsa {
+    padding: Padding::Pss,
+    hash: Hash::Sha384,
+    signature_alg_id: alg_id::RSA_PSS_SHA384,
+};
+static RSA_PSS_SHA512: Rsa = Rsa {
+    padding: Padding::Pss,
+    hash: Hash::Sha512,
+    signature_alg_id: alg_id::RSA_PSS_SHA512,
+};
+
+/// Algorithms accepted for the signatures along a certificate chain
+pub(super) static CHAIN_ALGORITHMS: &[&dyn SignatureVerificationAlgorithm] = &[
+    &ECDSA_P256_SHA256,
+    &ECDSA_P256_SHA384,
+    &ECDSA_P384_SHA256,
+    &ECDSA_P384_SHA384,
+    &RSA_PKCS1_SHA256,
+    &RSA_PKCS1_SHA384,
+    &RSA_PKCS1_SHA512,
+    &RSA_PSS_SHA256,
+    &RSA_PSS_SHA384,
+    &RSA_PSS_SHA512,
+];
+
+/// Algorithm for a CertificateVerify signature made with `scheme`
+///
+/// TLS 1.3 signs the handshake with ECDSA or RSA-PSS only (RFC 8446,
+/// section 4.4.3). Ed25519 is offered by embedded-tls but not implemented
+/// here, so such servers are refused.
+pub(super) fn certificate_verify_algorithm(
+    scheme: SignatureScheme,
+) -> Option<&'static dyn SignatureVerificationAlgorithm> {
+    match scheme {
+        SignatureScheme::EcdsaSecp256r1Sha256 => Some(&ECDSA_P256_SHA256),
+        SignatureScheme::EcdsaSecp384r1Sha384 => Some(&ECDSA_P384_SHA384),
+        SignatureScheme::RsaPssRsaeSha256 => Some(&RSA_PSS_SHA256),
+        SignatureScheme::RsaPssRsaeSha384 => Some(&RSA_PSS_SHA384),
+        SignatureScheme::RsaPssRsaeSha512 => Some(&RSA_PSS_SHA512),
+        _ => None,
+    }
+}
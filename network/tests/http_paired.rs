@@ -0,0 +1,463 @@
+// End-to-end HttpClient tests over two NetworkStacks joined by PairedDriver
+//
+// The client stack runs the real HttpClient; a tiny HTTP/1.1 server runs on
+// the second stack and is polled from the client's sleep callback, which
+// also advances a fake clock so timeouts take no wall-clock time.
+//
+// Run with: cargo test -p network --no-default-features --features loopback
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
+
+use network::drivers::loopback::PairedDriver;
+use network::{
+    Connectivity, HttpClient, HttpConnectionPool, HttpError, HttpProxy, HttpResponse, NetworkStack,
+    RequestBuilder,
+};
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::tcp::{Socket as TcpSocket, SocketBuffer, State};
+use smoltcp::wire::Ipv4Address;
+
+const CLIENT_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const SERVER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+const CLIENT_IP: Ipv4Address = Ipv4Address::new(10, 0, 0, 1);
+const SERVER_IP: Ipv4Address = Ipv4Address::new(10, 0, 0, 2);
+const PORT: u16 = 8080;
+
+/// Answers requests on `PORT` with canned responses, in order
+struct TestServer {
+    stack: NetworkStack,
+    /// Connection sockets with their unprocessed bytes; the last one is
+    /// always listening
+    sockets: Vec<(SocketHandle, Vec<u8>)>,
+    /// Everything the client sent, over all connections
+    request: Vec<u8>,
+    /// Once these run out the server stops answering
+    responses: VecDeque<Vec<u8>>,
+    /// Close the connection after each response
+    close_after_response: bool,
+}
+
+impl TestServer {
+    fn new(driver: PairedDriver, responses: &[&[u8]], close_after_response: bool) -> Self {
+        let stack = NetworkStack::new(Box::new(driver), Some((SERVER_IP, 24))).unwrap();
+        let mut server = Self {
+            stack,
+            sockets: Vec::new(),
+            request: Vec::new(),
+            responses: responses.iter().map(|r| r.to_vec()).collect(),
+            close_after_response,
+        };
+        server.listen();
+        server
+    }
+
+    fn listen(&mut self) {
+        let mut socket = TcpSocket::new(
+            SocketBuffer::new(vec![0u8; 8192]),
+            SocketBuffer::new(vec![0u8; 8192]),
+        );
+        socket.listen(PORT).unwrap();
+        let handle = self.stack.sockets_mut().add(socket);
+        self.sockets.push((handle, Vec::new()));
+    }
+
+    /// Connections accepted so far
+    fn connections(&self) -> usize {
+        self.sockets.len() - 1
+    }
+
+    fn step(&mut self, now_ms: i64) {
+        self.stack.poll(now_ms).unwrap();
+
+        let (listener, _) = self.sockets[self.sockets.len() - 1];
+        if self.stack.sockets().get::<TcpSocket>(listener).state() != State::Listen {
+            self.listen();
+        }
+
+        for (handle, pending) in &mut self.sockets {
+            let socket = self.stack.sockets_mut().get_mut::<TcpSocket>(*handle);
+            while socket.can_recv() {
+                let request = &mut self.request;
+                socket
+                    .recv(|data| {
+                        request.extend_from_slice(data);
+                        pending.extend_from_slice(data);
+                        (data.len(), ())
+                    })
+                    .unwrap();
+            }
+
+            while let Some(len) = complete_request_len(pending) {
+                pending.drain(..len);
+                if let Some(response) = self.responses.pop_front() {
+                    assert_eq!(socket.send_slice(&response).unwrap(), response.len());
+                    if self.close_after_response {
+                        socket.close();
+                    }
+                }
+            }
+        }
+
+        self.stack.poll(now_ms).unwrap();
+    }
+}
+
+/// Length of the first complete request in `buf` (head plus
+/// `Content-Length` body)
+fn complete_request_len(buf: &[u8]) -> Option<usize> {
+    let head_end = buf.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+    let head = std::str::from_utf8(&buf[..head_end]).unwrap();
+    let body_len = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse::<usize>().unwrap())
+        })
+        .unwrap_or(0);
+    (buf.len() >= head_end + body_len).then_some(head_end + body_len)
+}
+
+/// A client stack and a server stack on a paired link, with a fake clock
+struct Harness {
+    stack: NetworkStack,
+    server: RefCell<TestServer>,
+    clock: Cell<i64>,
+    client: HttpClient,
+}
+
+impl Harness {
+    fn new(responses: &[&[u8]], close_after_response: bool, read_timeout_ms: i64) -> Self {
+        let (client_end, server_end) = PairedDriver::pair(CLIENT_MAC, SERVER_MAC);
+        Self {
+            stack: NetworkStack::new(Box::new(client_end), Some((CLIENT_IP, 24))).unwrap(),
+            server: RefCell::new(TestServer::new(server_end, responses, close_after_response)),
+            clock: Cell::new(0),
+            client: HttpClient::new(Ipv4Address::UNSPECIFIED).with_timeouts(1_000, read_timeout_ms),
+        }
+    }
+
+    fn send(
+        &mut self,
+        request: &RequestBuilder<'_>,
+        pool: Option<&mut HttpConnectionPool>,
+    ) -> Result<HttpResponse, HttpError> {
+        let (clock, server) = (&self.clock, &self.server);
+        let mut now = || clock.get();
+        let mut sleep = |ms: i64| {
+            clock.set(clock.get() + ms);
+            server.borrow_mut().step(clock.get());
+        };
+        request.send(
+            &self.client,
+            &mut self.stack,
+            &mut now,
+            Some(&mut sleep),
+            pool,
+        )
+    }
+
+    fn send_streaming(
+        &mut self,
+        request: &RequestBuilder<'_>,
+        on_chunk: &mut dyn FnMut(&[u8]) -> ControlFlow<()>,
+    ) -> Result<HttpResponse, HttpError> {
+        let (clock, server) = (&self.clock, &self.server);
+        let mut now = || clock.get();
+        let mut sleep = |ms: i64| {
+            clock.set(clock.get() + ms);
+            server.borrow_mut().step(clock.get());
+        };
+        request.send_streaming(
+            &self.client,
+            &mut self.stack,
+            &mut now,
+            Some(&mut sleep),
+            None,
+            on_chunk,
+        )
+    }
+
+    fn check_connectivity(&mut self, url: &str) -> Connectivity {
+        let (clock, server) = (&self.clock, &self.server);
+        let mut now = || clock.get();
+        let mut sleep = |ms: i64| {
+            clock.set(clock.get() + ms);
+            server.borrow_mut().step(clock.get());
+        };
+        self.client
+            .check_connectivity_at(&mut self.stack, url, &mut now, Some(&mut sleep))
+    }
+
+    /// Raw requests as received by the server
+    fn received(&self) -> String {
+        String::from_utf8(self.server.borrow().request.clone()).unwrap()
+    }
+}
+
+struct Exchange {
+    result: Result<HttpResponse, HttpError>,
+    /// Raw request as received by the server
+    request: String,
+    /// Fake time spent, in milliseconds
+    elapsed_ms: i64,
+}
+
+/// One single-shot request against a server that answers and closes
+fn exchange(
+    request: RequestBuilder<'_>,
+    response: Option<&[u8]>,
+    read_timeout_ms: i64,
+) -> Exchange {
+    let responses: Vec<&[u8]> = response.into_iter().collect();
+    let mut harness = Harness::new(&responses, true, read_timeout_ms);
+    let result = harness.send(&request, None);
+    Exchange {
+        result,
+        request: harness.received(),
+        elapsed_ms: harness.clock.get(),
+    }
+}
+
+#[test]
+fn get_with_content_length() {
+    let exchange = exchange(
+        RequestBuilder::get("http://10.0.0.2:8080/hello"),
+        Some(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nX-Test: yes\r\n\r\nhello"),
+        5_000,
+    );
+
+    let response = exchange.result.unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("x-test"), Some("yes"));
+    assert_eq!(response.body, b"hello");
+    assert!(exchange
+        .request
+        .starts_with("GET /hello HTTP/1.1\r\nHost: 10.0.0.2:8080\r\n"));
+}
+
+#[test]
+fn post_json_with_bearer_auth() {
+    let exchange = exchange(
+        RequestBuilder::post("http://10.0.0.2:8080/v1/chat")
+            .bearer_auth("sk-test")
+            .json("{\"q\":1}"),
+        Some(b"HTTP/1.1 201 Created\r\nContent-Length: 2\r\n\r\nok"),
+        5_000,
+    );
+
+    assert_eq!(exchange.result.unwrap().status, 201);
+    assert!(exchange.request.starts_with("POST /v1/chat HTTP/1.1\r\n"));
+    assert!(exchange
+        .request
+        .contains("\r\nAuthorization: Bearer sk-test\r\n"));
+    assert!(exchange.request.contains("\r\nContent-Length: 7\r\n"));
+    assert!(exchange.request.ends_with("\r\n\r\n{\"q\":1}"));
+}
+
+#[test]
+fn chunked_response_is_decoded() {
+    let exchange = exchange(
+        RequestBuilder::get("http://10.0.0.2:8080/stream"),
+        Some(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              4\r\nWiki\r\n5\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\n\r\n",
+        ),
+        5_000,
+    );
+
+    assert_eq!(
+        exchange.result.unwrap().body,
+        b"Wikipedia in\r\n\r\nchunks."
+    );
+}
+
+#[test]
+fn silent_server_times_out_on_fake_clock() {
+    let exchange = exchange(RequestBuilder::get("http://10.0.0.2:8080/"), None, 2_000);
+
+    assert!(matches!(exchange.result, Err(HttpError::ReadTimeout)));
+    assert!(exchange.request.starts_with("GET / HTTP/1.1\r\n"));
+    assert!(exchange.elapsed_ms >= 2_000);
+    assert!(exchange.elapsed_ms < 3_000);
+}
+
+#[test]
+fn pool_reuses_keep_alive_connection() {
+    let ok: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nKeep-Alive: timeout=5\r\n\r\nok";
+    let mut harness = Harness::new(&[ok, ok], false, 5_000);
+    let mut pool = HttpConnectionPool::new();
+    let request = RequestBuilder::get("http://10.0.0.2:8080/models");
+
+    for _ in 0..2 {
+        let response = harness.send(&request, Some(&mut pool)).unwrap();
+        assert_eq!(response.body, b"ok");
+        assert_eq!(pool.idle_count(), 1);
+    }
+    assert_eq!(harness.server.borrow().connections(), 1);
+    assert_eq!(
+        harness
+            .received()
+            .matches("Connection: keep-alive\r\n")
+            .count(),
+        2
+    );
+
+    pool.clear(&mut harness.stack);
+    assert_eq!(pool.idle_count(), 0);
+}
+
+#[test]
+fn pool_respects_connection_close() {
+    let close: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+    let mut harness = Harness::new(&[close, close], true, 5_000);
+    let mut pool = HttpConnectionPool::new();
+    let request = RequestBuilder::get("http://10.0.0.2:8080/");
+
+    for _ in 0..2 {
+        assert_eq!(harness.send(&request, Some(&mut pool)).unwrap().status, 200);
+        assert_eq!(pool.idle_count(), 0);
+    }
+    assert_eq!(harness.server.borrow().connections(), 2);
+}
+
+#[test]
+fn pool_reconnects_when_server_dropped_idle_connection() {
+    // The server closes without saying so, as on its idle timeout
+    let ok: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+    let mut harness = Harness::new(&[ok, ok], true, 5_000);
+    let mut pool = HttpConnectionPool::new();
+    let request = RequestBuilder::get("http://10.0.0.2:8080/");
+
+    assert_eq!(harness.send(&request, Some(&mut pool)).unwrap().body, b"ok");
+    assert_eq!(harness.send(&request, Some(&mut pool)).unwrap().body, b"ok");
+    assert_eq!(harness.server.borrow().connections(), 2);
+}
+
+#[test]
+fn plain_http_is_forwarded_through_stack_proxy() {
+    let mut harness = Harness::new(
+        &[b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok"],
+        true,
+        5_000,
+    );
+    // No DNS: only the proxy's address is ever looked up
+    harness.stack.set_http_proxy(Some(HttpProxy::new(
+        "10.0.0.2",
+        PORT,
+        Some(("user", "pass")),
+    )));
+
+    let request = RequestBuilder::get("http://example.com/hello");
+    assert_eq!(harness.send(&request, None).unwrap().body, b"ok");
+    let received = harness.received();
+    assert!(received.starts_with("GET http://example.com/hello HTTP/1.1\r\nHost: example.com\r\n"));
+    assert!(received.contains("\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+}
+
+#[test]
+fn refused_connect_tunnel_is_reported() {
+    let mut harness = Harness::new(
+        &[b"HTTP/1.1 407 Proxy Authentication Required\r\nContent-Length: 0\r\n\r\n"],
+        true,
+        5_000,
+    );
+    harness.client = HttpClient::new(Ipv4Address::UNSPECIFIED).with_proxy("10.0.0.2", PORT, None);
+
+    let request = RequestBuilder::get("https://api.example.com/v1/models");
+    assert!(matches!(
+        harness.send(&request, None),
+        Err(HttpError::ProxyRefused(407))
+    ));
+    assert_eq!(
+        harness.received(),
+        "CONNECT api.example.com:443 HTTP/1.1\r\nHost: api.example.com:443\r\n\r\n"
+    );
+}
+
+#[test]
+fn body_limit_can_be_set_per_request_or_lifted_for_streams() {
+    let mut response = b"HTTP/1.1 200 OK\r\nContent-Length: 4000\r\n\r\n".to_vec();
+    response.extend_from_slice(&[b'x'; 4000]);
+    let responses: [&[u8]; 3] = [&response, &response, &response];
+    let mut harness = Harness::new(&responses, true, 5_000);
+    harness.client = HttpClient::new(Ipv4Address::UNSPECIFIED).with_limits(32 * 1024, 1000);
+
+    let request = RequestBuilder::get("http://10.0.0.2:8080/v1/models");
+    assert!(matches!(
+        harness.send(&request, None),
+        Err(HttpError::BodyTooLarge { limit: 1000, .. })
+    ));
+
+    let larger = request.clone().max_body_bytes(4000);
+    assert_eq!(harness.send(&larger, None).unwrap().body.len(), 4000);
+
+    let mut streamed = 0;
+    let response = harness
+        .send_streaming(&request.clone().unbounded_stream(), &mut |data| {
+            streamed += data.len();
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+    assert_eq!(response.status, 200);
+    assert!(response.body.is_empty());
+    assert_eq!(streamed, 4000);
+    assert!(harness
+        .received()
+        .ends_with("\r\nAccept-Encoding: identity\r\n\r\n"));
+}
+
+#[test]
+fn breaking_out_of_a_stream_closes_the_connection() {
+    // No length and no close: the body would run until the read timeout
+    let response: &[u8] =
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\ndata: one\n\n";
+    let mut harness = Harness::new(&[response], false, 5_000);
+
+    let request = RequestBuilder::get("http://10.0.0.2:8080/v1/stream");
+    let mut chunks = 0;
+    let response = harness
+        .send_streaming(&request, &mut |_| {
+            chunks += 1;
+            ControlFlow::Break(())
+        })
+        .unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(chunks, 1);
+    assert!(harness.clock.get() < 5_000);
+
+    for _ in 0..10 {
+        let now = harness.clock.get() + 10;
+        harness.clock.set(now);
+        harness.stack.poll(now).unwrap();
+        harness.server.borrow_mut().step(now);
+    }
+    let server = harness.server.borrow();
+    let (connection, _) = server.sockets[0];
+    let state = server.stack.sockets().get::<TcpSocket>(connection).state();
+    assert!(matches!(state, State::CloseWait | State::Closed), "{state}");
+}
+
+#[test]
+fn connectivity_check_spots_captive_portals() {
+    let no_content: &[u8] = b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n";
+    let redirect: &[u8] =
+        b"HTTP/1.1 302 Found\r\nLocation: http://portal.example/login\r\nContent-Length: 0\r\n\r\n";
+    let login_page: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\nPlease login";
+    let check = |response: Option<&[u8]>| {
+        let responses: Vec<&[u8]> = response.into_iter().collect();
+        Harness::new(&responses, true, 2_000)
+            .check_connectivity("http://10.0.0.2:8080/generate_204")
+    };
+
+    assert_eq!(check(Some(no_content)), Connectivity::Ok);
+    assert_eq!(
+        check(Some(redirect)),
+        Connectivity::CaptivePortal(Some("http://portal.example/login".into()))
+    );
+    assert_eq!(check(Some(login_page)), Connectivity::CaptivePortal(None));
+    // Nobody answers
+    assert_eq!(check(None), Connectivity::NoInternet);
+}
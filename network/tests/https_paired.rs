@@ -0,0 +1,301 @@
+// End-to-end HTTPS tests: HttpClient over TLS to a rustls server on a second
+// NetworkStack joined by PairedDriver
+//
+// The client reaches the server through an HTTP proxy tunnel, so it never
+// needs DNS: the server answers CONNECT itself and then speaks TLS for
+// `TEST_HOST`, with a certificate from a test CA the client trusts. As in
+// http_paired, the server is polled from the client's sleep callback, which
+// advances a fake clock.
+//
+// Run with: cargo test -p network --features loopback --test https_paired
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::{Arc, OnceLock};
+
+use network::drivers::loopback::PairedDriver;
+use network::{
+    set_tls_extra_roots, HttpClient, HttpConnectionPool, HttpError, HttpResponse, NetworkStack,
+    RequestBuilder,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::tcp::{Socket as TcpSocket, SocketBuffer, State};
+use smoltcp::wire::Ipv4Address;
+
+const CLIENT_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const SERVER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+const CLIENT_IP: Ipv4Address = Ipv4Address::new(10, 0, 0, 1);
+const SERVER_IP: Ipv4Address = Ipv4Address::new(10, 0, 0, 2);
+const PROXY_PORT: u16 = 3128;
+/// Hostname on the server's certificate
+const TEST_HOST: &str = "tls.test";
+
+/// Server settings with a certificate for `TEST_HOST`
+///
+/// The issuing CA is added to the client's extra roots when this first runs.
+fn server_config() -> Arc<rustls::ServerConfig> {
+    static CONFIG: OnceLock<Arc<rustls::ServerConfig>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let ca_key = rcgen::KeyPair::generate().unwrap();
+            let mut ca_params = rcgen::CertificateParams::new(Vec::new()).unwrap();
+            ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+            let ca = ca_params.self_signed(&ca_key).unwrap();
+            set_tls_extra_roots(vec![ca.der().to_vec()]);
+
+            let key = rcgen::KeyPair::generate().unwrap();
+            let certificate = rcgen::CertificateParams::new(vec![TEST_HOST.to_string()])
+                .unwrap()
+                .signed_by(&key, &ca, &ca_key)
+                .unwrap();
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let config = rustls::ServerConfig::builder_with_provider(provider)
+                .with_protocol_versions(&[&rustls::version::TLS13])
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![CertificateDer::from(certificate.der().to_vec())],
+                    PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+                )
+                .unwrap();
+            Arc::new(config)
+        })
+        .clone()
+}
+
+/// An accepted connection: a CONNECT request, then TLS
+struct Connection {
+    socket: SocketHandle,
+    /// CONNECT request received so far
+    head: Vec<u8>,
+    /// Set once the tunnel is open
+    tls: Option<rustls::ServerConnection>,
+    /// Decrypted request bytes not yet answered
+    pending: Vec<u8>,
+    /// Waiting for room in the socket's send buffer
+    outbound: Vec<u8>,
+}
+
+impl Connection {
+    fn new(socket: SocketHandle) -> Self {
+        Self {
+            socket,
+            head: Vec::new(),
+            tls: None,
+            pending: Vec::new(),
+            outbound: Vec::new(),
+        }
+    }
+
+    /// Take bytes from the client, answering complete requests while
+    /// `responses` last
+    fn receive(&mut self, mut data: &[u8], server: &mut Responses) {
+        if self.tls.is_none() {
+            self.head.extend_from_slice(data);
+            if self.head.ends_with(b"\r\n\r\n") {
+                let connect = format!("CONNECT {TEST_HOST}:443 ");
+                assert!(self.head.starts_with(connect.as_bytes()));
+                self.outbound
+                    .extend_from_slice(b"HTTP/1.1 200 Connection Established\r\n\r\n");
+                self.tls = Some(rustls::ServerConnection::new(server_config()).unwrap());
+            }
+            return;
+        }
+        let tls = self.tls.as_mut().unwrap();
+
+        while !data.is_empty() {
+            tls.read_tls(&mut data).unwrap();
+            tls.process_new_packets().unwrap();
+        }
+        let mut buf = [0u8; 4096];
+        loop {
+            match tls.reader().read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => self.pending.extend_from_slice(&buf[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => panic!("reading the request: {e}"),
+            }
+        }
+
+        // Requests are bodiless GETs, so the head is the whole request
+        while let Some(end) = self.pending.windows(4).position(|w| w == b"\r\n\r\n") {
+            self.pending.drain(..end + 4);
+            server.requests += 1;
+            if let Some(response) = server.queue.pop_front() {
+                tls.writer().write_all(&response).unwrap();
+            }
+        }
+
+        while tls.wants_write() {
+            tls.write_tls(&mut self.outbound).unwrap();
+        }
+    }
+}
+
+/// Canned answers, and a count of the requests they were for
+struct Responses {
+    /// Once these run out the server stops answering
+    queue: VecDeque<Vec<u8>>,
+    /// Requests received, answered or not
+    requests: usize,
+}
+
+/// Proxy and HTTPS server on `PROXY_PORT`
+struct TestServer {
+    stack: NetworkStack,
+    listener: SocketHandle,
+    connections: Vec<Connection>,
+    responses: Responses,
+}
+
+impl TestServer {
+    fn new(driver: PairedDriver, responses: &[&[u8]]) -> Self {
+        let mut stack = NetworkStack::new(Box::new(driver), Some((SERVER_IP, 24))).unwrap();
+        let listener = listen(&mut stack);
+        Self {
+            stack,
+            listener,
+            connections: Vec::new(),
+            responses: Responses {
+                queue: responses.iter().map(|r| r.to_vec()).collect(),
+                requests: 0,
+            },
+        }
+    }
+
+    fn step(&mut self, now_ms: i64) {
+        self.stack.poll(now_ms).unwrap();
+
+        if self.stack.sockets().get::<TcpSocket>(self.listener).state() != State::Listen {
+            self.connections.push(Connection::new(self.listener));
+            self.listener = listen(&mut self.stack);
+        }
+
+        for connection in &mut self.connections {
+            let socket = self
+                .stack
+                .sockets_mut()
+                .get_mut::<TcpSocket>(connection.socket);
+            let mut received = Vec::new();
+            while socket.can_recv() {
+                socket
+                    .recv(|data| {
+                        received.extend_from_slice(data);
+                        (data.len(), ())
+                    })
+                    .unwrap();
+            }
+            connection.receive(&received, &mut self.responses);
+
+            if !connection.outbound.is_empty() && socket.can_send() {
+                let sent = socket.send_slice(&connection.outbound).unwrap();
+                connection.outbound.drain(..sent);
+            }
+        }
+
+        self.stack.poll(now_ms).unwrap();
+    }
+}
+
+fn listen(stack: &mut NetworkStack) -> SocketHandle {
+    let mut socket = TcpSocket::new(
+        SocketBuffer::new(vec![0u8; 16 * 1024]),
+        SocketBuffer::new(vec![0u8; 16 * 1024]),
+    );
+    socket.listen(PROXY_PORT).unwrap();
+    stack.sockets_mut().add(socket)
+}
+
+/// A client stack and a server stack on a paired link, with a fake clock
+struct Harness {
+    stack: NetworkStack,
+    server: RefCell<TestServer>,
+    clock: Cell<i64>,
+    client: HttpClient,
+}
+
+impl Harness {
+    fn new(responses: &[&[u8]], read_timeout_ms: i64) -> Self {
+        let (client_end, server_end) = PairedDriver::pair(CLIENT_MAC, SERVER_MAC);
+        Self {
+            stack: NetworkStack::new(Box::new(client_end), Some((CLIENT_IP, 24))).unwrap(),
+            server: RefCell::new(TestServer::new(server_end, responses)),
+            clock: Cell::new(0),
+            client: HttpClient::new(Ipv4Address::UNSPECIFIED)
+                .with_timeouts(5_000, read_timeout_ms)
+                .with_proxy("10.0.0.2", PROXY_PORT, None),
+        }
+    }
+
+    fn send(
+        &mut self,
+        request: &RequestBuilder<'_>,
+        pool: Option<&mut HttpConnectionPool>,
+    ) -> Result<HttpResponse, HttpError> {
+        let (clock, server) = (&self.clock, &self.server);
+        let mut now = || clock.get();
+        let mut sleep = |ms: i64| {
+            clock.set(clock.get() + ms);
+            server.borrow_mut().step(clock.get());
+        };
+        request.send(
+            &self.client,
+            &mut self.stack,
+            &mut now,
+            Some(&mut sleep),
+            pool,
+        )
+    }
+}
+
+#[test]
+fn get_over_tls_through_tunnel() {
+    let mut harness = Harness::new(&[b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok"], 5_000);
+
+    let request = RequestBuilder::get("https://tls.test/v1/models");
+    let response = harness.send(&request, None).unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"ok");
+    assert_eq!(harness.server.borrow().responses.requests, 1);
+}
+
+#[test]
+fn silent_tls_server_times_out_on_fake_clock() {
+    let mut harness = Harness::new(&[], 2_000);
+
+    let request = RequestBuilder::get("https://tls.test/v1/models");
+    let result = harness.send(&request, None);
+    assert!(matches!(result, Err(HttpError::ReadTimeout)));
+    // The request made it through the handshake before the wait began
+    assert_eq!(harness.server.borrow().responses.requests, 1);
+    assert!(harness.clock.get() >= 2_000);
+    assert!(harness.clock.get() < 3_000);
+}
+
+#[test]
+fn stalled_pooled_tls_connection_times_out() {
+    let mut harness = Harness::new(
+        &[b"HTTP/1.1 200 OK\r\nKeep-Alive: timeout=5\r\nContent-Length: 2\r\n\r\nok"],
+        5_000,
+    );
+    let mut pool = HttpConnectionPool::new();
+
+    let request = RequestBuilder::get("https://tls.test/v1/models").timeouts(5_000, 2_000);
+    assert_eq!(harness.send(&request, Some(&mut pool)).unwrap().body, b"ok");
+    assert_eq!(pool.idle_count(), 1);
+
+    // The pooled connection takes the second request but never answers it
+    let started = harness.clock.get();
+    let result = harness.send(&request, Some(&mut pool));
+    assert!(matches!(result, Err(HttpError::ReadTimeout)));
+    let elapsed = harness.clock.get() - started;
+    assert!(elapsed >= 2_000);
+    assert!(elapsed < 3_000);
+
+    let server = harness.server.borrow();
+    assert_eq!(server.connections.len(), 1);
+    assert_eq!(server.responses.requests, 2);
+    assert_eq!(pool.idle_count(), 0);
+}
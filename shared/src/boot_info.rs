@@ -20,6 +20,10 @@ pub struct BootInfo {
     pub heap_start: usize,
     /// Heap size in bytes
     pub heap_size: usize,
+    /// Firmware wall-clock time at boot (seconds since the Unix epoch)
+    pub boot_time_unix: Option<u64>,
+    /// Address of the UEFI system table's runtime view, for EFI variables
+    pub system_table_addr: Option<usize>,
 }
 
 impl BootInfo {
@@ -30,6 +34,8 @@ impl BootInfo {
         rsdp_addr: Option<usize>,
         heap_start: usize,
         heap_size: usize,
+        boot_time_unix: Option<u64>,
+        system_table_addr: Option<usize>,
     ) -> Self {
         Self {
             framebuffer,
@@ -37,6 +43,8 @@ impl BootInfo {
             rsdp_addr,
             heap_start,
             heap_size,
+            boot_time_unix,
+            system_table_addr,
         }
     }
 }
@@ -15,18 +15,38 @@ pub enum PixelFormat {
     Rgba,
     /// 32-bit BGRA (blue, green, red, alpha)
     Bgra,
+    /// 16-bit RGB565, stored little-endian
+    Rgb565,
 }
 
 impl PixelFormat {
     /// Get bytes per pixel for this format
     pub fn bytes_per_pixel(&self) -> usize {
         match self {
+            PixelFormat::Rgb565 => 2,
             PixelFormat::Rgb | PixelFormat::Bgr => 3,
             PixelFormat::Rgba | PixelFormat::Bgra => 4,
         }
     }
 }
 
+/// Pack a color into RGB565, dropping the low bits of each channel
+pub fn pack_rgb565(color: Color) -> u16 {
+    ((color.r as u16 >> 3) << 11) | ((color.g as u16 >> 2) << 5) | (color.b as u16 >> 3)
+}
+
+/// Expand an RGB565 value, repeating the high bits so white stays white
+pub fn unpack_rgb565(value: u16) -> Color {
+    let r = ((value >> 11) & 0x1F) as u8;
+    let g = ((value >> 5) & 0x3F) as u8;
+    let b = (value & 0x1F) as u8;
+    Color::rgb(
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    )
+}
+
 /// Framebuffer information structure
 ///
 /// This struct contains all information needed to access and write to the framebuffer.
@@ -113,6 +133,47 @@ impl FramebufferInfo {
                 pixel_ptr.add(2).write(color.r);
                 pixel_ptr.add(3).write(color.a);
             }
+            PixelFormat::Rgb565 => {
+                let [low, high] = pack_rgb565(color).to_le_bytes();
+                pixel_ptr.write(low);
+                pixel_ptr.add(1).write(high);
+            }
+        }
+    }
+
+    /// Read the pixel at the given coordinates (black if out of bounds)
+    ///
+    /// # Safety
+    ///
+    /// Same safety requirements as `write_pixel`, with the memory readable
+    unsafe fn read_pixel(&self, x: usize, y: usize) -> Color {
+        if x >= self.width || y >= self.height {
+            return Color::black();
+        }
+
+        let offset = (y * self.stride) + (x * self.bytes_per_pixel());
+        let pixel_ptr = self.base.add(offset);
+        let byte = |i: usize| pixel_ptr.add(i).read();
+
+        match self.pixel_format {
+            PixelFormat::Rgb => Color::rgb(byte(0), byte(1), byte(2)),
+            PixelFormat::Bgr => Color::rgb(byte(2), byte(1), byte(0)),
+            PixelFormat::Rgba => Color::new(byte(0), byte(1), byte(2), byte(3)),
+            PixelFormat::Bgra => Color::new(byte(2), byte(1), byte(0), byte(3)),
+            PixelFormat::Rgb565 => unpack_rgb565(u16::from_le_bytes([byte(0), byte(1)])),
+        }
+    }
+
+    /// Composite `color` over the existing pixel using `color.a`
+    ///
+    /// # Safety
+    ///
+    /// Same safety requirements as `write_pixel`, with the memory readable
+    pub unsafe fn blend_pixel(&self, x: usize, y: usize, color: Color) {
+        match color.a {
+            0 => {}
+            255 => self.write_pixel(x, y, color),
+            _ => self.write_pixel(x, y, blend_color(color, self.read_pixel(x, y))),
         }
     }
 
@@ -133,6 +194,48 @@ impl FramebufferInfo {
         }
     }
 
+    /// Composite `color` over a rectangle, e.g. to dim what is behind a dialog
+    ///
+    /// # Safety
+    ///
+    /// Same safety requirements as `blend_pixel`
+    pub unsafe fn fill_rectangle_blended(&self, rect: Rect, color: Color) {
+        let bounds = Rect::new(0, 0, self.width, self.height);
+        if let Some(clipped) = rect.clip_to(bounds) {
+            for py in clipped.y..clipped.bottom() {
+                for px in clipped.x..clipped.right() {
+                    self.blend_pixel(px, py, color);
+                }
+            }
+        }
+    }
+
+    /// Move the contents up by `pixels` rows and fill the exposed bottom rows
+    ///
+    /// Scrolling by the full height or more just fills the framebuffer.
+    ///
+    /// # Safety
+    ///
+    /// Same safety requirements as `write_pixel`, with the memory readable
+    pub unsafe fn scroll_up(&self, pixels: usize, fill: Color) {
+        if pixels == 0 {
+            return;
+        }
+        if pixels >= self.height {
+            self.fill_rectangle(Rect::new(0, 0, self.width, self.height), fill);
+            return;
+        }
+
+        // Rows are contiguous at `stride`, so one overlapping copy moves them all
+        let kept_rows = self.height - pixels;
+        core::ptr::copy(
+            self.base.add(pixels * self.stride),
+            self.base,
+            kept_rows * self.stride,
+        );
+        self.fill_rectangle(Rect::new(0, kept_rows, self.width, pixels), fill);
+    }
+
     /// Draw a line using Bresenham's algorithm
     ///
     /// # Safety
@@ -348,6 +451,20 @@ fn compute_region_code_signed(x: i32, y: i32, bounds: Rect) -> u8 {
     code
 }
 
+/// Composite `src` over `dst` using `src.a` ("source over")
+///
+/// The result keeps `dst`'s alpha, since the display itself is opaque.
+pub fn blend_color(src: Color, dst: Color) -> Color {
+    let alpha = src.a as u32;
+    let mix = |s: u8, d: u8| ((s as u32 * alpha + d as u32 * (255 - alpha) + 127) / 255) as u8;
+    Color::new(
+        mix(src.r, dst.r),
+        mix(src.g, dst.g),
+        mix(src.b, dst.b),
+        dst.a,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -545,4 +662,99 @@ mod tests {
         // Both out of bounds
         assert!(!fb.set_pixel(100, 100, color));
     }
+
+    #[test]
+    fn test_blend_color_math() {
+        let dst = Color::rgb(200, 100, 0);
+        assert_eq!(blend_color(Color::new(10, 20, 30, 0), dst), dst);
+        assert_eq!(
+            blend_color(Color::new(10, 20, 30, 255), dst),
+            Color::rgb(10, 20, 30)
+        );
+        // Roughly halfway, rounded to nearest
+        assert_eq!(
+            blend_color(Color::new(0, 0, 0, 128), dst),
+            Color::rgb(100, 50, 0)
+        );
+        assert_eq!(
+            blend_color(Color::new(255, 255, 255, 128), Color::new(0, 0, 0, 7)),
+            Color::new(128, 128, 128, 7)
+        );
+    }
+
+    #[test]
+    fn test_blend_pixel_in_every_format() {
+        let formats = [
+            (PixelFormat::Rgb, [200u8, 100, 0, 0]),
+            (PixelFormat::Bgr, [0, 100, 200, 0]),
+            (PixelFormat::Rgba, [200, 100, 0, 255]),
+            (PixelFormat::Bgra, [0, 100, 200, 255]),
+        ];
+        for (format, pixel) in formats {
+            let bpp = format.bytes_per_pixel();
+            let mut buffer = [0u8; 8];
+            buffer[bpp..bpp * 2].copy_from_slice(&pixel[..bpp]);
+            let fb = FramebufferInfo::new(buffer.as_mut_ptr(), 2, 1, bpp * 2, format);
+
+            unsafe {
+                fb.write_pixel(0, 0, Color::rgb(200, 100, 0));
+                fb.fill_rectangle_blended(Rect::new(0, 0, 10, 10), Color::new(0, 0, 0, 128));
+                assert_eq!(fb.read_pixel(0, 0).to_rgb(), (100, 50, 0), "{format:?}");
+                assert_eq!(fb.read_pixel(1, 0).to_rgb(), (100, 50, 0), "{format:?}");
+
+                fb.blend_pixel(0, 0, Color::new(255, 255, 255, 0));
+                assert_eq!(fb.read_pixel(0, 0).to_rgb(), (100, 50, 0));
+                fb.blend_pixel(0, 0, Color::new(1, 2, 3, 255));
+                assert_eq!(fb.read_pixel(0, 0).to_rgb(), (1, 2, 3));
+            }
+        }
+    }
+
+    #[test]
+    fn test_scroll_up_moves_rows_and_fills_bottom() {
+        // 2x4 RGB with a padded stride; each row is tagged with its index
+        const STRIDE: usize = 8;
+        let mut buffer = [0u8; STRIDE * 4];
+        for (row, bytes) in buffer.chunks_mut(STRIDE).enumerate() {
+            bytes.fill(row as u8 + 1);
+        }
+        let fb = FramebufferInfo::new(buffer.as_mut_ptr(), 2, 4, STRIDE, PixelFormat::Rgb);
+
+        unsafe { fb.scroll_up(1, Color::rgb(9, 9, 9)) };
+        assert_eq!(&buffer[..STRIDE], &[2; STRIDE]);
+        assert_eq!(&buffer[STRIDE * 2..STRIDE * 3], &[4; STRIDE]);
+        // Only the visible pixels of the exposed row are filled
+        assert_eq!(&buffer[STRIDE * 3..STRIDE * 3 + 6], &[9; 6]);
+
+        let fb = FramebufferInfo::new(buffer.as_mut_ptr(), 2, 4, STRIDE, PixelFormat::Rgb);
+        unsafe { fb.scroll_up(4, Color::rgb(7, 7, 7)) };
+        for row in buffer.chunks(STRIDE) {
+            assert_eq!(&row[..6], &[7; 6]);
+        }
+    }
+
+    #[test]
+    fn test_rgb565_packing_and_bounds() {
+        assert_eq!(PixelFormat::Rgb565.bytes_per_pixel(), 2);
+        assert_eq!(pack_rgb565(Color::rgb(255, 0, 0)), 0xF800);
+        assert_eq!(pack_rgb565(Color::rgb(0, 255, 0)), 0x07E0);
+        assert_eq!(pack_rgb565(Color::rgb(0, 0, 255)), 0x001F);
+        assert_eq!(pack_rgb565(Color::rgb(0x12, 0x34, 0x56)), 0x11AA);
+        assert_eq!(unpack_rgb565(0xFFFF), Color::white());
+        assert_eq!(
+            unpack_rgb565(pack_rgb565(Color::rgb(0x10, 0x34, 0x50))),
+            Color::rgb(0x10, 0x34, 0x52)
+        );
+
+        // 3x2 pixels with a padded stride
+        let mut buffer = [0u8; 16];
+        let fb = FramebufferInfo::new(buffer.as_mut_ptr(), 3, 2, 8, PixelFormat::Rgb565);
+        assert_eq!(fb.size_bytes(), 16);
+        assert!(fb.set_pixel(2, 1, Color::rgb(0x12, 0x34, 0x56)));
+        assert!(!fb.set_pixel(3, 1, Color::white()));
+        assert!(!fb.set_pixel(0, 2, Color::white()));
+        unsafe { fb.fill_rectangle_blended(Rect::new(0, 0, 9, 9), Color::new(0, 0, 0, 0)) };
+        assert_eq!(&buffer[12..14], &[0xAA, 0x11]);
+        assert!(buffer[..12].iter().chain(&buffer[14..]).all(|&b| b == 0));
+    }
 }
@@ -51,6 +51,32 @@ impl Color {
         }
     }
 
+    /// Parse a `#RGB`, `#RRGGBB` or `#RRGGBBAA` hex string; the `#` is optional
+    pub fn from_hex(s: &str) -> Result<Self, ColorError> {
+        let hex = s.strip_prefix('#').unwrap_or(s).as_bytes();
+        let mut digits = [0u8; 8];
+        if hex.len() > digits.len() {
+            return Err(ColorError::InvalidHex);
+        }
+        for (digit, &byte) in digits.iter_mut().zip(hex) {
+            *digit = match byte {
+                b'0'..=b'9' => byte - b'0',
+                b'a'..=b'f' => byte - b'a' + 10,
+                b'A'..=b'F' => byte - b'A' + 10,
+                _ => return Err(ColorError::InvalidHex),
+            };
+        }
+        let byte = |i: usize| (digits[i] << 4) | digits[i + 1];
+
+        match hex.len() {
+            // Each short-form digit is doubled: F -> FF
+            3 => Ok(Self::rgb(digits[0] * 17, digits[1] * 17, digits[2] * 17)),
+            6 => Ok(Self::rgb(byte(0), byte(2), byte(4))),
+            8 => Ok(Self::new(byte(0), byte(2), byte(4), byte(6))),
+            _ => Err(ColorError::InvalidHex),
+        }
+    }
+
     pub fn to_rgb(&self) -> (u8, u8, u8) {
         (self.r, self.g, self.b)
     }
@@ -127,7 +153,7 @@ pub enum FontError {
     BufferTooSmall,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum ColorError {
     InvalidHex,
 }
@@ -136,4 +162,50 @@ pub enum ColorError {
 pub use allocator::{init_heap, is_heap_initialized};
 pub use boot_info::BootInfo;
 pub use framebuffer::{FramebufferInfo, PixelFormat};
-pub use memory::{MemoryKind, MemoryMap, MemoryRegion};
+pub use memory::{
+    alloc_dma, dma_stats, free_dma, init_dma, DmaPool, DmaStats, MemoryKind, MemoryMap,
+    MemoryRegion,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_hex_short_form() {
+        assert_eq!(Color::from_hex("#F80"), Ok(Color::rgb(0xFF, 0x88, 0x00)));
+        assert_eq!(Color::from_hex("f80"), Ok(Color::rgb(0xFF, 0x88, 0x00)));
+    }
+
+    #[test]
+    fn test_from_hex_long_form() {
+        assert_eq!(Color::from_hex("#0D1117"), Ok(Color::rgb(0x0D, 0x11, 0x17)));
+        assert_eq!(Color::from_hex("58a6ff"), Ok(Color::rgb(0x58, 0xA6, 0xFF)));
+    }
+
+    #[test]
+    fn test_from_hex_with_alpha() {
+        assert_eq!(
+            Color::from_hex("#11223380"),
+            Ok(Color::new(0x11, 0x22, 0x33, 0x80))
+        );
+    }
+
+    #[test]
+    fn test_from_hex_rejects_invalid() {
+        for bad in [
+            "",
+            "#",
+            "#12",
+            "#1234",
+            "#12345",
+            "#1234567",
+            "#123456789",
+            "#GG0000",
+            "#12 456",
+            "##123",
+        ] {
+            assert_eq!(Color::from_hex(bad), Err(ColorError::InvalidHex), "{}", bad);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 // Memory map types shared across moteOS crates
 
+use spin::Mutex;
+
 /// Memory region kind
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -58,6 +60,214 @@ impl MemoryMap {
             .map(|r| r.len)
             .sum()
     }
+
+    /// Find `size` bytes of usable, page-aligned memory outside `avoid`
+    ///
+    /// `avoid` is a `(start, len)` range already claimed (the heap). Memory
+    /// below 1 MiB is skipped so a region never starts at address zero.
+    pub fn find_dma_window(&self, size: usize, avoid: (usize, usize)) -> Option<usize> {
+        let (avoid_start, avoid_end) = (avoid.0, avoid.0.saturating_add(avoid.1));
+        self.regions
+            .iter()
+            .filter(|r| r.kind == MemoryKind::Usable)
+            .flat_map(|r| {
+                let end = r.start.saturating_add(r.len);
+                // The parts of the region before and after the avoided range
+                [
+                    (r.start, end.min(avoid_start)),
+                    (r.start.max(avoid_end), end),
+                ]
+            })
+            .find_map(|(start, end)| {
+                let start = start
+                    .max(LOW_MEMORY_END)
+                    .checked_next_multiple_of(PAGE_SIZE)?;
+                (end.saturating_sub(start) >= size).then_some(start)
+            })
+    }
+}
+
+/// Size of the pages handed out for DMA
+pub const PAGE_SIZE: usize = 4096;
+
+/// Pages reserved for device DMA (4 MiB)
+pub const DMA_POOL_PAGES: usize = 1024;
+
+/// End of legacy low memory, never used for DMA
+const LOW_MEMORY_END: usize = 0x10_0000;
+
+const DMA_BITMAP_WORDS: usize = DMA_POOL_PAGES / 64;
+
+/// DMA pool usage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaStats {
+    /// Bytes in the pool
+    pub total: usize,
+    /// Bytes currently allocated (whole pages)
+    pub used: usize,
+    /// Live allocations
+    pub allocations: usize,
+}
+
+/// Physically contiguous pages set aside for devices
+///
+/// Allocations are whole pages, tracked in a bitmap, so any run handed out
+/// is contiguous in physical memory no matter how the heap is mapped.
+pub struct DmaPool {
+    /// Physical address of the first page
+    base: usize,
+    /// Added to a physical address to get the kernel's virtual address
+    virt_offset: usize,
+    /// Pages in the pool
+    pages: usize,
+    /// One bit per page, set while allocated
+    bitmap: [u64; DMA_BITMAP_WORDS],
+    used_pages: usize,
+    allocations: usize,
+}
+
+impl DmaPool {
+    /// Create a pool over `pages` pages at physical `base` (page-aligned)
+    ///
+    /// At most `DMA_POOL_PAGES` pages are managed.
+    pub const fn new(base: usize, pages: usize, virt_offset: usize) -> Self {
+        Self {
+            base,
+            virt_offset,
+            pages: if pages < DMA_POOL_PAGES {
+                pages
+            } else {
+                DMA_POOL_PAGES
+            },
+            bitmap: [0; DMA_BITMAP_WORDS],
+            used_pages: 0,
+            allocations: 0,
+        }
+    }
+
+    fn is_used(&self, page: usize) -> bool {
+        self.bitmap[page / 64] & (1 << (page % 64)) != 0
+    }
+
+    fn set_used(&mut self, pages: core::ops::Range<usize>, used: bool) {
+        for page in pages {
+            if used {
+                self.bitmap[page / 64] |= 1 << (page % 64);
+            } else {
+                self.bitmap[page / 64] &= !(1 << (page % 64));
+            }
+        }
+    }
+
+    /// Reserve at least `len` bytes aligned to `align` (a power of two)
+    ///
+    /// # Returns
+    /// `(virtual address, physical address)`, or `None` if no run of free
+    /// pages is large enough
+    pub fn alloc(&mut self, len: usize, align: usize) -> Option<(usize, u64)> {
+        if len == 0 || !align.is_power_of_two() {
+            return None;
+        }
+        let align = align.max(PAGE_SIZE);
+        let count = len.div_ceil(PAGE_SIZE);
+
+        let mut page = 0;
+        while page + count <= self.pages {
+            let addr = self.base + page * PAGE_SIZE;
+            if !addr.is_multiple_of(align) {
+                page = (addr.next_multiple_of(align) - self.base) / PAGE_SIZE;
+                continue;
+            }
+            match (page..page + count).find(|&p| self.is_used(p)) {
+                Some(used) => page = used + 1,
+                None => {
+                    self.set_used(page..page + count, true);
+                    self.used_pages += count;
+                    self.allocations += 1;
+                    return Some((addr.wrapping_add(self.virt_offset), addr as u64));
+                }
+            }
+        }
+        None
+    }
+
+    /// Release an allocation made with `alloc`
+    ///
+    /// # Returns
+    /// `false` if `phys`/`len` don't describe allocated pages in this pool
+    pub fn free(&mut self, phys: u64, len: usize) -> bool {
+        let phys = phys as usize;
+        if len == 0 || phys < self.base || !(phys - self.base).is_multiple_of(PAGE_SIZE) {
+            return false;
+        }
+        let first = (phys - self.base) / PAGE_SIZE;
+        let count = len.div_ceil(PAGE_SIZE);
+        if first + count > self.pages || !(first..first + count).all(|p| self.is_used(p)) {
+            return false;
+        }
+        self.set_used(first..first + count, false);
+        self.used_pages -= count;
+        self.allocations -= 1;
+        true
+    }
+
+    pub fn stats(&self) -> DmaStats {
+        DmaStats {
+            total: self.pages * PAGE_SIZE,
+            used: self.used_pages * PAGE_SIZE,
+            allocations: self.allocations,
+        }
+    }
+}
+
+/// Global DMA pool, set up by `init_dma`
+static DMA_POOL: Mutex<Option<DmaPool>> = Mutex::new(None);
+
+/// Reserve the DMA pool from the boot memory map
+///
+/// # Safety
+///
+/// - Memory must be identity-mapped (the UEFI boot mapping)
+/// - `heap_start`/`heap_size` must describe the heap so the pool avoids it
+/// - Usable regions must not be in use by anything else
+///
+/// # Returns
+/// The pool's physical `(start, size)`, or `None` if no region fits
+pub unsafe fn init_dma(
+    memory_map: &MemoryMap,
+    heap_start: usize,
+    heap_size: usize,
+) -> Option<(usize, usize)> {
+    let size = DMA_POOL_PAGES * PAGE_SIZE;
+    let base = memory_map.find_dma_window(size, (heap_start, heap_size))?;
+    *DMA_POOL.lock() = Some(DmaPool::new(base, DMA_POOL_PAGES, 0));
+    Some((base, size))
+}
+
+/// Allocate zeroed, physically contiguous memory for a device
+///
+/// # Returns
+/// `(virtual address, physical address)`, or `None` if the pool is not
+/// initialized or exhausted
+pub fn alloc_dma(len: usize, align: usize) -> Option<(usize, u64)> {
+    let (virt, phys) = DMA_POOL.lock().as_mut()?.alloc(len, align)?;
+    // SAFETY: the pages were just reserved from memory `init_dma` was told
+    // is unused and mapped
+    unsafe { core::ptr::write_bytes(virt as *mut u8, 0, len) };
+    Some((virt, phys))
+}
+
+/// Return memory from `alloc_dma`; the device must no longer be using it
+pub fn free_dma(phys: u64, len: usize) -> bool {
+    DMA_POOL
+        .lock()
+        .as_mut()
+        .is_some_and(|pool| pool.free(phys, len))
+}
+
+/// Current DMA pool usage, if the pool is initialized
+pub fn dma_stats() -> Option<DmaStats> {
+    DMA_POOL.lock().as_ref().map(DmaPool::stats)
 }
 
 #[cfg(test)]
@@ -80,4 +290,64 @@ mod tests {
         assert_eq!(region.start, 0x100000);
         assert_eq!(region.len, 64 * 1024 * 1024);
     }
+
+    #[test]
+    fn test_find_dma_window_skips_heap() {
+        static REGIONS: [MemoryRegion; 3] = [
+            MemoryRegion {
+                start: 0,
+                len: 0x9F000,
+                kind: MemoryKind::Usable,
+            },
+            MemoryRegion {
+                start: 0x10_0000,
+                len: 0x40_0000,
+                kind: MemoryKind::Reserved,
+            },
+            MemoryRegion {
+                start: 0x100_0000,
+                len: 0x800_0000,
+                kind: MemoryKind::Usable,
+            },
+        ];
+        let map = MemoryMap::new(&REGIONS);
+
+        // Heap at the start of the large region: the pool goes after it
+        let base = map.find_dma_window(0x40_0000, (0x100_0000, 0x400_0000));
+        assert_eq!(base, Some(0x500_0000));
+        // No heap: low memory is still skipped
+        assert_eq!(map.find_dma_window(0x1000, (0, 0)), Some(0x100_0000));
+        assert_eq!(map.find_dma_window(0x1000_0000, (0, 0)), None);
+    }
+
+    #[test]
+    fn test_dma_pool_alloc_free() {
+        let mut pool = DmaPool::new(0x20_0000, 32, 0);
+        let (virt, phys) = pool.alloc(100, 16).unwrap();
+        assert_eq!((virt, phys), (0x20_0000, 0x20_0000));
+
+        // A 64 KiB-aligned run skips ahead; a multi-page run is contiguous
+        let (_, aligned) = pool.alloc(PAGE_SIZE * 2, 0x1_0000).unwrap();
+        assert_eq!(aligned, 0x21_0000);
+        let (_, next) = pool.alloc(PAGE_SIZE + 1, PAGE_SIZE).unwrap();
+        assert_eq!(next, 0x20_1000);
+        assert_eq!(
+            pool.stats(),
+            DmaStats {
+                total: 32 * PAGE_SIZE,
+                used: 5 * PAGE_SIZE,
+                allocations: 3,
+            }
+        );
+
+        // Freeing returns the pages for reuse; bad frees are refused
+        assert!(pool.free(phys, 100));
+        assert!(!pool.free(phys, 100));
+        assert!(!pool.free(0x20_0800, 100));
+        assert_eq!(pool.alloc(PAGE_SIZE, PAGE_SIZE).unwrap().1, 0x20_0000);
+        assert!(pool.alloc(PAGE_SIZE * 16, PAGE_SIZE).is_none());
+
+        let mut offset = DmaPool::new(0x20_0000, 1, 0xFFFF_8000_0000_0000);
+        assert_eq!(offset.alloc(1, 1), Some((0xFFFF_8000_0020_0000, 0x20_0000)));
+    }
 }
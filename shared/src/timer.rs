@@ -9,6 +9,9 @@ static TICKS: AtomicU64 = AtomicU64::new(0);
 /// Timer frequency in Hz
 static TIMER_FREQUENCY: AtomicU64 = AtomicU64::new(100); // Default to 100Hz
 
+/// Unix time (seconds) at tick 0, or 0 when the wall clock is unknown
+static BOOT_UNIX_SECS: AtomicU64 = AtomicU64::new(0);
+
 /// Initialize the timer
 ///
 /// On x86_64, this attempts to use HPET (High Precision Event Timer) first,
@@ -107,6 +110,103 @@ pub fn get_frequency() -> u64 {
     TIMER_FREQUENCY.load(Ordering::Relaxed)
 }
 
+/// Seconds elapsed since the timer started
+fn uptime_secs() -> u64 {
+    get_ticks() / get_frequency().max(1)
+}
+
+/// Record the current wall-clock time (seconds since the Unix epoch)
+///
+/// Later readings advance it with the monotonic tick counter.
+pub fn set_wall_clock(unix_secs: u64) {
+    BOOT_UNIX_SECS.store(unix_secs.saturating_sub(uptime_secs()), Ordering::Relaxed);
+}
+
+/// Current wall-clock time in seconds since the Unix epoch, if it has been set
+pub fn wall_clock_secs() -> Option<u64> {
+    match BOOT_UNIX_SECS.load(Ordering::Relaxed) {
+        0 => None,
+        boot => Some(boot + uptime_secs()),
+    }
+}
+
+/// Convert a UTC calendar date and time to seconds since the Unix epoch
+///
+/// `month` is 1-12 and `day` 1-31; dates before 1970 clamp to 0.
+pub fn unix_seconds(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> u64 {
+    // Days from civil date (Howard Hinnant's algorithm), years starting in March
+    let year = year as i64 - if month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let secs = days * 86_400 + hour as i64 * 3_600 + minute as i64 * 60 + second as i64;
+    secs.max(0) as u64
+}
+
+/// A UTC calendar date and time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1-12
+    pub month: u8,
+    /// 1-31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Decode the CMOS RTC time registers
+    ///
+    /// `registers` holds seconds, minutes, hours, day of month, month and
+    /// two-digit year (CMOS registers 0x00, 0x02, 0x04, 0x07, 0x08, 0x09).
+    /// `status_b` is register 0x0B, which says whether they are BCD or binary
+    /// and whether hours are 12- or 24-hour. Years are taken to be 20xx.
+    pub fn from_cmos(registers: [u8; 6], status_b: u8) -> Self {
+        let binary = status_b & 0x04 != 0;
+        let hours_24 = status_b & 0x02 != 0;
+        let decode = |value: u8| if binary { value } else { bcd_to_binary(value) };
+
+        // In 12-hour mode bit 7 of the hour marks PM
+        let pm = registers[2] & 0x80 != 0;
+        let mut hour = decode(registers[2] & 0x7F);
+        if !hours_24 {
+            hour = hour % 12 + if pm { 12 } else { 0 };
+        }
+
+        Self {
+            year: 2000 + decode(registers[5]) as u16,
+            month: decode(registers[4]),
+            day: decode(registers[3]),
+            hour,
+            minute: decode(registers[1]),
+            second: decode(registers[0]),
+        }
+    }
+
+    /// Seconds since the Unix epoch
+    pub fn unix_seconds(&self) -> u64 {
+        unix_seconds(
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+        )
+    }
+}
+
+/// Decode a packed BCD byte, e.g. 0x59 to 59
+pub fn bcd_to_binary(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
 // ARM64 implementation
 #[cfg(target_arch = "aarch64")]
 pub unsafe fn init_timer(frequency_hz: u64) {
@@ -118,3 +218,50 @@ pub unsafe fn init_timer(frequency_hz: u64) {
     // 2. Setting CNTP_TVAL_EL0 (Timer Value register) for the desired frequency
     // 3. Enabling timer interrupts in GIC
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unix_seconds() {
+        assert_eq!(unix_seconds(1970, 1, 1, 0, 0, 0), 0);
+        assert_eq!(unix_seconds(2000, 3, 1, 0, 0, 0), 951_868_800);
+        assert_eq!(unix_seconds(2024, 2, 29, 12, 30, 15), 1_709_209_815);
+        assert_eq!(unix_seconds(1969, 12, 31, 23, 59, 59), 0);
+    }
+
+    #[test]
+    fn test_bcd_to_binary() {
+        assert_eq!(bcd_to_binary(0x00), 0);
+        assert_eq!(bcd_to_binary(0x09), 9);
+        assert_eq!(bcd_to_binary(0x10), 10);
+        assert_eq!(bcd_to_binary(0x59), 59);
+        assert_eq!(bcd_to_binary(0x99), 99);
+    }
+
+    #[test]
+    fn test_date_time_from_cmos() {
+        let expected = DateTime {
+            year: 2026,
+            month: 10,
+            day: 17,
+            hour: 21,
+            minute: 5,
+            second: 9,
+        };
+        // BCD, 24-hour (the usual PC setting)
+        let bcd = [0x09, 0x05, 0x21, 0x17, 0x10, 0x26];
+        assert_eq!(DateTime::from_cmos(bcd, 0x02), expected);
+        // Binary, 24-hour
+        let binary = [9, 5, 21, 17, 10, 26];
+        assert_eq!(DateTime::from_cmos(binary, 0x06), expected);
+        // BCD, 12-hour: 9 PM
+        let pm = [0x09, 0x05, 0x89, 0x17, 0x10, 0x26];
+        assert_eq!(DateTime::from_cmos(pm, 0x00), expected);
+        // 12 AM is midnight
+        let midnight = DateTime::from_cmos([0, 0, 0x12, 1, 1, 0x26], 0x00);
+        assert_eq!(midnight.hour, 0);
+        assert_eq!(expected.unix_seconds(), 1_792_271_109);
+    }
+}
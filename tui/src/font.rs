@@ -1,10 +1,21 @@
 // tui/src/font.rs
 #![no_std]
 
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
 use shared::FontError;
 
 pub type Result<T> = core::result::Result<T, FontError>;
 
+/// PSF2 flag: a Unicode description table follows the glyphs
+pub const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+
+/// PSF2 Unicode table: starts a multi-codepoint sequence
+const PSF2_START_SEQ: u8 = 0xFE;
+/// PSF2 Unicode table: ends the entry for one glyph
+const PSF2_SEPARATOR: u8 = 0xFF;
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct Pfs1Header {
@@ -37,6 +48,9 @@ pub struct Font {
     pub height: usize,
     pub glyph_count: usize,
     pub header: Version,
+    /// Glyph index for each character, from the PSF2 Unicode table if the
+    /// font has one; characters are used as indices directly otherwise
+    pub unicode_map: Option<BTreeMap<char, u32>>,
 }
 
 impl Font {
@@ -67,6 +81,7 @@ impl Font {
                 height: header.char_size as usize,
                 glyph_count,
                 header: Version::V1(header),
+                unicode_map: None,
             })
         } else {
             if data.len() < 4 {
@@ -84,12 +99,22 @@ impl Font {
                     return Err(FontError::BufferTooSmall);
                 }
 
+                let unicode_map = if header.flags & PSF2_HAS_UNICODE_TABLE != 0 {
+                    Some(parse_unicode_table(
+                        &glyphs[glyph_bytes as usize..],
+                        header.length,
+                    ))
+                } else {
+                    None
+                };
+
                 Ok(Font {
                     glyphs,
                     width: header.width as usize,
                     height: header.height as usize,
                     glyph_count: header.length as usize,
                     header: Version::V2(header),
+                    unicode_map,
                 })
             } else {
                 Err(FontError::InvalidMagic)
@@ -109,12 +134,9 @@ impl Font {
                 Some(&self.glyphs[glyph_start as usize..glyph_end as usize])
             }
             Version::V2(header) => {
-                let glyph_index = if header.flags == 0 {
-                    c as u32
-                } else {
-                    // TODO: Implement Unicode table lookup for PSF v2 fonts with a unicode table.
-                    // For now, we just cast the character to a u32, which works for ASCII characters.
-                    c as u32
+                let glyph_index = match &self.unicode_map {
+                    Some(map) => *map.get(&c)?,
+                    None => c as u32,
                 };
 
                 if glyph_index >= header.length {
@@ -140,3 +162,61 @@ impl Font {
         Some(&buffer[..glyph.len()])
     }
 }
+
+/// Map each character in a PSF2 Unicode table to its glyph index
+///
+/// The table holds one entry per glyph: UTF-8 characters, optionally
+/// followed by multi-codepoint sequences (each introduced by 0xFE), ending
+/// with 0xFF. Sequences and entries that are not valid UTF-8 are skipped.
+fn parse_unicode_table(table: &[u8], glyph_count: u32) -> BTreeMap<char, u32> {
+    let mut map = BTreeMap::new();
+    let entries = table.split(|&byte| byte == PSF2_SEPARATOR);
+    for (index, entry) in (0..glyph_count).zip(entries) {
+        let singles = entry
+            .split(|&byte| byte == PSF2_START_SEQ)
+            .next()
+            .unwrap_or(&[]);
+        if let Ok(text) = core::str::from_utf8(singles) {
+            for c in text.chars() {
+                // The first glyph listed for a character wins
+                map.entry(c).or_insert(index);
+            }
+        }
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn psf2_unicode_table_maps_characters_to_glyphs() {
+        // Three 8x1 glyphs; one byte each
+        let mut data = Vec::from([0x72, 0xb5, 0x4a, 0x86]);
+        // version, header_size, flags, length, char_size, height, width
+        for field in [0u32, 32, PSF2_HAS_UNICODE_TABLE, 3, 1, 1, 8] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        data.extend_from_slice(&[0x11, 0x22, 0x33]);
+        // Glyph 0: '?'; glyph 1: 'A' and 'А' (Cyrillic), plus the sequence
+        // "A\u{300}"; glyph 2: 'λ'
+        data.extend_from_slice(b"?\xff");
+        data.extend_from_slice("AА".as_bytes());
+        data.push(PSF2_START_SEQ);
+        data.extend_from_slice("A\u{300}".as_bytes());
+        data.push(PSF2_SEPARATOR);
+        data.extend_from_slice("λ".as_bytes());
+        data.push(PSF2_SEPARATOR);
+
+        let font = unsafe { Font::load_psf(Vec::leak(data)) }.unwrap();
+        assert_eq!(font.glyph_data('?'), Some(&[0x11][..]));
+        assert_eq!(font.glyph_data('A'), Some(&[0x22][..]));
+        assert_eq!(font.glyph_data('А'), Some(&[0x22][..]));
+        assert_eq!(font.glyph_data('λ'), Some(&[0x33][..]));
+        // Not in the table, even though its codepoint is a valid index
+        assert_eq!(font.glyph_data('\u{1}'), None);
+        assert_eq!(font.glyph_data('\u{300}'), None);
+    }
+}
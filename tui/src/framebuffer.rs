@@ -15,12 +15,15 @@ pub enum PixelFormat {
     Rgba,
     /// 32-bit BGRA
     Bgra,
+    /// 16-bit RGB565, little-endian
+    Rgb565,
 }
 
 impl PixelFormat {
     /// Returns the number of bytes per pixel
     pub const fn bytes_per_pixel(&self) -> usize {
         match self {
+            PixelFormat::Rgb565 => 2,
             PixelFormat::Rgb | PixelFormat::Bgr => 3,
             PixelFormat::Rgba | PixelFormat::Bgra => 4,
         }
@@ -51,6 +54,11 @@ impl PixelFormat {
                 buffer[2] = color.r;
                 buffer[3] = color.a;
             }
+            PixelFormat::Rgb565 => {
+                let packed =
+                    shared::framebuffer::pack_rgb565(shared::Color::rgb(color.r, color.g, color.b));
+                buffer[..2].copy_from_slice(&packed.to_le_bytes());
+            }
         }
     }
 }
@@ -91,6 +99,7 @@ impl From<SharedPixelFormat> for PixelFormat {
             SharedPixelFormat::Bgr => PixelFormat::Bgr,
             SharedPixelFormat::Rgba => PixelFormat::Rgba,
             SharedPixelFormat::Bgra => PixelFormat::Bgra,
+            SharedPixelFormat::Rgb565 => PixelFormat::Rgb565,
         }
     }
 }
@@ -221,6 +230,26 @@ impl Framebuffer {
         }
     }
 
+    /// Write a horizontal run of pixels starting at (x, y)
+    ///
+    /// # Safety
+    ///
+    /// This function performs bounds checking and clips the run to the
+    /// framebuffer width.
+    pub unsafe fn write_span(&mut self, x: usize, y: usize, pixels: &[Color]) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        let bpp = self.pixel_format.bytes_per_pixel();
+        let len = pixels.len().min(self.width - x);
+        let row_ptr = self.base.add(y * self.stride + x * bpp);
+        let row = core::slice::from_raw_parts_mut(row_ptr, len * bpp);
+        for (pixel, &color) in row.chunks_exact_mut(bpp).zip(pixels) {
+            self.pixel_format.write_color(pixel, color);
+        }
+    }
+
     /// Clear the entire framebuffer with a color
     ///
     /// # Safety
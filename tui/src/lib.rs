@@ -23,4 +23,8 @@ pub use theme::{Theme, DARK_THEME, LIGHT_THEME};
 pub use types::{CursorDirection, Key, Point, Rect, WidgetEvent};
 pub use widget::Widget;
 pub use widgets::{InputWidget, MessageRole, MessageWidget};
-pub use screens::{ChatEvent, ChatScreen, ConnectionStatus};
+pub use screens::{
+    ChatEvent, ChatScreen, ConfigEvent, ConfigScreen, ConnectionStatus, HelpEvent, HelpScreen,
+    ModelEntry, ModelSelectEvent, ModelSelectScreen, ProviderEntry, ProviderSelectEvent,
+    ProviderSelectScreen,
+};
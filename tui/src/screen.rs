@@ -1,10 +1,14 @@
 //! High-level screen interface for widget rendering
 
+extern crate alloc;
+
 use crate::colors::Color;
 use crate::font::Font;
 use crate::framebuffer::{Framebuffer, FramebufferInfo};
 use crate::theme::Theme;
 use crate::types::Rect;
+use alloc::vec;
+use alloc::vec::Vec;
 
 /// Box drawing style
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -22,9 +26,15 @@ pub enum BoxStyle {
 /// Main screen structure for rendering
 ///
 /// Provides a safe, high-level interface to the framebuffer for rendering
-/// text, boxes, and widgets.
+/// text, boxes, and widgets. Drawing goes to an off-screen back buffer;
+/// [`Screen::present`] copies the changed region to the framebuffer in one
+/// pass, so partially drawn frames are never visible.
 pub struct Screen {
     framebuffer: Framebuffer,
+    /// Off-screen copy of the display, one color per pixel in row order
+    back_buffer: Vec<Color>,
+    /// Region of the back buffer changed since the last present
+    dirty_rect: Option<Rect>,
     font: Option<&'static Font>,
     theme: &'static Theme,
     dirty: bool,
@@ -37,8 +47,13 @@ impl Screen {
     ///
     /// The framebuffer info must point to valid video memory.
     pub unsafe fn new(fb_info: FramebufferInfo, theme: &'static Theme) -> Self {
+        let framebuffer = Framebuffer::new(fb_info);
+        let bounds = Rect::new(0, 0, framebuffer.width(), framebuffer.height());
         Self {
-            framebuffer: Framebuffer::new(fb_info),
+            back_buffer: vec![theme.background; bounds.area()],
+            // The first present replaces whatever the bootloader left on screen
+            dirty_rect: Some(bounds),
+            framebuffer,
             font: None,
             theme,
             dirty: true,
@@ -88,52 +103,97 @@ impl Screen {
 
     /// Clear the screen with the theme's background color
     pub fn clear(&mut self) {
-        unsafe {
-            self.framebuffer.clear(self.theme.background);
-        }
+        self.fill_back_buffer(self.bounds(), self.theme.background);
         self.dirty = false;
     }
 
     /// Clear a rectangular region with a color
     pub fn clear_rect(&mut self, rect: Rect, color: Color) {
-        unsafe {
-            self.framebuffer.fill_rect(rect, color);
-        }
+        self.fill_back_buffer(rect, color);
         self.dirty = true;
     }
 
     /// Set a pixel at the given coordinates
     pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
-        unsafe {
-            self.framebuffer.set_pixel(x, y, color);
-        }
+        self.fill_back_buffer(Rect::new(x, y, 1, 1), color);
         self.dirty = true;
     }
 
     /// Fill a rectangle with a solid color
     pub fn fill_rect(&mut self, rect: Rect, color: Color) {
-        unsafe {
-            self.framebuffer.fill_rect(rect, color);
+        self.fill_back_buffer(rect, color);
+        self.dirty = true;
+    }
+
+    /// Composite a color over a rectangle using its alpha, e.g. to dim the
+    /// screen behind an overlay
+    pub fn fill_rect_blended(&mut self, rect: Rect, color: Color) {
+        let Some(rect) = self.clip(rect) else {
+            return;
+        };
+        let src = shared::Color::new(color.r, color.g, color.b, color.a);
+        let width = self.width();
+        for y in rect.y..rect.y + rect.height {
+            let start = y * width + rect.x;
+            for pixel in &mut self.back_buffer[start..start + rect.width] {
+                let dst = shared::Color::new(pixel.r, pixel.g, pixel.b, pixel.a);
+                let out = shared::framebuffer::blend_color(src, dst);
+                *pixel = Color::new_rgba(out.r, out.g, out.b, out.a);
+            }
         }
+        self.mark_region(rect);
         self.dirty = true;
     }
 
     /// Draw a horizontal line
     pub fn draw_hline(&mut self, x: usize, y: usize, width: usize, color: Color) {
-        unsafe {
-            self.framebuffer.draw_hline(x, y, width, color);
-        }
+        self.fill_back_buffer(Rect::new(x, y, width, 1), color);
         self.dirty = true;
     }
 
     /// Draw a vertical line
     pub fn draw_vline(&mut self, x: usize, y: usize, height: usize, color: Color) {
-        unsafe {
-            self.framebuffer.draw_vline(x, y, height, color);
-        }
+        self.fill_back_buffer(Rect::new(x, y, 1, height), color);
         self.dirty = true;
     }
 
+    /// Clip `rect` to the screen, returning None when nothing is left
+    fn clip(&self, rect: Rect) -> Option<Rect> {
+        let x_end = (rect.x + rect.width).min(self.width());
+        let y_end = (rect.y + rect.height).min(self.height());
+        if rect.x >= x_end || rect.y >= y_end {
+            return None;
+        }
+        Some(Rect::new(rect.x, rect.y, x_end - rect.x, y_end - rect.y))
+    }
+
+    /// Grow the dirty region to cover `rect`, which must already be clipped
+    fn mark_region(&mut self, rect: Rect) {
+        self.dirty_rect = Some(match self.dirty_rect {
+            None => rect,
+            Some(dirty) => {
+                let x = dirty.x.min(rect.x);
+                let y = dirty.y.min(rect.y);
+                let x_end = (dirty.x + dirty.width).max(rect.x + rect.width);
+                let y_end = (dirty.y + dirty.height).max(rect.y + rect.height);
+                Rect::new(x, y, x_end - x, y_end - y)
+            }
+        });
+    }
+
+    /// Fill a region of the back buffer and mark it for the next present
+    fn fill_back_buffer(&mut self, rect: Rect, color: Color) {
+        let Some(rect) = self.clip(rect) else {
+            return;
+        };
+        let width = self.width();
+        for y in rect.y..rect.y + rect.height {
+            let start = y * width + rect.x;
+            self.back_buffer[start..start + rect.width].fill(color);
+        }
+        self.mark_region(rect);
+    }
+
     /// Draw a box with the specified style
     pub fn draw_box(&mut self, rect: Rect, style: BoxStyle, color: Color) {
         match style {
@@ -186,6 +246,10 @@ impl Screen {
 
     /// Draw a single glyph at the given position
     fn draw_glyph(&mut self, x: usize, y: usize, font: &Font, glyph_data: &[u8], color: Color) {
+        let Some(cell) = self.clip(Rect::new(x, y, font.width, font.height)) else {
+            return;
+        };
+        let screen_width = self.width();
         let bytes_per_row = (font.width + 7) / 8;
 
         for row in 0..font.height {
@@ -208,22 +272,32 @@ impl Screen {
                     let bit_set = (byte >> bit_index) & 1 == 1;
 
                     if bit_set {
-                        unsafe {
-                            self.framebuffer.set_pixel(x + col, y + row, color);
-                        }
+                        self.back_buffer[(y + row) * screen_width + x + col] = color;
                     }
                 }
             }
         }
+        self.mark_region(cell);
     }
 
     /// Present the screen (flush to display)
     ///
-    /// This is a no-op for direct framebuffer rendering but provides
-    /// a hook for future double-buffering implementations.
+    /// Copies the region of the back buffer drawn since the last present to
+    /// the framebuffer. Does nothing when nothing was drawn.
     pub fn present(&mut self) {
-        // For direct framebuffer rendering, this is a no-op
-        // Future implementations might use this for double buffering
+        if let Some(rect) = self.dirty_rect.take() {
+            let width = self.width();
+            for y in rect.y..rect.y + rect.height {
+                let start = y * width + rect.x;
+                unsafe {
+                    self.framebuffer.write_span(
+                        rect.x,
+                        y,
+                        &self.back_buffer[start..start + rect.width],
+                    );
+                }
+            }
+        }
         self.dirty = false;
     }
 
@@ -252,3 +326,120 @@ impl Screen {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::PixelFormat;
+    use crate::theme::DARK_THEME;
+
+    const WIDTH: usize = 8;
+    const HEIGHT: usize = 4;
+    const STRIDE: usize = WIDTH * 4;
+
+    fn pixel(memory: &[u8], x: usize, y: usize) -> &[u8] {
+        let offset = y * STRIDE + x * 4;
+        &memory[offset..offset + 4]
+    }
+
+    #[test]
+    fn drawing_reaches_framebuffer_only_on_present() {
+        let mut memory = vec![0u8; STRIDE * HEIGHT];
+        let info = FramebufferInfo::new(
+            memory.as_mut_ptr(),
+            WIDTH,
+            HEIGHT,
+            STRIDE,
+            PixelFormat::Rgba,
+        );
+        let mut screen = unsafe { Screen::new(info, &DARK_THEME) };
+        let background = DARK_THEME.background;
+        let red = Color::new(255, 0, 0);
+
+        screen.fill_rect(Rect::new(1, 1, 2, 2), red);
+        assert_eq!(pixel(&memory, 1, 1), &[0, 0, 0, 0]);
+
+        screen.present();
+        assert_eq!(pixel(&memory, 1, 1), &[255, 0, 0, 255]);
+        assert_eq!(pixel(&memory, 2, 2), &[255, 0, 0, 255]);
+        assert_eq!(
+            pixel(&memory, 0, 0),
+            &[background.r, background.g, background.b, background.a]
+        );
+    }
+
+    #[test]
+    fn blended_fill_dims_what_is_underneath() {
+        let mut memory = vec![0u8; STRIDE * HEIGHT];
+        let info = FramebufferInfo::new(
+            memory.as_mut_ptr(),
+            WIDTH,
+            HEIGHT,
+            STRIDE,
+            PixelFormat::Rgba,
+        );
+        let mut screen = unsafe { Screen::new(info, &DARK_THEME) };
+
+        screen.fill_rect(Rect::new(0, 0, WIDTH, HEIGHT), Color::new(200, 100, 0));
+        screen.fill_rect_blended(Rect::new(0, 0, 1, 1), Color::new_rgba(0, 0, 0, 128));
+        screen.present();
+        assert_eq!(pixel(&memory, 0, 0), &[100, 50, 0, 255]);
+        assert_eq!(pixel(&memory, 1, 0), &[200, 100, 0, 255]);
+    }
+
+    #[test]
+    fn present_copies_only_the_dirty_region() {
+        let mut memory = vec![0u8; STRIDE * HEIGHT];
+        let info = FramebufferInfo::new(
+            memory.as_mut_ptr(),
+            WIDTH,
+            HEIGHT,
+            STRIDE,
+            PixelFormat::Rgba,
+        );
+        let mut screen = unsafe { Screen::new(info, &DARK_THEME) };
+        screen.present();
+
+        memory[0..4].copy_from_slice(&[9, 9, 9, 9]);
+        screen.draw_hline(4, 3, 10, Color::new(0, 255, 0));
+        screen.present();
+
+        assert_eq!(pixel(&memory, 0, 0), &[9, 9, 9, 9]);
+        assert_eq!(pixel(&memory, 7, 3), &[0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn wide_psf2_glyphs_use_their_full_width() {
+        // 12px wide, 2 rows: 2 bytes per row
+        const WIDTH: u32 = 12;
+        const HEIGHT: u32 = 2;
+        let mut data = Vec::from([0x72, 0xb5, 0x4a, 0x86]);
+        // version, header_size, flags, length, char_size, height, width
+        for field in [0u32, 32, 0, 66, 4, HEIGHT, WIDTH] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        let mut glyphs = vec![0u8; 66 * 4];
+        // 'A': row 0 has columns 0 and 11 set, row 1 has column 9
+        glyphs[65 * 4..66 * 4].copy_from_slice(&[0x80, 0x10, 0x00, 0x40]);
+        data.extend_from_slice(&glyphs);
+        let font = unsafe { Font::load_psf(Vec::leak(data)) }.unwrap();
+        let font: &'static Font = alloc::boxed::Box::leak(alloc::boxed::Box::new(font));
+
+        let stride = 32 * 4;
+        let mut memory = vec![0u8; stride * 2];
+        let info = FramebufferInfo::new(memory.as_mut_ptr(), 32, 2, stride, PixelFormat::Rgba);
+        let mut screen = unsafe { Screen::new(info, &DARK_THEME) };
+        screen.set_font(font);
+        let white = Color::new(255, 255, 255);
+
+        assert_eq!(screen.draw_text(0, 0, "AA", white), 2);
+        screen.present();
+
+        let lit = |x: usize, y: usize| memory[y * stride + x * 4..][..4] == [255, 255, 255, 255];
+        let set: Vec<(usize, usize)> = (0..2)
+            .flat_map(|y| (0..32).map(move |x| (x, y)))
+            .filter(|&(x, y)| lit(x, y))
+            .collect();
+        assert_eq!(set, [(0, 0), (11, 0), (12, 0), (23, 0), (9, 1), (21, 1)]);
+    }
+}
@@ -24,6 +24,10 @@ const MARGIN_V: usize = 1;  // Vertical margin from screen edge
 const HEADER_LINES: usize = 1;
 const INPUT_LINES: usize = 2;
 const FOOTER_LINES: usize = 1;
+const SCROLLBAR_MIN_THUMB: usize = 8; // Minimum thumb height in pixels
+const STATUS_ERROR_MAX_CHARS: usize = 20;
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+const SPINNER_TICKS_PER_FRAME: u32 = 6; // ~100ms at the 16ms event loop
 
 /// Connection status for the chat screen
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -32,6 +36,8 @@ pub enum ConnectionStatus {
     Connected,
     /// Disconnected from the LLM provider
     Disconnected,
+    /// No DHCP lease, only a link-local address: LAN providers may still work
+    LinkLocalOnly,
     /// Error state with message
     Error(String),
 }
@@ -41,7 +47,7 @@ pub enum ConnectionStatus {
 pub enum ChatEvent {
     /// No event
     None,
-    /// User submitted a message (content is in the input widget)
+    /// User submitted a message (see `ChatScreen::last_submitted`)
     MessageSubmitted,
     /// User wants to scroll up
     ScrollUp,
@@ -51,6 +57,8 @@ pub enum ChatEvent {
     ScrollToTop,
     /// User wants to go to bottom
     ScrollToBottom,
+    /// User wants to switch to the next persona (F5)
+    CyclePersona,
     /// Custom event
     Custom(&'static str),
 }
@@ -77,6 +85,20 @@ pub struct ChatScreen {
     model: String,
     /// Title to display in header
     title: String,
+    /// Cumulative prompt tokens for this conversation
+    prompt_tokens: usize,
+    /// Cumulative completion tokens for this conversation
+    completion_tokens: usize,
+    /// Estimated spend this session, in micro-dollars
+    session_cost_micros: u64,
+    /// Text of the most recently submitted message
+    last_submitted: String,
+    /// Wall-clock time (seconds since the Unix epoch) for new messages
+    clock: Option<fn() -> Option<u64>>,
+    /// Whether a response is being generated
+    generating: bool,
+    /// Ticks since generation started, for the spinner animation
+    spinner_ticks: u32,
 }
 
 impl ChatScreen {
@@ -95,9 +117,24 @@ impl ChatScreen {
             provider,
             model,
             title: "moteOS Chat".to_string(),
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            session_cost_micros: 0,
+            last_submitted: String::new(),
+            clock: None,
+            generating: false,
+            spinner_ticks: 0,
         }
     }
 
+    /// Set the clock used to timestamp new messages
+    ///
+    /// Messages are shown without a time until a clock is set, or while it
+    /// returns `None`.
+    pub fn set_clock(&mut self, clock: fn() -> Option<u64>) {
+        self.clock = Some(clock);
+    }
+
     /// Add a message to the conversation
     ///
     /// # Arguments
@@ -105,7 +142,7 @@ impl ChatScreen {
     /// * `role` - The role of the message sender
     /// * `content` - The message content
     pub fn add_message(&mut self, role: MessageRole, content: String) {
-        let timestamp = None; // TODO: Get actual timestamp when timer is available
+        let timestamp = self.clock.and_then(|clock| clock());
         let message = MessageWidget::new(role, content, timestamp);
         self.messages.push(message);
         // Auto-scroll to bottom when new message is added
@@ -153,6 +190,19 @@ impl ChatScreen {
         self.scroll_offset = 0;
     }
 
+    /// Current scroll position as a fraction of the scrollable range
+    ///
+    /// Returns 0.0 when scrolled to the oldest message and 1.0 when the
+    /// newest message is at the bottom (the default).
+    pub fn scroll_fraction(&self) -> f32 {
+        let max_scroll = self.messages.len().saturating_sub(1);
+        if max_scroll == 0 {
+            return 1.0;
+        }
+        let offset = self.scroll_offset.min(max_scroll);
+        1.0 - offset as f32 / max_scroll as f32
+    }
+
     /// Set the connection status
     ///
     /// # Arguments
@@ -167,6 +217,38 @@ impl ChatScreen {
         &self.status
     }
 
+    /// Show or hide the spinner next to the status
+    pub fn set_generating(&mut self, generating: bool) {
+        self.generating = generating;
+        self.spinner_ticks = 0;
+    }
+
+    /// Whether the spinner is shown
+    pub fn is_generating(&self) -> bool {
+        self.generating
+    }
+
+    /// Advance the spinner by one tick
+    ///
+    /// # Returns
+    /// `true` when the spinner moved to a new glyph and the header needs
+    /// redrawing (see `render_header_only`)
+    pub fn tick_spinner(&mut self) -> bool {
+        if !self.generating {
+            return false;
+        }
+        self.spinner_ticks = self.spinner_ticks.wrapping_add(1);
+        self.spinner_ticks.is_multiple_of(SPINNER_TICKS_PER_FRAME)
+    }
+
+    /// Current spinner glyph, if generating
+    fn spinner_glyph(&self) -> Option<char> {
+        self.generating.then(|| {
+            let frame = self.spinner_ticks / SPINNER_TICKS_PER_FRAME;
+            SPINNER_FRAMES[frame as usize % SPINNER_FRAMES.len()]
+        })
+    }
+
     /// Set the provider name
     ///
     /// # Arguments
@@ -195,6 +277,43 @@ impl ChatScreen {
         &self.model
     }
 
+    /// Set the title shown in the header (e.g. a generated conversation title)
+    pub fn set_title(&mut self, title: String) {
+        self.title = title;
+    }
+
+    /// Get the header title
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Add token usage from a completed request to the conversation totals
+    pub fn add_token_usage(&mut self, prompt_tokens: usize, completion_tokens: usize) {
+        self.prompt_tokens = self.prompt_tokens.saturating_add(prompt_tokens);
+        self.completion_tokens = self.completion_tokens.saturating_add(completion_tokens);
+    }
+
+    /// Reset the conversation token totals
+    pub fn reset_token_usage(&mut self) {
+        self.prompt_tokens = 0;
+        self.completion_tokens = 0;
+    }
+
+    /// Cumulative (prompt, completion) tokens for this conversation
+    pub fn token_usage(&self) -> (usize, usize) {
+        (self.prompt_tokens, self.completion_tokens)
+    }
+
+    /// Set the estimated spend this session, in micro-dollars
+    pub fn set_session_cost(&mut self, micros: u64) {
+        self.session_cost_micros = micros;
+    }
+
+    /// Cumulative total tokens for this conversation
+    pub fn total_tokens(&self) -> usize {
+        self.prompt_tokens.saturating_add(self.completion_tokens)
+    }
+
     /// Get the input widget (mutable)
     pub fn input_mut(&mut self) -> &mut InputWidget {
         &mut self.input
@@ -223,7 +342,9 @@ impl ChatScreen {
             WidgetEvent::Submit => {
                 let text = self.input.get_text().to_string();
                 if !text.trim().is_empty() {
+                    self.input.add_history(text.clone());
                     self.input.clear();
+                    self.last_submitted = text;
                     return ChatEvent::MessageSubmitted;
                 }
                 ChatEvent::None
@@ -254,6 +375,7 @@ impl ChatScreen {
                         self.scroll_to_bottom();
                         ChatEvent::ScrollToBottom
                     }
+                    Key::F5 => ChatEvent::CyclePersona,
                     _ => ChatEvent::None,
                 }
             }
@@ -261,6 +383,14 @@ impl ChatScreen {
         }
     }
 
+    /// Get the text of the most recently submitted message
+    ///
+    /// The input is cleared on submit, so read the message from here after
+    /// receiving `ChatEvent::MessageSubmitted`.
+    pub fn last_submitted(&self) -> &str {
+        &self.last_submitted
+    }
+
     /// Render only the input area (fast update for typing)
     ///
     /// This avoids redrawing the entire screen when only the input has changed.
@@ -309,6 +439,30 @@ impl ChatScreen {
         self.input.render(screen, input_rect);
     }
 
+    /// Render only the header bar (faster for spinner updates)
+    ///
+    /// # Arguments
+    ///
+    /// * `screen` - The screen to render to
+    pub fn render_header_only(&self, screen: &mut Screen) {
+        let theme = screen.theme();
+        let bounds = screen.bounds();
+
+        let Some((char_width, char_height)) = screen.char_size() else {
+            return; // Can't render without a font
+        };
+
+        // Header sits at the top of the container, inside its border
+        let inner_x = MARGIN_H * char_width + 1;
+        let inner_y = MARGIN_V * char_height + 1;
+        let inner_width = bounds
+            .width
+            .saturating_sub(MARGIN_H * char_width * 2)
+            .saturating_sub(2);
+        let header_rect = Rect::new(inner_x, inner_y, inner_width, HEADER_LINES * char_height);
+        self.render_header(screen, header_rect, theme, char_width, char_height);
+    }
+
     /// Render the chat screen to the given screen
     ///
     /// # Arguments
@@ -417,6 +571,12 @@ impl ChatScreen {
         let title_x = rect.x + char_width;
         screen.draw_text(title_x, text_y, &self.title, theme.text_primary);
 
+        // Context consumed so far, after the title
+        if let Some(tokens_text) = self.format_header_tokens() {
+            let tokens_x = title_x + (self.title.chars().count() + 2) * char_width;
+            screen.draw_text(tokens_x, text_y, &tokens_text, theme.text_tertiary);
+        }
+
         // Render provider and model in the middle
         let mut provider_text = self.provider.clone();
         provider_text.push_str(" / ");
@@ -425,8 +585,13 @@ impl ChatScreen {
         let provider_x = rect.x + (rect.width / 2).saturating_sub(provider_text_width / 2);
         screen.draw_text(provider_x, text_y, &provider_text, theme.text_secondary);
 
-        // Render status on the right
-        let status_text = self.format_status();
+        // Render status on the right, after the spinner while generating
+        let mut status_text = String::new();
+        if let Some(glyph) = self.spinner_glyph() {
+            status_text.push(glyph);
+            status_text.push(' ');
+        }
+        status_text.push_str(&self.format_status());
         let status_color = self.get_status_color(theme);
         let status_text_width = status_text.chars().count() * char_width;
         let status_x = rect.x + rect.width.saturating_sub(status_text_width + char_width);
@@ -541,21 +706,45 @@ impl ChatScreen {
             }
         }
 
-        // Show scroll indicator if scrolled
-        if self.scroll_offset > 0 {
-            use alloc::string::ToString;
-            let mut indicator = String::from("↑ ");
-            indicator.push_str(&self.scroll_offset.to_string());
-            indicator.push_str(" more");
-            screen.draw_text(
-                rect.x + char_width,
-                rect.y + char_height / 2,
-                &indicator,
-                theme.text_tertiary,
-            );
+        // Draw scrollbar when content overflows
+        if total_height > rect.height {
+            self.render_scrollbar(screen, rect, theme, char_width, total_height);
         }
     }
 
+    /// Render a vertical scrollbar on the right edge of the chat area
+    ///
+    /// The thumb size reflects the visible portion of the content and its
+    /// position follows `scroll_fraction()`.
+    fn render_scrollbar(
+        &self,
+        screen: &mut Screen,
+        rect: Rect,
+        theme: &Theme,
+        char_width: usize,
+        total_height: usize,
+    ) {
+        if rect.height == 0 || total_height == 0 {
+            return;
+        }
+
+        // Track sits in the right-hand gutter next to the messages
+        let track_width = (char_width / 2).max(2).min(rect.width);
+        let track_x = rect.x + rect.width - track_width;
+        let track_rect = Rect::new(track_x, rect.y, track_width, rect.height);
+        screen.fill_rect(track_rect, theme.surface);
+
+        let thumb_height = (rect.height * rect.height / total_height)
+            .max(SCROLLBAR_MIN_THUMB)
+            .min(rect.height);
+        let travel = rect.height - thumb_height;
+        let thumb_offset = ((travel as f32) * self.scroll_fraction()) as usize;
+        let thumb_y = rect.y + thumb_offset.min(travel);
+
+        let thumb_rect = Rect::new(track_x, thumb_y, track_width, thumb_height);
+        screen.fill_rect(thumb_rect, theme.accent_primary);
+    }
+
     /// Estimate the height needed for a message
     fn estimate_message_height(
         &self,
@@ -600,6 +789,7 @@ impl ChatScreen {
             ("F2", "Provider"),
             ("F3", "Model"),
             ("F4", "Config"),
+            ("F5", "Persona"),
             ("F9", "New"),
             ("F10", "Quit"),
         ];
@@ -614,6 +804,48 @@ impl ChatScreen {
             screen.draw_text(x, text_y, label, theme.text_secondary);
             x += label.chars().count() * char_width + char_width; // Single char spacing
         }
+
+        // Cumulative token usage and cost on the right, if they fit after
+        // the hotkeys
+        if self.total_tokens() > 0 || self.session_cost_micros > 0 {
+            let usage_text = self.format_usage_summary();
+            let usage_width = usage_text.chars().count() * char_width;
+            let usage_x = rect.x + rect.width.saturating_sub(usage_width + char_width);
+            if usage_x > x {
+                screen.draw_text(usage_x, text_y, &usage_text, theme.text_tertiary);
+            }
+        }
+    }
+
+    /// Token usage and session cost, whichever are known
+    fn format_usage_summary(&self) -> String {
+        let mut text = String::new();
+        if self.total_tokens() > 0 {
+            text.push_str(&self.format_token_usage());
+        }
+        if self.session_cost_micros > 0 {
+            if !text.is_empty() {
+                text.push_str("  ");
+            }
+            text.push_str(&format_session_cost(self.session_cost_micros));
+        }
+        text
+    }
+
+    /// Format the cumulative token usage for the status bar
+    fn format_token_usage(&self) -> String {
+        alloc::format!(
+            "Tokens: {} ({} in / {} out)",
+            self.total_tokens(),
+            self.prompt_tokens,
+            self.completion_tokens
+        )
+    }
+
+    /// Format the conversation's total tokens for the header, once known
+    fn format_header_tokens(&self) -> Option<String> {
+        let total = self.total_tokens();
+        (total > 0).then(|| alloc::format!("{} tokens", total))
     }
 
     /// Format the connection status as a string
@@ -621,13 +853,16 @@ impl ChatScreen {
         match &self.status {
             ConnectionStatus::Connected => "● Connected".to_string(),
             ConnectionStatus::Disconnected => "○ Disconnected".to_string(),
+            ConnectionStatus::LinkLocalOnly => "● No internet — link-local only".to_string(),
             ConnectionStatus::Error(msg) => {
-                // Truncate error message if too long
+                // Truncate error message if too long (on a char boundary)
                 let mut error_text = String::from("● Error: ");
-                if msg.len() > 20 {
-                    error_text.push_str(&msg[..20]);
-                } else {
-                    error_text.push_str(msg);
+                match msg.char_indices().nth(STATUS_ERROR_MAX_CHARS) {
+                    Some((end, _)) => {
+                        error_text.push_str(&msg[..end]);
+                        error_text.push('…');
+                    }
+                    None => error_text.push_str(msg),
                 }
                 error_text
             }
@@ -639,8 +874,157 @@ impl ChatScreen {
         match &self.status {
             ConnectionStatus::Connected => theme.accent_success,
             ConnectionStatus::Disconnected => theme.text_tertiary,
+            ConnectionStatus::LinkLocalOnly => theme.accent_warning,
             ConnectionStatus::Error(_) => theme.accent_error,
         }
     }
 }
 
+/// Format a micro-dollar amount as "$0.0123 this session"
+///
+/// Integer arithmetic only; the amount is truncated to 1/100 of a cent.
+fn format_session_cost(micros: u64) -> String {
+    alloc::format!(
+        "${}.{:04} this session",
+        micros / 1_000_000,
+        (micros % 1_000_000) / 100
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen_with_messages(count: usize) -> ChatScreen {
+        let mut chat = ChatScreen::new("OpenAI".into(), "gpt-4o".into());
+        for i in 0..count {
+            chat.add_message(MessageRole::User, alloc::format!("message {i}"));
+        }
+        chat
+    }
+
+    #[test]
+    fn test_messages_are_timestamped_once_a_clock_is_set() {
+        let mut chat = screen_with_messages(1);
+        assert_eq!(chat.messages[0].timestamp, None);
+
+        chat.set_clock(|| Some(1_792_271_109));
+        chat.add_message(MessageRole::Assistant, "hi".into());
+        assert_eq!(chat.messages[1].timestamp, Some(1_792_271_109));
+    }
+
+    #[test]
+    fn test_scroll_fraction_at_bottom_by_default() {
+        let chat = screen_with_messages(5);
+        assert_eq!(chat.scroll_fraction(), 1.0);
+    }
+
+    #[test]
+    fn test_scroll_fraction_clamps_at_top() {
+        let mut chat = screen_with_messages(5);
+        chat.scroll_to_top();
+        assert_eq!(chat.scroll_fraction(), 0.0);
+    }
+
+    #[test]
+    fn test_scroll_fraction_midway() {
+        let mut chat = screen_with_messages(21);
+        chat.scroll_up();
+        assert!((chat.scroll_fraction() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_format_status_truncates_multibyte_error() {
+        let mut chat = screen_with_messages(0);
+        chat.set_status(ConnectionStatus::Error("ошибка сети: соединение сброшено".into()));
+        let text = chat.format_status();
+        let prefix = "● Error: ".chars().count();
+        assert!(text.ends_with('…'));
+        assert_eq!(text.chars().count(), prefix + STATUS_ERROR_MAX_CHARS + 1);
+    }
+
+    #[test]
+    fn test_format_status_short_error_untouched() {
+        let mut chat = screen_with_messages(0);
+        chat.set_status(ConnectionStatus::Error("délai dépassé".into()));
+        assert_eq!(chat.format_status(), "● Error: délai dépassé");
+    }
+
+    #[test]
+    fn test_format_status_link_local_only() {
+        let mut chat = screen_with_messages(0);
+        chat.set_status(ConnectionStatus::LinkLocalOnly);
+        assert_eq!(chat.format_status(), "● No internet — link-local only");
+    }
+
+    #[test]
+    fn test_spinner_cycles_while_generating() {
+        let mut chat = screen_with_messages(0);
+        assert!(!chat.tick_spinner());
+        assert_eq!(chat.spinner_glyph(), None);
+
+        chat.set_generating(true);
+        assert_eq!(chat.spinner_glyph(), Some('|'));
+        let mut glyphs = alloc::vec::Vec::new();
+        for _ in 0..SPINNER_TICKS_PER_FRAME * 4 {
+            if chat.tick_spinner() {
+                glyphs.push(chat.spinner_glyph().unwrap());
+            }
+        }
+        assert_eq!(glyphs, ['/', '-', '\\', '|']);
+
+        chat.set_generating(false);
+        assert_eq!(chat.spinner_glyph(), None);
+        assert!(!chat.tick_spinner());
+    }
+
+    #[test]
+    fn test_token_usage_accumulates() {
+        let mut chat = screen_with_messages(0);
+        chat.add_token_usage(12, 30);
+        chat.add_token_usage(50, 8);
+        assert_eq!(chat.token_usage(), (62, 38));
+        assert_eq!(chat.total_tokens(), 100);
+        assert_eq!(chat.format_token_usage(), "Tokens: 100 (62 in / 38 out)");
+        assert_eq!(chat.format_header_tokens().as_deref(), Some("100 tokens"));
+        chat.reset_token_usage();
+        assert_eq!(chat.total_tokens(), 0);
+        assert_eq!(chat.format_header_tokens(), None);
+    }
+
+    #[test]
+    fn test_session_cost_formatting() {
+        assert_eq!(format_session_cost(12_300), "$0.0123 this session");
+        assert_eq!(format_session_cost(2_500_099), "$2.5000 this session");
+
+        let mut chat = screen_with_messages(0);
+        chat.set_session_cost(12_300);
+        assert_eq!(chat.format_usage_summary(), "$0.0123 this session");
+    }
+
+    #[test]
+    fn test_submit_records_message_and_history() {
+        let mut chat = screen_with_messages(0);
+        chat.handle_input(Key::Char('h'));
+        chat.handle_input(Key::Char('i'));
+        assert_eq!(chat.handle_input(Key::Enter), ChatEvent::MessageSubmitted);
+        assert_eq!(chat.last_submitted(), "hi");
+        assert_eq!(chat.input().get_text(), "");
+        chat.handle_input(Key::Up);
+        assert_eq!(chat.input().get_text(), "hi");
+    }
+
+    #[test]
+    fn test_f5_cycles_persona_without_touching_input() {
+        let mut chat = screen_with_messages(0);
+        chat.handle_input(Key::Char('x'));
+        assert_eq!(chat.handle_input(Key::F5), ChatEvent::CyclePersona);
+        assert_eq!(chat.input().get_text(), "x");
+    }
+
+    #[test]
+    fn test_scroll_fraction_empty() {
+        let chat = screen_with_messages(0);
+        assert_eq!(chat.scroll_fraction(), 1.0);
+    }
+}
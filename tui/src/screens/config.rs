@@ -0,0 +1,339 @@
+//! Configuration screen implementation
+//!
+//! Provides a dialog for managing provider API keys:
+//! - Provider list with configured/not-set markers
+//! - Masked API key input
+//! - Hint line with the available keys
+//!
+//! The screen only collects input; persisting the key is left to the caller,
+//! which receives a `ConfigEvent::ApiKeySet`.
+
+extern crate alloc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::screen::{BoxStyle, Screen};
+use crate::types::{Key, Rect, WidgetEvent};
+use crate::widget::Widget;
+use crate::widgets::InputWidget;
+
+// Layout constants (in character units)
+const DIALOG_WIDTH: usize = 48;
+const INPUT_LINES: usize = 3;
+
+/// A provider shown in the configuration screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderEntry {
+    /// Identifier reported in events (e.g. "openai")
+    pub id: String,
+    /// Display name (e.g. "OpenAI")
+    pub label: String,
+    /// Whether an API key is already stored for this provider
+    pub configured: bool,
+}
+
+impl ProviderEntry {
+    pub fn new(id: &str, label: &str, configured: bool) -> Self {
+        Self {
+            id: id.to_string(),
+            label: label.to_string(),
+            configured,
+        }
+    }
+}
+
+/// Events emitted by the configuration screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigEvent {
+    /// No event
+    None,
+    /// User entered a new API key for a provider
+    ApiKeySet { provider: String, key: String },
+    /// User wants to leave the configuration screen
+    Close,
+}
+
+/// Configuration screen for entering or replacing provider API keys
+///
+/// Up/Down selects a provider and Enter starts editing its key. While
+/// editing, Enter submits the key and Escape cancels; Escape in the list
+/// closes the screen.
+pub struct ConfigScreen {
+    /// Providers that can be configured
+    providers: Vec<ProviderEntry>,
+    /// Index of the selected provider
+    selected: usize,
+    /// Masked input for the API key
+    input: InputWidget,
+    /// Whether the key field currently has focus
+    editing: bool,
+    /// Feedback line shown under the input (e.g. "Saved")
+    notice: Option<String>,
+}
+
+impl ConfigScreen {
+    /// Create a new configuration screen
+    ///
+    /// # Arguments
+    ///
+    /// * `providers` - Providers to list, in display order
+    pub fn new(providers: Vec<ProviderEntry>) -> Self {
+        let mut input = InputWidget::new("Press Enter to set a key".into());
+        input.set_masked(true);
+        Self {
+            providers,
+            selected: 0,
+            input,
+            editing: false,
+            notice: None,
+        }
+    }
+
+    /// Get the listed providers
+    pub fn providers(&self) -> &[ProviderEntry] {
+        &self.providers
+    }
+
+    /// Get the currently selected provider, if any
+    pub fn selected(&self) -> Option<&ProviderEntry> {
+        self.providers.get(self.selected)
+    }
+
+    /// Check whether the key field is being edited
+    pub fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    /// Update whether a provider has a stored key
+    pub fn set_configured(&mut self, provider: &str, configured: bool) {
+        if let Some(entry) = self.providers.iter_mut().find(|p| p.id == provider) {
+            entry.configured = configured;
+        }
+    }
+
+    /// Show a feedback line under the input (e.g. the result of saving)
+    pub fn set_notice(&mut self, notice: String) {
+        self.notice = Some(notice);
+    }
+
+    /// Handle keyboard input
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that was pressed
+    ///
+    /// # Returns
+    ///
+    /// A ConfigEvent indicating what action should be taken
+    pub fn handle_input(&mut self, key: Key) -> ConfigEvent {
+        if self.editing {
+            return self.handle_edit_input(key);
+        }
+
+        match key {
+            Key::Up => {
+                self.selected = self.selected.saturating_sub(1);
+                self.notice = None;
+                ConfigEvent::None
+            }
+            Key::Down => {
+                if self.selected + 1 < self.providers.len() {
+                    self.selected += 1;
+                }
+                self.notice = None;
+                ConfigEvent::None
+            }
+            Key::Enter => {
+                if self.selected().is_some() {
+                    self.editing = true;
+                    self.notice = None;
+                    self.input.clear();
+                    self.input.set_focused(true);
+                }
+                ConfigEvent::None
+            }
+            Key::Escape | Key::F4 => ConfigEvent::Close,
+            _ => ConfigEvent::None,
+        }
+    }
+
+    /// Handle input while the key field has focus
+    fn handle_edit_input(&mut self, key: Key) -> ConfigEvent {
+        match self.input.handle_input(key) {
+            WidgetEvent::Submit => {
+                let key = self.input.get_text().trim().to_string();
+                if key.is_empty() {
+                    return ConfigEvent::None;
+                }
+                self.stop_editing();
+                match self.selected() {
+                    Some(entry) => ConfigEvent::ApiKeySet {
+                        provider: entry.id.clone(),
+                        key,
+                    },
+                    None => ConfigEvent::None,
+                }
+            }
+            WidgetEvent::Close => {
+                self.stop_editing();
+                ConfigEvent::None
+            }
+            _ => ConfigEvent::None,
+        }
+    }
+
+    /// Leave the key field, dropping whatever was typed
+    fn stop_editing(&mut self) {
+        self.editing = false;
+        self.input.clear();
+        self.input.set_focused(false);
+    }
+
+    /// Render the configuration screen as a centered dialog
+    ///
+    /// # Arguments
+    ///
+    /// * `screen` - The screen to render to
+    pub fn render(&mut self, screen: &mut Screen) {
+        let theme = screen.theme();
+        let bounds = screen.bounds();
+
+        // Get character dimensions for layout calculations
+        let Some((char_width, char_height)) = screen.char_size() else {
+            return; // Can't render without a font
+        };
+
+        // Title, blank, providers, blank, input, notice, blank, hints
+        let rows = 2 + self.providers.len() + 1 + INPUT_LINES + 1 + 1 + 1;
+        let width = (DIALOG_WIDTH * char_width).min(bounds.width);
+        let height = ((rows + 2) * char_height).min(bounds.height);
+        let dialog = Rect::new(
+            bounds.x + (bounds.width - width) / 2,
+            bounds.y + (bounds.height - height) / 2,
+            width,
+            height,
+        );
+
+        screen.fill_rect(dialog, theme.surface);
+        screen.draw_box(dialog, BoxStyle::Double, theme.border);
+
+        let text_x = dialog.x + char_width * 2;
+        let mut y = dialog.y + char_height;
+
+        screen.draw_text(text_x, y, "Configuration", theme.accent_primary);
+        y += char_height * 2;
+
+        // Provider list
+        for (i, entry) in self.providers.iter().enumerate() {
+            let is_selected = i == self.selected;
+            let marker = if is_selected { "> " } else { "  " };
+            let color = if is_selected {
+                theme.accent_primary
+            } else {
+                theme.text_secondary
+            };
+            let mut line = String::from(marker);
+            line.push_str(&entry.label);
+            screen.draw_text(text_x, y, &line, color);
+
+            let (state, state_color) = if entry.configured {
+                ("[key set]", theme.accent_success)
+            } else {
+                ("[not set]", theme.text_tertiary)
+            };
+            let state_x = dialog.x
+                + dialog
+                    .width
+                    .saturating_sub((state.chars().count() + 2) * char_width);
+            screen.draw_text(state_x, y, state, state_color);
+            y += char_height;
+        }
+        y += char_height;
+
+        // Masked API key field
+        let input_rect = Rect::new(
+            text_x,
+            y,
+            dialog.width.saturating_sub(char_width * 4),
+            INPUT_LINES * char_height,
+        );
+        self.input.render(screen, input_rect);
+        y += input_rect.height;
+
+        if let Some(notice) = &self.notice {
+            screen.draw_text(text_x, y, notice, theme.text_secondary);
+        }
+        y += char_height * 2;
+
+        let hints = if self.editing {
+            "Enter: Save  Esc: Cancel"
+        } else {
+            "Up/Down: Select  Enter: Edit key  Esc: Close"
+        };
+        screen.draw_text(text_x, y, hints, theme.text_tertiary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen() -> ConfigScreen {
+        ConfigScreen::new(alloc::vec![
+            ProviderEntry::new("openai", "OpenAI", true),
+            ProviderEntry::new("anthropic", "Anthropic", false),
+        ])
+    }
+
+    fn type_text(config: &mut ConfigScreen, text: &str) {
+        for ch in text.chars() {
+            config.handle_input(Key::Char(ch));
+        }
+    }
+
+    #[test]
+    fn test_selection_is_clamped() {
+        let mut config = screen();
+        config.handle_input(Key::Up);
+        assert_eq!(config.selected().unwrap().id, "openai");
+        config.handle_input(Key::Down);
+        config.handle_input(Key::Down);
+        assert_eq!(config.selected().unwrap().id, "anthropic");
+    }
+
+    #[test]
+    fn test_enter_key_emits_api_key_set() {
+        let mut config = screen();
+        config.handle_input(Key::Down);
+        config.handle_input(Key::Enter);
+        assert!(config.is_editing());
+        type_text(&mut config, "sk-ant");
+        assert_eq!(
+            config.handle_input(Key::Enter),
+            ConfigEvent::ApiKeySet {
+                provider: "anthropic".into(),
+                key: "sk-ant".into(),
+            }
+        );
+        assert!(!config.is_editing());
+    }
+
+    #[test]
+    fn test_escape_cancels_edit_then_closes() {
+        let mut config = screen();
+        config.handle_input(Key::Enter);
+        type_text(&mut config, "abc");
+        assert_eq!(config.handle_input(Key::Escape), ConfigEvent::None);
+        assert!(!config.is_editing());
+        assert_eq!(config.handle_input(Key::Escape), ConfigEvent::Close);
+    }
+
+    #[test]
+    fn test_empty_key_is_not_submitted() {
+        let mut config = screen();
+        config.handle_input(Key::Enter);
+        type_text(&mut config, "  ");
+        assert_eq!(config.handle_input(Key::Enter), ConfigEvent::None);
+        assert!(config.is_editing());
+    }
+}
@@ -0,0 +1,150 @@
+//! Help screen implementation
+//!
+//! Renders a centered, bordered overlay listing the keybindings. The dialog
+//! is clamped to the screen so it stays usable on small framebuffers.
+
+use crate::screen::{BoxStyle, Screen};
+use crate::types::{Key, Rect};
+
+// Layout constants (in character units)
+const KEY_COLUMN: usize = 16; // Width of the key column, including padding
+const PADDING: usize = 2;
+
+/// Keybindings shown by the help screen, as (keys, description)
+const KEYBINDINGS: &[(&str, &str)] = &[
+    ("Enter", "Send message"),
+    ("PageUp/PageDown", "Scroll conversation"),
+    ("Home/End", "Jump to top/bottom"),
+    ("F1", "Toggle this help"),
+    ("F2", "Select LLM provider"),
+    ("F3", "Switch model"),
+    ("F4", "Configure API keys"),
+    ("F5", "Next persona (new chat)"),
+    ("F9", "New chat"),
+    ("F10", "Shutdown"),
+    ("F12", "Toggle network statistics"),
+    ("Esc", "Close this help"),
+];
+
+/// Events emitted by the help screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HelpEvent {
+    /// No event
+    None,
+    /// User dismissed the help screen
+    Close,
+}
+
+/// Help overlay describing all keybindings
+#[derive(Debug, Default)]
+pub struct HelpScreen;
+
+impl HelpScreen {
+    /// Create a new help screen
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Keybindings listed by the help screen
+    pub fn keybindings(&self) -> &'static [(&'static str, &'static str)] {
+        KEYBINDINGS
+    }
+
+    /// Handle keyboard input
+    ///
+    /// Esc and F1 close the help screen; other keys are ignored.
+    pub fn handle_input(&mut self, key: Key) -> HelpEvent {
+        match key {
+            Key::Escape | Key::F1 => HelpEvent::Close,
+            _ => HelpEvent::None,
+        }
+    }
+
+    /// Render the help overlay centered on the screen
+    ///
+    /// # Arguments
+    ///
+    /// * `screen` - The screen to render to
+    pub fn render(&self, screen: &mut Screen) {
+        let theme = screen.theme();
+        let bounds = screen.bounds();
+
+        // Get character dimensions for layout calculations
+        let Some((char_width, char_height)) = screen.char_size() else {
+            return; // Can't render without a font
+        };
+
+        let dialog = dialog_rect(bounds, char_width, char_height);
+        if dialog.width == 0 || dialog.height == 0 {
+            return;
+        }
+
+        screen.fill_rect(dialog, theme.surface);
+        screen.draw_box(dialog, BoxStyle::Double, theme.border);
+
+        let text_x = dialog.x + PADDING * char_width;
+        let desc_x = text_x + KEY_COLUMN * char_width;
+        let bottom = dialog.y + dialog.height;
+        let mut y = dialog.y + char_height;
+
+        screen.draw_text(text_x, y, "Keybindings", theme.accent_primary);
+        y += char_height * 2;
+
+        // Draw as many rows as fit; the dialog may be clamped on small screens
+        for (keys, description) in KEYBINDINGS {
+            if y + char_height * 2 > bottom {
+                break;
+            }
+            screen.draw_text(text_x, y, keys, theme.accent_primary);
+            screen.draw_text(desc_x, y, description, theme.text_secondary);
+            y += char_height;
+        }
+    }
+}
+
+/// Compute the dialog rectangle, centered and clamped to `bounds`
+fn dialog_rect(bounds: Rect, char_width: usize, char_height: usize) -> Rect {
+    let longest = KEYBINDINGS
+        .iter()
+        .map(|(_, description)| description.chars().count())
+        .max()
+        .unwrap_or(0);
+    let columns = PADDING * 2 + KEY_COLUMN + longest;
+    // Border, title, blank line, rows, bottom padding
+    let rows = 1 + 2 + KEYBINDINGS.len() + 1;
+
+    let width = (columns * char_width).min(bounds.width);
+    let height = (rows * char_height).min(bounds.height);
+    Rect::new(
+        bounds.x + (bounds.width - width) / 2,
+        bounds.y + (bounds.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_and_f1_close() {
+        let mut help = HelpScreen::new();
+        assert_eq!(help.handle_input(Key::Escape), HelpEvent::Close);
+        assert_eq!(help.handle_input(Key::F1), HelpEvent::Close);
+        assert_eq!(help.handle_input(Key::Enter), HelpEvent::None);
+    }
+
+    #[test]
+    fn test_dialog_is_centered() {
+        let rect = dialog_rect(Rect::new(0, 0, 1024, 768), 8, 16);
+        assert_eq!(rect.x, (1024 - rect.width) / 2);
+        assert_eq!(rect.y, (768 - rect.height) / 2);
+    }
+
+    #[test]
+    fn test_dialog_clamps_to_small_screen() {
+        let rect = dialog_rect(Rect::new(0, 0, 200, 100), 8, 16);
+        assert_eq!(rect, Rect::new(0, 0, 200, 100));
+    }
+}
@@ -4,6 +4,14 @@
 //! configuration screen, and setup wizard.
 
 pub mod chat;
+pub mod config;
+pub mod help;
+pub mod model_select;
+pub mod provider_select;
 
 // Re-export screens
 pub use chat::{ChatEvent, ChatScreen, ConnectionStatus};
+pub use config::{ConfigEvent, ConfigScreen, ProviderEntry};
+pub use help::{HelpEvent, HelpScreen};
+pub use model_select::{ModelEntry, ModelSelectEvent, ModelSelectScreen};
+pub use provider_select::{ProviderSelectEvent, ProviderSelectScreen};
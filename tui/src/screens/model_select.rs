@@ -0,0 +1,274 @@
+//! Model selection screen implementation
+//!
+//! Provides a centered dialog with a scrollable list of the models offered by
+//! the current provider. The selection is highlighted and follows Up/Down;
+//! Enter chooses the highlighted model.
+
+extern crate alloc;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::screen::{BoxStyle, Screen};
+use crate::types::{Key, Rect};
+
+// Layout constants (in character units)
+const DIALOG_WIDTH: usize = 56;
+const MAX_VISIBLE_ROWS: usize = 12;
+const PADDING: usize = 2;
+
+/// A model shown in the selection list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelEntry {
+    /// Identifier reported in events (e.g. "gpt-4o")
+    pub id: String,
+    /// Display name (e.g. "GPT-4o")
+    pub name: String,
+    /// Context window in tokens (0 if unknown)
+    pub context_length: usize,
+}
+
+impl ModelEntry {
+    pub fn new(id: &str, name: &str, context_length: usize) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            context_length,
+        }
+    }
+}
+
+/// Events emitted by the model selection screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelSelectEvent {
+    /// No event
+    None,
+    /// User chose a model (its id)
+    Selected(String),
+    /// User wants to leave without changing the model
+    Close,
+}
+
+/// Scrollable list for picking one of the provider's models
+pub struct ModelSelectScreen {
+    /// Models that can be chosen
+    models: Vec<ModelEntry>,
+    /// Index of the highlighted model
+    selected: usize,
+    /// Index of the first visible row
+    scroll: usize,
+}
+
+impl ModelSelectScreen {
+    /// Create a new model selection screen
+    ///
+    /// # Arguments
+    ///
+    /// * `models` - Models to list, in display order
+    /// * `current` - Id of the active model, highlighted initially
+    pub fn new(models: Vec<ModelEntry>, current: &str) -> Self {
+        let selected = models.iter().position(|m| m.id == current).unwrap_or(0);
+        Self {
+            models,
+            selected,
+            scroll: 0,
+        }
+    }
+
+    /// Get the listed models
+    pub fn models(&self) -> &[ModelEntry] {
+        &self.models
+    }
+
+    /// Get the highlighted model, if any
+    pub fn selected(&self) -> Option<&ModelEntry> {
+        self.models.get(self.selected)
+    }
+
+    /// Handle keyboard input
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that was pressed
+    ///
+    /// # Returns
+    ///
+    /// A ModelSelectEvent indicating what action should be taken
+    pub fn handle_input(&mut self, key: Key) -> ModelSelectEvent {
+        match key {
+            Key::Up => {
+                self.selected = self.selected.saturating_sub(1);
+                ModelSelectEvent::None
+            }
+            Key::Down => {
+                if self.selected + 1 < self.models.len() {
+                    self.selected += 1;
+                }
+                ModelSelectEvent::None
+            }
+            Key::PageUp => {
+                self.selected = self.selected.saturating_sub(MAX_VISIBLE_ROWS);
+                ModelSelectEvent::None
+            }
+            Key::PageDown => {
+                self.selected = (self.selected + MAX_VISIBLE_ROWS)
+                    .min(self.models.len().saturating_sub(1));
+                ModelSelectEvent::None
+            }
+            Key::Enter => match self.selected() {
+                Some(model) => ModelSelectEvent::Selected(model.id.clone()),
+                None => ModelSelectEvent::None,
+            },
+            Key::Escape | Key::F3 => ModelSelectEvent::Close,
+            _ => ModelSelectEvent::None,
+        }
+    }
+
+    /// Adjust the scroll offset so the selection is within `rows` visible rows
+    fn scroll_to_selection(&mut self, rows: usize) {
+        if rows == 0 {
+            return;
+        }
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+    }
+
+    /// Render the model list as a centered dialog
+    ///
+    /// # Arguments
+    ///
+    /// * `screen` - The screen to render to
+    pub fn render(&mut self, screen: &mut Screen) {
+        let theme = screen.theme();
+        let bounds = screen.bounds();
+
+        // Get character dimensions for layout calculations
+        let Some((char_width, char_height)) = screen.char_size() else {
+            return; // Can't render without a font
+        };
+
+        // Title, blank, list, blank, hints, plus a line of padding each side
+        let list_rows = self.models.len().clamp(1, MAX_VISIBLE_ROWS);
+        let rows = 1 + 2 + list_rows + 2 + 1;
+        let width = (DIALOG_WIDTH * char_width).min(bounds.width);
+        let height = (rows * char_height).min(bounds.height);
+        let dialog = Rect::new(
+            bounds.x + (bounds.width - width) / 2,
+            bounds.y + (bounds.height - height) / 2,
+            width,
+            height,
+        );
+
+        screen.fill_rect(dialog, theme.surface);
+        screen.draw_box(dialog, BoxStyle::Double, theme.border);
+
+        let text_x = dialog.x + PADDING * char_width;
+        let mut y = dialog.y + char_height;
+
+        screen.draw_text(text_x, y, "Select Model", theme.accent_primary);
+        y += char_height * 2;
+
+        if self.models.is_empty() {
+            screen.draw_text(
+                text_x,
+                y,
+                "No models available for this provider",
+                theme.text_secondary,
+            );
+        } else {
+            // Fewer rows fit when the dialog is clamped to a small screen
+            let fit = (dialog.y + dialog.height).saturating_sub(y + char_height * 3) / char_height;
+            let visible = list_rows.min(fit.max(1));
+            self.scroll_to_selection(visible);
+
+            for (i, model) in self.models.iter().enumerate().skip(self.scroll).take(visible) {
+                let is_selected = i == self.selected;
+                let row = Rect::new(dialog.x + 1, y, dialog.width.saturating_sub(2), char_height);
+                let color = if is_selected {
+                    screen.fill_rect(row, theme.background);
+                    theme.accent_primary
+                } else {
+                    theme.text_secondary
+                };
+                let marker = if is_selected { "> " } else { "  " };
+                let mut line = String::from(marker);
+                line.push_str(&model.name);
+                screen.draw_text(text_x, y, &line, color);
+
+                if model.context_length > 0 {
+                    let context = format!("{}k", model.context_length / 1000);
+                    let context_x = dialog.x
+                        + dialog
+                            .width
+                            .saturating_sub((context.chars().count() + PADDING) * char_width);
+                    screen.draw_text(context_x, y, &context, theme.text_tertiary);
+                }
+                y += char_height;
+            }
+        }
+
+        let hints_y = dialog.y + dialog.height.saturating_sub(char_height * 2);
+        screen.draw_text(
+            text_x,
+            hints_y,
+            "Up/Down: Select  Enter: Choose  Esc: Cancel",
+            theme.text_tertiary,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn models(count: usize) -> Vec<ModelEntry> {
+        (0..count)
+            .map(|i| {
+                let id = format!("model-{}", i);
+                ModelEntry::new(&id, &id, 8_000)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_starts_on_current_model() {
+        let screen = ModelSelectScreen::new(models(3), "model-2");
+        assert_eq!(screen.selected().unwrap().id, "model-2");
+    }
+
+    #[test]
+    fn test_enter_emits_selected_model() {
+        let mut screen = ModelSelectScreen::new(models(3), "model-0");
+        screen.handle_input(Key::Down);
+        assert_eq!(
+            screen.handle_input(Key::Enter),
+            ModelSelectEvent::Selected("model-1".into())
+        );
+    }
+
+    #[test]
+    fn test_empty_list_ignores_enter() {
+        let mut screen = ModelSelectScreen::new(Vec::new(), "");
+        screen.handle_input(Key::Down);
+        assert_eq!(screen.handle_input(Key::Enter), ModelSelectEvent::None);
+        assert_eq!(screen.handle_input(Key::Escape), ModelSelectEvent::Close);
+    }
+
+    #[test]
+    fn test_scroll_follows_selection() {
+        let mut screen = ModelSelectScreen::new(models(20), "model-0");
+        for _ in 0..15 {
+            screen.handle_input(Key::Down);
+        }
+        screen.scroll_to_selection(MAX_VISIBLE_ROWS);
+        assert_eq!(screen.scroll, 15 + 1 - MAX_VISIBLE_ROWS);
+        for _ in 0..15 {
+            screen.handle_input(Key::Up);
+        }
+        screen.scroll_to_selection(MAX_VISIBLE_ROWS);
+        assert_eq!(screen.scroll, 0);
+    }
+}
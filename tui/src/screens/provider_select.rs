@@ -0,0 +1,220 @@
+//! Provider selection screen implementation
+//!
+//! Provides a centered dialog listing the LLM providers. Providers without an
+//! API key are grayed out and cannot be chosen; the dialog points the user to
+//! the configuration screen (F4) instead.
+
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::screen::{BoxStyle, Screen};
+use crate::screens::config::ProviderEntry;
+use crate::types::{Key, Rect};
+
+// Layout constants (in character units)
+const DIALOG_WIDTH: usize = 48;
+const PADDING: usize = 2;
+
+/// Events emitted by the provider selection screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderSelectEvent {
+    /// No event
+    None,
+    /// User chose a provider (its id)
+    Selected(String),
+    /// User wants to leave without switching
+    Close,
+}
+
+/// List for switching the active LLM provider
+pub struct ProviderSelectScreen {
+    /// Providers to list; `configured` marks those with an API key
+    providers: Vec<ProviderEntry>,
+    /// Index of the highlighted provider
+    selected: usize,
+    /// Whether to show the "set a key in F4" hint
+    show_key_hint: bool,
+}
+
+impl ProviderSelectScreen {
+    /// Create a new provider selection screen
+    ///
+    /// # Arguments
+    ///
+    /// * `providers` - Providers to list, in display order
+    /// * `current` - Id of the active provider, highlighted initially
+    pub fn new(providers: Vec<ProviderEntry>, current: &str) -> Self {
+        let selected = providers.iter().position(|p| p.id == current).unwrap_or(0);
+        Self {
+            providers,
+            selected,
+            show_key_hint: false,
+        }
+    }
+
+    /// Get the listed providers
+    pub fn providers(&self) -> &[ProviderEntry] {
+        &self.providers
+    }
+
+    /// Get the highlighted provider, if any
+    pub fn selected(&self) -> Option<&ProviderEntry> {
+        self.providers.get(self.selected)
+    }
+
+    /// Handle keyboard input
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that was pressed
+    ///
+    /// # Returns
+    ///
+    /// A ProviderSelectEvent indicating what action should be taken
+    pub fn handle_input(&mut self, key: Key) -> ProviderSelectEvent {
+        match key {
+            Key::Up => {
+                self.selected = self.selected.saturating_sub(1);
+                self.show_key_hint = false;
+                ProviderSelectEvent::None
+            }
+            Key::Down => {
+                if self.selected + 1 < self.providers.len() {
+                    self.selected += 1;
+                }
+                self.show_key_hint = false;
+                ProviderSelectEvent::None
+            }
+            Key::Enter => match self.selected() {
+                Some(entry) if entry.configured => ProviderSelectEvent::Selected(entry.id.clone()),
+                Some(_) => {
+                    self.show_key_hint = true;
+                    ProviderSelectEvent::None
+                }
+                None => ProviderSelectEvent::None,
+            },
+            Key::Escape | Key::F2 => ProviderSelectEvent::Close,
+            _ => ProviderSelectEvent::None,
+        }
+    }
+
+    /// Render the provider list as a centered dialog
+    ///
+    /// # Arguments
+    ///
+    /// * `screen` - The screen to render to
+    pub fn render(&self, screen: &mut Screen) {
+        let theme = screen.theme();
+        let bounds = screen.bounds();
+
+        // Get character dimensions for layout calculations
+        let Some((char_width, char_height)) = screen.char_size() else {
+            return; // Can't render without a font
+        };
+
+        // Title, blank, providers, blank, key hint, hints, plus a line of padding each side
+        let rows = 1 + 2 + self.providers.len() + 1 + 1 + 1 + 1;
+        let width = (DIALOG_WIDTH * char_width).min(bounds.width);
+        let height = (rows * char_height).min(bounds.height);
+        let dialog = Rect::new(
+            bounds.x + (bounds.width - width) / 2,
+            bounds.y + (bounds.height - height) / 2,
+            width,
+            height,
+        );
+
+        screen.fill_rect(dialog, theme.surface);
+        screen.draw_box(dialog, BoxStyle::Double, theme.border);
+
+        let text_x = dialog.x + PADDING * char_width;
+        let mut y = dialog.y + char_height;
+
+        screen.draw_text(text_x, y, "Select Provider", theme.accent_primary);
+        y += char_height * 2;
+
+        for (i, entry) in self.providers.iter().enumerate() {
+            let is_selected = i == self.selected;
+            let color = match (entry.configured, is_selected) {
+                (true, true) => theme.accent_primary,
+                (true, false) => theme.text_secondary,
+                // Providers without a key are grayed out
+                (false, _) => theme.text_tertiary,
+            };
+            let marker = if is_selected { "> " } else { "  " };
+            let mut line = String::from(marker);
+            line.push_str(&entry.label);
+            screen.draw_text(text_x, y, &line, color);
+
+            if !entry.configured {
+                let state = "no key";
+                let state_x = dialog.x
+                    + dialog
+                        .width
+                        .saturating_sub((state.chars().count() + PADDING) * char_width);
+                screen.draw_text(state_x, y, state, theme.text_tertiary);
+            }
+            y += char_height;
+        }
+        y += char_height;
+
+        if self.show_key_hint {
+            screen.draw_text(
+                text_x,
+                y,
+                "No API key set - press F4 to add one",
+                theme.accent_warning,
+            );
+        }
+        y += char_height;
+
+        screen.draw_text(
+            text_x,
+            y,
+            "Up/Down: Select  Enter: Switch  Esc: Cancel",
+            theme.text_tertiary,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen() -> ProviderSelectScreen {
+        ProviderSelectScreen::new(
+            alloc::vec![
+                ProviderEntry::new("openai", "OpenAI", true),
+                ProviderEntry::new("anthropic", "Anthropic", false),
+                ProviderEntry::new("groq", "Groq", true),
+            ],
+            "groq",
+        )
+    }
+
+    #[test]
+    fn test_starts_on_current_provider() {
+        assert_eq!(screen().selected().unwrap().id, "groq");
+    }
+
+    #[test]
+    fn test_enter_selects_configured_provider() {
+        let mut select = screen();
+        select.handle_input(Key::Up);
+        select.handle_input(Key::Up);
+        assert_eq!(
+            select.handle_input(Key::Enter),
+            ProviderSelectEvent::Selected("openai".into())
+        );
+    }
+
+    #[test]
+    fn test_provider_without_key_shows_hint() {
+        let mut select = screen();
+        select.handle_input(Key::Up);
+        assert_eq!(select.handle_input(Key::Enter), ProviderSelectEvent::None);
+        assert!(select.show_key_hint);
+        select.handle_input(Key::Down);
+        assert!(!select.show_key_hint);
+    }
+}
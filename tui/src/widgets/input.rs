@@ -5,11 +5,15 @@
 
 extern crate alloc;
 use alloc::string::String;
+use alloc::vec::Vec;
 
 use crate::screen::Screen;
 use crate::types::{CursorDirection, Key, Rect, WidgetEvent};
 use crate::widget::Widget;
 
+/// Maximum number of submitted entries kept for recall
+pub const HISTORY_LIMIT: usize = 50;
+
 /// Text input widget with cursor support
 ///
 /// This widget displays a text input field with a cursor that can be moved
@@ -29,12 +33,20 @@ use crate::widget::Widget;
 pub struct InputWidget {
     /// Current text content
     text: String,
-    /// Cursor position (byte offset in the string)
+    /// Cursor position (character index into the text)
     cursor_pos: usize,
     /// Placeholder text shown when empty
     placeholder: String,
     /// Whether the widget has focus
     focused: bool,
+    /// Whether the text is drawn as `*` (for secrets such as API keys)
+    masked: bool,
+    /// Previously submitted entries, oldest first
+    history: Vec<String>,
+    /// Index into `history` while recalling entries with Up/Down
+    history_index: Option<usize>,
+    /// Text that was being typed before history recall started
+    draft: String,
 }
 
 impl InputWidget {
@@ -56,6 +68,10 @@ impl InputWidget {
             cursor_pos: 0,
             placeholder,
             focused: false,
+            masked: false,
+            history: Vec::new(),
+            history_index: None,
+            draft: String::new(),
         }
     }
 
@@ -189,6 +205,57 @@ impl InputWidget {
     pub fn clear(&mut self) {
         self.text.clear();
         self.cursor_pos = 0;
+        self.history_index = None;
+    }
+
+    /// Remember a submitted entry for recall with Up/Down
+    ///
+    /// Empty entries and repeats of the most recent entry are skipped; the
+    /// oldest entry is dropped once [`HISTORY_LIMIT`] is reached.
+    pub fn add_history(&mut self, entry: String) {
+        self.history_index = None;
+        if entry.trim().is_empty() || self.history.last() == Some(&entry) {
+            return;
+        }
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+        self.history.push(entry);
+    }
+
+    /// Get the submitted entries, oldest first
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Replace the text with the previous (older) history entry
+    fn history_prev(&mut self) {
+        let index = match self.history_index {
+            Some(0) => return,
+            Some(index) => index - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = core::mem::take(&mut self.text);
+                self.history.len() - 1
+            }
+        };
+        self.history_index = Some(index);
+        self.set_text(self.history[index].clone());
+    }
+
+    /// Replace the text with the next (newer) history entry, or the draft
+    fn history_next(&mut self) {
+        let Some(index) = self.history_index else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.history_index = Some(index + 1);
+            self.set_text(self.history[index + 1].clone());
+        } else {
+            self.history_index = None;
+            let draft = core::mem::take(&mut self.draft);
+            self.set_text(draft);
+        }
     }
 
     /// Set the focus state of the widget
@@ -209,6 +276,19 @@ impl InputWidget {
         self.focused
     }
 
+    /// Set whether the text is masked when rendered
+    ///
+    /// The stored text is unaffected; only the rendering replaces each
+    /// character with `*`.
+    pub fn set_masked(&mut self, masked: bool) {
+        self.masked = masked;
+    }
+
+    /// Check if the widget masks its text
+    pub fn is_masked(&self) -> bool {
+        self.masked
+    }
+
     /// Set the text content directly
     ///
     /// This replaces the current text and moves the cursor to the end.
@@ -229,6 +309,17 @@ impl InputWidget {
     pub fn cursor_position(&self) -> usize {
         self.cursor_pos
     }
+
+    /// Index of the first character shown in a field `columns` wide
+    ///
+    /// The view scrolls horizontally just enough to keep the cursor cell
+    /// visible, so the cursor sits at the right edge while typing past it.
+    fn visible_start(&self, columns: usize) -> usize {
+        if columns == 0 {
+            return self.cursor_pos;
+        }
+        (self.cursor_pos + 1).saturating_sub(columns)
+    }
 }
 
 impl Widget for InputWidget {
@@ -271,21 +362,40 @@ impl Widget for InputWidget {
         let text_x = rect.x + 1 + padding;
         let text_y = rect.y + (rect.height.saturating_sub(char_height)) / 2; // Vertically center
 
+        // Number of character cells between the left and right padding
+        let columns = rect.width.saturating_sub(2 * (padding + 1)) / char_width.max(1);
+        let start = self.visible_start(columns);
+
         // Render text or placeholder
         if self.text.is_empty() {
             // Show placeholder in a dimmer color
             screen.draw_text(text_x, text_y, &self.placeholder, theme.text_tertiary);
         } else {
-            screen.draw_text(text_x, text_y, &self.text, text_color);
+            let visible: String = if self.masked {
+                "*".repeat(self.text.chars().count().saturating_sub(start).min(columns))
+            } else {
+                self.text.chars().skip(start).take(columns).collect()
+            };
+            screen.draw_text(text_x, text_y, &visible, text_color);
         }
 
-        // Draw cursor if focused
-        if self.focused {
-            let cursor_x = text_x + (self.cursor_pos * char_width);
-
-            // Draw cursor as a vertical line (blinking block cursor style)
-            if cursor_x < rect.x + rect.width.saturating_sub(padding + 1) {
-                screen.draw_vline(cursor_x, text_y, char_height, theme.accent_primary);
+        // Draw a block cursor over the character cell at the cursor
+        if self.focused && columns > 0 {
+            let cursor_x = text_x + (self.cursor_pos - start) * char_width;
+            screen.fill_rect(
+                Rect::new(cursor_x, text_y, char_width, char_height),
+                theme.accent_primary,
+            );
+            // Redraw the character under the cursor so it stays readable
+            let under = if self.masked {
+                self.text.chars().nth(self.cursor_pos).map(|_| '*')
+            } else {
+                self.text.chars().nth(self.cursor_pos)
+            };
+            if let Some(ch) = under {
+                let mut cell = String::new();
+                cell.push(ch);
+                screen.draw_text(cursor_x, text_y, &cell, bg_color);
             }
         }
     }
@@ -320,6 +430,15 @@ impl Widget for InputWidget {
                 self.move_cursor(CursorDirection::End);
                 WidgetEvent::Changed
             }
+            // Single-line input: Up/Down recall submitted entries
+            Key::Up => {
+                self.history_prev();
+                WidgetEvent::Changed
+            }
+            Key::Down => {
+                self.history_next();
+                WidgetEvent::Changed
+            }
             Key::Enter => {
                 WidgetEvent::Submit
             }
@@ -444,4 +563,67 @@ mod tests {
         input.insert_char('b');
         assert_eq!(input.get_text(), "abc");
     }
+
+    #[test]
+    fn test_edit_in_middle_of_text() {
+        let mut input = InputWidget::new("".into());
+        input.set_text("hello world".into());
+        input.handle_input(Key::Home);
+        for _ in 0..5 {
+            input.handle_input(Key::Right);
+        }
+        input.handle_input(Key::Char(','));
+        assert_eq!(input.get_text(), "hello, world");
+        input.handle_input(Key::Backspace);
+        input.handle_input(Key::Delete);
+        assert_eq!(input.get_text(), "helloworld");
+        assert_eq!(input.cursor_position(), 5);
+    }
+
+    fn input_with_history() -> InputWidget {
+        let mut input = InputWidget::new("".into());
+        input.add_history("first".into());
+        input.add_history("second".into());
+        input.add_history("second".into());
+        input
+    }
+
+    #[test]
+    fn test_history_skips_repeats_and_caps() {
+        let mut input = input_with_history();
+        assert_eq!(input.history().len(), 2);
+        for i in 0..HISTORY_LIMIT {
+            input.add_history(alloc::format!("entry {}", i));
+        }
+        assert_eq!(input.history().len(), HISTORY_LIMIT);
+        assert_eq!(input.history()[0], "entry 0");
+    }
+
+    #[test]
+    fn test_history_cycles_backward_and_forward() {
+        let mut input = input_with_history();
+        input.set_text("draft".into());
+        input.handle_input(Key::Up);
+        assert_eq!(input.get_text(), "second");
+        input.handle_input(Key::Up);
+        assert_eq!(input.get_text(), "first");
+        input.handle_input(Key::Up);
+        assert_eq!(input.get_text(), "first");
+        input.handle_input(Key::Down);
+        assert_eq!(input.get_text(), "second");
+        input.handle_input(Key::Down);
+        assert_eq!(input.get_text(), "draft");
+        input.handle_input(Key::Down);
+        assert_eq!(input.get_text(), "draft");
+    }
+
+    #[test]
+    fn test_view_scrolls_to_keep_cursor_visible() {
+        let mut input = InputWidget::new("".into());
+        input.set_text("abcdefghij".into());
+        assert_eq!(input.visible_start(4), 7);
+        input.handle_input(Key::Home);
+        assert_eq!(input.visible_start(4), 0);
+        assert_eq!(input.visible_start(20), 0);
+    }
 }
@@ -102,13 +102,30 @@ impl MessageWidget {
     /// Wrap text to fit within the given width in characters
     ///
     /// Returns a vector of lines, each line being a string that fits
-    /// within the specified width.
+    /// within the specified width. Lines break at whitespace; words longer
+    /// than the width are split. Embedded newlines start a new line, and
+    /// blank lines in the content are kept.
     pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
         if width == 0 {
             return Vec::new();
         }
 
         let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let paragraph = paragraph.strip_suffix('\r').unwrap_or(paragraph);
+            let start = lines.len();
+            Self::wrap_paragraph(paragraph, width, &mut lines);
+            if lines.len() == start {
+                // Blank line in the content
+                lines.push(String::new());
+            }
+        }
+
+        lines
+    }
+
+    /// Wrap a single paragraph (no newlines) at word boundaries
+    fn wrap_paragraph(text: &str, width: usize, lines: &mut Vec<String>) {
         let mut current_line = String::new();
         let mut current_width = 0;
 
@@ -174,13 +191,6 @@ impl MessageWidget {
         if !current_line.is_empty() {
             lines.push(current_line);
         }
-
-        // If no lines were created (empty text), return at least one empty line
-        if lines.is_empty() {
-            lines.push(String::new());
-        }
-
-        lines
     }
 
     /// Get the background color for the message bubble based on role and theme
@@ -357,6 +367,24 @@ mod tests {
         assert!(lines.len() > 1);
     }
 
+    #[test]
+    fn test_wrap_text_breaks_between_words() {
+        let lines = MessageWidget::wrap_text("the quick brown fox jumps", 11);
+        assert_eq!(lines, alloc::vec!["the quick", "brown fox", "jumps"]);
+    }
+
+    #[test]
+    fn test_wrap_text_long_word_hard_break() {
+        let lines = MessageWidget::wrap_text("a abcdefghijkl b", 5);
+        assert_eq!(lines, alloc::vec!["a", "abcde", "fghij", "kl b"]);
+    }
+
+    #[test]
+    fn test_wrap_text_preserves_newlines() {
+        let lines = MessageWidget::wrap_text("first line\n\nsecond line here", 11);
+        assert_eq!(lines, alloc::vec!["first line", "", "second line", "here"]);
+    }
+
     #[test]
     fn test_format_timestamp() {
         // Test with seconds